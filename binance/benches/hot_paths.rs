@@ -0,0 +1,154 @@
+//! 热路径基准
+//!
+//! 覆盖行情解析、转换为通用结构、向多个策略端扇出以及下单签名，用于发现性能回退、验证零拷贝与缓冲复用等优化。
+//! 由 criterion 采样并与上一次运行的结果比较。
+//!
+//! ```sh
+//! cargo bench -p binance --bench hot_paths
+//! cargo bench -p binance --bench hot_paths -- fan_out
+//! ```
+
+use binance::model::depth::BinanceSpotDepth;
+use binance::model::kline::BinanceKline;
+use binance::model::quote::BinanceQuote;
+use binance::model::Event;
+use binance::rest::Rest;
+use binance::Subscriber;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use cryptoflow::chat::{SGeneralDepth, SGeneralKline};
+use openssl::pkey::PKey;
+use serde_json::Value;
+use std::hint::black_box;
+use websocket::{bounded_channel, ChannelConfig};
+
+fn depth_json(levels: usize) -> String {
+    let side = |base: f64, step: f64| {
+        (0..levels)
+            .map(|i| {
+                format!(
+                    r#"["{:.2}","{:.5}"]"#,
+                    base + step * i as f64,
+                    0.1 + i as f64
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        r#"{{"stream":"btcusdt@depth20@100ms","data":{{"lastUpdateId":160,"bids":[{}],"asks":[{}]}}}}"#,
+        side(64280.0, -0.01),
+        side(64280.01, 0.01)
+    )
+}
+
+const KLINE: &str = r#"{"stream":"btcusdt@kline_1m","data":{"e":"kline","E":1672515780000,"s":"BTCUSDT",
+"k":{"t":1672515780000,"T":1672515839999,"s":"BTCUSDT","i":"1m","f":100,"L":200,"o":"16500.10",
+"c":"16510.20","h":"16515.00","l":"16499.00","v":"1000","n":100,"x":false,"q":"1.0000","V":"500",
+"Q":"0.500","B":"123456"}}}"#;
+
+fn parse(c: &mut Criterion) {
+    let depth = depth_json(20);
+    let depth_value: Value = serde_json::from_str(&depth).unwrap();
+    let kline_value: Value = serde_json::from_str(KLINE).unwrap();
+
+    let mut group = c.benchmark_group("parse");
+    group.bench_function("depth20 text", |b| {
+        b.iter(|| serde_json::from_str::<Event>(black_box(&depth)).unwrap())
+    });
+    group.bench_function("depth20 value", |b| {
+        b.iter_batched(
+            || depth_value.clone(),
+            |v| serde_json::from_value::<Event>(v).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("kline text", |b| {
+        b.iter(|| serde_json::from_str::<Event>(black_box(KLINE)).unwrap())
+    });
+    group.bench_function("kline value", |b| {
+        b.iter_batched(
+            || kline_value.clone(),
+            |v| serde_json::from_value::<Event>(v).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+/// 转换为通用结构并序列化
+fn convert(c: &mut Criterion) {
+    let depth = depth_json(20);
+    let spot_depth: BinanceSpotDepth = serde_json::from_str(&depth).unwrap();
+    let kline: BinanceKline = serde_json::from_str(KLINE).unwrap();
+    let general = SGeneralDepth::<BinanceQuote>::from(spot_depth.clone());
+
+    let mut group = c.benchmark_group("convert");
+    group.bench_function("depth20", |b| {
+        b.iter_batched(
+            || spot_depth.clone(),
+            SGeneralDepth::<BinanceQuote>::from,
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("kline", |b| {
+        b.iter_batched(|| kline.clone(), SGeneralKline::from, BatchSize::SmallInput)
+    });
+    group.finish();
+
+    c.benchmark_group("serialize")
+        .bench_function("depth20", |b| {
+            b.iter(|| serde_json::to_string(black_box(&general)).unwrap())
+        });
+}
+
+fn fan_out(c: &mut Criterion) {
+    let stream = "btcusdt@depth20@100ms".to_string();
+    let spot_depth: BinanceSpotDepth = serde_json::from_str(&depth_json(20)).unwrap();
+    let data = serde_json::to_string(&SGeneralDepth::<BinanceQuote>::from(spot_depth)).unwrap();
+
+    let mut group = c.benchmark_group("fan_out");
+    for clients in [1, 10, 100] {
+        let mut subscribers = Vec::new();
+        let mut receivers = Vec::new();
+        for _ in 0..clients {
+            let (tx, rx) = bounded_channel("bench", &ChannelConfig::default());
+            let mut subscriber = Subscriber::new(tx.into());
+            subscriber.on_strategy_client_subscribe(0, 0, vec![stream.clone()]);
+            subscribers.push(subscriber);
+            receivers.push(rx);
+        }
+        group.bench_function(BenchmarkId::new("clients", clients), |b| {
+            b.iter(|| {
+                for subscriber in subscribers.iter() {
+                    if subscriber.is_subscribed(&stream) {
+                        subscriber.forward_stream(&stream, &data).unwrap();
+                    }
+                }
+                for rx in receivers.iter_mut() {
+                    while rx.try_recv().is_ok() {}
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn sign(c: &mut Criterion) {
+    let key = PKey::generate_ed25519().unwrap();
+    let rest = Rest::from_pem(
+        "https://api.binance.com",
+        "apikey",
+        &key.private_key_to_pem_pkcs8().unwrap(),
+        3000,
+    )
+    .unwrap();
+    let query = "symbol=BTCUSDT&side=BUY&type=LIMIT&timeInForce=GTC&quantity=0.001&price=16500.1\
+                 &newClientOrderId=x-1-2&recvWindow=3000&timestamp=1672515780000"
+        .to_string();
+    c.benchmark_group("sign").bench_function("order", |b| {
+        b.iter(|| rest.sign(black_box(&query)).unwrap())
+    });
+}
+
+criterion_group!(hot_paths, parse, convert, fan_out, sign);
+criterion_main!(hot_paths);
@@ -0,0 +1,170 @@
+mod normalize;
+mod vision;
+
+use chrono::NaiveDate;
+use clap::Parser;
+use cryptoflow::{init_tracing_with_config, LogConfig};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{error, info, warn};
+use vision::{archive_path, Dataset, MarketType, Vision};
+
+fn default_base_url() -> String {
+    "https://data.binance.vision".into()
+}
+
+fn default_output() -> String {
+    "data/csv".into()
+}
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    market: MarketType,
+    symbols: Vec<String>,
+    #[serde(default)]
+    klines: Vec<String>,
+    #[serde(default)]
+    agg_trades: bool,
+    #[serde(default)]
+    book_depth: bool,
+    start: NaiveDate,
+    end: NaiveDate,
+    #[serde(default = "default_output")]
+    output: String,
+    #[serde(default = "default_base_url")]
+    base_url: String,
+    #[serde(default)]
+    log: LogConfig,
+}
+
+impl Config {
+    fn datasets(&self) -> Vec<Dataset> {
+        let mut datasets: Vec<_> = self
+            .klines
+            .iter()
+            .map(|interval| Dataset::Klines(interval.clone()))
+            .collect();
+        if self.agg_trades {
+            datasets.push(Dataset::AggTrades);
+        }
+        if self.book_depth {
+            if self.market == MarketType::Um {
+                datasets.push(Dataset::BookDepth);
+            } else {
+                warn!("bookDepth is only published for futures, ignored");
+            }
+        }
+        datasets
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Download Binance Vision archives into the data service csv layout"
+)]
+struct Args {
+    #[arg(short, long, help = "Config path")]
+    config: String,
+    #[arg(short, long, default_value_t = tracing::Level::INFO)]
+    level: tracing::Level,
+}
+
+impl Args {
+    pub fn load(&self) -> anyhow::Result<Config> {
+        info!("Load config from {}", self.config);
+        let buf = std::fs::read_to_string(self.config.clone())?;
+        let config: Config = native_json::parse(&buf)?;
+        Ok(config)
+    }
+}
+
+/// 已转换的归档清单，重复运行时跳过，避免重复追加
+struct Manifest {
+    path: PathBuf,
+    done: HashSet<String>,
+}
+
+impl Manifest {
+    fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join("vision.manifest");
+        let done = match std::fs::read_to_string(&path) {
+            Ok(s) => s.lines().map(String::from).collect(),
+            Err(_) => HashSet::new(),
+        };
+        Ok(Self { path, done })
+    }
+
+    fn contains(&self, archive: &str) -> bool {
+        self.done.contains(archive)
+    }
+
+    fn insert(&mut self, archive: &str) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", archive)?;
+        self.done.insert(archive.to_string());
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let config = args.load()?;
+
+    let _guard =
+        init_tracing_with_config("data", &config.log, &args.level.to_string().to_lowercase())?;
+
+    if config.start > config.end {
+        anyhow::bail!("start {} is after end {}", config.start, config.end);
+    }
+
+    let output = PathBuf::from(&config.output);
+    std::fs::create_dir_all(&output)?;
+    let mut manifest = Manifest::load(&output)?;
+    let vision = Vision::new(&config.base_url);
+    let datasets = config.datasets();
+
+    let (mut converted, mut missing, mut failed) = (0, 0, 0);
+    for symbol in config.symbols.iter() {
+        for dataset in datasets.iter() {
+            let target = normalize::output_path(&output, dataset, symbol);
+            for date in config.start.iter_days().take_while(|d| *d <= config.end) {
+                let archive = archive_path(config.market, symbol, dataset, date);
+                if manifest.contains(&archive) {
+                    continue;
+                }
+
+                match vision.fetch(&archive).await {
+                    Ok(Some(csv)) => {
+                        let rows = normalize::normalize(dataset, symbol, &csv)?;
+                        normalize::append(&target, normalize::header(dataset), &rows)?;
+                        manifest.insert(&archive)?;
+                        converted += 1;
+                        info!("{} -> {} ({} rows)", archive, target.display(), rows.len());
+                    }
+                    Ok(None) => {
+                        missing += 1;
+                        warn!("{} not found", archive);
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        error!("{}: {}", archive, e);
+                    }
+                }
+            }
+        }
+    }
+
+    info!(
+        "Finish, converted {}, missing {}, failed {}",
+        converted, missing, failed
+    );
+    Ok(())
+}
@@ -0,0 +1,214 @@
+//! 把 Binance Vision 归档转换成数据服务（pyalgo.data）使用的 CSV 格式
+//!
+//! kline 与 `DataStorage` / `DataQuery` 写入的列完全一致，文件为
+//! `{dir}/klines/{symbol}_{interval}.csv`，下载的数据可以直接被策略端读取；
+//! aggTrades 与 bookDepth 分别写入 `aggtrades/`、`bookdepth/` 目录。
+
+use crate::vision::Dataset;
+use chrono::{DateTime, NaiveDateTime};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const KLINE_HEADER: &str = "symbol,interval,open_time,close_time,open,high,low,close,volume,quote_volume,trade_count,taker_buy_volume,taker_buy_quote_volume,is_closed,datetime";
+const AGG_TRADE_HEADER: &str =
+    "symbol,agg_trade_id,price,quantity,first_trade_id,last_trade_id,timestamp,is_buyer_maker,datetime";
+const BOOK_DEPTH_HEADER: &str = "symbol,timestamp,percentage,depth,notional,datetime";
+
+pub fn header(dataset: &Dataset) -> &'static str {
+    match dataset {
+        Dataset::Klines(_) => KLINE_HEADER,
+        Dataset::AggTrades => AGG_TRADE_HEADER,
+        Dataset::BookDepth => BOOK_DEPTH_HEADER,
+    }
+}
+
+pub fn output_path(dir: &Path, dataset: &Dataset, symbol: &str) -> PathBuf {
+    let symbol = symbol.to_lowercase();
+    match dataset {
+        Dataset::Klines(interval) => dir
+            .join("klines")
+            .join(format!("{}_{}.csv", symbol, interval)),
+        Dataset::AggTrades => dir
+            .join("aggtrades")
+            .join(format!("{}_aggtrades.csv", symbol)),
+        Dataset::BookDepth => dir
+            .join("bookdepth")
+            .join(format!("{}_bookdepth.csv", symbol)),
+    }
+}
+
+/// 现货归档从 2025 年开始使用微秒时间戳，统一成毫秒
+fn millis(field: &str) -> anyhow::Result<i64> {
+    let ts: i64 = field.trim().parse()?;
+    Ok(if ts > 100_000_000_000_000 {
+        ts / 1000
+    } else {
+        ts
+    })
+}
+
+fn utc(ts: i64) -> String {
+    DateTime::from_timestamp_millis(ts)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// 与 Python csv 模块写出的布尔值保持一致
+fn py_bool(field: &str) -> &'static str {
+    if field.trim().eq_ignore_ascii_case("true") {
+        "True"
+    } else {
+        "False"
+    }
+}
+
+/// 合约归档带表头，现货归档没有，首列不是数字的行直接跳过
+fn data_lines(csv: &str) -> impl Iterator<Item = Vec<&str>> {
+    csv.lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|line| line.split(',').collect())
+}
+
+/// 把归档 csv 转换成目标格式的行
+pub fn normalize(dataset: &Dataset, symbol: &str, csv: &str) -> anyhow::Result<Vec<String>> {
+    let symbol = symbol.to_lowercase();
+    let mut rows = Vec::new();
+
+    for f in data_lines(csv) {
+        let row = match dataset {
+            // open_time,open,high,low,close,volume,close_time,quote_volume,count,
+            // taker_buy_volume,taker_buy_quote_volume,ignore
+            Dataset::Klines(interval) => {
+                if f.len() < 11 {
+                    anyhow::bail!("invalid kline row {:?}", f);
+                }
+                let open_time = millis(f[0])?;
+                format!(
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},True,{}",
+                    symbol,
+                    interval,
+                    open_time,
+                    millis(f[6])?,
+                    f[1],
+                    f[2],
+                    f[3],
+                    f[4],
+                    f[5],
+                    f[7],
+                    f[8],
+                    f[9],
+                    f[10],
+                    utc(open_time)
+                )
+            }
+            // agg_trade_id,price,quantity,first_trade_id,last_trade_id,transact_time,is_buyer_maker[,is_best_match]
+            Dataset::AggTrades => {
+                if f.len() < 7 {
+                    anyhow::bail!("invalid aggTrades row {:?}", f);
+                }
+                let time = millis(f[5])?;
+                format!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    symbol,
+                    f[0],
+                    f[1],
+                    f[2],
+                    f[3],
+                    f[4],
+                    time,
+                    py_bool(f[6]),
+                    utc(time)
+                )
+            }
+            // timestamp,percentage,depth,notional（timestamp 为 UTC 字符串）
+            Dataset::BookDepth => {
+                if f.len() < 4 {
+                    anyhow::bail!("invalid bookDepth row {:?}", f);
+                }
+                let time = NaiveDateTime::parse_from_str(f[0].trim(), "%Y-%m-%d %H:%M:%S")?
+                    .and_utc()
+                    .timestamp_millis();
+                format!(
+                    "{},{},{},{},{},{}",
+                    symbol,
+                    time,
+                    f[1],
+                    f[2],
+                    f[3],
+                    utc(time)
+                )
+            }
+        };
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// 追加写入，新文件先写表头
+pub fn append(path: &Path, header: &str, rows: &[String]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let exists = path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if !exists {
+        writeln!(file, "{}", header)?;
+    }
+    for row in rows {
+        writeln!(file, "{}", row)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kline() {
+        // 合约归档带表头
+        let csv = "open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore\n\
+                   1704153600000,42283.58,42298.62,42261.02,42298.61,35.92724,1704153659999,1519124.78,1327,20.35272,860648.55,0\n";
+        let rows = normalize(&Dataset::Klines("1m".into()), "BTCUSDT", csv).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0],
+            "btcusdt,1m,1704153600000,1704153659999,42283.58,42298.62,42261.02,42298.61,35.92724,1519124.78,1327,20.35272,860648.55,True,2024-01-02 00:00:00"
+        );
+        assert_eq!(rows[0].split(',').count(), KLINE_HEADER.split(',').count());
+
+        // 现货 2025 年后的微秒时间戳
+        let csv = "1735689600000000,1,2,0.5,1.5,10,1735689659999999,15,3,4,6,0";
+        let rows = normalize(&Dataset::Klines("1m".into()), "btcusdt", csv).unwrap();
+        assert!(rows[0].starts_with("btcusdt,1m,1735689600000,1735689659999,"));
+    }
+
+    #[test]
+    fn test_agg_trades() {
+        let csv = "3359525,42283.58,0.00100000,3355746,3355746,1704153600034,false,true\n";
+        let rows = normalize(&Dataset::AggTrades, "btcusdt", csv).unwrap();
+        assert_eq!(
+            rows[0],
+            "btcusdt,3359525,42283.58,0.00100000,3355746,3355746,1704153600034,False,2024-01-02 00:00:00"
+        );
+    }
+
+    #[test]
+    fn test_book_depth() {
+        let csv =
+            "timestamp,percentage,depth,notional\n2024-01-02 00:00:08,-5,6504.5,275012371.8\n";
+        let rows = normalize(&Dataset::BookDepth, "ETHUSDT", csv).unwrap();
+        assert_eq!(
+            rows[0],
+            "ethusdt,1704153608000,-5,6504.5,275012371.8,2024-01-02 00:00:08"
+        );
+    }
+
+    #[test]
+    fn test_invalid_row() {
+        assert!(normalize(&Dataset::Klines("1m".into()), "btcusdt", "1,2,3").is_err());
+    }
+}
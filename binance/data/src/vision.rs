@@ -0,0 +1,195 @@
+//! Binance Vision 公共历史数据
+//!
+//! 归档地址格式：
+//! `{base}/data/{spot|futures/um}/daily/{dataset}/{SYMBOL}/[interval/]{SYMBOL}-{..}-{date}.zip`
+//! 每个 zip 旁边都有一个 `.CHECKSUM` 文件，内容为 `sha256  文件名`。
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Read};
+use tracing::debug;
+
+/// 现货或 U 本位合约
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketType {
+    Spot,
+    Um,
+}
+
+impl MarketType {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Self::Spot => "spot",
+            Self::Um => "futures/um",
+        }
+    }
+}
+
+/// 归档数据集
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dataset {
+    Klines(String),
+    AggTrades,
+    /// 只有合约提供，按价格百分比档位聚合的深度快照
+    BookDepth,
+}
+
+impl Dataset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Klines(_) => "klines",
+            Self::AggTrades => "aggTrades",
+            Self::BookDepth => "bookDepth",
+        }
+    }
+}
+
+/// 归档文件相对路径（不含 base url）
+pub fn archive_path(
+    market: MarketType,
+    symbol: &str,
+    dataset: &Dataset,
+    date: NaiveDate,
+) -> String {
+    let symbol = symbol.to_uppercase();
+    let date = date.format("%Y-%m-%d");
+    match dataset {
+        Dataset::Klines(interval) => format!(
+            "data/{}/daily/klines/{}/{}/{}-{}-{}.zip",
+            market.prefix(),
+            symbol,
+            interval,
+            symbol,
+            interval,
+            date
+        ),
+        _ => format!(
+            "data/{}/daily/{}/{}/{}-{}-{}.zip",
+            market.prefix(),
+            dataset.name(),
+            symbol,
+            symbol,
+            dataset.name(),
+            date
+        ),
+    }
+}
+
+/// 校验归档的 sha256，`checksum` 为 `.CHECKSUM` 文件的内容
+pub fn verify_checksum(archive: &[u8], checksum: &str) -> anyhow::Result<()> {
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty checksum"))?
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(archive));
+    if actual != expected {
+        anyhow::bail!("checksum mismatch, expected {} actual {}", expected, actual);
+    }
+    Ok(())
+}
+
+/// 解压归档中的第一个 csv 文件
+pub fn extract_csv(archive: &[u8]) -> anyhow::Result<String> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.name().ends_with(".csv") {
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            return Ok(content);
+        }
+    }
+    anyhow::bail!("no csv file in archive")
+}
+
+/// 下载器
+pub struct Vision {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl Vision {
+    pub fn new(base: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base: base.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// 下载并校验归档，归档不存在（例如当日数据尚未发布）时返回 None
+    pub async fn fetch(&self, path: &str) -> anyhow::Result<Option<String>> {
+        let url = format!("{}/{}", self.base, path);
+        debug!("GET {}", url);
+
+        let rsp = self.client.get(&url).send().await?;
+        if rsp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let archive = rsp.error_for_status()?.bytes().await?;
+
+        let checksum = self
+            .client
+            .get(format!("{}.CHECKSUM", url))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        verify_checksum(&archive, &checksum)?;
+
+        Ok(Some(extract_csv(&archive)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_archive_path() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(
+            archive_path(
+                MarketType::Spot,
+                "btcusdt",
+                &Dataset::Klines("1m".into()),
+                date
+            ),
+            "data/spot/daily/klines/BTCUSDT/1m/BTCUSDT-1m-2024-01-02.zip"
+        );
+        assert_eq!(
+            archive_path(MarketType::Um, "ethusdt", &Dataset::AggTrades, date),
+            "data/futures/um/daily/aggTrades/ETHUSDT/ETHUSDT-aggTrades-2024-01-02.zip"
+        );
+        assert_eq!(
+            archive_path(MarketType::Um, "ethusdt", &Dataset::BookDepth, date),
+            "data/futures/um/daily/bookDepth/ETHUSDT/ETHUSDT-bookDepth-2024-01-02.zip"
+        );
+    }
+
+    #[test]
+    fn test_checksum_and_extract() {
+        let mut buf = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut buf));
+            zip.start_file(
+                "BTCUSDT-1m-2024-01-02.csv",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+            zip.write_all(b"1,2,3\n").unwrap();
+            zip.finish().unwrap();
+        }
+
+        let checksum = format!("{:x}  BTCUSDT-1m-2024-01-02.zip", Sha256::digest(&buf));
+        assert!(verify_checksum(&buf, &checksum).is_ok());
+        assert!(verify_checksum(&buf, "deadbeef  BTCUSDT-1m-2024-01-02.zip").is_err());
+        assert!(verify_checksum(&buf, "").is_err());
+
+        assert_eq!(extract_csv(&buf).unwrap(), "1,2,3\n");
+    }
+}
@@ -0,0 +1,98 @@
+//! 比较运行时配置下行情扇出的延迟
+//!
+//! 一个线程模拟交易所推送，打上发送时间后交给"行情扇出"任务，扇出任务再转发给若干个模拟策略端的任务，
+//! 策略端记录从发送到收到的延迟。分别在共享的多线程运行时与独立的行情线程上运行，输出分位数。
+//!
+//! ```sh
+//! cargo run --release -p binance --example runtime_latency -- 8 20000
+//! ```
+
+use cryptoflow::runtime::RuntimeConfig;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+async fn fan_out(
+    mut rx: mpsc::UnboundedReceiver<Instant>,
+    clients: Vec<mpsc::UnboundedSender<Instant>>,
+) {
+    while let Some(sent) = rx.recv().await {
+        for client in clients.iter() {
+            let _ = client.send(sent);
+        }
+    }
+}
+
+fn measure(name: &str, config: RuntimeConfig, clients: usize, events: usize) -> anyhow::Result<()> {
+    let runtime = config.build()?;
+    let latencies = runtime.block_on(async {
+        let (feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let mut senders = Vec::new();
+        let mut handles = Vec::new();
+        for _ in 0..clients {
+            let (tx, mut rx) = mpsc::unbounded_channel::<Instant>();
+            senders.push(tx);
+            handles.push(tokio::spawn(async move {
+                let mut latencies = Vec::with_capacity(events);
+                while let Some(sent) = rx.recv().await {
+                    latencies.push(sent.elapsed());
+                }
+                latencies
+            }));
+        }
+        config.spawn_market(fan_out(feed_rx, senders))?;
+
+        std::thread::spawn(move || {
+            for _ in 0..events {
+                let _ = feed_tx.send(Instant::now());
+                std::thread::sleep(Duration::from_micros(100));
+            }
+        });
+
+        let mut latencies = Vec::new();
+        for handle in handles {
+            latencies.extend(handle.await?);
+        }
+        anyhow::Ok(latencies)
+    })?;
+
+    let mut latencies = latencies;
+    latencies.sort_unstable();
+    let at = |q: f64| latencies[((latencies.len() - 1) as f64 * q) as usize];
+    println!(
+        "{:<24} p50 {:>8.1?} p99 {:>8.1?} p99.9 {:>8.1?} max {:>8.1?}",
+        name,
+        at(0.5),
+        at(0.99),
+        at(0.999),
+        latencies.last().copied().unwrap_or_default()
+    );
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let clients = args.next().and_then(|s| s.parse().ok()).unwrap_or(8);
+    let events = args.next().and_then(|s| s.parse().ok()).unwrap_or(20000);
+    println!("{} clients, {} events", clients, events);
+
+    measure("shared runtime", RuntimeConfig::default(), clients, events)?;
+    measure(
+        "2 workers",
+        RuntimeConfig {
+            worker_threads: Some(2),
+            ..Default::default()
+        },
+        clients,
+        events,
+    )?;
+    measure(
+        "dedicated market thread",
+        RuntimeConfig {
+            market_thread: true,
+            ..Default::default()
+        },
+        clients,
+        events,
+    )?;
+    Ok(())
+}
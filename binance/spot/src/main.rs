@@ -1,21 +1,128 @@
 mod trade;
 
 use crate::rest::Rest;
-use binance::{event_handlers::DefaultUserDataHandler, *};
+use binance::admin::AdminConfig;
+use binance::apikey::{check_api_key, ApiKeyConfig, Permission};
+use binance::bar::BarClockConfig;
+use binance::capability::Capability;
+use binance::credential::CredentialConfig;
+use binance::depth_check::{DepthBooks, DepthCheckConfig};
+use binance::fat_finger::FatFingerConfig;
+use binance::flatten::{Flatten, FlattenConfig};
+use binance::gtx_retry::GtxRetryConfig;
+use binance::halt::Halts;
+use binance::heartbeat::HeartbeatConfig;
+use binance::hub::{Hub, HubConfig};
+use binance::idempotency::IdempotencyConfig;
+use binance::inflight::InFlightConfig;
+use binance::liquidity::LiquidityConfig;
+use binance::margin::MarginConfig;
+use binance::ops_report::{OpsReport, OpsReportConfig};
+use binance::order_caps::OrderCapsConfig;
+use binance::order_check::OrderCheckConfig;
+use binance::params::{ParamStore, ParamsConfig};
+use binance::peg::{BookTickers, PegConfig};
+use binance::pm::{AccountMode, PmUserStream, PortfolioMarginConfig};
+use binance::prefetch::{Prefetch, PrefetchConfig, PrefetchPaths};
+use binance::venue_status::{self, VenueStatus, VenueStatusConfig};
+use binance::watchdog::WatchdogConfig;
+use binance::{event_handlers::DefaultUserDataHandler, fee::FeeBalanceConfig, *};
 use clap::Parser;
-use cryptoflow::init_tracing;
+use cryptoflow::alert::{AlertConfig, Alerter};
+use cryptoflow::fx::{FxConfig, FxRates};
+use cryptoflow::my_trades::MyTradesConfig;
+use cryptoflow::portfolio::{Portfolio, PortfolioConfig};
+use cryptoflow::runtime::RuntimeConfig;
+use cryptoflow::storage::{self, StorageConfig};
+use cryptoflow::tenant::{TenantConfig, Tenants};
+use cryptoflow::{init_tracing_with_config, LogConfig};
 use serde::Deserialize;
 use std::sync::Arc;
 use tracing::{error, info};
 use trade::SpotTrade;
-use websocket::Credentials;
+use websocket::{ChannelConfig, Credentials, ListenerConfig};
 
 #[derive(Debug, Deserialize)]
 struct Config {
     margin: bool,
+    #[serde(default)]
+    margin_account: MarginConfig,
+    #[serde(default)]
+    account_mode: AccountMode,
+    #[serde(default)]
+    portfolio_margin: PortfolioMarginConfig,
+    #[serde(default)]
     apikey: String,
+    #[serde(default)]
     pem: String,
+    credential: Option<CredentialConfig>,
     local: String,
+    #[serde(default)]
+    listener: ListenerConfig,
+    #[serde(default)]
+    channel: ChannelConfig,
+    #[serde(default)]
+    market: MarketConfig,
+    #[serde(default)]
+    alert: AlertConfig,
+    #[serde(default)]
+    portfolio: PortfolioConfig,
+    #[serde(default)]
+    tenants: Vec<TenantConfig>,
+    #[serde(default)]
+    storage: StorageConfig,
+    #[serde(default)]
+    fx: FxConfig,
+    #[serde(default)]
+    fee_balance: FeeBalanceConfig,
+    #[serde(default)]
+    api_key: ApiKeyConfig,
+    #[serde(default)]
+    capability: Capability,
+    #[serde(default)]
+    in_flight: InFlightConfig,
+    #[serde(default)]
+    idempotency: IdempotencyConfig,
+    #[serde(default)]
+    peg: PegConfig,
+    #[serde(default)]
+    log: LogConfig,
+    #[serde(default)]
+    bar_clock: BarClockConfig,
+    #[serde(default)]
+    runtime: RuntimeConfig,
+    #[serde(default)]
+    params: ParamsConfig,
+    #[serde(default)]
+    admin: AdminConfig,
+    #[serde(default)]
+    flatten: FlattenConfig,
+    #[serde(default)]
+    heartbeat: HeartbeatConfig,
+    #[serde(default)]
+    venue_status: VenueStatusConfig,
+    #[serde(default)]
+    watchdog: WatchdogConfig,
+    #[serde(default)]
+    liquidity: LiquidityConfig,
+    #[serde(default)]
+    order_check: OrderCheckConfig,
+    #[serde(default)]
+    fat_finger: FatFingerConfig,
+    #[serde(default)]
+    depth_check: DepthCheckConfig,
+    #[serde(default)]
+    order_caps: OrderCapsConfig,
+    #[serde(default)]
+    ops_report: OpsReportConfig,
+    #[serde(default)]
+    hub: HubConfig,
+    #[serde(default)]
+    gtx_retry: GtxRetryConfig,
+    #[serde(default)]
+    prefetch: PrefetchConfig,
+    #[serde(default)]
+    my_trades: MyTradesConfig,
 }
 
 #[derive(Debug, Parser)]
@@ -25,6 +132,8 @@ struct Args {
     config: String,
     #[arg(short, long, default_value_t = tracing::Level::INFO)]
     level: tracing::Level,
+    #[arg(long, help = "Run without sending orders to the exchange")]
+    dry_run: bool,
 }
 
 impl Args {
@@ -36,11 +145,13 @@ impl Args {
     }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     let config = args.load()?;
+    config.runtime.build()?.block_on(run(args, config))
+}
 
+async fn run(args: Args, config: Config) -> anyhow::Result<()> {
     let path = std::env::current_exe()?;
     let filename = match path.file_name() {
         Some(name) => name.to_string_lossy(),
@@ -48,28 +159,144 @@ async fn main() -> anyhow::Result<()> {
     };
 
     // 初始化日志
-    let _guard = init_tracing(&filename, "log", &args.level.to_string().to_lowercase())?;
+    let _guard = init_tracing_with_config(
+        &filename,
+        &config.log,
+        &args.level.to_string().to_lowercase(),
+    )?;
+    config.runtime.log();
+
+    let alerter = Alerter::new(config.alert.clone());
+    let fx = FxRates::new(config.fx.clone());
+    let tenants = Tenants::new(config.tenants.clone())?;
+    let portfolio = Portfolio::new(config.portfolio.clone())
+        .with_fx(fx.clone())
+        .with_tenants(tenants.clone());
+    let book_tickers = BookTickers::default();
+    let halts = Halts::default();
+    let depth_books = DepthBooks::default();
+    let venue_status = VenueStatus::new(config.venue_status.clone(), trade::VENUE);
+    venue_status.spawn_refresh(venue_status::SPOT);
+    let ops_report = OpsReport::new(config.ops_report.clone());
+
+    let credential =
+        CredentialConfig::or_plain(config.credential.clone(), &config.apikey, &config.pem)
+            .load()?;
+    let rest = Arc::new(
+        Rest::from_pem(
+            "https://api.binance.com",
+            &credential.apikey,
+            credential.pem.as_bytes(),
+            3000,
+        )?
+        .with_capability(config.capability),
+    );
+    // 连接行情与账户的同时预取，结束后才接受策略端连接
+    let prefetch = Prefetch::spawn(
+        &config.prefetch,
+        rest.clone(),
+        rest.clone(),
+        PrefetchPaths::spot(),
+    );
 
     // 创建websocket server，接收Python策略端发送的请求
-    let app = Application::new(&config.local).await?;
+    let app = Application::new(&config.local)
+        .await?
+        .with_listener(&config.listener)?
+        .with_channel(config.channel.clone())
+        .with_idempotency(config.idempotency.clone())
+        .with_runtime(config.runtime.clone())
+        .with_alerter(alerter.clone())
+        .with_portfolio(portfolio.clone())
+        .with_params(ParamStore::open(&config.params)?)
+        .with_admin(config.admin.clone())
+        .with_flatten(Flatten::new(&config.flatten)?)
+        .with_heartbeat(config.heartbeat.clone())
+        .with_tenants(tenants.clone())
+        .with_halts(halts.clone())
+        .with_venue_status(venue_status)
+        .with_ops_report(ops_report.clone())
+        .with_prefetch(prefetch.clone());
 
-    let market = Market::new().await?;
+    let storage = storage::open(&config.storage.url).await?;
+    ops_report.spawn(storage.clone(), alerter.clone(), trade::VENUE);
+    let mut market = Market::new()
+        .await?
+        .with_config(&config.market)
+        .with_storage(storage.clone(), &config.storage)
+        .with_prefetched(prefetch.prefetched())
+        .with_bar_clock(&config.bar_clock)
+        .with_book_tickers(book_tickers.clone())
+        .with_depth_check(&config.depth_check, depth_books.clone())
+        .with_venue(trade::VENUE)
+        .with_fx(fx.clone())
+        .await?
+        .with_options(&config.market.options)
+        .await?;
 
-    let rest = Arc::new(Rest::new(
-        "https://api.binance.com",
-        &config.apikey,
-        &config.pem,
-        3000,
-    )?);
+    // 行情中心不交易，不检查 API Key 的交易权限，也不连接账户推送
+    if config.hub.enabled {
+        let (products, _) = trade::get_positions(&rest).await?;
+        let hub = Hub::new(products, trade::validate_stream);
+        hub.warm(&mut market, &config.hub.streams).await?;
+        if let Err(e) = app.keep_running(market, hub).await {
+            error!("{}", e);
+        }
+        return Ok(());
+    }
 
-    let credentials = Credentials::new(config.apikey, config.pem, "".to_string(), "0");
+    // 统一账户的现货交易走全仓杠杆
+    let pm = config.account_mode == AccountMode::PortfolioMargin;
+    let margin = config.margin || pm;
+    let mut required = vec![Permission::Reading, Permission::Spot];
+    if margin {
+        required.push(Permission::Margin);
+    }
+    if pm {
+        required.push(Permission::PortfolioMargin);
+    }
+    let required = config.capability.permissions(required);
+    check_api_key(rest.clone(), &required, config.api_key, alerter.clone()).await?;
 
-    let account = Account::new(&credentials, DefaultUserDataHandler).await;
+    let credentials = Credentials::new(credential.apikey, credential.pem, "".to_string(), "0");
 
-    let state = account.get_stream_state();
-    info!("{:?}", state);
+    let account = match config.account_mode {
+        AccountMode::Classic => {
+            let account = Account::new(&credentials, DefaultUserDataHandler).await;
+            info!("{:?}", account.get_stream_state());
+            account.into()
+        }
+        AccountMode::PortfolioMargin => {
+            PmUserStream::connect(config.account_mode.trading_rest(&rest))
+                .await?
+                .into()
+        }
+    };
 
-    let trade = SpotTrade::new(rest.clone(), account, config.margin).await?;
+    let trade = SpotTrade::new(rest.clone(), account, margin, config.account_mode)
+        .await?
+        .with_storage(storage.clone(), &config.storage, tenants.clone())
+        .await?
+        .with_margin(config.margin_account)
+        .with_portfolio_margin(config.portfolio_margin)
+        .with_alerter(alerter)
+        .with_dry_run(args.dry_run)
+        .with_in_flight(config.in_flight)
+        .with_pegs(config.peg, book_tickers)
+        .with_watchdog(config.watchdog)
+        .with_liquidity(config.liquidity)
+        .with_order_check(config.order_check)
+        .with_fat_finger(config.fat_finger)
+        .with_gtx_retry(config.gtx_retry)
+        .with_halts(halts)
+        .with_depth_check(config.depth_check, depth_books)
+        .with_order_caps(config.order_caps)
+        .with_ops_report(ops_report)
+        .with_fx(fx)
+        .with_fee_monitor(config.fee_balance)
+        .with_portfolio(portfolio)
+        .with_my_trades(config.my_trades)
+        .await?;
     if let Err(e) = app.keep_running(market, trade).await {
         error!("{}", e);
     }
@@ -1,31 +1,66 @@
-use crate::rest::Rest;
+use crate::rest::{order_params, Rest};
 use ::serde::Serialize;
-use binance::event_handlers::DefaultUserDataHandler;
+use binance::dedup::{EventKey, OrderEvents};
+use binance::depth_check::{self, DepthBooks, DepthCheckConfig, Verdict};
+use binance::dryrun::{self, DryRun};
+use binance::fat_finger::{self, FatFingerConfig};
+use binance::fee::{FeeAction, FeeBalanceConfig, FeeMonitor};
+use binance::gtx_retry::{GtxRetries, GtxRetryConfig};
+use binance::halt::Halts;
+use binance::inflight::{InFlight, InFlightConfig};
+use binance::lifecycle::{correlation_id, order_span, Lifecycle, Stage};
+use binance::liquidity::{Liquidity, LiquidityConfig};
+use binance::margin::{LoanType, MarginAccount, MarginConfig, SLoan};
+use binance::model::filter::FilterField;
 use binance::model::order::BinanceCancel;
-use binance::model::order::BinanceOrder;
+use binance::model::order::{BinanceOrder, BinanceQuote};
 use binance::model::symbol::BinanceSymbol;
-use binance::model::user_data::UserDataEvent;
-use binance::model::EventMessage;
-use binance::model::{Event, ExecutionReport};
+use binance::model::user_data::{MarginLevelStatusChange, OutboundAccountPosition, UserDataEvent};
+use binance::model::{Event, ExecutionReport, RiskLevelChange};
+use binance::my_trades::MyTrades;
+use binance::ops_report::OpsReport;
+use binance::order_caps::{Admission, Limits, OrderCaps, OrderCapsConfig};
+use binance::order_check::{self, OrderCheckConfig};
+use binance::peg::{BookTickers, PegAction, PegConfig, Pegs};
+use binance::pm::{AccountMode, PmRisk, PortfolioMarginConfig};
+use binance::post_only;
+use binance::quote::{QuoteAction, Quotes};
+use binance::replace::{ReplaceAction, Replaces};
+use binance::sanity::{self, working_orders, ExchangeState, LocalState};
+use binance::state;
+use binance::watchdog::{OrderWatchdog, WatchdogConfig};
+use binance::wire::ClientSender;
 use binance::*;
+use cryptoflow::alert::Alerter;
 use cryptoflow::chat::*;
 use cryptoflow::error_code::*;
-use cryptoflow::parser::JsonParser;
+use cryptoflow::fx::FxRates;
+use cryptoflow::income::{Income, SIncomeReq};
+use cryptoflow::my_trades::{MyTrade, MyTradesConfig, SMyTradesReq};
+use cryptoflow::parser::RequestParser;
+use cryptoflow::portfolio::Portfolio;
 use cryptoflow::position::PositionDB;
-use native_json::Deserialize;
-use std::collections::HashMap;
+use cryptoflow::storage::{Storage, StorageConfig};
+use cryptoflow::tenant::Tenants;
+use cryptoflow::trading_rules::TradingRules;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::mpsc::UnboundedSender;
-use tracing::{debug, error, info, warn};
-use tungstenite::Message;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn, Instrument};
 
-/// 通过rest api获取所有交易对
-async fn get_positions(rest: &Arc<Rest>) -> anyhow::Result<HashMap<String, BinanceSymbol>> {
+/// 通过rest api获取所有交易对，同时返回账户的挂单数上限
+pub(crate) async fn get_positions(
+    rest: &Arc<Rest>,
+) -> anyhow::Result<(HashMap<String, BinanceSymbol>, Limits)> {
     let rsp = rest.get("/api/v3/exchangeInfo", &[], false).await?;
     let results: serde_json::Value = serde_json::from_str(&rsp.text().await?)?;
 
+    let exchange_filters: Vec<FilterField> = match results.get("exchangeFilters") {
+        Some(filters) => serde_json::from_value(filters.to_owned())?,
+        None => Vec::new(),
+    };
     let results = results.get("symbols").unwrap();
     let results: Vec<BinanceSymbol> = serde_json::from_value(results.to_owned())?;
     let mut products = HashMap::new();
@@ -35,43 +70,570 @@ async fn get_positions(rest: &Arc<Rest>) -> anyhow::Result<HashMap<String, Binan
     }
 
     info!("There are {} products in binance spot", products.len());
-    Ok(products)
+    Ok((products, Limits::of_exchange(&exchange_filters)))
 }
 
+/// 交易对之后的流名称是否可以订阅，行情中心模式也用它校验，见 [`binance::hub`]
+pub fn validate_stream(stream: &str) -> bool {
+    match stream.split_once(":") {
+        Some((stream, interval)) => match stream {
+            "kline" => matches!(
+                interval,
+                "1s" | "1m"
+                    | "3m"
+                    | "5m"
+                    | "15m"
+                    | "30m"
+                    | "1h"
+                    | "2h"
+                    | "4h"
+                    | "6h"
+                    | "8h"
+                    | "12h"
+                    | "1d"
+                    | "3d"
+                    | "1w"
+                    | "1M"
+            ),
+            "depth" => matches!(interval, "100ms"),
+            "cvd" => binance::flow::window_ms(interval).is_some(),
+            _ => false,
+        },
+        None => matches!(stream, "depth" | "bbo"),
+    }
+}
+
+/// 现货账户每个资产的可用与冻结余额之和
+async fn get_balances(rest: &Arc<Rest>) -> anyhow::Result<BTreeMap<String, f64>> {
+    let rsp = rest.get("/api/v3/account", &[], true).await?;
+    let value: serde_json::Value = serde_json::from_str(&rsp.text().await?)?;
+    let balances = value["balances"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+    Ok(balances
+        .iter()
+        .filter_map(|b| {
+            let amount = |key: &str| b[key].as_str()?.parse::<f64>().ok();
+            Some((
+                b["asset"].as_str()?.to_string(),
+                amount("free")? + amount("locked")?,
+            ))
+        })
+        .collect())
+}
+
+/// 组合敞口中的交易场所名
+pub const VENUE: &str = "binance-spot";
+
+/// 现货成交接口单次查询的最大时间跨度
+const MY_TRADES_WINDOW: i64 = 24 * 3600 * 1000;
+
+/// 网关自己下单（例如补充 BNB）使用的 session，不对应任何策略
+const GATEWAY_SESSION: u16 = u16::MAX;
+
 pub struct SpotTrade {
+    /// 交易与账户接口
     rest: Arc<Rest>,
+    /// 交易规则等公开接口
+    public: Arc<Rest>,
+    mode: AccountMode,
 
-    account: Account<DefaultUserDataHandler>,
-    margin: bool,
+    account: UserStream,
+    /// 杠杆账户，现货账户时为空
+    margin: Option<MarginAccount>,
+    /// 统一账户的保证金检查，经典账户时为空
+    pm: Option<PmRisk>,
     // addr -> tx
-    txs: HashMap<SocketAddr, UnboundedSender<Message>>,
+    txs: HashMap<SocketAddr, ClientSender>,
     // addr -> session_id
     session_id_map: HashMap<SocketAddr, u16>,
     // session_id -> session
     session_map: HashMap<u16, Session>,
     posdb: Arc<PositionDB>,
     products: HashMap<String, BinanceSymbol>,
+    alerter: Alerter,
+    portfolio: Portfolio,
+    in_flight: InFlight,
+    lifecycle: Lifecycle,
+    /// 丢弃账户推送中重复的订单回报
+    events: OrderEvents,
+    liquidity: Liquidity,
+    order_check: OrderCheckConfig,
+    fat_finger: FatFingerConfig,
+    gtx_retries: GtxRetries,
+    halts: Halts,
+    depth_check: DepthCheckConfig,
+    depth_books: DepthBooks,
+    order_caps: OrderCaps,
+    /// 账户的挂单数上限
+    exchange_limits: Limits,
+    ops_report: OpsReport,
+    dry_run: Option<DryRun>,
+    quotes: Quotes,
+    pegs: Pegs,
+    replaces: Replaces,
+    watchdog: OrderWatchdog,
+    book_tickers: BookTickers,
+    fx: FxRates,
+    fee: FeeMonitor,
+    my_trades: Option<MyTrades>,
+    gateway_order_id: u32,
+    /// 资产 -> 账户推送中的可用与冻结余额之和
+    balances: BTreeMap<String, f64>,
 }
 
 impl SpotTrade {
     pub async fn new(
-        rest: Arc<Rest>,
-        account: Account<DefaultUserDataHandler>,
+        public: Arc<Rest>,
+        account: UserStream,
         margin: bool,
+        mode: AccountMode,
     ) -> anyhow::Result<Self> {
-        let products = get_positions(&rest).await?;
+        let (products, exchange_limits) = get_positions(&public).await?;
 
         Ok(Self {
-            rest,
+            rest: mode.trading_rest(&public),
+            public,
+            mode,
             txs: HashMap::default(),
             account,
-            margin,
+            margin: margin.then(MarginAccount::default),
+            pm: None,
             session_id_map: HashMap::default(),
             session_map: HashMap::default(),
             posdb: Arc::new(PositionDB::new("pos.db").await?),
             products,
+            alerter: Alerter::default(),
+            portfolio: Portfolio::default(),
+            in_flight: InFlight::default(),
+            lifecycle: Lifecycle::default(),
+            events: OrderEvents::default(),
+            liquidity: Liquidity::default(),
+            order_check: OrderCheckConfig::default(),
+            fat_finger: FatFingerConfig::default(),
+            gtx_retries: GtxRetries::default(),
+            halts: Halts::default(),
+            depth_check: DepthCheckConfig::default(),
+            depth_books: DepthBooks::default(),
+            order_caps: OrderCaps::default(),
+            exchange_limits,
+            ops_report: OpsReport::default(),
+            dry_run: None,
+            quotes: Quotes::default(),
+            pegs: Pegs::default(),
+            replaces: Replaces::default(),
+            watchdog: OrderWatchdog::default(),
+            book_tickers: BookTickers::default(),
+            fx: FxRates::default(),
+            fee: FeeMonitor::new(FeeBalanceConfig::default()),
+            my_trades: None,
+            gateway_order_id: 0,
+            balances: BTreeMap::default(),
         })
     }
+
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = alerter;
+        self
+    }
+
+    /// 杠杆账户的借还款方式与风险率检查，并开始定时查询风险率
+    /// 统一账户只有全仓，风险率由统一账户的 uniMMR 代替
+    pub fn with_margin(mut self, config: MarginConfig) -> Self {
+        if self.margin.is_none() {
+            return self;
+        }
+        let margin = match self.mode {
+            AccountMode::Classic => {
+                let margin = MarginAccount::new(config);
+                margin.spawn_refresh(self.rest.clone());
+                margin
+            }
+            AccountMode::PortfolioMargin => {
+                if config.isolated {
+                    warn!("Portfolio margin does not support isolated margin, use cross margin");
+                }
+                MarginAccount::new(MarginConfig {
+                    isolated: false,
+                    min_margin_level: 0.0,
+                    ..config
+                })
+            }
+        };
+        self.margin = Some(margin);
+        self
+    }
+
+    /// 统一账户按 uniMMR 检查新订单与借币，并开始定时查询统一账户
+    pub fn with_portfolio_margin(mut self, config: PortfolioMarginConfig) -> Self {
+        if self.mode == AccountMode::PortfolioMargin {
+            let pm = PmRisk::new(config);
+            pm.spawn_refresh(self.rest.clone());
+            self.pm = Some(pm);
+        }
+        self
+    }
+
+    /// 演练模式：下单与撤单不发往交易所，以模拟回报应答策略端
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        if enabled {
+            warn!("Dry run, orders are not sent to the exchange");
+            self.dry_run = Some(DryRun::default());
+        }
+        self
+    }
+
+    /// 限制每个会话的在途请求数
+    pub fn with_in_flight(mut self, config: InFlightConfig) -> Self {
+        self.in_flight = InFlight::new(config);
+        self
+    }
+
+    /// 钉住订单按 market 维护的最优买卖价改价
+    pub fn with_pegs(mut self, config: PegConfig, book_tickers: BookTickers) -> Self {
+        self.pegs = Pegs::new(config);
+        self.book_tickers = book_tickers;
+        self
+    }
+
+    /// 挂单与吃单成交统计划分自然日的时区与保留的天数
+    pub fn with_liquidity(mut self, config: LiquidityConfig) -> Self {
+        self.liquidity = Liquidity::new(config);
+        self
+    }
+
+    /// 下单前按交易所的过滤器校验
+    pub fn with_order_check(mut self, config: OrderCheckConfig) -> Self {
+        self.order_check = config;
+        self
+    }
+
+    pub fn with_fat_finger(mut self, config: FatFingerConfig) -> Self {
+        self.fat_finger = config;
+        self
+    }
+
+    /// 只做 maker 的订单因为会立即成交被拒绝时改价重发
+    pub fn with_gtx_retry(mut self, config: GtxRetryConfig) -> Self {
+        self.gtx_retries = GtxRetries::new(config);
+        self
+    }
+
+    /// 与 handler 共享暂停交易的交易对，暂停期间拒绝这些交易对的新订单
+    pub fn with_halts(mut self, halts: Halts) -> Self {
+        self.halts = halts;
+        self
+    }
+
+    /// 吃单前按 market 维护的深度检查流动性
+    pub fn with_depth_check(mut self, config: DepthCheckConfig, books: DepthBooks) -> Self {
+        self.depth_check = config;
+        self.depth_books = books;
+        self
+    }
+
+    /// 订单会超过交易所的挂单数上限时排队下单
+    pub fn with_order_caps(mut self, config: OrderCapsConfig) -> Self {
+        self.order_caps = OrderCaps::new(config);
+        self.order_caps
+            .set_products(&self.products, self.exchange_limits);
+        self
+    }
+
+    /// 统计下单、回报与成交，写入每日运维报告
+    pub fn with_ops_report(mut self, ops_report: OpsReport) -> Self {
+        self.ops_report = ops_report;
+        self
+    }
+
+    /// 定时查询挂单，撤销或告警过期的订单；逐仓杠杆需要按交易对查询，不支持
+    pub fn with_watchdog(mut self, config: WatchdogConfig) -> Self {
+        self.watchdog = OrderWatchdog::new(config);
+        if !self.watchdog.enabled() {
+            return self;
+        }
+        let path = match &self.margin {
+            Some(margin) if !margin.cancel_params().is_empty() => {
+                warn!("Order watchdog does not support isolated margin");
+                return self;
+            }
+            Some(_) => self.mode.margin_open_orders(),
+            None => "/api/v3/openOrders",
+        };
+        self.watchdog.spawn_refresh(self.rest.clone(), path);
+        self
+    }
+
+    /// 登记所有交易对的基础资产与计价资产，供汇率表换算
+    pub fn with_fx(mut self, fx: FxRates) -> Self {
+        for product in self.products.values() {
+            fx.register(&product.symbol, &product.baseAsset, &product.quoteAsset);
+        }
+        self.fx = fx;
+        self
+    }
+
+    /// 监控手续费抵扣资产余额，需要自动买入时价格取自汇率表
+    pub fn with_fee_monitor(mut self, config: FeeBalanceConfig) -> Self {
+        self.fee = FeeMonitor::new(config);
+        self
+    }
+
+    /// 开启成交历史查询，杠杆账户查询杠杆成交
+    pub async fn with_my_trades(mut self, config: MyTradesConfig) -> anyhow::Result<Self> {
+        let path = match self.margin {
+            Some(_) => self.mode.margin_trades(),
+            None => "/api/v3/myTrades",
+        };
+        self.my_trades = MyTrades::open(config, path, MY_TRADES_WINDOW).await?;
+        Ok(self)
+    }
+
+    /// 持仓按租户分表保存到 `storage`，开启 `orders` 时同时保存订单回报与成交；需要在 `with_portfolio`
+    /// 与会话登录之前设置
+    pub async fn with_storage(
+        mut self,
+        storage: Arc<dyn Storage>,
+        config: &StorageConfig,
+        tenants: Tenants,
+    ) -> anyhow::Result<Self> {
+        let posdb = PositionDB::with_storage(storage, tenants).await?;
+        self.posdb = Arc::new(posdb.with_orders(config.orders));
+        Ok(self)
+    }
+
+    /// 设置组合敞口，并用已持久化的持仓初始化
+    pub fn with_portfolio(mut self, portfolio: Portfolio) -> Self {
+        for (session_id, positions) in self.posdb.sessions() {
+            for position in positions.values() {
+                portfolio.update_position(
+                    VENUE,
+                    session_id,
+                    &position.symbol,
+                    &self.underlying(&position.symbol),
+                    position.net,
+                );
+            }
+        }
+        self.portfolio = portfolio;
+        self
+    }
+
+    /// 交易对的标的资产，未知交易对按交易对本身计
+    fn underlying(&self, symbol: &str) -> String {
+        match self.products.get(&symbol.to_lowercase()) {
+            Some(product) => product.baseAsset.clone(),
+            None => symbol.to_uppercase(),
+        }
+    }
+
+    fn cancel_order(&mut self, session_id: u16, order_id: u32, symbol: &str) {
+        if let Some((addr, order)) = self.order_caps.cancel(session_id, order_id) {
+            self.cancel_queued(&addr, &order);
+            return;
+        }
+        if let Some(dry_run) = self.dry_run.as_mut() {
+            if let Some((tx, order)) = dry_run.cancel(session_id, order_id) {
+                self.on_dry_run(&tx, &order, State::CANCELED);
+            }
+            return;
+        }
+        let rest = self.rest.clone();
+        let symbol = symbol.to_uppercase();
+        let orig = u64::from(session_id) << 32 | u64::from(order_id);
+        // 撤单只登记不设上限，避免拥塞时连撤单也被拒
+        let guard = self.in_flight.acquire_unchecked(session_id);
+        let (path, extra) = match &self.margin {
+            Some(margin) => (self.mode.margin_order(), margin.cancel_params()),
+            None => ("/api/v3/order", Vec::new()),
+        };
+
+        tokio::spawn(async move {
+            let _guard = guard;
+            let result = match extra.is_empty() {
+                true => rest.cancel(path, symbol, orig).await,
+                false => {
+                    let mut params = vec![
+                        ("symbol".into(), symbol),
+                        ("origClientOrderId".into(), orig.to_string()),
+                    ];
+                    params.extend(extra);
+                    rest.delete(path, &params, true).await
+                }
+            };
+            if let Err(e) = result {
+                error!("{}", e)
+            }
+        });
+    }
+
+    /// 撤销排队中的订单，与演练模式一样由网关给出撤单回报
+    fn cancel_queued(&mut self, addr: &SocketAddr, order: &BinanceOrder) {
+        info!(
+            "Cancel queued order {} of session {}",
+            order.id, order.session_id
+        );
+        if let Some(tx) = self.txs.get(addr).cloned() {
+            self.on_dry_run(&tx, order, State::CANCELED);
+        }
+    }
+
+    /// 发出挂单数降到上限以下的排队订单，拒绝排队超时的订单
+    fn release_queued(&mut self) {
+        let (ready, expired) = self.order_caps.release(Instant::now());
+        for (addr, order) in expired {
+            warn!("Reject order {:?}: queued too long", order);
+            if let Some(tx) = self.txs.get(&addr).cloned() {
+                self.alerter
+                    .on_order_state(order.session_id, State::REJECTED);
+                self.reject(&tx, &order);
+            }
+        }
+        for (addr, order, cap) in ready {
+            info!(
+                "Release queued order {} of session {}",
+                order.id, order.session_id
+            );
+            let Some(tx) = self.txs.get(&addr) else {
+                self.order_caps.on_reject(order.session_id, order.id);
+                continue;
+            };
+            self.notify(tx, &cap);
+            if let Err(e) = self.add_order(&addr, &order) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    fn notify<T: Serialize>(&self, tx: &ClientSender, message: &T) {
+        if let Err(e) = tx.reply(message) {
+            error!("{}", e);
+        }
+    }
+
+    /// 告警并撤销过期订单巡检发现的订单，网关自己的买入订单除外
+    fn cancel_stale_orders(&mut self, now: Instant) {
+        for stale in self.watchdog.check(now) {
+            if stale.session_id == GATEWAY_SESSION {
+                continue;
+            }
+            warn!("{}", stale);
+            self.alerter
+                .on_stale_order(stale.session_id, stale.order_id, stale.to_string());
+            if stale.open() && self.watchdog.cancels() {
+                self.pegs.on_cancel(stale.session_id, stale.order_id);
+                self.replaces.on_cancel(stale.session_id, stale.order_id);
+                self.cancel_order(stale.session_id, stale.order_id, &stale.symbol);
+            }
+        }
+    }
+
+    fn apply_quotes(&mut self, actions: Vec<QuoteAction>) {
+        for action in actions {
+            match action {
+                QuoteAction::Place(addr, order) => {
+                    if let Err(e) = self.add_order(&addr, &order) {
+                        error!("{}", e);
+                    }
+                }
+                QuoteAction::Cancel {
+                    session_id,
+                    order_id,
+                    symbol,
+                } => self.cancel_order(session_id, order_id, &symbol),
+            }
+        }
+    }
+
+    fn apply_pegs(&mut self, actions: Vec<PegAction>) {
+        for action in actions {
+            match action {
+                PegAction::Place(addr, order) => {
+                    if let Err(e) = self.add_order(&addr, &order) {
+                        error!("{}", e);
+                    }
+                }
+                PegAction::Cancel {
+                    session_id,
+                    order_id,
+                    symbol,
+                } => self.cancel_order(session_id, order_id, &symbol),
+            }
+        }
+    }
+
+    fn apply_replaces(&mut self, actions: Vec<ReplaceAction>) {
+        for action in actions {
+            match action {
+                ReplaceAction::Place(addr, order) => {
+                    if let Err(e) = self.add_order(&addr, &order) {
+                        error!("{}", e);
+                    }
+                }
+                ReplaceAction::Cancel {
+                    session_id,
+                    order_id,
+                    symbol,
+                } => self.cancel_order(session_id, order_id, &symbol),
+            }
+        }
+    }
+
+    /// 演练模式下的模拟回报，与交易所回报一样经过钉住、改单与报价的处理
+    fn on_dry_run(&mut self, tx: &ClientSender, order: &BinanceOrder, state: State) {
+        let (session_id, order_id) = (order.session_id, order.id);
+        self.alerter.on_order_state(session_id, state);
+        self.order_caps.on_order(session_id, order_id, state);
+        let (forward, actions) = self.pegs.on_order(session_id, order_id, state, 0.0);
+        self.apply_pegs(actions);
+        if !forward {
+            return;
+        }
+        let (forward, actions) = self.replaces.on_order(session_id, order_id, state, 0.0);
+        self.apply_replaces(actions);
+        if !forward {
+            return;
+        }
+        dryrun::notify(tx, order, state);
+        let actions = self.quotes.on_order(session_id, order_id, state);
+        self.apply_quotes(actions);
+    }
+
+    /// 按交易对的过滤器校验，MAX_POSITION 的持仓为账户推送中基础资产的余额，没有推送过时不校验
+    fn check_filters(&self, order: &BinanceOrder) -> Result<(), String> {
+        let Some(product) = self.products.get(&order.symbol.to_lowercase()) else {
+            return Ok(());
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        let reference = order_check::reference(product, &self.book_tickers, now);
+        let position = self.balances.get(&product.baseAsset).copied();
+        order_check::check(&self.order_check, product, order, reference, position)
+    }
+
+    fn reject(&self, tx: &ClientSender, order: &BinanceOrder) {
+        self.order_caps.on_reject(order.session_id, order.id);
+        self.quotes.on_reject(order.session_id, order.id);
+        self.watchdog.on_reject(order.session_id, order.id);
+        self.pegs.on_reject(order.session_id, order.id);
+        self.replaces.on_reject(order.session_id, order.id);
+        let order = SOrder::new(
+            order.id,
+            order.symbol.clone(),
+            order.side,
+            State::REJECTED,
+            order.order_type,
+            order.tif,
+            order.quantity,
+            order.price,
+        );
+
+        if let Err(e) = tx.reply(&order) {
+            error!("{}", e);
+        }
+    }
 }
 
 impl Trade for SpotTrade {
@@ -83,26 +645,178 @@ impl Trade for SpotTrade {
         &self.products
     }
 
+    /// 现货没有交易所的持仓，只比较挂单与余额；杠杆账户不比较余额，不支持逐仓杠杆
+    async fn sanity_check(&mut self, req: &SSanityCheck) -> anyhow::Result<SSanityReport> {
+        if self.dry_run.is_some() {
+            anyhow::bail!("sanity_check is not available in dry run");
+        }
+        let (open_orders, order) = match &self.margin {
+            Some(margin) if !margin.cancel_params().is_empty() => {
+                anyhow::bail!("sanity_check does not support isolated margin")
+            }
+            Some(_) => (self.mode.margin_open_orders(), self.mode.margin_order()),
+            None => ("/api/v3/openOrders", "/api/v3/order"),
+        };
+        let exchange = ExchangeState {
+            orders: sanity::fetch_open_orders(&self.rest, open_orders).await?,
+            positions: None,
+            balances: match &self.margin {
+                Some(_) => None,
+                None => Some(get_balances(&self.rest).await?),
+            },
+        };
+        let local = LocalState {
+            orders: working_orders(&self.session_map),
+            positions: HashMap::new(),
+            balances: self.balances.clone(),
+        };
+        let mut report = sanity::compare(&local, &exchange);
+        sanity::alert(&report, &self.alerter);
+        if req.heal {
+            report.canceled =
+                sanity::cancel_orders(&self.rest, order, &report.unknown_orders).await;
+        }
+        Ok(report)
+    }
+
+    fn get_liquidity_stats(&self, req: &SLiquidityReq) -> Vec<SLiquidity> {
+        self.liquidity.report(req)
+    }
+
+    fn export_state(&self) -> Result<SGatewayState, SError> {
+        Ok(state::export(
+            VENUE,
+            &self.session_map,
+            &self.posdb,
+            BTreeMap::new(),
+        ))
+    }
+
+    /// 导入的持仓同时计入组合敞口
+    async fn import_state(&mut self, gateway: &SGatewayState) -> Result<SImportReport, SError> {
+        let report = state::import(VENUE, gateway, &mut self.session_map, &self.posdb).await?;
+        for session in gateway.sessions.iter() {
+            for position in session.positions.iter() {
+                self.portfolio.update_position(
+                    VENUE,
+                    session.session_id,
+                    &position.symbol,
+                    &self.underlying(&position.symbol),
+                    position.net,
+                );
+            }
+        }
+        Ok(report)
+    }
+
+    fn get_attribution(&self, session_id: u16) -> Option<Vec<SAttribution>> {
+        self.session_map
+            .get(&session_id)
+            .map(|session| session.attribution())
+    }
+
     fn get_positions(&self, session_id: u16) -> Option<&HashMap<String, Position>> {
         self.posdb.get_positions(session_id)
     }
 
     async fn get_products(&mut self) -> anyhow::Result<()> {
-        self.products = get_positions(&self.rest).await?;
+        (self.products, self.exchange_limits) = get_positions(&self.public).await?;
+        self.order_caps
+            .set_products(&self.products, self.exchange_limits);
         Ok(())
     }
 
-    async fn process(&mut self) -> anyhow::Result<bool> {
-        let msg = self.account.process().await.unwrap();
-
-        if let Some(s) = msg {
-            match serde_json::from_str::<EventMessage>(&s)? {
-                EventMessage {
-                    event: Event::UserDataEvent(UserDataEvent::ExecutionReport(order)),
-                    ..
-                } => self.on_order(&order),
-                _ => {}
+    async fn get_income(&mut self, _req: &SIncomeReq) -> anyhow::Result<Vec<Income>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_my_trades(&mut self, req: &SMyTradesReq) -> Result<Vec<MyTrade>, SError> {
+        let Some(my_trades) = &self.my_trades else {
+            return Err(SError {
+                code: UNSUPPORTED,
+                msg: "trade history is disabled".into(),
+            });
+        };
+        if !self.products.contains_key(&req.symbol.to_lowercase()) {
+            return Err(SError {
+                code: INVALID_SYMBOL,
+                msg: format!("unknown symbol {}", req.symbol),
+            });
+        }
+        my_trades.get(&self.rest, req).await
+    }
+
+    async fn borrow_repay(
+        &mut self,
+        loan_type: LoanType,
+        loan: &SLoan,
+    ) -> anyhow::Result<Option<SError>> {
+        if !self.session_map.contains_key(&loan.session_id) {
+            return Ok(Some(SError {
+                code: NOT_LOGIN,
+                msg: format!("session {} not login", loan.session_id),
+            }));
+        }
+        let Some(margin) = self.margin.as_ref() else {
+            return Ok(Some(SError {
+                code: UNSUPPORTED,
+                msg: "margin account is not enabled".into(),
+            }));
+        };
+        let request = match self.pm.as_ref() {
+            Some(pm) => pm.loan_request(loan_type, loan),
+            None => margin
+                .loan_params(loan_type, loan)
+                .map(|params| ("/sapi/v1/margin/borrow-repay", params)),
+        };
+        let (path, params) = match request {
+            Ok(request) => request,
+            Err(msg) => {
+                warn!("Reject {:?} {:?}: {}", loan_type, loan, msg);
+                return Ok(Some(SError {
+                    code: INVALID_LOAN,
+                    msg,
+                }));
             }
+        };
+        if self.dry_run.is_some() {
+            info!("Dry run {:?} {:?}", loan_type, loan);
+            return Ok(None);
+        }
+
+        let rsp = self.rest.post(path, &params, true).await?;
+        // 成功时返回 tranId
+        match rsp.json::<SError>().await {
+            Ok(e) => {
+                error!("{:?} {:?}: {:?}", loan_type, loan, e);
+                Ok(Some(e))
+            }
+            Err(_) => {
+                info!("{:?} {:?} done", loan_type, loan);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn process(&mut self) -> anyhow::Result<bool> {
+        match self.account.process().await? {
+            Some(Event::UserDataEvent(event)) => match event {
+                UserDataEvent::ExecutionReport(order) => self.on_order(&order),
+                UserDataEvent::OutboundAccountPosition(position) => {
+                    self.on_account_position(&position)
+                }
+                UserDataEvent::UserLiabilityChange(change) => {
+                    if let Some(margin) = self.margin.as_ref() {
+                        margin.on_liability(&change);
+                    }
+                }
+                UserDataEvent::MarginLevelStatusChange(change) => {
+                    self.on_margin_level_status(&change)
+                }
+                _ => (),
+            },
+            Some(Event::RiskLevelChange(change)) => self.on_risk_level(&change),
+            _ => (),
         }
 
         Ok(self.disconnected())
@@ -111,43 +825,233 @@ impl Trade for SpotTrade {
     fn add_order(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
         match self.txs.get_mut(addr) {
             Some(tx) => {
-                let rest = self.rest.clone();
                 let tx = tx.clone();
+                let cid = correlation_id(order.session_id, order.id);
+                let span = order_span(cid);
+                let _enter = span.enter();
+                self.lifecycle.mark(cid, Stage::Request);
+                if let Err(e) = self.halts.check(&order.symbol) {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let tick = self
+                    .products
+                    .get(&order.symbol.to_lowercase())
+                    .map(|p| p.tick_size())
+                    .unwrap_or_default();
+                let bbo = self.book_tickers.get(&order.symbol);
+                let pegged;
+                let order = match order.peg {
+                    Some(_) => {
+                        pegged = self.pegs.register(*addr, order, tick, bbo);
+                        &pegged
+                    }
+                    None => order,
+                };
+                let posted;
+                let order = match post_only::prepare(order, bbo, tick, false) {
+                    Ok(Some(prepared)) => {
+                        posted = prepared;
+                        &posted
+                    }
+                    Ok(None) => order,
+                    Err(e) => {
+                        warn!("Reject order {:?}: {}", order, e);
+                        self.reject(&tx, order);
+                        return Ok(());
+                    }
+                };
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as i64)
+                    .unwrap_or_default();
+                let book = self.depth_books.get(&order.symbol);
+                let clamped;
+                let order =
+                    match depth_check::check(&self.depth_check, order, book.as_ref(), tick, now) {
+                        Verdict::Pass => order,
+                        Verdict::Warn(e) => {
+                            warn!("Thin book for order {:?}: {}", order, e);
+                            order
+                        }
+                        Verdict::Clamp(clipped, e) => {
+                            warn!("Clamp order {:?} at {}: {}", order, clipped.price, e);
+                            clamped = clipped;
+                            &clamped
+                        }
+                        Verdict::Reject(e) => {
+                            warn!("Reject order {:?}: {}", order, e);
+                            self.alerter.on_risk_limit_breach(order.session_id, e);
+                            self.reject(&tx, order);
+                            return Ok(());
+                        }
+                    };
+                // 现货没有双向持仓
+                if let Err(e) = order
+                    .check_position_side(false)
+                    .and_then(|_| order.check_side_effect(self.margin.is_some()))
+                    .and_then(|_| self.check_filters(order))
+                {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let reference = fat_finger::reference(bbo);
+                if let Err(e) = fat_finger::check(&self.fat_finger, order, reference) {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.alerter.on_risk_limit_breach(order.session_id, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let checked = match (&self.margin, &self.pm) {
+                    (Some(margin), None) => margin.check_order(order),
+                    (_, Some(pm)) => pm.check_order(),
+                    (None, None) => Ok(()),
+                };
+                if let Err(e) = checked {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.alerter.on_risk_limit_breach(order.session_id, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let quantity = match order.side {
+                    Side::BUY => order.quantity,
+                    Side::SELL => -order.quantity,
+                };
+                if let Err(breach) = self.portfolio.check_order(
+                    order.session_id,
+                    VENUE,
+                    &order.symbol,
+                    &self.underlying(&order.symbol),
+                    quantity,
+                    order.price,
+                ) {
+                    warn!("Reject order {:?}: {}", order, breach);
+                    self.alerter
+                        .on_risk_limit_breach(order.session_id, breach.to_string());
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                match self.order_caps.admit(*addr, order, Instant::now()) {
+                    Admission::Send => (),
+                    Admission::Queue(cap) => {
+                        warn!(
+                            "Queue order {:?}: {} of {} reached {}",
+                            order, cap.filter, cap.symbol, cap.limit
+                        );
+                        self.notify(&tx, &cap);
+                        return Ok(());
+                    }
+                    Admission::Reject(e) => {
+                        warn!("Reject order {:?}: {}", order, e);
+                        self.alerter.on_risk_limit_breach(order.session_id, e);
+                        self.reject(&tx, order);
+                        return Ok(());
+                    }
+                }
+
+                let Some(guard) = self.in_flight.acquire(order.session_id) else {
+                    warn!(
+                        "Reject order {:?}: {} requests in flight",
+                        order,
+                        self.in_flight.max()
+                    );
+                    self.alerter
+                        .on_order_state(order.session_id, State::REJECTED);
+                    self.reject(&tx, order);
+                    return Ok(());
+                };
+                self.lifecycle.mark(cid, Stage::Validated);
+
+                if let Some(session) = self.session_map.get_mut(&order.session_id) {
+                    session.tag_order(order, attribution::arrival_price(order, bbo));
+                }
+                if let Some(ttl_ms) = order.ttl_ms {
+                    if let Some(session) = self.session_map.get_mut(&order.session_id) {
+                        let deadline = Instant::now() + Duration::from_millis(ttl_ms);
+                        session.set_deadline(order.id, &order.symbol, deadline);
+                    }
+                }
+
+                if let Some(dry_run) = self.dry_run.as_mut() {
+                    drop(guard);
+                    dry_run.place(&tx, order);
+                    self.on_dry_run(&tx, order, State::NEW);
+                    return Ok(());
+                }
+
+                self.watchdog
+                    .on_sent(order.session_id, order.id, &order.symbol, Instant::now());
+                let rest = self.rest.clone();
+                let alerter = self.alerter.clone();
+                let quotes = self.quotes.clone();
+                let pegs = self.pegs.clone();
+                let replaces = self.replaces.clone();
+                let watchdog = self.watchdog.clone();
+                let gtx_retries = self.gtx_retries.clone();
+                let order_caps = self.order_caps.clone();
+                let ops_report = self.ops_report.clone();
+                let (addr, retry) = (*addr, order.clone());
 
                 let symbol = order.symbol.clone();
                 let price = order.price;
                 let quantity = order.quantity;
-                let side = order.side.clone();
-                let order_type = order.order_type.clone();
-                let tif = order.tif.clone();
+                let side = order.side;
+                let order_type = order.order_type;
+                let tif = order.tif;
                 let session_id = order.session_id;
                 let id = order.id;
 
-                let path = if self.margin {
-                    "/sapi/v1/margin/order"
-                } else {
-                    "/api/v3/order"
+                // LIMIT_MAKER 不带 timeInForce
+                let time_in_force = match order_type {
+                    OrderType::LIMIT_MAKER => "UNDEF".to_string(),
+                    _ => format!("{:?}", tif),
+                };
+                let mut params = order_params(
+                    symbol.to_uppercase(),
+                    price.to_string(),
+                    quantity.to_string(),
+                    format!("{:?}", side),
+                    format!("{:?}", order_type),
+                    time_in_force,
+                    session_id,
+                    id,
+                );
+                let path = match &self.margin {
+                    Some(margin) => {
+                        params.extend(margin.order_params(order));
+                        self.mode.margin_order()
+                    }
+                    None => "/api/v3/order",
                 };
 
-                tokio::spawn(async move {
-                    match rest
-                        .add_order(
-                            path,
-                            symbol.clone().to_uppercase(),
-                            price.to_string(),
-                            quantity.to_string(),
-                            format!("{:?}", side),
-                            format!("{:?}", order_type),
-                            format!("{:?}", tif),
-                            session_id,
-                            id,
-                        )
-                        .await
-                    {
+                let lifecycle = self.lifecycle.clone();
+                self.lifecycle.mark(cid, Stage::Sent);
+                self.ops_report.on_placed();
+                let sent = Instant::now();
+                let task = async move {
+                    // 收到响应后释放在途名额
+                    let _guard = guard;
+                    match rest.post(path, &params, true).await {
                         Ok(rsp) => {
+                            lifecycle.mark(cid, Stage::Acked);
+                            ops_report.on_latency(sent.elapsed());
                             // exchange rej
                             if let Ok(e) = rsp.json::<cryptoflow::chat::SError>().await {
+                                order_caps.on_reject(session_id, id);
+                                ops_report.on_reject(&format!("{} {}", e.code, e.msg));
+                                if gtx_retries.on_reject(addr, &retry, tick, e.code.into(), &e.msg)
+                                {
+                                    warn!("Post-only order rejected, retry: {:?}", e);
+                                    return;
+                                }
                                 error!("{:?}", e);
+                                alerter.on_order_state(session_id, State::REJECTED);
+                                quotes.on_reject(session_id, id);
+                                pegs.on_reject(session_id, id);
+                                replaces.on_reject(session_id, id);
+                                watchdog.on_reject(session_id, id);
                                 let order = SOrder::new(
                                     id,
                                     symbol,
@@ -159,20 +1063,21 @@ impl Trade for SpotTrade {
                                     price,
                                 );
 
-                                match serde_json::to_string(&order) {
-                                    Ok(s) => {
-                                        println!("send order result: {:?}", order);
-                                        if let Err(e) = tx.send(Message::Text(s.into())) {
-                                            error!("{}", e);
-                                        }
-                                    }
-                                    Err(e) => error!("{}", e),
+                                println!("send order result: {:?}", order);
+                                if let Err(e) = tx.reply(&order) {
+                                    error!("{}", e);
                                 }
                             }
                         }
-                        // network error
+                        // network error，订单可能已经挂出，留给过期订单巡检核对
                         Err(e) => {
                             error!("{:?}", e);
+                            order_caps.on_reject(session_id, id);
+                            ops_report.on_reject("network");
+                            alerter.on_order_state(session_id, State::REJECTED);
+                            quotes.on_reject(session_id, id);
+                            pegs.on_reject(session_id, id);
+                            replaces.on_reject(session_id, id);
                             let order = SOrder::new(
                                 id,
                                 symbol,
@@ -184,17 +1089,13 @@ impl Trade for SpotTrade {
                                 price,
                             );
 
-                            match serde_json::to_string(&order) {
-                                Ok(s) => {
-                                    if let Err(e) = tx.send(Message::Text(s.into())) {
-                                        error!("{}", e);
-                                    }
-                                }
-                                Err(e) => error!("{}", e),
+                            if let Err(e) = tx.reply(&order) {
+                                error!("{}", e);
                             }
                         }
                     }
-                });
+                };
+                tokio::spawn(task.instrument(span.clone()));
             }
             None => warn!("Missing session {}, maybe a bug", addr),
         }
@@ -205,38 +1106,70 @@ impl Trade for SpotTrade {
     fn cancel(&mut self, addr: &SocketAddr, cancel: &BinanceCancel) -> anyhow::Result<()> {
         match self.txs.get_mut(addr) {
             Some(_) => {
-                let rest = self.rest.clone();
-
-                let symbol = cancel.symbol.clone().to_uppercase();
-                let session_id = cancel.session_id;
-                let order_id = cancel.order_id;
-
-                let orig = u64::from(session_id) << 32 | u64::from(order_id);
-                let path = if self.margin {
-                    "/sapi/v1/margin/order"
-                } else {
-                    "/api/v3/order"
-                };
-
-                tokio::spawn(async move {
-                    if let Err(e) = rest.cancel(path, symbol, orig).await {
-                        error!("{}", e)
-                    }
-                });
+                self.pegs.on_cancel(cancel.session_id, cancel.order_id);
+                self.replaces.on_cancel(cancel.session_id, cancel.order_id);
+                self.cancel_order(cancel.session_id, cancel.order_id, &cancel.symbol);
             }
             None => warn!("Missing session {}, maybe a bug", addr),
         }
         Ok(())
     }
 
+    /// 双边报价，只撤改与上一次报价相比发生变化的一侧
+    fn quote(&mut self, addr: &SocketAddr, quote: &BinanceQuote) -> anyhow::Result<()> {
+        if !self.txs.contains_key(addr) {
+            warn!("Missing session {}, maybe a bug", addr);
+            return Ok(());
+        }
+        let actions = self.quotes.on_quote(*addr, quote);
+        self.apply_quotes(actions);
+        Ok(())
+    }
+
+    fn replace(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+        if !self.txs.contains_key(addr) {
+            warn!("Missing session {}, maybe a bug", addr);
+            return Ok(());
+        }
+        let working = self
+            .session_map
+            .get(&order.session_id)
+            .is_some_and(|s| s.working_orders().contains_key(&order.id))
+            || self
+                .dry_run
+                .as_ref()
+                .is_some_and(|d| d.contains(order.session_id, order.id));
+        let actions = self.replaces.on_replace(*addr, order, working);
+        self.apply_replaces(actions);
+        Ok(())
+    }
+
     /// 当某个addr的client关闭时，需要清理掉它的session
     /// txs, session_id_map, session_map
+    /// 登录时开启了 cancel_on_disconnect 的会话，先撤销它所有未完成的订单
     fn handle_strategy_client_close(&mut self, addr: &SocketAddr) -> anyhow::Result<()> {
-        if let Some(_) = self.txs.remove(addr) {
+        let queued = self.order_caps.drain(|a, _| a == addr);
+        if !queued.is_empty() {
+            warn!("{} disconnected, drop {} queued orders", addr, queued.len());
+        }
+        if self.txs.remove(addr).is_some() {
             match self.session_id_map.remove(addr) {
                 Some(id) => match self.session_map.get_mut(&id) {
                     Some(session) => {
+                        let orders = session.orders_to_cancel();
                         session.set_active(None);
+                        if !orders.is_empty() {
+                            warn!(
+                                "Session {} disconnected, cancel {} orders",
+                                id,
+                                orders.len()
+                            );
+                        }
+                        for (order_id, symbol) in orders {
+                            self.pegs.on_cancel(id, order_id);
+                            self.replaces.on_cancel(id, order_id);
+                            self.cancel_order(id, order_id, &symbol);
+                        }
                     }
                     None => warn!("Session used by {} isn't exist, maybe a bug", addr),
                 },
@@ -246,6 +1179,98 @@ impl Trade for SpotTrade {
         Ok(())
     }
 
+    fn cancel_all(&mut self, session_id: u16) -> usize {
+        let orders: Vec<_> = match self.session_map.get(&session_id) {
+            Some(session) => session
+                .working_orders()
+                .iter()
+                .map(|(id, symbol)| (*id, symbol.clone()))
+                .collect(),
+            None => return 0,
+        };
+        for (order_id, symbol) in orders.iter() {
+            self.pegs.on_cancel(session_id, *order_id);
+            self.replaces.on_cancel(session_id, *order_id);
+            self.cancel_order(session_id, *order_id, symbol);
+        }
+        let queued = self
+            .order_caps
+            .drain(|_, order| order.session_id == session_id);
+        for (addr, order) in queued.iter() {
+            self.cancel_queued(addr, order);
+        }
+        orders.len() + queued.len()
+    }
+
+    fn cancel_symbol(&mut self, symbol: &str) -> usize {
+        let mut orders = Vec::new();
+        for (session_id, session) in self.session_map.iter() {
+            for (order_id, s) in session.working_orders().iter() {
+                if s.eq_ignore_ascii_case(symbol) {
+                    orders.push((*session_id, *order_id, s.clone()));
+                }
+            }
+        }
+        for (session_id, order_id, symbol) in orders.iter() {
+            self.pegs.on_cancel(*session_id, *order_id);
+            self.replaces.on_cancel(*session_id, *order_id);
+            self.cancel_order(*session_id, *order_id, symbol);
+        }
+        let queued = self
+            .order_caps
+            .drain(|_, order| order.symbol.eq_ignore_ascii_case(symbol));
+        for (addr, order) in queued.iter() {
+            self.cancel_queued(addr, order);
+        }
+        orders.len() + queued.len()
+    }
+
+    fn expire_orders(&mut self) {
+        let now = Instant::now();
+        let mut orders = Vec::new();
+        for (session_id, session) in self.session_map.iter_mut() {
+            for (order_id, symbol) in session.expire_orders(now) {
+                orders.push((*session_id, order_id, symbol));
+            }
+        }
+        for (session_id, order_id, symbol) in orders {
+            info!(
+                "Order {} of session {} expired, cancel it",
+                order_id, session_id
+            );
+            self.pegs.on_cancel(session_id, order_id);
+            self.replaces.on_cancel(session_id, order_id);
+            self.cancel_order(session_id, order_id, &symbol);
+        }
+        self.cancel_stale_orders(now);
+    }
+
+    fn reprice_orders(&mut self) {
+        let actions = self.pegs.reprice(&self.book_tickers, Instant::now());
+        self.apply_pegs(actions);
+        self.release_queued();
+        for (addr, order) in self.gtx_retries.take() {
+            info!(
+                "Retry post-only order {} of session {} at {}",
+                order.id, order.session_id, order.price
+            );
+            if let Err(e) = self.add_order(&addr, &order) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    fn roll_contracts(&self, _alias: &str) -> Option<(String, String)> {
+        None
+    }
+
+    fn roll(&mut self, _addr: &SocketAddr, _req: &SRollReq) -> Result<SRollResult, SError> {
+        Err(SError {
+            code: UNSUPPORTED,
+            msg: "contract roll is only supported by the futures gateway".into(),
+        })
+    }
+
     /// 处理登录请求
     /// 如果session_id已存在，且active，则返回重复登录错误；
     /// 如果session_id已存在，且inactive，那么设置为新的session；
@@ -254,7 +1279,7 @@ impl Trade for SpotTrade {
         &mut self,
         addr: &SocketAddr,
         req: &SRequest<SLogin>,
-        tx: &UnboundedSender<Message>,
+        tx: &ClientSender,
     ) -> anyhow::Result<Option<SError>> {
         let login = &req.params;
         let session_id = login.session_id;
@@ -268,16 +1293,20 @@ impl Trade for SpotTrade {
                     }));
                 } else {
                     session.set_active(Some(tx.clone()));
+                    session.set_cancel_on_disconnect(login.cancel_on_disconnect);
+                    session.set_fills(login.fills);
                 }
             }
             None => {
-                let session = Session::new(session_id, self.posdb.clone(), tx.clone()).await?;
+                let mut session = Session::new(session_id, self.posdb.clone(), tx.clone()).await?;
+                session.set_cancel_on_disconnect(login.cancel_on_disconnect);
+                session.set_fills(login.fills);
                 self.session_map.insert(session_id, session);
             }
         }
 
-        self.txs.insert(addr.clone(), tx.clone());
-        self.session_id_map.insert(addr.clone(), session_id);
+        self.txs.insert(*addr, tx.clone());
+        self.session_id_map.insert(*addr, session_id);
 
         info!("session addr {} -> {}", addr, session_id);
         Ok(None)
@@ -286,73 +1315,52 @@ impl Trade for SpotTrade {
     #[allow(unused)]
     fn handle_strategy_client_subscribe(
         &mut self,
-        addr: &SocketAddr,
+        _addr: &SocketAddr,
         req: &SRequest<Vec<String>>,
-    ) -> Option<SError> {
-        let mut params = Vec::new();
-
-        for symbol in req.params.iter() {
-            let symbol = symbol.to_lowercase();
-            match symbol.split_once("@") {
-                Some((name, stream)) => {
-                    if !self.products.contains_key(name) {
-                        return Some(SError {
-                            code: INVALID_SYMBOL,
-                            msg: format!("invalid symbol {}", symbol),
-                        });
+    ) -> Vec<SStreamResult> {
+        req.params
+            .iter()
+            .map(|symbol| {
+                let error = |code, kind| {
+                    SStreamResult::rejected(
+                        symbol,
+                        SError {
+                            code,
+                            msg: format!("invalid {} {}", kind, symbol),
+                        },
+                    )
+                };
+                // 期权的流由行情模块校验
+                if binance::options::exchange_stream(symbol).is_some() {
+                    return SStreamResult::accepted(symbol);
+                }
+                match symbol.to_lowercase().split_once("@") {
+                    Some((name, _)) if !self.products.contains_key(name) => {
+                        error(INVALID_SYMBOL, "symbol")
                     }
-                    if !self.validate_symbol(name, stream) {
-                        return Some(SError {
-                            code: INVALID_STREAM,
-                            msg: format!("invalid stream {}", symbol),
-                        });
+                    Some((name, stream)) if !self.validate_symbol(name, stream) => {
+                        error(INVALID_STREAM, "stream")
                     }
+                    Some(_) => SStreamResult::accepted(symbol),
+                    None => error(INVALID_SYMBOL, "symbol"),
                 }
-                None => {
-                    return Some(SError {
-                        code: INVALID_SYMBOL,
-                        msg: format!("invalid symbol {}", symbol),
-                    });
-                }
-            }
-            params.push(symbol);
-        }
-        None
+            })
+            .collect()
     }
 
-    #[allow(unused)]
-    fn validate_symbol(&self, symbol: &str, stream: &str) -> bool {
-        match stream.split_once(":") {
-            Some((stream, interval)) => {
-                match stream {
-                    "kline" => match interval {
-                        "1s" | "1m" | "3m" | "5m" | "15m" | "30m" | "1h" | "2h" | "4h" | "6h"
-                        | "8h" | "12h" | "1d" | "3d" | "1w" | "1M" => return true,
-                        _ => return false,
-                    },
-                    "depth" => match interval {
-                        "100ms" => return true,
-                        _ => return false,
-                    },
-                    _ => return false,
-                };
-            }
-            None => match stream {
-                "depth" | "bbo" => true,
-                _ => false,
-            },
-        }
+    fn validate_symbol(&self, _symbol: &str, stream: &str) -> bool {
+        validate_stream(stream)
     }
 
     fn handle_strategy_client_disconnect(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
     ) -> anyhow::Result<()> {
-        if let Some(id) = parser.get("id") {
+        if let Some(id) = parser.id() {
             self.reply(
                 addr,
-                i64::deserialize(id)?,
+                id,
                 SError {
                     code: DISCONNECTED,
                     msg: "trade disconnected".into(),
@@ -369,14 +1377,10 @@ impl Trade for SpotTrade {
         result: T,
     ) -> anyhow::Result<()> {
         if let Some(tx) = self.txs.get_mut(addr) {
-            let response = SResponse {
-                id: id,
-                result: result,
-            };
+            let response = SResponse { id, result };
 
             debug!("{:?}", response);
-            let rsp = Message::Text(serde_json::to_string(&response)?.into());
-            tx.send(rsp)?;
+            tx.reply(&response)?;
         }
         Ok(())
     }
@@ -386,6 +1390,10 @@ impl Trade for SpotTrade {
 impl SpotTrade {
     fn on_order(&mut self, order: &ExecutionReport) {
         info!("{:?}", order);
+        if !self.events.first(EventKey::of(order)) {
+            debug!("Duplicate execution report {} of {}", order.i, order.s);
+            return;
+        }
         let client_order_id = match order.X {
             State::CANCELED => &order.C,
             _ => &order.c,
@@ -394,17 +1402,167 @@ impl SpotTrade {
         match client_order_id.parse::<u64>() {
             Ok(client_order_id) => {
                 let session_id = (client_order_id >> 32) as u16;
+                if session_id == GATEWAY_SESSION {
+                    info!("Gateway order {} {:?}", order.s, order.X);
+                    return;
+                }
+                let span = order_span(client_order_id);
+                let _enter = span.enter();
+                self.lifecycle.mark(client_order_id, Stage::Report);
+                self.alerter.on_order_state(session_id, order.X);
 
+                let order_id = (client_order_id & 0xFFFFFFFF) as u32;
+                self.watchdog
+                    .on_update(session_id, order_id, &order.s, order.X, Instant::now());
+                match order.X {
+                    State::REJECTED => self.ops_report.on_reject(&order.r),
+                    state => self.ops_report.on_order(state),
+                }
+                if let Some(fill) = order.fill() {
+                    self.liquidity.on_fill(session_id, &fill);
+                    self.ops_report.on_fill(&fill);
+                }
+                self.gtx_retries
+                    .on_order(session_id, order_id, order.state());
+                self.order_caps
+                    .on_order(session_id, order_id, order.state());
+                let trd_vol = order.trd_vol().unwrap_or_default();
+                let (forward, actions) =
+                    self.pegs
+                        .on_order(session_id, order_id, order.state(), trd_vol);
+                self.apply_pegs(actions);
+                if !forward {
+                    debug!(
+                        "Reprice pegged order {} of session {}",
+                        order_id, session_id
+                    );
+                    return;
+                }
+                let (forward, actions) =
+                    self.replaces
+                        .on_order(session_id, order_id, order.state(), trd_vol);
+                self.apply_replaces(actions);
+                if !forward {
+                    debug!("Replace order {} of session {}", order_id, session_id);
+                    return;
+                }
+
+                let underlying = self.underlying(order.symbol());
                 match self.session_map.get_mut(&session_id) {
                     Some(session) => {
-                        if let Err(e) = session.on_order(order) {
-                            error!("{}", e);
+                        match session.on_order(order_id, order) {
+                            Ok(()) => self.lifecycle.mark(client_order_id, Stage::Notified),
+                            Err(e) => error!("{}", e),
+                        }
+                        if let Some(position) = session.position(order.symbol()) {
+                            self.portfolio
+                                .update_price(VENUE, &position.symbol, order.trd_prc());
+                            self.portfolio.update_position(
+                                VENUE,
+                                session_id,
+                                &position.symbol,
+                                &underlying,
+                                position.net,
+                            );
                         }
                     }
                     None => warn!("Missing session {}, maybe a bug", session_id),
                 }
+
+                let actions = self.quotes.on_order(session_id, order_id, order.state());
+                self.apply_quotes(actions);
             }
             Err(_) => info!("Extrnal order:{:?} ", order),
         }
     }
+
+    fn on_account_position(&mut self, position: &OutboundAccountPosition) {
+        for balance in position.B.iter() {
+            let total = balance.f.parse::<f64>().unwrap_or_default()
+                + balance.l.parse::<f64>().unwrap_or_default();
+            self.balances.insert(balance.a.clone(), total);
+        }
+        let Some(balance) = position.B.iter().find(|b| b.a == self.fee.asset()) else {
+            return;
+        };
+        let free = balance.f.parse::<f64>().unwrap_or_default();
+        let product = self.products.get(self.fee.symbol()).cloned();
+        let price = product
+            .as_ref()
+            .and_then(|p| self.fx.rate(&p.baseAsset, &p.quoteAsset));
+        let day = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64 / 86400)
+            .unwrap_or_default();
+
+        for action in self.fee.on_balance(free, price, Instant::now(), day) {
+            match action {
+                FeeAction::Alert { free } => {
+                    warn!("{} balance {} is low", balance.a, free);
+                    self.alerter
+                        .on_low_balance(&balance.a, free, self.fee.threshold());
+                }
+                FeeAction::Blocked(reason) => warn!("Skip {} top up: {}", balance.a, reason),
+                FeeAction::TopUp { symbol, quantity } => {
+                    let quantity = match &product {
+                        Some(product) => product.adjust_quantity(quantity),
+                        None => quantity,
+                    };
+                    self.top_up(symbol, quantity);
+                }
+            }
+        }
+    }
+
+    /// 全仓风险率进入追加保证金或强平区间时告警
+    fn on_margin_level_status(&mut self, change: &MarginLevelStatusChange) {
+        let Some(margin) = self.margin.as_ref() else {
+            return;
+        };
+        if margin.on_margin_level_status(change) {
+            self.alerter
+                .on_margin_call(format!("margin level {} status {}", change.l, change.s));
+        }
+    }
+
+    fn on_risk_level(&mut self, change: &RiskLevelChange) {
+        let Some(pm) = self.pm.as_ref() else {
+            return;
+        };
+        if pm.on_risk_level(change) {
+            self.alerter
+                .on_margin_call(format!("uniMMR {} status {}", change.u, change.s));
+        }
+    }
+
+    /// 市价买入手续费抵扣资产
+    fn top_up(&mut self, symbol: String, quantity: f64) {
+        info!("Top up {} {}", symbol, quantity);
+        self.gateway_order_id = self.gateway_order_id.wrapping_add(1);
+        let id = self.gateway_order_id;
+        let rest = self.rest.clone();
+        let path = match self.margin {
+            Some(_) => self.mode.margin_order(),
+            None => "/api/v3/order",
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = rest
+                .add_order(
+                    path,
+                    symbol.to_uppercase(),
+                    "0".into(),
+                    quantity.to_string(),
+                    "BUY".into(),
+                    "MARKET".into(),
+                    "UNDEF".into(),
+                    GATEWAY_SESSION,
+                    id,
+                )
+                .await
+            {
+                error!("{}", e);
+            }
+        });
+    }
 }
@@ -6,7 +6,7 @@ use serde_json::Value;
 use tracing::{info, warn};
 
 use crate::{
-    event_handlers::UserDataEventHandler,
+    event_handlers::{DefaultUserDataHandler, UserDataEventHandler},
     model::{
         session::SessionLogonResponse,
         user_data::{
@@ -15,6 +15,7 @@ use crate::{
         },
         Event, EventMessage,
     },
+    pm::PmUserStream,
     session_manager::SessionManager,
 };
 use websocket::Credentials;
@@ -40,7 +41,7 @@ impl<T: UserDataEventHandler> Account<T> {
     pub async fn new(credentials: &Credentials, event_handler: T) -> Self {
         let mut session_manager = SessionManager::new();
         let rx = session_manager
-            .login(&credentials)
+            .login(credentials)
             .await
             .expect("Account账户登录失败");
         Self {
@@ -90,6 +91,7 @@ impl<T: UserDataEventHandler> Account<T> {
     /// # Arguments
     ///
     /// * `subscription_id` - 要取消订阅的用户数据流 ID
+    ///
     /// 如果没有参数，将取消所有订阅
     pub async fn unsubscribe_user_data(
         &mut self,
@@ -170,6 +172,8 @@ impl<T: UserDataEventHandler> Account<T> {
                 if let Ok(event_message) = serde_json::from_value::<EventMessage>(inner.clone()) {
                     if let Event::UserDataEvent(event) = event_message.event {
                         self.handle_user_data_event(&event).await?;
+                        // 交给上层处理订单回报与余额
+                        return Ok(Some(inner.to_string()));
                     } else {
                         info!("Account收到Event:非数据推送: {:?}", event_message);
                     }
@@ -322,6 +326,57 @@ impl<T: UserDataEventHandler> Account<T> {
     }
 }
 
+/// 交易使用的用户数据流
+pub enum UserStream {
+    /// 经典账户，WS-API 会话订阅
+    WsApi(Account<DefaultUserDataHandler>),
+    /// 统一账户，listenKey 数据流
+    PortfolioMargin(PmUserStream),
+}
+
+impl From<Account<DefaultUserDataHandler>> for UserStream {
+    fn from(account: Account<DefaultUserDataHandler>) -> Self {
+        Self::WsApi(account)
+    }
+}
+
+impl From<PmUserStream> for UserStream {
+    fn from(stream: PmUserStream) -> Self {
+        Self::PortfolioMargin(stream)
+    }
+}
+
+impl UserStream {
+    pub fn disconnected(&self) -> bool {
+        match self {
+            Self::WsApi(account) => account.disconnected(),
+            Self::PortfolioMargin(stream) => stream.disconnected(),
+        }
+    }
+
+    /// 取出一条用户数据事件
+    pub async fn process(&mut self) -> anyhow::Result<Option<Event>> {
+        match self {
+            Self::WsApi(account) => match account.process().await? {
+                Some(s) => Ok(Some(serde_json::from_str::<EventMessage>(&s)?.event)),
+                None => Ok(None),
+            },
+            Self::PortfolioMargin(stream) => {
+                let Some(value) = stream.process() else {
+                    return Ok(None);
+                };
+                match serde_json::from_value::<Event>(value) {
+                    Ok(event) => Ok(Some(event)),
+                    Err(e) => {
+                        warn!("Unhandled portfolio margin user data: {}", e);
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// 生成递增的请求 ID
 fn next_request_id() -> i64 {
     static mut COUNTER: i64 = 1;
@@ -0,0 +1,72 @@
+//! 运维请求的认证
+//!
+//! `sanity_check`、`halt_trading`、`resume_trading`、`export_state` 与 `import_state` 是运维请求，请求中的
+//! `token` 须与 `admin.token` 一致。未配置 token 时拒绝所有运维请求。修改策略参数的 `set_params` 仍由
+//! `params.admin_token` 认证，见 [`crate::params`]，两者都使用 [`AdminConfig::authorize`] 校验。
+
+use cryptoflow::chat::SError;
+use cryptoflow::error_code::{PERMISSION_DENIED, UNSUPPORTED};
+use openssl::memcmp;
+use openssl::sha::sha256;
+use serde::Deserialize;
+
+/// ```json
+/// "admin": {
+///     "token": "change-me"
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// 为空时不接受运维请求
+    pub token: String,
+}
+
+impl AdminConfig {
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+        }
+    }
+
+    /// 校验运维请求的 token；比较两者的摘要，耗时与 token 的内容和长度无关
+    pub fn authorize(&self, token: &str) -> Result<(), SError> {
+        if self.token.is_empty() {
+            return Err(SError {
+                code: UNSUPPORTED,
+                msg: "admin requests are disabled".into(),
+            });
+        }
+        if !memcmp::eq(&sha256(token.as_bytes()), &sha256(self.token.as_bytes())) {
+            return Err(SError {
+                code: PERMISSION_DENIED,
+                msg: "invalid admin token".into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let err = AdminConfig::default().authorize("").unwrap_err();
+        assert_eq!(
+            (err.code, err.msg.as_str()),
+            (UNSUPPORTED, "admin requests are disabled")
+        );
+
+        let config: AdminConfig = serde_json::from_str(r#"{"token": "change-me"}"#).unwrap();
+        assert!(config.authorize("change-me").is_ok());
+        assert!(config.authorize("change-m").is_err());
+        assert!(config.authorize("change-me ").is_err());
+        let err = config.authorize("wrong").unwrap_err();
+        assert_eq!(
+            (err.code, err.msg.as_str()),
+            (PERMISSION_DENIED, "invalid admin token")
+        );
+    }
+}
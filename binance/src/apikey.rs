@@ -0,0 +1,282 @@
+//! API Key 权限与有效期自检
+//!
+//! 网关启动时查询 API Key 的权限与限制，缺少必需权限时直接退出并给出处理办法；
+//! 之后定期复查，交易权限临近到期时提前告警。
+
+use crate::rest::Rest;
+use cryptoflow::alert::Alerter;
+use log::*;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 现货网关与合约网关都从现货域名查询 Key 限制
+pub const API_RESTRICTIONS_URI: &str = "https://api.binance.com";
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// ```json
+/// "api_key": {
+///     "check_secs": 3600,
+///     "expiry_warn_days": 7
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiKeyConfig {
+    /// 是否在启动时检查
+    pub enabled: bool,
+    /// 复查间隔，0 表示只在启动时检查
+    pub check_secs: u64,
+    /// 交易权限到期前多少天开始告警
+    pub expiry_warn_days: i64,
+}
+
+impl Default for ApiKeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_secs: 3600,
+            expiry_warn_days: 7,
+        }
+    }
+}
+
+/// 网关需要的权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Reading,
+    Spot,
+    Margin,
+    Futures,
+    PortfolioMargin,
+}
+
+impl Permission {
+    fn hint(&self) -> &'static str {
+        match self {
+            Self::Reading => "enable reading",
+            Self::Spot => "enable spot & margin trading",
+            Self::Margin => "enable margin loan, repay & transfer",
+            Self::Futures => "enable futures (the account must have opened a futures wallet)",
+            Self::PortfolioMargin => "enable portfolio margin trading",
+        }
+    }
+}
+
+/// 不影响运行但需要处理的问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiKeyWarning {
+    /// 交易权限还有多少天到期
+    Expiring(i64),
+    NoIpRestrict,
+    Withdrawals,
+}
+
+impl Display for ApiKeyWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Expiring(days) => write!(
+                f,
+                "API key trading permission expires in {} days, restrict the key to trusted IPs to keep it",
+                days
+            ),
+            Self::NoIpRestrict => write!(f, "API key is not restricted to trusted IPs"),
+            Self::Withdrawals => write!(
+                f,
+                "API key has withdrawals enabled, the gateway does not need it"
+            ),
+        }
+    }
+}
+
+/// GET /sapi/v1/account/apiRestrictions
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ApiRestrictions {
+    pub ipRestrict: bool,
+    pub createTime: i64,
+    pub enableReading: bool,
+    pub enableSpotAndMarginTrading: bool,
+    pub enableFutures: bool,
+    pub enableMargin: bool,
+    pub enablePortfolioMarginTrading: bool,
+    pub enableWithdrawals: bool,
+    pub enableInternalTransfer: bool,
+    pub permitsUniversalTransfer: bool,
+    /// 现货与杠杆交易权限的到期时间，未限制 IP 的 Key 才有
+    pub tradingAuthorityExpirationTime: Option<i64>,
+}
+
+impl ApiRestrictions {
+    fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Reading => self.enableReading,
+            Permission::Spot => self.enableSpotAndMarginTrading,
+            Permission::Margin => self.enableMargin,
+            Permission::Futures => self.enableFutures,
+            Permission::PortfolioMargin => self.enablePortfolioMarginTrading,
+        }
+    }
+
+    /// 缺少必需权限时返回错误，其余问题作为警告返回
+    pub fn check(
+        &self,
+        required: &[Permission],
+        now: i64,
+        expiry_warn_days: i64,
+    ) -> anyhow::Result<Vec<ApiKeyWarning>> {
+        let missing: Vec<&str> = required
+            .iter()
+            .filter(|p| !self.allows(**p))
+            .map(|p| p.hint())
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "API key lacks required permissions, {} in API management",
+                missing.join(", ")
+            ));
+        }
+
+        if let Some(expiry) = self.tradingAuthorityExpirationTime {
+            if expiry <= now {
+                return Err(anyhow::anyhow!(
+                    "API key trading permission expired, restrict the key to trusted IPs or re-enable trading"
+                ));
+            }
+        }
+
+        let mut warnings = Vec::new();
+        if let Some(expiry) = self.tradingAuthorityExpirationTime {
+            let days = (expiry - now) / DAY_MS;
+            if days < expiry_warn_days {
+                warnings.push(ApiKeyWarning::Expiring(days));
+            }
+        }
+        if !self.ipRestrict {
+            warnings.push(ApiKeyWarning::NoIpRestrict);
+        }
+        if self.enableWithdrawals {
+            warnings.push(ApiKeyWarning::Withdrawals);
+        }
+        Ok(warnings)
+    }
+}
+
+pub async fn get_api_restrictions(rest: &Rest) -> anyhow::Result<ApiRestrictions> {
+    let rsp = rest
+        .get("/sapi/v1/account/apiRestrictions", &[], true)
+        .await?;
+    let status = rsp.status();
+    let text = rsp.text().await?;
+    if !status.is_success() {
+        return Err(anyhow::anyhow!(
+            "Query API key restrictions failed: {}",
+            text
+        ));
+    }
+    Ok(serde_json::from_str(&text)?)
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+async fn verify(
+    rest: &Rest,
+    required: &[Permission],
+    config: &ApiKeyConfig,
+) -> anyhow::Result<Vec<ApiKeyWarning>> {
+    get_api_restrictions(rest)
+        .await?
+        .check(required, now_ms(), config.expiry_warn_days)
+}
+
+/// 启动时检查 API Key，失败直接返回错误；之后按 `check_secs` 定期复查
+pub async fn check_api_key(
+    rest: Arc<Rest>,
+    required: &[Permission],
+    config: ApiKeyConfig,
+    alerter: Alerter,
+) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+
+    for warning in verify(&rest, required, &config).await? {
+        warn!("{}", warning);
+    }
+    info!("API key permissions verified");
+
+    if config.check_secs == 0 {
+        return Ok(());
+    }
+    let required = required.to_vec();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.check_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match verify(&rest, &required, &config).await {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        warn!("{}", warning);
+                        // 其余问题启动时已经提示过，复查只对到期告警
+                        if let ApiKeyWarning::Expiring(_) = warning {
+                            alerter.on_api_key(warning.to_string());
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    alerter.on_api_key(e.to_string());
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let restrictions: ApiRestrictions = serde_json::from_str(
+            r#"{"ipRestrict":false,"createTime":1698645219000,"enableReading":true,
+            "enableWithdrawals":false,"enableInternalTransfer":false,"enableMargin":false,
+            "enableFutures":false,"permitsUniversalTransfer":false,
+            "enableVanillaOptions":false,"enableFixApiTrade":false,"enableFixReadOnly":false,
+            "enableSpotAndMarginTrading":true,"enablePortfolioMarginTrading":false,
+            "tradingAuthorityExpirationTime":1700000000000}"#,
+        )
+        .unwrap();
+
+        let now = 1700000000000 - 3 * DAY_MS;
+        let warnings = restrictions
+            .check(&[Permission::Reading, Permission::Spot], now, 7)
+            .unwrap();
+        assert_eq!(
+            warnings,
+            vec![ApiKeyWarning::Expiring(3), ApiKeyWarning::NoIpRestrict]
+        );
+        assert_eq!(
+            restrictions.check(&[Permission::Spot], now, 1).unwrap(),
+            vec![ApiKeyWarning::NoIpRestrict]
+        );
+
+        let err = restrictions
+            .check(&[Permission::Reading, Permission::Futures], now, 7)
+            .unwrap_err();
+        assert!(err.to_string().contains("enable futures"));
+        assert!(restrictions
+            .check(&[Permission::Spot], 1700000000000, 7)
+            .is_err());
+    }
+}
@@ -2,21 +2,159 @@ use super::handler::Handler;
 use crate::market::Market; // 交易所（Binance）交互
 use crate::Trade; // 交易逻辑（撮合/下单接口）
 
+use crate::admin::AdminConfig;
+use crate::flatten::Flatten;
+use crate::halt::Halts;
+use crate::heartbeat::{HeartbeatConfig, Heartbeats};
+use crate::idempotency::{IdempotencyCache, IdempotencyConfig};
+use crate::ops_report::OpsReport;
+use crate::params::ParamStore;
+use crate::prefetch::Prefetch;
+use crate::venue_status::VenueStatus;
+use crate::wire::{ClientSender, Connection, SharedFormat, WireState};
+use cryptoflow::alert::Alerter;
+use cryptoflow::portfolio::Portfolio;
+use cryptoflow::runtime::RuntimeConfig;
+use cryptoflow::tenant::Tenants;
 use log::*;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use std::sync::Mutex;
 use tokio::sync::oneshot;
 use tungstenite::Message;
-use websocket::{Connection, TcpStreamReceiver, TcpStreamSender, WebSocketServer}; // 与 Python 客户端的 WS 服务器
+// 与 Python 客户端的 WS 服务器
+use websocket::{
+    bounded_channel, BoundedReceiver, BoundedSender, ChannelConfig, ListenerConfig,
+    TcpStreamReceiver, TcpStreamSender, WebSocketServer,
+};
 
 pub struct Application {
     listener: WebSocketServer,
+    alerter: Alerter,
+    portfolio: Portfolio,
+    idempotency: IdempotencyConfig,
+    channel: ChannelConfig,
+    runtime: RuntimeConfig,
+    params: Option<ParamStore>,
+    admin: AdminConfig,
+    flatten: Option<Flatten>,
+    heartbeat: HeartbeatConfig,
+    tenants: Tenants,
+    halts: Halts,
+    venue_status: VenueStatus,
+    ops_report: OpsReport,
+    prefetch: Option<Prefetch>,
 }
 
 impl Application {
     pub async fn new(local: &str) -> anyhow::Result<Self> {
         info!("-------------------- Start --------------------");
         let listener = WebSocketServer::new(local).await?;
-        Ok(Self { listener })
+        Ok(Self {
+            listener,
+            alerter: Alerter::default(),
+            portfolio: Portfolio::default(),
+            idempotency: IdempotencyConfig::default(),
+            channel: ChannelConfig::default(),
+            runtime: RuntimeConfig::default(),
+            params: None,
+            admin: AdminConfig::default(),
+            flatten: None,
+            heartbeat: HeartbeatConfig::default(),
+            tenants: Tenants::default(),
+            halts: Halts::default(),
+            venue_status: VenueStatus::default(),
+            ops_report: OpsReport::default(),
+            prefetch: None,
+        })
+    }
+
+    /// 设置策略端监听端口的准入限制
+    pub fn with_listener(mut self, config: &ListenerConfig) -> anyhow::Result<Self> {
+        self.listener = self.listener.with_limits(config)?;
+        Ok(self)
+    }
+
+    /// 设置告警器，交易所断线超过阈值时通过它推送告警
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = alerter;
+        self
+    }
+
+    /// 设置组合敞口，策略端通过 get_portfolio 查询
+    pub fn with_portfolio(mut self, portfolio: Portfolio) -> Self {
+        self.portfolio = portfolio;
+        self
+    }
+
+    /// 设置下单与撤单幂等键的去重窗口
+    pub fn with_idempotency(mut self, config: IdempotencyConfig) -> Self {
+        self.idempotency = config;
+        self
+    }
+
+    /// 设置策略端与 handler 之间通道的容量与溢出处理
+    pub fn with_channel(mut self, config: ChannelConfig) -> Self {
+        self.channel = config;
+        self
+    }
+
+    /// 设置策略参数的存储，策略端通过 get_params 读取、运维通过 set_params 修改
+    pub fn with_params(mut self, params: ParamStore) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    /// 设置运维请求的认证
+    pub fn with_admin(mut self, config: AdminConfig) -> Self {
+        self.admin = config;
+        self
+    }
+
+    /// 设置定时平仓的计划
+    pub fn with_flatten(mut self, flatten: Flatten) -> Self {
+        self.flatten = Some(flatten);
+        self
+    }
+
+    /// 设置策略心跳巡检，策略卡死时保护它的订单与持仓
+    pub fn with_heartbeat(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeat = config;
+        self
+    }
+
+    /// 设置租户，登录时校验租户，策略端只能查询本租户的数据
+    pub fn with_tenants(mut self, tenants: Tenants) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// 设置暂停交易的交易对，须与交易共享，运维通过 halt_trading 与 resume_trading 修改
+    pub fn with_halts(mut self, halts: Halts) -> Self {
+        self.halts = halts;
+        self
+    }
+
+    /// 设置交易场所状态的巡检，状态变化推送给策略端并按配置暂停交易对
+    pub fn with_venue_status(mut self, venue_status: VenueStatus) -> Self {
+        self.venue_status = venue_status;
+        self
+    }
+
+    /// 设置运维报告，须与交易共享，handler 统计交易所连接的重连次数
+    pub fn with_ops_report(mut self, ops_report: OpsReport) -> Self {
+        self.ops_report = ops_report;
+        self
+    }
+
+    /// 设置 handler 是否运行在独立的行情扇出线程上
+    /// 预取结束后才接受策略端连接
+    pub fn with_prefetch(mut self, prefetch: Prefetch) -> Self {
+        self.prefetch = Some(prefetch);
+        self
+    }
+
+    pub fn with_runtime(mut self, config: RuntimeConfig) -> Self {
+        self.runtime = config;
+        self
     }
 
     /// 接收“策略客户端（Python）⇄本系统”的 WebSocket 连接，并把连接交给 handler
@@ -24,7 +162,7 @@ impl Application {
     /// 当addr地址（往往是8111）通过accept收到新链接的时候
     async fn accept_strategy_clients(
         &self,
-        client_conn_tx: &UnboundedSender<Connection>,
+        client_conn_tx: &BoundedSender<Connection>,
         mut stop: oneshot::Receiver<()>,
     ) -> anyhow::Result<()> {
         loop {
@@ -33,16 +171,20 @@ impl Application {
                     match res {
                         Ok((addr, client_sender, client_receiver)) => {
                             // to_handler: 客户端请求 -> handler；from_handler: handler 响应 -> 客户端
-                            let (to_handler_tx, to_handler_rx) = unbounded_channel();
-                            let (from_handler_tx, from_handler_rx) = unbounded_channel();
+                            let (to_handler_tx, to_handler_rx) = bounded_channel(format!("{} -> handler", addr), &self.channel);
+                            let (from_handler_tx, from_handler_rx) = bounded_channel(format!("handler -> {}", addr), &self.channel);
 
                             // 通知 handler 有新连接，把新链接信息发送给链接处理handler
                             // from_handler_tx: sender[handler -> 策略端]
                             // to_handler_rx: receiver[策略端 -> handler]
-                            client_conn_tx.send((addr, from_handler_tx, to_handler_rx))?;
+                            // 每条连接各自协商编码，handler 与转发任务共用
+                            let format = SharedFormat::default();
+                            let from_handler_tx = ClientSender::new(from_handler_tx, format.clone());
+                            client_conn_tx.send_wait((addr, from_handler_tx, to_handler_rx)).await?;
 
                             // 为该策略新链接启动转发任务
-                            tokio::spawn(manage_connection_with_strategy(to_handler_tx, from_handler_rx, client_sender, client_receiver));
+                            let wire = WireState::new(format);
+                            tokio::spawn(manage_connection_with_strategy(to_handler_tx, from_handler_rx, client_sender, client_receiver, wire));
                         },
                         Err(e) => error!("Accept new connection error: {}", e)
                     }
@@ -58,17 +200,38 @@ impl Application {
     /// 这个函数是用来运行整个后端的
     /// 它会等待策略端的链接，策略端会把会话的writer和reader发送过来，然后
     pub async fn keep_running<T: Trade + Send + 'static>(
-        self,
+        mut self,
         mut market: Market,
         mut trade: T,
     ) -> anyhow::Result<()> {
         // 当有新的策略客户端连接时，client_conn_tx会把链接的信息发送给client_conn_rx，即handler
-        let (client_conn_tx, client_conn_rx) = unbounded_channel();
+        let (client_conn_tx, client_conn_rx) = bounded_channel("connections", &self.channel);
         // 当handler出错，也终止接收新的client连接
         let (stop_tx, stop_rx) = oneshot::channel();
 
-        tokio::spawn(async move {
-            let mut handler = Handler::new();
+        let alerter = self.alerter.clone();
+        let portfolio = self.portfolio.clone();
+        let idempotency = IdempotencyCache::new(self.idempotency.clone());
+        let params = self.params.take().unwrap_or_default();
+        let admin = self.admin.clone();
+        let flatten = self.flatten.take().unwrap_or_default();
+        let heartbeats = Heartbeats::new(self.heartbeat.clone());
+        let tenants = self.tenants.clone();
+        let halts = self.halts.clone();
+        let venue_status = self.venue_status.clone();
+        let ops_report = self.ops_report.clone();
+        self.runtime.spawn_market(async move {
+            let mut handler = Handler::with_alerter(alerter)
+                .with_portfolio(portfolio)
+                .with_idempotency(idempotency)
+                .with_params(params)
+                .with_admin(admin)
+                .with_flatten(flatten)
+                .with_heartbeats(heartbeats)
+                .with_tenants(tenants)
+                .with_halts(halts)
+                .with_venue_status(venue_status)
+                .with_ops_report(ops_report);
 
             // client_conn_rx是接收链接信息的，里面包含了收发通道
             if let Err(e) = handler
@@ -80,8 +243,11 @@ impl Application {
 
             info!("-------------------- Exit --------------------");
             let _ = stop_tx.send(());
-        });
+        })?;
 
+        if let Some(mut prefetch) = self.prefetch.take() {
+            prefetch.ready().await;
+        }
         // 接收策略端的链接，进行消息转发。
         self.accept_strategy_clients(&client_conn_tx, stop_rx)
             .await?;
@@ -91,17 +257,22 @@ impl Application {
 // 从客户端接收消息并转发给服务端处理器
 async fn forward_client_to_server(
     client_receiver: &mut TcpStreamReceiver,
-    to_handler_tx: &UnboundedSender<Message>,
+    to_handler_tx: &BoundedSender<Message>,
+    wire: &Mutex<WireState>,
 ) -> anyhow::Result<()> {
     if let Some(inner) = client_receiver.recv().await {
-        let msg = inner?;
+        let msg = wire
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .on_client(inner?)?;
         match msg {
             Message::Close(_) => {
                 info!("Peer {} Close", client_receiver.addr());
-                to_handler_tx.send(msg)?;
+                to_handler_tx.send_wait(msg).await?;
                 return Err(anyhow::anyhow!("WebSocket Close"));
             }
-            _ => to_handler_tx.send(msg)?,
+            // 策略端请求方向背压，不丢弃
+            _ => to_handler_tx.send_wait(msg).await?,
         }
     }
 
@@ -110,11 +281,23 @@ async fn forward_client_to_server(
 
 // 从服务端处理器接收响应并转发给客户端
 async fn forward_server_to_client(
-    from_handler_rx: &mut UnboundedReceiver<Message>,
+    from_handler_rx: &mut BoundedReceiver<Message>,
     client_sender: &mut TcpStreamSender,
+    wire: &Mutex<WireState>,
 ) -> anyhow::Result<()> {
     match from_handler_rx.recv().await {
-        Some(inner) => client_sender.send(inner).await?,
+        Some(_) if from_handler_rx.overflowed() => {
+            return Err(anyhow::anyhow!("Slow consumer, channel overflowed"))
+        }
+        Some(inner) => {
+            let msg = wire
+                .lock()
+                .unwrap_or_else(|p| p.into_inner())
+                .on_server(inner)?;
+            if let Some(msg) = msg {
+                client_sender.send(msg).await?
+            }
+        }
         None => {
             if from_handler_rx.is_closed() {
                 return Err(anyhow::anyhow!("Receiver Close"));
@@ -126,20 +309,22 @@ async fn forward_server_to_client(
 
 // 管理单个客户端的双向消息转发
 async fn manage_connection_with_strategy(
-    to_server_tx: UnboundedSender<Message>,
-    mut from_server_rx: UnboundedReceiver<Message>,
+    to_server_tx: BoundedSender<Message>,
+    mut from_server_rx: BoundedReceiver<Message>,
     mut client_sender: TcpStreamSender,
     mut client_receiver: TcpStreamReceiver,
+    wire: WireState,
 ) {
+    let wire = Mutex::new(wire);
     loop {
         tokio::select! {
-            res = forward_client_to_server(&mut client_receiver, &to_server_tx) => {
+            res = forward_client_to_server(&mut client_receiver, &to_server_tx, &wire) => {
                 if let Err(e) = res {
                     error!("{}", e);
                     break
                 }
             },
-            res = forward_server_to_client(&mut from_server_rx, &mut client_sender) => {
+            res = forward_server_to_client(&mut from_server_rx, &mut client_sender, &wire) => {
                 if let Err(e) = res {
                     error!("{}", e);
                     break
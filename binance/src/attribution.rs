@@ -0,0 +1,259 @@
+//! 按策略与标签归因
+//!
+//! 下单请求可以带 `strategy` 与 `tags`，同一个会话中运行多个策略时，网关按它们把成交归入分组：
+//! `strategy:{name}` 与每个 `tag:{name}`，两者都没有的归入 `untagged`。一笔成交同时计入它的策略与所有
+//! 标签，不同分组的统计不能相加。
+//!
+//! 每个分组统计成交量、成交额、手续费、按移动平均成本计算的已实现盈亏，以及相对到达价的滑点。
+//! 到达价是网关收到下单请求时的盘口中间价，没有盘口时用委托价。统计只保存在内存中，网关重启后清零；
+//! 逐笔的订单与成交连同 `strategy`、`tags` 由 [`cryptoflow::storage`] 保存，可以离线重新计算。
+
+use crate::model::order::BinanceOrder;
+use cryptoflow::chat::{SAttribution, SAttributionPosition, SFill, Side};
+use std::collections::{BTreeMap, HashMap};
+
+/// 既没有 `strategy` 也没有 `tags` 的订单归入的分组
+pub const UNTAGGED: &str = "untagged";
+
+#[derive(Debug, Clone)]
+pub struct Tagged {
+    pub strategy: Option<String>,
+    pub tags: Vec<String>,
+    pub arrival: f64,
+}
+
+impl Tagged {
+    fn buckets(&self) -> Vec<String> {
+        let mut buckets: Vec<String> = self
+            .strategy
+            .iter()
+            .map(|strategy| format!("strategy:{}", strategy))
+            .chain(self.tags.iter().map(|tag| format!("tag:{}", tag)))
+            .collect();
+        if buckets.is_empty() {
+            buckets.push(UNTAGGED.into());
+        }
+        buckets
+    }
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    stats: SAttribution,
+    /// 有到达价的成交额，滑点按它加权
+    arrival_notional: f64,
+    /// symbol -> (净持仓, 平均成本)
+    positions: HashMap<String, (f64, f64)>,
+}
+
+impl Bucket {
+    fn on_fill(&mut self, fill: &SFill, arrival: Option<f64>) {
+        let notional = fill.price * fill.quantity;
+        let stats = &mut self.stats;
+        stats.fills += 1;
+        stats.volume += fill.quantity;
+        stats.notional += notional;
+        if fill.commission != 0.0 {
+            *stats
+                .commissions
+                .entry(fill.commission_asset.clone())
+                .or_default() += fill.commission;
+        }
+
+        let sign = match fill.side {
+            Side::BUY => 1.0,
+            Side::SELL => -1.0,
+        };
+        if let Some(arrival) = arrival.filter(|arrival| *arrival > 0.0) {
+            let bps = sign * (fill.price - arrival) / arrival * 1e4;
+            let total = self.arrival_notional + notional;
+            if total > 0.0 {
+                stats.slippage_bps =
+                    (stats.slippage_bps * self.arrival_notional + bps * notional) / total;
+            }
+            self.arrival_notional = total;
+        }
+
+        let (net, avg) = self.positions.entry(fill.symbol.clone()).or_default();
+        let quantity = sign * fill.quantity;
+        if *net == 0.0 || net.signum() == quantity.signum() {
+            *avg = (*avg * net.abs() + fill.price * fill.quantity) / (net.abs() + fill.quantity);
+        } else {
+            let closed = fill.quantity.min(net.abs());
+            stats.realized_pnl += closed * (fill.price - *avg) * net.signum();
+            if fill.quantity > closed {
+                // 反手，剩余部分按成交价开仓
+                *avg = fill.price;
+            }
+        }
+        *net += quantity;
+        if net.abs() < 1e-12 {
+            *net = 0.0;
+            *avg = 0.0;
+        }
+    }
+
+    fn report(&self) -> SAttribution {
+        let mut positions: Vec<_> = self
+            .positions
+            .iter()
+            .filter(|(_, (net, _))| *net != 0.0)
+            .map(|(symbol, (net, avg))| SAttributionPosition {
+                symbol: symbol.clone(),
+                net: *net,
+                avg_price: *avg,
+            })
+            .collect();
+        positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        SAttribution {
+            positions,
+            ..self.stats.clone()
+        }
+    }
+}
+
+/// 一个会话的归因统计
+#[derive(Debug, Default)]
+pub struct Attribution {
+    /// order_id -> 订单的策略、标签与到达价，订单终结后移除
+    orders: HashMap<u32, Tagged>,
+    buckets: BTreeMap<String, Bucket>,
+}
+
+impl Attribution {
+    /// 登记通过风控的订单，`arrival` 为到达价
+    pub fn on_order(&mut self, order: &BinanceOrder, arrival: f64) {
+        let tagged = Tagged {
+            strategy: order.strategy.clone(),
+            tags: order.tags.clone(),
+            arrival,
+        };
+        for name in tagged.buckets() {
+            let bucket = self.bucket(name);
+            bucket.stats.orders += 1;
+        }
+        self.orders.insert(order.id, tagged);
+    }
+
+    pub fn get(&self, order_id: u32) -> Option<&Tagged> {
+        self.orders.get(&order_id)
+    }
+
+    /// 成交计入订单的所有分组，不是经由 [`Self::on_order`] 登记的订单计入 `untagged` 且没有滑点
+    pub fn on_fill(&mut self, order_id: u32, fill: &SFill) {
+        let (names, arrival) = match self.orders.get(&order_id) {
+            Some(tagged) => (tagged.buckets(), Some(tagged.arrival)),
+            None => (vec![UNTAGGED.to_string()], None),
+        };
+        for name in names {
+            self.bucket(name).on_fill(fill, arrival);
+        }
+    }
+
+    /// 订单终结后不会再有成交
+    pub fn remove(&mut self, order_id: u32) {
+        self.orders.remove(&order_id);
+    }
+
+    pub fn report(&self) -> Vec<SAttribution> {
+        self.buckets.values().map(Bucket::report).collect()
+    }
+
+    fn bucket(&mut self, name: String) -> &mut Bucket {
+        self.buckets.entry(name.clone()).or_insert_with(|| Bucket {
+            stats: SAttribution {
+                name,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// 到达价，有盘口时为中间价，否则为委托价
+pub fn arrival_price(order: &BinanceOrder, bbo: Option<(f64, f64)>) -> f64 {
+    match bbo {
+        Some((bid, ask)) if bid > 0.0 && ask > 0.0 => (bid + ask) / 2.0,
+        _ => order.price,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u32, side: Side, strategy: Option<&str>, tags: &[&str]) -> BinanceOrder {
+        BinanceOrder {
+            id,
+            symbol: "btcusdt".into(),
+            price: 100.0,
+            quantity: 2.0,
+            side,
+            session_id: 1,
+            strategy: strategy.map(Into::into),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn fill(order_id: u32, side: Side, price: f64, quantity: f64) -> SFill {
+        SFill {
+            internal_id: order_id,
+            order_id: order_id as i64,
+            symbol: "btcusdt".into(),
+            side,
+            trade_id: order_id as i64,
+            time: 1,
+            price,
+            quantity,
+            maker: false,
+            commission: 0.01,
+            commission_asset: "USDT".into(),
+        }
+    }
+
+    #[test]
+    fn test_attribution() {
+        let mut attribution = Attribution::default();
+        let buy = order(1, Side::BUY, Some("mm"), &["alpha"]);
+        attribution.on_order(&buy, arrival_price(&buy, Some((99.0, 101.0))));
+        attribution.on_fill(1, &fill(1, Side::BUY, 101.0, 2.0));
+        attribution.remove(1);
+
+        let sell = order(2, Side::SELL, Some("mm"), &[]);
+        attribution.on_order(&sell, arrival_price(&sell, None));
+        attribution.on_fill(2, &fill(2, Side::SELL, 105.0, 3.0));
+        // 没有登记的订单
+        attribution.on_fill(3, &fill(3, Side::BUY, 90.0, 1.0));
+
+        let report = attribution.report();
+        let names: Vec<_> = report.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["strategy:mm", "tag:alpha", UNTAGGED]);
+
+        let mm = &report[0];
+        assert_eq!((mm.orders, mm.fills, mm.volume), (2, 2, 5.0));
+        assert_eq!(mm.notional, 202.0 + 315.0);
+        assert_eq!(mm.commissions["USDT"], 0.02);
+        // 平掉 2 个赚 (105 - 101) * 2，反手开空 1 个
+        assert!((mm.realized_pnl - 8.0).abs() < 1e-9);
+        assert_eq!(
+            mm.positions,
+            vec![SAttributionPosition {
+                symbol: "btcusdt".into(),
+                net: -1.0,
+                avg_price: 105.0,
+            }]
+        );
+        // 买入高于中间价 100 bps，卖出高于委托价 500 bps
+        let expected = (100.0 * 202.0 - 500.0 * 315.0) / 517.0;
+        assert!((mm.slippage_bps - expected).abs() < 1e-9);
+
+        let alpha = &report[1];
+        assert_eq!((alpha.orders, alpha.fills, alpha.realized_pnl), (1, 1, 0.0));
+        assert!((alpha.slippage_bps - 100.0).abs() < 1e-9);
+
+        let untagged = &report[2];
+        assert_eq!((untagged.orders, untagged.fills), (0, 1));
+        assert_eq!(untagged.slippage_bps, 0.0);
+    }
+}
@@ -0,0 +1,219 @@
+//! 重连后补齐断线期间的 K 线
+//!
+//! 行情连接断开期间交易所推送的 K 线全部丢失。重连后网关按每条 K 线流最后收到的 K 线，通过 REST
+//! 拉取断线期间已经完结的 K 线，标记 `backfill` 后按时间顺序推送给订阅者，之后才继续推送实时行情。
+
+use cryptoflow::chat::SGeneralKline;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// ```json
+/// "backfill": {
+///     "enabled": true,
+///     "url": "https://api.binance.com/api/v3/klines",
+///     "limit": 1000,
+///     "max_bars": 5000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BackfillConfig {
+    pub enabled: bool,
+    /// K 线 REST 接口，合约为 `https://fapi.binance.com/fapi/v1/klines`
+    pub url: String,
+    /// 每个请求最多返回的 K 线数
+    pub limit: usize,
+    /// 每条 K 线流最多补齐的 K 线数，断线更久时只补最早的部分
+    pub max_bars: usize,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: "https://api.binance.com/api/v3/klines".into(),
+            limit: 1000,
+            max_bars: 5000,
+        }
+    }
+}
+
+/// 需要补齐的 K 线流
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gap {
+    /// 交易所 K 线流名称，如 `btcusdt@kline_1m`
+    pub stream: String,
+    /// 从这个时间开始的 K 线都需要补齐
+    pub from: i64,
+}
+
+/// 根据最后收到的 K 线计算补齐的起点：已完结的从下一根开始，未完结的连同这一根重新拉取
+pub fn resume_from(kline: &SGeneralKline) -> i64 {
+    match kline.is_closed {
+        true => kline.time + 1,
+        false => kline.start_time,
+    }
+}
+
+/// 解析 REST 返回的 K 线数组，只保留在 `now` 之前完结的 K 线
+fn parse_klines(stream: &str, value: &Value, now: i64) -> anyhow::Result<Vec<SGeneralKline>> {
+    let (symbol, interval) = stream
+        .split_once("@kline_")
+        .ok_or_else(|| anyhow::anyhow!("{} is not a kline stream", stream))?;
+    let rows = value
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected klines {}", value))?;
+
+    let int = |row: &[Value], i: usize| row.get(i).and_then(Value::as_i64).unwrap_or_default();
+    let float = |row: &[Value], i: usize| -> f64 {
+        row.get(i)
+            .and_then(Value::as_str)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default()
+    };
+
+    let mut klines = Vec::new();
+    for row in rows {
+        let row = row
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected kline {}", row))?;
+        let time = int(row, 6);
+        if time >= now {
+            continue;
+        }
+        klines.push(SGeneralKline {
+            time,
+            start_time: int(row, 0),
+            symbol: symbol.to_lowercase(),
+            stream: format!("{}@kline:{}", symbol, interval).to_lowercase(),
+            interval: interval.to_string(),
+            open: float(row, 1),
+            high: float(row, 2),
+            low: float(row, 3),
+            close: float(row, 4),
+            volume: float(row, 5),
+            amount: float(row, 7),
+            first_trade_id: 0,
+            last_trade_id: 0,
+            trade_count: int(row, 8),
+            is_closed: true,
+            buy_volume: float(row, 9),
+            buy_amount: float(row, 10),
+            backfill: true,
+        });
+    }
+    Ok(klines)
+}
+
+/// 拉取一条 K 线流从 `gap.from` 到现在已经完结的 K 线
+pub async fn fetch(
+    client: &reqwest::Client,
+    config: &BackfillConfig,
+    gap: &Gap,
+) -> anyhow::Result<Vec<SGeneralKline>> {
+    let (symbol, interval) = gap
+        .stream
+        .split_once("@kline_")
+        .ok_or_else(|| anyhow::anyhow!("{} is not a kline stream", gap.stream))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let limit = config.limit.max(1);
+
+    let mut klines = Vec::new();
+    let mut from = gap.from;
+    while klines.len() < config.max_bars {
+        let value: Value = client
+            .get(&config.url)
+            .query(&[
+                ("symbol", symbol.to_uppercase()),
+                ("interval", interval.to_string()),
+                ("startTime", from.to_string()),
+                ("limit", limit.to_string()),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+        let rows = value.as_array().map(Vec::len).unwrap_or_default();
+        let page = parse_klines(&gap.stream, &value, now)?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        from = last.time + 1;
+        klines.extend(page);
+        if rows < limit {
+            break;
+        }
+    }
+    if klines.len() > config.max_bars {
+        warn!(
+            "Backfill of {} truncated to {} bars",
+            gap.stream, config.max_bars
+        );
+        klines.truncate(config.max_bars);
+    }
+    info!("Backfill {} klines of {}", klines.len(), gap.stream);
+    Ok(klines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_klines() {
+        let value = json!([
+            [
+                1672515780000i64,
+                "1.0",
+                "3.0",
+                "0.5",
+                "2.0",
+                "100",
+                1672515839999i64,
+                "200",
+                10,
+                "40",
+                "80",
+                "0"
+            ],
+            [
+                1672515840000i64,
+                "2.0",
+                "2.0",
+                "2.0",
+                "2.0",
+                "1",
+                1672515899999i64,
+                "2",
+                1,
+                "0",
+                "0",
+                "0"
+            ]
+        ]);
+        // 第二根在 now 时尚未完结
+        let klines = parse_klines("btcusdt@kline_1m", &value, 1672515850000).unwrap();
+        assert_eq!(klines.len(), 1);
+        let kline = &klines[0];
+        assert_eq!(kline.stream, "btcusdt@kline:1m");
+        assert_eq!(
+            (kline.start_time, kline.time),
+            (1672515780000, 1672515839999)
+        );
+        assert_eq!(
+            (kline.open, kline.high, kline.low, kline.close),
+            (1.0, 3.0, 0.5, 2.0)
+        );
+        assert_eq!(
+            (kline.amount, kline.trade_count, kline.buy_amount),
+            (200.0, 10, 80.0)
+        );
+        assert!(kline.is_closed && kline.backfill);
+
+        assert_eq!(resume_from(kline), 1672515839999 + 1);
+        assert!(parse_klines("btcusdt@bookTicker", &value, 0).is_err());
+    }
+}
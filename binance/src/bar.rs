@@ -0,0 +1,196 @@
+//! 按时钟推送 K 线收线
+//!
+//! 交易所的 K 线推送断流或延迟时，策略收不到 `is_closed` 的 K 线。网关按订阅的周期在每个收线时刻
+//! （加上可配置的宽限）主动推送 `bar_close` 事件，附带最近一根 K 线。时钟按 K 线推送的事件时间与本地时间的
+//! 偏差校准到交易所时间。
+
+use cryptoflow::chat::{SBarClose, SGeneralKline};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECOND: i64 = 1000;
+const MINUTE: i64 = 60 * SECOND;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+/// 周线从周一开始，1970-01-01 是周四
+const WEEK_OFFSET: i64 = 4 * DAY;
+
+/// ```json
+/// "bar_clock": {
+///     "enabled": true,
+///     "delay_ms": 500
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct BarClockConfig {
+    pub enabled: bool,
+    /// 收线时刻之后再等待这么久推送，留给交易所的收线 K 线先到达
+    pub delay_ms: i64,
+}
+
+/// K 线周期的毫秒数与对齐偏移，不支持按自然月的 `1M`
+pub(crate) fn interval_ms(interval: &str) -> Option<(i64, i64)> {
+    let (n, unit) = interval.split_at(interval.len().checked_sub(1)?);
+    let n: i64 = n.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "s" => Some((n * SECOND, 0)),
+        "m" => Some((n * MINUTE, 0)),
+        "h" => Some((n * HOUR, 0)),
+        "d" => Some((n * DAY, 0)),
+        "w" => Some((n * 7 * DAY, WEEK_OFFSET)),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct Bar {
+    symbol: String,
+    interval: String,
+    length: i64,
+    offset: i64,
+    /// 下一次收线时刻，首次推送前为 None
+    next_close: Option<i64>,
+    last: Option<SGeneralKline>,
+}
+
+impl Bar {
+    fn boundary_after(&self, time: i64) -> i64 {
+        (time - self.offset).div_euclid(self.length) * self.length + self.length + self.offset
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BarClock {
+    config: BarClockConfig,
+    /// 交易所 K 线流名称，如 `btcusdt@kline_1m`
+    bars: HashMap<String, Bar>,
+    /// 交易所时间减本地时间
+    offset_ms: i64,
+}
+
+impl BarClock {
+    pub fn new(config: &BarClockConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// 开始为一条 K 线流计时，不是 K 线流或周期不支持时忽略
+    pub fn track(&mut self, stream: &str) {
+        if !self.config.enabled || self.bars.contains_key(stream) {
+            return;
+        }
+        let Some((symbol, interval)) = stream.split_once("@kline_") else {
+            return;
+        };
+        let Some((length, offset)) = interval_ms(interval) else {
+            return;
+        };
+        self.bars.insert(
+            stream.to_string(),
+            Bar {
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+                length,
+                offset,
+                next_close: None,
+                last: None,
+            },
+        );
+    }
+
+    pub fn untrack(&mut self, stream: &str) {
+        self.bars.remove(stream);
+    }
+
+    /// 记录交易所推送的 K 线，`event_time` 用于校准时钟
+    pub fn on_kline(&mut self, stream: &str, event_time: i64, kline: &SGeneralKline) {
+        if let Some(bar) = self.bars.get_mut(stream) {
+            self.offset_ms = event_time - now_ms();
+            bar.last = Some(kline.clone());
+        }
+    }
+
+    /// 按校准后的时间取出到期的收线
+    pub fn due(&mut self) -> Vec<(String, SBarClose)> {
+        self.due_at(now_ms() + self.offset_ms)
+    }
+
+    fn due_at(&mut self, now: i64) -> Vec<(String, SBarClose)> {
+        let delay = self.config.delay_ms.max(0);
+        let mut closes = Vec::new();
+        for (stream, bar) in self.bars.iter_mut() {
+            let upcoming = bar.boundary_after(now - delay);
+            let next = *bar.next_close.get_or_insert(upcoming);
+            if now < next + delay {
+                continue;
+            }
+            // 长时间阻塞后只补最近一次收线
+            let latest = upcoming - bar.length;
+            closes.push((
+                stream.clone(),
+                SBarClose {
+                    bar_close: latest,
+                    symbol: bar.symbol.clone(),
+                    stream: format!("{}@kline:{}", bar.symbol, bar.interval),
+                    interval: bar.interval.clone(),
+                    kline: bar.last.clone(),
+                },
+            ));
+            bar.next_close = Some(upcoming);
+        }
+        closes
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bar_clock() {
+        assert_eq!(interval_ms("15m"), Some((15 * MINUTE, 0)));
+        assert_eq!(interval_ms("1w"), Some((7 * DAY, WEEK_OFFSET)));
+        assert_eq!(interval_ms("1M"), None);
+
+        let mut clock = BarClock::new(&BarClockConfig {
+            enabled: true,
+            delay_ms: 100,
+        });
+        clock.track("btcusdt@kline_1m");
+        clock.track("btcusdt@kline_1M");
+        clock.track("btcusdt@bookTicker");
+        assert_eq!(clock.bars.len(), 1);
+
+        let start = 1672515780000;
+        assert!(clock.due_at(start + 200).is_empty());
+        // 收线时刻到了，宽限期内不推送
+        assert!(clock.due_at(start + MINUTE + 50).is_empty());
+        let closes = clock.due_at(start + MINUTE + 100);
+        assert_eq!(closes.len(), 1);
+        let (stream, close) = &closes[0];
+        assert_eq!(stream, "btcusdt@kline_1m");
+        assert_eq!(close.bar_close, start + MINUTE);
+        assert_eq!(close.stream, "btcusdt@kline:1m");
+        assert!(close.kline.is_none());
+        assert!(clock.due_at(start + MINUTE + 200).is_empty());
+
+        // 跨过多个周期只推送最近一次
+        let closes = clock.due_at(start + 5 * MINUTE + 100);
+        assert_eq!(closes[0].1.bar_close, start + 5 * MINUTE);
+        assert_eq!(closes.len(), 1);
+
+        clock.untrack("btcusdt@kline_1m");
+        assert!(clock.due_at(start + 10 * MINUTE).is_empty());
+    }
+}
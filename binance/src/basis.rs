@@ -0,0 +1,232 @@
+//! 永续合约与现货的基差
+//!
+//! 合约网关开启 `basis` 后另外连接现货行情（见 [`crate::feed`]）。策略订阅 `{symbol}@basis`（如
+//! `btcusdt@basis`），网关在主连接上订阅该合约的 `bookTicker` 与 `markPrice@1s`，在现货连接上订阅同名
+//! 现货的 `bookTicker`。任一边的最优价变化后按买一卖一的中间价计算基差，每个交易对至多每 `interval_ms`
+//! 推送一次；资金费率取自标记价格流，按 `funding_interval_hours` 年化。`get_basis` 返回当前的基差。
+
+use cryptoflow::chat::SBasis;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const SUFFIX: &str = "@basis";
+
+/// ```json
+/// "basis": {
+///     "enabled": false,
+///     "spot_stream_url": "wss://stream.binance.com:9443/stream",
+///     "interval_ms": 1000,
+///     "funding_interval_hours": 8
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BasisConfig {
+    /// 只在合约网关上开启
+    pub enabled: bool,
+    /// 现货 combined 行情地址
+    pub spot_stream_url: String,
+    /// 同一交易对两次推送的最小间隔
+    pub interval_ms: i64,
+    /// 资金费的收取间隔，用于年化资金费率
+    pub funding_interval_hours: f64,
+}
+
+impl Default for BasisConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spot_stream_url: "wss://stream.binance.com:9443/stream".into(),
+            interval_ms: 1000,
+            funding_interval_hours: 8.0,
+        }
+    }
+}
+
+pub fn is_basis_stream(stream: &str) -> bool {
+    stream.strip_suffix(SUFFIX).is_some_and(|s| !s.is_empty())
+}
+
+/// 基差的流在合约连接上对应的交易所流
+fn perp_streams(symbol: &str) -> [String; 2] {
+    [
+        format!("{}@bookTicker", symbol),
+        format!("{}@markPrice@1s", symbol),
+    ]
+}
+
+/// 把基差的流换成合约连接与现货连接上的流，返回合约连接与现货连接上的流
+pub fn split_streams(streams: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut perp: Vec<String> = Vec::new();
+    let mut spot = Vec::new();
+    for stream in streams {
+        let wire = match stream.strip_suffix(SUFFIX).filter(|s| !s.is_empty()) {
+            Some(symbol) => {
+                spot.push(format!("{}@bookTicker", symbol));
+                perp_streams(symbol).to_vec()
+            }
+            None => vec![stream],
+        };
+        for stream in wire {
+            if !perp.contains(&stream) {
+                perp.push(stream);
+            }
+        }
+    }
+    (perp, spot)
+}
+
+#[derive(Debug, Default)]
+struct Leg {
+    spot: Option<f64>,
+    perp: Option<f64>,
+    funding_rate: Option<f64>,
+    next_funding_time: i64,
+    /// 上次推送的时间
+    published: Option<i64>,
+}
+
+/// 策略订阅了基差的交易对，按小写的交易对索引
+#[derive(Debug, Default)]
+pub struct Basis {
+    config: BasisConfig,
+    legs: HashMap<String, Leg>,
+}
+
+impl Basis {
+    pub fn new(config: &BasisConfig) -> Self {
+        Self {
+            config: config.clone(),
+            legs: HashMap::default(),
+        }
+    }
+
+    /// 开始计算 `{symbol}@basis`，不是基差的流忽略
+    pub fn track(&mut self, stream: &str) {
+        if let Some(symbol) = stream.strip_suffix(SUFFIX).filter(|s| !s.is_empty()) {
+            self.legs.entry(symbol.to_string()).or_default();
+        }
+    }
+
+    pub fn untrack(&mut self, stream: &str) {
+        if let Some(symbol) = stream.strip_suffix(SUFFIX) {
+            self.legs.remove(symbol);
+        }
+    }
+
+    /// 合约连接上的流是否仍被基差使用
+    pub fn uses(&self, stream: &str) -> bool {
+        stream
+            .split_once('@')
+            .is_some_and(|(symbol, _)| self.legs.contains_key(symbol))
+            && (stream.ends_with("@bookTicker") || stream.ends_with("@markPrice@1s"))
+    }
+
+    /// 现货的最优买卖价，`now` 为毫秒时间；到了推送间隔时返回最新的基差
+    pub fn on_spot(&mut self, symbol: &str, bid: f64, ask: f64, now: i64) -> Option<SBasis> {
+        if self.legs.is_empty() {
+            return None;
+        }
+        let symbol = symbol.to_lowercase();
+        self.legs.get_mut(&symbol)?.spot = mid(bid, ask);
+        self.publish(&symbol, now)
+    }
+
+    /// 合约的最优买卖价
+    pub fn on_perp(&mut self, symbol: &str, bid: f64, ask: f64, now: i64) -> Option<SBasis> {
+        if self.legs.is_empty() {
+            return None;
+        }
+        let symbol = symbol.to_lowercase();
+        self.legs.get_mut(&symbol)?.perp = mid(bid, ask);
+        self.publish(&symbol, now)
+    }
+
+    /// 标记价格流上的资金费率，不单独触发推送
+    pub fn on_mark_price(&mut self, symbol: &str, funding_rate: f64, next_funding_time: i64) {
+        if let Some(leg) = self.legs.get_mut(&symbol.to_lowercase()) {
+            leg.funding_rate = Some(funding_rate);
+            leg.next_funding_time = next_funding_time;
+        }
+    }
+
+    fn publish(&mut self, symbol: &str, now: i64) -> Option<SBasis> {
+        let interval = self.config.interval_ms;
+        let leg = self.legs.get(symbol)?;
+        if leg.published.is_some_and(|t| now - t < interval) {
+            return None;
+        }
+        let basis = self.basis(symbol, now)?;
+        self.legs.get_mut(symbol)?.published = Some(now);
+        Some(basis)
+    }
+
+    /// 两边都有报价时的基差
+    pub fn basis(&self, symbol: &str, now: i64) -> Option<SBasis> {
+        let symbol = symbol.to_lowercase();
+        let leg = self.legs.get(&symbol)?;
+        let (spot, perp) = (leg.spot?, leg.perp?);
+        let periods = 365.0 * 24.0 / self.config.funding_interval_hours;
+        Some(SBasis {
+            time: now,
+            stream: format!("{}{}", symbol, SUFFIX),
+            symbol,
+            spot,
+            perp,
+            basis: perp - spot,
+            basis_rate: (perp - spot) / spot,
+            funding_rate: leg.funding_rate,
+            next_funding_time: leg.next_funding_time,
+            annualized_funding: leg.funding_rate.map(|rate| rate * periods),
+        })
+    }
+}
+
+/// 一边没有报价时没有中间价
+fn mid(bid: f64, ask: f64) -> Option<f64> {
+    (bid > 0.0 && ask > 0.0).then_some((bid + ask) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis() {
+        let (perp, spot) = split_streams(vec![
+            "btcusdt@bbo".into(),
+            "btcusdt@basis".into(),
+            "btcusdt@bookTicker".into(),
+        ]);
+        assert_eq!(
+            perp,
+            vec!["btcusdt@bbo", "btcusdt@bookTicker", "btcusdt@markPrice@1s"]
+        );
+        assert_eq!(spot, vec!["btcusdt@bookTicker"]);
+        assert!(!is_basis_stream("@basis"));
+
+        let mut basis = Basis::new(&BasisConfig::default());
+        // 没有订阅的交易对不计算
+        assert!(basis.on_spot("ETHUSDT", 1.0, 2.0, 0).is_none());
+        basis.track("btcusdt@basis");
+        assert!(basis.uses("btcusdt@markPrice@1s"));
+        assert!(!basis.uses("btcusdt@kline_1m"));
+        assert!(basis.on_spot("BTCUSDT", 99.0, 101.0, 0).is_none());
+        basis.on_mark_price("BTCUSDT", 0.0001, 1562306400000);
+        let b = basis.on_perp("BTCUSDT", 100.5, 101.5, 10).unwrap();
+        assert_eq!(b.stream, "btcusdt@basis");
+        assert_eq!((b.spot, b.perp, b.basis), (100.0, 101.0, 1.0));
+        assert_eq!(b.basis_rate, 0.01);
+        assert!((b.annualized_funding.unwrap() - 0.1095).abs() < 1e-9);
+
+        // 推送间隔内只更新报价
+        assert!(basis.on_spot("BTCUSDT", 100.0, 101.0, 500).is_none());
+        let b = basis.on_perp("BTCUSDT", 100.5, 101.5, 1010).unwrap();
+        assert_eq!(b.basis, 0.5);
+        assert_eq!(basis.basis("btcusdt", 1020).unwrap().time, 1020);
+
+        basis.untrack("btcusdt@basis");
+        assert!(basis.basis("btcusdt", 1020).is_none());
+        assert!(!basis.uses("btcusdt@bookTicker"));
+    }
+}
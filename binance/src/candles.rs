@@ -0,0 +1,275 @@
+//! 看板图表用的 K 线
+//!
+//! 看板画图只需要几百个点，不值得请求交易所的 REST 接口或传输完整的行情文件。开启后网关为每条订阅的
+//! K 线流缓存最近 `bars` 根 K 线，包括未收线的最后一根与重连后补齐的 K 线。`get_candles` 按时间范围取出，
+//! 以列的形式返回；K 线数超过 `max_points` 时把相邻的 K 线合并为周期为整数倍的一根，合并后的周期按
+//! K 线周期的整数倍对齐：开盘价取第一根，收盘价取最后一根，最高最低价取极值，成交量求和。
+
+use crate::bar;
+use cryptoflow::chat::{SCandles, SGeneralKline};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+/// ```json
+/// "candles": {
+///     "enabled": false,
+///     "bars": 1440,
+///     "max_points": 500
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CandleConfig {
+    pub enabled: bool,
+    /// 每条 K 线流缓存的 K 线数
+    pub bars: usize,
+    /// 一次查询最多返回的点数，请求中的 `max_points` 不能超过它
+    pub max_points: usize,
+}
+
+impl Default for CandleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bars: 1440,
+            max_points: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candle {
+    start: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+}
+
+impl From<&SGeneralKline> for Candle {
+    fn from(kline: &SGeneralKline) -> Self {
+        Self {
+            start: kline.start_time,
+            open: kline.open,
+            high: kline.high,
+            low: kline.low,
+            close: kline.close,
+            volume: kline.volume,
+        }
+    }
+}
+
+/// 把 K 线按 `width` 毫秒对齐合并
+fn merge(candles: &[Candle], width: i64, offset: i64) -> Vec<Candle> {
+    let mut points: Vec<Candle> = Vec::new();
+    for candle in candles {
+        let start = (candle.start - offset).div_euclid(width) * width + offset;
+        match points.last_mut() {
+            Some(point) if point.start == start => {
+                point.high = point.high.max(candle.high);
+                point.low = point.low.min(candle.low);
+                point.close = candle.close;
+                point.volume += candle.volume;
+            }
+            _ => points.push(Candle { start, ..*candle }),
+        }
+    }
+    points
+}
+
+/// 合并到不超过 `max_points` 个点，返回每个点合并的 K 线数
+fn downsample(
+    candles: &[Candle],
+    length: i64,
+    offset: i64,
+    max_points: usize,
+) -> (usize, Vec<Candle>) {
+    let max_points = max_points.max(1);
+    let mut bars = candles.len().div_ceil(max_points).max(1);
+    loop {
+        let points = match bars {
+            1 => candles.to_vec(),
+            _ => merge(candles, length * bars as i64, offset),
+        };
+        // 对齐后首尾的点可能不满，点数多出时再加大周期
+        if points.len() <= max_points {
+            return (bars, points);
+        }
+        bars += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Candles {
+    config: CandleConfig,
+    /// K 线流 -> 按起始时间排列的 K 线
+    series: HashMap<String, VecDeque<Candle>>,
+}
+
+impl Candles {
+    pub fn new(config: &CandleConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 缓存 K 线流上的 K 线，同一根 K 线的更新替换之前的值，早于最后一根的忽略
+    pub fn on_kline(&mut self, stream: &str, kline: &SGeneralKline) {
+        if !self.config.enabled {
+            return;
+        }
+        let series = self.series.entry(stream.to_string()).or_default();
+        match series.back_mut() {
+            Some(last) if last.start == kline.start_time => *last = kline.into(),
+            Some(last) if last.start > kline.start_time => return,
+            _ => series.push_back(kline.into()),
+        }
+        while series.len() > self.config.bars {
+            series.pop_front();
+        }
+    }
+
+    pub fn untrack(&mut self, stream: &str) {
+        self.series.remove(stream);
+    }
+
+    /// `[start, end]` 内起始的 K 线，周期不支持时为 None
+    pub fn query(
+        &self,
+        stream: &str,
+        symbol: &str,
+        interval: &str,
+        range: (Option<i64>, Option<i64>),
+        max_points: Option<usize>,
+    ) -> Option<SCandles> {
+        let (length, offset) = bar::interval_ms(interval)?;
+        let (start, end) = (range.0.unwrap_or(i64::MIN), range.1.unwrap_or(i64::MAX));
+        let candles: Vec<Candle> = self
+            .series
+            .get(stream)
+            .into_iter()
+            .flatten()
+            .filter(|c| c.start >= start && c.start <= end)
+            .copied()
+            .collect();
+        let max_points = max_points
+            .unwrap_or(self.config.max_points)
+            .min(self.config.max_points);
+        let (bars_per_point, points) = downsample(&candles, length, offset, max_points);
+
+        let mut candles = SCandles {
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            bars_per_point,
+            ..Default::default()
+        };
+        for point in points {
+            candles.time.push(point.start);
+            candles.open.push(point.open);
+            candles.high.push(point.high);
+            candles.low.push(point.low);
+            candles.close.push(point.close);
+            candles.volume.push(point.volume);
+        }
+        Some(candles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: &str = "btcusdt@kline_1m";
+
+    fn kline(minute: i64, close: f64) -> SGeneralKline {
+        let start = minute * 60_000;
+        SGeneralKline {
+            time: start + 59_999,
+            start_time: start,
+            symbol: "BTCUSDT".into(),
+            stream: STREAM.into(),
+            interval: "1m".into(),
+            open: close - 1.0,
+            high: close + 1.0,
+            low: close - 2.0,
+            close,
+            volume: 1.0,
+            amount: close,
+            first_trade_id: 0,
+            last_trade_id: 0,
+            trade_count: 1,
+            is_closed: true,
+            buy_volume: 0.5,
+            buy_amount: 0.5 * close,
+            backfill: false,
+        }
+    }
+
+    #[test]
+    fn test_candles() {
+        let mut candles = Candles::new(&CandleConfig {
+            enabled: true,
+            bars: 8,
+            max_points: 4,
+        });
+        for minute in 0..10 {
+            candles.on_kline(STREAM, &kline(minute, 100.0 + minute as f64));
+        }
+        // 未收线的 K 线更新替换最后一根，更早的 K 线忽略
+        candles.on_kline(STREAM, &kline(9, 120.0));
+        candles.on_kline(STREAM, &kline(1, 0.0));
+
+        // 只保留最近 8 根 K 线：2..=9 分钟，合并为 2 分钟一根
+        let all = candles
+            .query(STREAM, "btcusdt", "1m", (None, None), None)
+            .unwrap();
+        assert_eq!(all.bars_per_point, 2);
+        assert_eq!(all.time, vec![120_000, 240_000, 360_000, 480_000]);
+        assert_eq!(all.open, vec![101.0, 103.0, 105.0, 107.0]);
+        assert_eq!(all.close, vec![103.0, 105.0, 107.0, 120.0]);
+        assert_eq!(all.high, vec![104.0, 106.0, 108.0, 121.0]);
+        assert_eq!(all.low, vec![100.0, 102.0, 104.0, 106.0]);
+        assert_eq!(all.volume, vec![2.0; 4]);
+
+        // 对齐后首尾不满时加大周期，点数不超过上限
+        let range = candles
+            .query(
+                STREAM,
+                "btcusdt",
+                "1m",
+                (Some(300_000), Some(480_000)),
+                Some(2),
+            )
+            .unwrap();
+        assert_eq!(range.bars_per_point, 3);
+        assert_eq!(range.time, vec![180_000, 360_000]);
+        assert_eq!(range.open, vec![104.0, 105.0]);
+        assert_eq!(range.close, vec![105.0, 108.0]);
+
+        // 点数不多时不合并，请求的点数不能超过配置
+        let few = candles
+            .query(STREAM, "btcusdt", "1m", (Some(420_000), None), Some(100))
+            .unwrap();
+        assert_eq!(few.bars_per_point, 1);
+        assert_eq!(few.time, vec![420_000, 480_000, 540_000]);
+
+        assert!(candles
+            .query(STREAM, "btcusdt", "1M", (None, None), None)
+            .is_none());
+        candles.untrack(STREAM);
+        let empty = candles
+            .query(STREAM, "btcusdt", "1m", (None, None), None)
+            .unwrap();
+        assert!(empty.time.is_empty());
+
+        let mut disabled = Candles::default();
+        disabled.on_kline(STREAM, &kline(0, 1.0));
+        assert!(disabled.series.is_empty());
+    }
+}
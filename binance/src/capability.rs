@@ -0,0 +1,192 @@
+//! 凭证的接口能力
+//!
+//! 每个网关的凭证声明允许的操作：`trading` 可以查询与交易，`read_only` 只能查询。提币、划转等
+//! 资金转移接口不属于任何能力，`Rest` 在签名之前就拒绝，请求不会发出；即使 API Key 开启了提币
+//! 权限，网关也不会转出资产。
+
+use crate::apikey::Permission;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// 路径中出现这些片段的接口会转移资产，任何能力都不允许
+///
+/// 提币、万能划转与各账户之间的划转、子账户、小额资产兑换、统一账户的资金归集、礼品卡
+pub const BLOCKED: &[&str] = &[
+    "withdraw",
+    "transfer",
+    "sub-account",
+    "dust",
+    "collection",
+    "giftcard",
+];
+
+/// ```json
+/// "capability": "read_only"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// 查询与交易，包括下单撤单、调整杠杆、借币还币
+    #[default]
+    Trading,
+    /// 只查询，用于监控与对账
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Trade,
+    AssetMovement,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "read"),
+            Self::Trade => write!(f, "trade"),
+            Self::AssetMovement => write!(f, "asset movement"),
+        }
+    }
+}
+
+/// 按方法与路径判断接口的操作类型
+///
+/// GET 与 listenKey 的创建、延长、关闭是查询，其余写操作是交易。路径先转为小写并去掉查询串，
+/// 大小写与拼接方式不能绕过资金转移的检查。
+pub fn classify(method: &Method, path: &str) -> Operation {
+    let path = path.split('?').next().unwrap_or_default().to_lowercase();
+    if BLOCKED.iter().any(|fragment| path.contains(fragment)) {
+        return Operation::AssetMovement;
+    }
+    if method == Method::GET || path.ends_with("/listenkey") || path.ends_with("/userdatastream") {
+        return Operation::Read;
+    }
+    Operation::Trade
+}
+
+impl Capability {
+    pub fn allows(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Read => true,
+            Operation::Trade => *self == Self::Trading,
+            Operation::AssetMovement => false,
+        }
+    }
+
+    /// API Key 需要的权限，只读凭证只需要查询权限
+    pub fn permissions(&self, required: Vec<Permission>) -> Vec<Permission> {
+        match self {
+            Self::Trading => required,
+            Self::ReadOnly => vec![Permission::Reading],
+        }
+    }
+
+    /// 不允许的请求返回错误，调用方不应再签名或发出
+    pub fn check(&self, method: &Method, path: &str) -> anyhow::Result<()> {
+        match classify(method, path) {
+            Operation::AssetMovement => Err(anyhow::anyhow!(
+                "Refused {} {}: the gateway never withdraws or transfers assets",
+                method,
+                path
+            )),
+            operation if !self.allows(operation) => Err(anyhow::anyhow!(
+                "Refused {} {}: {} is not allowed for a {:?} credential",
+                method,
+                path,
+                operation,
+                self
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::Rest;
+    use openssl::pkey::PKey;
+
+    #[tokio::test]
+    async fn test_capability() {
+        assert_eq!(classify(&Method::GET, "/api/v3/account"), Operation::Read);
+        assert_eq!(
+            classify(&Method::POST, "/papi/v1/listenKey"),
+            Operation::Read
+        );
+        assert_eq!(classify(&Method::POST, "/fapi/v1/order"), Operation::Trade);
+        assert_eq!(
+            classify(&Method::POST, "/sapi/v1/margin/borrow-repay"),
+            Operation::Trade
+        );
+        for path in [
+            "/sapi/v1/capital/withdraw/apply",
+            "/sapi/v1/asset/transfer",
+            "/sapi/v1/futures/transfer",
+            "/sapi/v1/sub-account/universalTransfer",
+            "/sapi/v1/asset/dust",
+            "/papi/v1/auto-collection",
+            "/papi/v1/bnb-transfer",
+            "/SAPI/V1/Capital/Withdraw/Apply?coin=BTC",
+        ] {
+            assert_eq!(classify(&Method::POST, path), Operation::AssetMovement);
+            // 查询提币记录也一并拒绝
+            assert_eq!(classify(&Method::GET, path), Operation::AssetMovement);
+        }
+
+        let trading = Capability::default();
+        assert!(trading.check(&Method::DELETE, "/api/v3/order").is_ok());
+        let read_only: Capability = serde_json::from_str(r#""read_only""#).unwrap();
+        assert!(read_only
+            .check(&Method::GET, "/fapi/v2/positionRisk")
+            .is_ok());
+        assert!(read_only.check(&Method::PUT, "/papi/v1/listenKey").is_ok());
+        let err = read_only
+            .check(&Method::POST, "/fapi/v1/order")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Refused POST /fapi/v1/order: trade is not allowed for a ReadOnly credential"
+        );
+
+        // REST 客户端在发出请求前拒绝，端口不可达也不会等到连接失败
+        let key = PKey::generate_ed25519().unwrap();
+        let rest = Rest::from_pem(
+            "http://127.0.0.1:9",
+            "apikey",
+            &key.private_key_to_pem_pkcs8().unwrap(),
+            3000,
+        )
+        .unwrap();
+        let params = [
+            ("coin".to_string(), "BTC".to_string()),
+            ("address".to_string(), "x".to_string()),
+            ("amount".to_string(), "1".to_string()),
+        ];
+        let err = rest
+            .post("/sapi/v1/capital/withdraw/apply", &params, true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("never withdraws or transfers"));
+        let err = rest
+            .with_base_uri("http://127.0.0.1:9/")
+            .post("/sapi/v1/asset/transfer", &params, true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("never withdraws or transfers"));
+
+        let read_only = rest.with_capability(Capability::ReadOnly);
+        assert!(read_only
+            .delete("/api/v3/order", &[], true)
+            .await
+            .unwrap_err()
+            .to_string()
+            .starts_with("Refused DELETE /api/v3/order"));
+        assert_eq!(
+            read_only.with_base_uri("http://127.0.0.1:9").capability(),
+            Capability::ReadOnly
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! 交易所请求的关联
+//!
+//! 发往交易所的请求 id 由连接代数与代内序号组成：高 32 位为代数，低 32 位为序号。
+//! 每次重连进入新的一代，旧连接上未应答的请求随之作废，迟到的应答也不会被新连接上的同号请求认领；
+//! 序号用完时同样进入新的一代。初始代数取自启动时间，重启前后的 id 不会重复。
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 代数只用 31 位，线上 id 保持为正数
+const GENERATION_MASK: u32 = 0x7fff_ffff;
+
+/// 请求的关联键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RequestKey {
+    pub generation: u32,
+    pub seq: u32,
+}
+
+impl RequestKey {
+    /// 发往交易所的 id
+    pub fn wire(&self) -> i64 {
+        ((self.generation as i64) << 32) | self.seq as i64
+    }
+
+    /// 解析交易所应答中的 id
+    pub fn from_wire(id: i64) -> Self {
+        Self {
+            generation: ((id >> 32) as u32) & GENERATION_MASK,
+            seq: id as u32,
+        }
+    }
+}
+
+/// 请求 id 分配
+#[derive(Debug, Clone)]
+pub struct RequestIds {
+    generation: u32,
+    /// 当前连接开始时的代数，更早的请求属于已断开的连接
+    connection: u32,
+    seq: u32,
+}
+
+impl Default for RequestIds {
+    fn default() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self::new(secs as u32)
+    }
+}
+
+impl RequestIds {
+    pub fn new(generation: u32) -> Self {
+        let generation = (generation & GENERATION_MASK).max(1);
+        Self {
+            generation,
+            connection: generation,
+            seq: 0,
+        }
+    }
+
+    /// 分配下一个请求，序号从 1 开始
+    pub fn allocate(&mut self) -> RequestKey {
+        if self.seq == u32::MAX {
+            self.bump();
+        }
+        self.seq += 1;
+        RequestKey {
+            generation: self.generation,
+            seq: self.seq,
+        }
+    }
+
+    /// 新连接进入新的一代，返回新的代数
+    pub fn reconnect(&mut self) -> u32 {
+        self.bump();
+        self.connection = self.generation;
+        self.generation
+    }
+
+    /// 请求是否发自当前连接
+    pub fn is_current(&self, key: &RequestKey) -> bool {
+        // 代数回绕后按差值比较
+        self.generation.wrapping_sub(key.generation) & GENERATION_MASK
+            <= self.generation.wrapping_sub(self.connection) & GENERATION_MASK
+    }
+
+    fn bump(&mut self) {
+        self.generation = (self.generation + 1) & GENERATION_MASK;
+        if self.generation == 0 {
+            self.generation = 1;
+        }
+        self.seq = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_ids() {
+        let mut ids = RequestIds::new(7);
+        let first = ids.allocate();
+        assert_eq!(
+            first,
+            RequestKey {
+                generation: 7,
+                seq: 1
+            }
+        );
+        assert_eq!(first.wire(), (7 << 32) | 1);
+        assert_eq!(RequestKey::from_wire(first.wire()), first);
+        assert!(ids.is_current(&first));
+
+        // 重连后旧连接的请求不再有效，同一序号的 id 也不同
+        assert_eq!(ids.reconnect(), 8);
+        let second = ids.allocate();
+        assert_eq!(second.seq, 1);
+        assert_ne!(second.wire(), first.wire());
+        assert!(!ids.is_current(&first));
+        assert!(ids.is_current(&second));
+
+        // 序号用完进入新的一代，仍属于当前连接
+        ids.seq = u32::MAX;
+        let third = ids.allocate();
+        assert_eq!(
+            third,
+            RequestKey {
+                generation: 9,
+                seq: 1
+            }
+        );
+        assert!(ids.is_current(&second) && ids.is_current(&third));
+
+        // 代数回绕
+        let mut ids = RequestIds::new(GENERATION_MASK);
+        let old = ids.allocate();
+        ids.reconnect();
+        let new = ids.allocate();
+        assert_eq!(new.generation, 1);
+        assert!(new.wire() > 0);
+        assert!(!ids.is_current(&old) && ids.is_current(&new));
+
+        // 重启后从新的代数开始
+        let restarted = RequestIds::default().allocate();
+        assert_ne!(restarted.generation, 7);
+        // 不带 id 的订阅应答
+        assert_eq!(RequestKey::from_wire(0).seq, 0);
+    }
+}
@@ -0,0 +1,151 @@
+//! 订单回报去重
+//!
+//! 经 WS-API 下单时，`order.place` 的响应与账户推送的 `ORDER_TRADE_UPDATE` 都带有订单的 NEW 状态；
+//! 账户推送重连后也可能重复推送同一条回报。网关把两个来源合并为一个回报序列：
+//!
+//! - 每条回报按 (symbol, 交易所订单 id, 更新时间, 状态, 成交 id) 识别，与持久化订单回报的唯一键相同，
+//!   已经转发过的回报不再转发
+//! - WS-API 的响应只在它先于账户推送到达时作为 NEW 回报转发，之后账户推送的同一条 NEW 被丢弃；
+//!   账户推送已经到达过该订单的任何回报时，响应不再转发，避免订单状态倒退
+//!
+//! 只记住最近 [`CAPACITY`] 条回报。
+
+use crate::OrderTrait;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// 记住的回报条数
+pub const CAPACITY: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EventKey {
+    pub symbol: String,
+    pub order_id: i64,
+    pub update_time: i64,
+    pub state: String,
+    /// 不是成交回报时为 -1
+    pub trade_id: i64,
+}
+
+impl EventKey {
+    pub fn of<T: OrderTrait>(order: &T) -> Self {
+        Self {
+            symbol: order.symbol().to_lowercase(),
+            order_id: order.exchange_order_id(),
+            update_time: order.update_time(),
+            state: format!("{:?}", order.state()),
+            trade_id: order.fill().map_or(-1, |fill| fill.trade_id),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OrderEvents {
+    keys: HashSet<EventKey>,
+    /// (symbol, 交易所订单 id) -> 记住的回报条数
+    orders: HashMap<(String, i64), usize>,
+    queue: VecDeque<EventKey>,
+}
+
+impl OrderEvents {
+    /// 第一次见到这条回报时返回 true 并记住它
+    pub fn first(&mut self, key: EventKey) -> bool {
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+        *self
+            .orders
+            .entry((key.symbol.clone(), key.order_id))
+            .or_default() += 1;
+        self.queue.push_back(key);
+        if self.queue.len() > CAPACITY {
+            if let Some(oldest) = self.queue.pop_front() {
+                self.forget(oldest);
+            }
+        }
+        true
+    }
+
+    /// 是否已经转发过该订单的回报
+    pub fn seen(&self, symbol: &str, order_id: i64) -> bool {
+        self.orders.contains_key(&(symbol.to_lowercase(), order_id))
+    }
+
+    fn forget(&mut self, key: EventKey) {
+        self.keys.remove(&key);
+        let order = (key.symbol, key.order_id);
+        if let Some(count) = self.orders.get_mut(&order) {
+            *count -= 1;
+            if *count == 0 {
+                self.orders.remove(&order);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::State;
+
+    fn key(order_id: i64, update_time: i64, state: State, trade_id: i64) -> EventKey {
+        EventKey {
+            symbol: "btcusdt".into(),
+            order_id,
+            update_time,
+            state: format!("{:?}", state),
+            trade_id,
+        }
+    }
+
+    #[test]
+    fn test_order_events() {
+        let mut events = OrderEvents::default();
+        assert!(!events.seen("BTCUSDT", 1));
+        assert!(events.first(key(1, 10, State::NEW, -1)));
+        assert!(events.seen("BTCUSDT", 1));
+        // 账户推送的同一条 NEW
+        assert!(!events.first(key(1, 10, State::NEW, -1)));
+        assert!(events.first(key(1, 11, State::PARTIALLY_FILLED, 7)));
+        assert!(events.first(key(1, 11, State::PARTIALLY_FILLED, 8)));
+        assert!(!events.first(key(1, 11, State::PARTIALLY_FILLED, 8)));
+
+        // 超过容量后最早的回报被忘记
+        for i in 0..CAPACITY as i64 {
+            events.first(key(2, i, State::NEW, -1));
+        }
+        assert!(!events.seen("btcusdt", 1));
+        assert!(events.first(key(1, 10, State::NEW, -1)));
+    }
+
+    #[test]
+    fn test_wsapi_ack() {
+        use crate::model::order::usdt::OrderUpdate;
+
+        let result = serde_json::json!({
+            "orderId": 325078477, "symbol": "BTCUSDT", "status": "NEW", "clientOrderId": "4294967303",
+            "price": "43187.00", "avgPrice": "0.00", "origQty": "0.100", "executedQty": "0.000",
+            "cumQty": "0.000", "cumQuote": "0.00000", "timeInForce": "GTC", "type": "LIMIT",
+            "reduceOnly": false, "closePosition": false, "side": "BUY", "positionSide": "BOTH",
+            "stopPrice": "0.00", "workingType": "CONTRACT_PRICE", "priceProtect": false,
+            "origType": "LIMIT", "priceMatch": "NONE", "selfTradePreventionMode": "NONE",
+            "goodTillDate": 0, "updateTime": 1702555534435i64
+        });
+        let ack = OrderUpdate::from_wsapi(&result).unwrap();
+        let pushed: OrderUpdate = serde_json::from_str(
+            r#"{"e":"ORDER_TRADE_UPDATE","E":1702555534440,"T":1702555534435,"o":{"s":"BTCUSDT",
+            "c":"4294967303","S":"BUY","o":"LIMIT","f":"GTC","q":"0.100","p":"43187.00","ap":"0","sp":"0",
+            "x":"NEW","X":"NEW","i":325078477,"l":"0","z":"0","L":"0","n":"0","T":1702555534435,"t":0,
+            "b":"0","a":"0","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"LIMIT","ps":"BOTH","rp":"0"}}"#,
+        )
+        .unwrap();
+        assert_eq!(EventKey::of(&ack), EventKey::of(&pushed));
+
+        let mut events = OrderEvents::default();
+        assert!(events.first(EventKey::of(&ack)));
+        assert!(!events.first(EventKey::of(&pushed)));
+
+        // 已成交的响应以账户推送为准
+        let filled = serde_json::json!({"status": "FILLED", "updateTime": 1});
+        assert!(OrderUpdate::from_wsapi(&filled).is_none());
+    }
+}
@@ -0,0 +1,246 @@
+//! 交割合约的交割提醒与结算
+//!
+//! U 本位合约中的季度合约（如 `btcusdt_261225`）在 `deliveryDate` 交割，交易所按交割价结算并平掉持仓，
+//! 没有成交回报，会话的持仓不会随之变化。开启后网关在交割前 `notify_before_secs` 的每个时间点向持有该
+//! 合约的会话推送一次 [`SDelivery`] 提醒；交割后账户推送的该合约持仓归零，或过了 `settle_timeout_secs`
+//! 仍没有推送，网关把会话持仓置零、写入持仓库并推送结算。交割后才登录的会话在登录时结算。
+//!
+//! 永续合约的 `deliveryDate` 在很远的将来，不处理。
+//!
+//! [`SDelivery`]: cryptoflow::chat::SDelivery
+
+use crate::model::symbol::BinanceSymbol;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+
+/// 永续合约的 deliveryDate（2100-12-25），不早于它的不是交割合约
+pub const PERPETUAL_DELIVERY: i64 = 4133404800000;
+
+/// ```json
+/// "delivery": {
+///     "enabled": false,
+///     "notify_before_secs": [86400, 3600, 600],
+///     "settle_timeout_secs": 300
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DeliveryConfig {
+    pub enabled: bool,
+    /// 交割前的这些时间点各提醒一次
+    pub notify_before_secs: Vec<u64>,
+    /// 交割后等待账户持仓归零的时间，超时后直接结算会话持仓
+    pub settle_timeout_secs: u64,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            notify_before_secs: vec![86400, 3600, 600],
+            settle_timeout_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Contract {
+    delivery_time: i64,
+    /// 已经提醒过的时间点
+    notified: HashSet<u64>,
+    /// 交割后账户推送的持仓已归零
+    cleared: bool,
+}
+
+/// 一次交割提醒，`remaining_ms` 为距交割的时间
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notice {
+    pub symbol: String,
+    pub delivery_time: i64,
+    pub remaining_ms: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct Deliveries {
+    config: DeliveryConfig,
+    /// 小写的交易对 -> 尚未结算的交割合约
+    contracts: HashMap<String, Contract>,
+    /// 本次运行中已结算的合约与交割时间
+    settled: HashMap<String, i64>,
+}
+
+impl Deliveries {
+    pub fn new(config: &DeliveryConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 从交易规则中登记交割合约，已登记的保留提醒记录
+    pub fn on_products(&mut self, products: &HashMap<String, BinanceSymbol>) {
+        if !self.enabled() {
+            return;
+        }
+        for (symbol, product) in products {
+            let Some(delivery_time) = product.deliveryDate.map(|t| t as i64) else {
+                continue;
+            };
+            if delivery_time >= PERPETUAL_DELIVERY || self.settled.contains_key(symbol) {
+                continue;
+            }
+            self.contracts
+                .entry(symbol.clone())
+                .or_insert_with(|| {
+                    info!("Track delivery of {} at {}", symbol, delivery_time);
+                    Contract {
+                        delivery_time,
+                        notified: HashSet::new(),
+                        cleared: false,
+                    }
+                })
+                .delivery_time = delivery_time;
+        }
+    }
+
+    /// 到了提醒时间点的合约，同时跨过的几个时间点只提醒一次，`now` 为毫秒时间
+    pub fn notices(&mut self, now: i64) -> Vec<Notice> {
+        let mut notices = Vec::new();
+        for (symbol, contract) in self.contracts.iter_mut() {
+            let remaining_ms = contract.delivery_time - now;
+            if remaining_ms <= 0 {
+                continue;
+            }
+            let due: Vec<u64> = self
+                .config
+                .notify_before_secs
+                .iter()
+                .copied()
+                .filter(|secs| (*secs as i64) * 1000 >= remaining_ms)
+                .filter(|secs| !contract.notified.contains(secs))
+                .collect();
+            if due.is_empty() {
+                continue;
+            }
+            contract.notified.extend(due);
+            notices.push(Notice {
+                symbol: symbol.clone(),
+                delivery_time: contract.delivery_time,
+                remaining_ms,
+            });
+        }
+        notices.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        notices
+    }
+
+    /// 账户推送的合约持仓，交割后归零说明交易所已经结算
+    pub fn on_account_position(&mut self, symbol: &str, amount: f64, now: i64) {
+        if let Some(contract) = self.contracts.get_mut(symbol) {
+            if now >= contract.delivery_time && amount == 0.0 {
+                contract.cleared = true;
+            }
+        }
+    }
+
+    /// 可以结算会话持仓的合约与交割时间，每个合约只返回一次
+    pub fn settlements(&mut self, now: i64) -> Vec<(String, i64)> {
+        let timeout = self.config.settle_timeout_secs as i64 * 1000;
+        let due: Vec<String> = self
+            .contracts
+            .iter()
+            .filter(|(_, c)| {
+                now >= c.delivery_time && (c.cleared || now >= c.delivery_time + timeout)
+            })
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        let mut settlements = Vec::new();
+        for symbol in due {
+            if let Some(contract) = self.contracts.remove(&symbol) {
+                self.settled.insert(symbol.clone(), contract.delivery_time);
+                settlements.push((symbol, contract.delivery_time));
+            }
+        }
+        settlements.sort();
+        settlements
+    }
+
+    /// 本次运行中已结算的合约，登录的会话还持有时补做结算
+    pub fn settled(&self) -> impl Iterator<Item = (&String, &i64)> {
+        self.settled.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(symbol: &str, delivery: u64) -> BinanceSymbol {
+        let s = format!(
+            r#"{{"symbol":"{}","status":"TRADING","baseAsset":"BTC","baseAssetPrecision":8,"quoteAsset":"USDT","quotePrecision":8,"orderTypes":[],"filters":[],"deliveryDate":{}}}"#,
+            symbol, delivery
+        );
+        serde_json::from_str(&s).unwrap()
+    }
+
+    #[test]
+    fn test_deliveries() {
+        let config = DeliveryConfig {
+            enabled: true,
+            notify_before_secs: vec![3600, 600],
+            settle_timeout_secs: 60,
+        };
+        let mut deliveries = Deliveries::new(&config);
+        let delivery = 1_800_000_000_000u64;
+        let mut products = HashMap::new();
+        for p in [
+            product("BTCUSDT_261225", delivery),
+            product("BTCUSDT", PERPETUAL_DELIVERY as u64),
+        ] {
+            products.insert(p.symbol.clone(), p);
+        }
+        deliveries.on_products(&products);
+
+        let t = delivery as i64;
+        assert!(deliveries.notices(t - 3_601_000).is_empty());
+        // 启动时已跨过两个时间点，只提醒一次
+        let notices = deliveries.notices(t - 500_000);
+        assert_eq!(
+            notices,
+            vec![Notice {
+                symbol: "btcusdt_261225".into(),
+                delivery_time: t,
+                remaining_ms: 500_000
+            }]
+        );
+        assert!(deliveries.notices(t - 400_000).is_empty());
+
+        // 交割前的持仓推送不算结算
+        deliveries.on_account_position("btcusdt_261225", 0.0, t - 1);
+        assert!(deliveries.settlements(t).is_empty());
+        deliveries.on_account_position("btcusdt_261225", 0.0, t + 1);
+        assert_eq!(
+            deliveries.settlements(t + 1),
+            vec![("btcusdt_261225".to_string(), t)]
+        );
+        assert!(deliveries.settlements(t + 120_000).is_empty());
+        assert_eq!(deliveries.settled().count(), 1);
+
+        // 结算后重新拉取的交易规则不再登记
+        deliveries.on_products(&products);
+        assert!(deliveries.settlements(t + 120_000).is_empty());
+
+        let mut timeout = Deliveries::new(&config);
+        timeout.on_products(&products);
+        assert!(timeout.settlements(t + 59_999).is_empty());
+        assert_eq!(timeout.settlements(t + 60_000).len(), 1);
+
+        let mut disabled = Deliveries::default();
+        disabled.on_products(&products);
+        assert!(disabled.notices(t - 1000).is_empty());
+    }
+}
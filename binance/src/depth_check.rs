@@ -0,0 +1,366 @@
+//! 吃单前按盘口检查流动性
+//!
+//! 开启后，名义价值不低于 `min_notional` 的市价单与会立即成交的限价单（不含只做 maker 的订单）在下单前
+//! 按维护的 20 档深度估算：从对手方最优价起，价格在 `max_slippage_bps` 以内的档位数量不够成交整笔订单时，
+//! 按 `action` 处理：`warn` 记录日志后照常下单，`reject` 拒绝并发出风控告警，`clamp` 把价格限制在预算边缘：
+//! 订单改成预算边缘价格的限价单，数量不变。市价单同时改为 IOC，预算以内成交不了的剩余数量由交易所撤销，
+//! 回报为 EXPIRED；限价单保留原来的 time in force，GTC 的剩余数量以预算边缘价格挂在盘口上，直到成交或撤单。
+//! 网关不会为剩余数量再下单，是否继续由策略根据回报决定。
+//! 限价单的价格本身在预算以内时不会扫穿盘口，不检查。
+//!
+//! 网关在一个交易对第一次收到可能吃单的订单时订阅它的 20 档深度，订阅不随策略端退出而取消。只统计可见的
+//! 20 档，20 档都在预算以内时实际的流动性可能更多。还没有深度或深度超过 `max_age_ms` 没有更新时不检查。`sessions` 按会话覆盖全局的设置。
+
+use crate::model::order::BinanceOrder;
+use crate::model::quote::BinanceQuote;
+use crate::post_only;
+use cryptoflow::chat::{OrderType, Side, TimeInForce};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DepthAction {
+    #[default]
+    Warn,
+    Clamp,
+    Reject,
+}
+
+/// ```json
+/// "depth_check": {
+///     "enabled": false,
+///     "min_notional": 10000,
+///     "max_slippage_bps": 20,
+///     "action": "warn",
+///     "max_age_ms": 2000,
+///     "sessions": {
+///         "1": {"max_slippage_bps": 10, "action": "clamp"}
+///     }
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DepthCheckConfig {
+    pub enabled: bool,
+    /// 名义价值低于它的订单不检查，按对手方最优价估算
+    pub min_notional: f64,
+    /// 相对对手方最优价可以接受的滑点，基点
+    pub max_slippage_bps: f64,
+    /// 流动性不够时的处理
+    pub action: DepthAction,
+    /// 深度超过这么久没有更新时不检查
+    pub max_age_ms: i64,
+    /// session_id -> 该会话的设置，没有填的项使用全局的设置
+    pub sessions: HashMap<u16, DepthCheckLimit>,
+}
+
+impl Default for DepthCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_notional: 10000.0,
+            max_slippage_bps: 20.0,
+            action: DepthAction::Warn,
+            max_age_ms: 2000,
+            sessions: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DepthCheckLimit {
+    #[serde(default)]
+    pub min_notional: Option<f64>,
+    #[serde(default)]
+    pub max_slippage_bps: Option<f64>,
+    #[serde(default)]
+    pub action: Option<DepthAction>,
+}
+
+impl DepthCheckConfig {
+    /// 会话的 (名义价值下限, 滑点预算, 处理)
+    fn limits(&self, session_id: u16) -> (f64, f64, DepthAction) {
+        let limit = self.sessions.get(&session_id);
+        (
+            limit
+                .and_then(|l| l.min_notional)
+                .unwrap_or(self.min_notional),
+            limit
+                .and_then(|l| l.max_slippage_bps)
+                .unwrap_or(self.max_slippage_bps),
+            limit.and_then(|l| l.action).unwrap_or(self.action),
+        )
+    }
+}
+
+/// 订单是否可能吃单，需要维护它的深度
+pub fn is_candidate(order: &BinanceOrder) -> bool {
+    matches!(order.order_type, OrderType::MARKET | OrderType::LIMIT)
+        && !post_only::is_post_only(order)
+}
+
+/// 一个交易对最新的 20 档深度
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    pub time: i64,
+    pub bids: Vec<BinanceQuote>,
+    pub asks: Vec<BinanceQuote>,
+}
+
+/// 各交易对的深度，由 market 写入、trade 读取
+#[derive(Debug, Clone, Default)]
+pub struct DepthBooks {
+    books: Arc<Mutex<HashMap<String, Book>>>,
+}
+
+impl DepthBooks {
+    pub fn update(&self, symbol: &str, time: i64, bids: &[BinanceQuote], asks: &[BinanceQuote]) {
+        let book = Book {
+            time,
+            bids: bids.to_vec(),
+            asks: asks.to_vec(),
+        };
+        let mut books = self.books.lock().unwrap_or_else(|p| p.into_inner());
+        books.insert(symbol.to_lowercase(), book);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<Book> {
+        let books = self.books.lock().unwrap_or_else(|p| p.into_inner());
+        books.get(&symbol.to_lowercase()).cloned()
+    }
+}
+
+/// 检查的结果
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    Pass,
+    Warn(String),
+    /// 价格限制在预算边缘的订单，数量不变
+    Clamp(BinanceOrder, String),
+    Reject(String),
+}
+
+/// 按 `book` 检查 `order`，`now_ms` 用于判断深度是否过期
+pub fn check(
+    config: &DepthCheckConfig,
+    order: &BinanceOrder,
+    book: Option<&Book>,
+    tick: f64,
+    now_ms: i64,
+) -> Verdict {
+    if !config.enabled || !is_candidate(order) {
+        return Verdict::Pass;
+    }
+    let Some(book) = book.filter(|b| now_ms - b.time <= config.max_age_ms) else {
+        return Verdict::Pass;
+    };
+    let levels = match order.side {
+        Side::BUY => &book.asks,
+        Side::SELL => &book.bids,
+    };
+    let Some(best) = levels.first().map(|l| l.price).filter(|p| *p > 0.0) else {
+        return Verdict::Pass;
+    };
+    let (min_notional, bps, action) = config.limits(order.session_id);
+    if order.quantity * best < min_notional {
+        return Verdict::Pass;
+    }
+    let budget = match order.side {
+        Side::BUY => best * (1.0 + bps / 10000.0),
+        Side::SELL => best * (1.0 - bps / 10000.0),
+    };
+    let within = |price: f64| match order.side {
+        Side::BUY => price <= budget,
+        Side::SELL => price >= budget,
+    };
+    // 限价单的价格在预算以内，最多成交到预算为止
+    if order.order_type == OrderType::LIMIT && within(order.price) {
+        return Verdict::Pass;
+    }
+    let available: f64 = levels
+        .iter()
+        .take_while(|l| within(l.price))
+        .map(|l| l.quantity)
+        .sum();
+    if available >= order.quantity {
+        return Verdict::Pass;
+    }
+    let msg = format!(
+        "only {} of {} {} available within {} bps of {}",
+        available, order.quantity, order.symbol, bps, best
+    );
+    match action {
+        DepthAction::Warn => Verdict::Warn(msg),
+        DepthAction::Reject => Verdict::Reject(msg),
+        DepthAction::Clamp => {
+            let price = match (order.side, tick > 0.0) {
+                (Side::BUY, true) => (budget / tick + 1e-9).floor() * tick,
+                (Side::SELL, true) => (budget / tick - 1e-9).ceil() * tick,
+                (_, false) => budget,
+            };
+            let tif = match order.order_type {
+                OrderType::MARKET => TimeInForce::IOC,
+                _ => order.tif,
+            };
+            let clamped = BinanceOrder {
+                order_type: OrderType::LIMIT,
+                price,
+                tif,
+                ..order.clone()
+            };
+            Verdict::Clamp(clamped, msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: Side, order_type: OrderType, price: f64, quantity: f64) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price,
+            quantity,
+            side,
+            order_type,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    fn quotes(levels: &[(f64, f64)]) -> Vec<BinanceQuote> {
+        levels
+            .iter()
+            .map(|&(price, quantity)| BinanceQuote { price, quantity })
+            .collect()
+    }
+
+    #[test]
+    fn test_depth_check() {
+        let books = DepthBooks::default();
+        books.update(
+            "BTCUSDT",
+            1000,
+            &quotes(&[(99.9, 50.0), (99.5, 50.0)]),
+            &quotes(&[(100.0, 100.0), (100.1, 100.0), (100.5, 1000.0)]),
+        );
+        let book = books.get("btcusdt");
+        let mut config = DepthCheckConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        // 20 bps 以内有 200
+        let buy = order(Side::BUY, OrderType::MARKET, 0.0, 150.0);
+        assert!(matches!(
+            check(&config, &buy, book.as_ref(), 0.1, 1500),
+            Verdict::Pass
+        ));
+        let buy = order(Side::BUY, OrderType::MARKET, 0.0, 300.0);
+        assert!(matches!(
+            check(&config, &buy, book.as_ref(), 0.1, 1500),
+            Verdict::Warn(_)
+        ));
+        // 深度过期、名义价值太小与只做 maker 的订单不检查
+        assert!(matches!(
+            check(&config, &buy, book.as_ref(), 0.1, 5000),
+            Verdict::Pass
+        ));
+        let small = order(Side::BUY, OrderType::MARKET, 0.0, 50.0);
+        assert!(matches!(
+            check(&config, &small, book.as_ref(), 0.1, 1500),
+            Verdict::Pass
+        ));
+        let mut maker = order(Side::BUY, OrderType::LIMIT, 101.0, 300.0);
+        maker.tif = TimeInForce::GTX;
+        assert!(matches!(
+            check(&config, &maker, book.as_ref(), 0.1, 1500),
+            Verdict::Pass
+        ));
+        // 价格在预算以内的限价单不会扫穿盘口
+        let limit = order(Side::BUY, OrderType::LIMIT, 100.1, 300.0);
+        assert!(matches!(
+            check(&config, &limit, book.as_ref(), 0.1, 1500),
+            Verdict::Pass
+        ));
+
+        // 按会话限价，市价单改成预算边缘的 IOC 限价单
+        config.sessions.insert(
+            1,
+            DepthCheckLimit {
+                action: Some(DepthAction::Clamp),
+                ..Default::default()
+            },
+        );
+        let Verdict::Clamp(clamped, _) = check(&config, &buy, book.as_ref(), 0.1, 1500) else {
+            panic!("expect clamp");
+        };
+        assert_eq!(clamped.order_type, OrderType::LIMIT);
+        assert_eq!(clamped.tif, TimeInForce::IOC);
+        assert!((clamped.price - 100.2).abs() < 1e-9);
+
+        // 卖单只有 99.9 一档在 10 bps 以内
+        config.sessions.insert(
+            1,
+            DepthCheckLimit {
+                max_slippage_bps: Some(10.0),
+                action: Some(DepthAction::Reject),
+                ..Default::default()
+            },
+        );
+        let sell = order(Side::SELL, OrderType::LIMIT, 99.0, 120.0);
+        let Verdict::Reject(msg) = check(&config, &sell, book.as_ref(), 0.1, 1500) else {
+            panic!("expect reject");
+        };
+        assert!(msg.starts_with("only 50 of 120 btcusdt"), "{}", msg);
+        assert!(matches!(
+            check(&config, &sell, None, 0.1, 1500),
+            Verdict::Pass
+        ));
+    }
+
+    #[test]
+    fn test_clamp_remainder() {
+        let book = Book {
+            time: 1000,
+            bids: quotes(&[(99.9, 50.0)]),
+            asks: quotes(&[(100.0, 100.0), (100.1, 100.0), (100.5, 1000.0)]),
+        };
+        let config = DepthCheckConfig {
+            enabled: true,
+            action: DepthAction::Clamp,
+            ..Default::default()
+        };
+
+        // 数量不变：预算以内的 200 按 100.2 以内的价格成交，剩余的 100 由交易所按 IOC 撤销
+        let market = order(Side::BUY, OrderType::MARKET, 0.0, 300.0);
+        let Verdict::Clamp(clamped, msg) = check(&config, &market, Some(&book), 0.1, 1500) else {
+            panic!("expect clamp");
+        };
+        assert_eq!(
+            msg,
+            "only 200 of 300 btcusdt available within 20 bps of 100"
+        );
+        assert_eq!(clamped.quantity, 300.0);
+        assert_eq!(clamped.tif, TimeInForce::IOC);
+        let filled: f64 = book
+            .asks
+            .iter()
+            .filter(|l| l.price <= clamped.price)
+            .map(|l| l.quantity)
+            .sum();
+        assert_eq!(clamped.quantity - filled, 100.0);
+
+        // GTC 限价单的剩余数量以 100.2 挂在盘口上
+        let limit = order(Side::BUY, OrderType::LIMIT, 101.0, 300.0);
+        let Verdict::Clamp(clamped, _) = check(&config, &limit, Some(&book), 0.1, 1500) else {
+            panic!("expect clamp");
+        };
+        assert_eq!(clamped.quantity, 300.0);
+        assert_eq!(clamped.tif, TimeInForce::GTC);
+        assert!((clamped.price - 100.2).abs() < 1e-9);
+    }
+}
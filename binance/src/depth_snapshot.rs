@@ -0,0 +1,344 @@
+//! 代理交易所的深度快照
+//!
+//! 网关转发的深度流只有前 20 档，策略端初始化本地订单簿需要的完整快照由 `get_depth_snapshot` 通过网关
+//! 向交易所的 REST 接口查询，策略端不需要自己的 REST 凭证。同一交易对在 `ttl_ms` 内的快照直接返回缓存，
+//! 档位更多的快照也用于档位更少的查询；代理的请求按接口权重限制每分钟的用量，交易所返回 429、418 时
+//! 按 `Retry-After` 暂停，避免多个策略端同时初始化时耗尽网关所在 IP 的权重。启动时预取的快照（见
+//! [`crate::prefetch`]）同样在 `ttl_ms` 内直接返回。
+
+use crate::model::quote::BinanceQuote;
+use crate::prefetch::Prefetched;
+use cryptoflow::chat::{SDepthSnapshot, SError};
+use cryptoflow::error_code::{INVALID_RANGE, RATE_LIMITED, UNDEF_ERROR};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// 接口允许的最大档位数
+pub const MAX_LIMIT: u32 = 5000;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// ```json
+/// "depth_snapshot": {
+///     "enabled": true,
+///     "url": "https://api.binance.com/api/v3/depth",
+///     "ttl_ms": 1000,
+///     "max_weight": 300,
+///     "timeout_ms": 3000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DepthSnapshotConfig {
+    pub enabled: bool,
+    /// 深度 REST 接口，合约为 `https://fapi.binance.com/fapi/v1/depth`
+    pub url: String,
+    /// 快照的缓存时间
+    pub ttl_ms: u64,
+    /// 每分钟代理的请求最多使用的权重，其余留给下单等接口
+    pub max_weight: u32,
+    /// 交易所超过该时间未响应按失败处理
+    pub timeout_ms: u64,
+}
+
+impl Default for DepthSnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: "https://api.binance.com/api/v3/depth".into(),
+            ttl_ms: 1000,
+            max_weight: 300,
+            timeout_ms: 3000,
+        }
+    }
+}
+
+/// 现货接口按档位计算的权重，合约接口的权重更低，按现货计算偏保守
+fn weight(limit: u32) -> u32 {
+    match limit {
+        0..=100 => 5,
+        101..=500 => 25,
+        501..=1000 => 50,
+        _ => 250,
+    }
+}
+
+#[derive(Deserialize)]
+struct RestDepth {
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: i64,
+    bids: Vec<BinanceQuote>,
+    asks: Vec<BinanceQuote>,
+}
+
+/// 解析深度接口的响应
+pub(crate) fn parse(symbol: &str, body: &str) -> anyhow::Result<SDepthSnapshot<BinanceQuote>> {
+    let depth: RestDepth = serde_json::from_str(body)?;
+    Ok(SDepthSnapshot {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default(),
+        symbol: symbol.to_string(),
+        last_update_id: depth.last_update_id,
+        bids: depth.bids,
+        asks: depth.asks,
+    })
+}
+
+struct Cached {
+    fetched: Instant,
+    limit: u32,
+    snapshot: SDepthSnapshot<BinanceQuote>,
+}
+
+pub struct DepthSnapshots {
+    config: DepthSnapshotConfig,
+    http: reqwest::Client,
+    cache: HashMap<String, Cached>,
+    prefetched: Prefetched,
+    /// 最近一分钟内代理的请求与权重
+    spent: VecDeque<(Instant, u32)>,
+    /// 交易所限流后暂停请求到这个时间
+    paused_until: Option<Instant>,
+}
+
+impl Default for DepthSnapshots {
+    fn default() -> Self {
+        Self::new(&DepthSnapshotConfig::default())
+    }
+}
+
+impl DepthSnapshots {
+    pub fn new(config: &DepthSnapshotConfig) -> Self {
+        Self {
+            config: config.clone(),
+            http: reqwest::Client::new(),
+            cache: HashMap::default(),
+            prefetched: Prefetched::default(),
+            spent: VecDeque::default(),
+            paused_until: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 缓存没有命中时使用启动时预取的快照
+    pub fn with_prefetched(mut self, prefetched: Prefetched) -> Self {
+        self.prefetched = prefetched;
+        self
+    }
+
+    /// 缓存时间内且档位不少于 `limit` 的快照，截取前 `limit` 档
+    fn cached(
+        &self,
+        symbol: &str,
+        limit: u32,
+        now: Instant,
+    ) -> Option<SDepthSnapshot<BinanceQuote>> {
+        let ttl = Duration::from_millis(self.config.ttl_ms);
+        let fresh = |fetched: Instant, cached_limit: u32| {
+            cached_limit >= limit && now.saturating_duration_since(fetched) < ttl
+        };
+        let mut snapshot = match self.cache.get(symbol) {
+            Some(cached) if fresh(cached.fetched, cached.limit) => cached.snapshot.clone(),
+            _ => {
+                let (fetched, cached_limit, snapshot) = self.prefetched.depth(symbol)?;
+                if !fresh(fetched, cached_limit) {
+                    return None;
+                }
+                snapshot
+            }
+        };
+        snapshot.bids.truncate(limit as usize);
+        snapshot.asks.truncate(limit as usize);
+        Some(snapshot)
+    }
+
+    /// 在每分钟的权重内时记下这次请求
+    fn acquire(&mut self, limit: u32, now: Instant) -> Result<(), SError> {
+        let limited = |msg: String| SError {
+            code: RATE_LIMITED,
+            msg,
+        };
+        if let Some(until) = self.paused_until.filter(|until| *until > now) {
+            let secs = until.saturating_duration_since(now).as_secs_f64().ceil();
+            return Err(limited(format!(
+                "depth snapshot is rate limited by exchange, retry after {}s",
+                secs
+            )));
+        }
+        while let Some((time, _)) = self.spent.front() {
+            if now.saturating_duration_since(*time) < MINUTE {
+                break;
+            }
+            self.spent.pop_front();
+        }
+        let spent: u32 = self.spent.iter().map(|(_, weight)| weight).sum();
+        let weight = weight(limit);
+        if spent + weight > self.config.max_weight {
+            return Err(limited(format!(
+                "depth snapshot weight {} of {} per minute is used up",
+                spent, self.config.max_weight
+            )));
+        }
+        self.spent.push_back((now, weight));
+        Ok(())
+    }
+
+    /// 处理交易所的响应，成功时缓存快照
+    fn on_response(
+        &mut self,
+        symbol: &str,
+        limit: u32,
+        status: StatusCode,
+        retry_after: Option<u64>,
+        body: &str,
+        now: Instant,
+    ) -> Result<SDepthSnapshot<BinanceQuote>, SError> {
+        if status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 418 {
+            let pause = Duration::from_secs(retry_after.unwrap_or(60));
+            warn!("Depth snapshot is rate limited, pause {:?}", pause);
+            self.paused_until = Some(now + pause);
+            return Err(SError {
+                code: RATE_LIMITED,
+                msg: format!("depth snapshot is rate limited by exchange: {}", status),
+            });
+        }
+        if !status.is_success() {
+            return Err(SError {
+                code: UNDEF_ERROR,
+                msg: format!("{} {}", status, body),
+            });
+        }
+        let snapshot = parse(symbol, body).map_err(|e| SError {
+            code: UNDEF_ERROR,
+            msg: format!("unexpected depth snapshot: {}", e),
+        })?;
+        self.cache.insert(
+            symbol.to_string(),
+            Cached {
+                fetched: now,
+                limit,
+                snapshot: snapshot.clone(),
+            },
+        );
+        Ok(snapshot)
+    }
+
+    pub async fn get(
+        &mut self,
+        symbol: &str,
+        limit: u32,
+    ) -> Result<SDepthSnapshot<BinanceQuote>, SError> {
+        if limit == 0 || limit > MAX_LIMIT {
+            return Err(SError {
+                code: INVALID_RANGE,
+                msg: format!("limit should be in [1, {}]", MAX_LIMIT),
+            });
+        }
+        let symbol = symbol.to_lowercase();
+        let now = Instant::now();
+        if let Some(snapshot) = self.cached(&symbol, limit, now) {
+            debug!("Depth snapshot of {} from cache", symbol);
+            return Ok(snapshot);
+        }
+        self.acquire(limit, now)?;
+
+        let rsp = self
+            .http
+            .get(&self.config.url)
+            .query(&[
+                ("symbol", symbol.to_uppercase()),
+                ("limit", limit.to_string()),
+            ])
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .send()
+            .await
+            .map_err(|e| SError {
+                code: UNDEF_ERROR,
+                msg: e.to_string(),
+            })?;
+        let status = rsp.status();
+        let retry_after = rsp
+            .headers()
+            .get("retry-after")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        let body = rsp.text().await.unwrap_or_default();
+        self.on_response(&symbol, limit, status, retry_after, &body, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth_snapshots() {
+        let mut snapshots = DepthSnapshots::new(&DepthSnapshotConfig {
+            max_weight: 30,
+            ..Default::default()
+        });
+        let body = r#"{"lastUpdateId":1027024,"E":1,"T":1,"bids":[["4.00","431.0"],["3.99","9.5"]],"asks":[["4.02","12.0"],["4.03","1.0"]]}"#;
+        let now = Instant::now();
+        snapshots.acquire(500, now).unwrap();
+        let snapshot = snapshots
+            .on_response("btcusdt", 500, StatusCode::OK, None, body, now)
+            .unwrap();
+        assert_eq!(snapshot.last_update_id, 1027024);
+        assert_eq!(snapshot.bids[1].price, 3.99);
+        assert_eq!(snapshot.asks[0].quantity, 12.0);
+
+        // 缓存时间内档位更少的查询使用缓存，档位更多或过期后重新请求
+        let cached = snapshots.cached("btcusdt", 1, now).unwrap();
+        assert_eq!((cached.bids.len(), cached.asks.len()), (1, 1));
+        assert!(snapshots.cached("btcusdt", 1000, now).is_none());
+        assert!(snapshots
+            .cached("btcusdt", 1, now + Duration::from_secs(1))
+            .is_none());
+
+        // 每分钟的权重用完后拒绝，一分钟后恢复
+        snapshots.acquire(100, now).unwrap();
+        assert_eq!(snapshots.acquire(100, now).unwrap_err().code, RATE_LIMITED);
+        snapshots.acquire(100, now + MINUTE).unwrap();
+
+        // 交易所限流后按 Retry-After 暂停
+        let later = now + 2 * MINUTE;
+        let e = snapshots
+            .on_response(
+                "ethusdt",
+                5,
+                StatusCode::TOO_MANY_REQUESTS,
+                Some(10),
+                "",
+                later,
+            )
+            .unwrap_err();
+        assert_eq!(e.code, RATE_LIMITED);
+        assert!(snapshots
+            .acquire(5, later + Duration::from_secs(9))
+            .is_err());
+        snapshots
+            .acquire(5, later + Duration::from_secs(10))
+            .unwrap();
+
+        let e = snapshots
+            .on_response(
+                "xxx",
+                5,
+                StatusCode::BAD_REQUEST,
+                None,
+                "invalid symbol",
+                later,
+            )
+            .unwrap_err();
+        assert_eq!(e.code, UNDEF_ERROR);
+    }
+}
@@ -0,0 +1,169 @@
+//! 策略注册的派生流
+//!
+//! 策略用 `derive` 请求为已订阅的流注册一段表达式（见 [`cryptoflow::expr`]），网关在该流的每个 tick
+//! 上计算一次，结果以 `{symbol}@derived:{name}` 推送给注册的策略，重的逐 tick 计算不必在 Python 中完成。
+//!
+//! 可用的变量：深度与 bbo 为 `bid0`、`ask0`、`bidvol0`、`askvol0` 等，数字为档位；K 线为 `open`、`high`、
+//! `low`、`close`、`volume`、`amount`、`buy_volume`、`buy_amount`、`trade_count`。
+
+use crate::model::quote::BinanceQuote;
+use cryptoflow::chat::{SDerived, SGeneralKline};
+use cryptoflow::expr::Program;
+use std::net::SocketAddr;
+
+#[derive(Debug)]
+struct Derived {
+    addr: SocketAddr,
+    /// 交易所流名称
+    source: String,
+    symbol: String,
+    stream: String,
+    program: Program,
+}
+
+#[derive(Debug, Default)]
+pub struct DerivedStreams {
+    streams: Vec<Derived>,
+}
+
+impl DerivedStreams {
+    /// 注册派生流，同一策略的同名派生流被替换
+    pub fn add(&mut self, addr: SocketAddr, source: &str, name: &str, program: Program) {
+        let symbol = source.split('@').next().unwrap_or_default().to_string();
+        let stream = stream_name(source, name);
+        self.streams
+            .retain(|d| !(d.addr == addr && d.stream == stream));
+        self.streams.push(Derived {
+            addr,
+            source: source.to_string(),
+            symbol,
+            stream,
+            program,
+        });
+    }
+
+    /// 策略已注册的派生流数，不含将被同名派生流替换的那条
+    pub fn count(&self, addr: &SocketAddr, source: &str, name: &str) -> usize {
+        let stream = stream_name(source, name);
+        self.streams
+            .iter()
+            .filter(|d| d.addr == *addr && d.stream != stream)
+            .count()
+    }
+
+    pub fn remove_client(&mut self, addr: &SocketAddr) {
+        self.streams.retain(|d| d.addr != *addr);
+    }
+
+    /// 策略退订了 `source`，移除其上该策略的派生流
+    pub fn remove_source(&mut self, addr: &SocketAddr, source: &str) {
+        self.streams
+            .retain(|d| !(d.addr == *addr && d.source == source));
+    }
+
+    pub fn has_source(&self, source: &str) -> bool {
+        self.streams.iter().any(|d| d.source == source)
+    }
+
+    /// 计算一条流上的所有派生流，返回要推送的策略与结果
+    pub fn on_tick(
+        &mut self,
+        source: &str,
+        time: i64,
+        vars: impl Fn(&str) -> Option<f64>,
+    ) -> Vec<(SocketAddr, SDerived)> {
+        self.streams
+            .iter_mut()
+            .filter(|d| d.source == source)
+            .filter_map(|d| {
+                let values = d.program.eval(&vars);
+                (!values.is_empty()).then(|| {
+                    (
+                        d.addr,
+                        SDerived {
+                            time,
+                            symbol: d.symbol.clone(),
+                            stream: d.stream.clone(),
+                            values,
+                        },
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+fn stream_name(source: &str, name: &str) -> String {
+    let symbol = source.split('@').next().unwrap_or_default();
+    format!("{}@derived:{}", symbol, name)
+}
+
+/// `bid0`、`askvol3` 等盘口变量
+pub fn depth_var(name: &str, bids: &[BinanceQuote], asks: &[BinanceQuote]) -> Option<f64> {
+    let (quotes, rest) = match name.strip_prefix("bid") {
+        Some(rest) => (bids, rest),
+        None => (asks, name.strip_prefix("ask")?),
+    };
+    match rest.strip_prefix("vol") {
+        Some(level) => quotes.get(level.parse::<usize>().ok()?).map(|q| q.quantity),
+        None => quotes.get(rest.parse::<usize>().ok()?).map(|q| q.price),
+    }
+}
+
+pub fn kline_var(name: &str, kline: &SGeneralKline) -> Option<f64> {
+    Some(match name {
+        "open" => kline.open,
+        "high" => kline.high,
+        "low" => kline.low,
+        "close" => kline.close,
+        "volume" => kline.volume,
+        "amount" => kline.amount,
+        "buy_volume" => kline.buy_volume,
+        "buy_amount" => kline.buy_amount,
+        "trade_count" => kline.trade_count as f64,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derived_streams() {
+        let quote = |price, quantity| BinanceQuote { price, quantity };
+        let (bids, asks) = (vec![quote(99.0, 1.0)], vec![quote(101.0, 3.0)]);
+        assert_eq!(depth_var("bid0", &bids, &asks), Some(99.0));
+        assert_eq!(depth_var("askvol0", &bids, &asks), Some(3.0));
+        assert_eq!(depth_var("ask1", &bids, &asks), None);
+        assert_eq!(depth_var("close", &bids, &asks), None);
+
+        let a: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:1001".parse().unwrap();
+        let mut derived = DerivedStreams::default();
+        let program = || Program::parse("mid = (bid0 + ask0) / 2").unwrap();
+        derived.add(a, "btcusdt@bookTicker", "mid", program());
+        derived.add(a, "btcusdt@bookTicker", "mid", program());
+        derived.add(b, "btcusdt@bookTicker", "mid", program());
+        assert!(derived.has_source("btcusdt@bookTicker"));
+        assert_eq!(derived.count(&a, "btcusdt@depth5", "mid"), 0);
+        assert_eq!(derived.count(&a, "btcusdt@bookTicker", "spread"), 1);
+
+        let outputs = derived.on_tick("btcusdt@bookTicker", 1, |n| depth_var(n, &bids, &asks));
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].1.stream, "btcusdt@derived:mid");
+        assert_eq!(outputs[0].1.values["mid"], 100.0);
+        assert!(derived
+            .on_tick("ethusdt@bookTicker", 1, |_| None)
+            .is_empty());
+
+        derived.remove_source(&a, "btcusdt@bookTicker");
+        let outputs = derived.on_tick("btcusdt@bookTicker", 2, |n| depth_var(n, &bids, &asks));
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].0, b);
+
+        derived.remove_client(&a);
+        derived.remove_client(&b);
+        assert!(!derived.has_source("btcusdt@bookTicker"));
+    }
+}
@@ -0,0 +1,149 @@
+//! 深度的价格分档
+//!
+//! 订阅深度时可以带上 `bucket`（如 0.5、1.0），网关把每一档归入该宽度的价格带、合并数量后再转发：
+//! 买盘向下取整、卖盘向上取整，合并后的价格不会优于原始报价。同一条深度按分档宽度
+//! 只计算一次，不分档的订阅者收到的数据不变。
+
+use crate::model::quote::BinanceQuote;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// 转发给策略端的深度中的一档
+#[derive(Debug, Deserialize)]
+struct Level {
+    price: f64,
+    quantity: f64,
+}
+
+/// 只有深度流可以分档，宽度必须为正数
+pub fn validate_bucket(stream: &str, bucket: f64) -> Result<(), String> {
+    if !stream.contains("@depth") {
+        return Err(format!("{} is not a depth stream", stream));
+    }
+    if !bucket.is_finite() || bucket <= 0.0 {
+        return Err(format!("invalid bucket {}", bucket));
+    }
+    Ok(())
+}
+
+/// 把价格取到宽度的整数倍，`up` 为向上取整；按宽度的小数位数舍入浮点误差
+fn snap(price: f64, bucket: f64, up: bool) -> f64 {
+    // 恰好落在边界上的价格不因浮点误差跨到相邻的价格带
+    let n = price / bucket;
+    let n = if up {
+        (n - 1e-9).ceil()
+    } else {
+        (n + 1e-9).floor()
+    };
+    let decimals = (-bucket.log10()).ceil().max(0.0) as i32;
+    let scale = 10f64.powi(decimals);
+    (n * bucket * scale).round() / scale
+}
+
+/// 按价格带合并已排好序的一侧深度，`bid` 为买盘；合并后保持原来的顺序
+pub fn bucket_levels(levels: &[BinanceQuote], bucket: f64, bid: bool) -> Vec<BinanceQuote> {
+    let mut merged: Vec<BinanceQuote> = Vec::new();
+    for level in levels {
+        let price = snap(level.price, bucket, !bid);
+        match merged.last_mut() {
+            Some(last) if last.price == price => last.quantity += level.quantity,
+            _ => merged.push(BinanceQuote {
+                price,
+                quantity: level.quantity,
+            }),
+        }
+    }
+    merged
+}
+
+/// 对一条已序列化的深度分档，其他字段保持不变
+pub fn bucket_depth(data: &str, bucket: f64) -> anyhow::Result<String> {
+    let mut value: Value = serde_json::from_str(data)?;
+    for (side, bid) in [("bids", true), ("asks", false)] {
+        let Some(levels) = value.get_mut(side) else {
+            anyhow::bail!("No {} in depth {}", side, data);
+        };
+        let quotes: Vec<BinanceQuote> = serde_json::from_value::<Vec<Level>>(levels.take())?
+            .into_iter()
+            .map(|l| BinanceQuote {
+                price: l.price,
+                quantity: l.quantity,
+            })
+            .collect();
+        *levels = serde_json::to_value(bucket_levels(&quotes, bucket, bid))?;
+    }
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// 按分档宽度缓存同一条深度的分档结果，每个宽度只计算一次
+#[derive(Default)]
+pub struct Buckets {
+    cache: Vec<(f64, String)>,
+}
+
+impl Buckets {
+    pub fn get(&mut self, data: &str, bucket: f64) -> anyhow::Result<&String> {
+        let index = match self.cache.iter().position(|(b, _)| *b == bucket) {
+            Some(index) => index,
+            None => {
+                self.cache.push((bucket, bucket_depth(data, bucket)?));
+                self.cache.len() - 1
+            }
+        };
+        Ok(&self.cache[index].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quotes(levels: &[(f64, f64)]) -> Vec<BinanceQuote> {
+        levels
+            .iter()
+            .map(|(price, quantity)| BinanceQuote {
+                price: *price,
+                quantity: *quantity,
+            })
+            .collect()
+    }
+
+    fn pairs(quotes: &[BinanceQuote]) -> Vec<(f64, f64)> {
+        quotes.iter().map(|q| (q.price, q.quantity)).collect()
+    }
+
+    #[test]
+    fn test_bucket_levels() {
+        let bids = quotes(&[(100.3, 1.0), (100.1, 2.0), (100.0, 0.5), (99.6, 1.0)]);
+        assert_eq!(
+            pairs(&bucket_levels(&bids, 0.5, true)),
+            vec![(100.0, 3.5), (99.5, 1.0)]
+        );
+        let asks = quotes(&[(100.4, 1.0), (100.5, 2.0), (100.6, 1.0), (101.0, 1.0)]);
+        assert_eq!(
+            pairs(&bucket_levels(&asks, 0.5, false)),
+            vec![(100.5, 3.0), (101.0, 2.0)]
+        );
+        // 浮点误差不改变所在的价格带
+        let bids = quotes(&[(0.3, 1.0), (0.2, 1.0)]);
+        assert_eq!(
+            pairs(&bucket_levels(&bids, 0.1, true)),
+            vec![(0.3, 1.0), (0.2, 1.0)]
+        );
+
+        let data = r#"{"offset":3,"time":1,"symbol":"btcusdt","stream":"btcusdt@depth5",
+            "bids":[{"price":100.3,"quantity":1.0},{"price":100.1,"quantity":2.0}],
+            "asks":[{"price":100.4,"quantity":1.0},{"price":101.2,"quantity":1.0}]}"#;
+        let value: Value = serde_json::from_str(&bucket_depth(data, 1.0).unwrap()).unwrap();
+        assert_eq!(value["offset"], 3);
+        assert_eq!(
+            value["bids"],
+            serde_json::json!([{"price": 100.0, "quantity": 3.0}])
+        );
+        assert_eq!(value["asks"][1]["price"], 102.0);
+
+        assert!(validate_bucket("btcusdt@depth20@100ms", 0.5).is_ok());
+        assert!(validate_bucket("btcusdt@kline_1m", 0.5).is_err());
+        assert!(validate_bucket("btcusdt@depth5", 0.0).is_err());
+    }
+}
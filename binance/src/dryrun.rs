@@ -0,0 +1,90 @@
+//! 演练模式
+//!
+//! 以 `--dry-run` 启动时网关照常连接行情与账户推送、接受策略端连接，但下单与撤单不发往交易所：
+//! 请求记录在日志中，并以模拟的 NEW / CANCELED 回报应答策略端。
+
+use crate::model::order::BinanceOrder;
+use crate::wire::ClientSender;
+use cryptoflow::chat::{SOrder, State};
+use std::collections::HashMap;
+use tracing::{error, info};
+
+/// 演练模式下挂着的订单
+#[derive(Debug, Default)]
+pub struct DryRun {
+    orders: HashMap<(u16, u32), (ClientSender, BinanceOrder)>,
+}
+
+impl DryRun {
+    /// 拦截下单，订单视为已挂出
+    pub fn place(&mut self, tx: &ClientSender, order: &BinanceOrder) {
+        info!("Dry run order {:?}", order);
+        self.orders
+            .insert((order.session_id, order.id), (tx.clone(), order.clone()));
+    }
+
+    /// 拦截撤单，返回被撤的订单；订单不存在时返回 None
+    pub fn cancel(
+        &mut self,
+        session_id: u16,
+        order_id: u32,
+    ) -> Option<(ClientSender, BinanceOrder)> {
+        info!("Dry run cancel {} of session {}", order_id, session_id);
+        self.orders.remove(&(session_id, order_id))
+    }
+
+    pub fn contains(&self, session_id: u16, order_id: u32) -> bool {
+        self.orders.contains_key(&(session_id, order_id))
+    }
+}
+
+/// 向策略端推送模拟的订单状态
+pub fn notify(tx: &ClientSender, order: &BinanceOrder, state: State) {
+    let order = SOrder::new(
+        order.id,
+        order.symbol.clone(),
+        order.side,
+        state,
+        order.order_type,
+        order.tif,
+        order.quantity,
+        order.price,
+    );
+    if let Err(e) = tx.reply(&order) {
+        error!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::Message;
+    use websocket::bounded_channel;
+
+    #[test]
+    fn test_dry_run() {
+        let (tx, mut rx) = bounded_channel("dry run", &Default::default());
+        let tx = ClientSender::from(tx);
+        let order = BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price: 100.0,
+            quantity: 2.0,
+            session_id: 1,
+            ..Default::default()
+        };
+        let mut dry_run = DryRun::default();
+        dry_run.place(&tx, &order);
+        assert!(dry_run.contains(1, 1));
+        notify(&tx, &order, State::NEW);
+        match rx.try_recv() {
+            Ok(Message::Text(text)) => assert!(text.contains("NEW")),
+            other => panic!("{:?}", other),
+        }
+
+        assert!(dry_run.cancel(1, 2).is_none());
+        let (_, canceled) = dry_run.cancel(1, 1).unwrap();
+        assert_eq!(canceled.id, 1);
+        assert!(!dry_run.contains(1, 1));
+    }
+}
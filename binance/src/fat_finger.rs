@@ -0,0 +1,190 @@
+//! 下单价格与名义价值的合理性校验
+//!
+//! 与交易所的过滤器无关，用来在策略出错时止损：限价单的价格偏离参考价超过 `max_deviation_pct`，或者
+//! 订单的名义价值超过 `max_notional` 时，网关拒绝订单并发出风控告警。参考价为 bookTicker 最新的中间价，
+//! 合约用它近似标记价格；没有盘口时不校验偏离，市价单的名义价值也无法估算，不校验。
+//!
+//! 确实要下这样的订单时带上 `"force": true`，网关记录日志后放行；`allow_force` 为 false 时忽略该标记。
+//! `symbols` 按交易对覆盖全局的限制，为 0 的限制不生效。
+
+use crate::model::order::BinanceOrder;
+use cryptoflow::chat::OrderType;
+use log::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// ```json
+/// "fat_finger": {
+///     "enabled": true,
+///     "max_deviation_pct": 5,
+///     "max_notional": 100000,
+///     "allow_force": true,
+///     "symbols": {
+///         "btcusdt": {"max_deviation_pct": 2, "max_notional": 500000}
+///     }
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FatFingerConfig {
+    pub enabled: bool,
+    /// 限价单的价格偏离参考价的上限，百分比
+    pub max_deviation_pct: f64,
+    /// 单笔订单名义价值的上限
+    pub max_notional: f64,
+    /// 是否接受订单上的 `force`
+    pub allow_force: bool,
+    /// symbol -> 该交易对的限制，没有填的项使用全局的限制
+    pub symbols: HashMap<String, FatFingerLimit>,
+}
+
+impl Default for FatFingerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_deviation_pct: 5.0,
+            max_notional: 0.0,
+            allow_force: true,
+            symbols: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct FatFingerLimit {
+    #[serde(default)]
+    pub max_deviation_pct: Option<f64>,
+    #[serde(default)]
+    pub max_notional: Option<f64>,
+}
+
+impl FatFingerConfig {
+    /// 交易对的 (价格偏离上限, 名义价值上限)
+    fn limits(&self, symbol: &str) -> (f64, f64) {
+        let limit = self.symbols.get(&symbol.to_lowercase());
+        (
+            limit
+                .and_then(|l| l.max_deviation_pct)
+                .unwrap_or(self.max_deviation_pct),
+            limit
+                .and_then(|l| l.max_notional)
+                .unwrap_or(self.max_notional),
+        )
+    }
+}
+
+/// 参考价，bbo 为最新的最优买卖价
+pub fn reference(bbo: Option<(f64, f64)>) -> Option<f64> {
+    bbo.filter(|(bid, ask)| *bid > 0.0 && *ask > 0.0)
+        .map(|(bid, ask)| (bid + ask) / 2.0)
+}
+
+/// 校验一笔委托，`reference` 为 None 时只校验限价单的名义价值
+pub fn check(
+    config: &FatFingerConfig,
+    order: &BinanceOrder,
+    reference: Option<f64>,
+) -> Result<(), String> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let Err(e) = violation(config, order, reference) else {
+        return Ok(());
+    };
+    if order.force && config.allow_force {
+        warn!("Force order {:?} past the fat finger check: {}", order, e);
+        return Ok(());
+    }
+    Err(e)
+}
+
+fn violation(
+    config: &FatFingerConfig,
+    order: &BinanceOrder,
+    reference: Option<f64>,
+) -> Result<(), String> {
+    let (max_deviation_pct, max_notional) = config.limits(&order.symbol);
+    let limit = order.order_type != OrderType::MARKET && order.price > 0.0;
+    if let (true, true, Some(reference)) = (limit, max_deviation_pct > 0.0, reference) {
+        let deviation = (order.price - reference).abs() / reference * 100.0;
+        if deviation > max_deviation_pct {
+            return Err(format!(
+                "price {} is {:.2}% away from the reference price {}, above {}%",
+                order.price, deviation, reference, max_deviation_pct
+            ));
+        }
+    }
+    let price = match limit {
+        true => Some(order.price),
+        false => reference,
+    };
+    if let (true, Some(price)) = (max_notional > 0.0, price) {
+        let notional = price * order.quantity;
+        if notional > max_notional {
+            return Err(format!(
+                "notional {} is above the ceiling {}",
+                notional, max_notional
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(symbol: &str, order_type: OrderType, price: f64, quantity: f64) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: symbol.into(),
+            price,
+            quantity,
+            order_type,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fat_finger() {
+        let config: FatFingerConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "max_deviation_pct": 5,
+            "max_notional": 10000,
+            "symbols": {"btcusdt": {"max_notional": 100000}},
+        }))
+        .unwrap();
+        let reference = reference(Some((49999.0, 50001.0)));
+        assert_eq!(reference, Some(50000.0));
+
+        let buy = order("btcusdt", OrderType::LIMIT, 52000.0, 1.0);
+        assert_eq!(check(&config, &buy, reference), Ok(()));
+        let buy = order("BTCUSDT", OrderType::LIMIT, 53000.0, 1.0);
+        assert!(check(&config, &buy, reference)
+            .unwrap_err()
+            .contains("reference price"));
+        // 没有盘口时只校验名义价值
+        assert_eq!(check(&config, &buy, None), Ok(()));
+        let buy = order("btcusdt", OrderType::LIMIT, 50000.0, 3.0);
+        assert!(check(&config, &buy, None).unwrap_err().contains("ceiling"));
+        // 其它交易对使用全局的限制，市价单按参考价估算名义价值
+        let market = order("ethusdt", OrderType::MARKET, 0.0, 0.5);
+        assert!(check(&config, &market, reference).is_err());
+        assert_eq!(check(&config, &market, None), Ok(()));
+
+        let mut forced = order("btcusdt", OrderType::LIMIT, 60000.0, 1.0);
+        forced.force = true;
+        assert_eq!(check(&config, &forced, reference), Ok(()));
+        let config = FatFingerConfig {
+            allow_force: false,
+            ..config
+        };
+        assert!(check(&config, &forced, reference).is_err());
+        let config = FatFingerConfig {
+            enabled: false,
+            ..config
+        };
+        assert_eq!(check(&config, &forced, reference), Ok(()));
+    }
+}
@@ -0,0 +1,251 @@
+//! 手续费抵扣资产（BNB）余额监控
+//!
+//! 用 BNB 抵扣手续费可以享受折扣，但余额耗尽后会悄悄改为按原币种收取。
+//! 余额跌破阈值时告警一次；配置了补充数量时，网关用市价单买入，
+//! 买入受冷却时间、每日次数和单次最大金额限制。
+
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// ```json
+/// "fee_balance": {
+///     "threshold": 1.0,
+///     "top_up": 2.0,
+///     "symbol": "bnbusdt",
+///     "max_notional": 1500,
+///     "max_daily": 1,
+///     "cooldown_secs": 3600
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FeeBalanceConfig {
+    /// 监控的资产
+    pub asset: String,
+    /// 可用余额低于该值时告警，0 表示不监控
+    pub threshold: f64,
+    /// 每次买入的数量，0 表示只告警不买入
+    pub top_up: f64,
+    /// 买入使用的交易对
+    pub symbol: String,
+    /// 单次买入的最大金额（交易对计价资产），0 表示不限制；没有价格时不买入
+    pub max_notional: f64,
+    /// 每个 UTC 自然日最多买入次数
+    pub max_daily: u32,
+    /// 两次买入的最小间隔
+    pub cooldown_secs: u64,
+}
+
+impl Default for FeeBalanceConfig {
+    fn default() -> Self {
+        Self {
+            asset: "BNB".into(),
+            threshold: 0.0,
+            top_up: 0.0,
+            symbol: "bnbusdt".into(),
+            max_notional: 0.0,
+            max_daily: 1,
+            cooldown_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeeAction {
+    /// 余额跌破阈值
+    Alert { free: f64 },
+    /// 买入被限制拦下，附原因
+    Blocked(String),
+    /// 市价买入 `quantity`
+    TopUp { symbol: String, quantity: f64 },
+}
+
+#[derive(Debug)]
+pub struct FeeMonitor {
+    config: FeeBalanceConfig,
+    /// 是否已经处于阈值以下，用于只在跌破时告警一次
+    below: bool,
+    last_top_up: Option<Instant>,
+    /// (UTC 日序号, 当日买入次数)
+    daily: (i64, u32),
+}
+
+impl FeeMonitor {
+    pub fn new(mut config: FeeBalanceConfig) -> Self {
+        config.asset = config.asset.to_uppercase();
+        config.symbol = config.symbol.to_lowercase();
+        Self {
+            config,
+            below: false,
+            last_top_up: None,
+            daily: (0, 0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.threshold > 0.0
+    }
+
+    pub fn asset(&self) -> &str {
+        &self.config.asset
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.config.symbol
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.config.threshold
+    }
+
+    /// 收到账户余额更新，`price` 为买入交易对的最新价格，`day` 为 UTC 日序号
+    pub fn on_balance(
+        &mut self,
+        free: f64,
+        price: Option<f64>,
+        now: Instant,
+        day: i64,
+    ) -> Vec<FeeAction> {
+        let mut actions = Vec::new();
+        if !self.enabled() {
+            return actions;
+        }
+        if free >= self.config.threshold {
+            self.below = false;
+            return actions;
+        }
+
+        if !self.below {
+            self.below = true;
+            actions.push(FeeAction::Alert { free });
+        }
+
+        if self.config.top_up <= 0.0 {
+            return actions;
+        }
+        match self.guard(price, now, day) {
+            Ok(()) => {
+                self.last_top_up = Some(now);
+                self.daily.1 += 1;
+                actions.push(FeeAction::TopUp {
+                    symbol: self.config.symbol.clone(),
+                    quantity: self.config.top_up,
+                });
+            }
+            Err(reason) => actions.push(FeeAction::Blocked(reason)),
+        }
+        actions
+    }
+
+    fn guard(&mut self, price: Option<f64>, now: Instant, day: i64) -> Result<(), String> {
+        let cooldown = Duration::from_secs(self.config.cooldown_secs);
+        if let Some(last) = self.last_top_up {
+            if now.duration_since(last) < cooldown {
+                return Err("cooling down".into());
+            }
+        }
+
+        if self.daily.0 != day {
+            self.daily = (day, 0);
+        }
+        if self.daily.1 >= self.config.max_daily {
+            return Err(format!("daily limit {} reached", self.config.max_daily));
+        }
+
+        if self.config.max_notional > 0.0 {
+            let Some(price) = price.filter(|p| *p > 0.0) else {
+                return Err(format!("no price for {}", self.config.symbol));
+            };
+            let notional = price * self.config.top_up;
+            if notional > self.config.max_notional {
+                return Err(format!(
+                    "notional {} exceeds {}",
+                    notional, self.config.max_notional
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> FeeMonitor {
+        FeeMonitor::new(FeeBalanceConfig {
+            threshold: 1.0,
+            top_up: 2.0,
+            max_notional: 1500.0,
+            max_daily: 2,
+            cooldown_secs: 60,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_alert_once() {
+        let mut monitor = FeeMonitor::new(FeeBalanceConfig {
+            threshold: 1.0,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(monitor.on_balance(5.0, None, now, 0).is_empty());
+        assert_eq!(
+            monitor.on_balance(0.5, None, now, 0),
+            vec![FeeAction::Alert { free: 0.5 }]
+        );
+        assert!(monitor.on_balance(0.4, None, now, 0).is_empty());
+        // 回到阈值以上后再次跌破会重新告警
+        assert!(monitor.on_balance(3.0, None, now, 0).is_empty());
+        assert_eq!(monitor.on_balance(0.3, None, now, 0).len(), 1);
+
+        assert!(!FeeMonitor::new(FeeBalanceConfig::default()).enabled());
+    }
+
+    #[test]
+    fn test_top_up_guards() {
+        let mut monitor = monitor();
+        let now = Instant::now();
+
+        assert_eq!(
+            monitor.on_balance(0.5, Some(600.0), now, 0),
+            vec![
+                FeeAction::Alert { free: 0.5 },
+                FeeAction::TopUp {
+                    symbol: "bnbusdt".into(),
+                    quantity: 2.0
+                }
+            ]
+        );
+        assert_eq!(
+            monitor.on_balance(0.5, Some(600.0), now + Duration::from_secs(10), 0),
+            vec![FeeAction::Blocked("cooling down".into())]
+        );
+
+        let later = now + Duration::from_secs(61);
+        assert!(matches!(
+            monitor.on_balance(0.5, None, later, 0)[..],
+            [FeeAction::Blocked(_)]
+        ));
+        assert!(matches!(
+            monitor.on_balance(0.5, Some(800.0), later, 0)[..],
+            [FeeAction::Blocked(_)]
+        ));
+        assert!(matches!(
+            monitor.on_balance(0.5, Some(600.0), later, 0)[..],
+            [FeeAction::TopUp { .. }]
+        ));
+
+        // 每日次数限制，跨日重置
+        let later = later + Duration::from_secs(61);
+        assert_eq!(
+            monitor.on_balance(0.5, Some(600.0), later, 0),
+            vec![FeeAction::Blocked("daily limit 2 reached".into())]
+        );
+        assert!(matches!(
+            monitor.on_balance(0.5, Some(600.0), later, 1)[..],
+            [FeeAction::TopUp { .. }]
+        ));
+    }
+}
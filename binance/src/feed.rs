@@ -0,0 +1,125 @@
+//! 主行情连接之外的公共行情连接
+//!
+//! 期权行情与基差用到的现货行情不在网关的主行情连接上，各自另开一条只订阅公共流的连接。连接断开后
+//! 在后台重连并重新订阅；重连期间的订阅与退订只记录，重连后统一订阅。
+
+use serde_json::Value;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tracing::{error, info};
+use websocket::BinanceWebsocketClient;
+
+/// 重连失败后的重试间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+async fn connect(
+    name: &str,
+    url: &str,
+) -> anyhow::Result<(BinanceWebsocketClient, Receiver<Value>)> {
+    let mut client = BinanceWebsocketClient::new_public(name);
+    client.set_url(url);
+    let rx = client.connect().await?;
+    Ok((client, rx))
+}
+
+pub struct PublicFeed {
+    name: &'static str,
+    url: String,
+    client: BinanceWebsocketClient,
+    rx: Receiver<Value>,
+    /// 连接上订阅的流
+    streams: HashSet<String>,
+    reconnecting: Option<tokio::sync::oneshot::Receiver<(BinanceWebsocketClient, Receiver<Value>)>>,
+}
+
+impl PublicFeed {
+    /// `name` 用于日志与 websocket 客户端的名称
+    pub async fn connect(name: &'static str, url: &str) -> anyhow::Result<Self> {
+        let (client, rx) = connect(name, url).await?;
+        info!("{} market connected", name);
+        Ok(Self {
+            name,
+            url: url.to_string(),
+            client,
+            rx,
+            streams: HashSet::default(),
+            reconnecting: None,
+        })
+    }
+
+    /// 订阅或退订，重连期间只记录，重连后统一订阅
+    pub async fn call(&mut self, method: &str, streams: &[String], id: i64) -> anyhow::Result<()> {
+        match method {
+            "SUBSCRIBE" => self.streams.extend(streams.iter().cloned()),
+            _ => self.streams.retain(|s| !streams.contains(s)),
+        }
+        if self.reconnecting.is_none() {
+            self.client
+                .wsapi_call(method, serde_json::json!(streams), id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 等待下一条推送，连接断开时在后台重连
+    pub async fn recv(&mut self) -> Value {
+        loop {
+            if let Some(reconnecting) = self.reconnecting.as_mut() {
+                match reconnecting.await {
+                    Ok((client, rx)) => {
+                        self.reconnecting = None;
+                        self.client = client;
+                        self.rx = rx;
+                        if !self.streams.is_empty() {
+                            let streams: Vec<_> = self.streams.iter().cloned().collect();
+                            if let Err(e) = self
+                                .client
+                                .wsapi_call("SUBSCRIBE", serde_json::json!(streams), 0)
+                                .await
+                            {
+                                error!("Resubscribe {} failed: {}", self.name, e);
+                            }
+                        }
+                        info!(
+                            "{} market reconnected, {} streams resubscribed",
+                            self.name,
+                            self.streams.len()
+                        );
+                    }
+                    Err(e) => {
+                        error!("{} reconnect task aborted: {}", self.name, e);
+                        self.start_reconnect();
+                    }
+                }
+                continue;
+            }
+
+            match self.rx.recv().await {
+                Some(value) => return value,
+                None => {
+                    error!("{} market disconnected", self.name);
+                    self.start_reconnect();
+                }
+            }
+        }
+    }
+
+    fn start_reconnect(&mut self) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let (name, url) = (self.name, self.url.clone());
+        tokio::spawn(async move {
+            let connected = loop {
+                match connect(name, &url).await {
+                    Ok(connected) => break connected,
+                    Err(e) => {
+                        error!("Reconnect {} market failed: {}", name, e);
+                        tokio::time::sleep(RECONNECT_INTERVAL).await;
+                    }
+                }
+            };
+            let _ = tx.send(connected);
+        });
+        self.reconnecting = Some(rx);
+    }
+}
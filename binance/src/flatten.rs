@@ -0,0 +1,359 @@
+//! 定时平仓
+//!
+//! 在配置的时间（如周末前、重要经济数据公布前）把选定会话的持仓全部市价平掉。每个计划列出参与的会话，
+//! 没有列出的会话不受影响；时间均为 UTC，支持三种写法：
+//!
+//! - `"23:50"`：每天
+//! - `"Fri 23:50"`：每周
+//! - `"2026-10-16 12:25"`：只执行一次
+//!
+//! 到点后按会话当前的持仓为每个持仓键生成一笔反向市价单，双向持仓的多空两腿分别平仓。`dry_run` 时只在
+//! 日志中预览会发出的订单；生成的每笔订单以 `audit` 为 target 记入日志。会话没有登录交易时无法接收
+//! 回报，跳过并记录。
+
+use crate::model::order::BinanceOrder;
+use crate::model::symbol::BinanceSymbol;
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, TimeDelta, Utc, Weekday};
+use cryptoflow::chat::{OrderType, Position, Side, TimeInForce};
+use cryptoflow::trading_rules::TradingRules;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::info;
+
+/// 网关生成的平仓单从这个编号开始，避开策略端自增的订单编号
+pub const FIRST_ORDER_ID: u32 = 0xF000_0000;
+
+/// 审计日志的 target
+pub const AUDIT: &str = "audit";
+
+/// ```json
+/// "flatten": {
+///     "enabled": false,
+///     "dry_run": true,
+///     "schedules": [
+///         {"name": "weekend", "at": "Fri 23:50", "sessions": [1, 2]},
+///         {"name": "cpi", "at": "2026-10-15 12:25", "sessions": [1]}
+///     ]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FlattenConfig {
+    pub enabled: bool,
+    /// 只预览生成的订单，不发出
+    pub dry_run: bool,
+    pub schedules: Vec<FlattenSchedule>,
+}
+
+impl Default for FlattenConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dry_run: true,
+            schedules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FlattenSchedule {
+    /// 日志中的名称，不填时使用 `at`
+    #[serde(default)]
+    pub name: String,
+    /// UTC 时间，写法见模块文档
+    pub at: String,
+    /// 参与的会话
+    pub sessions: Vec<u16>,
+}
+
+impl FlattenSchedule {
+    fn label(&self) -> &str {
+        match self.name.is_empty() {
+            true => &self.at,
+            false => &self.name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum At {
+    Daily(NaiveTime),
+    Weekly(Weekday, NaiveTime),
+    Once(NaiveDateTime),
+}
+
+impl At {
+    fn parse(at: &str) -> anyhow::Result<Self> {
+        let at = at.trim();
+        if let Ok(time) = NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M") {
+            return Ok(Self::Once(time));
+        }
+        if let Ok(time) = NaiveTime::parse_from_str(at, "%H:%M") {
+            return Ok(Self::Daily(time));
+        }
+        let (day, time) = at
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("invalid flatten time {}", at))?;
+        let day = day
+            .parse::<Weekday>()
+            .map_err(|_| anyhow!("invalid weekday in flatten time {}", at))?;
+        let time = NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|e| anyhow!("invalid flatten time {}: {}", at, e))?;
+        Ok(Self::Weekly(day, time))
+    }
+
+    /// 不晚于 `now` 的最近一次
+    fn last(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = now.date_naive();
+        let (time, period) = match *self {
+            Self::Once(time) => return Some(time.and_utc()).filter(|t| *t <= now),
+            Self::Daily(time) => (today.and_time(time).and_utc(), TimeDelta::days(1)),
+            Self::Weekly(day, time) => {
+                let back =
+                    (now.weekday().num_days_from_monday() + 7 - day.num_days_from_monday()) % 7;
+                let date = today - TimeDelta::days(back as i64);
+                (date.and_time(time).and_utc(), TimeDelta::weeks(1))
+            }
+        };
+        Some(if time > now { time - period } else { time })
+    }
+
+    /// 晚于 `now` 的下一次
+    fn next(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match *self {
+            Self::Once(time) => Some(time.and_utc()).filter(|t| *t > now),
+            Self::Daily(_) => self.last(now).map(|t| t + TimeDelta::days(1)),
+            Self::Weekly(..) => self.last(now).map(|t| t + TimeDelta::weeks(1)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Flatten {
+    dry_run: bool,
+    schedules: Vec<(At, FlattenSchedule)>,
+    /// 上次检查的时间，只执行晚于它的计划，网关启动前错过的不补
+    checked: Option<DateTime<Utc>>,
+    next_id: u32,
+}
+
+impl Default for Flatten {
+    fn default() -> Self {
+        Self {
+            dry_run: true,
+            schedules: Vec::new(),
+            checked: None,
+            next_id: FIRST_ORDER_ID,
+        }
+    }
+}
+
+impl Flatten {
+    /// 解析计划的时间，写法错误时返回错误
+    pub fn new(config: &FlattenConfig) -> anyhow::Result<Self> {
+        let mut flatten = Self {
+            dry_run: config.dry_run,
+            ..Default::default()
+        };
+        if !config.enabled {
+            return Ok(flatten);
+        }
+        let now = Utc::now();
+        for schedule in config.schedules.iter() {
+            let at = At::parse(&schedule.at)?;
+            match at.next(now) {
+                Some(next) => info!(
+                    "Flatten {} for sessions {:?} next at {}, dry run {}",
+                    schedule.label(),
+                    schedule.sessions,
+                    next,
+                    config.dry_run
+                ),
+                None => info!("Flatten {} at {} has passed", schedule.label(), schedule.at),
+            }
+            flatten.schedules.push((at, schedule.clone()));
+        }
+        Ok(flatten)
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// 下一笔平仓单的订单号，导出状态时保存
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// 导入另一个网关的计数器，只向前推进
+    pub fn advance_id(&mut self, next_id: u32) {
+        self.next_id = self.next_id.max(next_id);
+    }
+
+    /// 从上次检查到 `now` 之间到点的计划，返回计划名称与参与的会话
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<(String, u16)> {
+        if self.schedules.is_empty() {
+            return Vec::new();
+        }
+        let Some(checked) = self.checked.replace(now) else {
+            return Vec::new();
+        };
+        let mut due: Vec<(String, u16)> = Vec::new();
+        let due_schedules = self
+            .schedules
+            .iter()
+            .filter(|(at, _)| at.last(now).is_some_and(|t| t > checked));
+        for (_, schedule) in due_schedules {
+            for session_id in schedule.sessions.iter() {
+                // 同时到点的计划只平仓一次
+                if !due.iter().any(|(_, id)| id == session_id) {
+                    due.push((schedule.label().to_string(), *session_id));
+                }
+            }
+        }
+        due
+    }
+
+    /// 平掉会话所有持仓的市价单，数量按交易对的数量步长舍入
+    pub fn orders(
+        &mut self,
+        session_id: u16,
+        positions: &HashMap<String, Position>,
+        products: &HashMap<String, BinanceSymbol>,
+    ) -> Vec<BinanceOrder> {
+        let mut positions: Vec<_> = positions.iter().collect();
+        positions.sort_by(|a, b| a.0.cmp(b.0));
+        let mut orders = Vec::new();
+        for (_, position) in positions {
+            let step = products
+                .get(&position.symbol.to_lowercase())
+                .map(|p| p.lot_size())
+                .unwrap_or_default();
+            let quantity = round_to_step(position.net.abs(), step);
+            if quantity <= 0.0 {
+                continue;
+            }
+            let side = match position.net > 0.0 {
+                true => Side::SELL,
+                false => Side::BUY,
+            };
+            orders.push(BinanceOrder {
+                id: self.next_id,
+                symbol: position.symbol.clone(),
+                price: 0.0,
+                quantity,
+                side,
+                order_type: OrderType::MARKET,
+                tif: TimeInForce::GTC,
+                session_id,
+                position_side: position.side,
+                // 平仓不受价格与名义价值的合理性校验限制
+                force: true,
+                ..Default::default()
+            });
+            self.next_id = self.next_id.wrapping_add(1).max(FIRST_ORDER_ID);
+        }
+        orders
+    }
+}
+
+/// 按步长舍入，并去掉浮点误差；没有步长时原样返回
+fn round_to_step(quantity: f64, step: f64) -> f64 {
+    if step <= 0.0 {
+        return quantity;
+    }
+    let decimals = (-step.log10()).ceil().max(0.0) as i32;
+    let scale = 10f64.powi(decimals);
+    ((quantity / step).round() * step * scale).round() / scale
+}
+
+/// 记录一笔生成的平仓单，`action` 为预览、发出或跳过
+pub fn audit(schedule: &str, order: &BinanceOrder, action: &str) {
+    info!(
+        target: AUDIT,
+        "Flatten {} {}: session {} order {} {:?} {} {} position side {:?}",
+        schedule,
+        action,
+        order.session_id,
+        order.id,
+        order.side,
+        order.quantity,
+        order.symbol,
+        order.position_side
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::PositionSide;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    #[test]
+    fn test_flatten() {
+        // 2026-10-16 是周五
+        let now = utc("2026-10-14 12:00");
+        let weekly = At::parse("fri 23:50").unwrap();
+        assert_eq!(weekly.last(now), Some(utc("2026-10-09 23:50")));
+        assert_eq!(weekly.next(now), Some(utc("2026-10-16 23:50")));
+        let daily = At::parse("08:00").unwrap();
+        assert_eq!(daily.last(now), Some(utc("2026-10-14 08:00")));
+        let once = At::parse("2026-10-15 12:25").unwrap();
+        assert_eq!(once.last(now), None);
+        assert!(At::parse("Someday 10:00").is_err());
+
+        let config = FlattenConfig {
+            enabled: true,
+            dry_run: false,
+            schedules: vec![
+                FlattenSchedule {
+                    name: "weekend".into(),
+                    at: "Fri 23:50".into(),
+                    sessions: vec![1, 2],
+                },
+                FlattenSchedule {
+                    name: String::new(),
+                    at: "2026-10-16 23:50".into(),
+                    sessions: vec![1],
+                },
+            ],
+        };
+        let mut flatten = Flatten::new(&config).unwrap();
+        // 第一次检查只记录时间，启动前错过的计划不补
+        assert!(flatten.due(utc("2026-10-16 23:49")).is_empty());
+        assert_eq!(
+            flatten.due(utc("2026-10-16 23:50")),
+            vec![("weekend".to_string(), 1), ("weekend".to_string(), 2)]
+        );
+        assert!(flatten.due(utc("2026-10-16 23:51")).is_empty());
+
+        let mut positions = HashMap::new();
+        positions.insert(
+            "btcusdt".to_string(),
+            Position::new("btcusdt", 0.30000000000000004),
+        );
+        positions.insert("ethusdt".to_string(), Position::new("ethusdt", 0.0));
+        let short = Position {
+            side: Some(PositionSide::SHORT),
+            ..Position::new("solusdt", -3.0)
+        };
+        positions.insert(short.key(), short);
+        let orders = flatten.orders(1, &positions, &HashMap::new());
+        assert_eq!(orders.len(), 2);
+        assert_eq!(
+            (orders[0].side, orders[0].quantity, orders[0].id),
+            (Side::SELL, 0.30000000000000004, FIRST_ORDER_ID)
+        );
+        assert_eq!(orders[1].side, Side::BUY);
+        assert_eq!(orders[1].position_side, Some(PositionSide::SHORT));
+        assert_eq!(orders[1].id, FIRST_ORDER_ID + 1);
+        assert_eq!(round_to_step(0.30000000000000004, 0.001), 0.3);
+        assert_eq!(round_to_step(1.23456, 0.01), 1.23);
+    }
+}
@@ -0,0 +1,214 @@
+//! 成交流向
+//!
+//! 策略订阅 `{symbol}@cvd:{window}`（如 `btcusdt@cvd:1m`），网关改为向交易所订阅该交易对的归集成交
+//! `{symbol}@aggTrade`，每笔成交后推送累计主动买卖差（CVD）以及窗口内的主动买入量、卖出量与买卖失衡。
+//! 同一交易对的不同窗口共用一条 `aggTrade` 订阅；CVD 从网关开始统计这条流时累计，窗口内的成交按秒归集，
+//! 窗口最长一天。
+
+use crate::bar;
+use cryptoflow::chat::STradeFlow;
+use std::collections::{HashMap, VecDeque};
+
+/// 窗口内的成交按这个粒度归集
+const SLOT_MS: i64 = 1000;
+const MAX_WINDOW_MS: i64 = 24 * 3600 * 1000;
+
+/// 窗口的毫秒数，如 `30s`、`1m`、`4h`
+pub fn window_ms(window: &str) -> Option<i64> {
+    bar::interval_ms(window)
+        .filter(|(length, offset)| *offset == 0 && *length <= MAX_WINDOW_MS)
+        .map(|(length, _)| length)
+}
+
+/// 策略端的成交流向流对应的交易所流，不是成交流向的流返回 None
+pub fn exchange_stream(stream: &str) -> Option<String> {
+    let (symbol, window) = stream.split_once("@cvd:")?;
+    window_ms(window).map(|_| format!("{}@aggTrade", symbol))
+}
+
+/// 发往交易所的流名称，成交流向的流换成 `aggTrade` 并去重
+pub fn wire_streams(streams: &[String]) -> Vec<String> {
+    let mut wire: Vec<String> = Vec::new();
+    for stream in streams {
+        let stream = exchange_stream(stream).unwrap_or_else(|| stream.clone());
+        if !wire.contains(&stream) {
+            wire.push(stream);
+        }
+    }
+    wire
+}
+
+#[derive(Debug)]
+struct Slot {
+    /// 成交时间除以 [`SLOT_MS`]
+    index: i64,
+    buy: f64,
+    sell: f64,
+}
+
+#[derive(Debug)]
+struct Flow {
+    symbol: String,
+    /// 交易所流名称
+    source: String,
+    window_ms: i64,
+    cvd: f64,
+    slots: VecDeque<Slot>,
+    buy: f64,
+    sell: f64,
+}
+
+impl Flow {
+    fn on_trade(&mut self, time: i64, quantity: f64) {
+        let (buy, sell) = match quantity >= 0.0 {
+            true => (quantity, 0.0),
+            false => (0.0, -quantity),
+        };
+        self.cvd += quantity;
+        self.buy += buy;
+        self.sell += sell;
+
+        let index = time.div_euclid(SLOT_MS);
+        match self.slots.back_mut() {
+            Some(slot) if slot.index >= index => {
+                slot.buy += buy;
+                slot.sell += sell;
+            }
+            _ => self.slots.push_back(Slot { index, buy, sell }),
+        }
+
+        // 起点落在窗口之前的整秒移出窗口
+        let start = (time - self.window_ms).div_euclid(SLOT_MS);
+        while self.slots.front().is_some_and(|slot| slot.index <= start) {
+            let slot = self.slots.pop_front().unwrap();
+            self.buy -= slot.buy;
+            self.sell -= slot.sell;
+        }
+        // 累减的浮点误差不会让成交量变成负数
+        self.buy = self.buy.max(0.0);
+        self.sell = self.sell.max(0.0);
+    }
+
+    fn imbalance(&self) -> f64 {
+        let total = self.buy + self.sell;
+        match total > 0.0 {
+            true => (self.buy - self.sell) / total,
+            false => 0.0,
+        }
+    }
+}
+
+/// 网关正在统计的成交流向，按策略端的流名称索引
+#[derive(Debug, Default)]
+pub struct TradeFlows {
+    flows: HashMap<String, Flow>,
+}
+
+impl TradeFlows {
+    /// 开始统计一条成交流向的流，不是成交流向的流或已在统计时忽略
+    pub fn track(&mut self, stream: &str) {
+        if self.flows.contains_key(stream) {
+            return;
+        }
+        let (Some(source), Some((symbol, window))) =
+            (exchange_stream(stream), stream.split_once("@cvd:"))
+        else {
+            return;
+        };
+        let Some(window_ms) = window_ms(window) else {
+            return;
+        };
+        self.flows.insert(
+            stream.to_string(),
+            Flow {
+                symbol: symbol.to_string(),
+                source,
+                window_ms,
+                cvd: 0.0,
+                slots: VecDeque::new(),
+                buy: 0.0,
+                sell: 0.0,
+            },
+        );
+    }
+
+    /// 停止统计，该交易对没有其他窗口时返回要退订的 `aggTrade` 流
+    pub fn untrack(&mut self, stream: &str) -> Option<String> {
+        let flow = self.flows.remove(stream)?;
+        let used = self.flows.values().any(|f| f.source == flow.source);
+        (!used).then_some(flow.source)
+    }
+
+    pub fn is_tracked(&self, stream: &str) -> bool {
+        self.flows.contains_key(stream)
+    }
+
+    /// 一笔归集成交，`quantity` 主动买入为正、主动卖出为负；返回该交易对每个窗口的最新统计
+    pub fn on_trade(&mut self, source: &str, time: i64, quantity: f64) -> Vec<STradeFlow> {
+        self.flows
+            .iter_mut()
+            .filter(|(_, flow)| flow.source == source)
+            .map(|(stream, flow)| {
+                flow.on_trade(time, quantity);
+                STradeFlow {
+                    time,
+                    symbol: flow.symbol.clone(),
+                    stream: stream.clone(),
+                    cvd: flow.cvd,
+                    buy_volume: flow.buy,
+                    sell_volume: flow.sell,
+                    imbalance: flow.imbalance(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trade_flows() {
+        assert_eq!(
+            exchange_stream("btcusdt@cvd:1m").as_deref(),
+            Some("btcusdt@aggTrade")
+        );
+        assert_eq!(exchange_stream("btcusdt@cvd:1w"), None);
+        assert_eq!(exchange_stream("btcusdt@kline_1m"), None);
+        let streams = ["btcusdt@cvd:1m", "btcusdt@cvd:5m", "btcusdt@depth20"].map(String::from);
+        assert_eq!(
+            wire_streams(&streams),
+            vec!["btcusdt@aggTrade", "btcusdt@depth20"]
+        );
+
+        let mut flows = TradeFlows::default();
+        flows.track("btcusdt@cvd:1m");
+        flows.track("btcusdt@cvd:5m");
+        flows.track("btcusdt@kline_1m");
+        assert!(!flows.is_tracked("btcusdt@kline_1m"));
+
+        let t = 1_700_000_000_000;
+        flows.on_trade("btcusdt@aggTrade", t, 3.0);
+        flows.on_trade("btcusdt@aggTrade", t + 500, -1.0);
+        // 2 分钟后 1m 窗口只剩这一笔，5m 窗口包含全部成交
+        let mut out = flows.on_trade("btcusdt@aggTrade", t + 120_000, -2.0);
+        out.sort_by(|a, b| a.stream.cmp(&b.stream));
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].stream, "btcusdt@cvd:1m");
+        assert_eq!(out[0].cvd, 0.0);
+        assert_eq!((out[0].buy_volume, out[0].sell_volume), (0.0, 2.0));
+        assert_eq!(out[0].imbalance, -1.0);
+        assert_eq!((out[1].buy_volume, out[1].sell_volume), (3.0, 3.0));
+        assert_eq!(out[1].imbalance, 0.0);
+        assert!(flows.on_trade("ethusdt@aggTrade", t, 1.0).is_empty());
+
+        // 最后一个窗口停止统计时才退订 aggTrade
+        assert_eq!(flows.untrack("btcusdt@cvd:1m"), None);
+        assert_eq!(
+            flows.untrack("btcusdt@cvd:5m").as_deref(),
+            Some("btcusdt@aggTrade")
+        );
+        assert_eq!(flows.untrack("btcusdt@cvd:5m"), None);
+    }
+}
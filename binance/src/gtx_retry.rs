@@ -0,0 +1,225 @@
+//! 只做 maker 的订单被交易所拒绝后改价重发
+//!
+//! 盘口在下单途中移动时，只做 maker 的订单（合约的 GTX、现货的 `LIMIT_MAKER`）会因为会立即成交被交易所
+//! 拒绝。开启后网关把这样的订单往被动方向挪 `reprice_ticks` 个 tick 后用原订单号重发，最多重发
+//! `max_retries` 次；重发期间的拒绝不推送给策略，之后的回报带着最终挂出的价格。重发次数用完仍被拒绝时，
+//! 最后一次拒绝照常推送。只处理交易所返回会立即成交的拒绝，其余的拒绝与网关自己的拒绝不重发。
+
+use crate::model::order::BinanceOrder;
+use crate::post_only;
+use cryptoflow::chat::State;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// 合约：Post Only 订单会立即成交
+const FUTURES_GTX_REJECTED: i64 = -5022;
+
+/// 现货：`LIMIT_MAKER` 订单会立即成交，与其他原因共用错误码
+const SPOT_ORDER_REJECTED: i64 = -2010;
+
+/// ```json
+/// "gtx_retry": {
+///     "enabled": true,
+///     "max_retries": 3,
+///     "reprice_ticks": 1
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct GtxRetryConfig {
+    pub enabled: bool,
+    /// 每笔订单最多重发的次数
+    pub max_retries: u32,
+    /// 每次重发往被动方向挪的 tick 数
+    pub reprice_ticks: u32,
+}
+
+impl Default for GtxRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_retries: 3,
+            reprice_ticks: 1,
+        }
+    }
+}
+
+/// 交易所的错误是否因为只做 maker 的订单会立即成交
+pub fn would_cross(code: i64, msg: &str) -> bool {
+    code == FUTURES_GTX_REJECTED
+        || (code == SPOT_ORDER_REJECTED && msg.contains("immediately match"))
+}
+
+/// 拆开 WS-API 的错误 `code msg`，没有错误码时为 0
+pub fn split_wsapi_error(error: &str) -> (i64, &str) {
+    match error.split_once(' ') {
+        Some((code, msg)) => (code.parse().unwrap_or_default(), msg),
+        None => (0, error),
+    }
+}
+
+#[derive(Debug, Default)]
+struct RetryBook {
+    config: GtxRetryConfig,
+    /// (session_id, order_id) -> 已重发的次数
+    attempts: HashMap<(u16, u32), u32>,
+    /// 等待主循环重发的订单
+    pending: Vec<(SocketAddr, BinanceOrder)>,
+}
+
+/// 下单任务与主循环共享
+#[derive(Debug, Clone, Default)]
+pub struct GtxRetries {
+    book: Arc<Mutex<RetryBook>>,
+}
+
+impl GtxRetries {
+    pub fn new(config: GtxRetryConfig) -> Self {
+        Self {
+            book: Arc::new(Mutex::new(RetryBook {
+                config,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn with_book<R>(&self, f: impl FnOnce(&mut RetryBook) -> R) -> R {
+        let mut book = self.book.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut book)
+    }
+
+    /// 交易所拒绝了 `order`，返回 true 时已按更被动的价格排队重发，拒绝不需要推送给策略
+    pub fn on_reject(
+        &self,
+        addr: SocketAddr,
+        order: &BinanceOrder,
+        tick: f64,
+        code: i64,
+        msg: &str,
+    ) -> bool {
+        self.with_book(|book| {
+            let key = (order.session_id, order.id);
+            let config = &book.config;
+            if !config.enabled
+                || !post_only::is_post_only(order)
+                || !would_cross(code, msg)
+                || tick <= 0.0
+            {
+                book.attempts.remove(&key);
+                return false;
+            }
+            let attempts = book.attempts.entry(key).or_default();
+            if *attempts >= config.max_retries {
+                book.attempts.remove(&key);
+                return false;
+            }
+            *attempts += 1;
+            let ticks = config.reprice_ticks.max(1);
+            let price = post_only::step_passive(order.side, order.price, tick, ticks);
+            if price <= 0.0 {
+                book.attempts.remove(&key);
+                return false;
+            }
+            // 存活时间从第一次下单算起，重发时不再设置
+            let order = BinanceOrder {
+                price,
+                ttl_ms: None,
+                ..order.clone()
+            };
+            book.pending.push((addr, order));
+            true
+        })
+    }
+
+    /// 订单回报，挂出或结束后不再重发
+    pub fn on_order(&self, session_id: u16, order_id: u32, state: State) {
+        if !matches!(state, State::REJECTED) {
+            self.with_book(|book| book.attempts.remove(&(session_id, order_id)));
+        }
+    }
+
+    /// 取出等待重发的订单
+    pub fn take(&self) -> Vec<(SocketAddr, BinanceOrder)> {
+        self.with_book(|book| std::mem::take(&mut book.pending))
+    }
+
+    /// 已重发的次数
+    pub fn attempts(&self, session_id: u16, order_id: u32) -> u32 {
+        self.with_book(|book| {
+            book.attempts
+                .get(&(session_id, order_id))
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::{Side, TimeInForce};
+
+    fn order(side: Side, price: f64, tif: TimeInForce) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price,
+            quantity: 1.0,
+            side,
+            tif,
+            session_id: 1,
+            ttl_ms: Some(1000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_gtx_retry() {
+        assert!(would_cross(-5022, ""));
+        assert!(would_cross(
+            -2010,
+            "Order would immediately match and take."
+        ));
+        assert!(!would_cross(-2010, "Account has insufficient balance"));
+        assert_eq!(
+            split_wsapi_error("-1013 Filter failure: PRICE_FILTER"),
+            (-1013, "Filter failure: PRICE_FILTER")
+        );
+
+        let addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let retries = GtxRetries::new(GtxRetryConfig {
+            enabled: true,
+            max_retries: 2,
+            reprice_ticks: 1,
+        });
+        let buy = order(Side::BUY, 100.3, TimeInForce::GTX);
+        assert!(retries.on_reject(addr, &buy, 0.1, -5022, ""));
+        let pending = retries.take();
+        let [(_, retried)] = &pending[..] else {
+            panic!("{:?}", pending);
+        };
+        assert_eq!((retried.price, retried.ttl_ms), (100.2, None));
+        assert!(retries.on_reject(addr, retried, 0.1, -5022, ""));
+        assert_eq!(retries.take()[0].1.price, 100.1);
+        // 重发次数用完后照常拒绝，次数清零
+        assert!(!retries.on_reject(addr, &buy, 0.1, -5022, ""));
+        assert_eq!(retries.attempts(1, 1), 0);
+
+        // 卖单往上挪，挂出后重新计数
+        let sell = order(Side::SELL, 99.9, TimeInForce::GTX);
+        assert!(retries.on_reject(addr, &sell, 0.1, -5022, ""));
+        assert_eq!(retries.take()[0].1.price, 100.0);
+        retries.on_order(1, 1, State::NEW);
+        assert_eq!(retries.attempts(1, 1), 0);
+
+        // 其他原因的拒绝、非只做 maker 的订单与关闭时不重发
+        assert!(!retries.on_reject(addr, &sell, 0.1, -2010, "insufficient balance"));
+        let gtc = order(Side::BUY, 100.3, TimeInForce::GTC);
+        assert!(!retries.on_reject(addr, &gtc, 0.1, -5022, ""));
+        let disabled = GtxRetries::default();
+        assert!(!disabled.on_reject(addr, &buy, 0.1, -5022, ""));
+        assert!(retries.take().is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! 按交易对暂停交易
+//!
+//! 运维在新闻发布等时点用 `halt_trading` 暂停一个交易对：暂停期间网关拒绝所有会话在该交易对上的新订单，
+//! 包括改单、双边报价与钉住订单改价重挂时发出的订单，请求中带 `cancel` 时同时撤销已有的挂单。
+//! `resume_trading` 恢复交易。暂停与恢复都推送 [`STradingStatus`] 给所有连接，登录时推送当前暂停的交易对。
+//! 暂停只保存在内存中，网关重启后所有交易对恢复交易。
+//!
+//! [`STradingStatus`]: cryptoflow::chat::STradingStatus
+
+use cryptoflow::chat::STradingStatus;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+struct Halt {
+    time: i64,
+    reason: Option<String>,
+}
+
+/// handler 与交易共享，handler 暂停与恢复，交易下单前检查
+#[derive(Debug, Clone, Default)]
+pub struct Halts {
+    /// 小写的交易对 -> 暂停
+    halts: Arc<Mutex<BTreeMap<String, Halt>>>,
+}
+
+impl Halts {
+    fn with_halts<R>(&self, f: impl FnOnce(&mut BTreeMap<String, Halt>) -> R) -> R {
+        let mut halts = self.halts.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut halts)
+    }
+
+    /// 暂停交易，已经暂停时只更新原因
+    pub fn halt(&self, symbol: &str, reason: Option<String>, time: i64) -> STradingStatus {
+        let symbol = symbol.to_lowercase();
+        self.with_halts(|halts| {
+            let halt = halts
+                .entry(symbol.clone())
+                .or_insert(Halt { time, reason: None });
+            halt.reason = reason.clone();
+            STradingStatus {
+                time: halt.time,
+                symbol,
+                trading: false,
+                reason,
+                canceled: 0,
+            }
+        })
+    }
+
+    /// 恢复交易
+    pub fn resume(&self, symbol: &str, reason: Option<String>, time: i64) -> STradingStatus {
+        let symbol = symbol.to_lowercase();
+        self.with_halts(|halts| halts.remove(&symbol));
+        STradingStatus {
+            time,
+            symbol,
+            trading: true,
+            reason,
+            canceled: 0,
+        }
+    }
+
+    /// 交易对暂停时返回拒绝的原因
+    pub fn check(&self, symbol: &str) -> Result<(), String> {
+        self.with_halts(|halts| match halts.get(&symbol.to_lowercase()) {
+            None => Ok(()),
+            Some(Halt {
+                reason: Some(reason),
+                ..
+            }) => Err(format!("trading of {} is halted: {}", symbol, reason)),
+            Some(_) => Err(format!("trading of {} is halted", symbol)),
+        })
+    }
+
+    /// 当前暂停的交易对
+    pub fn statuses(&self) -> Vec<STradingStatus> {
+        self.with_halts(|halts| {
+            halts
+                .iter()
+                .map(|(symbol, halt)| STradingStatus {
+                    time: halt.time,
+                    symbol: symbol.clone(),
+                    trading: false,
+                    reason: halt.reason.clone(),
+                    canceled: 0,
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_halts() {
+        let halts = Halts::default();
+        assert_eq!(halts.check("btcusdt"), Ok(()));
+
+        let status = halts.halt("BTCUSDT", Some("CPI".into()), 1000);
+        assert_eq!((status.symbol.as_str(), status.trading), ("btcusdt", false));
+        assert_eq!(
+            halts.check("BTCUSDT"),
+            Err("trading of BTCUSDT is halted: CPI".into())
+        );
+        assert_eq!(halts.check("ethusdt"), Ok(()));
+
+        // 重复暂停保留最初的时间
+        let status = halts.halt("btcusdt", None, 2000);
+        assert_eq!((status.time, status.reason), (1000, None));
+        halts.halt("ethusdt", None, 3000);
+        let symbols: Vec<_> = halts.statuses().into_iter().map(|s| s.symbol).collect();
+        assert_eq!(symbols, ["btcusdt", "ethusdt"]);
+
+        let status = halts.resume("BTCUSDT", None, 4000);
+        assert!(status.trading);
+        assert_eq!(halts.check("btcusdt"), Ok(()));
+        assert_eq!(halts.statuses().len(), 1);
+    }
+}
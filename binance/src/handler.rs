@@ -1,30 +1,96 @@
+use crate::admin::AdminConfig;
+use crate::depth_check;
+use crate::dom;
+use crate::flatten::{self, Flatten};
+use crate::halt::Halts;
+use crate::heartbeat::Heartbeats;
+use crate::idempotency::IdempotencyCache;
+use crate::margin::{LoanType, SLoan};
 use crate::market::Market;
-use crate::model::order::{BinanceCancel, BinanceOrder};
-use crate::Trade;
+use crate::model::order::{BinanceCancel, BinanceOrder, BinanceQuote};
+use crate::ops_report::OpsReport;
+use crate::params::ParamStore;
+use crate::post_only;
+use crate::state;
+use crate::venue_status::{self, VenueStatus};
+use crate::wire::{ClientSender, Connection};
+use crate::{StreamOptions, Trade};
 use log::*;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 #[cfg(unix)]
 use tokio::signal::unix::{signal, SignalKind};
 #[cfg(windows)]
 use tokio::signal::windows::{ctrl_break, ctrl_c};
 
-use cryptoflow::chat::{SLogin, SPositionReq, SPositionRsp, SRequest};
-use cryptoflow::parser::JsonParser;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use cryptoflow::alert::Alerter;
+use cryptoflow::chat::{
+    SAttributionReq, SAttributionRsp, SBasisReq, SCandlesReq, SDepthSnapshotReq, SDerive, SError,
+    SExportState, SImportState, SLiquidityReq, SLiquidityRsp, SLogin, SOptionChainReq, SOrder,
+    SParamsReq, SPositionReq, SPositionRsp, SRequest, SResume, SRollReq, SSanityCheck, SSetParams,
+    SStreamResult, SSubscription, STradingSwitch, SVolatilityReq, State,
+};
+use cryptoflow::codec::WireFormat;
+use cryptoflow::error_code::{
+    INVALID_STREAM, INVALID_SYMBOL, PERMISSION_DENIED, QUOTA_EXCEEDED, UNDEF_ERROR,
+};
+use cryptoflow::income::SIncomeReq;
+use cryptoflow::my_trades::{SMyTrades, SMyTradesReq};
+use cryptoflow::parser::RequestParser;
+use cryptoflow::portfolio::Portfolio;
+use cryptoflow::tenant::Tenants;
+use std::time::Instant;
 use tokio::time::Duration;
 use tungstenite::Message;
-use websocket::Connection;
+use websocket::BoundedReceiver;
+
+/// 一个 `orders` 请求最多的订单数，与 pyalgo 的 `add_orders` 一致
+pub const MAX_BULK_ORDERS: usize = 255;
+
+fn login_required() -> SError {
+    SError {
+        code: PERMISSION_DENIED,
+        msg: "login required".into(),
+    }
+}
 
 /// 客户端方法枚举
 #[derive(Debug, Clone, Copy)]
 enum ClientMethod {
     Login,
     Subscribe,
+    Unsubscribe,
+    Derive,
+    Resume,
     GetProducts,
+    GetOptionChain,
+    GetVolatility,
+    GetCandles,
+    GetBasis,
+    GetDepthSnapshot,
     GetPositions,
+    GetAttribution,
+    GetLiquidityStats,
+    GetPortfolio,
+    GetIncome,
+    GetMyTrades,
+    GetParams,
+    SetParams,
+    SanityCheck,
+    HaltTrading,
+    ResumeTrading,
+    ExportState,
+    ImportState,
     Order,
+    Orders,
     Cancel,
+    Quote,
+    Replace,
+    Roll,
+    Heartbeat,
+    Borrow,
+    Repay,
 }
 
 impl ClientMethod {
@@ -32,10 +98,37 @@ impl ClientMethod {
         match s {
             "login" => Some(Self::Login),
             "subscribe" => Some(Self::Subscribe),
+            "unsubscribe" => Some(Self::Unsubscribe),
+            "derive" => Some(Self::Derive),
+            "resume" => Some(Self::Resume),
             "get_products" => Some(Self::GetProducts),
+            "get_option_chain" => Some(Self::GetOptionChain),
+            "get_volatility" => Some(Self::GetVolatility),
+            "get_candles" => Some(Self::GetCandles),
+            "get_basis" => Some(Self::GetBasis),
+            "get_depth_snapshot" => Some(Self::GetDepthSnapshot),
             "get_positions" => Some(Self::GetPositions),
+            "get_attribution" => Some(Self::GetAttribution),
+            "get_liquidity_stats" => Some(Self::GetLiquidityStats),
+            "get_portfolio" => Some(Self::GetPortfolio),
+            "get_income" => Some(Self::GetIncome),
+            "get_my_trades" => Some(Self::GetMyTrades),
+            "get_params" => Some(Self::GetParams),
+            "set_params" => Some(Self::SetParams),
+            "sanity_check" => Some(Self::SanityCheck),
+            "halt_trading" => Some(Self::HaltTrading),
+            "resume_trading" => Some(Self::ResumeTrading),
+            "export_state" => Some(Self::ExportState),
+            "import_state" => Some(Self::ImportState),
             "order" => Some(Self::Order),
+            "orders" => Some(Self::Orders),
             "cancel" => Some(Self::Cancel),
+            "quote" => Some(Self::Quote),
+            "replace" => Some(Self::Replace),
+            "roll" => Some(Self::Roll),
+            "heartbeat" => Some(Self::Heartbeat),
+            "borrow" => Some(Self::Borrow),
+            "repay" => Some(Self::Repay),
             _ => None,
         }
     }
@@ -44,16 +137,149 @@ impl ClientMethod {
 pub struct Handler {
     /// Python 策略客户端连接：addr -> (to_client_tx, from_client_rx)
     /// 可以收发消息
-    strategy_client_channels:
-        HashMap<SocketAddr, (UnboundedSender<Message>, UnboundedReceiver<Message>)>,
+    strategy_client_channels: HashMap<SocketAddr, (ClientSender, BoundedReceiver<Message>)>,
     keep_running: bool,
+    alerter: Alerter,
+    portfolio: Portfolio,
+    idempotency: IdempotencyCache,
+    params: ParamStore,
+    admin: AdminConfig,
+    flatten: Flatten,
+    heartbeats: Heartbeats,
+    tenants: Tenants,
+    halts: Halts,
+    venue_status: VenueStatus,
+    ops_report: OpsReport,
+    /// 交易场所状态变化时自动暂停的交易对，回到 `TRADING` 时恢复
+    venue_halts: HashSet<String>,
+    /// 连接 -> 登录的会话，配置了租户时只能查询本租户的数据
+    logins: HashMap<SocketAddr, u16>,
+    /// 登录了交易的会话 -> 连接，定时平仓的订单通过该连接下单
+    trading_sessions: HashMap<u16, SocketAddr>,
+    /// 上一次输出通道深度的时间
+    channel_report: Instant,
+}
+
+impl Default for Handler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Handler {
     pub fn new() -> Self {
+        Self::with_alerter(Alerter::default())
+    }
+
+    pub fn with_alerter(alerter: Alerter) -> Self {
         Self {
             strategy_client_channels: HashMap::default(),
             keep_running: false,
+            alerter,
+            portfolio: Portfolio::default(),
+            idempotency: IdempotencyCache::default(),
+            params: ParamStore::default(),
+            admin: AdminConfig::default(),
+            flatten: Flatten::default(),
+            heartbeats: Heartbeats::default(),
+            tenants: Tenants::default(),
+            halts: Halts::default(),
+            venue_status: VenueStatus::default(),
+            ops_report: OpsReport::default(),
+            venue_halts: HashSet::default(),
+            logins: HashMap::default(),
+            trading_sessions: HashMap::default(),
+            channel_report: Instant::now(),
+        }
+    }
+
+    pub fn with_portfolio(mut self, portfolio: Portfolio) -> Self {
+        self.portfolio = portfolio;
+        self
+    }
+
+    pub fn with_idempotency(mut self, idempotency: IdempotencyCache) -> Self {
+        self.idempotency = idempotency;
+        self
+    }
+
+    pub fn with_params(mut self, params: ParamStore) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_admin(mut self, config: AdminConfig) -> Self {
+        self.admin = config;
+        self
+    }
+
+    /// 校验运维请求的 token，见 [`crate::admin`]
+    fn authorize_admin(&self, token: &str) -> Result<(), SError> {
+        self.admin.authorize(token)
+    }
+
+    pub fn with_flatten(mut self, flatten: Flatten) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    pub fn with_heartbeats(mut self, heartbeats: Heartbeats) -> Self {
+        self.heartbeats = heartbeats;
+        self
+    }
+
+    pub fn with_tenants(mut self, tenants: Tenants) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    pub fn with_halts(mut self, halts: Halts) -> Self {
+        self.halts = halts;
+        self
+    }
+
+    pub fn with_venue_status(mut self, venue_status: VenueStatus) -> Self {
+        self.venue_status = venue_status;
+        self
+    }
+
+    pub fn with_ops_report(mut self, ops_report: OpsReport) -> Self {
+        self.ops_report = ops_report;
+        self
+    }
+
+    /// 推送给所有连接
+    fn broadcast<S: Serialize>(&self, message: &S) -> anyhow::Result<()> {
+        for (tx, _) in self.strategy_client_channels.values() {
+            tx.reply(message)?;
+        }
+        Ok(())
+    }
+
+    /// 配置了租户时，连接只能查询本租户会话的数据
+    fn visible(&self, addr: &SocketAddr, session_id: u16) -> Result<(), SError> {
+        if !self.tenants.enabled() {
+            return Ok(());
+        }
+        match self.logins.get(addr) {
+            Some(login) if self.tenants.same_tenant(*login, session_id) => Ok(()),
+            Some(login) => Err(SError {
+                code: PERMISSION_DENIED,
+                msg: format!("session {} can not access session {}", login, session_id),
+            }),
+            None => Err(login_required()),
+        }
+    }
+
+    /// 配置了租户时，只有 `account` 租户的连接可以查询账户级别的数据
+    fn account_visible(&self, addr: &SocketAddr) -> Result<(), SError> {
+        match self.logins.get(addr) {
+            _ if !self.tenants.enabled() => Ok(()),
+            Some(login) if self.tenants.account(*login) => Ok(()),
+            _ => Err(SError {
+                code: PERMISSION_DENIED,
+                msg: "account data is not visible to this tenant".into(),
+            }),
         }
     }
 
@@ -61,13 +287,13 @@ impl Handler {
     fn on_strategy_client_connect(&mut self, connection: Connection, market: &mut Market) {
         let (addr, tx, rx) = connection;
         market.handle_strategy_client_connect(&addr, &tx);
-        self.strategy_client_channels.insert(addr.clone(), (tx, rx));
+        self.strategy_client_channels.insert(addr, (tx, rx));
     }
 
     async fn handle_strategy_client_login<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -76,13 +302,34 @@ impl Handler {
             info!("{:?}", req);
 
             let params = &req.params;
+            if let Err(e) = self.tenants.authorize(params) {
+                warn!("Reject login from {}: {}", addr, e.msg);
+                return market.reply_to_strategy_client(addr, req.id, e);
+            }
+            self.logins.insert(*addr, params.session_id);
             if params.trading {
                 match trade.handle_strategy_client_login(addr, &req, tx).await? {
-                    Some(e) => trade.reply(addr, req.id, e)?,
-                    None => {}
+                    Some(e) => {
+                        trade.reply(addr, req.id, e)?;
+                        market.handle_strategy_client_login(addr, &req)?;
+                        return Ok(());
+                    }
+                    None => {
+                        self.trading_sessions.insert(params.session_id, *addr);
+                    }
                 }
             }
             market.handle_strategy_client_login(addr, &req)?;
+            // 登录成功的回复已经以 JSON 进入队列，之后的消息按协商的编码收发
+            if tx.format().is_json() && !params.format.is_json() {
+                tx.set_format(params.format);
+            }
+            self.heartbeats
+                .on_login(addr, params.session_id, params.heartbeat_ms, Instant::now());
+            // 登录后推送当前暂停交易的交易对
+            for status in self.halts.statuses() {
+                tx.reply(&status)?;
+            }
         }
 
         Ok(())
@@ -91,28 +338,95 @@ impl Handler {
     async fn handle_strategy_client_subscribe<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
-        let mut req = parser.decode::<SRequest<Vec<String>>>()?;
-        info!("{:?}", req);
+        let subscriptions = parser.decode::<SRequest<Vec<SSubscription>>>()?;
+        info!("{:?}", subscriptions);
+        let mut req = SRequest {
+            id: subscriptions.id,
+            method: subscriptions.method,
+            params: subscriptions
+                .params
+                .iter()
+                .map(|s| s.stream().clone())
+                .collect(),
+        };
 
-        match trade.handle_strategy_client_subscribe(addr, &mut req) {
-            Some(e) => market.reply_to_strategy_client(addr, req.id, e)?,
-            None => {
-                market
-                    .handle_strategy_client_subscribe(addr, &mut req)
-                    .await?
+        // 只订阅校验通过的流，被拒绝的流在回复中说明原因
+        let mut results = trade.handle_strategy_client_subscribe(addr, &req);
+        for (s, result) in subscriptions.params.iter().zip(results.iter_mut()) {
+            let Some(bucket) = s.bucket() else {
+                continue;
+            };
+            if let (true, Err(msg)) = (result.accepted, dom::validate_bucket(s.stream(), bucket)) {
+                *result = SStreamResult::rejected(
+                    s.stream(),
+                    SError {
+                        code: INVALID_STREAM,
+                        msg,
+                    },
+                );
             }
         }
-        Ok(())
+        let (params, options): (_, Vec<_>) = subscriptions
+            .params
+            .iter()
+            .zip(results.iter())
+            .filter(|(_, result)| result.accepted)
+            .map(|(s, _)| {
+                let options = StreamOptions {
+                    tag: s.tag().cloned(),
+                    bucket: s.bucket(),
+                    raw: s.raw(),
+                };
+                (s.stream().clone(), options)
+            })
+            .unzip();
+        req.params = params;
+        market
+            .handle_strategy_client_subscribe(addr, &mut req, &options, results)
+            .await
+    }
+
+    async fn handle_strategy_client_unsubscribe(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<Vec<String>>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_unsubscribe(addr, &req).await
+    }
+
+    fn handle_strategy_client_derive(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SDerive>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_derive(addr, &req)
+    }
+
+    fn handle_strategy_client_resume(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SResume>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_resume(addr, &req)
     }
 
     fn handle_strategy_client_get_products<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -133,10 +447,67 @@ impl Handler {
         Ok(())
     }
 
+    async fn handle_strategy_client_get_option_chain(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SOptionChainReq>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_option_chain(addr, &req).await
+    }
+
+    fn handle_strategy_client_get_candles(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SCandlesReq>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_get_candles(addr, &req)
+    }
+
+    fn handle_strategy_client_get_volatility(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SVolatilityReq>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_get_volatility(addr, &req)
+    }
+
+    fn handle_strategy_client_get_basis(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SBasisReq>>()?;
+        info!("{:?}", req);
+        market.handle_strategy_client_get_basis(addr, &req)
+    }
+
+    async fn handle_strategy_client_get_depth_snapshot(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SDepthSnapshotReq>>()?;
+        info!("{:?}", req);
+        market
+            .handle_strategy_client_get_depth_snapshot(addr, &req)
+            .await
+    }
+
     fn handle_strategy_client_get_positions<T: Trade>(
         &self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -146,6 +517,9 @@ impl Handler {
         let params = req.params;
         let session_id = params.session_id;
         let symbols = params.symbols;
+        if let Err(e) = self.visible(addr, session_id) {
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
 
         match trade.get_positions(session_id) {
             Some(positions) => {
@@ -156,12 +530,12 @@ impl Handler {
                         positions: params,
                     }
                 } else {
-                    let mut params = Vec::new();
-                    for symbol in symbols.iter() {
-                        if let Some(position) = positions.get(symbol) {
-                            params.push(position.clone());
-                        }
-                    }
+                    // 双向持仓的品种返回多空两腿
+                    let params: Vec<_> = positions
+                        .values()
+                        .filter(|p| symbols.contains(&p.symbol))
+                        .cloned()
+                        .collect();
                     SPositionRsp {
                         session_id,
                         positions: params,
@@ -182,32 +556,509 @@ impl Handler {
         Ok(())
     }
 
+    /// 会话按策略与标签归因的统计，会话不存在时为空
+    fn handle_strategy_client_get_attribution<T: Trade>(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SAttributionReq> = parser.decode()?;
+        info!("{:?}", req);
+
+        let session_id = req.params.session_id;
+        if let Err(e) = self.visible(addr, session_id) {
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        let rsp = SAttributionRsp {
+            session_id,
+            attribution: trade.get_attribution(session_id).unwrap_or_default(),
+        };
+        market.reply_to_strategy_client(addr, req.id, rsp)
+    }
+
+    /// 会话按交易对与自然日的挂单与吃单成交统计
+    fn handle_strategy_client_get_liquidity_stats<T: Trade>(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SLiquidityReq> = parser.decode()?;
+        info!("{:?}", req);
+
+        let session_id = req.params.session_id;
+        if let Err(e) = self.visible(addr, session_id) {
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        let rsp = SLiquidityRsp {
+            session_id,
+            stats: trade.get_liquidity_stats(&req.params),
+        };
+        market.reply_to_strategy_client(addr, req.id, rsp)
+    }
+
+    /// 组合敞口快照，所有会话共享；配置了租户时只包含本租户的会话
+    fn handle_strategy_client_get_portfolio(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<serde_json::Value> = parser.decode()?;
+        info!("{:?}", req);
+
+        if !self.tenants.enabled() {
+            return market.reply_to_strategy_client(addr, req.id, self.portfolio.snapshot());
+        }
+        match self.logins.get(addr) {
+            Some(login) => {
+                let snapshot = self.portfolio.snapshot_for(*login);
+                market.reply_to_strategy_client(addr, req.id, snapshot)
+            }
+            None => market.reply_to_strategy_client(addr, req.id, login_required()),
+        }
+    }
+
+    /// 回复会话的参数，之后该连接会收到参数的变化
+    fn handle_strategy_client_get_params(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SParamsReq> = parser.decode()?;
+        info!("{:?}", req);
+        if let Err(e) = self.visible(addr, req.params.session_id) {
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        self.params.watch(*addr, req.params.session_id);
+        market.reply_to_strategy_client(addr, req.id, self.params.get(req.params.session_id))
+    }
+
+    /// 运维修改参数，保存后推送给关注该会话的连接
+    fn handle_strategy_client_set_params(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SSetParams> = parser.decode()?;
+        let SSetParams {
+            session_id,
+            params,
+            token,
+        } = req.params;
+        info!(
+            "Set params of session {} from {}: {:?}",
+            session_id, addr, params
+        );
+        if let Err(e) = self.params.authorize(&token) {
+            warn!("Reject params from {}: {}", addr, e.msg);
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        let (params, watchers) = match self.params.set(session_id, params) {
+            Ok(updated) => updated,
+            Err(e) => {
+                error!("{}", e);
+                let e = SError {
+                    code: UNDEF_ERROR,
+                    msg: e.to_string(),
+                };
+                return market.reply_to_strategy_client(addr, req.id, e);
+            }
+        };
+        for watcher in watchers {
+            if let Some((tx, _)) = self.strategy_client_channels.get(&watcher) {
+                tx.reply(&params)?;
+            }
+        }
+        market.reply_to_strategy_client(addr, req.id, params)
+    }
+
+    /// 运维在开盘前核对本地与交易所的挂单、持仓与余额，见 [`crate::sanity`]
+    async fn handle_strategy_client_sanity_check<T: Trade>(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SSanityCheck> = parser.decode()?;
+        info!(
+            "Sanity check from {}, heal {} adopt session {:?}",
+            addr, req.params.heal, req.params.adopt_session
+        );
+        if let Err(e) = self.authorize_admin(&req.params.token) {
+            warn!("Reject sanity check from {}: {}", addr, e.msg);
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        match trade.sanity_check(&req.params).await {
+            Ok(report) => {
+                if !report.clean {
+                    warn!("Sanity check found differences: {:?}", report);
+                }
+                market.reply_to_strategy_client(addr, req.id, report)
+            }
+            Err(e) => {
+                error!("Sanity check failed: {}", e);
+                let e = SError {
+                    code: UNDEF_ERROR,
+                    msg: e.to_string(),
+                };
+                market.reply_to_strategy_client(addr, req.id, e)
+            }
+        }
+    }
+
+    /// 运维暂停或恢复一个交易对的交易，见 [`crate::halt`]；状态推送给所有连接
+    fn handle_strategy_client_switch_trading<T: Trade>(
+        &mut self,
+        trading: bool,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<STradingSwitch> = parser.decode()?;
+        let STradingSwitch {
+            token,
+            symbol,
+            cancel,
+            reason,
+        } = req.params;
+        info!(
+            "Switch trading of {} to {} from {}, cancel {} reason {:?}",
+            symbol, trading, addr, cancel, reason
+        );
+        if let Err(e) = self.authorize_admin(&token) {
+            warn!("Reject trading switch from {}: {}", addr, e.msg);
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        if !trade.products().contains_key(&symbol.to_lowercase()) {
+            let e = SError {
+                code: INVALID_SYMBOL,
+                msg: format!("unknown symbol {}", symbol),
+            };
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let status = match trading {
+            true => self.halts.resume(&symbol, reason, now),
+            false => {
+                let mut status = self.halts.halt(&symbol, reason, now);
+                if cancel {
+                    status.canceled = trade.cancel_symbol(&symbol);
+                }
+                status
+            }
+        };
+        warn!(
+            "Trading of {} is {}, cancel {} orders",
+            status.symbol,
+            if status.trading { "resumed" } else { "halted" },
+            status.canceled
+        );
+        // 运维恢复后不再按交易场所的状态自动恢复
+        self.venue_halts.remove(&status.symbol);
+        self.broadcast(&status)?;
+        market.reply_to_strategy_client(addr, req.id, status)
+    }
+
+    /// 运维导出网关的状态，用于迁移到另一台主机或升级，见 [`crate::state`]
+    fn handle_strategy_client_export_state<T: Trade>(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SExportState> = parser.decode()?;
+        info!("Export state from {}", addr);
+        if let Err(e) = self.authorize_admin(&req.params.token) {
+            warn!("Reject state export from {}: {}", addr, e.msg);
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        match trade.export_state() {
+            Ok(mut gateway) => {
+                gateway
+                    .counters
+                    .insert(state::FLATTEN.to_string(), self.flatten.next_id());
+                info!(
+                    "Exported {} sessions, counters {:?}",
+                    gateway.sessions.len(),
+                    gateway.counters
+                );
+                market.reply_to_strategy_client(addr, req.id, gateway)
+            }
+            Err(e) => market.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
+    /// 运维导入另一个网关导出的状态，应在策略端登录之前进行
+    async fn handle_strategy_client_import_state<T: Trade>(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SImportState> = parser.decode()?;
+        let SImportState {
+            token,
+            state: gateway,
+        } = req.params;
+        info!(
+            "Import state of {} exported at {} from {}",
+            gateway.venue, gateway.time, addr
+        );
+        if let Err(e) = self.authorize_admin(&token) {
+            warn!("Reject state import from {}: {}", addr, e.msg);
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        match trade.import_state(&gateway).await {
+            Ok(mut report) => {
+                if let Some(next_id) = gateway.counters.get(state::FLATTEN) {
+                    self.flatten.advance_id(*next_id);
+                }
+                report
+                    .counters
+                    .insert(state::FLATTEN.to_string(), self.flatten.next_id());
+                warn!("Imported state: {:?}", report);
+                market.reply_to_strategy_client(addr, req.id, report)
+            }
+            Err(e) => {
+                error!("Import state failed: {}", e.msg);
+                market.reply_to_strategy_client(addr, req.id, e)
+            }
+        }
+    }
+
+    async fn handle_strategy_client_get_income<T: Trade>(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SIncomeReq> = parser.decode()?;
+        info!("{:?}", req);
+        if let Err(e) = self.account_visible(addr) {
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+
+        let incomes = trade.get_income(&req.params).await?;
+        market.reply_to_strategy_client(addr, req.id, incomes)
+    }
+
+    async fn handle_strategy_client_get_my_trades<T: Trade>(
+        &self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req: SRequest<SMyTradesReq> = parser.decode()?;
+        info!("{:?}", req);
+        if let Err(e) = self.account_visible(addr) {
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+
+        match trade.get_my_trades(&req.params).await {
+            Ok(trades) => {
+                let rsp = SMyTrades {
+                    symbol: req.params.symbol.to_lowercase(),
+                    start: req.params.start,
+                    end: req.params.end,
+                    trades,
+                };
+                market.reply_to_strategy_client(addr, req.id, rsp)
+            }
+            Err(e) => market.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
     #[allow(unused)]
     async fn handle_strategy_client_order<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
         let req = parser.decode::<SRequest<BinanceOrder>>()?;
         info!("recv Order {:?}", req);
+        self.place_order(addr, &req.params, market, trade).await
+    }
 
-        trade.add_order(addr, &req.params)
+    /// 批量下单，按请求中的顺序逐笔按 `order` 处理；其中一笔出错时推送这一笔的拒单回报，不影响其余的订单。
+    /// 超过 [`MAX_BULK_ORDERS`] 笔时整批拒绝
+    async fn handle_strategy_client_orders<T: Trade>(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<Vec<BinanceOrder>>>()?;
+        info!("recv Orders {} from {}", req.params.len(), addr);
+        if req.params.len() > MAX_BULK_ORDERS {
+            let e = SError {
+                code: QUOTA_EXCEEDED,
+                msg: format!(
+                    "{} orders, at most {} per batch",
+                    req.params.len(),
+                    MAX_BULK_ORDERS
+                ),
+            };
+            return market.reply_to_strategy_client(addr, req.id, e);
+        }
+        for order in req.params.iter() {
+            info!("recv Order {:?}", order);
+            if let Err(e) = self.place_order(addr, order, market, trade).await {
+                error!("Order {:?}: {}", order, e);
+                self.reject_order(addr, order)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn reject_order(&self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+        let Some((tx, _)) = self.strategy_client_channels.get(addr) else {
+            return Ok(());
+        };
+        let order = SOrder::new(
+            order.id,
+            order.symbol.clone(),
+            order.side,
+            State::REJECTED,
+            order.order_type,
+            order.tif,
+            order.quantity,
+            order.price,
+        );
+        tx.reply(&order)?;
+        Ok(())
+    }
+
+    async fn place_order<T: Trade>(
+        &mut self,
+        addr: &SocketAddr,
+        order: &BinanceOrder,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let key = order.idempotency_key.as_deref();
+        if !self
+            .idempotency
+            .check(order.session_id, "order", key, Instant::now())
+        {
+            warn!("Drop duplicated order {:?} from {}", key, addr);
+            return Ok(());
+        }
+        // 只做 maker 的订单下单前按盘口检查
+        if order.peg.is_some() || post_only::is_post_only(order) {
+            market.track_book_ticker(&order.symbol).await?;
+        }
+        if depth_check::is_candidate(order) {
+            market.track_depth(&order.symbol).await?;
+        }
+        trade.add_order(addr, order)
     }
 
     #[allow(unused)]
     async fn handle_strategy_client_cancel<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
         let req = parser.decode::<SRequest<BinanceCancel>>()?;
         info!("{:?}", req);
 
-        trade.cancel(addr, &req.params)
+        let cancel = &req.params;
+        let key = cancel.idempotency_key.as_deref();
+        if !self
+            .idempotency
+            .check(cancel.session_id, "cancel", key, Instant::now())
+        {
+            warn!("Drop duplicated cancel {:?} from {}", key, addr);
+            return Ok(());
+        }
+        trade.cancel(addr, cancel)
+    }
+
+    fn handle_strategy_client_quote<T: Trade>(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<BinanceQuote>>()?;
+        info!("recv Quote {:?}", req);
+        trade.quote(addr, &req.params)
+    }
+
+    fn handle_strategy_client_replace<T: Trade>(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<BinanceOrder>>()?;
+        info!("recv Replace {:?}", req);
+
+        let order = &req.params;
+        let key = order.idempotency_key.as_deref();
+        if !self
+            .idempotency
+            .check(order.session_id, "replace", key, Instant::now())
+        {
+            warn!("Drop duplicated replace {:?} from {}", key, addr);
+            return Ok(());
+        }
+        trade.replace(addr, order)
+    }
+
+    async fn handle_strategy_client_roll<T: Trade>(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SRollReq>>()?;
+        info!("recv Roll {:?}", req);
+
+        // 挂单按两个合约的中间价平移，先确保订阅了它们的 bookTicker
+        if let Some((from, to)) = trade.roll_contracts(&req.params.alias) {
+            market.track_book_ticker(&from).await?;
+            market.track_book_ticker(&to).await?;
+        }
+        match trade.roll(addr, &req.params) {
+            Ok(rsp) => market.reply_to_strategy_client(addr, req.id, rsp),
+            Err(e) => market.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
+    async fn handle_strategy_client_loan<T: Trade>(
+        &mut self,
+        loan_type: LoanType,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+        market: &mut Market,
+        trade: &mut T,
+    ) -> anyhow::Result<()> {
+        let req = parser.decode::<SRequest<SLoan>>()?;
+        info!("recv {:?} {:?}", loan_type, req);
+
+        match trade.borrow_repay(loan_type, &req.params).await? {
+            Some(e) => market.reply_to_strategy_client(addr, req.id, e),
+            None => market.reply_to_strategy_client(addr, req.id, None::<u8>),
+        }
     }
 
     // 解析来自策略客户端的消息， Parser
@@ -215,13 +1066,20 @@ impl Handler {
         &mut self,
         addr: &SocketAddr,
         msg: &Message,
-    ) -> Option<JsonParser> {
-        let Message::Text(text) = msg else {
-            warn!("Invalid message {} from strategy client {}", msg, addr);
-            return None;
+    ) -> Option<RequestParser> {
+        let (tx, _) = self.strategy_client_channels.get(addr)?;
+        // 登录之前与登录请求本身都是 JSON，之后按协商的编码以二进制帧收发
+        let parsed = match msg {
+            Message::Text(text) => RequestParser::new(text.as_bytes(), WireFormat::Json),
+            Message::Binary(data) if !tx.format().is_json() => {
+                RequestParser::new(data, tx.format())
+            }
+            _ => {
+                warn!("Invalid message {} from strategy client {}", msg, addr);
+                return None;
+            }
         };
-
-        JsonParser::new(text)
+        parsed
             .map_err(|e| {
                 error!("Invalid request {} from {}({})", msg, addr, e);
             })
@@ -232,7 +1090,7 @@ impl Handler {
     async fn dispatch_strategy_client_request<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: JsonParser,
+        parser: RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -248,7 +1106,7 @@ impl Handler {
     fn check_connection_status<T: Trade>(
         &self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -265,14 +1123,11 @@ impl Handler {
     async fn handle_client_method<T: Trade>(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
-        let method = parser
-            .get("method")
-            .and_then(|v| v.as_str())
-            .and_then(ClientMethod::from_str);
+        let method = parser.method().and_then(ClientMethod::from_str);
 
         if let Some(method) = method {
             self.execute_client_method(method, addr, parser, market, trade)
@@ -287,7 +1142,7 @@ impl Handler {
         &mut self,
         method: ClientMethod,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -300,20 +1155,97 @@ impl Handler {
                 self.handle_strategy_client_subscribe(addr, parser, market, trade)
                     .await
             }
+            ClientMethod::Unsubscribe => {
+                self.handle_strategy_client_unsubscribe(addr, parser, market)
+                    .await
+            }
+            ClientMethod::Derive => self.handle_strategy_client_derive(addr, parser, market),
+            ClientMethod::Resume => self.handle_strategy_client_resume(addr, parser, market),
             ClientMethod::GetProducts => {
                 self.handle_strategy_client_get_products(addr, parser, market, trade)
             }
+            ClientMethod::GetOptionChain => {
+                self.handle_strategy_client_get_option_chain(addr, parser, market)
+                    .await
+            }
+            ClientMethod::GetVolatility => {
+                self.handle_strategy_client_get_volatility(addr, parser, market)
+            }
+            ClientMethod::GetCandles => {
+                self.handle_strategy_client_get_candles(addr, parser, market)
+            }
+            ClientMethod::GetBasis => self.handle_strategy_client_get_basis(addr, parser, market),
+            ClientMethod::GetDepthSnapshot => {
+                self.handle_strategy_client_get_depth_snapshot(addr, parser, market)
+                    .await
+            }
             ClientMethod::GetPositions => {
                 self.handle_strategy_client_get_positions(addr, parser, market, trade)
             }
+            ClientMethod::GetAttribution => {
+                self.handle_strategy_client_get_attribution(addr, parser, market, trade)
+            }
+            ClientMethod::GetLiquidityStats => {
+                self.handle_strategy_client_get_liquidity_stats(addr, parser, market, trade)
+            }
+            ClientMethod::GetPortfolio => {
+                self.handle_strategy_client_get_portfolio(addr, parser, market)
+            }
+            ClientMethod::GetIncome => {
+                self.handle_strategy_client_get_income(addr, parser, market, trade)
+                    .await
+            }
+            ClientMethod::GetMyTrades => {
+                self.handle_strategy_client_get_my_trades(addr, parser, market, trade)
+                    .await
+            }
+            ClientMethod::GetParams => self.handle_strategy_client_get_params(addr, parser, market),
+            ClientMethod::SetParams => self.handle_strategy_client_set_params(addr, parser, market),
+            ClientMethod::SanityCheck => {
+                self.handle_strategy_client_sanity_check(addr, parser, market, trade)
+                    .await
+            }
+            ClientMethod::HaltTrading => {
+                self.handle_strategy_client_switch_trading(false, addr, parser, market, trade)
+            }
+            ClientMethod::ResumeTrading => {
+                self.handle_strategy_client_switch_trading(true, addr, parser, market, trade)
+            }
+            ClientMethod::ExportState => {
+                self.handle_strategy_client_export_state(addr, parser, market, trade)
+            }
+            ClientMethod::ImportState => {
+                self.handle_strategy_client_import_state(addr, parser, market, trade)
+                    .await
+            }
             ClientMethod::Order => {
                 self.handle_strategy_client_order(addr, parser, market, trade)
                     .await
             }
+            ClientMethod::Orders => {
+                self.handle_strategy_client_orders(addr, parser, market, trade)
+                    .await
+            }
             ClientMethod::Cancel => {
                 self.handle_strategy_client_cancel(addr, parser, market, trade)
                     .await
             }
+            ClientMethod::Quote => self.handle_strategy_client_quote(addr, parser, trade),
+            ClientMethod::Replace => self.handle_strategy_client_replace(addr, parser, trade),
+            ClientMethod::Roll => {
+                self.handle_strategy_client_roll(addr, parser, market, trade)
+                    .await
+            }
+            // 收到请求时已刷新心跳，不回复
+            ClientMethod::Heartbeat => Ok(()),
+            ClientMethod::Borrow => {
+                self.handle_strategy_client_loan(LoanType::BORROW, addr, parser, market, trade)
+                    .await
+            }
+            ClientMethod::Repay => {
+                self.handle_strategy_client_loan(LoanType::REPAY, addr, parser, market, trade)
+                    .await
+            }
         }
     }
 
@@ -336,7 +1268,7 @@ impl Handler {
             loop {
                 match rx.try_recv() {
                     Ok(msg) => {
-                        batch.push((addr.clone(), Ok(msg), false));
+                        batch.push((*addr, Ok(msg), false));
                         cnt += 1;
                         if cnt >= MAX_CLIENT_MSG_BATCH {
                             break;
@@ -345,7 +1277,7 @@ impl Handler {
                     Err(e) => {
                         // 如果通道被关闭，记录以便后续 prune
                         if rx.is_closed() {
-                            batch.push((addr.clone(), Err(e), true));
+                            batch.push((*addr, Err(e), true));
                         }
                         break;
                     }
@@ -364,6 +1296,7 @@ impl Handler {
                     _ => {
                         // 成功接收，那么解析消息并处理
                         if let Some(req) = self.parse_strategy_client_message(&addr, &msg) {
+                            self.heartbeats.on_request(&addr, Instant::now());
                             if let Err(e) = self
                                 .dispatch_strategy_client_request(&addr, req, market, trade)
                                 .await
@@ -389,7 +1322,7 @@ impl Handler {
     // 2. 处理client的消息，处理后发送给exchange
     pub async fn process<T: Trade>(
         &mut self,
-        mut client_conn_rx: UnboundedReceiver<Connection>,
+        mut client_conn_rx: BoundedReceiver<Connection>,
         market: &mut Market,
         trade: &mut T,
     ) -> anyhow::Result<()> {
@@ -433,8 +1366,37 @@ impl Handler {
                 },
             }
 
+            // 清理交易所超时未响应的请求
+            market.expire_requests();
+            // 按时钟推送收线事件
+            market.emit_bar_closes();
+            // 别名换到新合约后切换用别名订阅的流
+            if let Err(e) = market.switch_aliases().await {
+                error!("{}", e);
+            }
+            // 撤销超过存活时间的订单
+            trade.expire_orders();
+            // 钉住订单跟随盘口改价，被拒绝的只做 maker 订单改价重发，发出排队的订单
+            trade.reprice_orders();
+            // 到点的定时平仓
+            self.flatten_positions(trade);
+            // 心跳超时的会话按配置保护
+            self.guard_heartbeats(trade);
+            // 推送交易场所的状态变化，按配置暂停或恢复交易对
+            if let Err(e) = self.apply_venue_status(trade) {
+                error!("{}", e);
+            }
+
+            // 断线持续超过阈值时告警，恢复时计入运维报告的重连次数
+            let (market_down, trade_down) = (market.disconnected(), trade.disconnected());
+            self.alerter.on_disconnect_state("market", market_down);
+            self.alerter.on_disconnect_state("trade", trade_down);
+            self.ops_report.on_connection("market", market_down);
+            self.ops_report.on_connection("trade", trade_down);
+
             // 每轮 select 后，批量处理各客户端队列中的消息
             self.drain_strategy_client_messages(market, trade).await;
+            self.report_channels();
         }
 
         Ok(())
@@ -447,12 +1409,149 @@ impl Handler {
         trade: &mut T,
     ) -> anyhow::Result<()> {
         self.strategy_client_channels.remove(addr);
+        self.params.remove_client(addr);
+        self.trading_sessions.retain(|_, a| a != addr);
+        self.heartbeats.remove(addr);
+        self.logins.remove(addr);
         market.handle_strategy_client_close(addr).await?;
         trade.handle_strategy_client_close(addr)?;
 
         Ok(())
     }
 
+    /// 按会话当前的持仓发出到点的平仓单，每笔订单记入审计日志
+    fn flatten_positions<T: Trade>(&mut self, trade: &mut T) {
+        for (schedule, session_id) in self.flatten.due(chrono::Utc::now()) {
+            let orders = match trade.get_positions(session_id) {
+                Some(positions) => self.flatten.orders(session_id, positions, trade.products()),
+                None => Vec::new(),
+            };
+            if orders.is_empty() {
+                info!(
+                    target: flatten::AUDIT,
+                    "Flatten {}: session {} has no position", schedule, session_id
+                );
+                continue;
+            }
+            let addr = self
+                .trading_sessions
+                .get(&session_id)
+                .filter(|addr| self.strategy_client_channels.contains_key(addr))
+                .copied();
+            for order in orders {
+                match addr {
+                    _ if self.flatten.dry_run() => flatten::audit(&schedule, &order, "preview"),
+                    Some(addr) => {
+                        flatten::audit(&schedule, &order, "send");
+                        if let Err(e) = trade.add_order(&addr, &order) {
+                            error!(target: flatten::AUDIT, "Flatten {} failed: {}", schedule, e);
+                        }
+                    }
+                    None => flatten::audit(&schedule, &order, "skip, session is not logged in"),
+                }
+            }
+        }
+    }
+
+    /// 心跳超时的会话按配置告警、撤销未完成的订单并平仓，平仓单记入审计日志
+    fn guard_heartbeats<T: Trade>(&mut self, trade: &mut T) {
+        if !self.heartbeats.enabled() {
+            return;
+        }
+        for timeout in self.heartbeats.timeouts(Instant::now()) {
+            warn!("{}", timeout);
+            let config = self.heartbeats.config();
+            let (cancel, flatten) = (config.cancel, config.flatten);
+            if config.alert {
+                self.alerter
+                    .on_heartbeat_timeout(timeout.session_id, timeout.to_string());
+            }
+            // 撤单与平仓只对登录了交易的会话生效
+            if self.trading_sessions.get(&timeout.session_id) != Some(&timeout.addr) {
+                continue;
+            }
+            if cancel {
+                let count = trade.cancel_all(timeout.session_id);
+                warn!(
+                    "Heartbeat of session {} lost, cancel {} orders",
+                    timeout.session_id, count
+                );
+            }
+            if !flatten {
+                continue;
+            }
+            let orders = match trade.get_positions(timeout.session_id) {
+                Some(positions) => {
+                    self.flatten
+                        .orders(timeout.session_id, positions, trade.products())
+                }
+                None => Vec::new(),
+            };
+            for order in orders {
+                flatten::audit("heartbeat", &order, "send");
+                if let Err(e) = trade.add_order(&timeout.addr, &order) {
+                    error!(target: flatten::AUDIT, "Flatten heartbeat failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// 推送交易场所的状态变化；开启 `auto_halt` 时交易对离开 `TRADING` 后暂停交易，回到 `TRADING` 后
+    /// 恢复自动暂停的交易对，见 [`crate::venue_status`]
+    fn apply_venue_status<T: Trade>(&mut self, trade: &mut T) -> anyhow::Result<()> {
+        for changed in self.venue_status.take() {
+            warn!("Venue status changed: {:?}", changed);
+            self.broadcast(&changed)?;
+            let config = self.venue_status.config();
+            let (auto_halt, cancel) = (config.auto_halt, config.cancel);
+            let Some(symbol) = changed.symbol.as_deref().filter(|_| auto_halt) else {
+                continue;
+            };
+            let trading = changed.status == venue_status::TRADING;
+            let status = match trading {
+                false if self.halts.check(symbol).is_ok() => {
+                    let reason = format!("exchange status {}", changed.status);
+                    let mut status = self.halts.halt(symbol, Some(reason), changed.time);
+                    if cancel {
+                        status.canceled = trade.cancel_symbol(symbol);
+                    }
+                    self.venue_halts.insert(status.symbol.clone());
+                    status
+                }
+                true if self.venue_halts.remove(symbol) => {
+                    let reason = format!("exchange status {}", changed.status);
+                    self.halts.resume(symbol, Some(reason), changed.time)
+                }
+                _ => continue,
+            };
+            warn!(
+                "Trading of {} is {} by venue status, cancel {} orders",
+                status.symbol,
+                if status.trading { "resumed" } else { "halted" },
+                status.canceled
+            );
+            self.broadcast(&status)?;
+        }
+        Ok(())
+    }
+
+    /// 每分钟输出一次各策略端通道的深度，有积压或丢弃时告警
+    fn report_channels(&mut self) {
+        if self.channel_report.elapsed() < Duration::from_secs(60) {
+            return;
+        }
+        self.channel_report = Instant::now();
+        for (tx, rx) in self.strategy_client_channels.values() {
+            for gauge in [tx.gauge(), rx.gauge()] {
+                if gauge.dropped > 0 || gauge.depth * 2 >= gauge.capacity {
+                    warn!("Channel {:?}", gauge);
+                } else {
+                    info!("Channel {:?}", gauge);
+                }
+            }
+        }
+    }
+
     pub fn stop(&mut self) {
         info!("Handler stop process");
         self.keep_running = false;
@@ -460,3 +1559,253 @@ impl Handler {
 }
 
 const MAX_CLIENT_MSG_BATCH: usize = 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::symbol::BinanceSymbol;
+    use cryptoflow::chat::*;
+    use cryptoflow::error_code::UNSUPPORTED;
+    use cryptoflow::income::Income;
+    use cryptoflow::my_trades::MyTrade;
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use std::fmt::Debug;
+    use tokio::net::TcpListener;
+    use websocket::{bounded_channel, BoundedSender};
+
+    fn unsupported() -> SError {
+        SError {
+            code: UNSUPPORTED,
+            msg: "unsupported".into(),
+        }
+    }
+
+    /// 记录下单顺序，交易对为 `bad` 的订单出错，其余回报 `NEW`
+    struct Orders {
+        tx: BoundedSender<Message>,
+        placed: Vec<u32>,
+        products: HashMap<String, BinanceSymbol>,
+    }
+
+    impl Trade for Orders {
+        fn disconnected(&self) -> bool {
+            false
+        }
+        fn products(&self) -> &HashMap<String, BinanceSymbol> {
+            &self.products
+        }
+        fn get_positions(&self, _: u16) -> Option<&HashMap<String, Position>> {
+            None
+        }
+        fn get_attribution(&self, _: u16) -> Option<Vec<SAttribution>> {
+            None
+        }
+        fn get_liquidity_stats(&self, _: &SLiquidityReq) -> Vec<SLiquidity> {
+            Vec::new()
+        }
+        async fn get_products(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn sanity_check(&mut self, _: &SSanityCheck) -> anyhow::Result<SSanityReport> {
+            anyhow::bail!("unsupported")
+        }
+        fn export_state(&self) -> Result<SGatewayState, SError> {
+            Err(unsupported())
+        }
+        async fn import_state(&mut self, _: &SGatewayState) -> Result<SImportReport, SError> {
+            Err(unsupported())
+        }
+        async fn get_income(&mut self, _: &SIncomeReq) -> anyhow::Result<Vec<Income>> {
+            Ok(Vec::new())
+        }
+        async fn get_my_trades(&mut self, _: &SMyTradesReq) -> Result<Vec<MyTrade>, SError> {
+            Ok(Vec::new())
+        }
+        async fn borrow_repay(&mut self, _: LoanType, _: &SLoan) -> anyhow::Result<Option<SError>> {
+            Ok(None)
+        }
+        async fn process(&mut self) -> anyhow::Result<bool> {
+            Ok(false)
+        }
+        fn add_order(&mut self, _: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+            if order.symbol == "bad" {
+                anyhow::bail!("invalid symbol");
+            }
+            self.placed.push(order.id);
+            let update = SOrder::new(
+                order.id,
+                order.symbol.clone(),
+                order.side,
+                State::NEW,
+                order.order_type,
+                order.tif,
+                order.quantity,
+                order.price,
+            );
+            self.tx
+                .send(Message::Text(serde_json::to_string(&update)?.into()))?;
+            Ok(())
+        }
+        fn cancel(&mut self, _: &SocketAddr, _: &BinanceCancel) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn quote(&mut self, _: &SocketAddr, _: &BinanceQuote) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn replace(&mut self, _: &SocketAddr, _: &BinanceOrder) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn handle_strategy_client_close(&mut self, _: &SocketAddr) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn cancel_all(&mut self, _: u16) -> usize {
+            0
+        }
+        fn cancel_symbol(&mut self, _: &str) -> usize {
+            0
+        }
+        fn expire_orders(&mut self) {}
+        fn reprice_orders(&mut self) {}
+        fn roll_contracts(&self, _: &str) -> Option<(String, String)> {
+            None
+        }
+        fn roll(&mut self, _: &SocketAddr, _: &SRollReq) -> Result<SRollResult, SError> {
+            Err(unsupported())
+        }
+        async fn handle_strategy_client_login(
+            &mut self,
+            _: &SocketAddr,
+            _: &SRequest<SLogin>,
+            _: &ClientSender,
+        ) -> anyhow::Result<Option<SError>> {
+            Ok(None)
+        }
+        fn handle_strategy_client_subscribe(
+            &mut self,
+            _: &SocketAddr,
+            _: &SRequest<Vec<String>>,
+        ) -> Vec<SStreamResult> {
+            Vec::new()
+        }
+        fn validate_symbol(&self, _: &str, _: &str) -> bool {
+            true
+        }
+        fn handle_strategy_client_disconnect(
+            &mut self,
+            _: &SocketAddr,
+            _: &RequestParser,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn reply<T: Serialize + Debug>(
+            &mut self,
+            _: &SocketAddr,
+            _: i64,
+            _: T,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 本地的行情地址，只接受连接
+    async fn market() -> Market {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                if let Ok(ws) = tokio_tungstenite::accept_async(stream).await {
+                    tokio::spawn(ws.for_each(|_| async {}));
+                }
+            }
+        });
+        Market::connect_to(Some(&url)).await.unwrap()
+    }
+
+    fn orders(id: i64, symbols: &[&str]) -> RequestParser {
+        let params: Vec<_> = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| {
+                json!({"id": i + 1, "symbol": symbol, "price": 60000, "quantity": 0.001,
+                       "side": "BUY", "order_type": "LIMIT", "tif": "GTC", "session_id": 1})
+            })
+            .collect();
+        let request = json!({"id": id, "method": "orders", "params": params}).to_string();
+        RequestParser::new(request.as_bytes(), WireFormat::Json).unwrap()
+    }
+
+    fn received(rx: &mut BoundedReceiver<Message>) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while let Ok(Message::Text(text)) = rx.try_recv() {
+            messages.push(serde_json::from_str(&text).unwrap());
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_bulk_orders() {
+        let mut market = market().await;
+        let mut handler = Handler::new();
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let (tx, mut rx) = bounded_channel("handler -> strategy", &Default::default());
+        let (_, from_strategy) = bounded_channel("strategy -> handler", &Default::default());
+        handler.on_strategy_client_connect((addr, tx.clone().into(), from_strategy), &mut market);
+        let mut trade = Orders {
+            tx,
+            placed: Vec::new(),
+            products: HashMap::new(),
+        };
+
+        // 出错的一笔推送拒单回报，其余照常下单，回报的顺序与请求中的顺序一致
+        let parser = orders(7, &["btcusdt", "ethusdt", "bad", "solusdt"]);
+        handler
+            .handle_strategy_client_orders(&addr, &parser, &mut market, &mut trade)
+            .await
+            .unwrap();
+        assert_eq!(trade.placed, vec![1, 2, 4]);
+        let updates: Vec<_> = received(&mut rx)
+            .iter()
+            .map(|v| {
+                (
+                    v["internal_id"].clone(),
+                    v["symbol"].clone(),
+                    v["state"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            updates,
+            vec![
+                (json!(1), json!("btcusdt"), json!("NEW")),
+                (json!(2), json!("ethusdt"), json!("NEW")),
+                (json!(3), json!("bad"), json!("REJECTED")),
+                (json!(4), json!("solusdt"), json!("NEW")),
+            ]
+        );
+
+        // 超过上限时整批拒绝，一笔也不下
+        trade.placed.clear();
+        let parser = orders(8, &["btcusdt"; MAX_BULK_ORDERS + 1]);
+        handler
+            .handle_strategy_client_orders(&addr, &parser, &mut market, &mut trade)
+            .await
+            .unwrap();
+        assert!(trade.placed.is_empty());
+        let replies = received(&mut rx);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0]["id"], 8);
+        assert_eq!(replies[0]["result"]["code"], QUOTA_EXCEEDED);
+        assert_eq!(
+            replies[0]["result"]["msg"],
+            "256 orders, at most 255 per batch"
+        );
+
+        let parser = orders(9, &["btcusdt"; MAX_BULK_ORDERS]);
+        handler
+            .handle_strategy_client_orders(&addr, &parser, &mut market, &mut trade)
+            .await
+            .unwrap();
+        assert_eq!(trade.placed, (1..=255).collect::<Vec<u32>>());
+    }
+}
@@ -0,0 +1,228 @@
+//! 策略心跳巡检
+//!
+//! `cancel_on_disconnect` 只在连接断开时生效；策略进程卡死而连接仍在时，网关收不到任何请求，会话的挂单与
+//! 持仓无人看管。策略登录时声明 `heartbeat_ms`，之后至少每隔这个时间发送一次请求，任何请求都算，空闲时
+//! 发送 `heartbeat`。开启后超过 `heartbeat_ms * misses` 没有收到请求，网关按配置保护该会话：
+//!
+//! - `cancel`：撤销会话所有未完成的订单
+//! - `alert`：通过告警通道推送
+//! - `flatten`：市价平掉会话的持仓，订单以 `audit` 为 target 记入日志
+//!
+//! 每次超时只处理一次，收到新的请求后恢复巡检。登录时没有声明 `heartbeat_ms` 的会话不受影响，撤单与平仓
+//! 只对登录了交易的会话生效。
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// ```json
+/// "heartbeat": {
+///     "enabled": false,
+///     "misses": 3,
+///     "min_interval_ms": 1000,
+///     "cancel": true,
+///     "alert": true,
+///     "flatten": false
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    pub enabled: bool,
+    /// 连续错过这么多个心跳间隔视为超时
+    pub misses: u32,
+    /// 策略声明的心跳间隔不小于这个值
+    pub min_interval_ms: u64,
+    /// 超时后撤销会话所有未完成的订单
+    pub cancel: bool,
+    /// 超时后告警
+    pub alert: bool,
+    /// 超时后市价平掉会话的持仓
+    pub flatten: bool,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            misses: 3,
+            min_interval_ms: 1000,
+            cancel: true,
+            alert: true,
+            flatten: false,
+        }
+    }
+}
+
+/// 一次心跳超时
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeout {
+    pub addr: SocketAddr,
+    pub session_id: u16,
+    /// 距上一次请求的时间
+    pub silent: Duration,
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Session {} on {} sent nothing for {}ms",
+            self.session_id,
+            self.addr,
+            self.silent.as_millis()
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Beat {
+    session_id: u16,
+    timeout: Duration,
+    last: Instant,
+    /// 已经按超时处理，收到新的请求前不再处理
+    tripped: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Heartbeats {
+    config: HeartbeatConfig,
+    beats: HashMap<SocketAddr, Beat>,
+}
+
+impl Heartbeats {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self {
+            config,
+            beats: HashMap::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &HeartbeatConfig {
+        &self.config
+    }
+
+    /// 登录时开始巡检声明了 `heartbeat_ms` 的连接，重复登录时按最新的声明
+    pub fn on_login(
+        &mut self,
+        addr: &SocketAddr,
+        session_id: u16,
+        heartbeat_ms: Option<u64>,
+        now: Instant,
+    ) {
+        let Some(interval) = heartbeat_ms.filter(|_| self.enabled()) else {
+            self.beats.remove(addr);
+            return;
+        };
+        let interval = interval.max(self.config.min_interval_ms);
+        let timeout = Duration::from_millis(interval * self.config.misses.max(1) as u64);
+        info!(
+            "Watch heartbeat of session {} on {}, timeout {:?}",
+            session_id, addr, timeout
+        );
+        self.beats.insert(
+            *addr,
+            Beat {
+                session_id,
+                timeout,
+                last: now,
+                tripped: false,
+            },
+        );
+    }
+
+    /// 收到连接的请求
+    pub fn on_request(&mut self, addr: &SocketAddr, now: Instant) {
+        if let Some(beat) = self.beats.get_mut(addr) {
+            if beat.tripped {
+                info!("Session {} on {} is alive again", beat.session_id, addr);
+                beat.tripped = false;
+            }
+            beat.last = now;
+        }
+    }
+
+    pub fn remove(&mut self, addr: &SocketAddr) {
+        self.beats.remove(addr);
+    }
+
+    /// 新超时的连接，每次超时只返回一次
+    pub fn timeouts(&mut self, now: Instant) -> Vec<Timeout> {
+        let mut timeouts: Vec<Timeout> = self
+            .beats
+            .iter_mut()
+            .filter(|(_, beat)| !beat.tripped)
+            .filter_map(|(addr, beat)| {
+                let silent = now.saturating_duration_since(beat.last);
+                if silent < beat.timeout {
+                    return None;
+                }
+                beat.tripped = true;
+                Some(Timeout {
+                    addr: *addr,
+                    session_id: beat.session_id,
+                    silent,
+                })
+            })
+            .collect();
+        timeouts.sort_by_key(|t| t.session_id);
+        timeouts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeats() {
+        let mut heartbeats = Heartbeats::new(HeartbeatConfig {
+            enabled: true,
+            misses: 2,
+            min_interval_ms: 100,
+            ..Default::default()
+        });
+        let a: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:1001".parse().unwrap();
+        let c: SocketAddr = "127.0.0.1:1002".parse().unwrap();
+        let t0 = Instant::now();
+        let ms = |ms| t0 + Duration::from_millis(ms);
+        heartbeats.on_login(&a, 1, Some(500), t0);
+        // 过小的间隔按 min_interval_ms 计
+        heartbeats.on_login(&b, 2, Some(10), t0);
+        // 没有声明心跳间隔的会话不巡检
+        heartbeats.on_login(&c, 3, None, t0);
+
+        assert!(heartbeats.timeouts(ms(199)).is_empty());
+        heartbeats.on_request(&a, ms(600));
+        assert_eq!(
+            heartbeats.timeouts(ms(1000)),
+            vec![Timeout {
+                addr: b,
+                session_id: 2,
+                silent: Duration::from_millis(1000)
+            }]
+        );
+        // 每次超时只处理一次，收到请求后恢复巡检
+        assert_eq!(heartbeats.timeouts(ms(1700)).len(), 1);
+        assert!(heartbeats.timeouts(ms(5000)).is_empty());
+        heartbeats.on_request(&a, ms(5000));
+        assert!(heartbeats.timeouts(ms(5999)).is_empty());
+        assert_eq!(heartbeats.timeouts(ms(6000))[0].session_id, 1);
+
+        heartbeats.remove(&b);
+        heartbeats.on_request(&b, ms(6000));
+        assert!(heartbeats.timeouts(ms(10000)).is_empty());
+
+        let mut disabled = Heartbeats::default();
+        disabled.on_login(&a, 1, Some(500), t0);
+        assert!(disabled.timeouts(ms(10000)).is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+//! 行情中心模式
+//!
+//! 开启 `hub.enabled` 的网关只提供行情，不连接账户推送，拒绝交易登录。`streams` 中的流在启动时订阅并
+//! 一直保留，没有策略端订阅时也维护回放缓存、K 线、波动率与深度，下游的策略端订阅后立即可用；整个交易台
+//! 的策略端通过同一个行情中心订阅，减少与交易所之间的连接数。
+
+use crate::margin::{LoanType, SLoan};
+use crate::market::Market;
+use crate::model::order::{BinanceCancel, BinanceOrder, BinanceQuote};
+use crate::model::symbol::BinanceSymbol;
+use crate::wire::ClientSender;
+use crate::{options, Trade};
+use cryptoflow::chat::*;
+use cryptoflow::error_code::*;
+use cryptoflow::income::{Income, SIncomeReq};
+use cryptoflow::my_trades::{MyTrade, SMyTradesReq};
+use cryptoflow::parser::RequestParser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use tracing::{debug, info, warn};
+
+/// ```json
+/// "hub": {
+///     "enabled": true,
+///     "streams": ["btcusdt@depth", "btcusdt@kline:1m", "ethusdt@bbo"]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HubConfig {
+    pub enabled: bool,
+    /// 启动时订阅并一直保留的流，格式与策略端订阅相同
+    pub streams: Vec<String>,
+}
+
+/// `@` 之后的流名称是否可以订阅，各交易场所不同
+pub type StreamCheck = fn(&str) -> bool;
+
+/// 行情中心的交易模块，只校验订阅，拒绝所有交易请求
+pub struct Hub {
+    products: HashMap<String, BinanceSymbol>,
+    check: StreamCheck,
+    txs: HashMap<SocketAddr, ClientSender>,
+}
+
+impl Hub {
+    pub fn new(products: HashMap<String, BinanceSymbol>, check: StreamCheck) -> Self {
+        Self {
+            products,
+            check,
+            txs: HashMap::default(),
+        }
+    }
+
+    /// 按请求中的顺序返回每个流的校验结果
+    pub fn check_streams(&self, streams: &[String]) -> Vec<SStreamResult> {
+        streams
+            .iter()
+            .map(|stream| {
+                let error = |code, kind| {
+                    SStreamResult::rejected(
+                        stream,
+                        SError {
+                            code,
+                            msg: format!("invalid {} {}", kind, stream),
+                        },
+                    )
+                };
+                // 期权的流由行情模块校验
+                if options::exchange_stream(stream).is_some() {
+                    return SStreamResult::accepted(stream);
+                }
+                match stream.to_lowercase().split_once("@") {
+                    Some((name, _)) if !self.products.contains_key(name) => {
+                        error(INVALID_SYMBOL, "symbol")
+                    }
+                    Some((_, kind)) if !(self.check)(kind) => error(INVALID_STREAM, "stream"),
+                    Some(_) => SStreamResult::accepted(stream),
+                    None => error(INVALID_SYMBOL, "symbol"),
+                }
+            })
+            .collect()
+    }
+
+    /// 校验配置的流并在 `market` 上一直保留订阅
+    pub async fn warm(&self, market: &mut Market, streams: &[String]) -> anyhow::Result<()> {
+        for result in self.check_streams(streams) {
+            if let Some(e) = result.error {
+                anyhow::bail!("hub stream {}: {}", result.stream, e.msg);
+            }
+        }
+        info!(
+            "Run as market data hub with {} pinned streams",
+            streams.len()
+        );
+        market.pin_streams(streams).await
+    }
+}
+
+fn not_trading() -> SError {
+    SError {
+        code: UNSUPPORTED,
+        msg: "market data hub does not trade".into(),
+    }
+}
+
+impl Trade for Hub {
+    fn disconnected(&self) -> bool {
+        false
+    }
+
+    fn products(&self) -> &HashMap<String, BinanceSymbol> {
+        &self.products
+    }
+
+    fn get_positions(&self, _session_id: u16) -> Option<&HashMap<String, Position>> {
+        None
+    }
+
+    fn get_attribution(&self, _session_id: u16) -> Option<Vec<SAttribution>> {
+        None
+    }
+
+    fn get_liquidity_stats(&self, _req: &SLiquidityReq) -> Vec<SLiquidity> {
+        Vec::new()
+    }
+
+    async fn get_products(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn sanity_check(&mut self, _req: &SSanityCheck) -> anyhow::Result<SSanityReport> {
+        anyhow::bail!("market data hub does not trade")
+    }
+
+    fn export_state(&self) -> Result<SGatewayState, SError> {
+        Err(not_trading())
+    }
+
+    async fn import_state(&mut self, _state: &SGatewayState) -> Result<SImportReport, SError> {
+        Err(not_trading())
+    }
+
+    async fn get_income(&mut self, _req: &SIncomeReq) -> anyhow::Result<Vec<Income>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_my_trades(&mut self, _req: &SMyTradesReq) -> Result<Vec<MyTrade>, SError> {
+        Err(not_trading())
+    }
+
+    async fn borrow_repay(
+        &mut self,
+        _loan_type: LoanType,
+        _loan: &SLoan,
+    ) -> anyhow::Result<Option<SError>> {
+        Ok(Some(not_trading()))
+    }
+
+    /// 没有账户推送，永远不返回
+    async fn process(&mut self) -> anyhow::Result<bool> {
+        std::future::pending().await
+    }
+
+    fn add_order(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+        warn!("Ignore order from {} on market data hub: {:?}", addr, order);
+        Ok(())
+    }
+
+    fn cancel(&mut self, addr: &SocketAddr, cancel: &BinanceCancel) -> anyhow::Result<()> {
+        warn!(
+            "Ignore cancel from {} on market data hub: {:?}",
+            addr, cancel
+        );
+        Ok(())
+    }
+
+    fn quote(&mut self, addr: &SocketAddr, quote: &BinanceQuote) -> anyhow::Result<()> {
+        warn!("Ignore quote from {} on market data hub: {:?}", addr, quote);
+        Ok(())
+    }
+
+    fn replace(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+        warn!(
+            "Ignore replace from {} on market data hub: {:?}",
+            addr, order
+        );
+        Ok(())
+    }
+
+    fn handle_strategy_client_close(&mut self, addr: &SocketAddr) -> anyhow::Result<()> {
+        self.txs.remove(addr);
+        Ok(())
+    }
+
+    fn cancel_all(&mut self, _session_id: u16) -> usize {
+        0
+    }
+
+    fn cancel_symbol(&mut self, _symbol: &str) -> usize {
+        0
+    }
+
+    fn expire_orders(&mut self) {}
+
+    fn reprice_orders(&mut self) {}
+
+    fn roll_contracts(&self, _alias: &str) -> Option<(String, String)> {
+        None
+    }
+
+    fn roll(&mut self, _addr: &SocketAddr, _req: &SRollReq) -> Result<SRollResult, SError> {
+        Err(not_trading())
+    }
+
+    /// 交易登录总是被拒绝，连接仍然可以订阅行情
+    async fn handle_strategy_client_login(
+        &mut self,
+        addr: &SocketAddr,
+        _req: &SRequest<SLogin>,
+        tx: &ClientSender,
+    ) -> anyhow::Result<Option<SError>> {
+        self.txs.insert(*addr, tx.clone());
+        Ok(Some(not_trading()))
+    }
+
+    fn handle_strategy_client_subscribe(
+        &mut self,
+        _addr: &SocketAddr,
+        req: &SRequest<Vec<String>>,
+    ) -> Vec<SStreamResult> {
+        self.check_streams(&req.params)
+    }
+
+    fn validate_symbol(&self, _symbol: &str, stream: &str) -> bool {
+        (self.check)(stream)
+    }
+
+    fn handle_strategy_client_disconnect(
+        &mut self,
+        addr: &SocketAddr,
+        parser: &RequestParser,
+    ) -> anyhow::Result<()> {
+        if let Some(id) = parser.id() {
+            self.reply(
+                addr,
+                id,
+                SError {
+                    code: DISCONNECTED,
+                    msg: "trade disconnected".into(),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn reply<T: Serialize + Debug>(
+        &mut self,
+        addr: &SocketAddr,
+        id: i64,
+        result: T,
+    ) -> anyhow::Result<()> {
+        if let Some(tx) = self.txs.get_mut(addr) {
+            let response = SResponse { id, result };
+
+            debug!("{:?}", response);
+            tx.reply(&response)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_streams() {
+        let product: BinanceSymbol = serde_json::from_value(serde_json::json!({
+            "symbol": "BTCUSDT",
+            "status": "TRADING",
+            "baseAsset": "BTC",
+            "baseAssetPrecision": 8,
+            "quoteAsset": "USDT",
+            "quotePrecision": 8,
+            "orderTypes": ["LIMIT"],
+            "filters": [],
+        }))
+        .unwrap();
+        let products = HashMap::from([(product.symbol.clone(), product)]);
+        let hub = Hub::new(products, |stream| matches!(stream, "depth" | "bbo"));
+
+        let streams: Vec<String> = [
+            "btcusdt@depth",
+            "BTCUSDT@bbo",
+            "btcusdt@trade",
+            "ethusdt@depth",
+            "btcusdt",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let results = hub.check_streams(&streams);
+        let accepted: Vec<_> = results.iter().map(|r| r.accepted).collect();
+        assert_eq!(accepted, vec![true, true, false, false, false]);
+        assert_eq!(results[2].error.as_ref().unwrap().code, INVALID_STREAM);
+        assert_eq!(results[3].error.as_ref().unwrap().code, INVALID_SYMBOL);
+        assert!(hub.validate_symbol("btcusdt", "bbo"));
+    }
+}
@@ -0,0 +1,111 @@
+//! 下单与撤单的幂等键
+//!
+//! 策略端可以在 order / cancel 请求中附带 `idempotency_key`，同一会话在时间窗口内
+//! 重复发送同一个键的请求只会转发一次，断线重连后重发请求不会导致重复下单。
+
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// ```json
+/// "idempotency": {
+///     "window_secs": 60
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct IdempotencyConfig {
+    /// 幂等键的保留时间
+    pub window_secs: u64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self { window_secs: 60 }
+    }
+}
+
+/// (session_id, 方法, 幂等键)
+type Key = (u16, &'static str, String);
+
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    window: Duration,
+    seen: HashMap<Key, Instant>,
+    /// 按时间顺序记录，用于过期清理
+    order: VecDeque<(Instant, Key)>,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(IdempotencyConfig::default())
+    }
+}
+
+impl IdempotencyCache {
+    pub fn new(config: IdempotencyConfig) -> Self {
+        Self {
+            window: Duration::from_secs(config.window_secs),
+            seen: HashMap::default(),
+            order: VecDeque::default(),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((time, _)) = self.order.front() {
+            if now.duration_since(*time) < self.window {
+                break;
+            }
+            if let Some((time, key)) = self.order.pop_front() {
+                if self.seen.get(&key) == Some(&time) {
+                    self.seen.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// 首次出现的键返回 true；窗口内重复出现返回 false，请求应被丢弃。没有键的请求总是放行
+    pub fn check(
+        &mut self,
+        session_id: u16,
+        method: &'static str,
+        key: Option<&str>,
+        now: Instant,
+    ) -> bool {
+        let Some(key) = key else {
+            return true;
+        };
+        self.expire(now);
+
+        let key = (session_id, method, key.to_string());
+        if self.seen.contains_key(&key) {
+            return false;
+        }
+        self.seen.insert(key.clone(), now);
+        self.order.push_back((now, key));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idempotency() {
+        let mut cache = IdempotencyCache::new(IdempotencyConfig { window_secs: 10 });
+        let now = Instant::now();
+
+        assert!(cache.check(1, "order", Some("a"), now));
+        assert!(!cache.check(1, "order", Some("a"), now + Duration::from_secs(5)));
+        // 会话、方法不同的键互不影响，没有键的请求不去重
+        assert!(cache.check(2, "order", Some("a"), now));
+        assert!(cache.check(1, "cancel", Some("a"), now));
+        assert!(cache.check(1, "order", None, now));
+        assert!(cache.check(1, "order", None, now));
+
+        // 过了窗口可以再次使用
+        assert!(cache.check(1, "order", Some("a"), now + Duration::from_secs(10)));
+        assert!(!cache.check(1, "order", Some("a"), now + Duration::from_secs(15)));
+    }
+}
@@ -0,0 +1,129 @@
+//! 按会话统计已发往交易所、尚未收到响应的请求
+//!
+//! 策略失控时会在短时间内堆积大量下单请求，全部转发出去会耗尽账户的请求权重。
+//! 每个会话的在途请求达到上限后，新的下单直接拒绝，等已有请求返回后再放行。
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// ```json
+/// "in_flight": {
+///     "max_per_session": 64
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct InFlightConfig {
+    /// 每个会话最多的在途请求数，0 表示不限制
+    pub max_per_session: usize,
+}
+
+impl Default for InFlightConfig {
+    fn default() -> Self {
+        Self {
+            max_per_session: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InFlight {
+    max: usize,
+    /// session_id -> 在途请求数
+    counts: Arc<Mutex<HashMap<u16, usize>>>,
+}
+
+impl InFlight {
+    pub fn new(config: InFlightConfig) -> Self {
+        Self {
+            max: config.max_per_session,
+            counts: Arc::default(),
+        }
+    }
+
+    fn with_counts<R>(&self, f: impl FnOnce(&mut HashMap<u16, usize>) -> R) -> R {
+        let mut counts = self.counts.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut counts)
+    }
+
+    /// 登记一个在途请求，达到上限时返回 None；返回的凭证析构时（收到响应）释放
+    pub fn acquire(&self, session_id: u16) -> Option<InFlightGuard> {
+        self.with_counts(|counts| {
+            let count = counts.entry(session_id).or_default();
+            if self.max > 0 && *count >= self.max {
+                return None;
+            }
+            *count += 1;
+            Some(())
+        })?;
+        Some(self.guard(session_id))
+    }
+
+    /// 登记一个不受上限约束的请求，例如撤单
+    pub fn acquire_unchecked(&self, session_id: u16) -> InFlightGuard {
+        self.with_counts(|counts| *counts.entry(session_id).or_default() += 1);
+        self.guard(session_id)
+    }
+
+    fn guard(&self, session_id: u16) -> InFlightGuard {
+        InFlightGuard {
+            session_id,
+            counts: self.counts.clone(),
+        }
+    }
+
+    pub fn outstanding(&self, session_id: u16) -> usize {
+        self.with_counts(|counts| counts.get(&session_id).copied().unwrap_or_default())
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+#[derive(Debug)]
+pub struct InFlightGuard {
+    session_id: u16,
+    counts: Arc<Mutex<HashMap<u16, usize>>>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap_or_else(|p| p.into_inner());
+        if let Some(count) = counts.get_mut(&self.session_id) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.remove(&self.session_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_flight() {
+        let in_flight = InFlight::new(InFlightConfig { max_per_session: 2 });
+        let first = in_flight.acquire(1).unwrap();
+        let _second = in_flight.acquire(1).unwrap();
+        assert!(in_flight.acquire(1).is_none());
+        // 各会话单独计数
+        assert!(in_flight.acquire(2).is_some());
+
+        // 撤单不受上限约束
+        let cancel = in_flight.acquire_unchecked(1);
+        assert_eq!(in_flight.outstanding(1), 3);
+
+        drop(first);
+        drop(cancel);
+        assert_eq!(in_flight.outstanding(1), 1);
+        assert!(in_flight.acquire(1).is_some());
+
+        let unlimited = InFlight::new(InFlightConfig { max_per_session: 0 });
+        let guards: Vec<_> = (0..100).filter_map(|_| unlimited.acquire(1)).collect();
+        assert_eq!(guards.len(), 100);
+    }
+}
@@ -1,13 +1,71 @@
 pub mod account;
+pub mod admin;
+pub mod apikey;
 pub mod app;
+pub mod attribution;
+pub mod backfill;
+pub mod bar;
+pub mod basis;
+pub mod candles;
+pub mod capability;
+pub mod correlation;
+pub mod credential;
+pub mod dedup;
+pub mod delivery;
+pub mod depth_check;
+pub mod depth_snapshot;
+pub mod derived;
+pub mod dom;
+pub mod dryrun;
 pub mod event_handlers;
+pub mod fat_finger;
+pub mod fee;
+pub mod feed;
+pub mod flatten;
+pub mod flow;
+pub mod gtx_retry;
+pub mod halt;
 pub mod handler;
+pub mod heartbeat;
+pub mod hub;
+pub mod idempotency;
+pub mod inflight;
+pub mod lifecycle;
+pub mod liquidity;
+pub mod margin;
 pub mod market;
 pub mod model;
+pub mod my_trades;
+pub mod ops_report;
+pub mod options;
+pub mod order_caps;
+pub mod order_check;
+pub mod paginate;
+pub mod params;
+pub mod peg;
+pub mod pm;
+pub mod prefetch;
+pub mod post_only;
+pub mod protocol;
+pub mod quota;
+pub mod quote;
+pub mod replace;
+pub mod replay;
+pub mod roll;
 pub mod rest;
+pub mod rules_check;
+pub mod sanity;
 pub mod session;
 pub mod session_manager;
+pub mod state;
+pub mod stream_budget;
+pub mod stream_gateway;
 pub mod subscriber;
+pub mod venue_status;
+pub mod vol;
+pub mod watchdog;
+pub mod wire;
+pub mod wsapi;
 
 pub use account::*;
 pub use app::*;
@@ -18,44 +76,95 @@ use std::future::Future;
 pub use subscriber::*;
 
 use cryptoflow::chat::*;
-use cryptoflow::parser::JsonParser;
+use cryptoflow::income::{Income, SIncomeReq};
+use cryptoflow::my_trades::{MyTrade, SMyTradesReq};
+use cryptoflow::parser::RequestParser;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::net::SocketAddr;
-use tokio::sync::mpsc::UnboundedSender;
-use tungstenite::Message;
 
+use crate::margin::{LoanType, SLoan};
 use crate::model::{
-    order::{BinanceCancel, BinanceOrder},
+    order::{BinanceCancel, BinanceOrder, BinanceQuote},
     symbol::BinanceSymbol,
 };
+use crate::wire::ClientSender;
 
 pub trait Trade {
     fn disconnected(&self) -> bool;
     fn products(&self) -> &HashMap<String, BinanceSymbol>;
     fn get_positions(&self, session_id: u16) -> Option<&HashMap<String, Position>>;
+    /// 会话按策略与标签归因的统计，会话不存在时为 None
+    fn get_attribution(&self, session_id: u16) -> Option<Vec<SAttribution>>;
+    /// 会话按交易对与自然日的挂单与吃单成交统计，见 [`crate::liquidity`]
+    fn get_liquidity_stats(&self, req: &SLiquidityReq) -> Vec<SLiquidity>;
     fn get_products(&mut self) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// 重新查询交易所的挂单、持仓与余额并与本地比较，见 [`crate::sanity`]
+    fn sanity_check(
+        &mut self,
+        req: &SSanityCheck,
+    ) -> impl Future<Output = anyhow::Result<SSanityReport>> + Send;
+    /// 导出会话的持仓、订单归属与订单号计数器，见 [`crate::state`]
+    fn export_state(&self) -> Result<SGatewayState, SError>;
+    /// 导入另一个网关导出的状态，只能在策略端登录之前进行，失败时不恢复任何会话
+    fn import_state(
+        &mut self,
+        state: &SGatewayState,
+    ) -> impl Future<Output = Result<SImportReport, SError>> + Send;
+    /// 查询同步到本地的资金流水，不支持的交易场所返回空
+    fn get_income(
+        &mut self,
+        req: &SIncomeReq,
+    ) -> impl Future<Output = anyhow::Result<Vec<Income>>> + Send;
+    /// 查询账户成交历史，本地库缺少的时间段先从交易所补齐；未开启或参数错误时返回错误
+    fn get_my_trades(
+        &mut self,
+        req: &SMyTradesReq,
+    ) -> impl Future<Output = Result<Vec<MyTrade>, SError>> + Send;
+    /// 杠杆账户借币或还款，不支持的交易场所返回错误
+    fn borrow_repay(
+        &mut self,
+        loan_type: LoanType,
+        loan: &SLoan,
+    ) -> impl Future<Output = anyhow::Result<Option<SError>>> + Send;
     fn process(&mut self) -> impl Future<Output = anyhow::Result<bool>> + Send;
     fn add_order(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()>;
     fn cancel(&mut self, addr: &SocketAddr, cancel: &BinanceCancel) -> anyhow::Result<()>;
+    /// 双边报价，只对变化的一侧撤单重挂
+    fn quote(&mut self, addr: &SocketAddr, quote: &BinanceQuote) -> anyhow::Result<()>;
+    /// 改单，撤单重挂完成前到达的改单只保留最新一笔
+    fn replace(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()>;
     fn handle_strategy_client_close(&mut self, addr: &SocketAddr) -> anyhow::Result<()>;
+    /// 撤销会话所有未完成的订单，返回撤单的笔数
+    fn cancel_all(&mut self, session_id: u16) -> usize;
+    /// 撤销所有会话在 `symbol` 上未完成的订单，返回撤单的笔数
+    fn cancel_symbol(&mut self, symbol: &str) -> usize;
+    /// 撤销超过 ttl_ms 仍未完成的订单
+    fn expire_orders(&mut self);
+    /// 钉住订单偏离盘口超过阈值时撤单重挂，发出挂单数降到上限以下的排队订单
+    fn reprice_orders(&mut self);
+    /// 别名对应的当季与次季合约，不是别名或不支持时为空
+    fn roll_contracts(&self, alias: &str) -> Option<(String, String)>;
+    /// 把会话的挂单与持仓从当季合约移到次季合约，不支持的交易场所返回错误
+    fn roll(&mut self, addr: &SocketAddr, req: &SRollReq) -> Result<SRollResult, SError>;
     fn handle_strategy_client_login(
         &mut self,
         addr: &SocketAddr,
         req: &SRequest<SLogin>,
-        tx: &UnboundedSender<Message>,
+        tx: &ClientSender,
     ) -> impl Future<Output = anyhow::Result<Option<SError>>> + Send;
+    /// 逐个校验订阅的流，按请求中的顺序返回每个流的结果
     fn handle_strategy_client_subscribe(
         &mut self,
         addr: &SocketAddr,
         req: &SRequest<Vec<String>>,
-    ) -> Option<SError>;
+    ) -> Vec<SStreamResult>;
     fn validate_symbol(&self, symbol: &str, stream: &str) -> bool;
     fn handle_strategy_client_disconnect(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
     ) -> anyhow::Result<()>;
     fn reply<T: Serialize + Debug>(
         &mut self,
@@ -68,10 +177,26 @@ pub trait Trade {
 pub trait OrderTrait {
     fn symbol(&self) -> &str;
     fn trd_vol(&self) -> anyhow::Result<f64>;
+    /// 最近一笔成交价格
+    fn trd_prc(&self) -> f64;
     fn commission(&self) -> f64;
     fn net(&self) -> anyhow::Result<f64>;
     fn side(&self) -> Side;
     fn state(&self) -> State;
+    /// 改写推送给策略的状态，例如网关撤销的超时订单
+    fn set_state(&mut self, state: State);
+    /// 交易所的订单 id
+    fn exchange_order_id(&self) -> i64;
+    /// 交易所处理这次更新的时间，与订单 id、状态和成交 id 一起唯一确定一次回报
+    fn update_time(&self) -> i64;
+    /// 成交计入的持仓方向，没有持仓方向的现货为 None
+    fn position_side(&self) -> Option<PositionSide> {
+        None
+    }
+    /// 这次回报中的一笔成交，不是成交回报时为 None
+    fn fill(&self) -> Option<SFill> {
+        None
+    }
 }
 
 // pub trait ListenKey {
@@ -0,0 +1,134 @@
+//! 订单全链路追踪
+//!
+//! 以 client order id（`session_id << 32 | order_id`）作为关联 id，订单从策略请求、网关校验、发往交易所、
+//! 交易所响应，到第一条回报推送给策略，各阶段的日志都在同一个 `order{cid}` span 下。第一条回报推送后
+//! 输出各阶段之间的耗时。
+//!
+//! 开启 `otlp` feature 并配置 `log.otlp` 后，这些 span 会导出到 OpenTelemetry，见 `cryptoflow::OtlpConfig`。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, info_span, Span};
+
+/// 追踪的订单数达到这个数量时清理过期的记录
+const MAX_TIMELINES: usize = 4096;
+/// 超过这么久仍没有回报的记录会被清理
+const TIMELINE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// 网关收到策略的下单请求
+    Request,
+    /// 通过风控与在途检查
+    Validated,
+    /// 发往交易所
+    Sent,
+    /// 交易所响应下单请求
+    Acked,
+    /// 收到交易所回报
+    Report,
+    /// 回报推送给策略
+    Notified,
+}
+
+pub fn correlation_id(session_id: u16, order_id: u32) -> u64 {
+    u64::from(session_id) << 32 | u64::from(order_id)
+}
+
+/// 订单生命周期内所有日志共用的 span
+pub fn order_span(cid: u64) -> Span {
+    info_span!("order", cid)
+}
+
+/// 按到达顺序记录的各阶段时间
+type Timeline = Vec<(Stage, Instant)>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Lifecycle {
+    timelines: Arc<Mutex<HashMap<u64, Timeline>>>,
+}
+
+impl Lifecycle {
+    /// 记录订单到达某个阶段，推送第一条回报后输出各阶段耗时
+    pub fn mark(&self, cid: u64, stage: Stage) {
+        if let Some(breakdown) = self.mark_at(cid, stage, Instant::now()) {
+            order_span(cid).in_scope(|| info!("Order lifecycle {}", format_breakdown(&breakdown)));
+        }
+    }
+
+    /// 返回 Notified 时各阶段相对上一阶段的耗时
+    fn mark_at(&self, cid: u64, stage: Stage, now: Instant) -> Option<Vec<(Stage, Duration)>> {
+        let mut timelines = self.timelines.lock().unwrap_or_else(|p| p.into_inner());
+        if stage == Stage::Request {
+            if timelines.len() >= MAX_TIMELINES {
+                timelines.retain(|_, t| now.duration_since(t[0].1) < TIMELINE_TTL);
+            }
+            // 改单、钉住订单的重挂沿用订单号，从新的请求重新计时
+            timelines.insert(cid, vec![(stage, now)]);
+            return None;
+        }
+
+        let timeline = timelines.get_mut(&cid)?;
+        if timeline.iter().any(|(s, _)| *s == stage) {
+            return None;
+        }
+        timeline.push((stage, now));
+        if stage != Stage::Notified {
+            return None;
+        }
+
+        let timeline = timelines.remove(&cid)?;
+        Some(
+            timeline
+                .windows(2)
+                .map(|w| (w[1].0, w[1].1.duration_since(w[0].1)))
+                .collect(),
+        )
+    }
+}
+
+fn format_breakdown(breakdown: &[(Stage, Duration)]) -> String {
+    let total: Duration = breakdown.iter().map(|(_, d)| *d).sum();
+    let stages: Vec<_> = breakdown
+        .iter()
+        .map(|(stage, d)| format!("{:?} +{}us", stage, d.as_micros()))
+        .collect();
+    format!("{} (total {}us)", stages.join(", "), total.as_micros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle() {
+        let lifecycle = Lifecycle::default();
+        let cid = correlation_id(1, 7);
+        assert_eq!(cid, (1 << 32) | 7);
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+
+        // 没有请求记录的回报不追踪
+        assert!(lifecycle.mark_at(cid, Stage::Notified, now).is_none());
+
+        assert!(lifecycle.mark_at(cid, Stage::Request, at(0)).is_none());
+        lifecycle.mark_at(cid, Stage::Validated, at(1));
+        lifecycle.mark_at(cid, Stage::Sent, at(1));
+        lifecycle.mark_at(cid, Stage::Report, at(9));
+        lifecycle.mark_at(cid, Stage::Acked, at(10));
+        let breakdown = lifecycle.mark_at(cid, Stage::Notified, at(10)).unwrap();
+        assert_eq!(
+            breakdown,
+            vec![
+                (Stage::Validated, Duration::from_millis(1)),
+                (Stage::Sent, Duration::ZERO),
+                (Stage::Report, Duration::from_millis(8)),
+                (Stage::Acked, Duration::from_millis(1)),
+                (Stage::Notified, Duration::ZERO),
+            ]
+        );
+        // 只统计第一条回报
+        assert!(lifecycle.mark_at(cid, Stage::Notified, at(20)).is_none());
+    }
+}
@@ -0,0 +1,198 @@
+//! 挂单与吃单成交统计
+//!
+//! 做市商需要跟踪手续费等级的进度与挂单比例的考核。网关按成交回报中的 maker 标志，把每个会话的成交按
+//! 交易对与自然日分为挂单（maker）与吃单（taker）两类，统计笔数、成交量与成交额，策略端或运维用
+//! `get_liquidity_stats` 查询。
+//!
+//! 自然日按 `utc_offset_hours` 划分，只保留最近 `days` 天。统计只保存在内存中，网关重启后清零；
+//! 逐笔成交连同 maker 标志由 [`cryptoflow::storage`] 保存，可以离线重新计算。
+
+use chrono::{DateTime, NaiveDate, TimeDelta};
+use cryptoflow::chat::{SFill, SLiquidity, SLiquidityReq};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// ```json
+/// "liquidity": {
+///     "days": 30,
+///     "utc_offset_hours": 0
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LiquidityConfig {
+    /// 保留的自然日数，交易所的手续费等级按 30 天的成交额计算
+    pub days: u32,
+    /// 划分自然日的时区，0 为 UTC，8 为北京时间
+    pub utc_offset_hours: i32,
+}
+
+impl Default for LiquidityConfig {
+    fn default() -> Self {
+        Self {
+            days: 30,
+            utc_offset_hours: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Liquidity {
+    config: LiquidityConfig,
+    /// (session_id, 日期, symbol) -> 统计
+    stats: BTreeMap<(u16, NaiveDate, String), SLiquidity>,
+    /// 最近一笔成交的日期，早于它 `days` 天的统计被移除
+    today: Option<NaiveDate>,
+}
+
+impl Liquidity {
+    pub fn new(config: LiquidityConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn on_fill(&mut self, session_id: u16, fill: &SFill) {
+        let Some(date) = self.date(fill.time) else {
+            return;
+        };
+        if self.today.is_none_or(|today| date > today) {
+            self.today = Some(date);
+            let oldest = self.oldest(self.config.days);
+            self.stats.retain(|(_, date, _), _| *date >= oldest);
+        }
+        if date < self.oldest(self.config.days) {
+            return;
+        }
+
+        let stats = self
+            .stats
+            .entry((session_id, date, fill.symbol.to_lowercase()))
+            .or_insert_with(|| SLiquidity {
+                date: date.to_string(),
+                symbol: fill.symbol.to_lowercase(),
+                ..Default::default()
+            });
+        let notional = fill.price * fill.quantity;
+        if fill.maker {
+            stats.maker_fills += 1;
+            stats.maker_volume += fill.quantity;
+            stats.maker_notional += notional;
+        } else {
+            stats.taker_fills += 1;
+            stats.taker_volume += fill.quantity;
+            stats.taker_notional += notional;
+        }
+        let total = stats.maker_notional + stats.taker_notional;
+        if total > 0.0 {
+            stats.maker_ratio = stats.maker_notional / total;
+        }
+    }
+
+    /// 按日期与交易对排序
+    pub fn report(&self, req: &SLiquidityReq) -> Vec<SLiquidity> {
+        let oldest = self.oldest(req.days.unwrap_or(self.config.days));
+        let symbol = req.symbol.as_ref().map(|s| s.to_lowercase());
+        self.stats
+            .iter()
+            .filter(|((session_id, date, s), _)| {
+                *session_id == req.session_id
+                    && *date >= oldest
+                    && symbol.as_ref().is_none_or(|symbol| symbol == s)
+            })
+            .map(|(_, stats)| stats.clone())
+            .collect()
+    }
+
+    fn date(&self, time: i64) -> Option<NaiveDate> {
+        let offset = TimeDelta::hours(self.config.utc_offset_hours as i64);
+        Some((DateTime::from_timestamp_millis(time)? + offset).date_naive())
+    }
+
+    /// 包括当天在内最近 `days` 天中最早的一天
+    fn oldest(&self, days: u32) -> NaiveDate {
+        match self.today {
+            Some(today) => today - TimeDelta::days(days.max(1) as i64 - 1),
+            None => NaiveDate::MIN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::Side;
+
+    const DAY: i64 = 24 * 3600 * 1000;
+
+    fn fill(symbol: &str, time: i64, price: f64, quantity: f64, maker: bool) -> SFill {
+        SFill {
+            internal_id: 1,
+            order_id: 1,
+            symbol: symbol.into(),
+            side: Side::BUY,
+            trade_id: 1,
+            time,
+            price,
+            quantity,
+            maker,
+            commission: 0.0,
+            commission_asset: "USDT".into(),
+        }
+    }
+
+    #[test]
+    fn test_liquidity() {
+        let mut liquidity = Liquidity::new(LiquidityConfig {
+            days: 2,
+            utc_offset_hours: 8,
+        });
+        // 2024-01-01 17:00 UTC 为北京时间 1 月 2 日
+        let time = 1704128400000;
+        liquidity.on_fill(1, &fill("BTCUSDT", time - DAY, 100.0, 1.0, true));
+        liquidity.on_fill(1, &fill("BTCUSDT", time, 100.0, 3.0, true));
+        liquidity.on_fill(1, &fill("BTCUSDT", time, 200.0, 1.0, false));
+        liquidity.on_fill(1, &fill("ETHUSDT", time, 10.0, 1.0, false));
+        liquidity.on_fill(2, &fill("BTCUSDT", time, 100.0, 1.0, false));
+
+        let req = SLiquidityReq {
+            session_id: 1,
+            ..Default::default()
+        };
+        let report = liquidity.report(&req);
+        let keys: Vec<_> = report
+            .iter()
+            .map(|s| (s.date.as_str(), s.symbol.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("2024-01-01", "btcusdt"),
+                ("2024-01-02", "btcusdt"),
+                ("2024-01-02", "ethusdt")
+            ]
+        );
+        let btc = &report[1];
+        assert_eq!((btc.maker_fills, btc.taker_fills), (1, 1));
+        assert_eq!((btc.maker_volume, btc.taker_volume), (3.0, 1.0));
+        assert_eq!(btc.maker_ratio, 0.6);
+
+        let req = SLiquidityReq {
+            session_id: 1,
+            symbol: Some("btcusdt".into()),
+            days: Some(1),
+        };
+        assert_eq!(liquidity.report(&req), vec![btc.clone()]);
+
+        // 超过 `days` 的日期被移除，迟到的成交不再计入
+        liquidity.on_fill(1, &fill("BTCUSDT", time + DAY, 100.0, 1.0, true));
+        liquidity.on_fill(1, &fill("BTCUSDT", time - DAY, 100.0, 1.0, true));
+        let req = SLiquidityReq {
+            session_id: 1,
+            ..Default::default()
+        };
+        let dates: Vec<_> = liquidity.report(&req).into_iter().map(|s| s.date).collect();
+        assert_eq!(dates, vec!["2024-01-02", "2024-01-02", "2024-01-03"]);
+    }
+}
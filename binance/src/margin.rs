@@ -0,0 +1,345 @@
+//! 杠杆账户（全仓 / 逐仓）
+//!
+//! 现货网关以 `margin` 启动时下单、撤单走杠杆账户，策略端可以用 `borrow` 与 `repay` 借币还币，
+//! 订单可带 `side_effect_type` 自动借还（MARGIN_BUY / AUTO_REPAY）。
+//! 网关定时查询风险率（marginLevel，总资产 / 总负债），并跟随 `marginLevelStatusChange` 推送更新；
+//! 会增加负债的借币与订单在风险率低于 `min_margin_level` 时被拒绝。
+
+use crate::model::order::BinanceOrder;
+use crate::model::user_data::{MarginLevelStatusChange, UserLiabilityChange};
+use crate::rest::Rest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// ```json
+/// "margin_account": {
+///     "isolated": false,
+///     "side_effect_type": "NO_SIDE_EFFECT",
+///     "min_margin_level": 1.5,
+///     "refresh_secs": 30
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MarginConfig {
+    /// 逐仓杠杆，借还款与下单都按交易对的逐仓账户
+    pub isolated: bool,
+    /// 订单没有指定 `side_effect_type` 时使用
+    pub side_effect_type: SideEffectType,
+    /// 风险率低于该值时拒绝借币与会借币的订单，0 表示不检查
+    pub min_margin_level: f64,
+    /// 查询风险率的间隔
+    pub refresh_secs: u64,
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        Self {
+            isolated: false,
+            side_effect_type: SideEffectType::NO_SIDE_EFFECT,
+            min_margin_level: 1.5,
+            refresh_secs: 30,
+        }
+    }
+}
+
+/// 杠杆下单的借还款方式
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SideEffectType {
+    NO_SIDE_EFFECT,
+    /// 余额不足时自动借币
+    MARGIN_BUY,
+    /// 成交后自动还款
+    AUTO_REPAY,
+    /// 自动借币并在成交后还款
+    AUTO_BORROW_REPAY,
+}
+
+impl SideEffectType {
+    /// 是否可能增加负债
+    pub fn borrows(&self) -> bool {
+        matches!(self, Self::MARGIN_BUY | Self::AUTO_BORROW_REPAY)
+    }
+}
+
+/// 借币或还款
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LoanType {
+    BORROW,
+    REPAY,
+}
+
+/// 策略端的借币 / 还款请求，逐仓时必须带上 `symbol`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SLoan {
+    pub session_id: u16,
+    pub asset: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Levels {
+    /// 全仓风险率
+    cross: Option<f64>,
+    /// 逐仓交易对 -> 风险率
+    isolated: HashMap<String, f64>,
+}
+
+/// 解析全仓 `/sapi/v1/margin/account` 或逐仓 `/sapi/v1/margin/isolated/account` 的响应
+fn parse_levels(text: &str, isolated: bool) -> anyhow::Result<Levels> {
+    let value: Value = serde_json::from_str(text)?;
+    let level = |v: &Value| {
+        v["marginLevel"]
+            .as_str()
+            .and_then(|s| s.parse::<f64>().ok())
+    };
+    let mut levels = Levels::default();
+    if isolated {
+        let assets = value["assets"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+        for asset in assets {
+            if let (Some(symbol), Some(level)) = (asset["symbol"].as_str(), level(asset)) {
+                levels.isolated.insert(symbol.to_lowercase(), level);
+            }
+        }
+    } else {
+        levels.cross =
+            Some(level(&value).ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?);
+    }
+    Ok(levels)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MarginAccount {
+    config: MarginConfig,
+    levels: Arc<Mutex<Levels>>,
+}
+
+impl MarginAccount {
+    pub fn new(config: MarginConfig) -> Self {
+        Self {
+            config,
+            levels: Arc::default(),
+        }
+    }
+
+    pub fn isolated(&self) -> bool {
+        self.config.isolated
+    }
+
+    fn with_levels<R>(&self, f: impl FnOnce(&mut Levels) -> R) -> R {
+        let mut levels = self.levels.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut levels)
+    }
+
+    /// 当前风险率，逐仓按交易对
+    pub fn level(&self, symbol: &str) -> Option<f64> {
+        self.with_levels(|levels| match self.config.isolated {
+            true => levels.isolated.get(&symbol.to_lowercase()).copied(),
+            false => levels.cross,
+        })
+    }
+
+    /// 订单实际使用的借还款方式
+    pub fn side_effect(&self, order: &BinanceOrder) -> SideEffectType {
+        order
+            .side_effect_type
+            .unwrap_or(self.config.side_effect_type)
+    }
+
+    /// 会增加负债时检查风险率，还没有查询到风险率时同样拒绝
+    fn check_level(&self, symbol: &str) -> Result<(), String> {
+        if self.config.min_margin_level <= 0.0 {
+            return Ok(());
+        }
+        match self.level(symbol) {
+            Some(level) if level >= self.config.min_margin_level => Ok(()),
+            Some(level) => Err(format!(
+                "margin level {} below {}",
+                level, self.config.min_margin_level
+            )),
+            None => Err("margin level unknown".into()),
+        }
+    }
+
+    pub fn check_order(&self, order: &BinanceOrder) -> Result<(), String> {
+        match self.side_effect(order).borrows() {
+            true => self.check_level(&order.symbol),
+            false => Ok(()),
+        }
+    }
+
+    /// 杠杆下单在现货参数之外追加的参数
+    pub fn order_params(&self, order: &BinanceOrder) -> Vec<(String, String)> {
+        let mut params = vec![(
+            "sideEffectType".into(),
+            format!("{:?}", self.side_effect(order)),
+        )];
+        if self.config.isolated {
+            params.push(("isIsolated".into(), "TRUE".into()));
+        }
+        params
+    }
+
+    /// 撤单在现货参数之外追加的参数
+    pub fn cancel_params(&self) -> Vec<(String, String)> {
+        match self.config.isolated {
+            true => vec![("isIsolated".into(), "TRUE".into())],
+            false => Vec::new(),
+        }
+    }
+
+    /// `/sapi/v1/margin/borrow-repay` 的参数，借币前检查风险率
+    pub fn loan_params(
+        &self,
+        loan_type: LoanType,
+        loan: &SLoan,
+    ) -> Result<Vec<(String, String)>, String> {
+        if loan.amount <= 0.0 {
+            return Err(format!("invalid amount {}", loan.amount));
+        }
+        let mut params = vec![
+            ("asset".into(), loan.asset.to_uppercase()),
+            ("amount".into(), loan.amount.to_string()),
+            ("type".into(), format!("{:?}", loan_type)),
+        ];
+        match (&loan.symbol, self.config.isolated) {
+            (Some(symbol), true) => {
+                params.push(("isIsolated".into(), "TRUE".into()));
+                params.push(("symbol".into(), symbol.to_uppercase()));
+            }
+            (None, true) => return Err("isolated margin requires symbol".into()),
+            (_, false) => params.push(("isIsolated".into(), "FALSE".into())),
+        }
+        if loan_type == LoanType::BORROW {
+            self.check_level(loan.symbol.as_deref().unwrap_or_default())?;
+        }
+        Ok(params)
+    }
+
+    /// 查询一次风险率
+    pub async fn refresh(&self, rest: &Rest) -> anyhow::Result<()> {
+        let path = match self.config.isolated {
+            true => "/sapi/v1/margin/isolated/account",
+            false => "/sapi/v1/margin/account",
+        };
+        let rsp = rest.get(path, &[], true).await?;
+        let levels = parse_levels(&rsp.text().await?, self.config.isolated)?;
+        self.with_levels(|current| *current = levels);
+        Ok(())
+    }
+
+    /// 按 `refresh_secs` 定时查询风险率
+    pub fn spawn_refresh(&self, rest: Arc<Rest>) {
+        let account = self.clone();
+        let period = Duration::from_secs(self.config.refresh_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = account.refresh(&rest).await {
+                    warn!("Refresh margin level failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 全仓风险率状态变化，进入追加保证金或强平区间时返回 true
+    pub fn on_margin_level_status(&self, change: &MarginLevelStatusChange) -> bool {
+        info!("Margin level {} status {}", change.l, change.s);
+        if let (false, Ok(level)) = (self.config.isolated, change.l.parse::<f64>()) {
+            self.with_levels(|levels| levels.cross = Some(level));
+        }
+        !matches!(change.s.as_str(), "NORMAL" | "EXCESSIVE")
+    }
+
+    pub fn on_liability(&self, change: &UserLiabilityChange) {
+        info!(
+            "Margin liability {} {} principal {} interest {}",
+            change.a, change.t, change.p, change.i
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_margin_account() {
+        let account = MarginAccount::new(MarginConfig::default());
+        let mut order = BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price: 100.0,
+            quantity: 1.0,
+            session_id: 1,
+            side_effect_type: Some(SideEffectType::MARGIN_BUY),
+            ..Default::default()
+        };
+        let loan = SLoan {
+            session_id: 1,
+            asset: "usdt".into(),
+            amount: 10.0,
+            symbol: None,
+        };
+
+        // 还没有查询到风险率，只允许不借币的订单与还款
+        assert!(account.check_order(&order).is_err());
+        assert!(account.loan_params(LoanType::BORROW, &loan).is_err());
+        assert!(account.loan_params(LoanType::REPAY, &loan).is_ok());
+        order.side_effect_type = Some(SideEffectType::AUTO_REPAY);
+        assert!(account.check_order(&order).is_ok());
+        assert_eq!(
+            account.order_params(&order),
+            vec![("sideEffectType".to_string(), "AUTO_REPAY".to_string())]
+        );
+
+        let levels = parse_levels(r#"{"marginLevel":"1.2","totalAssetOfBtc":"1"}"#, false).unwrap();
+        account.with_levels(|current| *current = levels);
+        order.side_effect_type = None;
+        assert!(account.check_order(&order).is_ok());
+        order.side_effect_type = Some(SideEffectType::AUTO_BORROW_REPAY);
+        assert!(account
+            .check_order(&order)
+            .unwrap_err()
+            .contains("below 1.5"));
+
+        let change = MarginLevelStatusChange {
+            E: 0,
+            l: "3.5".into(),
+            s: "NORMAL".into(),
+        };
+        assert!(!account.on_margin_level_status(&change));
+        assert!(account.check_order(&order).is_ok());
+        let params = account.loan_params(LoanType::BORROW, &loan).unwrap();
+        assert!(params.contains(&("type".into(), "BORROW".into())));
+        assert!(params.contains(&("isIsolated".into(), "FALSE".into())));
+
+        // 逐仓按交易对
+        let isolated = MarginAccount::new(MarginConfig {
+            isolated: true,
+            ..Default::default()
+        });
+        let levels = parse_levels(
+            r#"{"assets":[{"symbol":"BTCUSDT","marginLevel":"2.0"},{"symbol":"ETHUSDT","marginLevel":"1.1"}]}"#,
+            true,
+        )
+        .unwrap();
+        isolated.with_levels(|current| *current = levels);
+        assert_eq!(isolated.level("btcusdt"), Some(2.0));
+        assert!(isolated.check_order(&order).is_ok());
+        order.symbol = "ethusdt".into();
+        assert!(isolated.check_order(&order).is_err());
+        assert!(isolated.loan_params(LoanType::REPAY, &loan).is_err());
+        assert_eq!(isolated.cancel_params().len(), 1);
+    }
+}
@@ -1,70 +1,769 @@
+use crate::backfill::{self, BackfillConfig, Gap};
+use crate::bar::{BarClock, BarClockConfig};
+use crate::basis::{self, Basis, BasisConfig};
+use crate::candles::{CandleConfig, Candles};
+use crate::correlation::{RequestIds, RequestKey};
+use crate::depth_check::{DepthBooks, DepthCheckConfig};
+use crate::depth_snapshot::{DepthSnapshotConfig, DepthSnapshots};
+use crate::derived::{self, DerivedStreams};
+use crate::dom;
+use crate::feed::PublicFeed;
+use crate::flow::{self, TradeFlows};
+use crate::model::bookticker::BinanceBookTicker;
+use crate::model::envelope::{self, DecodeStats};
+use crate::model::option::OptionStream;
 use crate::model::quote::BinanceQuote;
 use crate::model::{Event, MarketStream};
-use crate::{Subscriber, Trade};
-use cryptoflow::parser::JsonParser;
+use crate::options::{self, OptionsConfig, OptionsFeed};
+use crate::peg::BookTickers;
+use crate::prefetch::Prefetched;
+use crate::quota::QuotaConfig;
+use crate::replay::{ReplayBuffer, ReplayConfig};
+use crate::roll::ContractAliases;
+use crate::stream_budget::{self, StreamBudget, StreamBudgetConfig};
+use crate::stream_gateway::{
+    GatewayAck, GatewayCommand, GatewayRequest, StreamGateway, StreamProperty,
+};
+use crate::vol::{Volatility, VolatilityConfig};
+use crate::wire::ClientSender;
+use crate::{StreamOptions, Subscriber, Trade};
+use cryptoflow::expr::Program;
+use cryptoflow::fx::FxRates;
+use cryptoflow::parser::RequestParser;
+use cryptoflow::storage::{SnapshotWriter, Storage, StorageConfig};
 use cryptoflow::{chat::*, error_code::*};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::net::SocketAddr;
-use std::{collections::HashMap, fmt::Debug};
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::time::Duration;
-use tracing::{debug, error, info};
-use tungstenite::Message;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 use websocket::{BinanceProtocol, WebsocketClient};
+
+/// ```json
+/// "market": {
+///     "request_timeout_ms": 10000,
+///     "backfill": {},
+///     "replay": {},
+///     "options": {},
+///     "volatility": {},
+///     "candles": {},
+///     "depth_snapshot": {},
+///     "basis": {},
+///     "stream_budget": {}
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MarketConfig {
+    /// 交易所超过该时间未响应的请求按超时处理
+    pub request_timeout_ms: u64,
+    /// 重连后补齐断线期间的 K 线
+    pub backfill: BackfillConfig,
+    /// 按流编号并缓存转发的行情，供重连的策略端补发
+    pub replay: ReplayConfig,
+    /// 期权行情，见 `with_options`
+    pub options: OptionsConfig,
+    /// 按收线 K 线估计波动率，见 [`crate::vol`]
+    pub volatility: VolatilityConfig,
+    /// 缓存 K 线供看板查询，见 [`crate::candles`]
+    pub candles: CandleConfig,
+    /// 代理交易所的深度快照，见 [`crate::depth_snapshot`]
+    pub depth_snapshot: DepthSnapshotConfig,
+    /// 永续合约与现货的基差，见 `with_basis`
+    pub basis: BasisConfig,
+    /// 每条连接的订阅上限与连接池，见 [`crate::stream_budget`]
+    pub stream_budget: StreamBudgetConfig,
+    /// 每个策略端的订阅与派生流上限，见 [`crate::quota`]
+    pub quota: QuotaConfig,
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout_ms: 10000,
+            backfill: BackfillConfig::default(),
+            replay: ReplayConfig::default(),
+            options: OptionsConfig::default(),
+            volatility: VolatilityConfig::default(),
+            candles: CandleConfig::default(),
+            depth_snapshot: DepthSnapshotConfig::default(),
+            basis: BasisConfig::default(),
+            stream_budget: StreamBudgetConfig::default(),
+            quota: QuotaConfig::default(),
+        }
+    }
+}
+
+/// 重连失败后的重试间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 后台重连的结果：新的连接，以及按流补齐的 K 线
+struct Reconnected {
+    client: WebsocketClient<BinanceProtocol>,
+    rx: tokio::sync::mpsc::Receiver<Value>,
+    /// 重连时订阅的流，重连期间新增的订阅需要补发
+    streams: Vec<String>,
+    klines: Vec<(String, Vec<SGeneralKline>)>,
+}
+
+/// 建立行情连接并先设置连接属性（combined 模式便于沿用现有解析），`url` 为空时连接交易所
+async fn connect(
+    url: Option<&str>,
+    requests: &[GatewayRequest],
+) -> anyhow::Result<(
+    WebsocketClient<BinanceProtocol>,
+    tokio::sync::mpsc::Receiver<Value>,
+)> {
+    let mut client = WebsocketClient::<BinanceProtocol>::new_public("market");
+    if let Some(url) = url {
+        client.set_url(url);
+    }
+    let rx = client.connect().await?;
+    for request in requests {
+        client
+            .wsapi_call(
+                request.command.method(),
+                request.command.params(),
+                request.id,
+            )
+            .await?;
+    }
+    Ok((client, rx))
+}
+
+/// 重连、恢复订阅并拉取断线期间的 K 线，失败时按间隔重试
+async fn reconnect(
+    url: Option<String>,
+    requests: Vec<GatewayRequest>,
+    streams: Vec<String>,
+    gaps: Vec<Gap>,
+    config: BackfillConfig,
+) -> Reconnected {
+    let (client, rx) = loop {
+        let connected = match connect(url.as_deref(), &requests).await {
+            Ok((client, rx)) if streams.is_empty() => Ok((client, rx)),
+            Ok((client, rx)) => client
+                .wsapi_call("SUBSCRIBE", serde_json::json!(streams), 0)
+                .await
+                .map(|_| (client, rx))
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        match connected {
+            Ok(connected) => break connected,
+            Err(e) => {
+                error!("Reconnect market failed: {}", e);
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+            }
+        }
+    };
+    info!("Market reconnected, {} streams resubscribed", streams.len());
+
+    // 先订阅再补齐，实时行情在通道中排队，补齐的 K 线推送完再处理
+    let mut klines = Vec::new();
+    let http = reqwest::Client::new();
+    for gap in gaps {
+        match backfill::fetch(&http, &config, &gap).await {
+            Ok(bars) => klines.push((gap.stream, bars)),
+            Err(e) => error!("Backfill {} failed: {}", gap.stream, e),
+        }
+    }
+    Reconnected {
+        client,
+        rx,
+        streams,
+        klines,
+    }
+}
+
+/// 交易所响应延迟统计
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub timeouts: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, ms: u64) {
+        self.count += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.total_ms as f64 / self.count as f64
+    }
+}
+
+/// 已发往交易所、等待响应的请求
+#[derive(Debug, Default)]
+struct PendingRequests {
+    timeout: Duration,
+    /// 交易所请求 -> (策略端地址, 发送时间)
+    requests: HashMap<RequestKey, (SocketAddr, Instant)>,
+    stats: LatencyStats,
+    last_sweep: Option<Instant>,
+}
+
+impl PendingRequests {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ..Default::default()
+        }
+    }
+
+    fn insert(&mut self, key: RequestKey, addr: SocketAddr, now: Instant) {
+        self.requests.insert(key, (addr, now));
+    }
+
+    /// 收到响应，记录延迟并返回请求来源
+    fn complete(&mut self, id: i64, now: Instant) -> Option<SocketAddr> {
+        let (addr, sent) = self.requests.remove(&RequestKey::from_wire(id))?;
+        let ms = now.duration_since(sent).as_millis() as u64;
+        debug!("Exchange request {} responded in {}ms", id, ms);
+        self.stats.record(ms);
+        Some(addr)
+    }
+
+    /// 取出所有超时的请求，每秒最多扫描一次
+    fn expire(&mut self, now: Instant) -> Vec<(i64, SocketAddr)> {
+        if self
+            .last_sweep
+            .is_some_and(|t| now.duration_since(t) < Duration::from_secs(1))
+        {
+            return Vec::new();
+        }
+        self.last_sweep = Some(now);
+
+        let timeout = self.timeout;
+        let expired = self.remove_where(|_, sent| now.duration_since(sent) >= timeout);
+        self.stats.timeouts += expired.len() as u64;
+        expired
+    }
+
+    /// 取出已断开的连接上发出的请求，这些请求不会再有应答
+    fn abandon(&mut self, ids: &RequestIds) -> Vec<(i64, SocketAddr)> {
+        self.remove_where(|key, _| !ids.is_current(key))
+    }
+
+    fn remove_where(&mut self, f: impl Fn(&RequestKey, Instant) -> bool) -> Vec<(i64, SocketAddr)> {
+        let mut removed: Vec<_> = self
+            .requests
+            .iter()
+            .filter(|(key, (_, sent))| f(key, *sent))
+            .map(|(key, (addr, _))| (*key, *addr))
+            .collect();
+        removed.sort_unstable_by_key(|(key, _)| *key);
+        for (key, _) in removed.iter() {
+            self.requests.remove(key);
+        }
+        removed
+            .into_iter()
+            .map(|(key, addr)| (key.wire(), addr))
+            .collect()
+    }
+}
+
 pub struct Market {
     /// 给策略端发送消息通道
-    txs: HashMap<SocketAddr, UnboundedSender<Message>>,
+    txs: HashMap<SocketAddr, ClientSender>,
     /// 不同策略端不同的subscriber
     subscribers: HashMap<SocketAddr, Subscriber>,
     symbols: HashMap<String, u16>,
     // Market发送的请求id与策略方地址的映射，每个请求都是由策略发送的
-    requests: HashMap<i64, SocketAddr>,
+    requests: PendingRequests,
     client: WebsocketClient<BinanceProtocol>,
     rx: tokio::sync::mpsc::Receiver<Value>,
     disconnected: bool,
-    id: i64,
+    /// 发往交易所的请求 id，每次重连进入新的一代
+    ids: RequestIds,
+    fx: FxRates,
+    /// 钉住订单使用的最优买卖价
+    book_tickers: BookTickers,
+    /// 已为钉住订单订阅的 bookTicker
+    pegged: HashSet<String>,
+    /// 吃单前检查流动性用的深度，未开启时为空
+    depth_books: Option<DepthBooks>,
+    /// 已为检查流动性订阅的深度
+    depth_tracked: HashSet<String>,
+    bar_clock: BarClock,
+    backfill: BackfillConfig,
+    /// 每条 K 线流补齐的起点
+    resume: HashMap<String, i64>,
+    reconnecting: Option<tokio::sync::oneshot::Receiver<Reconnected>>,
+    derived: DerivedStreams,
+    /// 成交流向的统计
+    flows: TradeFlows,
+    volatility: Volatility,
+    candles: Candles,
+    depth_snapshots: DepthSnapshots,
+    replay: ReplayBuffer,
+    /// 保存转发的行情，见 `with_storage`
+    snapshots: SnapshotWriter,
+    /// 期权行情连接，未开启时为空
+    options: Option<OptionsFeed>,
+    basis: Basis,
+    /// 计算基差用的现货行情连接，未开启时为空
+    spot: Option<PublicFeed>,
+    /// 连接属性与管理请求的确认
+    gateway: StreamGateway,
+    /// 行情连接地址，为空时连接交易所
+    url: Option<String>,
+    /// 连续合约别名，由合约交易模块更新
+    aliases: ContractAliases,
+    /// 上次检查别名订阅时别名的版本
+    alias_version: u64,
+    /// 转发原始帧时标明的交易所
+    venue: String,
+    /// 每条连接上的流
+    budget: StreamBudget,
+    /// 主连接订满后另开的行情连接，第 i 个是预算中的连接 i + 1
+    pool: Vec<PublicFeed>,
+    /// 无法解码的推送
+    decode_stats: DecodeStats,
+    quota: QuotaConfig,
 }
 
 impl Market {
     pub async fn new() -> anyhow::Result<Self> {
-        let mut client = WebsocketClient::<BinanceProtocol>::new_public("market");
-        let rx = client.connect().await?;
-        // 开启 combined 模式，便于沿用现有解析
-        client
-            .wsapi_call("SET_PROPERTY", serde_json::json!(["combined", true]), 0)
-            .await?;
+        Self::connect_to(None).await
+    }
+
+    /// 连接到指定的行情地址，如测试中模拟的交易所
+    pub async fn connect_to(url: Option<&str>) -> anyhow::Result<Self> {
+        let mut gateway = StreamGateway::default();
+        let mut ids = RequestIds::default();
+        let (client, rx) = connect(url, &gateway.on_connect(&mut ids, Instant::now())).await?;
 
         Ok(Self {
             txs: HashMap::default(),
             subscribers: HashMap::default(),
             symbols: HashMap::default(),
-            requests: HashMap::default(),
+            requests: PendingRequests::new(Duration::from_millis(
+                MarketConfig::default().request_timeout_ms,
+            )),
             client,
             rx,
             disconnected: false,
-            id: 1,
+            ids,
+            fx: FxRates::default(),
+            book_tickers: BookTickers::default(),
+            pegged: HashSet::default(),
+            depth_books: None,
+            depth_tracked: HashSet::default(),
+            bar_clock: BarClock::default(),
+            backfill: BackfillConfig::default(),
+            resume: HashMap::default(),
+            reconnecting: None,
+            derived: DerivedStreams::default(),
+            flows: TradeFlows::default(),
+            volatility: Volatility::default(),
+            candles: Candles::default(),
+            depth_snapshots: DepthSnapshots::default(),
+            replay: ReplayBuffer::new(&ReplayConfig::default()),
+            snapshots: SnapshotWriter::default(),
+            options: None,
+            basis: Basis::default(),
+            spot: None,
+            gateway,
+            url: url.map(String::from),
+            aliases: ContractAliases::default(),
+            alias_version: 0,
+            venue: "binance".into(),
+            budget: StreamBudget::default(),
+            pool: Vec::new(),
+            decode_stats: DecodeStats::default(),
+            quota: QuotaConfig::default(),
         })
     }
 
+    /// 开启时连接期权行情，期权的流与期权链查询都经过这个连接
+    pub async fn with_options(mut self, config: &OptionsConfig) -> anyhow::Result<Self> {
+        if config.enabled {
+            self.options = Some(OptionsFeed::connect(config.clone()).await?);
+        }
+        Ok(self)
+    }
+
+    /// 开启时连接现货行情，合约网关据此计算 `{symbol}@basis`
+    pub async fn with_basis(mut self, config: &BasisConfig) -> anyhow::Result<Self> {
+        if config.enabled {
+            self.spot = Some(PublicFeed::connect("spot", &config.spot_stream_url).await?);
+            self.basis = Basis::new(config);
+        }
+        Ok(self)
+    }
+
+    /// 设置汇率表，并订阅其中配置的交易对的 bookTicker，这些订阅不随策略端退出而取消
+    pub async fn with_fx(mut self, fx: FxRates) -> anyhow::Result<Self> {
+        let symbols: Vec<_> = fx
+            .symbols()
+            .iter()
+            .map(|symbol| format!("{}@bookTicker", symbol.to_lowercase()))
+            .collect();
+        if !symbols.is_empty() {
+            info!("Subscribe fx {:?}", symbols);
+            for symbol in symbols.iter() {
+                *self.symbols.entry(symbol.clone()).or_default() += 1;
+            }
+            self.subscribe_streams(&symbols).await?;
+        }
+        self.fx = fx;
+        Ok(self)
+    }
+
+    /// 转发原始帧时的 `venue`，如 `binance-usdt`
+    pub fn with_venue(mut self, venue: &str) -> Self {
+        self.venue = venue.to_string();
+        self
+    }
+
+    /// 共享最优买卖价缓存，供钉住订单改价
+    pub fn with_book_tickers(mut self, book_tickers: BookTickers) -> Self {
+        self.book_tickers = book_tickers;
+        self
+    }
+
+    /// 策略端可以用连续合约别名订阅流，别名换到新的合约时跟着切换，见 `switch_aliases`
+    pub fn with_aliases(mut self, aliases: ContractAliases) -> Self {
+        self.alias_version = aliases.version();
+        self.aliases = aliases;
+        self
+    }
+
+    /// 确保订阅了 `symbol` 的 bookTicker，钉住订单与只做 maker 的订单需要的订阅不随策略端退出而取消
+    pub async fn track_book_ticker(&mut self, symbol: &str) -> anyhow::Result<()> {
+        let stream = format!("{}@bookTicker", symbol.to_lowercase());
+        if !self.pegged.insert(stream.clone()) {
+            return Ok(());
+        }
+        let count = self.symbols.entry(stream.clone()).or_default();
+        *count += 1;
+        if *count == 1 {
+            info!("Subscribe {} for pegged and post-only orders", stream);
+            self.subscribe_streams(&[stream]).await?;
+        }
+        Ok(())
+    }
+
+    /// 开启时共享深度，供吃单前检查流动性
+    pub fn with_depth_check(mut self, config: &DepthCheckConfig, books: DepthBooks) -> Self {
+        self.depth_books = config.enabled.then_some(books);
+        self
+    }
+
+    /// 开启了流动性检查时确保订阅了 `symbol` 的 20 档深度，这些订阅不随策略端退出而取消
+    pub async fn track_depth(&mut self, symbol: &str) -> anyhow::Result<()> {
+        if self.depth_books.is_none() {
+            return Ok(());
+        }
+        let stream = format!("{}@depth20", symbol.to_lowercase());
+        if !self.depth_tracked.insert(stream.clone()) {
+            return Ok(());
+        }
+        let count = self.symbols.entry(stream.clone()).or_default();
+        *count += 1;
+        if *count == 1 {
+            info!("Subscribe {} for depth check", stream);
+            self.subscribe_streams(&[stream]).await?;
+        }
+        Ok(())
+    }
+
+    /// 订阅策略端格式的 `streams` 并一直保留，不随策略端退订而退订，见 [`crate::hub`]；不支持期权的流
+    pub async fn pin_streams(&mut self, streams: &[String]) -> anyhow::Result<()> {
+        let mut symbols = Vec::new();
+        for stream in streams {
+            let symbol = exchange_stream(stream);
+            if options::is_option_stream(&symbol) {
+                anyhow::bail!("can not pin option stream {}", stream);
+            }
+            let count = self.symbols.entry(symbol.clone()).or_default();
+            *count += 1;
+            if *count > 1 {
+                continue;
+            }
+            self.bar_clock.track(&symbol);
+            self.flows.track(&symbol);
+            self.basis.track(&symbol);
+            symbols.push(symbol);
+        }
+        if symbols.is_empty() {
+            return Ok(());
+        }
+        info!("Subscribe {:?} pinned by the hub", symbols);
+        let (streams, spot) = basis::split_streams(flow::wire_streams(&symbols));
+        if let Some(feed) = self.spot.as_mut().filter(|_| !spot.is_empty()) {
+            feed.call("SUBSCRIBE", &spot, 0).await?;
+        }
+        self.subscribe_streams(&streams).await
+    }
+
+    pub fn with_config(mut self, config: &MarketConfig) -> Self {
+        self.requests.timeout = Duration::from_millis(config.request_timeout_ms);
+        self.backfill = config.backfill.clone();
+        self.replay = ReplayBuffer::new(&config.replay);
+        self.volatility = Volatility::new(&config.volatility);
+        self.candles = Candles::new(&config.candles);
+        self.depth_snapshots = DepthSnapshots::new(&config.depth_snapshot);
+        self.budget = StreamBudget::new(&config.stream_budget);
+        self.quota = config.quota.clone();
+        self
+    }
+
+    /// 开启 `snapshots` 时把转发给策略端的行情批量写入 `storage`
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>, config: &StorageConfig) -> Self {
+        self.snapshots = SnapshotWriter::new(storage, config);
+        self
+    }
+
+    /// `get_depth_snapshot` 使用启动时预取的快照，需在 `with_config` 之后调用
+    pub fn with_prefetched(mut self, prefetched: Prefetched) -> Self {
+        self.depth_snapshots =
+            std::mem::take(&mut self.depth_snapshots).with_prefetched(prefetched);
+        self
+    }
+
+    /// 按时钟推送订阅周期的收线事件
+    pub fn with_bar_clock(mut self, config: &BarClockConfig) -> Self {
+        self.bar_clock = BarClock::new(config);
+        self
+    }
+
     pub fn disconnected(&self) -> bool {
         self.disconnected
     }
 
+    /// 交易所响应延迟
+    pub fn latency(&self) -> &LatencyStats {
+        &self.requests.stats
+    }
+
+    /// 未知的流与解析失败的推送计数
+    pub fn decode_stats(&self) -> &DecodeStats {
+        &self.decode_stats
+    }
+
+    /// 连接属性的确认状态与最近一次查询到的订阅
+    pub fn gateway(&self) -> &StreamGateway {
+        &self.gateway
+    }
+
+    /// 发送一个管理请求，返回交易所请求 id，确认结果见 `gateway`
+    async fn send_gateway_command(&mut self, command: GatewayCommand) -> anyhow::Result<i64> {
+        let id = self.ids.allocate().wire();
+        let request = self.gateway.request(id, command, Instant::now());
+        self.pace(0).await;
+        self.client
+            .wsapi_call(
+                request.command.method(),
+                request.command.params(),
+                request.id,
+            )
+            .await?;
+        Ok(request.id)
+    }
+
+    /// 设置连接属性，重连后自动重新设置
+    pub async fn set_property(
+        &mut self,
+        property: StreamProperty,
+        value: bool,
+    ) -> anyhow::Result<i64> {
+        self.send_gateway_command(GatewayCommand::SetProperty(property, value))
+            .await
+    }
+
+    pub async fn get_property(&mut self, property: StreamProperty) -> anyhow::Result<i64> {
+        self.send_gateway_command(GatewayCommand::GetProperty(property))
+            .await
+    }
+
+    /// 查询连接上的订阅，结果与网关记录的订阅不一致时补订缺少的流
+    pub async fn list_subscriptions(&mut self) -> anyhow::Result<i64> {
+        self.send_gateway_command(GatewayCommand::ListSubscriptions)
+            .await
+    }
+
+    /// 清理超时未响应的请求，并向发起请求的策略端回复超时错误
+    pub fn expire_requests(&mut self) {
+        for request in self.gateway.expire(Instant::now(), self.requests.timeout) {
+            warn!(
+                "{} {} timed out",
+                request.command.method(),
+                request.command.params()
+            );
+        }
+        for (id, addr) in self.requests.expire(Instant::now()) {
+            warn!("Exchange request {} from {} timed out", id, addr);
+            self.fail_request(id, &addr, TIMEOUT, "exchange request timeout");
+        }
+    }
+
+    /// 向发起请求的策略端回复错误
+    fn fail_request(&mut self, id: i64, addr: &SocketAddr, code: i32, msg: &str) {
+        if let Some(subscriber) = self.subscribers.get_mut(addr) {
+            let response = ErrorResponse {
+                id,
+                result: Error {
+                    code,
+                    msg: msg.into(),
+                },
+            };
+            if let Err(e) = subscriber.on_exchange_error(response) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// 推送到期的收线事件
+    pub fn emit_bar_closes(&mut self) {
+        for (stream, close) in self.bar_clock.due() {
+            let data = match serde_json::to_string(&close) {
+                Ok(data) => data,
+                Err(e) => {
+                    error!("{}", e);
+                    continue;
+                }
+            };
+            self.forward_stream(&stream, &data);
+        }
+    }
+
+    /// 订阅或退订，期权的流发往期权连接，基差的流拆到合约与现货连接上，返回交易所请求 id
+    async fn send_streams(
+        &mut self,
+        addr: &SocketAddr,
+        method: &str,
+        streams: Vec<String>,
+    ) -> anyhow::Result<Vec<i64>> {
+        let (mut streams, spot) = basis::split_streams(streams);
+        if method == "UNSUBSCRIBE" {
+            // 基差用到的 bookTicker 可能仍有其他订阅
+            streams.retain(|s| !self.symbols.contains_key(s) && !self.basis.uses(s));
+        }
+        if let Some(feed) = self.spot.as_mut().filter(|_| !spot.is_empty()) {
+            feed.call(method, &spot, 0).await?;
+        }
+        let (option_streams, streams): (Vec<_>, Vec<_>) = match self.options {
+            Some(_) => streams
+                .into_iter()
+                .partition(|s| options::is_option_stream(s)),
+            None => (Vec::new(), streams),
+        };
+        let mut ids = Vec::new();
+        if let Some(feed) = self.options.as_mut().filter(|_| !option_streams.is_empty()) {
+            let key = self.ids.allocate();
+            feed.call(method, &option_streams, key.wire()).await?;
+            self.requests.insert(key, *addr, Instant::now());
+            ids.push(key.wire());
+        }
+        let batches = match method {
+            "SUBSCRIBE" => {
+                let (batches, rejected) = self.budget.assign(&streams);
+                if !rejected.is_empty() {
+                    error!("Stream budget exhausted, {:?} not subscribed", rejected);
+                }
+                batches
+            }
+            _ => self.budget.release(&streams),
+        };
+        for (connection, streams) in batches {
+            if connection == 0 {
+                ids.push(self.send_to_exchange(addr, method.into(), streams).await?);
+                continue;
+            }
+            let key = self.ids.allocate();
+            self.send_to_pool(connection, method, &streams, key.wire())
+                .await?;
+            self.requests.insert(key, *addr, Instant::now());
+            ids.push(key.wire());
+        }
+        // 没有需要发送的流时仍向交易所发一个请求，由应答回复策略端
+        if ids.is_empty() {
+            ids.push(
+                self.send_to_exchange(addr, method.into(), Vec::<String>::new())
+                    .await?,
+            );
+        }
+        Ok(ids)
+    }
+
+    /// 订阅网关自己使用的流，按预算分到各条连接上，连接全部订满时记录错误
+    async fn subscribe_streams(&mut self, streams: &[String]) -> anyhow::Result<()> {
+        let (batches, rejected) = self.budget.assign(streams);
+        if !rejected.is_empty() {
+            error!("Stream budget exhausted, {:?} not subscribed", rejected);
+        }
+        for (connection, streams) in batches {
+            if connection == 0 {
+                self.pace(0).await;
+                self.client
+                    .wsapi_call("SUBSCRIBE", serde_json::json!(streams), 0)
+                    .await?;
+            } else {
+                self.send_to_pool(connection, "SUBSCRIBE", &streams, 0)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 发往连接池中的第 `connection` 条连接，连接还不存在时先建立
+    async fn send_to_pool(
+        &mut self,
+        connection: usize,
+        method: &str,
+        streams: &[String],
+        id: i64,
+    ) -> anyhow::Result<()> {
+        while self.pool.len() < connection {
+            info!(
+                "Open market connection {} for more streams",
+                self.pool.len() + 1
+            );
+            let url = stream_budget::pool_url(self.client.url());
+            let feed = PublicFeed::connect("market-pool", &url).await?;
+            self.pool.push(feed);
+        }
+        self.pace(connection).await;
+        self.pool[connection - 1].call(method, streams, id).await
+    }
+
+    /// 按 `max_messages_per_sec` 等到可以向 `connection` 发送下一条控制消息
+    async fn pace(&mut self, connection: usize) {
+        let delay = self.budget.schedule(connection, Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
     async fn send_to_exchange<T: Serialize + Debug>(
         &mut self,
         addr: &SocketAddr,
         method: String,
         param: T,
     ) -> anyhow::Result<i64> {
-        let id = self.id;
+        let key = self.ids.allocate();
+        self.pace(0).await;
         self.client
-            .wsapi_call(&method, serde_json::to_value(&param)?, id as i64)
+            .wsapi_call(&method, serde_json::to_value(&param)?, key.wire())
             .await?;
-        self.requests.insert(id, addr.clone());
-        self.id += 1;
-        // 注意，这里返回原本的id
-        Ok(id)
+        self.requests.insert(key, *addr, Instant::now());
+        // 注意，这里返回发往交易所的id
+        Ok(key.wire())
     }
 
     pub fn reply_to_strategy_client<T: Serialize + Debug>(
@@ -74,14 +773,10 @@ impl Market {
         result: T,
     ) -> anyhow::Result<()> {
         if let Some(tx) = self.txs.get_mut(addr) {
-            let response = SResponse {
-                id: id,
-                result: result,
-            };
+            let response = SResponse { id, result };
 
             tracing::info!("response!: {:?}", response);
-            let rsp = Message::Text(serde_json::to_string(&response)?.into());
-            tx.send(rsp)?;
+            tx.reply(&response)?;
         }
         Ok(())
     }
@@ -93,60 +788,229 @@ impl Market {
     }
 
     pub async fn process(&mut self) -> anyhow::Result<bool> {
-        match self.rx.recv().await {
+        if let Some(reconnecting) = self.reconnecting.as_mut() {
+            match reconnecting.await {
+                Ok(reconnected) => self.on_reconnected(reconnected).await?,
+                Err(e) => {
+                    error!("Market reconnect task aborted: {}", e);
+                    self.start_reconnect();
+                }
+            }
+            return Ok(self.disconnected);
+        }
+
+        let value = match (self.options.as_mut(), self.spot.as_mut()) {
+            (None, None) if self.pool.is_empty() => self.rx.recv().await,
+            (options, spot) => tokio::select! {
+                value = self.rx.recv() => value,
+                // 未开启的连接上一直等待
+                value = async {
+                    match options {
+                        Some(feed) => feed.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    self.handle_option_value(value);
+                    return Ok(self.disconnected);
+                }
+                value = async {
+                    match spot {
+                        Some(feed) => feed.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    self.handle_spot_value(value);
+                    return Ok(self.disconnected);
+                }
+                value = recv_pool(&mut self.pool) => {
+                    self.handle_exchange_value(value);
+                    return Ok(self.disconnected);
+                }
+            },
+        };
+        match value {
             Some(value) => {
-                // 直接从 JSON 反序列化 Event
-                match serde_json::from_value::<Event>(value) {
-                    Ok(e) => self.handle_exchange_event(e),
-                    Err(e) => error!("{}", e),
+                if let Some(ack) = self.gateway.on_response(&value) {
+                    self.on_gateway_ack(ack).await?;
+                    return Ok(self.disconnected);
                 }
+                self.handle_exchange_value(value);
             }
             None => {
                 if !self.disconnected {
                     error!("market disconnected");
                     self.disconnected = true
                 }
+                self.start_reconnect();
             }
         }
         Ok(self.disconnected)
     }
 }
 
+impl Market {
+    /// 主连接上应有的交易所流，不含期权连接与连接池上的流
+    fn exchange_streams(&self) -> Vec<String> {
+        let streams: Vec<_> = self
+            .symbols
+            .keys()
+            .filter(|s| !options::is_option_stream(s))
+            .cloned()
+            .collect();
+        let mut streams = basis::split_streams(flow::wire_streams(&streams)).0;
+        streams.retain(|s| self.budget.connection_of(s).unwrap_or(0) == 0);
+        streams
+    }
+
+    /// 在后台重连，重连期间 `process` 只等待重连结果
+    fn start_reconnect(&mut self) {
+        let streams = self.exchange_streams();
+        let gaps = match self.backfill.enabled {
+            true => streams
+                .iter()
+                .filter_map(|stream| {
+                    self.resume.get(stream).map(|from| Gap {
+                        stream: stream.clone(),
+                        from: *from,
+                    })
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+        // 旧连接上未应答的请求不再等待超时
+        self.ids.reconnect();
+        for (id, addr) in self.requests.abandon(&self.ids) {
+            warn!("Exchange request {} from {} lost on disconnect", id, addr);
+            self.fail_request(id, &addr, DISCONNECTED, "market disconnected");
+        }
+        let requests = self.gateway.on_connect(&mut self.ids, Instant::now());
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let config = self.backfill.clone();
+        let url = self.url.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(reconnect(url, requests, streams, gaps, config).await);
+        });
+        self.reconnecting = Some(rx);
+    }
+
+    /// 换上新的连接，按时间顺序推送补齐的 K 线，之后继续处理实时行情
+    async fn on_reconnected(&mut self, reconnected: Reconnected) -> anyhow::Result<()> {
+        self.reconnecting = None;
+        self.client = reconnected.client;
+        self.rx = reconnected.rx;
+        self.disconnected = false;
+
+        for (stream, klines) in reconnected.klines {
+            for kline in klines {
+                self.resume
+                    .insert(stream.clone(), backfill::resume_from(&kline));
+                self.volatility.on_kline(&stream, &kline);
+                self.candles.on_kline(&stream, &kline);
+                let data = serde_json::to_string(&kline)?;
+                self.forward_stream(&stream, &data);
+            }
+        }
+
+        let added: Vec<_> = self
+            .exchange_streams()
+            .into_iter()
+            .filter(|s| !reconnected.streams.contains(s))
+            .collect();
+        if !added.is_empty() {
+            info!("Subscribe {:?} added during reconnect", added);
+            self.client
+                .wsapi_call("SUBSCRIBE", serde_json::json!(added), 0)
+                .await?;
+        }
+        self.list_subscriptions().await?;
+        Ok(())
+    }
+
+    /// 管理请求的确认，订阅查询结果缺少的流重新订阅
+    async fn on_gateway_ack(&mut self, ack: GatewayAck) -> anyhow::Result<()> {
+        match ack {
+            GatewayAck::Property(property, value) => {
+                info!("Market property {} is {}", property.name(), value)
+            }
+            GatewayAck::Subscriptions(streams) => {
+                let missing: Vec<_> = self
+                    .exchange_streams()
+                    .into_iter()
+                    .filter(|s| !streams.contains(s))
+                    .collect();
+                if !missing.is_empty() {
+                    warn!("Resubscribe {:?} missing on the exchange", missing);
+                    self.client
+                        .wsapi_call("SUBSCRIBE", serde_json::json!(missing), 0)
+                        .await?;
+                }
+            }
+            GatewayAck::Rejected(command, msg) => {
+                error!(
+                    "{} {} rejected: {}",
+                    command.method(),
+                    command.params(),
+                    msg
+                )
+            }
+        }
+        Ok(())
+    }
+}
+
 // handler
 impl Market {
     pub async fn handle_strategy_client_close(&mut self, addr: &SocketAddr) -> anyhow::Result<()> {
-        if let Some(_) = self.txs.remove(addr) {
-            let mut unsubscribe = Vec::new();
+        if self.txs.remove(addr).is_some() {
+            self.derived.remove_client(addr);
             let val = self.subscribers.remove(addr);
-            match &val {
+            let unsubscribe = match &val {
                 Some(subscriber) => {
                     info!("Bye subscriber {}", addr);
-                    // unsubscribe
-                    for symbol in subscriber.iter() {
-                        if let Some(cnt) = self.symbols.get_mut(symbol) {
-                            *cnt -= 1;
-                            if *cnt == 0 {
-                                if let Some(_) = self.symbols.remove(symbol) {
-                                    info!("Unsubscribe {}", symbol);
-                                    unsubscribe.push(symbol.replace(":", "_"));
-                                }
-                            }
-                        }
-                    }
+                    let symbols: Vec<_> = subscriber.iter().cloned().collect();
+                    self.release(&symbols)
                 }
-                None => info!("Subscriber({}) isn't login", addr),
-            }
+                None => {
+                    info!("Subscriber({}) isn't login", addr);
+                    Vec::new()
+                }
+            };
 
             if !unsubscribe.is_empty() {
-                self.send_to_exchange(addr, "UNSUBSCRIBE".into(), unsubscribe)
-                    .await?;
+                self.send_streams(addr, "UNSUBSCRIBE", unsubscribe).await?;
             }
         }
         Ok(())
     }
 
+    /// 减少流的订阅计数，返回没有订阅者、需要向交易所退订的流
+    fn release(&mut self, symbols: &[String]) -> Vec<String> {
+        let mut unsubscribe = Vec::new();
+        for symbol in symbols {
+            if let Some(cnt) = self.symbols.get_mut(symbol) {
+                *cnt -= 1;
+                if *cnt == 0 && self.symbols.remove(symbol).is_some() {
+                    info!("Unsubscribe {}", symbol);
+                    self.bar_clock.untrack(symbol);
+                    self.resume.remove(symbol);
+                    self.replay.clear(symbol);
+                    self.volatility.untrack(symbol);
+                    self.candles.untrack(symbol);
+                    self.basis.untrack(symbol);
+                    // 成交流向的流在该交易对的最后一个窗口退订时才退订 aggTrade
+                    if self.flows.is_tracked(symbol) {
+                        unsubscribe.extend(self.flows.untrack(symbol));
+                    } else {
+                        unsubscribe.push(symbol.replace(":", "_"));
+                    }
+                }
+            }
+        }
+        unsubscribe
+    }
+
     fn handle_exchange_error(&mut self, err: ErrorResponse) {
-        if let Some(index) = self.requests.remove(&err.id) {
+        if let Some(index) = self.requests.complete(err.id, Instant::now()) {
             if let Some(subscriber) = self.subscribers.get_mut(&index) {
                 if let Err(e) = subscriber.on_exchange_error(err) {
                     error!("{}", e);
@@ -156,7 +1020,7 @@ impl Market {
     }
 
     fn handle_exchange_success(&mut self, suc: Response<Option<i64>>) {
-        if let Some(index) = self.requests.remove(&suc.id) {
+        if let Some(index) = self.requests.complete(suc.id, Instant::now()) {
             if let Some(subscriber) = self.subscribers.get_mut(&index) {
                 if let Err(e) = subscriber.on_exchange_response(suc) {
                     error!("{}", e);
@@ -165,50 +1029,239 @@ impl Market {
         }
     }
 
+    /// 为一条流的数据编号，转发给订阅了该流的策略端；指定了分档的订阅者收到分档后的深度
+    fn forward_stream(&mut self, stream: &String, data: &str) {
+        let data = match self.replay.push(stream, data, now_ms()) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+        if let Some(offset) = self.replay.last_offset(stream) {
+            self.snapshots.push(stream, offset, &data, now_ms());
+        }
+        let mut buckets = dom::Buckets::default();
+        for subscriber in self.subscribers.values() {
+            if subscriber.wants_normalized(stream) {
+                let sent = match subscriber.bucket(stream) {
+                    Some(bucket) => buckets
+                        .get(&data, bucket)
+                        .and_then(|data| subscriber.forward_stream(stream, data)),
+                    None => subscriber.forward_stream(stream, &data),
+                };
+                if let Err(e) = sent {
+                    error!("{}", e);
+                }
+            }
+        }
+    }
+
+    /// 把交易所的原始帧转发给订阅时带了 `raw` 的策略端，没有这样的订阅者时不序列化
+    fn forward_raw(&self, value: &Value) {
+        let Some(stream) = value.get("stream").and_then(Value::as_str) else {
+            return;
+        };
+        let stream = stream.to_string();
+        let mut frame = None;
+        for subscriber in self.subscribers.values() {
+            if !subscriber.is_subscribed(&stream) || subscriber.raw(&stream) == RawMode::Off {
+                continue;
+            }
+            let frame = frame.get_or_insert_with(|| value.to_string());
+            if let Err(e) = subscriber.forward_raw(&stream, &self.venue, frame) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// 现货连接上的 bookTicker，只用于计算基差
+    fn handle_spot_value(&mut self, value: Value) {
+        if value.get("stream").is_none() {
+            return;
+        }
+        let book = match serde_json::from_value::<BinanceBookTicker>(value) {
+            Ok(book) => book,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+        let bid = book.data.b.parse().unwrap_or_default();
+        let ask = book.data.a.parse().unwrap_or_default();
+        if let Some(basis) = self.basis.on_spot(&book.data.s, bid, ask, now_ms()) {
+            match serde_json::to_string(&basis) {
+                Ok(data) => self.forward_stream(&basis.stream, &data),
+                Err(e) => error!("{}", e),
+            }
+        }
+    }
+
+    /// 主连接与连接池上的推送先按流名称解码，没有流名称的是请求的应答
+    fn handle_exchange_value(&mut self, value: Value) {
+        if value.get("stream").is_none() {
+            match serde_json::from_value::<Event>(value) {
+                Ok(e) => self.handle_exchange_event(e),
+                Err(e) => error!("{}", e),
+            }
+            return;
+        }
+        self.forward_raw(&value);
+        match envelope::decode(value) {
+            Ok(stream) => self.handle_exchange_event(Event::Stream(stream)),
+            Err(e) => self.decode_stats.record(&e),
+        }
+    }
+
+    /// 期权连接上的推送，每个期权的希腊值单独转发
+    fn handle_option_value(&mut self, value: Value) {
+        if value.get("stream").is_none() {
+            match serde_json::from_value::<Event>(value) {
+                Ok(e) => self.handle_exchange_event(e),
+                Err(e) => error!("{}", e),
+            }
+            return;
+        }
+        self.forward_raw(&value);
+        let stream = match serde_json::from_value::<OptionStream>(value) {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+        let s = stream.stream().clone();
+        for greeks in Vec::<SOptionGreeks>::from(stream) {
+            match serde_json::to_string(&greeks) {
+                Ok(data) => self.forward_stream(&s, &data),
+                Err(e) => error!("{}", e),
+            }
+        }
+    }
+
     fn handle_exchange_stream(&mut self, stream: MarketStream) -> anyhow::Result<()> {
+        if let MarketStream::BookTicker(book) = &stream {
+            let bid = book.data.b.parse().unwrap_or_default();
+            let ask = book.data.a.parse().unwrap_or_default();
+            self.fx.on_book_ticker(&book.data.s, bid, ask);
+            self.book_tickers.update(&book.data.s, bid, ask);
+            if let Some(basis) = self.basis.on_perp(&book.data.s, bid, ask, now_ms()) {
+                self.forward_stream(&basis.stream, &serde_json::to_string(&basis)?);
+            }
+        }
+
         let s = match &stream {
             MarketStream::BookTicker(book) => book.stream().clone(),
             MarketStream::Kline(kline) => kline.stream().clone(),
             MarketStream::SpotDepth(depth) => depth.stream().clone(),
             MarketStream::FutureDepth(depth) => depth.stream().clone(),
+            MarketStream::AggTrade(trade) => trade.stream().clone(),
+            MarketStream::MarkPrice(mark) => mark.stream().clone(),
         };
 
+        let derive = self.derived.has_source(&s);
+        let mut derived = Vec::new();
+        let mut volatility = None;
         let data = match stream {
             MarketStream::BookTicker(book) => {
+                if derive {
+                    let quote = |price: &String, quantity: &String| BinanceQuote {
+                        price: price.parse().unwrap_or_default(),
+                        quantity: quantity.parse().unwrap_or_default(),
+                    };
+                    let bids = [quote(&book.data.b, &book.data.B)];
+                    let asks = [quote(&book.data.a, &book.data.A)];
+                    let time = book.data.E.unwrap_or_else(now_ms);
+                    derived = self
+                        .derived
+                        .on_tick(&s, time, |name| derived::depth_var(name, &bids, &asks));
+                }
                 // FIXME: add BookTicker in python
                 serde_json::to_string(&book)?
             }
             MarketStream::Kline(kline) => {
+                let event_time = kline.data.E;
                 let kline: SGeneralKline = kline.into();
+                self.bar_clock.on_kline(&s, event_time, &kline);
+                self.resume.insert(s.clone(), backfill::resume_from(&kline));
+                volatility = self.volatility.on_kline(&s, &kline);
+                self.candles.on_kline(&s, &kline);
+                if derive {
+                    derived = self
+                        .derived
+                        .on_tick(&s, event_time, |name| derived::kline_var(name, &kline));
+                }
                 serde_json::to_string(&kline)?
             }
             MarketStream::SpotDepth(depth) => {
                 let depth: SGeneralDepth<BinanceQuote> = depth.into();
+                if let Some(books) = self.depth_books.as_ref() {
+                    books.update(&depth.symbol, depth.time, &depth.bids, &depth.asks);
+                }
+                if derive {
+                    derived = self.derived.on_tick(&s, depth.time, |name| {
+                        derived::depth_var(name, &depth.bids, &depth.asks)
+                    });
+                }
                 serde_json::to_string(&depth)?
             }
             MarketStream::FutureDepth(depth) => {
                 let depth: SGeneralDepth<BinanceQuote> = depth.into();
+                if let Some(books) = self.depth_books.as_ref() {
+                    books.update(&depth.symbol, depth.time, &depth.bids, &depth.asks);
+                }
+                if derive {
+                    derived = self.derived.on_tick(&s, depth.time, |name| {
+                        derived::depth_var(name, &depth.bids, &depth.asks)
+                    });
+                }
                 serde_json::to_string(&depth)?
             }
+            MarketStream::AggTrade(trade) => {
+                let flows = self
+                    .flows
+                    .on_trade(&s, trade.data.T, trade.signed_quantity());
+                for flow in flows {
+                    self.forward_stream(&flow.stream, &serde_json::to_string(&flow)?);
+                }
+                return Ok(());
+            }
+            // 标记价格只用于基差的资金费率
+            MarketStream::MarkPrice(mark) => {
+                let rate = mark.funding_rate();
+                self.basis.on_mark_price(&mark.data.s, rate, mark.data.T);
+                return Ok(());
+            }
         };
 
-        for subscriber in self.subscribers.values_mut() {
-            if subscriber.is_subscribed(&s) {
-                if let Err(e) = subscriber.forward_to_strategy_client(&data) {
+        self.forward_stream(&s, &data);
+        for (addr, output) in derived {
+            if let Some(subscriber) = self.subscribers.get(&addr) {
+                let sent = serde_json::to_string(&output)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|data| subscriber.forward_to_strategy_client(&data));
+                if let Err(e) = sent {
                     error!("{}", e);
                 }
             }
         }
+        // 波动率推送给 K 线流的订阅者，不占用 K 线流的编号
+        if let Some(volatility) = volatility {
+            let data = serde_json::to_string(&volatility)?;
+            for subscriber in self.subscribers.values() {
+                if subscriber.is_subscribed(&s) {
+                    if let Err(e) = subscriber.forward_to_strategy_client(&data) {
+                        error!("{}", e);
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
 
-    pub fn handle_strategy_client_connect(
-        &mut self,
-        addr: &SocketAddr,
-        tx: &UnboundedSender<Message>,
-    ) {
-        self.txs.insert(addr.clone(), tx.clone());
+    pub fn handle_strategy_client_connect(&mut self, addr: &SocketAddr, tx: &ClientSender) {
+        self.txs.insert(*addr, tx.clone());
     }
 
     pub fn handle_strategy_client_login(
@@ -219,17 +1272,20 @@ impl Market {
         if let Some(tx) = self.txs.get_mut(addr) {
             if !self.subscribers.contains_key(addr) {
                 info!("New subscriber {}", addr);
-                self.subscribers
-                    .insert(addr.clone(), Subscriber::new(tx.clone()));
+                self.subscribers.insert(*addr, Subscriber::new(tx.clone()));
             }
         }
         self.reply_to_strategy_client(addr, req.id, req.params.clone())
     }
 
+    /// `req.params` 为校验通过的流，`options` 与之对应；`results` 为原请求中每个流的结果，
+    /// 交易所确认订阅后一并回复
     pub async fn handle_strategy_client_subscribe(
         &mut self,
         addr: &SocketAddr,
         req: &mut SRequest<Vec<String>>,
+        options: &[StreamOptions],
+        mut results: Vec<SStreamResult>,
     ) -> anyhow::Result<()> {
         if !self.validate_login(addr) {
             return self.reply_to_strategy_client(
@@ -242,22 +1298,90 @@ impl Market {
             );
         }
 
+        let mut accepted = Vec::new();
+        for (i, stream) in req.params.iter().enumerate() {
+            if self.options.is_none() && options::is_option_stream(&exchange_stream(stream)) {
+                let result = results
+                    .iter_mut()
+                    .find(|r| r.accepted && r.stream == *stream);
+                if let Some(result) = result {
+                    *result = SStreamResult::rejected(
+                        stream,
+                        SError {
+                            code: UNSUPPORTED,
+                            msg: "options market is disabled".into(),
+                        },
+                    );
+                }
+                continue;
+            }
+            if self.spot.is_none() && basis::is_basis_stream(stream) {
+                let result = results
+                    .iter_mut()
+                    .find(|r| r.accepted && r.stream == *stream);
+                if let Some(result) = result {
+                    *result = SStreamResult::rejected(
+                        stream,
+                        SError {
+                            code: UNSUPPORTED,
+                            msg: "basis is disabled".into(),
+                        },
+                    );
+                }
+                continue;
+            }
+            accepted.push((stream.clone(), options.get(i).cloned().unwrap_or_default()));
+        }
+        if accepted.is_empty() {
+            return self.reply_to_strategy_client(addr, req.id, results);
+        }
+
         if let Some(subscriber) = self.subscribers.get_mut(addr) {
             let mut symbols = Vec::new();
-            for symbol in req.params.iter() {
-                if subscriber.is_subscribed(symbol) {
+            let mut tagged = Vec::new();
+            // 所有连接都订满时拒绝新的流，而不是让交易所静默地丢弃
+            let mut room = self.budget.available();
+            let subscribed = subscriber.iter().count();
+            for (symbol, options) in accepted {
+                // 用别名订阅的流换成当前合约的流，转发时换回别名
+                let (symbol, alias) = match self.aliases.resolve_stream(&symbol) {
+                    Some(resolved) => (resolved, Some(symbol)),
+                    None => (symbol, None),
+                };
+                if subscriber.is_subscribed(&symbol) {
+                    subscriber.set_options(&symbol, options);
                     continue;
                 }
 
-                let symbol = if symbol.contains("kline") {
-                    symbol.replace(":", "_")
-                } else if symbol.contains("bbo") {
-                    symbol.replace("bbo", "bookTicker")
-                } else if symbol.contains("depth") {
-                    symbol.replace("depth", "depth20").replace(":", "@")
-                } else {
-                    symbol.clone()
-                };
+                let requested = alias.clone().unwrap_or_else(|| symbol.clone());
+                if let Err(e) = self.quota.check_stream(subscribed + tagged.len()) {
+                    let result = results
+                        .iter_mut()
+                        .find(|r| r.accepted && r.stream == requested);
+                    if let Some(result) = result {
+                        *result = SStreamResult::rejected(&requested, e);
+                    }
+                    continue;
+                }
+                let symbol = exchange_stream(&symbol);
+                if !self.symbols.contains_key(&symbol) && !options::is_option_stream(&symbol) {
+                    if room == 0 {
+                        let result = results
+                            .iter_mut()
+                            .find(|r| r.accepted && r.stream == requested);
+                        if let Some(result) = result {
+                            *result = SStreamResult::rejected(
+                                &requested,
+                                SError {
+                                    code: STREAM_LIMIT,
+                                    msg: "stream limit reached".into(),
+                                },
+                            );
+                        }
+                        continue;
+                    }
+                    room -= 1;
+                }
 
                 match self.symbols.get_mut(&symbol) {
                     Some(cnt) => *cnt += 1,
@@ -266,20 +1390,403 @@ impl Market {
                     }
                 }
 
+                self.bar_clock.track(&symbol);
+                self.flows.track(&symbol);
+                self.basis.track(&symbol);
+                tagged.push((symbol.clone(), options, alias));
                 symbols.push(symbol);
             }
 
-            let id = self
-                .send_to_exchange(addr, "SUBSCRIBE".into(), symbols.clone())
+            let ids = self
+                .send_streams(addr, "SUBSCRIBE", flow::wire_streams(&symbols))
                 .await?;
             if let Some(subscriber) = self.subscribers.get_mut(addr) {
-                subscriber.on_strategy_client_subscribe(id, req.id, symbols);
+                subscriber.expect_subscribe_results(req.id, ids.len(), results);
+                for id in ids {
+                    subscriber.on_strategy_client_subscribe(id, req.id, symbols.clone());
+                }
+                for (symbol, options, alias) in tagged {
+                    subscriber.set_options(&symbol, options);
+                    subscriber.set_alias(&symbol, alias);
+                }
             }
         }
 
         Ok(())
     }
 
+    /// 退订流，没有其他订阅者的流向交易所退订；回复与订阅相同，未订阅的流被拒绝
+    pub async fn handle_strategy_client_unsubscribe(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<Vec<String>>,
+    ) -> anyhow::Result<()> {
+        let Some(subscriber) = self.subscribers.get_mut(addr) else {
+            return self.reply_to_strategy_client(
+                addr,
+                req.id,
+                SError {
+                    code: NOT_LOGIN,
+                    msg: "please login first".into(),
+                },
+            );
+        };
+
+        let mut results = Vec::new();
+        let mut removed = Vec::new();
+        for stream in req.params.iter() {
+            let symbol = subscriber
+                .aliased(stream)
+                .cloned()
+                .unwrap_or_else(|| exchange_stream(stream));
+            if subscriber.remove(&symbol) {
+                results.push(SStreamResult::accepted(stream));
+                removed.push(symbol);
+            } else {
+                let error = SError {
+                    code: INVALID_STREAM,
+                    msg: format!("{} is not subscribed", stream),
+                };
+                results.push(SStreamResult::rejected(stream, error));
+            }
+        }
+        for symbol in removed.iter() {
+            self.derived.remove_source(addr, symbol);
+        }
+
+        let unsubscribe = self.release(&removed);
+        if unsubscribe.is_empty() {
+            return self.reply_to_strategy_client(addr, req.id, results);
+        }
+        let ids = self.send_streams(addr, "UNSUBSCRIBE", unsubscribe).await?;
+        if let Some(subscriber) = self.subscribers.get_mut(addr) {
+            subscriber.expect_subscribe_results(req.id, ids.len(), results);
+            for id in ids {
+                subscriber.on_exchange_request(id, req.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// 别名换到新的合约后，把用别名订阅的流换到新合约的流，先订阅新流再退订旧流，并向订阅者推送
+    /// [`SSymbolChanged`]；别名没有变化时不做任何事
+    pub async fn switch_aliases(&mut self) -> anyhow::Result<()> {
+        let version = self.aliases.version();
+        if version == self.alias_version {
+            return Ok(());
+        }
+        self.alias_version = version;
+
+        let mut switches = Vec::new();
+        for (addr, subscriber) in self.subscribers.iter() {
+            for (symbol, alias) in subscriber.aliases() {
+                // 别名暂时没有对应的合约时保留原来的流
+                let Some(stream) = self.aliases.resolve_stream(alias) else {
+                    continue;
+                };
+                let stream = exchange_stream(&stream);
+                if stream != *symbol {
+                    switches.push((*addr, alias.clone(), symbol.clone(), stream));
+                }
+            }
+        }
+
+        for (addr, alias, previous, symbol) in switches {
+            let Some(subscriber) = self.subscribers.get_mut(&addr) else {
+                continue;
+            };
+            let options = subscriber.options(&previous);
+            subscriber.remove(&previous);
+            subscriber.add(&symbol);
+            subscriber.set_options(&symbol, options);
+            subscriber.set_alias(&symbol, Some(alias.clone()));
+            let contract = |stream: &str| stream.split_once('@').map(|(s, _)| s.to_string());
+            let changed = SSymbolChanged {
+                time: now_ms(),
+                alias: contract(&alias).unwrap_or_default(),
+                stream: alias,
+                previous: contract(&previous).unwrap_or_default(),
+                symbol: contract(&symbol).unwrap_or_default(),
+            };
+            info!(
+                "Switch {} of {} from {} to {}",
+                changed.stream, addr, previous, symbol
+            );
+            if let Err(e) = serde_json::to_string(&changed)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| subscriber.forward_to_strategy_client(&data))
+            {
+                error!("{}", e);
+            }
+            self.derived.remove_source(&addr, &previous);
+
+            let count = self.symbols.entry(symbol.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                self.bar_clock.track(&symbol);
+                self.flows.track(&symbol);
+                self.basis.track(&symbol);
+                self.send_streams(&addr, "SUBSCRIBE", flow::wire_streams(&[symbol]))
+                    .await?;
+            }
+            let unsubscribe = self.release(&[previous]);
+            if !unsubscribe.is_empty() {
+                self.send_streams(&addr, "UNSUBSCRIBE", unsubscribe).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 为已订阅的流注册派生流
+    pub fn handle_strategy_client_derive(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SDerive>,
+    ) -> anyhow::Result<()> {
+        let source = exchange_stream(&req.params.source.to_lowercase());
+        let error = match self.subscribers.get(addr) {
+            None => Some(SError {
+                code: NOT_LOGIN,
+                msg: "please login first".into(),
+            }),
+            Some(subscriber) if !subscriber.is_subscribed(&source) => Some(SError {
+                code: INVALID_STREAM,
+                msg: format!("subscribe {} first", req.params.source),
+            }),
+            Some(_) => match Program::parse(&req.params.expr) {
+                Ok(program) => {
+                    let derived = self.derived.count(addr, &source, &req.params.name);
+                    match self.quota.check_derive(derived, &program) {
+                        Ok(()) => {
+                            info!("Derive {} from {} for {}", req.params.name, source, addr);
+                            self.derived.add(*addr, &source, &req.params.name, program);
+                            None
+                        }
+                        Err(e) => Some(e),
+                    }
+                }
+                Err(e) => Some(SError {
+                    code: INVALID_EXPR,
+                    msg: e.to_string(),
+                }),
+            },
+        };
+        match error {
+            Some(e) => self.reply_to_strategy_client(addr, req.id, e),
+            None => self.reply_to_strategy_client(addr, req.id, None::<u8>),
+        }
+    }
+
+    /// 补发缓存中 `offset` 之后的行情，补发完成后回复
+    pub fn handle_strategy_client_resume(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SResume>,
+    ) -> anyhow::Result<()> {
+        let stream = exchange_stream(&req.params.stream.to_lowercase());
+        let error = match self.subscribers.get(addr) {
+            None => Some(SError {
+                code: NOT_LOGIN,
+                msg: "please login first".into(),
+            }),
+            Some(subscriber) if !subscriber.is_subscribed(&stream) => Some(SError {
+                code: INVALID_STREAM,
+                msg: format!("subscribe {} first", req.params.stream),
+            }),
+            Some(subscriber) => match self.replay.since(&stream, req.params.offset) {
+                Some(events) => {
+                    info!(
+                        "Replay {} events of {} after {} for {}",
+                        events.len(),
+                        stream,
+                        req.params.offset,
+                        addr
+                    );
+                    for data in events {
+                        match subscriber.bucket(&stream) {
+                            Some(bucket) => subscriber
+                                .forward_stream(&stream, &dom::bucket_depth(data, bucket)?)?,
+                            None => subscriber.forward_stream(&stream, data)?,
+                        }
+                    }
+                    None
+                }
+                None => Some(SError {
+                    code: OFFSET_EXPIRED,
+                    msg: format!(
+                        "offset {} of {} is out of the replay window",
+                        req.params.offset, req.params.stream
+                    ),
+                }),
+            },
+        };
+        match error {
+            Some(e) => self.reply_to_strategy_client(addr, req.id, e),
+            None => self.reply_to_strategy_client(addr, req.id, None::<u8>),
+        }
+    }
+
+    /// 标的下的期权合约
+    pub async fn handle_strategy_client_option_chain(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SOptionChainReq>,
+    ) -> anyhow::Result<()> {
+        let chain = match self.options.as_ref() {
+            None => Err(SError {
+                code: UNSUPPORTED,
+                msg: "options market is disabled".into(),
+            }),
+            Some(feed) => feed
+                .option_chain(&req.params.underlying)
+                .await
+                .map_err(|e| SError {
+                    code: UNDEF_ERROR,
+                    msg: e.to_string(),
+                }),
+        };
+        match chain {
+            Ok(chain) => self.reply_to_strategy_client(addr, req.id, chain),
+            Err(e) => self.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
+    /// 已订阅的 K 线流上当前的波动率估计
+    pub fn handle_strategy_client_get_volatility(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SVolatilityReq>,
+    ) -> anyhow::Result<()> {
+        let symbol = req.params.symbol.to_lowercase();
+        let stream = format!("{}@kline_{}", symbol, req.params.window);
+        let estimate = if !self.validate_login(addr) {
+            Err(SError {
+                code: NOT_LOGIN,
+                msg: "please login first".into(),
+            })
+        } else if !self.volatility.enabled() {
+            Err(SError {
+                code: UNSUPPORTED,
+                msg: "volatility estimator is disabled".into(),
+            })
+        } else if !self.symbols.contains_key(&stream) {
+            Err(SError {
+                code: INVALID_STREAM,
+                msg: format!("subscribe {}@kline:{} first", symbol, req.params.window),
+            })
+        } else {
+            self.volatility.estimate(&stream).ok_or_else(|| SError {
+                code: INVALID_STREAM,
+                msg: format!("invalid window {}", req.params.window),
+            })
+        };
+        match estimate {
+            Ok(estimate) => self.reply_to_strategy_client(addr, req.id, estimate),
+            Err(e) => self.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
+    /// 已订阅的 K 线流上缓存的 K 线，按请求的点数降采样
+    pub fn handle_strategy_client_get_candles(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SCandlesReq>,
+    ) -> anyhow::Result<()> {
+        let params = &req.params;
+        let symbol = params.symbol.to_lowercase();
+        let stream = format!("{}@kline_{}", symbol, params.interval);
+        let candles = if !self.validate_login(addr) {
+            Err(SError {
+                code: NOT_LOGIN,
+                msg: "please login first".into(),
+            })
+        } else if !self.candles.enabled() {
+            Err(SError {
+                code: UNSUPPORTED,
+                msg: "candle cache is disabled".into(),
+            })
+        } else if !self.symbols.contains_key(&stream) {
+            Err(SError {
+                code: INVALID_STREAM,
+                msg: format!("subscribe {}@kline:{} first", symbol, params.interval),
+            })
+        } else {
+            let range = (params.start, params.end);
+            self.candles
+                .query(&stream, &symbol, &params.interval, range, params.max_points)
+                .ok_or_else(|| SError {
+                    code: INVALID_STREAM,
+                    msg: format!("invalid interval {}", params.interval),
+                })
+        };
+        match candles {
+            Ok(candles) => self.reply_to_strategy_client(addr, req.id, candles),
+            Err(e) => self.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
+    /// 已订阅的基差流上当前的基差
+    pub fn handle_strategy_client_get_basis(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SBasisReq>,
+    ) -> anyhow::Result<()> {
+        let symbol = req.params.symbol.to_lowercase();
+        let stream = format!("{}@basis", symbol);
+        let basis = if !self.validate_login(addr) {
+            Err(SError {
+                code: NOT_LOGIN,
+                msg: "please login first".into(),
+            })
+        } else if self.spot.is_none() {
+            Err(SError {
+                code: UNSUPPORTED,
+                msg: "basis is disabled".into(),
+            })
+        } else if !self.symbols.contains_key(&stream) {
+            Err(SError {
+                code: INVALID_STREAM,
+                msg: format!("subscribe {} first", stream),
+            })
+        } else {
+            self.basis.basis(&symbol, now_ms()).ok_or_else(|| SError {
+                code: INVALID_STREAM,
+                msg: format!("no quotes of {} yet", stream),
+            })
+        };
+        match basis {
+            Ok(basis) => self.reply_to_strategy_client(addr, req.id, basis),
+            Err(e) => self.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
+    /// 交易所的深度快照，取自缓存或代理 REST 查询
+    pub async fn handle_strategy_client_get_depth_snapshot(
+        &mut self,
+        addr: &SocketAddr,
+        req: &SRequest<SDepthSnapshotReq>,
+    ) -> anyhow::Result<()> {
+        let snapshot = if !self.validate_login(addr) {
+            Err(SError {
+                code: NOT_LOGIN,
+                msg: "please login first".into(),
+            })
+        } else if !self.depth_snapshots.enabled() {
+            Err(SError {
+                code: UNSUPPORTED,
+                msg: "depth snapshot is disabled".into(),
+            })
+        } else {
+            self.depth_snapshots
+                .get(&req.params.symbol, req.params.limit)
+                .await
+        };
+        match snapshot {
+            Ok(snapshot) => self.reply_to_strategy_client(addr, req.id, snapshot),
+            Err(e) => self.reply_to_strategy_client(addr, req.id, e),
+        }
+    }
+
     fn handle_exchange_event(&mut self, event: Event) {
         debug!("{:?}", event);
         match event {
@@ -297,12 +1804,12 @@ impl Market {
     pub fn handle_strategy_client_disconnect(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
     ) -> anyhow::Result<()> {
-        if let Some(id) = parser.get("id") {
+        if let Some(id) = parser.id() {
             self.reply_to_strategy_client(
                 addr,
-                i64::deserialize(id)?,
+                id,
                 SError {
                     code: DISCONNECTED,
                     msg: "market disconnected".into(),
@@ -313,8 +1820,351 @@ impl Market {
     }
 }
 
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// 等待连接池中任意一条连接的推送，池为空时一直等待
+async fn recv_pool(pool: &mut [PublicFeed]) -> Value {
+    if pool.is_empty() {
+        return std::future::pending().await;
+    }
+    let feeds = pool.iter_mut().map(|feed| Box::pin(feed.recv()));
+    futures::future::select_all(feeds).await.0
+}
+
+/// 策略端的流名称转换为交易所的流名称
+fn exchange_stream(symbol: &str) -> String {
+    if let Some(stream) = options::exchange_stream(symbol) {
+        stream
+    } else if symbol.contains("kline") {
+        symbol.replace(":", "_")
+    } else if symbol.contains("bbo") {
+        symbol.replace("bbo", "bookTicker")
+    } else if symbol.contains("depth") {
+        symbol.replace("depth", "depth20").replace(":", "@")
+    } else {
+        symbol.to_string()
+    }
+}
+
 impl Market {
     fn validate_login(&self, addr: &SocketAddr) -> bool {
         self.subscribers.contains_key(addr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+    use tungstenite::Message;
+    use websocket::{bounded_channel, BoundedReceiver};
+
+    type Requests = Arc<Mutex<Vec<Value>>>;
+
+    /// 本地模拟的交易所，记录收到的请求并应答成功
+    async fn fake_exchange() -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+                    continue;
+                };
+                let received = received.clone();
+                tokio::spawn(async move {
+                    while let Some(Ok(msg)) = ws.next().await {
+                        let Message::Text(text) = msg else {
+                            continue;
+                        };
+                        let request: Value = serde_json::from_str(&text).unwrap();
+                        let reply = serde_json::json!({"result": null, "id": request["id"]});
+                        received.lock().unwrap().push(request);
+                        if ws
+                            .send(Message::Text(reply.to_string().into()))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    /// 交易所收到的某个方法的请求中的流，等待到 `done` 满足为止
+    async fn streams_sent(
+        requests: &Requests,
+        method: &str,
+        done: impl Fn(&[String]) -> bool,
+    ) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let streams: Vec<String> = requests
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|r| r["method"] == method)
+                .flat_map(|r| r["params"].as_array().cloned().unwrap_or_default())
+                .filter_map(|s| s.as_str().map(String::from))
+                .collect();
+            if done(&streams) || Instant::now() > deadline {
+                return streams;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    async fn login(market: &mut Market, port: u16) -> (SocketAddr, BoundedReceiver<Message>) {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let (tx, rx) = bounded_channel("strategy", &Default::default());
+        market.handle_strategy_client_connect(&addr, &tx.into());
+        let login = SRequest {
+            id: 1,
+            method: "login".into(),
+            params: SLogin {
+                session_id: port,
+                name: None,
+                trading: false,
+                cancel_on_disconnect: false,
+                fills: false,
+                format: Default::default(),
+                heartbeat_ms: None,
+                tenant: None,
+                token: None,
+                version: None,
+            },
+        };
+        market.handle_strategy_client_login(&addr, &login).unwrap();
+        (addr, rx)
+    }
+
+    async fn subscribe(market: &mut Market, addr: &SocketAddr, stream: &str) {
+        let mut req = SRequest {
+            id: 2,
+            method: "subscribe".into(),
+            params: vec![stream.to_string()],
+        };
+        market
+            .handle_strategy_client_subscribe(
+                addr,
+                &mut req,
+                &[StreamOptions::default()],
+                vec![SStreamResult::accepted(stream)],
+            )
+            .await
+            .unwrap();
+    }
+
+    async fn unsubscribe(market: &mut Market, addr: &SocketAddr, stream: &str) {
+        let req = SRequest {
+            id: 3,
+            method: "unsubscribe".into(),
+            params: vec![stream.to_string()],
+        };
+        market
+            .handle_strategy_client_unsubscribe(addr, &req)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_refcount() {
+        const STREAM: &str = "btcusdt@bookTicker";
+        const PINNED: &str = "ethusdt@bookTicker";
+        let (url, requests) = fake_exchange().await;
+        let mut market = Market::connect_to(Some(&url)).await.unwrap();
+        market.pin_streams(&[PINNED.to_string()]).await.unwrap();
+
+        let (a, mut rx) = login(&mut market, 9001).await;
+        let (b, _rx) = login(&mut market, 9002).await;
+        for addr in [&a, &b] {
+            subscribe(&mut market, addr, STREAM).await;
+            subscribe(&mut market, addr, PINNED).await;
+        }
+        assert_eq!(market.symbols[STREAM], 2);
+        assert_eq!(market.symbols[PINNED], 3);
+
+        // 第一个订阅者退订时保留交易所的订阅，直接回复策略端
+        while rx.try_recv().is_ok() {}
+        unsubscribe(&mut market, &a, STREAM).await;
+        assert_eq!(market.symbols[STREAM], 1);
+        let Ok(Message::Text(reply)) = rx.try_recv() else {
+            panic!("no reply to unsubscribe");
+        };
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["result"][0]["stream"], STREAM);
+
+        // 最后一个订阅者退订时向交易所退订，之前没有发出过退订
+        unsubscribe(&mut market, &b, STREAM).await;
+        assert!(!market.symbols.contains_key(STREAM));
+        let sent = streams_sent(&requests, "UNSUBSCRIBE", |s| !s.is_empty()).await;
+        assert_eq!(sent, vec![STREAM.to_string()]);
+
+        // 固定的流在所有策略端退订、断开后仍保留
+        unsubscribe(&mut market, &a, PINNED).await;
+        market.handle_strategy_client_close(&b).await.unwrap();
+        assert_eq!(market.symbols[PINNED], 1);
+        // 之后的订阅送达时，之前的退订也已送达
+        subscribe(&mut market, &a, "solusdt@bookTicker").await;
+        streams_sent(&requests, "SUBSCRIBE", |s| {
+            s.iter().any(|s| s == "solusdt@bookTicker")
+        })
+        .await;
+        assert_eq!(
+            streams_sent(&requests, "UNSUBSCRIBE", |_| true).await,
+            vec![STREAM.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_switch_aliases() {
+        const ALIAS: &str = "btcusdt_quarter@bookTicker";
+        const OLD: &str = "btcusdt_250627@bookTicker";
+        const NEW: &str = "btcusdt_250926@bookTicker";
+        let (url, requests) = fake_exchange().await;
+        let aliases = ContractAliases::default();
+        aliases.update(HashMap::from([(
+            "btcusdt_quarter".to_string(),
+            "btcusdt_250627".to_string(),
+        )]));
+        let mut market = Market::connect_to(Some(&url))
+            .await
+            .unwrap()
+            .with_aliases(aliases.clone());
+
+        let (a, mut rx_a) = login(&mut market, 9001).await;
+        let (b, mut rx_b) = login(&mut market, 9002).await;
+        for addr in [&a, &b] {
+            subscribe(&mut market, addr, ALIAS).await;
+        }
+        assert_eq!(market.symbols[OLD], 2);
+        assert_eq!(
+            market.subscribers[&a].aliased(ALIAS),
+            Some(&OLD.to_string())
+        );
+
+        // 别名没有变化时不切换
+        market.switch_aliases().await.unwrap();
+        assert_eq!(market.symbols[OLD], 2);
+
+        aliases.update(HashMap::from([(
+            "btcusdt_quarter".to_string(),
+            "btcusdt_250926".to_string(),
+        )]));
+        while rx_a.try_recv().is_ok() {}
+        while rx_b.try_recv().is_ok() {}
+        market.switch_aliases().await.unwrap();
+
+        // 两个订阅者都换到新合约的流，旧的流没有订阅者后向交易所退订
+        assert!(!market.symbols.contains_key(OLD));
+        assert_eq!(market.symbols[NEW], 2);
+        for addr in [&a, &b] {
+            let subscriber = &market.subscribers[addr];
+            assert!(subscriber.is_subscribed(&NEW.to_string()));
+            assert!(!subscriber.is_subscribed(&OLD.to_string()));
+            assert_eq!(subscriber.aliased(ALIAS), Some(&NEW.to_string()));
+        }
+        for rx in [&mut rx_a, &mut rx_b] {
+            let Ok(Message::Text(text)) = rx.try_recv() else {
+                panic!("symbol change not pushed");
+            };
+            let changed: SSymbolChanged = serde_json::from_str(&text).unwrap();
+            assert_eq!(
+                (
+                    changed.stream.as_str(),
+                    changed.previous.as_str(),
+                    changed.symbol.as_str()
+                ),
+                (ALIAS, "btcusdt_250627", "btcusdt_250926")
+            );
+        }
+        let unsubscribed = streams_sent(&requests, "UNSUBSCRIBE", |s| !s.is_empty()).await;
+        assert_eq!(unsubscribed, vec![OLD.to_string()]);
+        let subscribed = streams_sent(&requests, "SUBSCRIBE", |s| s.iter().any(|s| s == NEW)).await;
+        assert_eq!(subscribed.iter().filter(|s| *s == NEW).count(), 1);
+
+        // 退订别名时退订的是新合约的流
+        unsubscribe(&mut market, &a, ALIAS).await;
+        unsubscribe(&mut market, &b, ALIAS).await;
+        assert!(market.symbols.is_empty());
+        let unsubscribed = streams_sent(&requests, "UNSUBSCRIBE", |s| s.len() == 2).await;
+        assert_eq!(unsubscribed, vec![OLD.to_string(), NEW.to_string()]);
+    }
+
+    #[test]
+    fn test_pending_requests() {
+        let addr: SocketAddr = "127.0.0.1:8111".parse().unwrap();
+        let mut pending = PendingRequests::new(Duration::from_secs(10));
+        let now = Instant::now();
+        pending.insert(RequestKey::from_wire(1), addr, now);
+        pending.insert(RequestKey::from_wire(2), addr, now);
+        pending.insert(RequestKey::from_wire(3), addr, now + Duration::from_secs(5));
+
+        assert_eq!(
+            pending.complete(1, now + Duration::from_millis(30)),
+            Some(addr)
+        );
+        assert_eq!(pending.complete(1, now), None);
+        assert!(pending.expire(now + Duration::from_secs(1)).is_empty());
+
+        assert_eq!(
+            pending.expire(now + Duration::from_secs(12)),
+            vec![(2, addr)]
+        );
+        assert_eq!(
+            pending.expire(now + Duration::from_secs(15)),
+            vec![(3, addr)]
+        );
+        // 扫描间隔内不重复扫描
+        pending.insert(RequestKey::from_wire(4), addr, now);
+        assert!(pending
+            .expire(now + Duration::from_millis(15500))
+            .is_empty());
+        assert_eq!(
+            pending.expire(now + Duration::from_secs(16)),
+            vec![(4, addr)]
+        );
+
+        let stats = &pending.stats;
+        assert_eq!((stats.count, stats.timeouts, stats.max_ms), (1, 3, 30));
+        assert_eq!(stats.mean_ms(), 30.0);
+    }
+
+    #[test]
+    fn test_abandoned_requests() {
+        let a: SocketAddr = "127.0.0.1:8111".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:8112".parse().unwrap();
+        let mut ids = RequestIds::new(100);
+        let mut pending = PendingRequests::new(Duration::from_secs(10));
+        let now = Instant::now();
+        let old = ids.allocate();
+        pending.insert(old, a, now);
+
+        // 重连后旧连接的请求立即作废，新连接上同一序号的请求不会被旧的应答认领
+        ids.reconnect();
+        let new = ids.allocate();
+        assert_eq!(old.seq, new.seq);
+        pending.insert(new, b, now);
+        assert_eq!(pending.abandon(&ids), vec![(old.wire(), a)]);
+        assert_eq!(pending.complete(old.wire(), now), None);
+        assert_eq!(pending.complete(new.wire(), now), Some(b));
+
+        // 重启后的网关从新的代数开始分配
+        let restarted = RequestIds::default().allocate();
+        pending.insert(restarted, a, now);
+        assert_eq!(pending.complete(new.wire(), now), None);
+        assert!(pending.abandon(&ids).len() == 1);
+    }
+}
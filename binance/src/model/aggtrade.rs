@@ -0,0 +1,66 @@
+//! see: https://developers.binance.com/docs/zh-CN/binance-spot-api-docs/web-socket-streams#归集交易
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinanceAggTrade {
+    pub stream: String,
+    pub data: BinanceAggTradeData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct BinanceAggTradeData {
+    pub e: String, // Event type
+    pub E: i64,    // Event time
+    pub s: String, // Symbol
+    pub a: i64,    // Aggregate trade ID
+    pub p: String, // Price
+    pub q: String, // Quantity
+    pub T: i64,    // Trade time
+    pub m: bool,   // Is the buyer the market maker?
+}
+
+impl BinanceAggTrade {
+    pub fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    /// 主动买入为正、主动卖出为负的成交量
+    pub fn signed_quantity(&self) -> f64 {
+        let quantity: f64 = self.data.q.parse().unwrap_or_default();
+        match self.data.m {
+            true => -quantity,
+            false => quantity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binance_aggtrade() {
+        let s = r#"{
+                        "stream": "btcusdt@aggTrade",
+                        "data": {
+                            "e": "aggTrade",
+                            "E": 1672515782136,
+                            "s": "BTCUSDT",
+                            "a": 12345,
+                            "p": "0.001",
+                            "q": "100",
+                            "f": 100,
+                            "l": 105,
+                            "T": 1672515782136,
+                            "m": true,
+                            "M": true
+                        }
+                    }"#;
+        let trade: BinanceAggTrade = serde_json::from_str(s).unwrap();
+        assert_eq!(trade.stream(), "btcusdt@aggTrade");
+        assert_eq!(trade.data.T, 1672515782136);
+        assert_eq!(trade.signed_quantity(), -100.0);
+    }
+}
@@ -34,7 +34,7 @@ impl BinanceSpotDepth {
 impl From<BinanceSpotDepth> for SGeneralDepth<BinanceQuote> {
     fn from(value: BinanceSpotDepth) -> Self {
         let time = now();
-        let (symbol, stream) = value.stream.split_once("@").unwrap();
+        let (symbol, stream) = value.stream.split_once("@").unwrap_or((&value.stream, ""));
 
         match stream.split_once("@") {
             Some((_, interval)) => SGeneralDepth {
@@ -78,7 +78,7 @@ impl BinanceFutureDepth {
 
 impl From<BinanceFutureDepth> for SGeneralDepth<BinanceQuote> {
     fn from(value: BinanceFutureDepth) -> Self {
-        let (symbol, stream) = value.stream.split_once("@").unwrap();
+        let (symbol, stream) = value.stream.split_once("@").unwrap_or((&value.stream, ""));
 
         match stream.split_once("@") {
             Some((_, interval)) => SGeneralDepth {
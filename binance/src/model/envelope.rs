@@ -0,0 +1,145 @@
+//! 组合流的信封
+//!
+//! 组合流的推送是 `{"stream": "btcusdt@kline_1m", "data": {...}}`。先按流名称 `@` 之后的类型在
+//! `DECODERS` 中找到解码器再解析，未知的流给出 `unsupported stream X`，解析失败的流带上流名称，
+//! 而不是在无标签的 `Event` 中逐个尝试后报出含糊的错误。新增流类型时在 `DECODERS` 中加一行。
+
+use super::MarketStream;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use tracing::{debug, error, warn};
+
+pub type Decoder = fn(Value) -> serde_json::Result<MarketStream>;
+
+/// 流类型的前缀 -> 解码器，按顺序取第一个匹配的
+const DECODERS: &[(&str, Decoder)] = &[
+    ("bookTicker", |value| {
+        serde_json::from_value(value).map(MarketStream::BookTicker)
+    }),
+    ("depth", decode_depth),
+    ("kline_", |value| {
+        serde_json::from_value(value).map(MarketStream::Kline)
+    }),
+    ("aggTrade", |value| {
+        serde_json::from_value(value).map(MarketStream::AggTrade)
+    }),
+    ("markPrice", |value| {
+        serde_json::from_value(value).map(MarketStream::MarkPrice)
+    }),
+];
+
+/// 现货的部分深度是 `bids`/`asks`，合约的深度与现货的增量深度是 `b`/`a`
+fn decode_depth(value: Value) -> serde_json::Result<MarketStream> {
+    match value
+        .get("data")
+        .is_some_and(|data| data.get("bids").is_some())
+    {
+        true => serde_json::from_value(value).map(MarketStream::SpotDepth),
+        false => serde_json::from_value(value).map(MarketStream::FutureDepth),
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// 没有解码器的流
+    Unsupported(String),
+    /// 流的类型已知，数据不符合格式
+    Invalid(String, serde_json::Error),
+    /// 没有 `stream` 字段
+    MissingStream,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Unsupported(stream) => write!(f, "unsupported stream {}", stream),
+            DecodeError::Invalid(stream, e) => write!(f, "invalid {}: {}", stream, e),
+            DecodeError::MissingStream => write!(f, "missing stream name"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 按流名称解码组合流的推送
+pub fn decode(value: Value) -> Result<MarketStream, DecodeError> {
+    let stream = match value.get("stream").and_then(Value::as_str) {
+        Some(stream) => stream.to_string(),
+        None => return Err(DecodeError::MissingStream),
+    };
+    let kind = stream.split_once('@').map(|(_, kind)| kind).unwrap_or("");
+    match DECODERS.iter().find(|(prefix, _)| kind.starts_with(prefix)) {
+        Some((_, decoder)) => decoder(value).map_err(|e| DecodeError::Invalid(stream, e)),
+        None => Err(DecodeError::Unsupported(stream)),
+    }
+}
+
+/// 解码失败的计数
+#[derive(Debug, Default, Clone)]
+pub struct DecodeStats {
+    /// 未知的流 -> 次数
+    pub unsupported: BTreeMap<String, u64>,
+    pub invalid: u64,
+}
+
+impl DecodeStats {
+    /// 计数并记录日志，同一条未知的流只在第一次告警
+    pub fn record(&mut self, err: &DecodeError) {
+        match err {
+            DecodeError::Unsupported(stream) => {
+                let count = self.unsupported.entry(stream.clone()).or_default();
+                *count += 1;
+                match *count {
+                    1 => warn!("{}", err),
+                    _ => debug!("{}", err),
+                }
+            }
+            DecodeError::Invalid(..) | DecodeError::MissingStream => {
+                self.invalid += 1;
+                error!("{}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode() {
+        let book = json!({
+            "stream": "btcusdt@bookTicker",
+            "data": {"s": "BTCUSDT", "b": "1", "B": "2", "a": "3", "A": "4"}
+        });
+        assert!(matches!(decode(book), Ok(MarketStream::BookTicker(_))));
+
+        let spot = json!({"stream": "btcusdt@depth20", "data": {"bids": [], "asks": []}});
+        assert!(matches!(decode(spot), Ok(MarketStream::SpotDepth(_))));
+        let future = json!({
+            "stream": "btcusdt@depth20@100ms",
+            "data": {"E": 1, "s": "BTCUSDT", "b": [], "a": []}
+        });
+        assert!(matches!(decode(future), Ok(MarketStream::FutureDepth(_))));
+
+        let mut stats = DecodeStats::default();
+        let trade = json!({"stream": "btcusdt@trade", "data": {}});
+        let err = decode(trade.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported stream btcusdt@trade");
+        stats.record(&err);
+        stats.record(&decode(trade).unwrap_err());
+        assert_eq!(stats.unsupported["btcusdt@trade"], 2);
+
+        let kline = json!({"stream": "btcusdt@kline_1m", "data": {"e": "kline"}});
+        let err = decode(kline).unwrap_err();
+        assert!(err.to_string().starts_with("invalid btcusdt@kline_1m: "));
+        stats.record(&err);
+        assert!(matches!(
+            decode(json!({"result": null, "id": 1})),
+            Err(DecodeError::MissingStream)
+        ));
+        assert_eq!(stats.invalid, 1);
+    }
+}
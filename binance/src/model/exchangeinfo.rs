@@ -197,7 +197,7 @@ mod tests {
                                                    {"multiplierUp": "1.0500", "multiplierDown": "0.9500", "filterType": "PERCENT_PRICE", "multiplierDecimal": "4"}], 
                                        "orderTypes": ["LIMIT", "MARKET", "STOP", "STOP_MARKET", "TAKE_PROFIT", "TAKE_PROFIT_MARKET", "TRAILING_STOP_MARKET"], 
                                        "timeInForce": ["GTC", "IOC", "FOK", "GTX", "GTD"]}]}"#;
-        let rsp: BinanceExchangeInfo = serde_json::from_str(&s).unwrap();
+        let rsp: BinanceExchangeInfo = serde_json::from_str(s).unwrap();
         println!("{:?}", rsp);
     }
 }
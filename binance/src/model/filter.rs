@@ -24,8 +24,10 @@ pub enum FilterField {
         multiplier_up: String, // 价格上限倍数
         #[serde(rename = "multiplierDown")]
         multiplier_down: String, // 价格下限倍数
-        #[serde(rename = "avgPriceMins")]
-        avg_price_mins: i32, // 平均价格计算分钟数
+        #[serde(rename = "avgPriceMins", default)]
+        avg_price_mins: i32, // 平均价格计算分钟数（仅现货）
+        #[serde(rename = "multiplierDecimal", default)]
+        multiplier_decimal: Option<String>, // 倍数精度（仅期货）
     },
 
     /// 按方向百分比价格过滤器 - 买卖方向不同的价格范围
@@ -56,11 +58,11 @@ pub enum FilterField {
     /// 最小名义价值过滤器 - 订单的最小名义价值
     /// spot, margin, futures
     MIN_NOTIONAL {
-        #[serde(rename = "minNotional")]
-        min_notional: String, // 最小名义价值
-        #[serde(rename = "applyToMarket")]
+        #[serde(rename = "minNotional", alias = "notional")]
+        min_notional: String, // 最小名义价值（期货字段名为 notional）
+        #[serde(rename = "applyToMarket", default)]
         apply_to_market: bool, // 是否应用于市价单
-        #[serde(rename = "avgPriceMins")]
+        #[serde(rename = "avgPriceMins", default)]
         avg_price_mins: i32, // 平均价格计算分钟数
     },
 
@@ -99,14 +101,14 @@ pub enum FilterField {
     /// 最大订单数过滤器 - 单个交易对的最大订单数
     /// spot, margin
     MAX_NUM_ORDERS {
-        #[serde(rename = "maxNumOrders")]
-        max_num_orders: i64, // 最大订单数
+        #[serde(rename = "maxNumOrders", alias = "limit")]
+        max_num_orders: i64, // 最大订单数（期货字段名为 limit）
     },
 
     /// 最大算法订单数过滤器 - 单个交易对的最大算法订单数
     /// spot, margin, futures
     MAX_NUM_ALGO_ORDERS {
-        #[serde(rename = "maxNumAlgoOrders")]
+        #[serde(rename = "maxNumAlgoOrders", alias = "limit")]
         max_num_algo_orders: i64, // 最大算法订单数
     },
 
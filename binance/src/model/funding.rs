@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use super::deserialize_symbol;
+
+/// GET /fapi/v1/premiumIndex 返回的标记价格与资金费率
+/// See: https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/market-data/rest-api/Mark-Price
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinancePremiumIndex {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String,
+    pub markPrice: String,
+    pub indexPrice: String,
+    pub lastFundingRate: String,
+    pub nextFundingTime: i64,
+    pub time: i64,
+}
+
+impl BinancePremiumIndex {
+    pub fn funding_rate(&self) -> f64 {
+        self.lastFundingRate.parse().unwrap_or_default()
+    }
+}
+
+/// GET /fapi/v1/fundingInfo 返回的资金费率上下限与收取间隔，只包含调整过的交易对
+/// See: https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/market-data/rest-api/Get-Funding-Rate-Info
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceFundingInfo {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String,
+    pub adjustedFundingRateCap: String,
+    pub adjustedFundingRateFloor: String,
+    pub fundingIntervalHours: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binance_funding() {
+        let s = r#"{
+            "symbol": "BTCUSDT",
+            "markPrice": "11793.63104562",
+            "indexPrice": "11781.80495970",
+            "estimatedSettlePrice": "11781.16138815",
+            "lastFundingRate": "0.00038246",
+            "interestRate": "0.00010000",
+            "nextFundingTime": 1597392000000,
+            "time": 1597370495002
+        }"#;
+        let premium: BinancePremiumIndex = serde_json::from_str(s).unwrap();
+        assert_eq!(premium.symbol, "btcusdt");
+        assert_eq!(premium.funding_rate(), 0.00038246);
+
+        let s = r#"[{"symbol": "BLZUSDT", "adjustedFundingRateCap": "0.02500000", "adjustedFundingRateFloor": "-0.02500000", "fundingIntervalHours": 8, "disclaimer": false}]"#;
+        let info: Vec<BinanceFundingInfo> = serde_json::from_str(s).unwrap();
+        assert_eq!(info[0].symbol, "blzusdt");
+        assert_eq!(info[0].fundingIntervalHours, 8);
+    }
+}
@@ -0,0 +1,242 @@
+//! 交易所消息解码的变异测试
+//!
+//! 以真实推送为种子，随机删除字段、置空、换成极大的数字或非数字的字符串、增加未知字段、改变类型，
+//! 再走一遍行情与回报的解码、转换和序列化，确认任何输入都只会解码失败，而不会让网关 panic。
+
+use super::order::usdt::OrderUpdate;
+use super::user_data::UserDataEvent;
+use super::{Event, ExecutionReport, MarketStream};
+use crate::model::quote::BinanceQuote;
+use crate::OrderTrait;
+use cryptoflow::chat::{Position, SGeneralDepth, SGeneralKline, SOrder};
+use serde_json::{json, Value};
+
+const SEEDS: &[&str] = &[
+    r#"{"stream":"btcusdt@bookTicker","data":{"u":400900217,"s":"BTCUSDT","b":"25.35190000","B":"31.21000000","a":"25.36520000","A":"40.66000000"}}"#,
+    r#"{"stream":"btcusdt@depth20@100ms","data":{"lastUpdateId":160,"bids":[["0.0024","10"]],"asks":[["0.0026","100"]]}}"#,
+    r#"{"stream":"btcusdt@depth20@100ms","data":{"e":"depthUpdate","E":1672515782136,"T":1672515782136,"s":"BTCUSDT","U":157,"u":160,"pu":149,"b":[["0.0024","10"]],"a":[["0.0026","100"]]}}"#,
+    r#"{"stream":"bnbusdt@kline_1m","data":{"e":"kline","E":1672515780000,"s":"BNBUSDT","k":{"t":1672515780000,"T":1672515839999,"s":"BNBUSDT","i":"1m","f":100,"L":200,"o":"0.0010","c":"0.0020","h":"0.0025","l":"0.0015","v":"1000","n":100,"x":false,"q":"1.0000","V":"500","Q":"0.500","B":"123456"}}}"#,
+    r#"{"e":"executionReport","E":1499405658658,"s":"ETHBTC","c":"4294967297","S":"BUY","o":"LIMIT","f":"GTC","q":"1.00000000","p":"0.10264410","P":"0.00000000","F":"0.00000000","g":-1,"C":"","x":"TRADE","X":"PARTIALLY_FILLED","r":"NONE","i":4293153,"l":"0.50000000","z":"0.50000000","L":"0.10264410","n":"0.0001","N":"BNB","T":1499405658657,"t":7432,"I":8641984,"w":true,"m":false,"M":false,"O":1499405658657,"Z":"0.05132205","Y":"0.05132205","Q":"0.00000000","W":1499405658657,"V":"NONE"}"#,
+    r#"{"e":"ORDER_TRADE_UPDATE","E":1568879465651,"T":1568879465650,"o":{"s":"BTCUSDT","c":"4294967298","S":"SELL","o":"LIMIT","f":"GTC","q":"0.001","p":"9910","ap":"0","sp":"0","x":"NEW","X":"NEW","i":8886774,"l":"0","z":"0","L":"0","N":"USDT","n":"0","T":1568879465650,"t":0,"b":"0","a":"9.91","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"LIMIT","ps":"BOTH","cp":false,"rp":"0","pP":false,"si":0,"ss":0,"V":"NONE","pm":"NONE","gtd":0}}"#,
+    r#"{"e":"ACCOUNT_UPDATE","E":1564745798939,"T":1564745798938,"a":{"m":"ORDER","B":[{"a":"USDT","wb":"122624.12345678","cw":"100.12345678","bc":"50.12345678"}],"P":[{"s":"BTCUSDT","pa":"0","ep":"0.00000","bep":"0","cr":"200","up":"0","mt":"isolated","iw":"0.00000000","ps":"BOTH"}]}}"#,
+    r#"{"e":"balanceUpdate","E":1573200697110,"a":"BTC","d":"100.00000000","T":1573200697068}"#,
+    r#"{"e":"outboundAccountPosition","E":1564034571105,"u":1564034571073,"B":[{"a":"ETH","f":"10000.000000","l":"0.000000"}]}"#,
+    r#"{"id":1,"result":null}"#,
+    r#"{"id":2,"error":{"code":-1121,"msg":"Invalid symbol."}}"#,
+    r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":1672515782136,"s":"BTCUSDT","a":12345,"p":"0.001","q":"100","f":100,"l":105,"T":1672515782136,"m":true,"M":true}}"#,
+    r#"{"stream":"btcusdt@markPrice@1s","data":{"e":"markPriceUpdate","E":1562305380000,"s":"BTCUSDT","p":"11794.15000000","i":"11784.62659091","P":"11784.25641265","r":"0.00038167","T":1562306400000}}"#,
+];
+
+/// xorshift，保证每次运行的变异相同，失败可以复现
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+fn nodes(value: &Value, path: &mut Vec<Value>, all: &mut Vec<Vec<Value>>) {
+    all.push(path.clone());
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                path.push(json!(k));
+                nodes(v, path, all);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                path.push(json!(i));
+                nodes(v, path, all);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+fn at<'a>(value: &'a mut Value, path: &[Value]) -> &'a mut Value {
+    path.iter().fold(value, |v, key| match key {
+        Value::String(k) => &mut v[k.as_str()],
+        _ => &mut v[key.as_u64().unwrap_or_default() as usize],
+    })
+}
+
+fn mutate(value: &mut Value, rng: &mut Rng) {
+    let mut all = Vec::new();
+    nodes(value, &mut Vec::new(), &mut all);
+    let path = &all[rng.below(all.len())];
+    let huge = [
+        json!(u64::MAX),
+        json!(i64::MIN),
+        json!(1e308),
+        json!(-1e308),
+        json!(-1),
+        json!(0.5),
+    ];
+    let strings = [
+        "",
+        "NaN",
+        "1e999",
+        "-0",
+        "abc",
+        "99999999999999999999999999",
+        "@",
+    ];
+
+    match rng.below(6) {
+        0 => {
+            if let Some((last, parent)) = path.split_last() {
+                match (at(value, parent), last) {
+                    (Value::Object(map), Value::String(k)) => {
+                        map.remove(k);
+                    }
+                    (Value::Array(items), i) => {
+                        let i = i.as_u64().unwrap_or_default() as usize;
+                        if i < items.len() {
+                            items.remove(i);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        1 => *at(value, path) = Value::Null,
+        2 => *at(value, path) = huge[rng.below(huge.len())].clone(),
+        3 => *at(value, path) = json!(strings[rng.below(strings.len())]),
+        4 => {
+            if let Value::Object(map) = at(value, path) {
+                map.insert(format!("unknown{}", rng.below(100)), json!({"x": [1, "2"]}));
+            }
+        }
+        _ => {
+            let node = at(value, path);
+            *node = match &*node {
+                Value::Bool(b) => json!(*b as u8),
+                Value::Number(_) => json!(true),
+                Value::Array(items) => json!({"0": items.first().cloned()}),
+                Value::Object(_) => json!([]),
+                other => json!([other.clone()]),
+            };
+        }
+    }
+}
+
+fn order<T: OrderTrait + Clone + Into<SOrder>>(order: &T) {
+    let _ = order.trd_vol();
+    let _ = order.net();
+    order.trd_prc();
+    order.commission();
+    order.fill();
+    let order: SOrder = order.clone().into();
+    serde_json::to_string(&order).unwrap();
+}
+
+fn stream(stream: MarketStream) {
+    match stream {
+        MarketStream::BookTicker(book) => {
+            serde_json::to_string(&book).unwrap();
+        }
+        MarketStream::Kline(kline) => {
+            serde_json::to_string(&SGeneralKline::from(kline)).unwrap();
+        }
+        MarketStream::SpotDepth(depth) => {
+            serde_json::to_string(&SGeneralDepth::<BinanceQuote>::from(depth)).unwrap();
+        }
+        MarketStream::FutureDepth(depth) => {
+            serde_json::to_string(&SGeneralDepth::<BinanceQuote>::from(depth)).unwrap();
+        }
+        MarketStream::AggTrade(trade) => {
+            trade.signed_quantity();
+        }
+        MarketStream::MarkPrice(mark) => {
+            mark.funding_rate();
+        }
+    }
+}
+
+fn user(event: UserDataEvent) {
+    if let UserDataEvent::ExecutionReport(report) = event {
+        order::<ExecutionReport>(&report);
+    }
+}
+
+/// 解码、转换、序列化，只允许返回错误
+fn exercise(value: &Value) {
+    for event in [
+        serde_json::from_value::<Event>(value.clone()).ok(),
+        serde_json::from_str::<Event>(&value.to_string()).ok(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        match event {
+            Event::Stream(s) => stream(s),
+            Event::UserDataEvent(e) => user(e),
+            Event::OrderUpdate(update) => order::<OrderUpdate>(&update),
+            Event::AccountUpdate(update) => {
+                for position in update.a.P.iter() {
+                    serde_json::to_string(&Position::from(position)).unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+    if let Ok(s) = serde_json::from_value::<MarketStream>(value.clone()) {
+        stream(s);
+    }
+    if let Ok(e) = serde_json::from_value::<UserDataEvent>(value.clone()) {
+        user(e);
+    }
+}
+
+#[test]
+fn test_mutated_payloads() {
+    let mut rng = Rng(0x9E3779B97F4A7C15);
+    for seed in SEEDS {
+        let value: Value = serde_json::from_str(seed).unwrap();
+        exercise(&value);
+        for _ in 0..500 {
+            let mut mutated = value.clone();
+            for _ in 0..=rng.below(3) {
+                mutate(&mut mutated, &mut rng);
+            }
+            let text = mutated.to_string();
+            let result = std::panic::catch_unwind(|| exercise(&mutated));
+            assert!(result.is_ok(), "panicked on {}", text);
+        }
+
+        // 截断的文本
+        for _ in 0..50 {
+            let cut = rng.below(seed.len());
+            if seed.is_char_boundary(cut) {
+                assert!(serde_json::from_str::<Event>(&seed[..cut]).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_unknown_enums() {
+    // 现货与合约新增的订单类型和有效方式不能让回报转换 panic
+    for (o, f) in [
+        ("STOP_MARKET", "GTX"),
+        ("TRAILING_STOP_MARKET", "GTD"),
+        ("??", ""),
+    ] {
+        let mut value: Value = serde_json::from_str(SEEDS[5]).unwrap();
+        value["o"]["o"] = json!(o);
+        value["o"]["f"] = json!(f);
+        let update: OrderUpdate = serde_json::from_value(value).unwrap();
+        order(&update);
+    }
+}
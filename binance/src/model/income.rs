@@ -0,0 +1,70 @@
+use cryptoflow::income::Income;
+use serde::{Deserialize, Serialize};
+
+use super::deserialize_symbol;
+
+/// GET /fapi/v1/income 返回的一条流水
+/// See: https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/account/rest-api/Get-Income-History
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceIncome {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String,
+    pub incomeType: String,
+    pub income: String,
+    pub asset: String,
+    pub info: String,
+    pub time: i64,
+    pub tranId: i64,
+    #[serde(default)]
+    pub tradeId: String,
+}
+
+impl From<BinanceIncome> for Income {
+    fn from(value: BinanceIncome) -> Self {
+        Self {
+            tran_id: value.tranId,
+            symbol: value.symbol,
+            income_type: value.incomeType,
+            income: value.income.parse().unwrap_or_default(),
+            asset: value.asset,
+            time: value.time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binance_income() {
+        let s = r#"[
+            {
+                "symbol": "",
+                "incomeType": "TRANSFER",
+                "income": "-0.37500000",
+                "asset": "USDT",
+                "info": "TRANSFER",
+                "time": 1570608000000,
+                "tranId": 9689322392,
+                "tradeId": ""
+            },
+            {
+                "symbol": "BTCUSDT",
+                "incomeType": "COMMISSION",
+                "income": "-0.01000000",
+                "asset": "USDT",
+                "info": "COMMISSION",
+                "time": 1570636800000,
+                "tranId": 9689322392,
+                "tradeId": "2059192"
+            }
+        ]"#;
+        let incomes: Vec<BinanceIncome> = serde_json::from_str(s).unwrap();
+        let income: Income = incomes[1].clone().into();
+        assert_eq!(income.symbol, "btcusdt");
+        assert_eq!(income.income_type, "COMMISSION");
+        assert_eq!(income.income, -0.01);
+        assert_eq!(income.tran_id, 9689322392);
+    }
+}
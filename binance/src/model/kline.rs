@@ -66,6 +66,7 @@ impl From<BinanceKline> for SGeneralKline {
             is_closed: value.data.k.x,                          // K线是否完结
             buy_volume: value.data.k.V.parse().unwrap_or_default(), // 主动买入成交量
             buy_amount: value.data.k.Q.parse().unwrap_or_default(), // 主动买入成交额
+            backfill: false,
         }
     }
 }
@@ -120,7 +121,7 @@ mod tests {
         assert_eq!(kline.data.k.l, "0.0015");
         assert_eq!(kline.data.k.v, "1000");
         assert_eq!(kline.data.k.n, 100);
-        assert_eq!(kline.data.k.x, false);
+        assert!(!kline.data.k.x);
         assert_eq!(kline.data.k.q, "1.0000");
         assert_eq!(kline.data.k.V, "500");
         assert_eq!(kline.data.k.Q, "0.500");
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use super::deserialize_symbol;
+
+/// GET /fapi/v1/leverageBracket 返回的一个交易对的杠杆分层，名义价值越大允许的杠杆越低
+/// See: https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/account/rest-api/Notional-and-Leverage-Brackets
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceLeverageBracket {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String,
+    /// 用户分层相对默认分层的倍数，仅部分账户返回
+    #[serde(default)]
+    pub notionalCoef: Option<f64>,
+    pub brackets: Vec<Bracket>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Bracket {
+    pub bracket: u32,
+    /// 这一层的最高杠杆
+    pub initialLeverage: u16,
+    /// 这一层名义价值的上限
+    pub notionalCap: f64,
+    pub notionalFloor: f64,
+    pub maintMarginRatio: f64,
+    /// 维持保证金速算数
+    pub cum: f64,
+}
+
+/// 带 symbol 查询时返回单个对象，不带时返回数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LeverageBrackets {
+    One(BinanceLeverageBracket),
+    All(Vec<BinanceLeverageBracket>),
+}
+
+impl From<LeverageBrackets> for Vec<BinanceLeverageBracket> {
+    fn from(value: LeverageBrackets) -> Self {
+        match value {
+            LeverageBrackets::One(bracket) => vec![bracket],
+            LeverageBrackets::All(brackets) => brackets,
+        }
+    }
+}
+
+/// 杠杆 `leverage` 下允许的最大名义价值，即最高杠杆不低于它的分层中最大的上限；杠杆高于所有分层时为 None
+pub fn max_notional(brackets: &[Bracket], leverage: u16) -> Option<f64> {
+    brackets
+        .iter()
+        .filter(|b| b.initialLeverage >= leverage)
+        .map(|b| b.notionalCap)
+        .reduce(f64::max)
+}
+
+/// 检查下单后持仓的名义价值是否超过杠杆分层的上限，减仓的订单不检查
+///
+/// `position` 为下单前的持仓，`quantity` 带方向
+pub fn check_notional(
+    brackets: &[Bracket],
+    leverage: u16,
+    position: f64,
+    quantity: f64,
+    price: f64,
+) -> Result<(), String> {
+    let after = position + quantity;
+    if after.abs() <= position.abs() || price <= 0.0 {
+        return Ok(());
+    }
+    let Some(cap) = max_notional(brackets, leverage) else {
+        return Ok(());
+    };
+    let notional = after.abs() * price;
+    if notional > cap {
+        return Err(format!(
+            "position notional {} exceeds {} allowed at leverage {}x",
+            notional, cap, leverage
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binance_leverage_bracket() {
+        let s = r#"[{
+            "symbol": "ETHUSDT",
+            "notionalCoef": 1.50,
+            "brackets": [
+                {"bracket": 1, "initialLeverage": 75, "notionalCap": 10000, "notionalFloor": 0, "maintMarginRatio": 0.0065, "cum": 0},
+                {"bracket": 2, "initialLeverage": 50, "notionalCap": 50000, "notionalFloor": 10000, "maintMarginRatio": 0.01, "cum": 35}
+            ]
+        }]"#;
+        let brackets: Vec<BinanceLeverageBracket> =
+            serde_json::from_str::<LeverageBrackets>(s).unwrap().into();
+        assert_eq!(brackets[0].symbol, "ethusdt");
+        assert_eq!(brackets[0].notionalCoef, Some(1.5));
+        assert_eq!(brackets[0].brackets[1].initialLeverage, 50);
+        assert_eq!(brackets[0].brackets[1].cum, 35.0);
+
+        let s = r#"{"symbol": "BTCUSDT", "brackets": []}"#;
+        let brackets: Vec<BinanceLeverageBracket> =
+            serde_json::from_str::<LeverageBrackets>(s).unwrap().into();
+        assert_eq!(brackets.len(), 1);
+        assert_eq!(brackets[0].notionalCoef, None);
+    }
+
+    #[test]
+    fn test_check_notional() {
+        let bracket = |initial_leverage, notional_cap| Bracket {
+            bracket: 0,
+            initialLeverage: initial_leverage,
+            notionalCap: notional_cap,
+            notionalFloor: 0.0,
+            maintMarginRatio: 0.0,
+            cum: 0.0,
+        };
+        let brackets = vec![bracket(75, 10000.0), bracket(50, 50000.0)];
+        assert_eq!(max_notional(&brackets, 20), Some(50000.0));
+        assert_eq!(max_notional(&brackets, 75), Some(10000.0));
+        assert_eq!(max_notional(&brackets, 100), None);
+
+        // 75 倍杠杆下 10000 的上限
+        assert!(check_notional(&brackets, 75, 0.0, 4.0, 2500.0).is_ok());
+        assert!(check_notional(&brackets, 75, 0.0, 4.1, 2500.0).is_err());
+        assert!(check_notional(&brackets, 75, 5.0, -4.0, 2500.0).is_ok());
+        assert!(check_notional(&brackets, 75, -1.0, -4.0, 2500.0).is_err());
+        assert!(check_notional(&brackets, 50, -1.0, -4.0, 2500.0).is_ok());
+        // 减仓或反手后名义价值变小的不检查
+        assert!(check_notional(&brackets, 75, 8.0, -12.0, 2500.0).is_ok());
+        assert!(check_notional(&brackets, 75, 0.0, 100.0, 0.0).is_ok());
+    }
+}
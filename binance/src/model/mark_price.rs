@@ -0,0 +1,57 @@
+//! see: https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/websocket-market-streams/Mark-Price-Stream
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinanceMarkPrice {
+    pub stream: String,
+    pub data: BinanceMarkPriceData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct BinanceMarkPriceData {
+    pub e: String, // Event type
+    pub E: i64,    // Event time
+    pub s: String, // Symbol
+    pub p: String, // Mark price
+    pub r: String, // Funding rate
+    pub T: i64,    // Next funding time
+}
+
+impl BinanceMarkPrice {
+    pub fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    pub fn funding_rate(&self) -> f64 {
+        self.data.r.parse().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binance_mark_price() {
+        let s = r#"{
+                        "stream": "btcusdt@markPrice@1s",
+                        "data": {
+                            "e": "markPriceUpdate",
+                            "E": 1562305380000,
+                            "s": "BTCUSDT",
+                            "p": "11794.15000000",
+                            "i": "11784.62659091",
+                            "P": "11784.25641265",
+                            "r": "0.00038167",
+                            "T": 1562306400000
+                        }
+                    }"#;
+        let mark: BinanceMarkPrice = serde_json::from_str(s).unwrap();
+        assert_eq!(mark.stream(), "btcusdt@markPrice@1s");
+        assert_eq!(mark.data.s, "BTCUSDT");
+        assert_eq!(mark.funding_rate(), 0.00038167);
+        assert_eq!(mark.data.T, 1562306400000);
+    }
+}
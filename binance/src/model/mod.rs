@@ -1,24 +1,37 @@
 #![allow(non_snake_case)]
+pub mod aggtrade;
 pub mod bookticker;
 pub mod depth;
+pub mod envelope;
 pub mod exchangeinfo;
 pub mod filter;
+pub mod funding;
+pub mod income;
 pub mod kline;
+pub mod leverage;
+pub mod mark_price;
+pub mod my_trade;
+pub mod option;
 pub mod order;
 pub mod quote;
 pub mod session;
 pub mod symbol;
 pub mod user_data;
 
+#[cfg(test)]
+mod fuzz;
+
 use cryptoflow::chat::*;
 use native_json::json;
 use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
     model::{
+        aggtrade::BinanceAggTrade,
         bookticker::BinanceBookTicker,
         depth::{BinanceFutureDepth, BinanceSpotDepth},
         kline::BinanceKline,
+        mark_price::BinanceMarkPrice,
         order::usdt::OrderUpdate,
         user_data::UserDataEvent,
     },
@@ -27,11 +40,14 @@ use crate::{
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum MarketStream {
     BookTicker(BinanceBookTicker),
     SpotDepth(BinanceSpotDepth),
     FutureDepth(BinanceFutureDepth),
     Kline(BinanceKline),
+    AggTrade(BinanceAggTrade),
+    MarkPrice(BinanceMarkPrice),
 }
 
 // 用户数据事件结构体已移动到 user_data.rs 模块
@@ -63,6 +79,7 @@ pub struct ExecutionReport {
     pub n: String, // 手续费数量
     pub N: Option<String>, // 手续费资产类别
     pub T: i64,    // 成交时间
+    pub t: i64,    // 成交ID
     pub I: i64,    // Execution ID
     pub w: bool,   // 订单是否在订单簿上？
     pub m: bool,   // 该成交是作为挂单成交吗？
@@ -110,18 +127,50 @@ impl OrderTrait for ExecutionReport {
     fn state(&self) -> State {
         self.X
     }
+    fn set_state(&mut self, state: State) {
+        self.X = state;
+    }
+    fn exchange_order_id(&self) -> i64 {
+        self.i
+    }
+    fn update_time(&self) -> i64 {
+        self.T
+    }
     fn symbol(&self) -> &str {
         self.s.as_str()
     }
     fn trd_vol(&self) -> anyhow::Result<f64> {
         Ok(self.l.parse::<f64>()?)
     }
+    fn trd_prc(&self) -> f64 {
+        self.L.parse::<f64>().unwrap_or(0.0)
+    }
+    fn fill(&self) -> Option<SFill> {
+        let quantity = self.trd_vol().ok().filter(|q| *q > 0.0)?;
+        if self.x != "TRADE" {
+            return None;
+        }
+        let client_order_id = self.c.parse::<u64>().unwrap_or_default();
+        Some(SFill {
+            internal_id: (client_order_id & 0xFFFFFFFF) as u32,
+            order_id: self.i,
+            symbol: self.s.clone(),
+            side: self.S,
+            trade_id: self.t,
+            time: self.T,
+            price: self.trd_prc(),
+            quantity,
+            maker: self.m,
+            commission: self.commission(),
+            commission_asset: self.N.clone().unwrap_or_default(),
+        })
+    }
 }
 
 impl From<ExecutionReport> for SOrder {
     fn from(value: ExecutionReport) -> Self {
         let client_order_id = match value.X {
-            State::CANCELED => &value.C,
+            State::CANCELED | State::EXPIRED_BY_GATEWAY => &value.C,
             _ => &value.c,
         }
         .parse::<u64>()
@@ -134,8 +183,8 @@ impl From<ExecutionReport> for SOrder {
             order_id: value.i,
             symbol: value.s,
             side: value.S,
-            order_type: value.o.parse().unwrap(),
-            tif: value.f.parse().unwrap(),
+            order_type: order_type(&value.o),
+            tif: time_in_force(&value.f),
             price: value.p.parse().unwrap_or_default(),
             quantity: value.q.parse().unwrap_or_default(),
             trade_time: value.T,
@@ -207,6 +256,50 @@ json! {
     }
 }
 
+impl From<&UsdtPosition> for Position {
+    fn from(value: &UsdtPosition) -> Self {
+        Self {
+            side: parse_name(&value.ps),
+            entry_price: value.ep.parse().ok(),
+            unrealized_pnl: value.up.parse().ok(),
+            margin_type: parse_name(&value.mt),
+            ..Position::new(&value.s.to_lowercase(), 0.0)
+        }
+    }
+}
+
+// https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/trade/rest-api/Position-Information-V2
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PositionRisk {
+    pub symbol: String,
+    pub positionAmt: String,
+    pub entryPrice: String,
+    pub unRealizedProfit: String,
+    pub leverage: String,
+    /// 统一账户只有全仓，没有该字段
+    #[serde(default)]
+    pub marginType: String,
+    pub positionSide: String,
+}
+
+impl From<&PositionRisk> for Position {
+    fn from(value: &PositionRisk) -> Self {
+        Self {
+            side: parse_name(&value.positionSide),
+            entry_price: value.entryPrice.parse().ok(),
+            unrealized_pnl: value.unRealizedProfit.parse().ok(),
+            margin_type: parse_name(&value.marginType),
+            leverage: value.leverage.parse().ok(),
+            ..Position::new(&value.symbol.to_lowercase(), 0.0)
+        }
+    }
+}
+
+/// 按 serde 的名称与别名解析交易所的枚举字符串
+fn parse_name<T: serde::de::DeserializeOwned>(s: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(s.into())).ok()
+}
+
 json! {
     Asset {
         a: String,
@@ -223,7 +316,7 @@ json! {
         a: {
             m: String,
             B: [Asset],
-            P: [Position]
+            P: [UsdtPosition]
         }
     }
 }
@@ -316,7 +409,7 @@ pub struct EventMessage {
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(untagged)]
-#[allow(unused)]
+#[allow(unused, clippy::large_enum_variant)]
 pub enum Event {
     // market
     Success(Response<Option<i64>>),
@@ -341,6 +434,22 @@ pub enum Event {
     RiskLevelChange(RiskLevelChange),
 }
 
+/// 回报中的订单类型，未知的类型按 LIMIT 推送给策略端
+pub(crate) fn order_type(s: &str) -> OrderType {
+    s.parse().unwrap_or_else(|_| {
+        tracing::warn!("Unknown order type {}", s);
+        OrderType::LIMIT
+    })
+}
+
+/// 回报中的有效方式，未知的方式按 GTC 推送给策略端
+pub(crate) fn time_in_force(s: &str) -> TimeInForce {
+    s.parse().unwrap_or_else(|_| {
+        tracing::warn!("Unknown time in force {}", s);
+        TimeInForce::GTC
+    })
+}
+
 pub fn deserialize_symbol<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>,
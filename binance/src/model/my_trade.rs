@@ -0,0 +1,92 @@
+use cryptoflow::my_trades::MyTrade;
+use serde::{Deserialize, Serialize};
+
+use super::deserialize_symbol;
+
+/// GET /api/v3/myTrades 与 GET /fapi/v1/userTrades 返回的一笔成交，现货的 isBuyer、isMaker 在合约中为 buyer、maker
+/// See: https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/trade/rest-api/Account-Trade-List
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceMyTrade {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String,
+    pub id: i64,
+    pub orderId: i64,
+    pub price: String,
+    pub qty: String,
+    pub quoteQty: String,
+    pub commission: String,
+    pub commissionAsset: String,
+    pub time: i64,
+    #[serde(alias = "isBuyer")]
+    pub buyer: bool,
+    #[serde(alias = "isMaker")]
+    pub maker: bool,
+}
+
+impl From<BinanceMyTrade> for MyTrade {
+    fn from(value: BinanceMyTrade) -> Self {
+        Self {
+            symbol: value.symbol,
+            trade_id: value.id,
+            order_id: value.orderId,
+            side: if value.buyer { "BUY" } else { "SELL" }.into(),
+            price: value.price.parse().unwrap_or_default(),
+            quantity: value.qty.parse().unwrap_or_default(),
+            quote_quantity: value.quoteQty.parse().unwrap_or_default(),
+            commission: value.commission.parse().unwrap_or_default(),
+            commission_asset: value.commissionAsset,
+            maker: value.maker,
+            time: value.time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binance_my_trade() {
+        let spot = r#"{
+            "symbol": "BNBBTC",
+            "id": 28457,
+            "orderId": 100234,
+            "orderListId": -1,
+            "price": "4.00000100",
+            "qty": "12.00000000",
+            "quoteQty": "48.000012",
+            "commission": "10.10000000",
+            "commissionAsset": "BNB",
+            "time": 1499865549590,
+            "isBuyer": true,
+            "isMaker": false,
+            "isBestMatch": true
+        }"#;
+        let usdt = r#"{
+            "buyer": false,
+            "commission": "-0.07819010",
+            "commissionAsset": "USDT",
+            "id": 698759,
+            "maker": true,
+            "orderId": 25851813,
+            "price": "7819.01",
+            "qty": "0.002",
+            "quoteQty": "15.63802",
+            "realizedPnl": "-0.91539999",
+            "side": "SELL",
+            "positionSide": "SHORT",
+            "symbol": "BTCUSDT",
+            "time": 1569514978020
+        }"#;
+        let trade: MyTrade = serde_json::from_str::<BinanceMyTrade>(spot).unwrap().into();
+        assert_eq!(
+            (trade.symbol.as_str(), trade.side.as_str(), trade.maker),
+            ("bnbbtc", "BUY", false)
+        );
+        assert_eq!((trade.trade_id, trade.quantity), (28457, 12.0));
+
+        let trade: MyTrade = serde_json::from_str::<BinanceMyTrade>(usdt).unwrap().into();
+        assert_eq!((trade.side.as_str(), trade.maker), ("SELL", true));
+        assert_eq!(trade.commission, -0.0781901);
+    }
+}
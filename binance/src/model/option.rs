@@ -0,0 +1,255 @@
+//! 期权（eapi）的合约信息与行情
+//! see: https://developers.binance.com/docs/zh-CN/derivatives/option/market-data/Exchange-Information
+
+use cryptoflow::chat::SOptionGreeks;
+use serde::{Deserialize, Serialize};
+
+use super::deserialize_symbol;
+use crate::model::filter::FilterField;
+use crate::model::symbol::{BinanceSymbol, ConctactStatus};
+
+/// 期权类型
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptionKind {
+    CALL,
+    PUT,
+}
+
+/// 期权合约的要素，随交易规则一起推送给策略端
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OptionContract {
+    /// 标的，如 btcusdt
+    pub underlying: String,
+    /// 行权价
+    pub strike: f64,
+    /// 到期时间（毫秒）
+    pub expiry: u64,
+    pub kind: OptionKind,
+    /// 每张合约对应的标的数量
+    pub unit: f64,
+}
+
+/// `/eapi/v1/exchangeInfo` 中的 `optionSymbols`
+#[derive(Debug, Deserialize, Clone)]
+pub struct BinanceOptionSymbol {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String, // 如 BTC-250627-100000-C
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub underlying: String,
+    pub side: OptionKind,
+    pub strikePrice: String,
+    pub expiryDate: u64,
+    #[serde(default)]
+    pub unit: f64,
+    pub quoteAsset: String,
+    #[serde(default)]
+    pub priceScale: u8,
+    #[serde(default)]
+    pub quantityScale: u8,
+    #[serde(default)]
+    pub status: Option<ConctactStatus>,
+    pub filters: Vec<FilterField>,
+}
+
+impl From<BinanceOptionSymbol> for BinanceSymbol {
+    fn from(value: BinanceOptionSymbol) -> Self {
+        let quote = value.quoteAsset.to_lowercase();
+        let base = value
+            .underlying
+            .strip_suffix(&quote)
+            .unwrap_or(&value.underlying)
+            .to_uppercase();
+        BinanceSymbol {
+            symbol: value.symbol,
+            status: value.status.unwrap_or(ConctactStatus::TRADING),
+            baseAsset: base,
+            baseAssetPrecision: value.quantityScale,
+            quoteAsset: value.quoteAsset,
+            quotePrecision: value.priceScale,
+            quoteAssetPrecision: value.priceScale,
+            baseCommissionPrecision: 0,
+            quoteCommissionPrecision: 0,
+            orderTypes: vec!["LIMIT".into()],
+            icebergAllowed: false,
+            ocoAllowed: false,
+            otoAllowed: false,
+            quoteOrderQtyMarketAllowed: false,
+            allowTrailingStop: false,
+            cancelReplaceAllowed: false,
+            amendAllowed: false,
+            pegInstructionsAllowed: false,
+            isSpotTradingAllowed: false,
+            isMarginTradingAllowed: false,
+            filters: value.filters,
+            permissions: Vec::new(),
+            permissionSets: Vec::new(),
+            defaultSelfTradePreventionMode: String::new(),
+            allowedSelfTradePreventionModes: Vec::new(),
+            deliveryDate: Some(value.expiryDate),
+            onboardDate: None,
+            leverageBrackets: Vec::new(),
+            pair: String::new(),
+            contractType: String::new(),
+            option: Some(OptionContract {
+                underlying: value.underlying,
+                strike: value.strikePrice.parse().unwrap_or_default(),
+                expiry: value.expiryDate,
+                kind: value.side,
+                unit: value.unit,
+            }),
+        }
+    }
+}
+
+/// 期权 `24hrTicker` 与 `markPrice` 共有的标记价格与希腊值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceOptionGreeksData {
+    pub E: i64,
+    pub s: String,
+    pub mp: String, // 标记价格
+    #[serde(default)]
+    pub vo: String, // 标记价格的隐含波动率
+    #[serde(default)]
+    pub b: String, // 买一价的隐含波动率
+    #[serde(default)]
+    pub a: String, // 卖一价的隐含波动率
+    #[serde(default)]
+    pub d: String, // delta
+    #[serde(default)]
+    pub g: String, // gamma
+    #[serde(default)]
+    pub t: String, // theta
+    #[serde(default)]
+    pub v: String, // vega
+}
+
+/// `{SYMBOL}@ticker`，单个期权
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceOptionTicker {
+    pub stream: String,
+    pub data: BinanceOptionGreeksData,
+}
+
+/// `{ASSET}@markPrice`，标的下所有期权
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BinanceOptionMark {
+    pub stream: String,
+    pub data: Vec<BinanceOptionGreeksData>,
+}
+
+/// 期权行情连接推送的流
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum OptionStream {
+    Ticker(BinanceOptionTicker),
+    Mark(BinanceOptionMark),
+}
+
+impl OptionStream {
+    pub fn stream(&self) -> &String {
+        match self {
+            Self::Ticker(ticker) => &ticker.stream,
+            Self::Mark(mark) => &mark.stream,
+        }
+    }
+}
+
+fn greeks(data: BinanceOptionGreeksData, stream: String) -> SOptionGreeks {
+    let float = |s: &str| s.parse().unwrap_or_default();
+    SOptionGreeks {
+        time: data.E,
+        symbol: data.s.to_lowercase(),
+        stream,
+        mark_price: float(&data.mp),
+        mark_iv: float(&data.vo),
+        bid_iv: float(&data.b),
+        ask_iv: float(&data.a),
+        delta: float(&data.d),
+        gamma: float(&data.g),
+        theta: float(&data.t),
+        vega: float(&data.v),
+    }
+}
+
+impl From<OptionStream> for Vec<SOptionGreeks> {
+    fn from(value: OptionStream) -> Self {
+        match value {
+            OptionStream::Ticker(ticker) => {
+                let stream = format!("{}@greeks", ticker.data.s.to_lowercase());
+                vec![greeks(ticker.data, stream)]
+            }
+            OptionStream::Mark(mark) => {
+                let asset = mark.stream.split('@').next().unwrap_or_default();
+                let stream = format!("{}@mark", asset.to_lowercase());
+                mark.data
+                    .into_iter()
+                    .map(|data| greeks(data, stream.clone()))
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::trading_rules::TradingRules;
+
+    #[test]
+    fn test_option_symbol() {
+        let s = r#"{"contractId":2,"expiryDate":1751011200000,"filters":[
+            {"filterType":"PRICE_FILTER","minPrice":"5","maxPrice":"100000","tickSize":"5"},
+            {"filterType":"LOT_SIZE","minQty":"0.01","maxQty":"1000","stepSize":"0.01"}],
+            "id":17,"symbol":"BTC-250627-100000-C","side":"CALL","strikePrice":"100000.000",
+            "underlying":"BTCUSDT","unit":1,"makerFeeRate":"0.0002","takerFeeRate":"0.0002",
+            "minQty":"0.01","maxQty":"1000","initialMargin":"0.15","maintenanceMargin":"0.075",
+            "minInitialMargin":"0.1","minMaintenanceMargin":"0.05","priceScale":0,
+            "quantityScale":2,"quoteAsset":"USDT"}"#;
+        let option: BinanceOptionSymbol = serde_json::from_str(s).unwrap();
+        let product = BinanceSymbol::from(option);
+        assert_eq!(product.symbol, "btc-250627-100000-c");
+        assert_eq!(product.baseAsset, "BTC");
+        assert_eq!(product.tick_size(), 5.0);
+        assert_eq!(product.lot_size(), 0.01);
+        assert_eq!(product.deliveryDate, Some(1751011200000));
+        let contract = product.option.clone().unwrap();
+        assert_eq!(contract.underlying, "btcusdt");
+        assert_eq!(contract.strike, 100000.0);
+        assert_eq!(contract.kind, OptionKind::CALL);
+
+        // 现货与合约的交易规则不带期权要素
+        let value = serde_json::to_value(&product).unwrap();
+        assert_eq!(value["option"]["kind"], "CALL");
+        let spot: BinanceSymbol = serde_json::from_value(value).unwrap();
+        assert_eq!(spot.option, product.option);
+    }
+
+    #[test]
+    fn test_option_greeks() {
+        let s = r#"{"stream":"ETH@markPrice","data":[
+            {"e":"markPrice","E":1663684594227,"s":"ETH-220930-1500-C","mp":"30.3","hl":"997.8",
+             "ll":"0.1","vo":"0.512","rf":"0","d":"0.55","t":"-2.1","g":"0.0012","v":"1.57"},
+            {"e":"markPrice","E":1663684594227,"s":"ETH-220930-1500-P","mp":"12.1"}]}"#;
+        let stream: OptionStream = serde_json::from_str(s).unwrap();
+        assert_eq!(stream.stream(), "ETH@markPrice");
+        let greeks = Vec::<SOptionGreeks>::from(stream);
+        assert_eq!(greeks.len(), 2);
+        assert_eq!(greeks[0].symbol, "eth-220930-1500-c");
+        assert_eq!(greeks[0].stream, "eth@mark");
+        assert_eq!((greeks[0].mark_iv, greeks[0].delta), (0.512, 0.55));
+        assert_eq!((greeks[1].mark_price, greeks[1].vega), (12.1, 0.0));
+
+        let s = r#"{"stream":"BTC-250627-100000-C@ticker","data":{"e":"24hrTicker",
+            "E":1657706425200,"T":1657706425220,"s":"BTC-250627-100000-C","o":"1000","h":"1000",
+            "l":"1000","c":"1000","V":"2.1","A":"2100","P":"0","p":"0","Q":"0.1","F":"1","L":"2",
+            "n":2,"bo":"990","ao":"1010","bq":"1","aq":"1","b":"0.48","a":"0.52","d":"0.31",
+            "t":"-40.2","g":"0.00001","v":"95.1","vo":"0.5","mp":"1001","hl":"5000","ll":"5",
+            "eep":"0"}}"#;
+        let stream: OptionStream = serde_json::from_str(s).unwrap();
+        let greeks = Vec::<SOptionGreeks>::from(stream);
+        assert_eq!(greeks[0].stream, "btc-250627-100000-c@greeks");
+        assert_eq!((greeks[0].bid_iv, greeks[0].ask_iv), (0.48, 0.52));
+        assert_eq!(greeks[0].mark_price, 1001.0);
+    }
+}
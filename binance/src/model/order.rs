@@ -1,7 +1,8 @@
-use cryptoflow::chat::{OrderType, Side, TimeInForce};
+use crate::margin::SideEffectType;
+use cryptoflow::chat::{OrderType, PositionSide, Side, TimeInForce};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BinanceOrder {
     pub id: u32,
     pub symbol: String,
@@ -11,20 +12,140 @@ pub struct BinanceOrder {
     pub order_type: OrderType,
     pub tif: TimeInForce,
     pub session_id: u16,
+    /// 重发同一意图时保持不变，网关在时间窗口内去重
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// 订单的存活时间，超时仍未完成时由网关撤销
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u64>,
+    /// 跟随盘口的钉住订单，价格由网关按 bookTicker 维护
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg: Option<Peg>,
+    /// 双向持仓模式下开平的是哪一腿，单向持仓时不填或为 BOTH
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_side: Option<PositionSide>,
+    /// 杠杆账户下单的借还款方式，不填时使用网关配置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side_effect_type: Option<SideEffectType>,
+    /// 只做 maker 的订单会立即成交时，改为不会成交的最优价格而不是拒绝
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reprice_passive: bool,
+    /// 跳过价格与名义价值的合理性校验，见 [`crate::fat_finger`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub force: bool,
+    /// 下单的策略，同一会话运行多个策略时按它归因，见 [`crate::attribution`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    /// 订单的标签，每个标签单独归因
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// 限价 GTC 买单，可选字段都不填，构造订单时只需写出关心的字段
+impl Default for BinanceOrder {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            symbol: String::new(),
+            price: 0.0,
+            quantity: 0.0,
+            side: Side::BUY,
+            order_type: OrderType::LIMIT,
+            tif: TimeInForce::GTC,
+            session_id: 0,
+            idempotency_key: None,
+            ttl_ms: None,
+            peg: None,
+            position_side: None,
+            side_effect_type: None,
+            reprice_passive: false,
+            force: false,
+            strategy: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl BinanceOrder {
+    /// 检查 `position_side` 与账户的持仓模式是否一致，`dual_side` 为双向持仓模式
+    pub fn check_position_side(&self, dual_side: bool) -> Result<(), String> {
+        match (dual_side, self.position_side) {
+            (false, Some(side @ (PositionSide::LONG | PositionSide::SHORT))) => {
+                Err(format!("positionSide {:?} requires hedge mode", side))
+            }
+            (true, None | Some(PositionSide::BOTH)) => {
+                Err("hedge mode requires positionSide LONG or SHORT".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 只有杠杆账户的订单可以带 `side_effect_type`
+    pub fn check_side_effect(&self, margin: bool) -> Result<(), String> {
+        match (margin, self.side_effect_type) {
+            (false, Some(side_effect)) => Err(format!(
+                "sideEffectType {:?} requires margin account",
+                side_effect
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn default_threshold_ticks() -> i64 {
+    1
+}
+
+/// 买单钉在买一价下方 `offset_ticks` 个 tick，卖单钉在卖一价上方
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Peg {
+    #[serde(default)]
+    pub offset_ticks: i64,
+    /// 目标价偏离当前挂单价达到这么多个 tick 才改价
+    #[serde(default = "default_threshold_ticks")]
+    pub threshold_ticks: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct QuoteLevel {
+    /// 这一侧需要挂新单时使用的订单号
+    pub id: u32,
+    pub price: f64,
+    pub quantity: f64,
+}
+
+fn default_tif() -> TimeInForce {
+    TimeInForce::GTC
 }
 
+/// 双边报价，缺少的一侧表示撤掉该侧报价
 #[derive(Debug, Deserialize)]
+pub struct BinanceQuote {
+    pub symbol: String,
+    pub session_id: u16,
+    #[serde(default)]
+    pub bid: Option<QuoteLevel>,
+    #[serde(default)]
+    pub ask: Option<QuoteLevel>,
+    #[serde(default = "default_tif")]
+    pub tif: TimeInForce,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct BinanceCancel {
     pub symbol: String,
     pub session_id: u16,
     pub order_id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
 pub mod usdt {
-    use cryptoflow::chat::{Side, State};
+    use cryptoflow::chat::{PositionSide, SFill, Side, State};
     use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
 
-    use super::super::deserialize_symbol;
+    use super::super::{deserialize_symbol, order_type, time_in_force};
     use crate::{OrderTrait, SOrder};
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -79,6 +200,55 @@ pub mod usdt {
         pub o: OrderData, // 订单数据
     }
 
+    impl OrderUpdate {
+        /// 把 WS-API `order.place` 响应中的 NEW 订单转换为账户推送的格式，其他状态返回 None，
+        /// 成交以账户推送为准
+        pub fn from_wsapi(result: &Value) -> Option<Self> {
+            if result["status"].as_str()? != "NEW" {
+                return None;
+            }
+            let field = |key: &str| result[key].clone();
+            let time = result["updateTime"].as_i64()?;
+            let update = json!({
+                "e": "ORDER_TRADE_UPDATE",
+                "E": time,
+                "T": time,
+                "o": {
+                    "s": field("symbol"),
+                    "c": field("clientOrderId"),
+                    "S": field("side"),
+                    "o": field("type"),
+                    "f": field("timeInForce"),
+                    "q": field("origQty"),
+                    "p": field("price"),
+                    "ap": field("avgPrice"),
+                    "sp": field("stopPrice"),
+                    "x": "NEW",
+                    "X": "NEW",
+                    "i": field("orderId"),
+                    "l": "0",
+                    "z": field("executedQty"),
+                    "L": "0",
+                    "n": "0",
+                    "T": time,
+                    "t": 0,
+                    "b": "0",
+                    "a": "0",
+                    "m": false,
+                    "R": field("reduceOnly"),
+                    "wt": field("workingType"),
+                    "ot": field("origType"),
+                    "ps": field("positionSide"),
+                    "rp": "0",
+                    "V": field("selfTradePreventionMode"),
+                    "pm": field("priceMatch"),
+                    "gtd": field("goodTillDate"),
+                }
+            });
+            serde_json::from_value(update).ok()
+        }
+    }
+
     impl OrderTrait for OrderUpdate {
         fn commission(&self) -> f64 {
             self.o.n.parse::<f64>().unwrap_or(0.0)
@@ -92,12 +262,47 @@ pub mod usdt {
         fn state(&self) -> State {
             self.o.X
         }
+        fn set_state(&mut self, state: State) {
+            self.o.X = state;
+        }
+        fn exchange_order_id(&self) -> i64 {
+            self.o.i
+        }
+        fn update_time(&self) -> i64 {
+            self.T
+        }
         fn symbol(&self) -> &str {
             self.o.s.as_str()
         }
         fn trd_vol(&self) -> anyhow::Result<f64> {
             Ok(self.o.l.parse::<f64>()?)
         }
+        fn trd_prc(&self) -> f64 {
+            self.o.L.parse::<f64>().unwrap_or(0.0)
+        }
+        fn position_side(&self) -> Option<PositionSide> {
+            self.o.ps.parse().ok()
+        }
+        fn fill(&self) -> Option<SFill> {
+            let quantity = self.trd_vol().ok().filter(|q| *q > 0.0)?;
+            if self.o.x != "TRADE" {
+                return None;
+            }
+            let client_order_id = self.o.c.parse::<u64>().unwrap_or_default();
+            Some(SFill {
+                internal_id: (client_order_id & 0xFFFFFFFF) as u32,
+                order_id: self.o.i,
+                symbol: self.o.s.clone(),
+                side: self.o.S,
+                trade_id: self.o.t,
+                time: self.o.T,
+                price: self.trd_prc(),
+                quantity,
+                maker: self.o.m,
+                commission: self.commission(),
+                commission_asset: self.o.N.clone().unwrap_or_default(),
+            })
+        }
     }
 
     impl From<OrderUpdate> for SOrder {
@@ -112,8 +317,8 @@ pub mod usdt {
                 order_id: o.i,
                 symbol: o.s,
                 side: o.S,
-                order_type: o.o.parse().unwrap(),
-                tif: o.f.parse().unwrap(),
+                order_type: order_type(&o.o),
+                tif: time_in_force(&o.f),
                 price: o.p.parse().unwrap_or_default(),
                 quantity: o.q.parse().unwrap_or_default(),
                 trade_time: o.T,
@@ -125,3 +330,27 @@ pub mod usdt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_position_side() {
+        let mut order: BinanceOrder = serde_json::from_str(
+            r#"{"id":1,"symbol":"btcusdt","price":100.0,"quantity":1.0,"side":"BUY",
+            "order_type":"LIMIT","tif":"GTC","session_id":1}"#,
+        )
+        .unwrap();
+        assert!(order.check_position_side(false).is_ok());
+        assert!(order.check_position_side(true).is_err());
+
+        order.position_side = Some(PositionSide::LONG);
+        assert!(order.check_position_side(false).is_err());
+        assert!(order.check_position_side(true).is_ok());
+
+        order.position_side = Some(PositionSide::BOTH);
+        assert!(order.check_position_side(false).is_ok());
+        assert!(order.check_position_side(true).is_err());
+    }
+}
@@ -1,8 +1,11 @@
+use cryptoflow::chat::Side;
 use cryptoflow::trading_rules::TradingRules;
 use serde::{Deserialize, Serialize};
 
 use super::deserialize_symbol;
 use crate::model::filter::FilterField;
+use crate::model::leverage::Bracket;
+use crate::model::option::OptionContract;
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -22,29 +25,54 @@ pub struct BinanceSymbol {
     pub baseAssetPrecision: u8,                       // 基础资产精度
     pub quoteAsset: String,                           // 计价资产
     pub quotePrecision: u8,                           // 计价精度
+    #[serde(default)]
     pub quoteAssetPrecision: u8,                      // 计价资产精度
+    #[serde(default)]
     pub baseCommissionPrecision: u8,                  // 基础资产手续费精度
+    #[serde(default)]
     pub quoteCommissionPrecision: u8,                 // 计价资产手续费精度
     pub orderTypes: Vec<String>,                      // 支持的订单类型
+    #[serde(default)]
     pub icebergAllowed: bool,                         // 是否支持冰山订单
+    #[serde(default)]
     pub ocoAllowed: bool,                             // 是否支持OCO订单
+    #[serde(default)]
     pub otoAllowed: bool,                             // 是否支持OTO订单
+    #[serde(default)]
     pub quoteOrderQtyMarketAllowed: bool,             // 是否支持市价单按计价资产数量下单
+    #[serde(default)]
     pub allowTrailingStop: bool,                      // 是否支持跟踪止损
+    #[serde(default)]
     pub cancelReplaceAllowed: bool,                   // 是否支持撤单改单
+    #[serde(default)]
     pub amendAllowed: bool,                           // 是否支持修改订单
+    #[serde(default)]
     pub pegInstructionsAllowed: bool,                 // 是否支持挂钩指令
+    #[serde(default)]
     pub isSpotTradingAllowed: bool,                   // 是否支持现货交易
+    #[serde(default)]
     pub isMarginTradingAllowed: bool,                 // 是否支持杠杆交易
     pub filters: Vec<FilterField>,                    // 交易规则过滤器
+    #[serde(default)]
     pub permissions: Vec<String>,                     // 权限列表
+    #[serde(default)]
     pub permissionSets: Vec<Vec<String>>,             // 权限集合
+    #[serde(default)]
     pub defaultSelfTradePreventionMode: String,       // 默认自成交防护模式
+    #[serde(default)]
     pub allowedSelfTradePreventionModes: Vec<String>, // 允许的自成交防护模式
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pair: String, // 标的交易对（期货合约）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub contractType: String, // 合约类型，如 PERPETUAL、CURRENT_QUARTER（期货合约）
     #[serde(default)]
     pub deliveryDate: Option<u64>, // 交割日期（期货合约）
     #[serde(default)]
     pub onboardDate: Option<u64>, // 上线日期
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<OptionContract>, // 期权要素（期权合约）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leverageBrackets: Vec<Bracket>, // 杠杆分层（U 本位合约）
 }
 
 impl TradingRules for BinanceSymbol {
@@ -64,7 +92,8 @@ impl TradingRules for BinanceSymbol {
     fn max_price(&self) -> f64 {
         for filter in &self.filters {
             if let FilterField::PRICE_FILTER { max_price, .. } = filter {
-                return max_price.parse::<f64>().unwrap_or(f64::MAX);
+                // 0 表示不限制
+                return unlimited(max_price);
             }
         }
         f64::MAX
@@ -91,7 +120,7 @@ impl TradingRules for BinanceSymbol {
     fn max_quantity(&self) -> f64 {
         for filter in &self.filters {
             if let FilterField::LOT_SIZE { max_qty, .. } = filter {
-                return max_qty.parse::<f64>().unwrap_or(f64::MAX);
+                return unlimited(max_qty);
             }
         }
         f64::MAX
@@ -120,4 +149,82 @@ impl TradingRules for BinanceSymbol {
         }
         0.0
     }
+
+    fn market_min_quantity(&self) -> f64 {
+        for filter in &self.filters {
+            if let FilterField::MARKET_LOT_SIZE { min_qty, .. } = filter {
+                return min_qty.parse::<f64>().unwrap_or(0.0);
+            }
+        }
+        self.min_quantity()
+    }
+
+    fn market_max_quantity(&self) -> f64 {
+        for filter in &self.filters {
+            if let FilterField::MARKET_LOT_SIZE { max_qty, .. } = filter {
+                return unlimited(max_qty);
+            }
+        }
+        self.max_quantity()
+    }
+
+    /// 现货的 MARKET_LOT_SIZE 步长常为 0，不限制步长
+    fn market_lot_size(&self) -> f64 {
+        for filter in &self.filters {
+            if let FilterField::MARKET_LOT_SIZE { step_size, .. } = filter {
+                return step_size.parse::<f64>().unwrap_or(0.0);
+            }
+        }
+        self.lot_size()
+    }
+
+    fn percent_price(&self, side: &Side) -> Option<(f64, f64)> {
+        let parse = |s: &String| s.parse::<f64>().unwrap_or(0.0);
+        for filter in &self.filters {
+            match (filter, side) {
+                (FilterField::PERCENT_PRICE { multiplier_up, multiplier_down, .. }, _) => {
+                    return Some((parse(multiplier_down), parse(multiplier_up)));
+                }
+                (FilterField::PERCENT_PRICE_BY_SIDE { bid_multiplier_up, bid_multiplier_down, .. }, Side::BUY) => {
+                    return Some((parse(bid_multiplier_down), parse(bid_multiplier_up)));
+                }
+                (FilterField::PERCENT_PRICE_BY_SIDE { ask_multiplier_up, ask_multiplier_down, .. }, Side::SELL) => {
+                    return Some((parse(ask_multiplier_down), parse(ask_multiplier_up)));
+                }
+                _ => continue,
+            }
+        }
+        None
+    }
+
+    /// 合约的 PERCENT_PRICE 相对标记价格，没有分钟数
+    fn avg_price_mins(&self) -> u32 {
+        for filter in &self.filters {
+            match filter {
+                FilterField::PERCENT_PRICE { avg_price_mins, .. }
+                | FilterField::PERCENT_PRICE_BY_SIDE { avg_price_mins, .. } => {
+                    return (*avg_price_mins).max(0) as u32;
+                }
+                _ => continue,
+            }
+        }
+        0
+    }
+
+    fn max_position(&self) -> Option<f64> {
+        for filter in &self.filters {
+            if let FilterField::MAX_POSITION { max_position } = filter {
+                return max_position.parse::<f64>().ok().filter(|max| *max > 0.0);
+            }
+        }
+        None
+    }
+}
+
+/// 过滤器中的上限，0 或无法解析时不限制
+fn unlimited(max: &str) -> f64 {
+    match max.parse::<f64>() {
+        Ok(max) if max > 0.0 => max,
+        _ => f64::MAX,
+    }
 }
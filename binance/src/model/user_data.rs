@@ -122,6 +122,7 @@ pub struct ListenStatus {
 /// 用户数据事件类型
 #[derive(Debug, Deserialize, Clone)]
 #[serde(tag = "e")]
+#[allow(clippy::large_enum_variant)]
 pub enum UserDataEvent {
     /// 订单执行报告
     /// See: https://developers.binance.com/docs/zh-CN/binance-spot-api-docs/user-data-stream#%E8%AE%A2%E5%8D%95%E6%9B%B4%E6%96%B0
@@ -0,0 +1,146 @@
+//! 按需补齐账户成交历史
+//!
+//! `get_my_trades` 查询前先把本地库没有覆盖的时间段从交易所的成交接口按时间分页补齐，再从库中返回。
+//! 成交接口单次查询的时间跨度有限（现货 24 小时、合约 7 天），由 [`Paginator`] 按窗口依次查询；
+//! 结束时间晚于当前时间时只补齐到当前时间，之后的成交在下次查询时再补齐。
+
+use crate::model::my_trade::BinanceMyTrade;
+use crate::paginate::{Cursor, Paginator};
+use crate::rest::Rest;
+use cryptoflow::chat::SError;
+use cryptoflow::error_code::{INVALID_RANGE, UNDEF_ERROR};
+use cryptoflow::my_trades::{missing_ranges, MyTrade, MyTradeDB, MyTradesConfig, SMyTradesReq};
+use futures::TryStreamExt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error};
+
+const DAY_MS: i64 = 24 * 3600 * 1000;
+const LIMIT: usize = 1000;
+
+/// 校验查询的时间段，`now` 为当前毫秒时间
+fn validate(req: &SMyTradesReq, max_days: i64, now: i64) -> Result<(), SError> {
+    let invalid = |msg: String| SError {
+        code: INVALID_RANGE,
+        msg,
+    };
+    if req.start >= req.end || req.start > now {
+        return Err(invalid(format!(
+            "invalid range [{}, {})",
+            req.start, req.end
+        )));
+    }
+    if req.end.min(now) - req.start > max_days * DAY_MS {
+        return Err(invalid(format!("range exceeds {} days", max_days)));
+    }
+    Ok(())
+}
+
+pub struct MyTrades {
+    config: MyTradesConfig,
+    db: MyTradeDB,
+    path: &'static str,
+    /// 成交接口单次查询的最大时间跨度
+    window: i64,
+}
+
+impl MyTrades {
+    /// 未开启时返回 None
+    pub async fn open(
+        config: MyTradesConfig,
+        path: &'static str,
+        window: i64,
+    ) -> anyhow::Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let db = MyTradeDB::new(&config.db).await?;
+        Ok(Some(Self {
+            config,
+            db,
+            path,
+            window,
+        }))
+    }
+
+    /// 分页补齐 [start, end)，返回新写入的条数
+    async fn sync(&self, rest: &Rest, symbol: &str, start: i64, end: i64) -> anyhow::Result<u64> {
+        // 接口的 endTime 包含该毫秒
+        let cursor = Cursor::Time {
+            start,
+            end: end - 1,
+            window: Some(self.window - 1),
+        };
+        let pages = Paginator::new(self.path, cursor, |t: &BinanceMyTrade| t.time)
+            .with_params(&[("symbol".into(), symbol.to_uppercase())])
+            .with_signature(true)
+            .with_limit(LIMIT)
+            .pages(rest);
+        futures::pin_mut!(pages);
+
+        let mut rows = 0;
+        while let Some(page) = pages.try_next().await? {
+            let trades: Vec<MyTrade> = page.into_iter().map(MyTrade::from).collect();
+            rows += self.db.insert(&trades).await?;
+        }
+        self.db.mark_synced(symbol, start, end).await?;
+        Ok(rows)
+    }
+
+    async fn fetch(
+        &self,
+        rest: &Rest,
+        req: &SMyTradesReq,
+        now: i64,
+    ) -> anyhow::Result<Vec<MyTrade>> {
+        let synced = self.db.synced(&req.symbol).await?;
+        for (start, end) in missing_ranges(synced, req.start, req.end.min(now)) {
+            let rows = self.sync(rest, &req.symbol, start, end).await?;
+            debug!(
+                "Sync {} trades of {} in [{}, {})",
+                rows, req.symbol, start, end
+            );
+        }
+        self.db.query(req).await
+    }
+
+    pub async fn get(&self, rest: &Rest, req: &SMyTradesReq) -> Result<Vec<MyTrade>, SError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        validate(req, self.config.max_days, now)?;
+        self.fetch(rest, req, now).await.map_err(|e| {
+            error!("Get trades of {} failed: {}", req.symbol, e);
+            SError {
+                code: UNDEF_ERROR,
+                msg: e.to_string(),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let req = |start, end| SMyTradesReq {
+            symbol: "btcusdt".into(),
+            start,
+            end,
+        };
+        let now = 100 * DAY_MS;
+        assert!(validate(&req(now - DAY_MS, now), 1, now).is_ok());
+        // 结束时间晚于当前时间时只按当前时间计算跨度
+        assert!(validate(&req(now - DAY_MS, i64::MAX), 1, now).is_ok());
+        assert_eq!(
+            validate(&req(now - 2 * DAY_MS, now), 1, now)
+                .unwrap_err()
+                .code,
+            INVALID_RANGE
+        );
+        assert!(validate(&req(now, now), 1, now).is_err());
+        assert!(validate(&req(now + 1, now + 2), 1, now).is_err());
+    }
+}
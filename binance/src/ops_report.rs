@@ -0,0 +1,385 @@
+//! 每日运维报告
+//!
+//! 按 UTC 日期统计下单、成交、撤单与拒单数量，拒单原因分布，交易所连接的重连次数，下单请求到交易所响应
+//! 的最大延迟，按成交计算的盈亏与手续费。每隔 `snapshot_secs` 把当天的统计写入存储的 `reports` 表，
+//! 跨天后写入前一天的最终统计，开启 `notify` 时通过告警通道发送，运维不需要额外的工具就能看到前一天的汇总。
+//!
+//! 盈亏只按当天的成交计算：每个交易对的现金流加上当天净成交量按最新成交价的估值，不含隔夜持仓的价格
+//! 变动，单位为交易对的计价资产。
+
+use chrono::{DateTime, NaiveDate, Utc};
+use cryptoflow::alert::Alerter;
+use cryptoflow::chat::{SFill, Side, State};
+use cryptoflow::storage::{DailyReport, Storage};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// ```json
+/// "ops_report": {
+///     "enabled": true,
+///     "snapshot_secs": 300,
+///     "notify": true
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OpsReportConfig {
+    pub enabled: bool,
+    /// 当天的统计写入存储的间隔
+    pub snapshot_secs: u64,
+    /// 跨天后通过告警通道发送前一天的报告
+    pub notify: bool,
+}
+
+impl Default for OpsReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            snapshot_secs: 300,
+            notify: true,
+        }
+    }
+}
+
+/// 一天的统计，写入存储时序列化为 JSON
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DailySummary {
+    pub placed: u64,
+    /// 完全成交的订单
+    pub filled: u64,
+    /// 撤销或过期的订单
+    pub canceled: u64,
+    pub rejected: u64,
+    /// 拒单原因 -> 次数
+    pub reject_reasons: BTreeMap<String, u64>,
+    /// 连接 -> 断线后恢复的次数
+    pub reconnects: BTreeMap<String, u64>,
+    pub max_latency_ms: f64,
+    pub fills: u64,
+    /// 交易对 -> 盈亏
+    pub pnl: BTreeMap<String, f64>,
+    /// 手续费资产 -> 手续费
+    pub fees: BTreeMap<String, f64>,
+}
+
+impl DailySummary {
+    /// 告警消息的正文
+    pub fn text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "orders placed {}, filled {}, canceled {}, rejected {}",
+                self.placed, self.filled, self.canceled, self.rejected
+            ),
+            format!(
+                "fills {}, max latency {:.1}ms",
+                self.fills, self.max_latency_ms
+            ),
+        ];
+        for (title, map) in [
+            ("rejects", format_map(&self.reject_reasons)),
+            ("reconnects", format_map(&self.reconnects)),
+            ("pnl", format_map(&self.pnl)),
+            ("fees", format_map(&self.fees)),
+        ] {
+            if !map.is_empty() {
+                lines.push(format!("{}: {}", title, map));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn format_map<V: std::fmt::Display>(map: &BTreeMap<String, V>) -> String {
+    map.iter()
+        .map(|(k, v)| format!("{} {}", k, v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 一个交易对当天的成交
+#[derive(Debug, Clone, Copy, Default)]
+struct Book {
+    /// 净成交量，买入为正
+    net: f64,
+    /// 卖出收入减去买入支出
+    cash: f64,
+    last: f64,
+}
+
+#[derive(Debug, Default)]
+struct OpsState {
+    day: Option<NaiveDate>,
+    summary: DailySummary,
+    books: HashMap<String, Book>,
+    /// 已经结束、尚未写入的天
+    done: Vec<(NaiveDate, DailySummary)>,
+    /// 连接 -> 上一次上报时是否断线
+    connections: HashMap<String, bool>,
+}
+
+impl OpsState {
+    /// 跨天时收起前一天的统计
+    fn roll(&mut self, now: DateTime<Utc>) -> &mut DailySummary {
+        let today = now.date_naive();
+        match self.day {
+            Some(day) if day != today => {
+                let summary = self.finish();
+                self.done.push((day, summary));
+                self.books.clear();
+                self.day = Some(today);
+            }
+            Some(_) => (),
+            None => self.day = Some(today),
+        }
+        &mut self.summary
+    }
+
+    /// 取出当前的统计，盈亏按最新成交价估值
+    fn finish(&mut self) -> DailySummary {
+        let mut summary = std::mem::take(&mut self.summary);
+        summary.pnl = self.pnl();
+        summary
+    }
+
+    fn pnl(&self) -> BTreeMap<String, f64> {
+        self.books
+            .iter()
+            .map(|(symbol, book)| (symbol.clone(), book.cash + book.net * book.last))
+            .collect()
+    }
+}
+
+/// 运维统计，可以廉价 clone，在 handler、交易与写入任务之间共享
+#[derive(Debug, Clone, Default)]
+pub struct OpsReport {
+    config: OpsReportConfig,
+    state: Arc<Mutex<OpsState>>,
+}
+
+impl OpsReport {
+    pub fn new(config: OpsReportConfig) -> Self {
+        Self {
+            config,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 订单发往交易所
+    pub fn on_placed(&self) {
+        self.with_summary(|summary| summary.placed += 1);
+    }
+
+    /// 下单请求发出到收到交易所响应的耗时
+    pub fn on_latency(&self, latency: Duration) {
+        let ms = latency.as_secs_f64() * 1000.0;
+        self.with_summary(|summary| summary.max_latency_ms = summary.max_latency_ms.max(ms));
+    }
+
+    /// 交易所回报的订单状态，拒单走 `on_reject`
+    pub fn on_order(&self, state: State) {
+        self.with_summary(|summary| match state {
+            State::FILLED => summary.filled += 1,
+            State::CANCELED
+            | State::EXPIRED
+            | State::EXPIRED_IN_MATCH
+            | State::EXPIRED_BY_GATEWAY
+            | State::MMP_CANCELED => summary.canceled += 1,
+            _ => (),
+        });
+    }
+
+    pub fn on_reject(&self, reason: &str) {
+        self.with_summary(|summary| {
+            summary.rejected += 1;
+            *summary
+                .reject_reasons
+                .entry(reason.to_string())
+                .or_default() += 1;
+        });
+    }
+
+    pub fn on_fill(&self, fill: &SFill) {
+        if !self.enabled() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        let summary = state.roll(Utc::now());
+        summary.fills += 1;
+        if fill.commission != 0.0 {
+            *summary
+                .fees
+                .entry(fill.commission_asset.clone())
+                .or_default() += fill.commission;
+        }
+        let book = state.books.entry(fill.symbol.clone()).or_default();
+        let signed = match fill.side {
+            Side::BUY => fill.quantity,
+            Side::SELL => -fill.quantity,
+        };
+        book.net += signed;
+        book.cash -= signed * fill.price;
+        book.last = fill.price;
+    }
+
+    /// 周期性地上报连接状态，从断线恢复时计一次重连
+    pub fn on_connection(&self, source: &str, disconnected: bool) {
+        if !self.enabled() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        let was = state
+            .connections
+            .insert(source.to_string(), disconnected)
+            .unwrap_or(false);
+        if was && !disconnected {
+            *state
+                .roll(Utc::now())
+                .reconnects
+                .entry(source.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    fn with_summary(&self, f: impl FnOnce(&mut DailySummary)) {
+        if !self.enabled() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        f(state.roll(Utc::now()));
+    }
+
+    /// 已经结束的天与当天的统计
+    fn take(
+        &self,
+        now: DateTime<Utc>,
+    ) -> (Vec<(NaiveDate, DailySummary)>, (NaiveDate, DailySummary)) {
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        state.roll(now);
+        let mut today = state.summary.clone();
+        today.pnl = state.pnl();
+        (std::mem::take(&mut state.done), (now.date_naive(), today))
+    }
+
+    /// 定时把统计写入 `storage`，`venue` 区分同一个库中不同网关的报告
+    pub fn spawn(&self, storage: Arc<dyn Storage>, alerter: Alerter, venue: &'static str) {
+        if !self.enabled() {
+            return;
+        }
+        let report = self.clone();
+        let start = Utc::now().timestamp_millis();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(report.config.snapshot_secs.max(1)));
+            loop {
+                interval.tick().await;
+                let now = Utc::now();
+                let (done, today) = report.take(now);
+                for (day, summary) in done.into_iter().chain(std::iter::once(today.clone())) {
+                    let final_day = day != today.0;
+                    let record = DailyReport {
+                        day: day.to_string(),
+                        venue: venue.to_string(),
+                        start,
+                        time: now.timestamp_millis(),
+                        data: match serde_json::to_string(&summary) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                error!("{}", e);
+                                continue;
+                            }
+                        },
+                    };
+                    if let Err(e) = storage.save_report(&record).await {
+                        error!("Save report {}: {}", record.day, e);
+                    }
+                    if final_day {
+                        info!("Daily report {} {}: {:?}", venue, day, summary);
+                        if report.config.notify {
+                            alerter.on_daily_report(&record.day, summary.text());
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ops_report() {
+        let report = OpsReport::new(OpsReportConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        let fill = |side, price, quantity| SFill {
+            internal_id: 1,
+            order_id: 1,
+            symbol: "BTCUSDT".into(),
+            side,
+            trade_id: 1,
+            time: 0,
+            price,
+            quantity,
+            maker: true,
+            commission: 0.1,
+            commission_asset: "USDT".into(),
+        };
+
+        report.on_placed();
+        report.on_placed();
+        report.on_latency(Duration::from_millis(12));
+        report.on_latency(Duration::from_millis(5));
+        report.on_reject("-2010 insufficient balance");
+        report.on_reject("-2010 insufficient balance");
+        report.on_order(State::NEW);
+        report.on_order(State::FILLED);
+        report.on_order(State::EXPIRED_BY_GATEWAY);
+        report.on_fill(&fill(Side::BUY, 100.0, 2.0));
+        report.on_fill(&fill(Side::SELL, 110.0, 1.0));
+        report.on_connection("trade", false);
+        report.on_connection("trade", true);
+        report.on_connection("trade", true);
+        report.on_connection("trade", false);
+
+        let now = Utc::now();
+        let (done, (day, today)) = report.take(now);
+        assert!(done.is_empty());
+        assert_eq!(day, now.date_naive());
+        assert_eq!(
+            (today.placed, today.filled, today.canceled, today.rejected),
+            (2, 1, 1, 2)
+        );
+        assert_eq!(today.reject_reasons["-2010 insufficient balance"], 2);
+        assert_eq!(today.reconnects["trade"], 1);
+        assert_eq!(today.max_latency_ms, 12.0);
+        assert_eq!(today.fills, 2);
+        // 卖出 1 赚 10，剩余 1 按最新成交价估值也赚 10
+        assert_eq!(today.pnl["BTCUSDT"], 20.0);
+        assert!((today.fees["USDT"] - 0.2).abs() < 1e-9);
+
+        // 跨天后前一天的统计收起，新的一天从零开始
+        let tomorrow = now + chrono::Duration::days(1);
+        let (done, (_, next)) = report.take(tomorrow);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].0, day);
+        assert_eq!(done[0].1.placed, 2);
+        assert_eq!(done[0].1.pnl["BTCUSDT"], 20.0);
+        assert_eq!(next, DailySummary::default());
+        assert!(done[0].1.text().contains("placed 2"));
+
+        // 没有开启时不统计
+        let report = OpsReport::default();
+        report.on_placed();
+        assert_eq!(report.take(now).1 .1, DailySummary::default());
+    }
+}
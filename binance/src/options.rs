@@ -0,0 +1,157 @@
+//! 期权（eapi）行情
+//!
+//! 开启后行情模块另外连接期权的行情地址，策略端照常订阅：`{symbol}@greeks` 为单个期权的标记价格与希腊值，
+//! 对应交易所的 `{SYMBOL}@ticker`；`{asset}@mark` 为标的下所有期权的标记价格与希腊值，对应 `{ASSET}@markPrice`。
+//! 期权的流名称是大写的，其余的流仍走原来的连接。`get_option_chain` 通过 REST 返回标的下的期权合约，
+//! 合约信息与现货、合约的交易规则格式相同，另外带有行权价、到期时间与期权类型。
+
+use crate::feed::PublicFeed;
+use crate::model::option::BinanceOptionSymbol;
+use crate::model::symbol::BinanceSymbol;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// ```json
+/// "options": {
+///     "enabled": false,
+///     "url": "https://eapi.binance.com",
+///     "stream_url": "wss://nbstream.binance.com/eoptions/stream"
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OptionsConfig {
+    pub enabled: bool,
+    /// 期权 REST 地址
+    pub url: String,
+    /// 期权 combined 行情地址
+    pub stream_url: String,
+}
+
+impl Default for OptionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "https://eapi.binance.com".into(),
+            stream_url: "wss://nbstream.binance.com/eoptions/stream".into(),
+        }
+    }
+}
+
+/// 策略端的期权流名称转换为交易所的流名称，不是期权的流返回 None
+pub fn exchange_stream(stream: &str) -> Option<String> {
+    let (symbol, kind) = stream.split_once('@')?;
+    match kind {
+        "greeks" => Some(format!("{}@ticker", symbol.to_uppercase())),
+        "mark" => Some(format!("{}@markPrice", symbol.to_uppercase())),
+        _ => None,
+    }
+}
+
+/// 是否为期权连接上的流
+pub fn is_option_stream(stream: &str) -> bool {
+    stream.split_once('@').is_some_and(|(symbol, kind)| {
+        matches!(kind, "ticker" | "markPrice") && symbol.chars().any(|c| c.is_ascii_uppercase())
+    })
+}
+
+/// 期权行情连接，期权 REST 查询也经过这里
+pub struct OptionsFeed {
+    config: OptionsConfig,
+    feed: PublicFeed,
+    http: reqwest::Client,
+}
+
+impl OptionsFeed {
+    pub async fn connect(config: OptionsConfig) -> anyhow::Result<Self> {
+        let feed = PublicFeed::connect("options", &config.stream_url).await?;
+        Ok(Self {
+            config,
+            feed,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    /// 订阅或退订，重连期间只记录，重连后统一订阅
+    pub async fn call(&mut self, method: &str, streams: &[String], id: i64) -> anyhow::Result<()> {
+        self.feed.call(method, streams, id).await
+    }
+
+    /// 等待下一条推送，连接断开时在后台重连
+    pub async fn recv(&mut self) -> Value {
+        self.feed.recv().await
+    }
+
+    /// 标的下的期权合约，按到期时间、行权价、类型排序
+    pub async fn option_chain(&self, underlying: &str) -> anyhow::Result<Vec<BinanceSymbol>> {
+        let url = format!("{}/eapi/v1/exchangeInfo", self.config.url);
+        let value: Value = self.http.get(url).send().await?.json().await?;
+        chain(&value, underlying)
+    }
+}
+
+fn chain(value: &Value, underlying: &str) -> anyhow::Result<Vec<BinanceSymbol>> {
+    let symbols = value
+        .get("optionSymbols")
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+    let symbols: Vec<BinanceOptionSymbol> = serde_json::from_value(symbols.clone())?;
+    let underlying = underlying.to_lowercase();
+    let mut chain: Vec<BinanceSymbol> = symbols
+        .into_iter()
+        .filter(|s| s.underlying == underlying)
+        .map(BinanceSymbol::from)
+        .collect();
+    chain.sort_by(|a, b| {
+        let key = |s: &BinanceSymbol| s.option.as_ref().map(|o| (o.expiry, o.strike, o.kind));
+        key(a)
+            .partial_cmp(&key(b))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_streams() {
+        let stream = exchange_stream("btc-250627-100000-c@greeks").unwrap();
+        assert_eq!(stream, "BTC-250627-100000-C@ticker");
+        assert!(is_option_stream(&stream));
+        assert_eq!(exchange_stream("eth@mark").unwrap(), "ETH@markPrice");
+        assert_eq!(exchange_stream("btcusdt@kline:1m"), None);
+        // 现货与合约的同名流是小写的
+        assert!(!is_option_stream("btcusdt@markPrice"));
+        assert!(!is_option_stream("btcusdt@ticker"));
+
+        let option = |symbol: &str, side: &str, strike: &str, expiry: u64| {
+            serde_json::json!({
+                "symbol": symbol, "side": side, "strikePrice": strike, "expiryDate": expiry,
+                "underlying": symbol.split('-').next().unwrap().to_string() + "USDT",
+                "unit": 1, "quoteAsset": "USDT", "filters": []
+            })
+        };
+        let value = serde_json::json!({"optionSymbols": [
+            option("BTC-250926-90000-P", "PUT", "90000", 1758873600000),
+            option("ETH-250627-3000-C", "CALL", "3000", 1751011200000),
+            option("BTC-250627-100000-C", "CALL", "100000", 1751011200000),
+            option("BTC-250627-90000-P", "PUT", "90000", 1751011200000),
+            option("BTC-250627-90000-C", "CALL", "90000", 1751011200000),
+        ]});
+        let symbols: Vec<_> = chain(&value, "BTCUSDT")
+            .unwrap()
+            .into_iter()
+            .map(|s| s.symbol)
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                "btc-250627-90000-c",
+                "btc-250627-90000-p",
+                "btc-250627-100000-c",
+                "btc-250926-90000-p"
+            ]
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! 按交易所的挂单数上限排队下单
+//!
+//! 交易所按交易对限制挂单数（MAX_NUM_ORDERS）与条件单数（MAX_NUM_ALGO_ORDERS），现货还按账户限制
+//! （EXCHANGE_MAX_NUM_ORDERS、EXCHANGE_MAX_NUM_ALGO_ORDERS），超过时拒绝新订单。策略分批下一串订单时，
+//! 中途被拒绝的订单很难处理。开启后网关按 exchangeInfo 中的过滤器统计自己发出、尚未结束的订单，
+//! 会超过上限的订单先排队，等有订单结束后再按到达的顺序发出；同一交易对已有订单排队时，新订单排在它们
+//! 后面。订单排队与发出时都推送 [`SOrderCap`] 给下单的会话。
+//!
+//! 每个会话最多排队 `max_queued` 笔，再多的直接拒绝并发出风控告警；排队超过 `max_wait_ms` 的订单拒绝。
+//! 排队中的订单可以照常撤单。只统计网关发出的订单，手工下的订单与网关重启前留下的订单不计入。
+//!
+//! [`SOrderCap`]: cryptoflow::chat::SOrderCap
+
+use crate::model::filter::FilterField;
+use crate::model::order::BinanceOrder;
+use crate::model::symbol::BinanceSymbol;
+use cryptoflow::chat::{OrderType, SOrderCap, State};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// ```json
+/// "order_caps": {
+///     "enabled": true,
+///     "max_queued": 100,
+///     "max_wait_ms": 60000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OrderCapsConfig {
+    pub enabled: bool,
+    /// 每个会话最多排队的订单数
+    pub max_queued: usize,
+    /// 订单最多排队的时间
+    pub max_wait_ms: u64,
+}
+
+impl Default for OrderCapsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_queued: 100,
+            max_wait_ms: 60000,
+        }
+    }
+}
+
+/// 挂单数与条件单数的上限，0 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_orders: i64,
+    pub max_algo_orders: i64,
+}
+
+impl Limits {
+    /// 交易对的 MAX_NUM_ORDERS 与 MAX_NUM_ALGO_ORDERS
+    pub fn of_symbol(filters: &[FilterField]) -> Self {
+        let mut limits = Self::default();
+        for filter in filters {
+            match filter {
+                FilterField::MAX_NUM_ORDERS { max_num_orders } => {
+                    limits.max_orders = *max_num_orders
+                }
+                FilterField::MAX_NUM_ALGO_ORDERS {
+                    max_num_algo_orders,
+                } => limits.max_algo_orders = *max_num_algo_orders,
+                _ => (),
+            }
+        }
+        limits
+    }
+
+    /// 账户的 EXCHANGE_MAX_NUM_ORDERS 与 EXCHANGE_MAX_NUM_ALGO_ORDERS
+    pub fn of_exchange(filters: &[FilterField]) -> Self {
+        let mut limits = Self::default();
+        for filter in filters {
+            match filter {
+                FilterField::EXCHANGE_MAX_NUM_ORDERS { max_num_orders } => {
+                    limits.max_orders = *max_num_orders
+                }
+                FilterField::EXCHANGE_MAX_NUM_ALGO_ORDERS {
+                    max_num_algo_orders,
+                } => limits.max_algo_orders = *max_num_algo_orders,
+                _ => (),
+            }
+        }
+        limits
+    }
+}
+
+/// 计入 MAX_NUM_ALGO_ORDERS 的条件单
+pub fn is_algo(order_type: OrderType) -> bool {
+    matches!(
+        order_type,
+        OrderType::STOP
+            | OrderType::STOP_MARKET
+            | OrderType::STOP_LOSS
+            | OrderType::STOP_LOSS_LIMIT
+            | OrderType::TAKE_PROFIT
+            | OrderType::TAKE_PROFIT_LIMIT
+            | OrderType::TAKE_PROFIT_MARKET
+            | OrderType::TRAILING_STOP_MARKET
+    )
+}
+
+/// 下单前的判断
+#[derive(Debug)]
+pub enum Admission {
+    Send,
+    /// 订单已排队，推送给策略
+    Queue(SOrderCap),
+    Reject(String),
+}
+
+#[derive(Debug)]
+struct Queued {
+    addr: SocketAddr,
+    order: BinanceOrder,
+    since: Instant,
+    /// 排队时挡住订单的过滤器与上限
+    filter: &'static str,
+    limit: i64,
+}
+
+#[derive(Debug, Default)]
+struct CapBook {
+    config: OrderCapsConfig,
+    /// 交易对（小写） -> 上限
+    symbols: HashMap<String, Limits>,
+    exchange: Limits,
+    /// (session_id, order_id) -> (交易对, 是否条件单)，已发出尚未结束的订单
+    open: HashMap<(u16, u32), (String, bool)>,
+    queue: VecDeque<Queued>,
+}
+
+impl CapBook {
+    /// 挡住订单的第一个过滤器：(名称, 上限, 当前的挂单数)
+    fn blocking(&self, symbol: &str, algo: bool) -> Option<(&'static str, i64, i64)> {
+        let symbol_limits = self.symbols.get(symbol).copied().unwrap_or_default();
+        let (mut orders, mut algos, mut all, mut all_algos) = (0, 0, 0, 0);
+        for (s, a) in self.open.values() {
+            all += 1;
+            all_algos += *a as i64;
+            if s == symbol {
+                orders += 1;
+                algos += *a as i64;
+            }
+        }
+        let checks = [
+            ("MAX_NUM_ORDERS", symbol_limits.max_orders, orders, true),
+            (
+                "MAX_NUM_ALGO_ORDERS",
+                symbol_limits.max_algo_orders,
+                algos,
+                algo,
+            ),
+            (
+                "EXCHANGE_MAX_NUM_ORDERS",
+                self.exchange.max_orders,
+                all,
+                true,
+            ),
+            (
+                "EXCHANGE_MAX_NUM_ALGO_ORDERS",
+                self.exchange.max_algo_orders,
+                all_algos,
+                algo,
+            ),
+        ];
+        checks
+            .into_iter()
+            .find(|(_, limit, open, applies)| *applies && *limit > 0 && *open >= *limit)
+            .map(|(filter, limit, open, _)| (filter, limit, open))
+    }
+
+    fn open_count(&self, symbol: &str) -> i64 {
+        self.open.values().filter(|(s, _)| s == symbol).count() as i64
+    }
+
+    fn notice(&self, order: &BinanceOrder, filter: &str, limit: i64, queued: bool) -> SOrderCap {
+        let symbol = order.symbol.to_lowercase();
+        SOrderCap {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default(),
+            open: self.open_count(&symbol),
+            symbol,
+            order_id: order.id,
+            filter: filter.to_string(),
+            limit,
+            queued,
+        }
+    }
+}
+
+/// 下单任务与主循环共享，主循环下单前判断、收到回报后释放名额
+#[derive(Debug, Clone, Default)]
+pub struct OrderCaps {
+    book: Arc<Mutex<CapBook>>,
+}
+
+impl OrderCaps {
+    pub fn new(config: OrderCapsConfig) -> Self {
+        Self {
+            book: Arc::new(Mutex::new(CapBook {
+                config,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn with_book<R>(&self, f: impl FnOnce(&mut CapBook) -> R) -> R {
+        let mut book = self.book.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut book)
+    }
+
+    /// 更新各交易对与账户的上限，交易规则刷新后调用
+    pub fn set_limits(&self, symbols: HashMap<String, Limits>, exchange: Limits) {
+        self.with_book(|book| {
+            book.symbols = symbols
+                .into_iter()
+                .map(|(symbol, limits)| (symbol.to_lowercase(), limits))
+                .collect();
+            book.exchange = exchange;
+        })
+    }
+
+    /// 按交易规则更新上限，`exchange` 为账户的上限
+    pub fn set_products(&self, products: &HashMap<String, BinanceSymbol>, exchange: Limits) {
+        let symbols = products
+            .iter()
+            .map(|(symbol, product)| (symbol.clone(), Limits::of_symbol(&product.filters)))
+            .collect();
+        self.set_limits(symbols, exchange);
+    }
+
+    /// 发出 `order` 之前调用，返回 `Send` 时已占用名额
+    pub fn admit(&self, addr: SocketAddr, order: &BinanceOrder, now: Instant) -> Admission {
+        self.with_book(|book| {
+            let key = (order.session_id, order.id);
+            // 排队后放行的订单已经占用了名额
+            if !book.config.enabled || book.open.contains_key(&key) {
+                return Admission::Send;
+            }
+            let symbol = order.symbol.to_lowercase();
+            let algo = is_algo(order.order_type);
+            let blocking = match book.blocking(&symbol, algo) {
+                Some((filter, limit, _)) => Some((filter, limit)),
+                // 同一交易对已有订单排队时排在它们后面
+                None => book
+                    .queue
+                    .iter()
+                    .rev()
+                    .find(|q| q.order.symbol.eq_ignore_ascii_case(&symbol))
+                    .map(|q| (q.filter, q.limit)),
+            };
+            let Some((filter, limit)) = blocking else {
+                book.open.insert(key, (symbol, algo));
+                return Admission::Send;
+            };
+            let queued = book
+                .queue
+                .iter()
+                .filter(|q| q.order.session_id == order.session_id)
+                .count();
+            if queued >= book.config.max_queued {
+                return Admission::Reject(format!(
+                    "{} of {} reached {} and {} orders already queued",
+                    filter, symbol, limit, queued
+                ));
+            }
+            let notice = book.notice(order, filter, limit, true);
+            book.queue.push_back(Queued {
+                addr,
+                order: order.clone(),
+                since: now,
+                filter,
+                limit,
+            });
+            Admission::Queue(notice)
+        })
+    }
+
+    /// 订单回报，结束的订单释放名额
+    pub fn on_order(&self, session_id: u16, order_id: u32, state: State) {
+        if !matches!(
+            state,
+            State::NEW | State::PARTIALLY_FILLED | State::PENDING_NEW
+        ) {
+            self.on_reject(session_id, order_id);
+        }
+    }
+
+    /// 订单被拒绝，释放名额
+    pub fn on_reject(&self, session_id: u16, order_id: u32) {
+        self.with_book(|book| book.open.remove(&(session_id, order_id)));
+    }
+
+    /// 排队中的订单：返回可以发出的订单（已占用名额）与排队超时的订单，同一交易对按到达的顺序发出
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        &self,
+        now: Instant,
+    ) -> (
+        Vec<(SocketAddr, BinanceOrder, SOrderCap)>,
+        Vec<(SocketAddr, BinanceOrder)>,
+    ) {
+        self.with_book(|book| {
+            let (mut ready, mut expired) = (Vec::new(), Vec::new());
+            if book.queue.is_empty() {
+                return (ready, expired);
+            }
+            let max_wait = Duration::from_millis(book.config.max_wait_ms);
+            let mut blocked = HashSet::new();
+            let mut kept = VecDeque::new();
+            while let Some(queued) = book.queue.pop_front() {
+                let symbol = queued.order.symbol.to_lowercase();
+                if now.duration_since(queued.since) > max_wait {
+                    expired.push((queued.addr, queued.order));
+                    continue;
+                }
+                let algo = is_algo(queued.order.order_type);
+                if blocked.contains(&symbol) || book.blocking(&symbol, algo).is_some() {
+                    blocked.insert(symbol);
+                    kept.push_back(queued);
+                    continue;
+                }
+                book.open
+                    .insert((queued.order.session_id, queued.order.id), (symbol, algo));
+                let notice = book.notice(&queued.order, queued.filter, queued.limit, false);
+                ready.push((queued.addr, queued.order, notice));
+            }
+            book.queue = kept;
+            (ready, expired)
+        })
+    }
+
+    /// 撤销排队中的订单，返回该订单；订单不在排队时返回 None
+    pub fn cancel(&self, session_id: u16, order_id: u32) -> Option<(SocketAddr, BinanceOrder)> {
+        self.with_book(|book| {
+            let index = book
+                .queue
+                .iter()
+                .position(|q| q.order.session_id == session_id && q.order.id == order_id)?;
+            book.queue.remove(index).map(|q| (q.addr, q.order))
+        })
+    }
+
+    /// 取出满足条件的排队订单
+    pub fn drain(
+        &self,
+        f: impl Fn(&SocketAddr, &BinanceOrder) -> bool,
+    ) -> Vec<(SocketAddr, BinanceOrder)> {
+        self.with_book(|book| {
+            let (taken, kept) = std::mem::take(&mut book.queue)
+                .into_iter()
+                .partition(|q| f(&q.addr, &q.order));
+            book.queue = kept;
+            taken
+                .into_iter()
+                .map(|q: Queued| (q.addr, q.order))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u32, symbol: &str, order_type: OrderType) -> BinanceOrder {
+        BinanceOrder {
+            id,
+            symbol: symbol.into(),
+            price: 100.0,
+            quantity: 1.0,
+            order_type,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let filters: Vec<FilterField> = serde_json::from_str(
+            r#"[{"filterType": "MAX_NUM_ORDERS", "maxNumOrders": 200},
+                {"filterType": "MAX_NUM_ALGO_ORDERS", "limit": 10},
+                {"filterType": "EXCHANGE_MAX_NUM_ORDERS", "maxNumOrders": 1000}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Limits::of_symbol(&filters),
+            Limits {
+                max_orders: 200,
+                max_algo_orders: 10
+            }
+        );
+        assert_eq!(
+            Limits::of_exchange(&filters),
+            Limits {
+                max_orders: 1000,
+                max_algo_orders: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_order_caps() {
+        let caps = OrderCaps::new(OrderCapsConfig {
+            enabled: true,
+            max_queued: 2,
+            max_wait_ms: 1000,
+        });
+        let limits = Limits {
+            max_orders: 2,
+            max_algo_orders: 1,
+        };
+        caps.set_limits(
+            HashMap::from([("BTCUSDT".to_string(), limits)]),
+            Limits::default(),
+        );
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let t0 = Instant::now();
+
+        assert!(matches!(
+            caps.admit(addr, &order(1, "btcusdt", OrderType::LIMIT), t0),
+            Admission::Send
+        ));
+        assert!(matches!(
+            caps.admit(addr, &order(2, "btcusdt", OrderType::STOP_LOSS_LIMIT), t0),
+            Admission::Send
+        ));
+        // 条件单与挂单数都到了上限
+        let Admission::Queue(notice) =
+            caps.admit(addr, &order(3, "btcusdt", OrderType::STOP_LOSS_LIMIT), t0)
+        else {
+            panic!("expect queue");
+        };
+        assert_eq!(notice.filter, "MAX_NUM_ORDERS");
+        assert_eq!((notice.limit, notice.open), (2, 2));
+        assert!(matches!(
+            caps.admit(addr, &order(4, "btcusdt", OrderType::LIMIT), t0),
+            Admission::Queue(_)
+        ));
+        assert!(matches!(
+            caps.admit(addr, &order(5, "btcusdt", OrderType::LIMIT), t0),
+            Admission::Reject(_)
+        ));
+        // 其他交易对不受影响
+        assert!(matches!(
+            caps.admit(addr, &order(6, "ethusdt", OrderType::LIMIT), t0),
+            Admission::Send
+        ));
+
+        // 普通订单结束后条件单仍受 MAX_NUM_ALGO_ORDERS 限制，排在后面的订单也要等
+        caps.on_order(1, 1, State::FILLED);
+        let (ready, expired) = caps.release(t0);
+        assert!(ready.is_empty() && expired.is_empty());
+        caps.on_order(1, 2, State::CANCELED);
+        let (ready, _) = caps.release(t0);
+        let ids: Vec<u32> = ready.iter().map(|(_, o, _)| o.id).collect();
+        assert_eq!(ids, vec![3, 4]);
+        assert!(!ready[0].2.queued);
+        // 放行的订单再次下单时直接发出
+        assert!(matches!(
+            caps.admit(addr, &order(3, "btcusdt", OrderType::STOP_LOSS_LIMIT), t0),
+            Admission::Send
+        ));
+
+        // 排队的订单可以撤单，超时的订单拒绝
+        let Admission::Queue(_) = caps.admit(addr, &order(7, "btcusdt", OrderType::LIMIT), t0)
+        else {
+            panic!("expect queue");
+        };
+        let Admission::Queue(_) = caps.admit(addr, &order(8, "btcusdt", OrderType::LIMIT), t0)
+        else {
+            panic!("expect queue");
+        };
+        assert_eq!(caps.cancel(1, 7).map(|(_, o)| o.id), Some(7));
+        assert!(caps.cancel(1, 7).is_none());
+        let (ready, expired) = caps.release(t0 + Duration::from_secs(2));
+        assert!(ready.is_empty());
+        assert_eq!(expired.len(), 1);
+        assert!(caps.drain(|_, _| true).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! 下单前按交易所的过滤器校验
+//!
+//! 交易所一定会拒绝的委托在网关直接拒绝，省去一次往返，也不占用下单频率。限价单（含只做 maker 的
+//! 订单）校验价格步长与范围、数量步长与范围、最小名义价值，市价单按 MARKET_LOT_SIZE 校验数量，其余
+//! 类型的订单交给交易所校验。另外：
+//!
+//! - `percent_price`：限价单的价格相对参考价的范围，即 PERCENT_PRICE 与 PERCENT_PRICE_BY_SIDE。
+//!   参考价为最近 `avgPriceMins` 分钟 bookTicker 中间价的平均值，每秒取一个点；合约的参考价是标记价格，
+//!   用最新的中间价近似。网关没有该交易对的 bookTicker 时不校验
+//! - `max_position`：买单与基础资产的持仓之和不能超过 MAX_POSITION。交易所还计入未成交的买单，
+//!   网关不计入，所以只拦截一定会被拒绝的买单
+
+use crate::model::order::BinanceOrder;
+use crate::peg::BookTickers;
+use cryptoflow::chat::OrderType;
+use cryptoflow::trading_rules::TradingRules;
+use serde::Deserialize;
+
+/// ```json
+/// "order_check": {
+///     "enabled": true,
+///     "percent_price": true,
+///     "max_position": true
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OrderCheckConfig {
+    pub enabled: bool,
+    /// 按 PERCENT_PRICE 校验限价单的价格
+    pub percent_price: bool,
+    /// 按 MAX_POSITION 校验买单
+    pub max_position: bool,
+}
+
+impl Default for OrderCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            percent_price: true,
+            max_position: true,
+        }
+    }
+}
+
+/// 交易所计算价格带用的参考价
+pub fn reference<T: TradingRules>(product: &T, tickers: &BookTickers, now_ms: i64) -> Option<f64> {
+    tickers.average(product.symbol(), product.avg_price_mins(), now_ms)
+}
+
+/// 校验一笔委托，`reference` 为参考价，`position` 为基础资产的持仓，为 None 时不校验对应的过滤器
+pub fn check<T: TradingRules>(
+    config: &OrderCheckConfig,
+    product: &T,
+    order: &BinanceOrder,
+    reference: Option<f64>,
+    position: Option<f64>,
+) -> Result<(), String> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let (price, quantity) = (order.price, order.quantity);
+    match order.order_type {
+        OrderType::MARKET => {
+            if !product.is_valid_market_quantity(quantity) {
+                return Err(format!(
+                    "market quantity {} is outside [{}, {}] or off the {} step",
+                    quantity,
+                    product.market_min_quantity(),
+                    product.market_max_quantity(),
+                    product.market_lot_size()
+                ));
+            }
+        }
+        OrderType::LIMIT | OrderType::LIMIT_MAKER => {
+            if !product.is_valid_price(price) {
+                return Err(format!(
+                    "price {} is outside [{}, {}] or off the {} tick",
+                    price,
+                    product.min_price(),
+                    product.max_price(),
+                    product.tick_size()
+                ));
+            }
+            if !product.is_valid_quantity(quantity) {
+                return Err(format!(
+                    "quantity {} is outside [{}, {}] or off the {} step",
+                    quantity,
+                    product.min_quantity(),
+                    product.max_quantity(),
+                    product.lot_size()
+                ));
+            }
+            if price * quantity < product.min_notional() {
+                return Err(format!(
+                    "notional {} is below {}",
+                    price * quantity,
+                    product.min_notional()
+                ));
+            }
+            if let (true, Some(reference)) = (config.percent_price, reference) {
+                if !product.is_within_percent_price(&order.side, price, reference) {
+                    let (down, up) = product.percent_price(&order.side).unwrap_or_default();
+                    return Err(format!(
+                        "{:?} price {} is outside [{}, {}] x reference price {}",
+                        order.side, price, down, up, reference
+                    ));
+                }
+            }
+        }
+        _ => (),
+    }
+    if let (true, Some(position)) = (config.max_position, position) {
+        if !product.is_within_max_position(&order.side, quantity, position) {
+            return Err(format!(
+                "position {} plus {} exceeds max position {}",
+                position,
+                quantity,
+                product.max_position().unwrap_or_default()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::symbol::BinanceSymbol;
+    use cryptoflow::chat::Side;
+
+    fn order(order_type: OrderType, side: Side, price: f64, quantity: f64) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price,
+            quantity,
+            side,
+            order_type,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_order_check() {
+        let product: BinanceSymbol = serde_json::from_value(serde_json::json!({
+            "symbol": "BTCUSDT",
+            "status": "TRADING",
+            "baseAsset": "BTC",
+            "baseAssetPrecision": 8,
+            "quoteAsset": "USDT",
+            "quotePrecision": 8,
+            "orderTypes": ["LIMIT", "MARKET"],
+            "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.01", "maxPrice": "0", "tickSize": "0.01"},
+                {"filterType": "LOT_SIZE", "minQty": "0.001", "maxQty": "9000", "stepSize": "0.001"},
+                {"filterType": "MARKET_LOT_SIZE", "minQty": "0", "maxQty": "120", "stepSize": "0"},
+                {"filterType": "NOTIONAL", "minNotional": "5", "applyMinToMarket": true,
+                 "maxNotional": "9000000", "applyMaxToMarket": false, "avgPriceMins": 5},
+                {"filterType": "PERCENT_PRICE_BY_SIDE", "bidMultiplierUp": "1.2", "bidMultiplierDown": "0.2",
+                 "askMultiplierUp": "5", "askMultiplierDown": "0.8", "avgPriceMins": 1},
+                {"filterType": "MAX_POSITION", "maxPosition": "10"},
+            ],
+        }))
+        .unwrap();
+        let config = OrderCheckConfig::default();
+        let check = |order: &BinanceOrder, reference, position| {
+            check(&config, &product, order, reference, position)
+        };
+
+        // 参考价为最近一分钟中间价的平均值
+        let tickers = BookTickers::default();
+        assert_eq!(reference(&product, &tickers, 0), None);
+        tickers.update_at("BTCUSDT", 39999.0, 40001.0, 1_000);
+        tickers.update_at("BTCUSDT", 59999.0, 60001.0, 30_000);
+        tickers.update_at("BTCUSDT", 49999.0, 50001.0, 30_500);
+        assert_eq!(reference(&product, &tickers, 60_000), Some(45000.0));
+        assert_eq!(reference(&product, &tickers, 61_000), Some(50000.0));
+        let reference = Some(50000.0);
+
+        let buy = order(OrderType::LIMIT, Side::BUY, 59000.0, 0.01);
+        assert_eq!(check(&buy, reference, Some(0.0)), Ok(()));
+        // 最大价格为 0 时不限制
+        let buy = order(OrderType::LIMIT, Side::BUY, 61000.0, 0.01);
+        assert!(check(&buy, reference, None)
+            .unwrap_err()
+            .contains("reference price"));
+        assert_eq!(check(&buy, None, None), Ok(()));
+        let sell = order(OrderType::LIMIT_MAKER, Side::SELL, 61000.0, 0.01);
+        assert_eq!(check(&sell, reference, None), Ok(()));
+        let sell = order(OrderType::LIMIT, Side::SELL, 39000.0, 0.01);
+        assert!(check(&sell, reference, None).is_err());
+        let buy = order(OrderType::LIMIT, Side::BUY, 50000.005, 0.01);
+        assert!(check(&buy, reference, None).unwrap_err().contains("tick"));
+        let buy = order(OrderType::LIMIT, Side::BUY, 100.0, 0.01);
+        assert!(check(&buy, None, None).unwrap_err().contains("notional"));
+
+        // 市价单的数量不限制步长，但上限更小
+        let market = order(OrderType::MARKET, Side::SELL, 0.0, 0.0015);
+        assert_eq!(check(&market, reference, None), Ok(()));
+        let market = order(OrderType::MARKET, Side::SELL, 0.0, 150.0);
+        assert!(check(&market, reference, None)
+            .unwrap_err()
+            .contains("market quantity"));
+
+        // 买入后超过最大持仓，卖出不受限制
+        let buy = order(OrderType::LIMIT, Side::BUY, 50000.0, 2.5);
+        assert!(check(&buy, reference, Some(8.0))
+            .unwrap_err()
+            .contains("max position"));
+        let sell = order(OrderType::LIMIT, Side::SELL, 50000.0, 2.5);
+        assert_eq!(check(&sell, reference, Some(8.0)), Ok(()));
+
+        let config = OrderCheckConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            crate::order_check::check(&config, &product, &buy, reference, Some(8.0)),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! REST 分页
+//!
+//! 成交、资金流水、K 线等接口每次最多返回 `limit` 条，需要按 `fromId` 或 `startTime`/`endTime` 翻页。
+//! `Paginator` 负责拼接翻页参数、按已用权重放慢请求，并以 Stream 逐页或逐条返回解析好的数据。
+//!
+//! 按时间翻页时，一页装满后下一页从最后一条的时间开始，跳过上一页已经返回的同一毫秒的数据；
+//! 整页都在同一毫秒时只能跳过该毫秒剩下的数据。设置了 `window` 的接口（如成交只能查 24 小时）按窗口依次查询。
+
+use crate::rest::Rest;
+use futures::{stream, Stream, TryStreamExt};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// 限频时最多重试的次数
+const MAX_RETRIES: usize = 3;
+
+/// 翻页方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cursor {
+    /// 按 `fromId` 翻页，下一页从最后一条的 id + 1 开始
+    FromId(i64),
+    /// 按 `startTime`、`endTime` 翻页，`window` 为单次查询的最大时间跨度（毫秒）
+    Time {
+        start: i64,
+        end: i64,
+        window: Option<i64>,
+    },
+}
+
+/// 请求节奏
+#[derive(Debug, Clone, Copy)]
+pub struct Pacing {
+    /// 两页之间的最小间隔
+    pub interval: Duration,
+    /// 1 分钟内已用权重达到该值时等到下一分钟，0 表示不检查
+    pub max_weight: u32,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            max_weight: 2000,
+        }
+    }
+}
+
+type Key<T> = Arc<dyn Fn(&T) -> i64 + Send + Sync>;
+
+/// 分页查询一个 REST 接口
+pub struct Paginator<T> {
+    path: String,
+    params: Vec<(String, String)>,
+    signature: bool,
+    limit: usize,
+    cursor: Cursor,
+    /// 每条数据的 id 或时间，与 `cursor` 对应
+    key: Key<T>,
+    pacing: Pacing,
+}
+
+/// 翻页的进度
+#[derive(Debug, Clone, PartialEq)]
+struct Progress {
+    cursor: Cursor,
+    /// 下一页开头需要跳过的、时间为 `last` 的条数
+    skip: usize,
+    last: Option<i64>,
+    done: bool,
+}
+
+impl<T> Paginator<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    pub fn new(
+        path: &str,
+        cursor: Cursor,
+        key: impl Fn(&T) -> i64 + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            params: Vec::new(),
+            signature: false,
+            limit: 500,
+            cursor,
+            key: Arc::new(key),
+            pacing: Pacing::default(),
+        }
+    }
+
+    /// 从 `start` 到现在按时间翻页
+    pub fn since(path: &str, start: i64, key: impl Fn(&T) -> i64 + Send + Sync + 'static) -> Self {
+        let cursor = Cursor::Time {
+            start,
+            end: now_ms(),
+            window: None,
+        };
+        Self::new(path, cursor, key)
+    }
+
+    /// 除翻页以外的查询参数
+    pub fn with_params(mut self, params: &[(String, String)]) -> Self {
+        self.params = params.to_vec();
+        self
+    }
+
+    pub fn with_signature(mut self, signature: bool) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// 每页条数，应为接口允许的最大值
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    fn query(&self, progress: &Progress) -> Vec<(String, String)> {
+        let mut params = self.params.clone();
+        match progress.cursor {
+            Cursor::FromId(from) => params.push(("fromId".into(), from.to_string())),
+            Cursor::Time { start, end, window } => {
+                params.push(("startTime".into(), start.to_string()));
+                params.push(("endTime".into(), window_end(start, end, window).to_string()));
+            }
+        }
+        params.push(("limit".into(), self.limit.to_string()));
+        params
+    }
+
+    /// 根据一页的结果推进进度，返回去掉重复后的数据
+    fn advance(&self, progress: &mut Progress, mut page: Vec<T>) -> Vec<T> {
+        let size = page.len();
+        if let Some(last) = progress.last {
+            let skip = page
+                .iter()
+                .take(progress.skip)
+                .take_while(|item| (self.key)(item) == last)
+                .count();
+            page.drain(..skip);
+        }
+
+        let full = size >= self.limit;
+        let last = page.last().map(|item| (self.key)(item));
+        match (&mut progress.cursor, last) {
+            (Cursor::FromId(from), Some(last)) if full => {
+                *from = last + 1;
+            }
+            (Cursor::Time { start, .. }, Some(last)) if full => {
+                if last > *start {
+                    *start = last;
+                    progress.skip = page
+                        .iter()
+                        .rev()
+                        .take_while(|i| (self.key)(i) == last)
+                        .count();
+                    progress.last = Some(last);
+                } else {
+                    warn!(
+                        "A full page of {} at {}, skip the rest of it",
+                        self.path, last
+                    );
+                    *start = last + 1;
+                    progress.skip = 0;
+                    progress.last = None;
+                }
+            }
+            (Cursor::Time { start, end, window }, _)
+                if window_end(*start, *end, *window) < *end =>
+            {
+                *start = window_end(*start, *end, *window) + 1;
+                progress.skip = 0;
+                progress.last = None;
+            }
+            _ => progress.done = true,
+        }
+        page
+    }
+
+    async fn fetch(&self, rest: &Rest, params: &[(String, String)]) -> anyhow::Result<Vec<T>> {
+        let mut retries = 0;
+        loop {
+            let rsp = rest.get(&self.path, params, self.signature).await?;
+            let status = rsp.status();
+            let retry_after = header(&rsp, "retry-after").unwrap_or(1);
+            let weight = header(&rsp, "x-mbx-used-weight-1m").unwrap_or_default();
+            let text = rsp.text().await?;
+
+            if status == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RETRIES {
+                retries += 1;
+                warn!("{} rate limited, retry after {}s", self.path, retry_after);
+                tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                continue;
+            }
+            if !status.is_success() {
+                anyhow::bail!("{} {}: {}", self.path, status, text);
+            }
+
+            if self.pacing.max_weight > 0 && weight >= u64::from(self.pacing.max_weight) {
+                let wait = 60 - (now_ms() / 1000) as u64 % 60;
+                warn!("Used weight {} of {}, wait {}s", weight, self.path, wait);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+            return Ok(serde_json::from_str(&text)?);
+        }
+    }
+
+    /// 逐页返回，最后一页可能为空
+    pub fn pages(self, rest: &Rest) -> impl Stream<Item = anyhow::Result<Vec<T>>> + '_ {
+        let progress = Progress {
+            cursor: self.cursor,
+            skip: 0,
+            last: None,
+            done: false,
+        };
+        stream::try_unfold(
+            (self, progress, true),
+            move |(paginator, mut progress, first)| async move {
+                if progress.done {
+                    return Ok(None);
+                }
+                if !first {
+                    tokio::time::sleep(paginator.pacing.interval).await;
+                }
+                let params = paginator.query(&progress);
+                debug!("Page {} {:?}", paginator.path, params);
+                let page = paginator.fetch(rest, &params).await?;
+                let page = paginator.advance(&mut progress, page);
+                Ok(Some((page, (paginator, progress, false))))
+            },
+        )
+    }
+
+    /// 逐条返回
+    pub fn items(self, rest: &Rest) -> impl Stream<Item = anyhow::Result<T>> + '_ {
+        self.pages(rest)
+            .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+            .try_flatten()
+    }
+}
+
+fn window_end(start: i64, end: i64, window: Option<i64>) -> i64 {
+    match window {
+        Some(window) => start.saturating_add(window).min(end),
+        None => end,
+    }
+}
+
+fn header(rsp: &reqwest::Response, name: &str) -> Option<u64> {
+    rsp.headers().get(name)?.to_str().ok()?.parse().ok()
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paginator(cursor: Cursor) -> Paginator<(i64, u8)> {
+        Paginator::new("/fapi/v1/income", cursor, |item: &(i64, u8)| item.0).with_limit(3)
+    }
+
+    #[test]
+    fn test_paginator() {
+        let time = Cursor::Time {
+            start: 100,
+            end: 1000,
+            window: Some(400),
+        };
+        let p = paginator(time);
+        let mut progress = Progress {
+            cursor: time,
+            skip: 0,
+            last: None,
+            done: false,
+        };
+        let query = p.query(&progress);
+        assert!(query.contains(&("endTime".into(), "500".into())));
+
+        // 满页，下一页从 120 开始并跳过已返回的两条 120
+        let page = p.advance(&mut progress, vec![(110, 0), (120, 1), (120, 2)]);
+        assert_eq!(page.len(), 3);
+        assert_eq!((progress.skip, progress.last), (2, Some(120)));
+        let page = p.advance(&mut progress, vec![(120, 1), (120, 2), (130, 3)]);
+        assert_eq!(page, vec![(130, 3)]);
+
+        // 不满页，进入下一个窗口
+        let page = p.advance(&mut progress, vec![(130, 3), (140, 4)]);
+        assert_eq!(page, vec![(140, 4)]);
+        assert!(p
+            .query(&progress)
+            .contains(&("startTime".into(), "531".into())));
+        assert!(p
+            .query(&progress)
+            .contains(&("endTime".into(), "931".into())));
+
+        p.advance(&mut progress, vec![]);
+        assert!(p
+            .query(&progress)
+            .contains(&("endTime".into(), "1000".into())));
+        p.advance(&mut progress, vec![]);
+        assert!(progress.done);
+
+        // 整页同一毫秒
+        let mut progress = Progress {
+            cursor: Cursor::Time {
+                start: 5,
+                end: 10,
+                window: None,
+            },
+            skip: 0,
+            last: None,
+            done: false,
+        };
+        p.advance(&mut progress, vec![(5, 0), (5, 1), (5, 2)]);
+        assert_eq!(p.query(&progress)[0], ("startTime".into(), "6".into()));
+
+        let p = paginator(Cursor::FromId(0));
+        let mut progress = Progress {
+            cursor: Cursor::FromId(0),
+            skip: 0,
+            last: None,
+            done: false,
+        };
+        p.advance(&mut progress, vec![(7, 0), (8, 0), (9, 0)]);
+        assert_eq!(p.query(&progress)[0], ("fromId".into(), "10".into()));
+        p.advance(&mut progress, vec![(10, 0)]);
+        assert!(progress.done);
+    }
+}
@@ -0,0 +1,199 @@
+//! 策略参数
+//!
+//! 按会话保存的键值参数，持久化到 `path` 指向的 JSON 文件。策略启动时用 `get_params` 读取本会话的参数，
+//! 之后该连接会收到参数的每次变化；运维用带 `admin_token` 的 `set_params` 修改参数，价差宽度等参数
+//! 可以在线调整，不必重启 Python 进程。
+
+use crate::admin::AdminConfig;
+use cryptoflow::chat::{SError, SParams};
+use cryptoflow::error_code::UNSUPPORTED;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+
+/// ```json
+/// "params": {
+///     "path": "params.json",
+///     "admin_token": "change-me"
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ParamsConfig {
+    pub path: String,
+    /// 为空时不接受修改
+    pub admin_token: String,
+}
+
+impl Default for ParamsConfig {
+    fn default() -> Self {
+        Self {
+            path: "params.json".into(),
+            admin_token: String::new(),
+        }
+    }
+}
+
+type Params = BTreeMap<String, Value>;
+
+#[derive(Debug, Default)]
+pub struct ParamStore {
+    config: ParamsConfig,
+    /// 校验 `admin_token`
+    admin: AdminConfig,
+    sessions: BTreeMap<u16, Params>,
+    /// 连接 -> 关注的会话
+    watchers: HashMap<SocketAddr, HashSet<u16>>,
+}
+
+impl ParamStore {
+    /// 读取已保存的参数，文件不存在时从空开始
+    pub fn open(config: &ParamsConfig) -> anyhow::Result<Self> {
+        let sessions = match std::fs::read_to_string(&config.path) {
+            Ok(data) => serde_json::from_str(&data)
+                .map_err(|e| anyhow::anyhow!("Parse params {} failed: {}", config.path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => anyhow::bail!("Read params {} failed: {}", config.path, e),
+        };
+        Ok(Self {
+            config: config.clone(),
+            admin: AdminConfig::new(&config.admin_token),
+            sessions,
+            watchers: HashMap::default(),
+        })
+    }
+
+    pub fn get(&self, session_id: u16) -> SParams {
+        SParams {
+            session_id,
+            params: self.sessions.get(&session_id).cloned().unwrap_or_default(),
+            changed: Vec::new(),
+        }
+    }
+
+    /// 连接关注一个会话的参数变化
+    pub fn watch(&mut self, addr: SocketAddr, session_id: u16) {
+        self.watchers.entry(addr).or_default().insert(session_id);
+    }
+
+    pub fn remove_client(&mut self, addr: &SocketAddr) {
+        self.watchers.remove(addr);
+    }
+
+    /// 校验运维的 token，未配置 token 时不接受修改
+    pub fn authorize(&self, token: &str) -> Result<(), SError> {
+        self.admin.authorize(token).map_err(|e| match e.code {
+            UNSUPPORTED => SError {
+                code: UNSUPPORTED,
+                msg: "parameter updates are disabled".into(),
+            },
+            _ => e,
+        })
+    }
+
+    /// 修改参数并保存，值为 null 的键被删除；返回修改后的参数与关注该会话的连接，没有变化时不保存
+    pub fn set(
+        &mut self,
+        session_id: u16,
+        updates: Params,
+    ) -> anyhow::Result<(SParams, Vec<SocketAddr>)> {
+        let params = self.sessions.entry(session_id).or_default();
+        let mut changed = Vec::new();
+        for (key, value) in updates {
+            let old = match value {
+                Value::Null => params.remove(&key),
+                value if params.get(&key) == Some(&value) => continue,
+                value => params.insert(key.clone(), value),
+            };
+            if old.is_some() || params.contains_key(&key) {
+                changed.push(key);
+            }
+        }
+        if params.is_empty() {
+            self.sessions.remove(&session_id);
+        }
+        if changed.is_empty() {
+            return Ok((self.get(session_id), Vec::new()));
+        }
+        self.save()?;
+
+        let watchers = self
+            .watchers
+            .iter()
+            .filter(|(_, sessions)| sessions.contains(&session_id))
+            .map(|(addr, _)| *addr)
+            .collect();
+        Ok((
+            SParams {
+                changed,
+                ..self.get(session_id)
+            },
+            watchers,
+        ))
+    }
+
+    /// 先写临时文件再改名，写到一半退出不会损坏已保存的参数
+    fn save(&self) -> anyhow::Result<()> {
+        let tmp = format!("{}.tmp", self.config.path);
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.sessions)?)?;
+        std::fs::rename(&tmp, &self.config.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::error_code::PERMISSION_DENIED;
+    use serde_json::json;
+
+    #[test]
+    fn test_param_store() {
+        let path = std::env::temp_dir().join(format!("params-{}.json", std::process::id()));
+        let config = ParamsConfig {
+            path: path.to_string_lossy().into(),
+            admin_token: "secret".into(),
+        };
+        let _ = std::fs::remove_file(&path);
+        let mut store = ParamStore::open(&config).unwrap();
+        assert_eq!(
+            store.authorize("wrong").unwrap_err().code,
+            PERMISSION_DENIED
+        );
+        assert!(store.authorize("secret").is_ok());
+        assert_eq!(
+            ParamStore::default().authorize("").unwrap_err().code,
+            UNSUPPORTED
+        );
+
+        let a: SocketAddr = "127.0.0.1:8111".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:8112".parse().unwrap();
+        store.watch(a, 1);
+        store.watch(b, 2);
+        let updates = |v: Value| serde_json::from_value::<Params>(v).unwrap();
+        let (params, watchers) = store
+            .set(1, updates(json!({"spread": 0.5, "size": 2})))
+            .unwrap();
+        assert_eq!(params.params["spread"], 0.5);
+        assert_eq!(params.changed, vec!["size", "spread"]);
+        assert_eq!(watchers, vec![a]);
+
+        // 没有变化的键不推送，null 删除键
+        let (params, _) = store
+            .set(
+                1,
+                updates(json!({"spread": 0.5, "size": null, "missing": null})),
+            )
+            .unwrap();
+        assert_eq!(params.changed, vec!["size"]);
+        let (params, watchers) = store.set(1, updates(json!({"spread": 0.5}))).unwrap();
+        assert!(params.changed.is_empty() && watchers.is_empty());
+
+        // 重新打开后参数还在
+        let store = ParamStore::open(&config).unwrap();
+        assert_eq!(store.get(1).params, updates(json!({"spread": 0.5})));
+        assert!(store.get(2).params.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,391 @@
+//! 跟随盘口的钉住订单
+//!
+//! 订单带上 `peg` 后，网关按 bookTicker 的最优买卖价（可偏移若干个 tick）给出价格，盘口变化超过
+//! `threshold_ticks` 时撤单重挂。重挂沿用原订单号，中间的撤单回报不推送给策略；每笔订单的改价
+//! 次数受 `min_reprice_ms` 与 `max_reprices_per_min` 限制，避免盘口抖动时耗尽请求权重。
+
+use crate::model::order::{BinanceOrder, Peg};
+use cryptoflow::chat::{Side, State};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 计算平均价时最多回看的秒数
+const HISTORY_SECS: i64 = 15 * 60;
+
+/// ```json
+/// "peg": {
+///     "min_reprice_ms": 500,
+///     "max_reprices_per_min": 30
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PegConfig {
+    /// 同一笔订单两次改价的最小间隔
+    pub min_reprice_ms: u64,
+    /// 同一笔订单每分钟最多改价次数，0 表示不限制
+    pub max_reprices_per_min: usize,
+}
+
+impl Default for PegConfig {
+    fn default() -> Self {
+        Self {
+            min_reprice_ms: 500,
+            max_reprices_per_min: 30,
+        }
+    }
+}
+
+/// 每秒最后一个中间价，(秒, 中间价)
+type Mids = VecDeque<(i64, f64)>;
+
+/// 各交易对的最优买卖价，由 market 写入、trade 读取
+#[derive(Debug, Clone, Default)]
+pub struct BookTickers {
+    bbo: Arc<Mutex<HashMap<String, (f64, f64)>>>,
+    mids: Arc<Mutex<HashMap<String, Mids>>>,
+}
+
+impl BookTickers {
+    pub fn update(&self, symbol: &str, bid: f64, ask: f64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        self.update_at(symbol, bid, ask, now);
+    }
+
+    pub fn update_at(&self, symbol: &str, bid: f64, ask: f64, time_ms: i64) {
+        if bid <= 0.0 || ask <= 0.0 {
+            return;
+        }
+        let symbol = symbol.to_lowercase();
+        {
+            let mut mids = self.mids.lock().unwrap_or_else(|p| p.into_inner());
+            let mids = mids.entry(symbol.clone()).or_default();
+            let (second, mid) = (time_ms / 1000, (bid + ask) / 2.0);
+            match mids.back_mut() {
+                Some(last) if last.0 == second => last.1 = mid,
+                _ => mids.push_back((second, mid)),
+            }
+            while mids
+                .front()
+                .is_some_and(|(s, _)| *s <= second - HISTORY_SECS)
+            {
+                mids.pop_front();
+            }
+        }
+        let mut bbo = self.bbo.lock().unwrap_or_else(|p| p.into_inner());
+        bbo.insert(symbol, (bid, ask));
+    }
+
+    /// 最近 `mins` 分钟内中间价的平均值，每秒取一个点，至多回看 15 分钟；`mins` 为 0 时为最新的中间价
+    pub fn average(&self, symbol: &str, mins: u32, now_ms: i64) -> Option<f64> {
+        let mids = self.mids.lock().unwrap_or_else(|p| p.into_inner());
+        let mids = mids.get(&symbol.to_lowercase())?;
+        if mins == 0 {
+            return mids.back().map(|(_, mid)| *mid);
+        }
+        let since = now_ms / 1000 - (mins as i64 * 60).min(HISTORY_SECS);
+        let (sum, count) = mids
+            .iter()
+            .rev()
+            .take_while(|(second, _)| *second > since)
+            .fold((0.0, 0), |(sum, count), (_, mid)| (sum + mid, count + 1));
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<(f64, f64)> {
+        let bbo = self.bbo.lock().unwrap_or_else(|p| p.into_inner());
+        bbo.get(&symbol.to_lowercase()).copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum PegAction {
+    /// 通过 addr 对应的连接重新挂单
+    Place(SocketAddr, BinanceOrder),
+    Cancel {
+        session_id: u16,
+        order_id: u32,
+        symbol: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PegState {
+    /// 已发出下单，等待交易所确认
+    Placing,
+    Live,
+    /// 已发出撤单，撤单回报到达后按新价格重挂
+    Repricing,
+}
+
+#[derive(Debug)]
+struct Pegged {
+    addr: SocketAddr,
+    /// 当前挂单，quantity 为原始数量
+    order: BinanceOrder,
+    peg: Peg,
+    tick: f64,
+    filled: f64,
+    state: PegState,
+    /// 最近一分钟内的改价时间
+    reprices: VecDeque<Instant>,
+}
+
+impl Pegged {
+    fn target(&self, bbo: (f64, f64)) -> f64 {
+        let offset = self.peg.offset_ticks as f64 * self.tick;
+        let price = match self.order.side {
+            Side::BUY => bbo.0 - offset,
+            Side::SELL => bbo.1 + offset,
+        };
+        if self.tick > 0.0 {
+            (price / self.tick).round() * self.tick
+        } else {
+            price
+        }
+    }
+
+    fn allow_reprice(&mut self, config: &PegConfig, now: Instant) -> bool {
+        while self
+            .reprices
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= Duration::from_secs(60))
+        {
+            self.reprices.pop_front();
+        }
+        if let Some(last) = self.reprices.back() {
+            if now.duration_since(*last) < Duration::from_millis(config.min_reprice_ms) {
+                return false;
+            }
+        }
+        config.max_reprices_per_min == 0 || self.reprices.len() < config.max_reprices_per_min
+    }
+}
+
+#[derive(Debug, Default)]
+struct PegBook {
+    config: PegConfig,
+    orders: HashMap<(u16, u32), Pegged>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pegs {
+    book: Arc<Mutex<PegBook>>,
+}
+
+impl Pegs {
+    pub fn new(config: PegConfig) -> Self {
+        Self {
+            book: Arc::new(Mutex::new(PegBook {
+                config,
+                orders: HashMap::default(),
+            })),
+        }
+    }
+
+    fn with_book<R>(&self, f: impl FnOnce(&mut PegBook) -> R) -> R {
+        let mut book = self.book.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut book)
+    }
+
+    /// 登记钉住订单，返回按当前盘口定价的订单；还没有盘口时沿用策略给出的价格
+    pub fn register(
+        &self,
+        addr: SocketAddr,
+        order: &BinanceOrder,
+        tick: f64,
+        bbo: Option<(f64, f64)>,
+    ) -> BinanceOrder {
+        let Some(peg) = order.peg.clone() else {
+            return order.clone();
+        };
+        let mut pegged = Pegged {
+            addr,
+            order: BinanceOrder {
+                peg: None,
+                ..order.clone()
+            },
+            peg,
+            tick,
+            filled: 0.0,
+            state: PegState::Placing,
+            reprices: VecDeque::default(),
+        };
+        if let Some(bbo) = bbo {
+            pegged.order.price = pegged.target(bbo);
+        }
+        let order = pegged.order.clone();
+        self.with_book(|book| book.orders.insert((order.session_id, order.id), pegged));
+        order
+    }
+
+    /// 订单回报，返回是否推送给策略以及需要执行的操作
+    pub fn on_order(
+        &self,
+        session_id: u16,
+        order_id: u32,
+        state: State,
+        trd_vol: f64,
+    ) -> (bool, Vec<PegAction>) {
+        self.with_book(|book| {
+            let key = (session_id, order_id);
+            let Some(pegged) = book.orders.get_mut(&key) else {
+                return (true, Vec::new());
+            };
+            pegged.filled += trd_vol;
+
+            match state {
+                State::NEW | State::LIVE | State::PENDING_NEW => {
+                    if pegged.state == PegState::Placing {
+                        pegged.state = PegState::Live;
+                    }
+                    (true, Vec::new())
+                }
+                State::PARTIALLY_FILLED | State::PENDING_CANCEL => (true, Vec::new()),
+                State::CANCELED if pegged.state == PegState::Repricing => {
+                    let remaining = pegged.order.quantity - pegged.filled;
+                    if remaining <= 0.0 {
+                        book.orders.remove(&key);
+                        return (true, Vec::new());
+                    }
+                    pegged.state = PegState::Placing;
+                    // 存活时间从第一次下单算起，重挂时不再设置
+                    let order = BinanceOrder {
+                        quantity: remaining,
+                        ttl_ms: None,
+                        ..pegged.order.clone()
+                    };
+                    (false, vec![PegAction::Place(pegged.addr, order)])
+                }
+                _ => {
+                    book.orders.remove(&key);
+                    (true, Vec::new())
+                }
+            }
+        })
+    }
+
+    /// 策略主动撤单，之后的撤单回报照常推送
+    pub fn on_cancel(&self, session_id: u16, order_id: u32) {
+        self.with_book(|book| book.orders.remove(&(session_id, order_id)));
+    }
+
+    /// 下单被拒绝
+    pub fn on_reject(&self, session_id: u16, order_id: u32) {
+        self.on_cancel(session_id, order_id);
+    }
+
+    /// 盘口偏离超过阈值的挂单发出撤单，撤单回报到达后按新价格重挂
+    pub fn reprice(&self, tickers: &BookTickers, now: Instant) -> Vec<PegAction> {
+        self.with_book(|book| {
+            let config = book.config.clone();
+            let mut actions = Vec::new();
+            for ((session_id, order_id), pegged) in book.orders.iter_mut() {
+                if pegged.state != PegState::Live {
+                    continue;
+                }
+                let Some(bbo) = tickers.get(&pegged.order.symbol) else {
+                    continue;
+                };
+                let target = pegged.target(bbo);
+                let threshold = pegged.peg.threshold_ticks.max(1) as f64 * pegged.tick;
+                if (target - pegged.order.price).abs() + f64::EPSILON < threshold {
+                    continue;
+                }
+                if !pegged.allow_reprice(&config, now) {
+                    continue;
+                }
+                pegged.reprices.push_back(now);
+                pegged.order.price = target;
+                pegged.state = PegState::Repricing;
+                actions.push(PegAction::Cancel {
+                    session_id: *session_id,
+                    order_id: *order_id,
+                    symbol: pegged.order.symbol.clone(),
+                });
+            }
+            actions
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(peg: Option<Peg>) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price: 100.0,
+            quantity: 2.0,
+            session_id: 1,
+            peg,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pegs() {
+        let pegs = Pegs::new(PegConfig {
+            min_reprice_ms: 1000,
+            max_reprices_per_min: 2,
+        });
+        let tickers = BookTickers::default();
+        let addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let peg = Peg {
+            offset_ticks: 1,
+            threshold_ticks: 2,
+        };
+
+        // 买单挂在买一下方一个 tick
+        let placed = pegs.register(addr, &order(Some(peg)), 0.5, Some((101.0, 101.5)));
+        assert_eq!(placed.price, 100.5);
+        assert!(placed.peg.is_none());
+        let now = Instant::now();
+        let (forward, actions) = pegs.on_order(1, 1, State::NEW, 0.0);
+        assert!(forward && actions.is_empty());
+
+        // 变化不足阈值不改价
+        tickers.update("BTCUSDT", 101.5, 102.0);
+        assert!(pegs.reprice(&tickers, now).is_empty());
+
+        tickers.update("BTCUSDT", 102.0, 102.5);
+        let actions = pegs.reprice(&tickers, now);
+        assert!(matches!(
+            actions[..],
+            [PegAction::Cancel { order_id: 1, .. }]
+        ));
+
+        // 撤单回报不推送，按新价格重挂剩余数量
+        assert!(pegs.on_order(1, 1, State::PARTIALLY_FILLED, 0.5).0);
+        let (forward, actions) = pegs.on_order(1, 1, State::CANCELED, 0.0);
+        assert!(!forward);
+        match &actions[..] {
+            [PegAction::Place(_, order)] => {
+                assert_eq!(order.price, 101.5);
+                assert_eq!(order.quantity, 1.5);
+            }
+            _ => panic!("{:?}", actions),
+        }
+        pegs.on_order(1, 1, State::NEW, 0.0);
+
+        // 间隔不足时不改价
+        tickers.update("BTCUSDT", 110.0, 110.5);
+        assert!(pegs.reprice(&tickers, now).is_empty());
+        assert_eq!(
+            pegs.reprice(&tickers, now + Duration::from_secs(1)).len(),
+            1
+        );
+
+        // 策略撤单后回报照常推送
+        pegs.on_cancel(1, 1);
+        assert!(pegs.on_order(1, 1, State::CANCELED, 0.0).0);
+    }
+}
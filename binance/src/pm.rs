@@ -0,0 +1,349 @@
+//! 统一账户（Portfolio Margin）
+//!
+//! `account_mode` 为 `portfolio_margin` 时，U 本位合约与现货网关的交易、持仓与资金流水改走
+//! `papi.binance.com` 的统一账户接口（现货交易走统一账户的全仓杠杆），交易规则仍取自各自的公开接口。
+//! 两个网关都从统一账户的 listenKey 用户数据流接收回报，只处理本交易场所的事件。
+//! 保证金按统一账户跨资产计算：网关定时查询统一维持保证金率 uniMMR 并跟随 `riskLevelChange` 更新，
+//! uniMMR 低于 `min_uni_mmr` 或账户进入只减仓、强平状态时拒绝新订单。
+
+use crate::margin::{LoanType, SLoan};
+use crate::model::RiskLevelChange;
+use crate::rest::Rest;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::Receiver;
+use tracing::{info, warn};
+use websocket::BinanceWebsocketClient;
+
+pub const PAPI_URI: &str = "https://papi.binance.com";
+
+const PM_STREAM_URI: &str = "wss://fstream.binance.com/pm/ws";
+
+/// 借还款的路径与参数
+pub type LoanRequest = (&'static str, Vec<(String, String)>);
+
+/// 账户模式
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountMode {
+    /// 现货、杠杆、合约各自独立的经典账户
+    #[default]
+    Classic,
+    /// 统一账户
+    PortfolioMargin,
+}
+
+/// U 本位合约交易与账户接口的路径
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UmEndpoints {
+    pub order: &'static str,
+    pub position_risk: &'static str,
+    pub dual_side: &'static str,
+    pub income: &'static str,
+    pub user_trades: &'static str,
+    pub open_orders: &'static str,
+    pub leverage_bracket: &'static str,
+    pub balance: &'static str,
+    /// 余额接口中钱包余额的字段名
+    pub wallet_balance: &'static str,
+}
+
+impl AccountMode {
+    /// 交易接口使用的 REST，经典账户沿用 `rest`，统一账户改为 papi 域名
+    pub fn trading_rest(&self, rest: &Arc<Rest>) -> Arc<Rest> {
+        match self {
+            Self::Classic => rest.clone(),
+            Self::PortfolioMargin => Arc::new(rest.with_base_uri(PAPI_URI)),
+        }
+    }
+
+    pub fn um(&self) -> UmEndpoints {
+        match self {
+            Self::Classic => UmEndpoints {
+                order: "/fapi/v1/order",
+                position_risk: "/fapi/v2/positionRisk",
+                dual_side: "/fapi/v1/positionSide/dual",
+                income: "/fapi/v1/income",
+                user_trades: "/fapi/v1/userTrades",
+                open_orders: "/fapi/v1/openOrders",
+                leverage_bracket: "/fapi/v1/leverageBracket",
+                balance: "/fapi/v2/balance",
+                wallet_balance: "balance",
+            },
+            Self::PortfolioMargin => UmEndpoints {
+                order: "/papi/v1/um/order",
+                position_risk: "/papi/v1/um/positionRisk",
+                dual_side: "/papi/v1/um/positionSide/dual",
+                income: "/papi/v1/um/income",
+                user_trades: "/papi/v1/um/userTrades",
+                open_orders: "/papi/v1/um/openOrders",
+                leverage_bracket: "/papi/v1/um/leverageBracket",
+                balance: "/papi/v1/balance",
+                wallet_balance: "umWalletBalance",
+            },
+        }
+    }
+
+    /// 杠杆下单与撤单的路径
+    pub fn margin_order(&self) -> &'static str {
+        match self {
+            Self::Classic => "/sapi/v1/margin/order",
+            Self::PortfolioMargin => "/papi/v1/margin/order",
+        }
+    }
+
+    /// 杠杆账户当前挂单的路径
+    pub fn margin_open_orders(&self) -> &'static str {
+        match self {
+            Self::Classic => "/sapi/v1/margin/openOrders",
+            Self::PortfolioMargin => "/papi/v1/margin/openOrders",
+        }
+    }
+
+    /// 杠杆账户成交历史的路径
+    pub fn margin_trades(&self) -> &'static str {
+        match self {
+            Self::Classic => "/sapi/v1/margin/myTrades",
+            Self::PortfolioMargin => "/papi/v1/margin/myTrades",
+        }
+    }
+}
+
+/// ```json
+/// "portfolio_margin": {
+///     "min_uni_mmr": 1.5,
+///     "refresh_secs": 30
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PortfolioMarginConfig {
+    /// uniMMR 低于该值时拒绝新订单，0 表示不检查
+    pub min_uni_mmr: f64,
+    /// 查询统一账户的间隔
+    pub refresh_secs: u64,
+}
+
+impl Default for PortfolioMarginConfig {
+    fn default() -> Self {
+        Self {
+            min_uni_mmr: 1.5,
+            refresh_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Risk {
+    uni_mmr: Option<f64>,
+    status: String,
+}
+
+/// 统一账户的跨资产保证金检查
+#[derive(Debug, Clone, Default)]
+pub struct PmRisk {
+    config: PortfolioMarginConfig,
+    risk: Arc<Mutex<Risk>>,
+}
+
+impl PmRisk {
+    pub fn new(config: PortfolioMarginConfig) -> Self {
+        Self {
+            config,
+            risk: Arc::default(),
+        }
+    }
+
+    fn with_risk<R>(&self, f: impl FnOnce(&mut Risk) -> R) -> R {
+        let mut risk = self.risk.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut risk)
+    }
+
+    pub fn uni_mmr(&self) -> Option<f64> {
+        self.with_risk(|risk| risk.uni_mmr)
+    }
+
+    fn update(&self, uni_mmr: &str, status: &str) {
+        let uni_mmr = uni_mmr.parse::<f64>().ok();
+        self.with_risk(|risk| {
+            if uni_mmr.is_some() {
+                risk.uni_mmr = uni_mmr;
+            }
+            if !status.is_empty() {
+                risk.status = status.to_string();
+            }
+        });
+    }
+
+    /// 新订单的检查，还没有查询到 uniMMR 时同样拒绝
+    pub fn check_order(&self) -> Result<(), String> {
+        self.with_risk(|risk| {
+            if matches!(
+                risk.status.as_str(),
+                "REDUCE_ONLY" | "ACTIVE_LIQUIDATION" | "FORCE_LIQUIDATION" | "BANKRUPTED"
+            ) {
+                return Err(format!("account status {}", risk.status));
+            }
+            if self.config.min_uni_mmr <= 0.0 {
+                return Ok(());
+            }
+            match risk.uni_mmr {
+                Some(uni_mmr) if uni_mmr >= self.config.min_uni_mmr => Ok(()),
+                Some(uni_mmr) => Err(format!(
+                    "uniMMR {} below {}",
+                    uni_mmr, self.config.min_uni_mmr
+                )),
+                None => Err("uniMMR unknown".into()),
+            }
+        })
+    }
+
+    /// 统一账户借币 `/papi/v1/marginLoan` 或还款 `/papi/v1/repayLoan` 的路径与参数，借币前检查保证金
+    pub fn loan_request(&self, loan_type: LoanType, loan: &SLoan) -> Result<LoanRequest, String> {
+        if loan.amount <= 0.0 {
+            return Err(format!("invalid amount {}", loan.amount));
+        }
+        let path = match loan_type {
+            LoanType::BORROW => {
+                self.check_order()?;
+                "/papi/v1/marginLoan"
+            }
+            LoanType::REPAY => "/papi/v1/repayLoan",
+        };
+        Ok((
+            path,
+            vec![
+                ("asset".into(), loan.asset.to_uppercase()),
+                ("amount".into(), loan.amount.to_string()),
+            ],
+        ))
+    }
+
+    /// 查询一次 `/papi/v1/account`
+    pub async fn refresh(&self, rest: &Rest) -> anyhow::Result<()> {
+        let rsp = rest.get("/papi/v1/account", &[], true).await?;
+        let value: Value = serde_json::from_str(&rsp.text().await?)?;
+        let uni_mmr = value["uniMMR"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+        self.update(uni_mmr, value["accountStatus"].as_str().unwrap_or_default());
+        Ok(())
+    }
+
+    /// 按 `refresh_secs` 定时查询统一账户
+    pub fn spawn_refresh(&self, rest: Arc<Rest>) {
+        let risk = self.clone();
+        let period = Duration::from_secs(self.config.refresh_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = risk.refresh(&rest).await {
+                    warn!("Refresh portfolio margin account failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 统一账户风险等级变化，进入追加保证金或更差的状态时返回 true
+    pub fn on_risk_level(&self, change: &RiskLevelChange) -> bool {
+        info!("uniMMR {} status {}", change.u, change.s);
+        self.update(&change.u, &change.s);
+        change.s != "NORMAL"
+    }
+}
+
+/// 统一账户的 listenKey 用户数据流，每 30 分钟延长一次 listenKey
+pub struct PmUserStream {
+    _client: BinanceWebsocketClient,
+    rx: Receiver<Value>,
+    disconnected: bool,
+}
+
+impl PmUserStream {
+    pub async fn connect(rest: Arc<Rest>) -> anyhow::Result<Self> {
+        let rsp = rest.post("/papi/v1/listenKey", &[], false).await?;
+        let value: Value = serde_json::from_str(&rsp.text().await?)?;
+        let listen_key = value["listenKey"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+
+        let mut client = BinanceWebsocketClient::new_public("pm user data");
+        client.set_url(format!("{}/{}", PM_STREAM_URI, listen_key));
+        let rx = client.connect().await?;
+        info!("Portfolio margin user data stream connected");
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(30 * 60));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = rest.put("/papi/v1/listenKey", &[], false).await {
+                    warn!("Keep alive listen key failed: {}", e);
+                }
+            }
+        });
+
+        Ok(Self {
+            _client: client,
+            rx,
+            disconnected: false,
+        })
+    }
+
+    pub fn disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    /// 取出一条推送
+    pub fn process(&mut self) -> Option<Value> {
+        match self.rx.try_recv() {
+            Ok(value) => Some(value),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                if !self.disconnected {
+                    warn!("Portfolio margin user data stream closed");
+                }
+                self.disconnected = true;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pm_risk() {
+        let mode: AccountMode = serde_json::from_str(r#""portfolio_margin""#).unwrap();
+        assert_eq!(mode.um().order, "/papi/v1/um/order");
+        assert_eq!(
+            AccountMode::default().margin_order(),
+            "/sapi/v1/margin/order"
+        );
+
+        let risk = PmRisk::new(PortfolioMarginConfig::default());
+        assert!(risk.check_order().is_err());
+        risk.update("5.2", "NORMAL");
+        assert!(risk.check_order().is_ok());
+
+        let change: RiskLevelChange = serde_json::from_str(
+            r#"{"E":1587727187525,"u":"1.2","s":"MARGIN_CALL","eq":"30.2","ae":"30.2","m":"25.2"}"#,
+        )
+        .unwrap();
+        assert!(risk.on_risk_level(&change));
+        assert_eq!(risk.uni_mmr(), Some(1.2));
+        assert!(risk.check_order().unwrap_err().contains("below 1.5"));
+
+        risk.update("3.0", "REDUCE_ONLY");
+        assert_eq!(
+            risk.check_order().unwrap_err(),
+            "account status REDUCE_ONLY"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! 只做 maker 的订单
+//!
+//! `LIMIT_MAKER` 与 GTX 的订单都只做 maker。合约支持 GTX，`LIMIT_MAKER` 换成 LIMIT + GTX 后交给交易所；
+//! 现货没有 GTX，网关改为 `LIMIT_MAKER` 下单，并在下单前按 bookTicker 检查会不会立即成交，会成交的
+//! 订单直接在网关拒绝。订单带上 `reprice_passive` 时两个交易场所都在下单前检查，会成交的订单改为
+//! 不会成交的最优价格：买单为卖一价减一个 tick，卖单为买一价加一个 tick。还没有收到 bookTicker 时
+//! 不检查，由交易所处理。
+
+use crate::model::order::BinanceOrder;
+use cryptoflow::chat::{OrderType, Side, TimeInForce};
+
+pub fn is_post_only(order: &BinanceOrder) -> bool {
+    order.order_type == OrderType::LIMIT_MAKER || order.tif == TimeInForce::GTX
+}
+
+/// 按最优买卖价会不会立即成交
+fn crosses(side: Side, price: f64, (bid, ask): (f64, f64)) -> bool {
+    match side {
+        Side::BUY => price >= ask,
+        Side::SELL => price <= bid,
+    }
+}
+
+/// 不会立即成交的最优价格，没有 tick 时与同侧最优价同价
+fn passive_price(side: Side, (bid, ask): (f64, f64), tick: f64) -> f64 {
+    if tick <= 0.0 {
+        return match side {
+            Side::BUY => bid,
+            Side::SELL => ask,
+        };
+    }
+    let n = match side {
+        Side::BUY => (ask / tick).round() - 1.0,
+        Side::SELL => (bid / tick).round() + 1.0,
+    };
+    to_price(n, tick)
+}
+
+/// 第 n 个 tick 的价格，按 tick 的小数位数舍入浮点误差
+fn to_price(n: f64, tick: f64) -> f64 {
+    let decimals = (-tick.log10()).ceil().max(0.0) as i32;
+    let scale = 10f64.powi(decimals);
+    (n * tick * scale).round() / scale
+}
+
+/// 从 `price` 往被动方向挪 `ticks` 个 tick：买单往下，卖单往上
+pub fn step_passive(side: Side, price: f64, tick: f64, ticks: u32) -> f64 {
+    let n = (price / tick).round();
+    match side {
+        Side::BUY => to_price(n - ticks as f64, tick),
+        Side::SELL => to_price(n + ticks as f64, tick),
+    }
+}
+
+/// 下单前处理只做 maker 的订单，`native_gtx` 为交易场所支持 GTX
+///
+/// 返回要发出的订单，与原订单相同时为 None；会立即成交又不改价时返回拒绝的原因
+pub fn prepare(
+    order: &BinanceOrder,
+    bbo: Option<(f64, f64)>,
+    tick: f64,
+    native_gtx: bool,
+) -> Result<Option<BinanceOrder>, String> {
+    if !is_post_only(order) {
+        return Ok(None);
+    }
+    let mut prepared = order.clone();
+    match native_gtx {
+        true => {
+            prepared.order_type = OrderType::LIMIT;
+            prepared.tif = TimeInForce::GTX;
+        }
+        false => prepared.order_type = OrderType::LIMIT_MAKER,
+    }
+
+    // 支持 GTX 且不改价时由交易所检查
+    let check = !native_gtx || order.reprice_passive;
+    if let Some(bbo) = bbo.filter(|_| check) {
+        if crosses(order.side, order.price, bbo) {
+            if !order.reprice_passive {
+                return Err(format!(
+                    "post-only {:?} at {} would cross {:?}",
+                    order.side, order.price, bbo
+                ));
+            }
+            prepared.price = passive_price(order.side, bbo, tick);
+            if prepared.price <= 0.0 {
+                return Err(format!(
+                    "no passive price for {:?} at {:?}",
+                    order.side, bbo
+                ));
+            }
+        }
+    }
+
+    let unchanged = prepared.order_type == order.order_type
+        && prepared.tif == order.tif
+        && prepared.price == order.price;
+    Ok((!unchanged).then_some(prepared))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: Side, price: f64, order_type: OrderType, tif: TimeInForce) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price,
+            quantity: 1.0,
+            side,
+            order_type,
+            tif,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prepare() {
+        let bbo = Some((100.0, 100.1));
+        let limit = order(Side::BUY, 100.1, OrderType::LIMIT, TimeInForce::GTC);
+        assert!(prepare(&limit, bbo, 0.1, false).unwrap().is_none());
+
+        // 合约原样交给交易所，LIMIT_MAKER 换成 GTX
+        let gtx = order(Side::BUY, 100.1, OrderType::LIMIT, TimeInForce::GTX);
+        assert!(prepare(&gtx, bbo, 0.1, true).unwrap().is_none());
+        let maker = order(Side::SELL, 100.0, OrderType::LIMIT_MAKER, TimeInForce::GTC);
+        let prepared = prepare(&maker, bbo, 0.1, true).unwrap().unwrap();
+        assert_eq!(
+            (prepared.order_type, prepared.tif, prepared.price),
+            (OrderType::LIMIT, TimeInForce::GTX, 100.0)
+        );
+
+        // 现货在网关检查，会成交时拒绝，没有盘口时不检查
+        assert!(prepare(&gtx, bbo, 0.1, false).is_err());
+        assert!(prepare(&maker, bbo, 0.1, false).is_err());
+        let prepared = prepare(&gtx, None, 0.1, false).unwrap().unwrap();
+        assert_eq!(
+            (prepared.order_type, prepared.price),
+            (OrderType::LIMIT_MAKER, 100.1)
+        );
+        let passive = order(Side::BUY, 100.05, OrderType::LIMIT_MAKER, TimeInForce::GTC);
+        assert!(prepare(&passive, bbo, 0.1, false).unwrap().is_none());
+
+        // 改价到不会成交的最优价格
+        let reprice = |side, price, native| {
+            let mut o = order(side, price, OrderType::LIMIT, TimeInForce::GTX);
+            o.reprice_passive = true;
+            prepare(&o, bbo, 0.1, native).unwrap().map(|o| o.price)
+        };
+        assert_eq!(reprice(Side::BUY, 101.0, true), Some(100.0));
+        assert_eq!(reprice(Side::SELL, 99.0, false), Some(100.1));
+        assert_eq!(reprice(Side::BUY, 99.5, true), None);
+        assert_eq!(passive_price(Side::SELL, (0.3, 0.5), 0.1), 0.4);
+    }
+}
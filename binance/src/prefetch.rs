@@ -0,0 +1,364 @@
+//! 启动时并发预取交易对的元数据
+//!
+//! 交易对很多时逐个查询杠杆分层、资金费率与深度快照会拖慢冷启动。开启后网关在连接行情与账户的同时，
+//! 以至多 `concurrency` 个并发请求预取 `symbols` 的这些数据，并按进度输出日志；预取结束后网关才开始
+//! 接受策略端连接，失败的请求只记录不重试。预取的深度快照在 `depth_snapshot.ttl_ms` 内直接用于
+//! `get_depth_snapshot`。
+
+use crate::depth_snapshot;
+use crate::model::funding::{BinanceFundingInfo, BinancePremiumIndex};
+use crate::model::leverage::{BinanceLeverageBracket, LeverageBrackets};
+use crate::model::quote::BinanceQuote;
+use crate::rest::Rest;
+use cryptoflow::chat::SDepthSnapshot;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// ```json
+/// "prefetch": {
+///     "enabled": false,
+///     "symbols": ["btcusdt", "ethusdt"],
+///     "concurrency": 8,
+///     "leverage_brackets": true,
+///     "funding": true,
+///     "depth_limit": 0
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PrefetchConfig {
+    pub enabled: bool,
+    pub symbols: Vec<String>,
+    /// 同时进行的请求数
+    pub concurrency: usize,
+    /// 预取杠杆分层，只有合约网关支持
+    pub leverage_brackets: bool,
+    /// 预取资金费率与上下限，只有合约网关支持
+    pub funding: bool,
+    /// 预取深度快照的档位数，0 表示不预取
+    pub depth_limit: u32,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            symbols: Vec::new(),
+            concurrency: 8,
+            leverage_brackets: true,
+            funding: true,
+            depth_limit: 0,
+        }
+    }
+}
+
+/// 各类数据的 REST 路径，交易场所不支持的为 None
+#[derive(Debug, Clone, Copy)]
+pub struct PrefetchPaths {
+    pub depth: &'static str,
+    pub leverage_bracket: Option<&'static str>,
+    pub premium_index: Option<&'static str>,
+    pub funding_info: Option<&'static str>,
+}
+
+impl PrefetchPaths {
+    pub fn spot() -> Self {
+        Self {
+            depth: "/api/v3/depth",
+            leverage_bracket: None,
+            premium_index: None,
+            funding_info: None,
+        }
+    }
+
+    /// `leverage_bracket` 随账户模式不同，见 [`crate::pm::UmEndpoints`]
+    pub fn usdt(leverage_bracket: &'static str) -> Self {
+        Self {
+            depth: "/fapi/v1/depth",
+            leverage_bracket: Some(leverage_bracket),
+            premium_index: Some("/fapi/v1/premiumIndex"),
+            funding_info: Some("/fapi/v1/fundingInfo"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Task {
+    /// 所有交易对一次返回
+    FundingInfo,
+    LeverageBracket(String),
+    PremiumIndex(String),
+    Depth(String),
+}
+
+fn tasks(config: &PrefetchConfig, paths: &PrefetchPaths) -> Vec<Task> {
+    let mut symbols: Vec<String> = Vec::new();
+    for symbol in config.symbols.iter() {
+        let symbol = symbol.to_lowercase();
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    let funding = config.funding && paths.premium_index.is_some();
+    let brackets = config.leverage_brackets && paths.leverage_bracket.is_some();
+    let mut tasks = Vec::new();
+    if config.funding && paths.funding_info.is_some() && !symbols.is_empty() {
+        tasks.push(Task::FundingInfo);
+    }
+    for symbol in symbols {
+        if brackets {
+            tasks.push(Task::LeverageBracket(symbol.clone()));
+        }
+        if funding {
+            tasks.push(Task::PremiumIndex(symbol.clone()));
+        }
+        if config.depth_limit > 0 {
+            tasks.push(Task::Depth(symbol));
+        }
+    }
+    tasks
+}
+
+/// 预取的深度快照与发出查询的时间、档位数
+pub type PrefetchedDepth = (Instant, u32, SDepthSnapshot<BinanceQuote>);
+
+#[derive(Debug, Default)]
+struct Data {
+    leverage_brackets: HashMap<String, BinanceLeverageBracket>,
+    premium_index: HashMap<String, BinancePremiumIndex>,
+    funding_info: HashMap<String, BinanceFundingInfo>,
+    depth: HashMap<String, PrefetchedDepth>,
+}
+
+/// 预取到的数据，按小写的交易对索引，预取过程中逐步写入
+#[derive(Debug, Clone, Default)]
+pub struct Prefetched {
+    data: Arc<Mutex<Data>>,
+}
+
+impl Prefetched {
+    fn with_data<R>(&self, f: impl FnOnce(&mut Data) -> R) -> R {
+        let mut data = self.data.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut data)
+    }
+
+    pub fn leverage_bracket(&self, symbol: &str) -> Option<BinanceLeverageBracket> {
+        self.with_data(|data| data.leverage_brackets.get(symbol).cloned())
+    }
+
+    pub fn premium_index(&self, symbol: &str) -> Option<BinancePremiumIndex> {
+        self.with_data(|data| data.premium_index.get(symbol).cloned())
+    }
+
+    /// 只有调整过资金费率上下限或收取间隔的交易对有记录
+    pub fn funding_info(&self, symbol: &str) -> Option<BinanceFundingInfo> {
+        self.with_data(|data| data.funding_info.get(symbol).cloned())
+    }
+
+    pub fn depth(&self, symbol: &str) -> Option<PrefetchedDepth> {
+        self.with_data(|data| data.depth.get(symbol).cloned())
+    }
+
+    /// 解析一个请求的响应并写入
+    fn store(&self, task: &Task, body: &str, limit: u32, fetched: Instant) -> anyhow::Result<()> {
+        match task {
+            Task::FundingInfo => {
+                let info: Vec<BinanceFundingInfo> = serde_json::from_str(body)?;
+                self.with_data(|data| {
+                    for info in info {
+                        data.funding_info.insert(info.symbol.clone(), info);
+                    }
+                });
+            }
+            Task::LeverageBracket(_) => {
+                let brackets: Vec<BinanceLeverageBracket> =
+                    serde_json::from_str::<LeverageBrackets>(body)?.into();
+                self.with_data(|data| {
+                    for bracket in brackets {
+                        data.leverage_brackets
+                            .insert(bracket.symbol.clone(), bracket);
+                    }
+                });
+            }
+            Task::PremiumIndex(symbol) => {
+                let premium: BinancePremiumIndex = serde_json::from_str(body)?;
+                self.with_data(|data| data.premium_index.insert(symbol.clone(), premium));
+            }
+            Task::Depth(symbol) => {
+                let snapshot = depth_snapshot::parse(symbol, body)?;
+                self.with_data(|data| {
+                    data.depth
+                        .insert(symbol.clone(), (fetched, limit, snapshot))
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn fetch(
+    task: &Task,
+    public: &Rest,
+    signed: &Rest,
+    paths: &PrefetchPaths,
+    depth_limit: u32,
+) -> anyhow::Result<String> {
+    let symbol = |symbol: &str| vec![("symbol".to_string(), symbol.to_uppercase())];
+    let (rest, path, params, signature) = match task {
+        Task::FundingInfo => (public, paths.funding_info, Vec::new(), false),
+        Task::LeverageBracket(s) => (signed, paths.leverage_bracket, symbol(s), true),
+        Task::PremiumIndex(s) => (public, paths.premium_index, symbol(s), false),
+        Task::Depth(s) => {
+            let mut params = symbol(s);
+            params.push(("limit".into(), depth_limit.to_string()));
+            (public, Some(paths.depth), params, false)
+        }
+    };
+    let path = path.ok_or_else(|| anyhow::anyhow!("{:?} is not supported", task))?;
+    let rsp = rest.get(path, &params, signature).await?;
+    let status = rsp.status();
+    let body = rsp.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("{} {}", status, body);
+    }
+    Ok(body)
+}
+
+/// 后台进行的预取，结束后 [`Prefetch::ready`] 返回
+#[derive(Debug, Clone)]
+pub struct Prefetch {
+    prefetched: Prefetched,
+    ready: watch::Receiver<bool>,
+}
+
+impl Prefetch {
+    /// 开始预取，未开启时立即就绪；`signed` 用于需要签名的杠杆分层查询
+    pub fn spawn(
+        config: &PrefetchConfig,
+        public: Arc<Rest>,
+        signed: Arc<Rest>,
+        paths: PrefetchPaths,
+    ) -> Self {
+        let prefetched = Prefetched::default();
+        let tasks = match config.enabled {
+            true => tasks(config, &paths),
+            false => Vec::new(),
+        };
+        if tasks.is_empty() {
+            let (_, ready) = watch::channel(true);
+            return Self { prefetched, ready };
+        }
+
+        let (tx, ready) = watch::channel(false);
+        let data = prefetched.clone();
+        let concurrency = config.concurrency.max(1);
+        let depth_limit = config.depth_limit;
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let total = tasks.len();
+            let step = (total / 10).max(1);
+            info!(
+                "Prefetch {} requests with concurrency {}",
+                total, concurrency
+            );
+            let mut results = futures::stream::iter(tasks)
+                .map(|task| {
+                    let (public, signed) = (public.clone(), signed.clone());
+                    async move {
+                        let fetched = Instant::now();
+                        let body = fetch(&task, &public, &signed, &paths, depth_limit).await;
+                        (task, fetched, body)
+                    }
+                })
+                .buffer_unordered(concurrency);
+
+            let (mut done, mut failed) = (0, 0);
+            while let Some((task, fetched, body)) = results.next().await {
+                done += 1;
+                if let Err(e) = body.and_then(|body| data.store(&task, &body, depth_limit, fetched))
+                {
+                    failed += 1;
+                    warn!("Prefetch {:?} failed: {}", task, e);
+                }
+                if done % step == 0 || done == total {
+                    info!("Prefetch {}/{}", done, total);
+                }
+            }
+            info!(
+                "Prefetch finished in {:?}, {} of {} failed",
+                start.elapsed(),
+                failed,
+                total
+            );
+            let _ = tx.send(true);
+        });
+        Self { prefetched, ready }
+    }
+
+    pub fn prefetched(&self) -> Prefetched {
+        self.prefetched.clone()
+    }
+
+    /// 等待预取结束
+    pub async fn ready(&mut self) {
+        // 预取任务异常退出时不再等待
+        let _ = self.ready.wait_for(|ready| *ready).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefetch() {
+        let config = PrefetchConfig {
+            enabled: true,
+            symbols: vec!["BTCUSDT".into(), "btcusdt".into(), "ethusdt".into()],
+            depth_limit: 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            tasks(&config, &PrefetchPaths::spot()),
+            vec![Task::Depth("btcusdt".into()), Task::Depth("ethusdt".into())]
+        );
+        let tasks = tasks(&config, &PrefetchPaths::usdt("/fapi/v1/leverageBracket"));
+        assert_eq!(tasks.len(), 7);
+        assert_eq!(tasks[0], Task::FundingInfo);
+        assert_eq!(tasks[1], Task::LeverageBracket("btcusdt".into()));
+
+        let prefetched = Prefetched::default();
+        let now = Instant::now();
+        prefetched
+            .store(
+                &Task::LeverageBracket("btcusdt".into()),
+                r#"{"symbol":"BTCUSDT","brackets":[{"bracket":1,"initialLeverage":125,"notionalCap":50000,"notionalFloor":0,"maintMarginRatio":0.004,"cum":0}]}"#,
+                100,
+                now,
+            )
+            .unwrap();
+        let bracket = prefetched.leverage_bracket("btcusdt").unwrap();
+        assert_eq!(bracket.brackets[0].initialLeverage, 125);
+
+        let depth =
+            r#"{"lastUpdateId":1027024,"bids":[["4.00","431.0"]],"asks":[["4.02","12.0"]]}"#;
+        prefetched
+            .store(&Task::Depth("btcusdt".into()), depth, 100, now)
+            .unwrap();
+        let (fetched, limit, snapshot) = prefetched.depth("btcusdt").unwrap();
+        assert_eq!(
+            (fetched, limit, snapshot.last_update_id),
+            (now, 100, 1027024)
+        );
+
+        assert!(prefetched
+            .store(&Task::PremiumIndex("btcusdt".into()), "{}", 100, now)
+            .is_err());
+        assert!(prefetched.premium_index("btcusdt").is_none());
+    }
+}
@@ -0,0 +1,194 @@
+//! 策略端协议的描述
+//!
+//! 列出策略端可以发送的请求与网关发给策略端的消息。`protocol-schema` 把每一种写成
+//! `doc/schema` 下的一个 JSON Schema 文件，第三方实现策略端时以此为准；`gateway-conformance`
+//! 按同样的 schema 校验客户端发来的请求。网关转发的流数据在开头加上了 `offset`，见 [`crate::replay`]。
+//! 每个文件的 `version` 为描述的协议版本，老版本客户端的兼容见 [`cryptoflow::compat`]。
+
+use crate::margin::SideEffectType;
+use crate::model::order::{BinanceCancel, BinanceOrder, Peg};
+use crate::model::quote::BinanceQuote;
+use cryptoflow::chat::{
+    OrderType, PositionSide, SError, SFill, SGeneralDepth, SGeneralKline, SLogin, SOrder,
+    SOrderCap, SResponse, SStreamResult, SSubscription, STradingStatus, SVenueStatus, Side,
+    TimeInForce,
+};
+use cryptoflow::compat::PROTOCOL_VERSION;
+use cryptoflow::schema::{self, Schema};
+use cryptoflow::{enum_schema, object_schema};
+use serde_json::{json, Value};
+
+pub const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+object_schema!(BinanceOrder {
+    id: u32,
+    symbol: String,
+    price: f64,
+    quantity: f64,
+    side: Side,
+    order_type: OrderType,
+    tif: TimeInForce,
+    session_id: u16,
+    #[default] idempotency_key: Option<String>,
+    #[default] ttl_ms: Option<u64>,
+    #[default] peg: Option<Peg>,
+    #[default] position_side: Option<PositionSide>,
+    #[default] side_effect_type: Option<SideEffectType>,
+    #[default] reprice_passive: bool,
+    #[default] force: bool,
+    #[default] strategy: Option<String>,
+    #[default] tags: Vec<String>,
+});
+
+object_schema!(Peg {
+    #[default]
+    offset_ticks: i64,
+    #[default]
+    threshold_ticks: i64,
+});
+
+object_schema!(BinanceCancel {
+    symbol: String,
+    session_id: u16,
+    order_id: u32,
+    #[default] idempotency_key: Option<String>,
+});
+
+object_schema!(BinanceQuote {
+    price: f64,
+    quantity: f64,
+});
+
+enum_schema!(SideEffectType {
+    NO_SIDE_EFFECT,
+    MARGIN_BUY,
+    AUTO_REPAY,
+    AUTO_BORROW_REPAY,
+});
+
+/// 策略端请求的方法与请求的 schema
+pub fn requests() -> Vec<(&'static str, Value)> {
+    vec![
+        ("login", schema::request::<SLogin>("login")),
+        (
+            "subscribe",
+            schema::request::<Vec<SSubscription>>("subscribe"),
+        ),
+        ("unsubscribe", schema::request::<Vec<String>>("unsubscribe")),
+        ("order", schema::request::<BinanceOrder>("order")),
+        ("orders", schema::request::<Vec<BinanceOrder>>("orders")),
+        ("cancel", schema::request::<BinanceCancel>("cancel")),
+    ]
+}
+
+pub fn request(method: &str) -> Option<Value> {
+    requests()
+        .into_iter()
+        .find(|(m, _)| *m == method)
+        .map(|(_, schema)| schema)
+}
+
+/// 流数据带有网关的编号
+fn stream<T: Schema>() -> Value {
+    let mut schema = T::schema();
+    schema["properties"]["offset"] = json!({"type": "integer"});
+    schema["required"]
+        .as_array_mut()
+        .unwrap()
+        .insert(0, json!("offset"));
+    schema
+}
+
+/// 网关发给策略端的消息
+pub fn messages() -> Vec<(&'static str, Value)> {
+    vec![
+        ("login", SResponse::<SLogin>::schema()),
+        ("stream_results", SResponse::<Vec<SStreamResult>>::schema()),
+        ("error", SResponse::<SError>::schema()),
+        ("depth", stream::<SGeneralDepth<BinanceQuote>>()),
+        ("kline", stream::<SGeneralKline>()),
+        ("order", SOrder::schema()),
+        ("fill", SFill::schema()),
+        ("trading_status", STradingStatus::schema()),
+        ("venue_status", SVenueStatus::schema()),
+        ("order_cap", SOrderCap::schema()),
+    ]
+}
+
+/// schema 文件相对 `doc/schema` 的路径与内容
+pub fn files() -> Vec<(String, String)> {
+    let file = |dir: &str, kind: &str, (name, mut schema): (&str, Value)| {
+        if let Some(object) = schema.as_object_mut() {
+            object.insert("$schema".into(), json!(DRAFT));
+            object.insert("title".into(), json!(format!("{} {}", name, kind)));
+            object.insert("version".into(), json!(PROTOCOL_VERSION));
+        }
+        let content = serde_json::to_string_pretty(&schema).unwrap_or_default() + "\n";
+        (format!("{}/{}.json", dir, name), content)
+    };
+    let requests = requests()
+        .into_iter()
+        .map(|r| file("requests", "request", r));
+    let messages = messages()
+        .into_iter()
+        .map(|m| file("messages", "message", m));
+    requests.chain(messages).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::{SRequest, State};
+    use std::path::Path;
+
+    #[test]
+    fn test_protocol_schema() {
+        let order = BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price: 100.0,
+            quantity: 0.1,
+            tif: TimeInForce::GTX,
+            session_id: 1,
+            idempotency_key: Some("k".into()),
+            peg: Some(Peg {
+                offset_ticks: 1,
+                threshold_ticks: 1,
+            }),
+            reprice_passive: true,
+            ..Default::default()
+        };
+        let req = SRequest {
+            id: 3,
+            method: "order".to_string(),
+            params: order,
+        };
+        let value = serde_json::to_value(&req).unwrap();
+        let order = request("order").unwrap();
+        assert_eq!(schema::validate(&order, &value), Ok(()));
+        let mut invalid = value.clone();
+        invalid["params"]["side"] = json!("LONG");
+        assert!(schema::validate(&order, &invalid).is_err());
+
+        let report = SOrder::new(
+            1,
+            "btcusdt".into(),
+            Side::SELL,
+            State::NEW,
+            OrderType::LIMIT,
+            TimeInForce::GTC,
+            1.0,
+            2.0,
+        );
+        let (_, message) = messages().into_iter().find(|m| m.0 == "order").unwrap();
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!(schema::validate(&message, &report), Ok(()));
+
+        // 提交的 schema 文件与结构体一致，结构体改动后用 protocol-schema 重新生成
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../doc/schema");
+        for (path, content) in files() {
+            let committed = std::fs::read_to_string(dir.join(&path)).unwrap_or_default();
+            assert!(committed == content, "doc/schema/{} is out of date", path);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! 策略端的资源配额
+//!
+//! 订阅的流与派生流都在网关中按 tick 处理，所有策略端共用同一个行情循环。每个会话的订阅数、派生流数
+//! 以及每段表达式的规模都有上限，超出时回复 `-10015` 并说明用量与上限，一个策略不会拖慢其他策略。
+//! 配额为 0 表示不限制。
+
+use cryptoflow::chat::SError;
+use cryptoflow::error_code::QUOTA_EXCEEDED;
+use cryptoflow::expr::Program;
+use serde::Deserialize;
+
+/// ```json
+/// "quota": {
+///     "max_streams": 200,
+///     "max_derived": 16,
+///     "max_expr_nodes": 256,
+///     "max_expr_window": 10000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// 每个会话订阅的流
+    pub max_streams: usize,
+    /// 每个会话注册的派生流
+    pub max_derived: usize,
+    /// 每段表达式的节点数，决定每个 tick 的计算量
+    pub max_expr_nodes: usize,
+    /// 每段表达式中 `sma` 的窗口长度之和，决定保存的历史值个数
+    pub max_expr_window: usize,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            max_streams: 200,
+            max_derived: 16,
+            max_expr_nodes: 256,
+            max_expr_window: 10000,
+        }
+    }
+}
+
+fn exceeded(what: &str, used: usize, max: usize, hint: &str) -> SError {
+    SError {
+        code: QUOTA_EXCEEDED,
+        msg: format!(
+            "{} quota exceeded: {} of {} per session, {}",
+            what, used, max, hint
+        ),
+    }
+}
+
+impl QuotaConfig {
+    /// 已订阅 `subscribed` 条流的会话能否再订阅一条
+    pub fn check_stream(&self, subscribed: usize) -> Result<(), SError> {
+        match self.max_streams {
+            max if max > 0 && subscribed >= max => Err(exceeded(
+                "stream",
+                subscribed + 1,
+                max,
+                "unsubscribe unused streams first",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// 已有 `derived` 条派生流的会话能否再注册 `program`，替换同名派生流时不计入已有的那条
+    pub fn check_derive(&self, derived: usize, program: &Program) -> Result<(), SError> {
+        if self.max_derived > 0 && derived >= self.max_derived {
+            return Err(exceeded(
+                "derived stream",
+                derived + 1,
+                self.max_derived,
+                "unsubscribe the source of unused derived streams first",
+            ));
+        }
+        let nodes = program.nodes();
+        if self.max_expr_nodes > 0 && nodes > self.max_expr_nodes {
+            return Err(SError {
+                code: QUOTA_EXCEEDED,
+                msg: format!(
+                    "expression too large: {} nodes, at most {}",
+                    nodes, self.max_expr_nodes
+                ),
+            });
+        }
+        let window = program.window();
+        if self.max_expr_window > 0 && window > self.max_expr_window {
+            return Err(SError {
+                code: QUOTA_EXCEEDED,
+                msg: format!(
+                    "expression window too long: sma lengths sum to {}, at most {}",
+                    window, self.max_expr_window
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota() {
+        let quota = QuotaConfig {
+            max_streams: 2,
+            max_derived: 1,
+            max_expr_nodes: 5,
+            max_expr_window: 100,
+        };
+        assert!(quota.check_stream(1).is_ok());
+        let err = quota.check_stream(2).unwrap_err();
+        assert_eq!(err.code, QUOTA_EXCEEDED);
+        assert_eq!(
+            err.msg,
+            "stream quota exceeded: 3 of 2 per session, unsubscribe unused streams first"
+        );
+
+        let mid = Program::parse("mid = (bid0 + ask0) / 2").unwrap();
+        assert!(quota.check_derive(0, &mid).is_ok());
+        assert!(quota
+            .check_derive(1, &mid)
+            .unwrap_err()
+            .msg
+            .starts_with("derived stream quota exceeded: 2 of 1"));
+        let large = Program::parse("a = bid0 + ask0; b = a * 2").unwrap();
+        assert_eq!(
+            quota.check_derive(0, &large).unwrap_err().msg,
+            "expression too large: 6 nodes, at most 5"
+        );
+        let long = Program::parse("sma(close, 1000)").unwrap();
+        assert!(quota
+            .check_derive(0, &long)
+            .unwrap_err()
+            .msg
+            .starts_with("expression window too long"));
+
+        let unlimited = QuotaConfig {
+            max_streams: 0,
+            max_derived: 0,
+            max_expr_nodes: 0,
+            max_expr_window: 0,
+        };
+        assert!(unlimited.check_stream(10000).is_ok());
+        assert!(unlimited.check_derive(10000, &long).is_ok());
+    }
+}
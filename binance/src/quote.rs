@@ -0,0 +1,276 @@
+//! 双边报价
+//!
+//! 做市策略通过 `quote` 一次给出买卖两侧的目标报价，网关与当前挂着的报价比较，只对变化的一侧撤单重挂。
+//! 同一侧总是等旧报价的撤单回报到达后才挂新单，任何时刻每侧最多只有一笔报价在交易所。
+
+use crate::model::order::{BinanceOrder, BinanceQuote, QuoteLevel};
+use cryptoflow::chat::{OrderType, Side, State, TimeInForce};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub enum QuoteAction {
+    /// 通过 addr 对应的连接下单
+    Place(SocketAddr, BinanceOrder),
+    Cancel {
+        session_id: u16,
+        order_id: u32,
+        symbol: String,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    /// 已发出且尚未终结的报价
+    live: Option<QuoteLevel>,
+    /// 已对 live 发出撤单
+    canceling: bool,
+    /// 等 live 撤单完成后再挂出的报价
+    pending: Option<QuoteLevel>,
+}
+
+impl Slot {
+    /// 把这一侧调整为 `desired`，返回需要立即执行的操作
+    fn update(&mut self, desired: Option<QuoteLevel>) -> Option<Action> {
+        match &self.live {
+            Some(live) => {
+                let same = desired
+                    .as_ref()
+                    .is_some_and(|d| d.price == live.price && d.quantity == live.quantity);
+                if same && !self.canceling {
+                    self.pending = None;
+                    return None;
+                }
+                self.pending = desired;
+                if self.canceling {
+                    return None;
+                }
+                self.canceling = true;
+                Some(Action::Cancel(live.id))
+            }
+            None => {
+                self.live = desired.clone();
+                desired.map(Action::Place)
+            }
+        }
+    }
+
+    /// live 报价终结后挂出等待中的报价
+    fn on_done(&mut self) -> Option<Action> {
+        self.live = None;
+        self.canceling = false;
+        let pending = self.pending.take();
+        self.update(pending)
+    }
+}
+
+enum Action {
+    Place(QuoteLevel),
+    Cancel(u32),
+}
+
+#[derive(Debug)]
+struct QuotePair {
+    addr: SocketAddr,
+    tif: TimeInForce,
+    bid: Slot,
+    ask: Slot,
+}
+
+impl QuotePair {
+    fn actions(
+        &self,
+        session_id: u16,
+        symbol: &str,
+        side: Side,
+        action: Option<Action>,
+    ) -> Option<QuoteAction> {
+        Some(match action? {
+            Action::Place(level) => QuoteAction::Place(
+                self.addr,
+                BinanceOrder {
+                    id: level.id,
+                    symbol: symbol.into(),
+                    price: level.price,
+                    quantity: level.quantity,
+                    side,
+                    order_type: OrderType::LIMIT,
+                    tif: self.tif,
+                    session_id,
+                    ..Default::default()
+                },
+            ),
+            Action::Cancel(order_id) => QuoteAction::Cancel {
+                session_id,
+                order_id,
+                symbol: symbol.into(),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct QuoteBook {
+    /// (session_id, symbol) -> 两侧报价
+    pairs: HashMap<(u16, String), QuotePair>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Quotes {
+    book: Arc<Mutex<QuoteBook>>,
+}
+
+impl Quotes {
+    fn with_book<R>(&self, f: impl FnOnce(&mut QuoteBook) -> R) -> R {
+        let mut book = self.book.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut book)
+    }
+
+    /// 策略更新报价，返回需要发往交易所的最少操作
+    pub fn on_quote(&self, addr: SocketAddr, quote: &BinanceQuote) -> Vec<QuoteAction> {
+        let symbol = quote.symbol.to_lowercase();
+        self.with_book(|book| {
+            let pair = book
+                .pairs
+                .entry((quote.session_id, symbol.clone()))
+                .or_insert_with(|| QuotePair {
+                    addr,
+                    tif: quote.tif,
+                    bid: Slot::default(),
+                    ask: Slot::default(),
+                });
+            pair.addr = addr;
+            pair.tif = quote.tif;
+
+            let bid = pair.bid.update(quote.bid.clone());
+            let ask = pair.ask.update(quote.ask.clone());
+            [(Side::BUY, bid), (Side::SELL, ask)]
+                .into_iter()
+                .filter_map(|(side, action)| pair.actions(quote.session_id, &symbol, side, action))
+                .collect()
+        })
+    }
+
+    /// 订单回报，报价终结时挂出等待中的新报价
+    pub fn on_order(&self, session_id: u16, order_id: u32, state: State) -> Vec<QuoteAction> {
+        if state.is_working() {
+            return Vec::new();
+        }
+        self.on_done(session_id, order_id)
+    }
+
+    /// 报价被网关或交易所拒绝，这一侧清空，等策略下一次报价
+    pub fn on_reject(&self, session_id: u16, order_id: u32) {
+        self.with_book(|book| {
+            for ((id, _), pair) in book.pairs.iter_mut() {
+                if *id != session_id {
+                    continue;
+                }
+                for slot in [&mut pair.bid, &mut pair.ask] {
+                    if slot.live.as_ref().is_some_and(|l| l.id == order_id) {
+                        *slot = Slot::default();
+                    }
+                }
+            }
+        })
+    }
+
+    fn on_done(&self, session_id: u16, order_id: u32) -> Vec<QuoteAction> {
+        self.with_book(|book| {
+            let mut actions = Vec::new();
+            for ((id, symbol), pair) in book.pairs.iter_mut() {
+                if *id != session_id {
+                    continue;
+                }
+                let live = |slot: &Slot| slot.live.as_ref().is_some_and(|l| l.id == order_id);
+                if live(&pair.bid) {
+                    let action = pair.bid.on_done();
+                    actions.extend(pair.actions(session_id, symbol, Side::BUY, action));
+                }
+                if live(&pair.ask) {
+                    let action = pair.ask.on_done();
+                    actions.extend(pair.actions(session_id, symbol, Side::SELL, action));
+                }
+            }
+            actions
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(id: u32, price: f64) -> Option<QuoteLevel> {
+        Some(QuoteLevel {
+            id,
+            price,
+            quantity: 1.0,
+        })
+    }
+
+    fn quote(bid: Option<QuoteLevel>, ask: Option<QuoteLevel>) -> BinanceQuote {
+        BinanceQuote {
+            symbol: "btcusdt".into(),
+            session_id: 1,
+            bid,
+            ask,
+            tif: TimeInForce::GTC,
+        }
+    }
+
+    fn placed(actions: &[QuoteAction]) -> Vec<u32> {
+        actions
+            .iter()
+            .filter_map(|a| match a {
+                QuoteAction::Place(_, order) => Some(order.id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn canceled(actions: &[QuoteAction]) -> Vec<u32> {
+        actions
+            .iter()
+            .filter_map(|a| match a {
+                QuoteAction::Cancel { order_id, .. } => Some(*order_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quotes() {
+        let quotes = Quotes::default();
+        let addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+
+        let actions = quotes.on_quote(addr, &quote(level(1, 99.0), level(2, 101.0)));
+        assert_eq!(placed(&actions), vec![1, 2]);
+
+        // 买价不变，只撤卖单，新卖单等撤单回报
+        let actions = quotes.on_quote(addr, &quote(level(3, 99.0), level(4, 102.0)));
+        assert!(placed(&actions).is_empty());
+        assert_eq!(canceled(&actions), vec![2]);
+
+        // 撤单期间再次改价，只保留最新的目标
+        let actions = quotes.on_quote(addr, &quote(level(5, 99.0), level(6, 103.0)));
+        assert!(actions.is_empty());
+
+        assert!(quotes.on_order(1, 2, State::NEW).is_empty());
+        let actions = quotes.on_order(1, 2, State::CANCELED);
+        assert_eq!(placed(&actions), vec![6]);
+
+        // 撤掉买单
+        let actions = quotes.on_quote(addr, &quote(None, level(7, 103.0)));
+        assert_eq!(canceled(&actions), vec![1]);
+        assert!(quotes.on_order(1, 1, State::CANCELED).is_empty());
+
+        // 被拒绝的报价不再占用这一侧
+        let actions = quotes.on_quote(addr, &quote(level(8, 98.0), level(9, 103.0)));
+        assert_eq!(placed(&actions), vec![8]);
+        quotes.on_reject(1, 8);
+        let actions = quotes.on_quote(addr, &quote(level(10, 98.0), level(11, 103.0)));
+        assert_eq!(placed(&actions), vec![10]);
+    }
+}
@@ -0,0 +1,233 @@
+//! 改单合并
+//!
+//! 策略通过 `replace` 修改挂单的价格或数量，网关先撤单、收到撤单回报后再用同一个订单号挂出新单。
+//! 一次撤单重挂完成之前到达的改单只保留最新的一笔，高频改价时发往交易所的请求数不超过每轮一撤一挂。
+
+use crate::model::order::BinanceOrder;
+use cryptoflow::chat::State;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub enum ReplaceAction {
+    /// 通过 addr 对应的连接挂出新单
+    Place(SocketAddr, BinanceOrder),
+    Cancel {
+        session_id: u16,
+        order_id: u32,
+        symbol: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    /// 等待挂单确认，确认后再处理等待中的改单
+    Placing,
+    /// 已发出撤单，撤单回报到达后挂出最新的改单
+    Canceling,
+}
+
+#[derive(Debug)]
+struct Replacing {
+    addr: SocketAddr,
+    stage: Stage,
+    /// 最新的改单
+    desired: Option<BinanceOrder>,
+    /// 撤单期间的成交数量，重挂时从数量中扣除
+    filled: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Replaces {
+    orders: Arc<Mutex<HashMap<(u16, u32), Replacing>>>,
+}
+
+impl Replaces {
+    fn with_orders<R>(&self, f: impl FnOnce(&mut HashMap<(u16, u32), Replacing>) -> R) -> R {
+        let mut orders = self.orders.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut orders)
+    }
+
+    /// 策略改单，`working` 表示交易所已确认该订单；返回需要立即执行的操作
+    pub fn on_replace(
+        &self,
+        addr: SocketAddr,
+        order: &BinanceOrder,
+        working: bool,
+    ) -> Vec<ReplaceAction> {
+        self.with_orders(|orders| {
+            let key = (order.session_id, order.id);
+            if let Some(replacing) = orders.get_mut(&key) {
+                // 上一轮撤单重挂还没完成，只保留最新的目标
+                replacing.addr = addr;
+                replacing.desired = Some(order.clone());
+                return Vec::new();
+            }
+
+            let stage = if working {
+                Stage::Canceling
+            } else {
+                Stage::Placing
+            };
+            orders.insert(
+                key,
+                Replacing {
+                    addr,
+                    stage,
+                    desired: Some(order.clone()),
+                    filled: 0.0,
+                },
+            );
+            match stage {
+                Stage::Canceling => vec![cancel(order.session_id, order.id, &order.symbol)],
+                Stage::Placing => Vec::new(),
+            }
+        })
+    }
+
+    /// 订单回报，返回是否推送给策略以及需要执行的操作
+    pub fn on_order(
+        &self,
+        session_id: u16,
+        order_id: u32,
+        state: State,
+        trd_vol: f64,
+    ) -> (bool, Vec<ReplaceAction>) {
+        self.with_orders(|orders| {
+            let key = (session_id, order_id);
+            let Some(replacing) = orders.get_mut(&key) else {
+                return (true, Vec::new());
+            };
+
+            match (replacing.stage, state) {
+                (Stage::Canceling, State::CANCELED) => {
+                    let Some(desired) = replacing.desired.take() else {
+                        orders.remove(&key);
+                        return (true, Vec::new());
+                    };
+                    let quantity = desired.quantity - replacing.filled;
+                    if quantity <= 0.0 {
+                        orders.remove(&key);
+                        return (true, Vec::new());
+                    }
+                    replacing.stage = Stage::Placing;
+                    replacing.filled = 0.0;
+                    let order = BinanceOrder {
+                        quantity,
+                        ..desired
+                    };
+                    (false, vec![ReplaceAction::Place(replacing.addr, order)])
+                }
+                (Stage::Canceling, state) if state.is_working() => {
+                    replacing.filled += trd_vol;
+                    (true, Vec::new())
+                }
+                (Stage::Placing, state) if state.is_working() => match &replacing.desired {
+                    Some(desired) => {
+                        let action = cancel(session_id, order_id, &desired.symbol);
+                        replacing.stage = Stage::Canceling;
+                        replacing.filled = trd_vol;
+                        (true, vec![action])
+                    }
+                    None => {
+                        orders.remove(&key);
+                        (true, Vec::new())
+                    }
+                },
+                // 成交完毕或被拒绝，等待中的改单作废
+                _ => {
+                    orders.remove(&key);
+                    (true, Vec::new())
+                }
+            }
+        })
+    }
+
+    /// 策略主动撤单，放弃等待中的改单
+    pub fn on_cancel(&self, session_id: u16, order_id: u32) {
+        self.with_orders(|orders| orders.remove(&(session_id, order_id)));
+    }
+
+    /// 重挂被拒绝
+    pub fn on_reject(&self, session_id: u16, order_id: u32) {
+        self.on_cancel(session_id, order_id);
+    }
+}
+
+fn cancel(session_id: u16, order_id: u32, symbol: &str) -> ReplaceAction {
+    ReplaceAction::Cancel {
+        session_id,
+        order_id,
+        symbol: symbol.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(price: f64) -> BinanceOrder {
+        BinanceOrder {
+            id: 1,
+            symbol: "btcusdt".into(),
+            price,
+            quantity: 2.0,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    fn placed(actions: &[ReplaceAction]) -> Vec<f64> {
+        actions
+            .iter()
+            .filter_map(|a| match a {
+                ReplaceAction::Place(_, order) => Some(order.price),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_replaces() {
+        let replaces = Replaces::default();
+        let addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+
+        let actions = replaces.on_replace(addr, &order(101.0), true);
+        assert!(matches!(
+            actions[..],
+            [ReplaceAction::Cancel { order_id: 1, .. }]
+        ));
+        // 撤单完成前的改单只保留最新一笔
+        assert!(replaces.on_replace(addr, &order(102.0), true).is_empty());
+        assert!(replaces.on_replace(addr, &order(103.0), true).is_empty());
+
+        assert!(replaces.on_order(1, 1, State::PARTIALLY_FILLED, 0.5).0);
+        let (forward, actions) = replaces.on_order(1, 1, State::CANCELED, 0.0);
+        assert!(!forward);
+        assert_eq!(placed(&actions), vec![103.0]);
+        match &actions[..] {
+            [ReplaceAction::Place(_, order)] => assert_eq!(order.quantity, 1.5),
+            _ => panic!("{:?}", actions),
+        }
+
+        // 新单确认前的改单等确认后再撤
+        assert!(replaces.on_replace(addr, &order(104.0), true).is_empty());
+        let (forward, actions) = replaces.on_order(1, 1, State::NEW, 0.0);
+        assert!(forward);
+        assert!(matches!(actions[..], [ReplaceAction::Cancel { .. }]));
+        let (_, actions) = replaces.on_order(1, 1, State::CANCELED, 0.0);
+        assert_eq!(placed(&actions), vec![104.0]);
+        let (_, actions) = replaces.on_order(1, 1, State::NEW, 0.0);
+        assert!(actions.is_empty());
+
+        // 没有改单在途时撤单回报照常推送
+        assert!(replaces.on_order(1, 1, State::CANCELED, 0.0).0);
+
+        // 撤单期间全部成交，改单作废
+        replaces.on_replace(addr, &order(105.0), true);
+        let (forward, actions) = replaces.on_order(1, 1, State::FILLED, 2.0);
+        assert!(forward && actions.is_empty());
+        assert!(replaces.on_order(1, 1, State::CANCELED, 0.0).0);
+    }
+}
@@ -0,0 +1,154 @@
+//! 行情回放
+//!
+//! 网关为转发的每条行情按流编号，数据中带上 `offset` 字段，并在内存中保留最近一段时间的数据。
+//! 策略端断线重连并重新订阅后，用 `resume` 请求带上最后收到的 `offset`，网关补发之后缓存的数据，
+//! 简单的策略不必自己处理快照与缺口。
+//!
+//! 编号从流第一条数据的毫秒时间戳乘以 1000 开始逐条递增，网关重启后仍然比之前的编号大，
+//! 重启前的 `offset` 会因超出缓存窗口被拒绝，而不是被误认为新数据。
+
+use crate::subscriber::insert_field;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+/// ```json
+/// "replay": {
+///     "window_ms": 60000,
+///     "max_events": 10000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// 每条流缓存的时间窗口，为 0 时不缓存，只编号
+    pub window_ms: i64,
+    /// 每条流最多缓存的条数
+    pub max_events: usize,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: 60000,
+            max_events: 10000,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Ring {
+    /// 下一条数据的编号
+    next: u64,
+    /// (编号, 接收时间, 带编号的数据)
+    events: VecDeque<(u64, i64, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayBuffer {
+    config: ReplayConfig,
+    /// 交易所流名称 -> 缓存
+    rings: HashMap<String, Ring>,
+}
+
+impl ReplayBuffer {
+    pub fn new(config: &ReplayConfig) -> Self {
+        Self {
+            config: config.clone(),
+            rings: HashMap::default(),
+        }
+    }
+
+    /// 为一条数据编号并缓存，返回带 `offset` 字段的数据
+    pub fn push(&mut self, stream: &str, data: &str, now: i64) -> anyhow::Result<String> {
+        let ring = self
+            .rings
+            .entry(stream.to_string())
+            .or_insert_with(|| Ring {
+                next: now.max(0) as u64 * 1000,
+                events: VecDeque::new(),
+            });
+        let offset = ring.next;
+        let data = insert_field(data, "offset", offset)?;
+        ring.next += 1;
+
+        if self.config.window_ms > 0 && self.config.max_events > 0 {
+            ring.events.push_back((offset, now, data.clone()));
+        }
+        while ring.events.len() > self.config.max_events
+            || ring
+                .events
+                .front()
+                .is_some_and(|(_, time, _)| now - time > self.config.window_ms)
+        {
+            ring.events.pop_front();
+        }
+        Ok(data)
+    }
+
+    /// 最近一条数据的编号
+    pub fn last_offset(&self, stream: &str) -> Option<u64> {
+        self.rings.get(stream).map(|ring| ring.next - 1)
+    }
+
+    /// 取出 `offset` 之后缓存的数据；紧随其后的数据已不在缓存中，或 `offset` 不是这条流的编号时返回 None
+    pub fn since(&self, stream: &str, offset: u64) -> Option<Vec<&String>> {
+        let ring = self.rings.get(stream)?;
+        let oldest = ring.events.front().map_or(ring.next, |(o, _, _)| *o);
+        if offset >= ring.next || offset + 1 < oldest {
+            return None;
+        }
+        Some(
+            ring.events
+                .iter()
+                .filter(|(o, _, _)| *o > offset)
+                .map(|(_, _, data)| data)
+                .collect(),
+        )
+    }
+
+    /// 流不再被订阅时清空缓存，编号继续递增
+    pub fn clear(&mut self, stream: &str) {
+        if let Some(ring) = self.rings.get_mut(stream) {
+            ring.events.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_buffer() {
+        let mut replay = ReplayBuffer::new(&ReplayConfig {
+            window_ms: 1000,
+            max_events: 3,
+        });
+        let stream = "btcusdt@kline_1m";
+        let start = 1672515780000;
+        let first = start as u64 * 1000;
+        let data = replay.push(stream, r#"{"close":1.0}"#, start).unwrap();
+        assert_eq!(data, format!(r#"{{"offset":{},"close":1.0}}"#, first));
+        for i in 1..4 {
+            replay.push(stream, "{}", start + i * 100).unwrap();
+        }
+
+        // 只保留最近 3 条
+        assert!(replay.since(stream, first).is_some());
+        assert!(replay.since(stream, first - 1).is_none());
+        assert_eq!(replay.since(stream, first + 1).unwrap().len(), 2);
+        assert!(replay.since(stream, first + 3).unwrap().is_empty());
+        assert!(replay.since(stream, first + 4).is_none());
+        assert!(replay.since("ethusdt@kline_1m", first).is_none());
+
+        // 超出时间窗口
+        replay.push(stream, "{}", start + 2500).unwrap();
+        assert!(replay.since(stream, first + 2).is_none());
+        assert_eq!(replay.since(stream, first + 3).unwrap().len(), 1);
+
+        replay.clear(stream);
+        assert!(replay.since(stream, first + 4).is_some());
+        let data = replay.push(stream, "{}", start + 3000).unwrap();
+        assert_eq!(data, format!(r#"{{"offset":{}}}"#, first + 5));
+    }
+}
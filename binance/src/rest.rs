@@ -1,3 +1,4 @@
+use crate::capability::Capability;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use log::*;
@@ -18,22 +19,55 @@ pub struct Rest {
     apikey: String,
     private_key: PKey<Private>,
     recvwindow: i64,
+    capability: Capability,
 }
 
 impl Rest {
     pub fn new(base_uri: &str, apikey: &str, pem: &str, recvwindow: i64) -> anyhow::Result<Self> {
         let mut buf = Vec::new();
         File::open(pem).unwrap().read_to_end(&mut buf)?;
-        let private_key = PKey::private_key_from_pem(&buf)?;
+        Self::from_pem(base_uri, apikey, &buf, recvwindow)
+    }
+
+    /// 直接使用私钥内容创建，私钥不必存放在文件中
+    pub fn from_pem(
+        base_uri: &str,
+        apikey: &str,
+        pem: &[u8],
+        recvwindow: i64,
+    ) -> anyhow::Result<Self> {
+        let private_key = PKey::private_key_from_pem(pem)?;
 
         Ok(Self {
             base_uri: base_uri.trim_end_matches("/").into(),
             apikey: apikey.into(),
             private_key,
             recvwindow,
+            capability: Capability::default(),
         })
     }
 
+    /// 同一对密钥访问另一个域名
+    pub fn with_base_uri(&self, base_uri: &str) -> Self {
+        Self {
+            base_uri: base_uri.trim_end_matches("/").into(),
+            apikey: self.apikey.clone(),
+            private_key: self.private_key.clone(),
+            recvwindow: self.recvwindow,
+            capability: self.capability,
+        }
+    }
+
+    /// 限制凭证允许的操作，资金转移接口在任何能力下都会被拒绝，见 [`crate::capability`]
+    pub fn with_capability(mut self, capability: Capability) -> Self {
+        self.capability = capability;
+        self
+    }
+
+    pub fn capability(&self) -> Capability {
+        self.capability
+    }
+
     pub fn apikey(&self) -> &str {
         &self.apikey
     }
@@ -97,7 +131,8 @@ impl Rest {
         params: &[(String, String)],
         signature: bool,
     ) -> anyhow::Result<Response> {
-        let mut params: Vec<_> = params.into_iter().cloned().collect();
+        self.capability.check(&method, path)?;
+        let mut params: Vec<_> = params.to_vec();
 
         if signature {
             let ts = std::time::SystemTime::now()
@@ -132,6 +167,7 @@ impl Rest {
         Ok(rsp)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn add_order(
         &self,
         path: &str,
@@ -144,34 +180,9 @@ impl Rest {
         session_id: u16,
         id: u32,
     ) -> anyhow::Result<Response> {
-        let client_order_id = u64::from(session_id) << 32 | u64::from(id);
-        let mut params = vec![
-            ("symbol".into(), symbol),
-            ("side".into(), side),
-            ("type".into(), order_type.clone()),
-            ("quantity".into(), quantity),
-            ("newClientOrderId".into(), client_order_id.to_string()),
-            ("newOrderRespType".into(), "RESULT".into()),
-        ];
-
-        match order_type.as_str() {
-            "STOP_MARKET" => {
-                // For stop market orders, the price is treated as stopPrice
-                params.push(("stopPrice".into(), price));
-            }
-            "MARKET" => {
-                // Market orders don't need a price, so no action needed
-            }
-            _ => {
-                // For other types (e.g., LIMIT), use price
-                params.push(("price".into(), price));
-            }
-        }
-
-        if tif != "UNDEF" {
-            params.push(("timeInForce".into(), tif));
-        }
-
+        let params = order_params(
+            symbol, price, quantity, side, order_type, tif, session_id, id,
+        );
         self.post(path, &params, true).await
     }
 
@@ -187,3 +198,45 @@ impl Rest {
         .await
     }
 }
+
+/// 下单参数，REST 与 WS-API 共用
+#[allow(clippy::too_many_arguments)]
+pub fn order_params(
+    symbol: String,
+    price: String,
+    quantity: String,
+    side: String,
+    order_type: String,
+    tif: String,
+    session_id: u16,
+    id: u32,
+) -> Vec<(String, String)> {
+    let client_order_id = u64::from(session_id) << 32 | u64::from(id);
+    let mut params = vec![
+        ("symbol".into(), symbol),
+        ("side".into(), side),
+        ("type".into(), order_type.clone()),
+        ("quantity".into(), quantity),
+        ("newClientOrderId".into(), client_order_id.to_string()),
+        ("newOrderRespType".into(), "RESULT".into()),
+    ];
+
+    match order_type.as_str() {
+        "STOP_MARKET" => {
+            // For stop market orders, the price is treated as stopPrice
+            params.push(("stopPrice".into(), price));
+        }
+        "MARKET" => {
+            // Market orders don't need a price, so no action needed
+        }
+        _ => {
+            // For other types (e.g., LIMIT), use price
+            params.push(("price".into(), price));
+        }
+    }
+
+    if tif != "UNDEF" {
+        params.push(("timeInForce".into(), tif));
+    }
+    params
+}
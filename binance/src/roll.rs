@@ -0,0 +1,558 @@
+//! 季度合约的连续合约别名与移仓
+//!
+//! U 本位的季度合约每个季度换一个交易对（如 `btcusdt_261225`）。开启后网关按交易规则中的 `pair` 与
+//! `contractType` 维护两个连续合约别名：
+//!
+//! - `{pair}_perp`（如 `btcusdt_perp`）：永续合约
+//! - `{pair}_quarter`（如 `btcusdt_quarter`，也可写作 `btcusdt_current_quarter`）：当季合约
+//! - `{pair}_next_quarter`：次季合约
+//!
+//! 下单与撤单的 `symbol` 可以填别名，网关换成当前对应的合约；行情的流也可以用别名订阅，见
+//! [`ContractAliases`]。当季合约交割前 `notify_before_hours`
+//! 向持有它的会话推送一次 [`SRoll`] 提醒；策略发出 `roll` 请求后，网关撤掉会话在当季合约上的挂单，
+//! 按次季合约的价格重新挂出，并用一对市价单把持仓从当季合约移到次季合约。挂单的价格按两个合约买一卖一
+//! 中间价的价差平移，请求中的 `spread` 优先；有挂单而两者都没有时不移仓并返回错误。
+//!
+//! [`SRoll`]: cryptoflow::chat::SRoll
+
+use crate::delivery::PERPETUAL_DELIVERY;
+use crate::model::order::BinanceOrder;
+use crate::model::symbol::{BinanceSymbol, ConctactStatus};
+use cryptoflow::chat::{OrderType, Position, Side, State, TimeInForce};
+use cryptoflow::chat::{SRoll, SRollResult};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// 网关生成的移仓单从这个编号开始，避开策略端的订单编号与定时平仓单
+pub const FIRST_ORDER_ID: u32 = 0xE000_0000;
+
+const PERPETUAL: &str = "PERPETUAL";
+const CURRENT_QUARTER: &str = "CURRENT_QUARTER";
+const NEXT_QUARTER: &str = "NEXT_QUARTER";
+
+/// ```json
+/// "roll": {
+///     "enabled": false,
+///     "notify_before_hours": 24
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RollConfig {
+    pub enabled: bool,
+    /// 当季合约交割前这么多小时提醒移仓
+    pub notify_before_hours: u64,
+}
+
+impl Default for RollConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            notify_before_hours: 24,
+        }
+    }
+}
+
+/// 别名当前对应的合约
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chain {
+    pub contract: String,
+    pub delivery_time: i64,
+    /// 到期后接替的合约，永续与次季合约的别名没有
+    pub next: Option<String>,
+}
+
+/// 别名的规范写法：小写，`_current_quarter` 写作 `_quarter`
+pub fn canonical(alias: &str) -> String {
+    let alias = alias.to_lowercase();
+    match alias.strip_suffix("_current_quarter") {
+        Some(pair) => format!("{}_quarter", pair),
+        None => alias,
+    }
+}
+
+/// 按交易规则中交易中的合约生成别名，键为规范写法
+pub fn chains<'a>(products: impl IntoIterator<Item = &'a BinanceSymbol>) -> HashMap<String, Chain> {
+    let trading: Vec<&BinanceSymbol> = products
+        .into_iter()
+        .filter(|p| p.status == ConctactStatus::TRADING && !p.pair.is_empty())
+        .collect();
+    let find = |pair: &str, contract_type: &str| {
+        trading
+            .iter()
+            .find(|p| p.pair == pair && p.contractType == contract_type)
+            .copied()
+    };
+    let chain = |product: &BinanceSymbol, next: Option<&BinanceSymbol>| Chain {
+        contract: product.symbol.clone(),
+        delivery_time: product.deliveryDate.unwrap_or_default() as i64,
+        next: next.map(|p| p.symbol.clone()),
+    };
+    let mut chains = HashMap::new();
+    for product in trading.iter() {
+        let pair = product.pair.to_lowercase();
+        match product.contractType.as_str() {
+            PERPETUAL => {
+                chains.insert(format!("{}_perp", pair), chain(product, None));
+            }
+            CURRENT_QUARTER => {
+                let next = find(&product.pair, NEXT_QUARTER);
+                chains.insert(format!("{}_quarter", pair), chain(product, next));
+            }
+            NEXT_QUARTER => {
+                chains.insert(format!("{}_next_quarter", pair), chain(product, None));
+            }
+            _ => (),
+        }
+    }
+    chains
+}
+
+/// 别名 -> 合约，由合约交易模块随交易规则更新，行情模块据此解析用别名订阅的流
+#[derive(Debug, Clone, Default)]
+pub struct ContractAliases {
+    inner: Arc<Mutex<(u64, HashMap<String, String>)>>,
+}
+
+impl ContractAliases {
+    fn with_inner<R>(&self, f: impl FnOnce(&mut (u64, HashMap<String, String>)) -> R) -> R {
+        let mut inner = self.inner.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut inner)
+    }
+
+    /// 替换全部别名，有变化时递增版本号
+    pub fn update(&self, aliases: HashMap<String, String>) {
+        self.with_inner(|(version, current)| {
+            if *current != aliases {
+                *version += 1;
+                *current = aliases;
+            }
+        });
+    }
+
+    /// 每次别名变化后递增，行情模块只在版本变化后检查订阅
+    pub fn version(&self) -> u64 {
+        self.with_inner(|(version, _)| *version)
+    }
+
+    /// 别名对应的合约，不是别名时为空
+    pub fn resolve(&self, symbol: &str) -> Option<String> {
+        let alias = canonical(symbol);
+        self.with_inner(|(_, aliases)| aliases.get(&alias).cloned())
+    }
+
+    /// 把 `{alias}@{stream}` 换成当前合约的流，不是别名的流为空
+    pub fn resolve_stream(&self, stream: &str) -> Option<String> {
+        let (symbol, rest) = stream.split_once('@')?;
+        Some(format!("{}@{}", self.resolve(symbol)?, rest))
+    }
+}
+
+/// 一次移仓要撤的挂单与要发的订单，订单按顺序发出
+#[derive(Debug, Default)]
+pub struct RollPlan {
+    pub from: String,
+    pub to: String,
+    /// (订单号, 交易对)
+    pub cancels: Vec<(u32, String)>,
+    pub orders: Vec<BinanceOrder>,
+}
+
+impl RollPlan {
+    pub fn result(&self, alias: &str) -> SRollResult {
+        SRollResult {
+            alias: alias.to_string(),
+            contract: self.from.clone(),
+            next: self.to.clone(),
+            canceled: self.cancels.iter().map(|(id, _)| *id).collect(),
+            orders: self.orders.iter().map(|o| o.id).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Rolls {
+    config: RollConfig,
+    /// 规范写法的别名 -> 当前对应的合约
+    chains: HashMap<String, Chain>,
+    aliases: ContractAliases,
+    /// 已提醒过的 (别名, 合约)
+    notified: HashSet<(String, String)>,
+    /// 季度合约上的挂单与已成交数量
+    resting: HashMap<(u16, u32), (BinanceOrder, f64)>,
+    next_id: u32,
+}
+
+impl Default for Rolls {
+    fn default() -> Self {
+        Self {
+            config: RollConfig::default(),
+            chains: HashMap::new(),
+            aliases: ContractAliases::default(),
+            notified: HashSet::new(),
+            resting: HashMap::new(),
+            next_id: FIRST_ORDER_ID,
+        }
+    }
+}
+
+impl Rolls {
+    /// `aliases` 与行情模块共享
+    pub fn new(config: &RollConfig, aliases: ContractAliases) -> Self {
+        Self {
+            config: config.clone(),
+            aliases,
+            ..Default::default()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 按交易规则重建别名，交割后重新拉取的交易规则把别名换到新的当季合约
+    pub fn on_products(&mut self, products: &HashMap<String, BinanceSymbol>) {
+        if !self.enabled() {
+            return;
+        }
+        let chains = chains(products.values());
+        for (alias, chain) in chains.iter() {
+            if self.chains.get(alias) != Some(chain) {
+                info!(
+                    "Alias {} -> {}, next {:?}",
+                    alias, chain.contract, chain.next
+                );
+            }
+        }
+        self.aliases.update(
+            chains
+                .iter()
+                .map(|(alias, chain)| (alias.clone(), chain.contract.clone()))
+                .collect(),
+        );
+        self.chains = chains;
+    }
+
+    pub fn chain(&self, alias: &str) -> Option<&Chain> {
+        self.chains.get(&canonical(alias))
+    }
+
+    /// 别名对应的合约，不是别名时为空
+    pub fn resolve(&self, symbol: &str) -> Option<String> {
+        self.chain(symbol).map(|c| c.contract.clone())
+    }
+
+    /// 进入提醒时间的当季合约，每个合约只提醒一次，`now` 为毫秒时间
+    pub fn notices(&mut self, now: i64) -> Vec<SRoll> {
+        let before = self.config.notify_before_hours as i64 * 3_600_000;
+        let mut notices = Vec::new();
+        for (alias, chain) in self.chains.iter() {
+            let remaining_ms = chain.delivery_time - now;
+            if chain.next.is_none() || remaining_ms <= 0 || remaining_ms > before {
+                continue;
+            }
+            if !self
+                .notified
+                .insert((alias.clone(), chain.contract.clone()))
+            {
+                continue;
+            }
+            notices.push(SRoll {
+                alias: alias.clone(),
+                contract: chain.contract.clone(),
+                next: chain.next.clone(),
+                delivery_time: chain.delivery_time,
+                remaining_ms,
+            });
+        }
+        notices.sort_by(|a, b| a.alias.cmp(&b.alias));
+        notices
+    }
+
+    fn is_quarterly(&self, symbol: &str) -> bool {
+        let symbol = symbol.to_lowercase();
+        self.chains.values().any(|c| {
+            c.delivery_time < PERPETUAL_DELIVERY
+                && (c.contract == symbol || c.next.as_ref() == Some(&symbol))
+        })
+    }
+
+    /// 订单已发往交易所，只记下季度合约上的限价单
+    pub fn on_sent(&mut self, order: &BinanceOrder) {
+        if !self.enabled()
+            || order.order_type == OrderType::MARKET
+            || !self.is_quarterly(&order.symbol)
+        {
+            return;
+        }
+        self.resting
+            .insert((order.session_id, order.id), (order.clone(), 0.0));
+    }
+
+    /// 订单回报，`filled` 为累计成交数量；终结的订单不再跟踪
+    pub fn on_update(&mut self, session_id: u16, order_id: u32, state: State, filled: f64) {
+        let key = (session_id, order_id);
+        if state.is_working() {
+            if let Some((_, f)) = self.resting.get_mut(&key) {
+                *f = filled;
+            }
+        } else {
+            self.resting.remove(&key);
+        }
+    }
+
+    /// 生成会话的移仓计划；`spread` 为次季减当季的价差，挂单按它平移价格，`tick` 为次季合约的价格步长
+    pub fn plan(
+        &mut self,
+        session_id: u16,
+        alias: &str,
+        positions: &HashMap<String, Position>,
+        spread: Option<f64>,
+        tick: f64,
+    ) -> Result<RollPlan, String> {
+        let chain = self
+            .chain(alias)
+            .ok_or_else(|| format!("unknown contract alias {}", alias))?;
+        let from = chain.contract.clone();
+        let to = chain
+            .next
+            .clone()
+            .ok_or_else(|| format!("{} has no next contract", alias))?;
+        let mut plan = RollPlan {
+            from: from.clone(),
+            to: to.clone(),
+            ..Default::default()
+        };
+
+        let mut resting: Vec<_> = self
+            .resting
+            .iter()
+            .filter(|((id, _), (order, _))| {
+                *id == session_id && order.symbol.to_lowercase() == from
+            })
+            .map(|(_, (order, filled))| (order.clone(), *filled))
+            .collect();
+        resting.sort_by_key(|(order, _)| order.id);
+        if !resting.is_empty() && spread.is_none() {
+            return Err(format!(
+                "no spread between {} and {} to move resting orders",
+                from, to
+            ));
+        }
+        for (order, filled) in resting {
+            let quantity = order.quantity - filled;
+            plan.cancels.push((order.id, order.symbol.clone()));
+            if quantity <= 0.0 {
+                continue;
+            }
+            let price = round_to_tick(order.price + spread.unwrap_or_default(), tick);
+            let moved = BinanceOrder {
+                id: self.next_order_id(),
+                symbol: to.clone(),
+                price,
+                quantity,
+                idempotency_key: None,
+                ttl_ms: None,
+                peg: None,
+                ..order
+            };
+            plan.orders.push(moved);
+        }
+
+        let mut legs: Vec<_> = positions
+            .values()
+            .filter(|p| p.symbol.to_lowercase() == from && p.net != 0.0)
+            .collect();
+        legs.sort_by_key(|p| p.key());
+        for position in legs {
+            let (close, open) = match position.net > 0.0 {
+                true => (Side::SELL, Side::BUY),
+                false => (Side::BUY, Side::SELL),
+            };
+            for (symbol, side) in [(&from, close), (&to, open)] {
+                let order = BinanceOrder {
+                    id: self.next_order_id(),
+                    symbol: symbol.clone(),
+                    price: 0.0,
+                    quantity: position.net.abs(),
+                    side,
+                    order_type: OrderType::MARKET,
+                    tif: TimeInForce::GTC,
+                    session_id,
+                    position_side: position.side,
+                    ..Default::default()
+                };
+                plan.orders.push(order);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// 下一笔移仓单的订单号，导出状态时保存
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// 导入另一个网关的计数器，只向前推进
+    pub fn advance_id(&mut self, next_id: u32) {
+        self.next_id = self.next_id.max(next_id);
+    }
+
+    fn next_order_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(FIRST_ORDER_ID);
+        id
+    }
+}
+
+/// 两个合约中间价的价差 `to - from`，任一边没有报价时为空
+pub fn mid_spread(from: Option<(f64, f64)>, to: Option<(f64, f64)>) -> Option<f64> {
+    let mid = |(bid, ask): (f64, f64)| (bid + ask) / 2.0;
+    Some(mid(to?) - mid(from?))
+}
+
+/// 按价格步长舍入，没有步长时原样返回
+fn round_to_tick(price: f64, tick: f64) -> f64 {
+    if tick <= 0.0 {
+        return price;
+    }
+    let decimals = (-tick.log10()).ceil().max(0.0) as i32;
+    let scale = 10f64.powi(decimals);
+    ((price / tick).round() * tick * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::PositionSide;
+
+    fn product(symbol: &str, contract_type: &str, delivery: u64) -> BinanceSymbol {
+        let s = format!(
+            r#"{{"symbol":"{}","pair":"BTCUSDT","contractType":"{}","status":"TRADING","baseAsset":"BTC","baseAssetPrecision":8,"quoteAsset":"USDT","quotePrecision":8,"orderTypes":[],"filters":[],"deliveryDate":{}}}"#,
+            symbol, contract_type, delivery
+        );
+        serde_json::from_str(&s).unwrap()
+    }
+
+    fn order(id: u32, symbol: &str, price: f64) -> BinanceOrder {
+        BinanceOrder {
+            id,
+            symbol: symbol.into(),
+            price,
+            quantity: 2.0,
+            session_id: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rolls() {
+        let delivery = 1_800_000_000_000u64;
+        let mut products = HashMap::new();
+        for p in [
+            product("BTCUSDT_261225", CURRENT_QUARTER, delivery),
+            product("BTCUSDT_270326", NEXT_QUARTER, delivery + 7_776_000_000),
+            product("BTCUSDT", PERPETUAL, PERPETUAL_DELIVERY as u64),
+        ] {
+            products.insert(p.symbol.clone(), p);
+        }
+        let aliases = ContractAliases::default();
+        let config = RollConfig {
+            enabled: true,
+            notify_before_hours: 1,
+        };
+        let mut rolls = Rolls::new(&config, aliases.clone());
+        rolls.on_products(&products);
+        assert_eq!(
+            rolls.resolve("BTCUSDT_QUARTER").as_deref(),
+            Some("btcusdt_261225")
+        );
+        assert_eq!(
+            aliases
+                .resolve_stream("btcusdt_current_quarter@kline:1m")
+                .as_deref(),
+            Some("btcusdt_261225@kline:1m")
+        );
+        assert_eq!(aliases.resolve("btcusdt_perp").as_deref(), Some("btcusdt"));
+        assert!(aliases.resolve_stream("btcusdt@kline:1m").is_none());
+        let version = aliases.version();
+        rolls.on_products(&products);
+        assert_eq!(aliases.version(), version);
+        assert_eq!(
+            rolls.resolve("btcusdt_next_quarter").as_deref(),
+            Some("btcusdt_270326")
+        );
+        assert!(rolls.resolve("btcusdt").is_none());
+
+        let t = delivery as i64;
+        assert!(rolls.notices(t - 3_600_001).is_empty());
+        let notices = rolls.notices(t - 1000);
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].next.as_deref(), Some("btcusdt_270326"));
+        assert!(rolls.notices(t - 500).is_empty());
+
+        // 1 部分成交后移剩余数量，2 已撤单，3 在永续上不跟踪
+        rolls.on_sent(&order(1, "btcusdt_261225", 100.0));
+        rolls.on_sent(&order(2, "btcusdt_261225", 99.0));
+        rolls.on_sent(&order(3, "btcusdt", 99.0));
+        assert_eq!(rolls.resting.len(), 2);
+        rolls.on_update(1, 1, State::PARTIALLY_FILLED, 0.5);
+        rolls.on_update(1, 2, State::CANCELED, 0.0);
+
+        let mut positions = HashMap::new();
+        let short = Position {
+            side: Some(PositionSide::SHORT),
+            ..Position::new("btcusdt_261225", -3.0)
+        };
+        positions.insert(short.key(), short);
+        assert!(rolls
+            .plan(1, "btcusdt_quarter", &positions, None, 0.1)
+            .is_err());
+        let plan = rolls
+            .plan(1, "btcusdt_quarter", &positions, Some(12.34), 0.1)
+            .unwrap();
+        assert_eq!(plan.cancels, vec![(1, "btcusdt_261225".to_string())]);
+        let orders: Vec<_> = plan
+            .orders
+            .iter()
+            .map(|o| (o.symbol.as_str(), o.side, o.price, o.quantity))
+            .collect();
+        assert_eq!(
+            orders,
+            vec![
+                ("btcusdt_270326", Side::BUY, 112.3, 1.5),
+                ("btcusdt_261225", Side::BUY, 0.0, 3.0),
+                ("btcusdt_270326", Side::SELL, 0.0, 3.0),
+            ]
+        );
+        assert_eq!(plan.orders[0].id, FIRST_ORDER_ID);
+        assert_eq!(plan.orders[2].position_side, Some(PositionSide::SHORT));
+        assert!(rolls
+            .plan(1, "btcusdt_next_quarter", &positions, None, 0.1)
+            .is_err());
+        assert_eq!(
+            mid_spread(Some((99.0, 101.0)), Some((110.0, 112.0))),
+            Some(11.0)
+        );
+        assert_eq!(mid_spread(None, Some((110.0, 112.0))), None);
+
+        // 交割后当季合约下架，别名换到新的当季合约
+        products.remove("btcusdt_261225");
+        products.insert(
+            "btcusdt_270326".into(),
+            product("BTCUSDT_270326", CURRENT_QUARTER, delivery + 7_776_000_000),
+        );
+        rolls.on_products(&products);
+        assert_eq!(aliases.version(), version + 1);
+        assert_eq!(
+            aliases.resolve("btcusdt_quarter").as_deref(),
+            Some("btcusdt_270326")
+        );
+
+        let mut disabled = Rolls::default();
+        disabled.on_products(&products);
+        assert!(disabled.resolve("btcusdt_quarter").is_none());
+    }
+}
@@ -0,0 +1,610 @@
+//! 交易规则的反例测试
+//!
+//! 对 exchangeInfo 中的每个交易对，按过滤器独立实现一遍交易所的校验，再用随机与边界上的价格、数量
+//! 检查 [`TradingRules`] 的实现：
+//!
+//! - `adjust_price`、`adjust_quantity` 的结果应当通过交易所的校验，再调整一次不变，并且价格只移动到最近
+//!   的一档、数量只向下取整。调整后的数量还要满足市价单的 MARKET_LOT_SIZE
+//! - `min_notional` 与 MIN_NOTIONAL、NOTIONAL 中的最小名义价值一致
+//! - `is_valid_order` 接受的委托交易所也应当接受，反之亦然。有参考价时按 PERCENT_PRICE 与
+//!   PERCENT_PRICE_BY_SIDE 校验价格带，价格在参考价上下随机取样
+//!
+//! 每个交易对每类问题只报告第一个反例。交易所的校验中数量步长为 0 表示不限制步长，最大值为 0 表示
+//! 不限制上限。解码失败的交易对同样报告。
+
+use crate::model::filter::FilterField;
+use crate::model::symbol::BinanceSymbol;
+use cryptoflow::chat::Side;
+use cryptoflow::trading_rules::TradingRules;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// 比较浮点数时的相对容差
+const EPSILON: f64 = 1e-9;
+
+/// 现货与 U 本位合约的公开接口，参考价取最新成交价
+#[derive(Debug, Clone, Copy)]
+pub struct Venue {
+    pub exchange_info: &'static str,
+    pub ticker_price: &'static str,
+}
+
+pub const SPOT: Venue = Venue {
+    exchange_info: "https://api.binance.com/api/v3/exchangeInfo",
+    ticker_price: "https://api.binance.com/api/v3/ticker/price",
+};
+
+pub const USDT: Venue = Venue {
+    exchange_info: "https://fapi.binance.com/fapi/v1/exchangeInfo",
+    ticker_price: "https://fapi.binance.com/fapi/v1/ticker/price",
+};
+
+/// 查询 exchangeInfo，`prices` 为 true 时同时查询所有交易对的参考价
+pub async fn fetch(venue: &Venue, prices: bool) -> anyhow::Result<(Value, HashMap<String, f64>)> {
+    let info: Value = reqwest::get(venue.exchange_info).await?.json().await?;
+    if !prices {
+        return Ok((info, HashMap::new()));
+    }
+    let tickers: Value = reqwest::get(venue.ticker_price).await?.json().await?;
+    let prices = tickers
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", tickers))?
+        .iter()
+        .filter_map(|ticker| {
+            let symbol = ticker["symbol"].as_str()?.to_lowercase();
+            let price = ticker["price"].as_str()?.parse().ok()?;
+            Some((symbol, price))
+        })
+        .collect();
+    Ok((info, prices))
+}
+
+/// 过滤器中的范围与步长，0 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Step {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl Step {
+    fn parse(min: &str, max: &str, step: &str) -> Self {
+        let parse = |s: &str| s.parse().unwrap_or_default();
+        Self {
+            min: parse(min),
+            max: parse(max),
+            step: parse(step),
+        }
+    }
+
+    /// 低于 `min`、高于 `max` 或不在从 `min` 起算的步长上时拒绝
+    pub fn check(&self, value: f64) -> Result<(), String> {
+        if self.min > 0.0 && value < self.min * (1.0 - EPSILON) {
+            return Err(format!("{} is below min {}", value, self.min));
+        }
+        if self.max > 0.0 && value > self.max * (1.0 + EPSILON) {
+            return Err(format!("{} is above max {}", value, self.max));
+        }
+        if self.step > 0.0 {
+            let steps = (value - self.min) / self.step;
+            if (steps - steps.round()).abs() > 1e-6 {
+                return Err(format!("{} is not on the {} step", value, self.step));
+            }
+        }
+        Ok(())
+    }
+
+    fn lower(&self) -> f64 {
+        match (self.min, self.step) {
+            (min, _) if min > 0.0 => min,
+            (_, step) if step > 0.0 => step,
+            _ => 1e-8,
+        }
+    }
+
+    fn upper(&self) -> f64 {
+        match self.max {
+            max if max > 0.0 => max,
+            _ => self.lower() * 1e8,
+        }
+    }
+
+    /// 不低于 `value` 的最小合法值
+    fn ceil(&self, value: f64) -> f64 {
+        let value = value.max(self.min);
+        match self.step {
+            step if step > 0.0 => {
+                self.min + ((value - self.min) / step * (1.0 - EPSILON)).ceil() * step
+            }
+            _ => value,
+        }
+    }
+}
+
+/// 相对参考价的价格带，PERCENT_PRICE 的买卖两边相同
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PercentBand {
+    pub bid_up: f64,
+    pub bid_down: f64,
+    pub ask_up: f64,
+    pub ask_down: f64,
+}
+
+impl PercentBand {
+    pub fn check(&self, side: &Side, price: f64, reference: f64) -> Result<(), String> {
+        let (up, down) = match side {
+            Side::BUY => (self.bid_up, self.bid_down),
+            Side::SELL => (self.ask_up, self.ask_down),
+        };
+        let (high, low) = (reference * up, reference * down);
+        if up > 0.0 && price > high * (1.0 + EPSILON) {
+            return Err(format!(
+                "{:?} {} is above {} x {}",
+                side, price, up, reference
+            ));
+        }
+        if price < low * (1.0 - EPSILON) {
+            return Err(format!(
+                "{:?} {} is below {} x {}",
+                side, price, down, reference
+            ));
+        }
+        Ok(())
+    }
+
+    fn widest(&self) -> (f64, f64) {
+        (
+            self.bid_down.min(self.ask_down),
+            self.bid_up.max(self.ask_up),
+        )
+    }
+}
+
+/// 交易所拒绝委托的过滤器与原因
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    pub filter: &'static str,
+    pub reason: String,
+}
+
+impl Rejection {
+    fn new(filter: &'static str, reason: String) -> Self {
+        Self { filter, reason }
+    }
+}
+
+/// 按交易所的规则解析的过滤器
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub price: Option<Step>,
+    pub lot: Option<Step>,
+    pub market_lot: Option<Step>,
+    pub min_notional: f64,
+    /// 0 表示不限制
+    pub max_notional: f64,
+    pub percent: Option<PercentBand>,
+}
+
+impl Filters {
+    pub fn new(symbol: &BinanceSymbol) -> Self {
+        let parse = |s: &str| s.parse().unwrap_or_default();
+        let mut filters = Self::default();
+        for filter in symbol.filters.iter() {
+            match filter {
+                FilterField::PRICE_FILTER {
+                    tick_size,
+                    max_price,
+                    min_price,
+                } => filters.price = Some(Step::parse(min_price, max_price, tick_size)),
+                FilterField::LOT_SIZE {
+                    step_size,
+                    max_qty,
+                    min_qty,
+                } => filters.lot = Some(Step::parse(min_qty, max_qty, step_size)),
+                FilterField::MARKET_LOT_SIZE {
+                    step_size,
+                    max_qty,
+                    min_qty,
+                } => filters.market_lot = Some(Step::parse(min_qty, max_qty, step_size)),
+                FilterField::MIN_NOTIONAL { min_notional, .. } => {
+                    filters.min_notional = parse(min_notional)
+                }
+                FilterField::NOTIONAL {
+                    min_notional,
+                    max_notional,
+                    ..
+                } => {
+                    filters.min_notional = parse(min_notional);
+                    filters.max_notional = parse(max_notional);
+                }
+                FilterField::PERCENT_PRICE {
+                    multiplier_up,
+                    multiplier_down,
+                    ..
+                } => {
+                    let (up, down) = (parse(multiplier_up), parse(multiplier_down));
+                    filters.percent = Some(PercentBand {
+                        bid_up: up,
+                        bid_down: down,
+                        ask_up: up,
+                        ask_down: down,
+                    });
+                }
+                FilterField::PERCENT_PRICE_BY_SIDE {
+                    bid_multiplier_up,
+                    bid_multiplier_down,
+                    ask_multiplier_up,
+                    ask_multiplier_down,
+                    ..
+                } => {
+                    filters.percent = Some(PercentBand {
+                        bid_up: parse(bid_multiplier_up),
+                        bid_down: parse(bid_multiplier_down),
+                        ask_up: parse(ask_multiplier_up),
+                        ask_down: parse(ask_multiplier_down),
+                    });
+                }
+                _ => (),
+            }
+        }
+        filters
+    }
+
+    /// 限价单的校验，`reference` 为交易所计算价格带用的参考价，没有时不校验价格带
+    pub fn check_limit(
+        &self,
+        side: &Side,
+        price: f64,
+        quantity: f64,
+        reference: Option<f64>,
+    ) -> Result<(), Rejection> {
+        if let Some(step) = self.price {
+            step.check(price).map_err(|e| Rejection::new("price", e))?;
+        }
+        if let Some(step) = self.lot {
+            step.check(quantity)
+                .map_err(|e| Rejection::new("quantity", e))?;
+        }
+        let notional = price * quantity;
+        if notional < self.min_notional * (1.0 - EPSILON) {
+            let reason = format!("notional {} is below {}", notional, self.min_notional);
+            return Err(Rejection::new("notional", reason));
+        }
+        if self.max_notional > 0.0 && notional > self.max_notional * (1.0 + EPSILON) {
+            let reason = format!("notional {} is above {}", notional, self.max_notional);
+            return Err(Rejection::new("notional", reason));
+        }
+        if let (Some(band), Some(reference)) = (self.percent, reference) {
+            band.check(side, price, reference)
+                .map_err(|e| Rejection::new("percent_price", e))?;
+        }
+        Ok(())
+    }
+
+    /// 市价单的数量校验，没有 MARKET_LOT_SIZE 时按 LOT_SIZE
+    pub fn check_market(&self, quantity: f64) -> Result<(), Rejection> {
+        match self.market_lot.or(self.lot) {
+            Some(step) => step
+                .check(quantity)
+                .map_err(|e| Rejection::new("market_quantity", e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 本地规则与交易所规则不一致的一个反例
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Violation {
+    pub symbol: String,
+    pub check: &'static str,
+    pub detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub symbols: usize,
+    pub samples: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub fn clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// splitmix64，同一种子每次取样相同，反例可以复现
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// [lo, hi] 上的对数均匀分布
+    fn between(&mut self, lo: f64, hi: f64) -> f64 {
+        lo * (hi / lo).powf(self.next_f64())
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= EPSILON * a.abs().max(b.abs()).max(f64::MIN_POSITIVE)
+}
+
+pub struct RulesCheck {
+    rng: Rng,
+    /// 每个交易对取样的价格、数量个数
+    samples: usize,
+}
+
+impl RulesCheck {
+    pub fn new(seed: u64, samples: usize) -> Self {
+        Self {
+            rng: Rng(seed),
+            samples,
+        }
+    }
+
+    /// 逐个解码 exchangeInfo 中的交易对并检查，`prices` 为 symbol -> 参考价
+    pub fn run(&mut self, info: &Value, prices: &HashMap<String, f64>) -> anyhow::Result<Report> {
+        let symbols = info["symbols"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("exchangeInfo without symbols"))?;
+        let mut report = Report::default();
+        for value in symbols {
+            report.symbols += 1;
+            match serde_json::from_value::<BinanceSymbol>(value.clone()) {
+                Ok(symbol) => {
+                    let reference = prices.get(&symbol.symbol).copied();
+                    report.samples += self.samples;
+                    report
+                        .violations
+                        .extend(self.check_symbol(&symbol, reference));
+                }
+                Err(e) => report.violations.push(Violation {
+                    symbol: value["symbol"].as_str().unwrap_or_default().to_lowercase(),
+                    check: "decode",
+                    detail: e.to_string(),
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn check_symbol(
+        &mut self,
+        symbol: &BinanceSymbol,
+        reference: Option<f64>,
+    ) -> Vec<Violation> {
+        let filters = Filters::new(symbol);
+        let price_step = filters.price.unwrap_or_default();
+        let lot_step = filters.lot.unwrap_or_default();
+        // 每类问题保留第一个反例
+        let mut found: BTreeMap<&'static str, String> = BTreeMap::new();
+        let mut fail = |check: &'static str, detail: String| {
+            found.entry(check).or_insert(detail);
+        };
+
+        if !close(symbol.min_notional(), filters.min_notional) {
+            fail(
+                "notional",
+                format!(
+                    "min_notional() is {}, the filters say {}",
+                    symbol.min_notional(),
+                    filters.min_notional
+                ),
+            );
+        }
+
+        let (mut lo, mut hi) = (price_step.lower(), price_step.upper());
+        if let Some(reference) = reference.filter(|r| *r > 0.0) {
+            // 在价格带外取一部分样本，检查本地规则是否拒绝
+            let (down, up) = filters.percent.map(|b| b.widest()).unwrap_or((0.5, 2.0));
+            lo = lo.max(reference * down.max(0.01) * 0.8);
+            hi = hi.min(reference * up.max(1.01) * 1.25);
+        }
+        let prices: Vec<f64> = [lo, hi]
+            .into_iter()
+            .chain((0..self.samples).map(|_| self.rng.between(lo, hi)))
+            .collect();
+        let quantities: Vec<f64> = [lot_step.lower(), lot_step.upper()]
+            .into_iter()
+            .chain((0..self.samples).map(|_| self.rng.between(lot_step.lower(), lot_step.upper())))
+            .collect();
+
+        let mut adjusted_prices = Vec::with_capacity(prices.len());
+        for price in prices.iter().copied() {
+            let adjusted = symbol.adjust_price(price);
+            adjusted_prices.push(adjusted);
+            if let Err(e) = price_step.check(adjusted) {
+                fail(
+                    "price",
+                    format!("adjust_price({}) = {}: {}", price, adjusted, e),
+                );
+            } else if !symbol.is_valid_price(adjusted) {
+                fail(
+                    "price",
+                    format!(
+                        "adjust_price({}) = {} fails is_valid_price",
+                        price, adjusted
+                    ),
+                );
+            } else if !close(symbol.adjust_price(adjusted), adjusted) {
+                fail(
+                    "price",
+                    format!("adjust_price({}) = {} is not stable", price, adjusted),
+                );
+            } else if price_step.check(price.clamp(lo, hi)).is_ok()
+                && (adjusted - price).abs() > price_step.step / 2.0 * (1.0 + 1e-6)
+            {
+                fail(
+                    "price",
+                    format!(
+                        "adjust_price({}) = {} moved more than half a tick",
+                        price, adjusted
+                    ),
+                );
+            }
+        }
+
+        let mut adjusted_quantities = Vec::with_capacity(quantities.len());
+        for quantity in quantities.iter().copied() {
+            let adjusted = symbol.adjust_quantity(quantity);
+            adjusted_quantities.push(adjusted);
+            if let Err(e) = lot_step.check(adjusted) {
+                fail(
+                    "quantity",
+                    format!("adjust_quantity({}) = {}: {}", quantity, adjusted, e),
+                );
+            } else if !symbol.is_valid_quantity(adjusted) {
+                fail(
+                    "quantity",
+                    format!(
+                        "adjust_quantity({}) = {} fails is_valid_quantity",
+                        quantity, adjusted
+                    ),
+                );
+            } else if !close(symbol.adjust_quantity(adjusted), adjusted) {
+                fail(
+                    "quantity",
+                    format!("adjust_quantity({}) = {} is not stable", quantity, adjusted),
+                );
+            } else if quantity >= lot_step.min && adjusted > quantity * (1.0 + EPSILON) {
+                fail(
+                    "quantity",
+                    format!("adjust_quantity({}) = {} rounded up", quantity, adjusted),
+                );
+            }
+            if let Err(e) = filters.check_market(adjusted) {
+                fail(
+                    e.filter,
+                    format!(
+                        "adjust_quantity({}) = {} is rejected for market orders: {}",
+                        quantity, adjusted, e.reason
+                    ),
+                );
+            }
+        }
+
+        for (i, price) in adjusted_prices.iter().copied().enumerate() {
+            if price <= 0.0 {
+                continue;
+            }
+            // 随机的数量与刚好满足最小名义价值的数量
+            let quantity = adjusted_quantities[i % adjusted_quantities.len()];
+            let smallest = lot_step.ceil(filters.min_notional / price);
+            for quantity in [quantity, smallest] {
+                for side in [Side::BUY, Side::SELL] {
+                    let local = symbol.is_valid_order(price, quantity);
+                    match (local, filters.check_limit(&side, price, quantity, reference)) {
+                        (true, Err(e)) => fail(
+                            e.filter,
+                            format!(
+                                "is_valid_order({}, {}) accepts a {:?} order the exchange rejects: {}",
+                                price, quantity, side, e.reason
+                            ),
+                        ),
+                        (false, Ok(())) => fail(
+                            "order",
+                            format!(
+                                "is_valid_order({}, {}) rejects a {:?} order the exchange accepts",
+                                price, quantity, side
+                            ),
+                        ),
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        found
+            .into_iter()
+            .map(|(check, detail)| Violation {
+                symbol: symbol.symbol.clone(),
+                check,
+                detail,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn info(filters: Value) -> Value {
+        json!({"symbols": [{
+            "symbol": "BTCUSDT",
+            "status": "TRADING",
+            "baseAsset": "BTC",
+            "baseAssetPrecision": 8,
+            "quoteAsset": "USDT",
+            "quotePrecision": 8,
+            "orderTypes": ["LIMIT", "MARKET"],
+            "filters": filters,
+        }]})
+    }
+
+    #[test]
+    fn test_rules_check() {
+        let basic = json!([
+            {"filterType": "PRICE_FILTER", "minPrice": "0.01", "maxPrice": "1000000", "tickSize": "0.01"},
+            {"filterType": "LOT_SIZE", "minQty": "0.001", "maxQty": "9000", "stepSize": "0.001"},
+            {"filterType": "NOTIONAL", "minNotional": "5", "applyMinToMarket": true,
+             "maxNotional": "9000000000", "applyMaxToMarket": false, "avgPriceMins": 5},
+        ]);
+        let prices = HashMap::from([("btcusdt".to_string(), 50000.0)]);
+        let report = RulesCheck::new(7, 200)
+            .run(&info(basic.clone()), &prices)
+            .unwrap();
+        assert_eq!((report.symbols, report.samples), (1, 200));
+        assert!(report.clean(), "{:?}", report.violations);
+
+        // 步长为 0 的 MARKET_LOT_SIZE 不限制步长，但上限比 LOT_SIZE 小
+        let mut filters = basic.as_array().unwrap().clone();
+        filters.push(json!({"filterType": "MARKET_LOT_SIZE", "minQty": "0", "maxQty": "120", "stepSize": "0"}));
+        filters.push(json!({"filterType": "PERCENT_PRICE_BY_SIDE", "bidMultiplierUp": "1.2",
+            "bidMultiplierDown": "0.2", "askMultiplierUp": "5", "askMultiplierDown": "0.8", "avgPriceMins": 5}));
+        let report = RulesCheck::new(7, 200)
+            .run(&info(json!(filters)), &prices)
+            .unwrap();
+        let checks: Vec<_> = report.violations.iter().map(|v| v.check).collect();
+        assert_eq!(checks, vec!["market_quantity", "percent_price"]);
+        assert!(report.violations[0].detail.contains("above max 120"));
+
+        // 最大价格为 0 表示不限制
+        let filters = json!([
+            {"filterType": "PRICE_FILTER", "minPrice": "0.1", "maxPrice": "0", "tickSize": "0.1"},
+            {"filterType": "LOT_SIZE", "minQty": "1", "maxQty": "1000", "stepSize": "1"},
+            {"filterType": "MIN_NOTIONAL", "notional": "100"},
+        ]);
+        let report = RulesCheck::new(7, 50).run(&info(filters), &prices).unwrap();
+        assert!(report.clean(), "{:?}", report.violations);
+
+        // 无法解码的交易对
+        let mut info = info(basic);
+        info["symbols"][0]["status"] = json!("SETTLING");
+        let report = RulesCheck::new(7, 10).run(&info, &prices).unwrap();
+        assert_eq!(report.violations[0].check, "decode");
+        assert_eq!(report.violations[0].symbol, "btcusdt");
+    }
+
+    #[test]
+    fn test_step() {
+        let step = Step::parse("0.1", "0", "0.05");
+        assert_eq!(step.check(0.15), Ok(()));
+        assert!(step.check(0.17).is_err());
+        assert!(step.check(0.05).is_err());
+        assert_eq!(step.check(1e9), Ok(()));
+        assert!((step.ceil(0.33) - 0.35).abs() < 1e-12);
+        assert!((step.ceil(0.35) - 0.35).abs() < 1e-12);
+        assert_eq!(Step::parse("0", "100", "0").check(1.23456), Ok(()));
+    }
+}
@@ -0,0 +1,279 @@
+//! 开盘前的状态核对
+//!
+//! 运维在每天开启交易之前发送带运维 token（见 [`crate::admin`]）的 `sanity_check`，网关重新查询交易所的
+//! 挂单、持仓与余额，与本地状态逐项比较：
+//!
+//! - 挂单：各会话未终结的订单与交易所的 openOrders 按 client order id 对应
+//! - 持仓：所有会话的持仓按持仓键相加后与交易所的持仓比较；现货没有交易所的持仓，不比较
+//! - 余额：网关启动后在账户推送中出现过的资产与交易所的余额比较，没有推送过的资产不比较
+//!
+//! 带 `heal` 时撤销交易所有而本地没有记录的挂单，并把持仓差额计入 `adopt_session`。本地有而交易所没有
+//! 的订单只报告，由随后的订单回报或订单巡检处理。
+//!
+//! 持仓与余额的每一项差异都会发送对账告警。
+
+use crate::lifecycle::correlation_id;
+use crate::rest::Rest;
+use crate::session::Session;
+use cryptoflow::alert::{Alerter, ReconcileKind};
+use cryptoflow::chat::{SError, SMissingOrder, SSanityDiff, SSanityReport, SUnknownOrder};
+use log::*;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 持仓与余额相差不超过这个值时视为一致
+pub const TOLERANCE: f64 = 1e-8;
+
+/// 网关本地的状态
+#[derive(Debug, Default)]
+pub struct LocalState {
+    /// client order id -> symbol，未终结的订单
+    pub orders: HashMap<u64, String>,
+    /// 持仓键 -> 所有会话的净持仓之和
+    pub positions: HashMap<String, f64>,
+    /// 资产 -> 最近一次账户推送中的余额
+    pub balances: BTreeMap<String, f64>,
+}
+
+/// 从交易所重新查询的状态，`positions`、`balances` 为 None 时不比较
+#[derive(Debug, Default)]
+pub struct ExchangeState {
+    pub orders: Vec<SUnknownOrder>,
+    pub positions: Option<HashMap<String, f64>>,
+    pub balances: Option<BTreeMap<String, f64>>,
+}
+
+/// 各会话未终结的订单，client order id -> symbol
+pub fn working_orders(sessions: &HashMap<u16, Session>) -> HashMap<u64, String> {
+    sessions
+        .iter()
+        .flat_map(|(session_id, session)| {
+            session
+                .working_orders()
+                .iter()
+                .map(|(order_id, symbol)| (correlation_id(*session_id, *order_id), symbol.clone()))
+        })
+        .collect()
+}
+
+pub fn compare(local: &LocalState, exchange: &ExchangeState) -> SSanityReport {
+    let mut seen = HashSet::new();
+    let mut unknown_orders = Vec::new();
+    for order in exchange.orders.iter() {
+        match order.client_order_id.parse::<u64>() {
+            Ok(cid) if local.orders.contains_key(&cid) => {
+                seen.insert(cid);
+            }
+            _ => unknown_orders.push(order.clone()),
+        }
+    }
+    unknown_orders.sort_by(|a, b| (&a.symbol, a.order_id).cmp(&(&b.symbol, b.order_id)));
+
+    let mut missing_orders: Vec<_> = local
+        .orders
+        .iter()
+        .filter(|(cid, _)| !seen.contains(*cid))
+        .map(|(cid, symbol)| SMissingOrder {
+            session_id: (cid >> 32) as u16,
+            order_id: (cid & 0xFFFFFFFF) as u32,
+            symbol: symbol.clone(),
+        })
+        .collect();
+    missing_orders.sort_by_key(|o| (o.session_id, o.order_id));
+
+    let positions = match &exchange.positions {
+        Some(remote) => {
+            let keys: BTreeSet<_> = local.positions.keys().chain(remote.keys()).collect();
+            keys.into_iter()
+                .filter_map(|key| {
+                    diff(
+                        key,
+                        local.positions.get(key).copied(),
+                        remote.get(key).copied(),
+                    )
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    let balances = match &exchange.balances {
+        Some(remote) => local
+            .balances
+            .iter()
+            .filter_map(|(asset, local)| diff(asset, Some(*local), remote.get(asset).copied()))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let clean = unknown_orders.is_empty()
+        && missing_orders.is_empty()
+        && positions.is_empty()
+        && balances.is_empty();
+    SSanityReport {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default(),
+        unknown_orders,
+        missing_orders,
+        positions,
+        balances,
+        canceled: Vec::new(),
+        adopted: Vec::new(),
+        clean,
+    }
+}
+
+/// 持仓与余额的差异逐项发送对账告警
+pub fn alert(report: &SSanityReport, alerter: &Alerter) {
+    for d in report.positions.iter() {
+        alerter.on_reconcile_mismatch(ReconcileKind::Position, &d.key, d.local, d.exchange);
+    }
+    for d in report.balances.iter() {
+        alerter.on_reconcile_mismatch(ReconcileKind::Balance, &d.key, d.local, d.exchange);
+    }
+}
+
+fn diff(key: &str, local: Option<f64>, exchange: Option<f64>) -> Option<SSanityDiff> {
+    let (local, exchange) = (local.unwrap_or_default(), exchange.unwrap_or_default());
+    ((local - exchange).abs() > TOLERANCE).then(|| SSanityDiff {
+        key: key.to_string(),
+        local,
+        exchange,
+    })
+}
+
+/// 查询所有挂单，`path` 为现货、杠杆或合约的 openOrders 接口
+pub async fn fetch_open_orders(rest: &Rest, path: &str) -> anyhow::Result<Vec<SUnknownOrder>> {
+    let rsp = rest.get(path, &[], true).await?;
+    let value: Value = serde_json::from_str(&rsp.text().await?)?;
+    let orders = value
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+    Ok(orders.iter().filter_map(exchange_order).collect())
+}
+
+fn exchange_order(value: &Value) -> Option<SUnknownOrder> {
+    Some(SUnknownOrder {
+        symbol: value["symbol"].as_str()?.to_lowercase(),
+        order_id: value["orderId"].as_i64()?,
+        client_order_id: value["clientOrderId"].as_str()?.to_string(),
+    })
+}
+
+/// 按交易所订单 id 撤销挂单，返回撤销成功的订单 id
+pub async fn cancel_orders(rest: &Rest, path: &str, orders: &[SUnknownOrder]) -> Vec<i64> {
+    let mut canceled = Vec::new();
+    for order in orders {
+        let params = [
+            ("symbol".to_string(), order.symbol.to_uppercase()),
+            ("orderId".to_string(), order.order_id.to_string()),
+        ];
+        let text = match rest.delete(path, &params, true).await {
+            Ok(rsp) => rsp.text().await.unwrap_or_default(),
+            Err(e) => {
+                error!("Cancel unknown order {:?}: {}", order, e);
+                continue;
+            }
+        };
+        match serde_json::from_str::<SError>(&text) {
+            Ok(e) => warn!("Cancel unknown order {:?} rejected: {:?}", order, e),
+            Err(_) => {
+                info!("Canceled unknown order {:?}", order);
+                canceled.push(order.order_id);
+            }
+        }
+    }
+    canceled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let cid = correlation_id;
+        let local = LocalState {
+            orders: HashMap::from([
+                (cid(1, 7), "btcusdt".to_string()),
+                (cid(2, 3), "ethusdt".to_string()),
+            ]),
+            positions: HashMap::from([
+                ("btcusdt".to_string(), 0.5),
+                ("ethusdt:LONG".to_string(), 2.0),
+            ]),
+            balances: BTreeMap::from([("USDT".to_string(), 100.0), ("BNB".to_string(), 1.0)]),
+        };
+        let rsp: Value = serde_json::from_str(
+            r#"[{"symbol":"BTCUSDT","orderId":11,"clientOrderId":"4294967303"},
+            {"symbol":"BTCUSDT","orderId":12,"clientOrderId":"web_abc"}]"#,
+        )
+        .unwrap();
+        let exchange = ExchangeState {
+            orders: rsp
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(exchange_order)
+                .collect(),
+            positions: Some(HashMap::from([
+                ("btcusdt".to_string(), 0.5),
+                ("ethusdt:LONG".to_string(), 1.5),
+                ("solusdt".to_string(), -3.0),
+            ])),
+            // 没有推送过的资产不比较
+            balances: Some(BTreeMap::from([
+                ("USDT".to_string(), 100.0 + 1e-10),
+                ("BNB".to_string(), 0.9),
+                ("BTC".to_string(), 0.1),
+            ])),
+        };
+
+        let report = compare(&local, &exchange);
+        assert_eq!(
+            report.unknown_orders,
+            vec![SUnknownOrder {
+                symbol: "btcusdt".into(),
+                order_id: 12,
+                client_order_id: "web_abc".into(),
+            }]
+        );
+        assert_eq!(
+            report.missing_orders,
+            vec![SMissingOrder {
+                session_id: 2,
+                order_id: 3,
+                symbol: "ethusdt".into(),
+            }]
+        );
+        let keys: Vec<_> = report.positions.iter().map(|d| d.key.as_str()).collect();
+        assert_eq!(keys, vec!["ethusdt:LONG", "solusdt"]);
+        assert_eq!(
+            report.balances,
+            vec![SSanityDiff {
+                key: "BNB".into(),
+                local: 1.0,
+                exchange: 0.9,
+            }]
+        );
+        assert!(!report.clean);
+
+        // 现货不比较持仓
+        let exchange = ExchangeState {
+            positions: None,
+            balances: None,
+            orders: vec![SUnknownOrder {
+                symbol: "btcusdt".into(),
+                order_id: 11,
+                client_order_id: cid(1, 7).to_string(),
+            }],
+        };
+        let local = LocalState {
+            orders: HashMap::from([(cid(1, 7), "btcusdt".to_string())]),
+            ..local
+        };
+        assert!(compare(&local, &exchange).clean);
+    }
+}
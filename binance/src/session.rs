@@ -1,26 +1,43 @@
-use log::*;
-use serde::Serialize;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::mpsc::UnboundedSender;
-use tungstenite::Message;
 use cryptoflow::chat::Side;
-use cryptoflow::chat::{Position, State};
+use cryptoflow::chat::{Position, SAttribution, SDelivery, SRoll, SSessionState, State};
 use cryptoflow::position::PositionDB;
+use cryptoflow::storage::{FillRecord, OrderRecord};
+use log::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::attribution::Attribution;
+use crate::model::order::BinanceOrder;
+use crate::wire::ClientSender;
 use crate::OrderTrait;
 
 pub struct Session {
     session_id: u16,
+    /// 持仓键 -> 持仓，双向持仓的多空两腿分开统计，见 [`Position::key`]
     positions: HashMap<String, Position>,
     posdb: Arc<PositionDB>,
-    tx: Option<UnboundedSender<Message>>,
+    tx: Option<ClientSender>,
+    /// order_id -> symbol，尚未终结的订单
+    working: HashMap<u32, String>,
+    cancel_on_disconnect: bool,
+    /// 逐笔推送成交
+    fills: bool,
+    /// order_id -> (到期时间, symbol)，带 ttl_ms 的订单
+    deadlines: HashMap<u32, (Instant, String)>,
+    /// 已因超时发出撤单，撤单回报需要改写为 EXPIRED_BY_GATEWAY
+    expired: HashSet<u32>,
+    attribution: Attribution,
+    /// 策略端登录过，导入的会话在登录之前为 false
+    logged_in: bool,
 }
 
 impl Session {
     pub async fn new(
         session_id: u16,
         posdb: Arc<PositionDB>,
-        tx: UnboundedSender<Message>,
+        tx: ClientSender,
     ) -> anyhow::Result<Self> {
         let positions = posdb.get_positions(session_id);
         posdb.create_table(session_id).await?;
@@ -29,55 +46,385 @@ impl Session {
             positions: positions.cloned().unwrap_or_default(),
             posdb,
             tx: Some(tx),
+            working: HashMap::default(),
+            cancel_on_disconnect: false,
+            fills: false,
+            deadlines: HashMap::default(),
+            expired: HashSet::default(),
+            attribution: Attribution::default(),
+            logged_in: true,
+        })
+    }
+
+    /// 按另一个网关导出的状态恢复未登录的会话，策略端登录后接着使用；持仓由 [`Session::persist`] 写入持仓库
+    pub async fn restore(state: &SSessionState, posdb: Arc<PositionDB>) -> anyhow::Result<Self> {
+        for position in &state.positions {
+            anyhow::ensure!(
+                !position.symbol.is_empty() && position.net.is_finite(),
+                "invalid position {:?}",
+                position
+            );
+        }
+        if let Some((order_id, _)) = state.working.iter().find(|(_, symbol)| symbol.is_empty()) {
+            anyhow::bail!("order {} has no symbol", order_id);
+        }
+        posdb.create_table(state.session_id).await?;
+        Ok(Self {
+            session_id: state.session_id,
+            positions: state
+                .positions
+                .iter()
+                .map(|position| (position.key(), position.clone()))
+                .collect(),
+            posdb,
+            tx: None,
+            working: state.working.clone().into_iter().collect(),
+            cancel_on_disconnect: state.cancel_on_disconnect,
+            fills: state.fills,
+            deadlines: HashMap::default(),
+            expired: HashSet::default(),
+            attribution: Attribution::default(),
+            logged_in: false,
         })
     }
 
+    /// 把所有持仓写入持仓库
+    pub fn persist(&self) {
+        for position in self.positions.values() {
+            self.posdb.update(self.session_id, position.clone());
+        }
+    }
+
+    /// 导出持仓、未终结的订单与登录选项；到期时间与归因统计不导出
+    pub fn export(&self) -> SSessionState {
+        let mut positions: Vec<Position> = self.positions.values().cloned().collect();
+        positions.sort_by_key(Position::key);
+        SSessionState {
+            session_id: self.session_id,
+            positions,
+            working: self.working.clone().into_iter().collect(),
+            cancel_on_disconnect: self.cancel_on_disconnect,
+            fills: self.fills,
+        }
+    }
+
+    /// 用交易所账户级别的持仓补充入场价、杠杆等字段，不推送
+    pub fn with_position_details<'a>(
+        mut self,
+        details: impl IntoIterator<Item = &'a Position>,
+    ) -> Self {
+        for detail in details {
+            if let Some(position) = self.positions.get_mut(&detail.key()) {
+                position.apply(detail);
+            }
+        }
+        self
+    }
+
+    /// 交易所推送的持仓变化，会话持有该品种时更新并推送给策略端
+    pub fn on_position_detail(&mut self, detail: &Position) -> anyhow::Result<()> {
+        if let Some(position) = self.positions.get_mut(&detail.key()) {
+            position.apply(detail);
+            let position = position.to_owned();
+            self.send(&position)?;
+        }
+        Ok(())
+    }
+
     pub fn active(&self) -> bool {
         self.tx.is_some()
     }
 
-    pub fn on_order<T: OrderTrait + Serialize>(&mut self, order: &T) -> anyhow::Result<()> {
-        if let State::FILLED | State::PARTIALLY_FILLED = order.state() {
+    pub fn logged_in(&self) -> bool {
+        self.logged_in
+    }
+
+    pub fn on_order<T: OrderTrait + Serialize + Clone>(
+        &mut self,
+        order_id: u32,
+        order: &T,
+    ) -> anyhow::Result<()> {
+        let state = order.state();
+        if let State::FILLED | State::PARTIALLY_FILLED = state {
             self.on_trade(order)?;
+            if let Some(fill) = order.fill() {
+                if self.fills {
+                    self.send(&fill)?;
+                }
+                self.attribution.on_fill(order_id, &fill);
+                let tagged = self.attribution.get(order_id);
+                self.posdb.record_fill(FillRecord {
+                    session_id: self.session_id,
+                    strategy: tagged.and_then(|t| t.strategy.clone()),
+                    tags: tagged.map(|t| t.tags.clone()).unwrap_or_default(),
+                    fill,
+                });
+            }
+        }
+        self.track_order(order_id, order.symbol(), state);
+
+        let expired = !state.is_working() && self.expired.remove(&order_id);
+        if expired && matches!(state, State::CANCELED) {
+            let mut order = order.clone();
+            order.set_state(State::EXPIRED_BY_GATEWAY);
+            self.record_order(order_id, &order);
+            self.attribution.remove(order_id);
+            return self.send(&order);
+        }
+        self.record_order(order_id, order);
+        if !state.is_working() {
+            self.attribution.remove(order_id);
         }
         self.send(order)?;
 
         Ok(())
     }
 
+    /// 登记通过风控的订单的策略与标签，`arrival` 为到达价，见 [`crate::attribution`]
+    pub fn tag_order(&mut self, order: &BinanceOrder, arrival: f64) {
+        if order.strategy.is_some() || !order.tags.is_empty() {
+            info!(
+                "Order {} strategy {:?} tags {:?}",
+                order.id, order.strategy, order.tags
+            );
+        }
+        self.attribution.on_order(order, arrival);
+    }
+
+    /// 按策略与标签归因的统计
+    pub fn attribution(&self) -> Vec<SAttribution> {
+        self.attribution.report()
+    }
+
+    /// 保存推送给策略端的订单回报
+    fn record_order<T: OrderTrait + Serialize>(&self, order_id: u32, order: &T) {
+        let data = match serde_json::to_string(order) {
+            Ok(data) => data,
+            Err(e) => return error!("{}", e),
+        };
+        let tagged = self.attribution.get(order_id);
+        self.posdb.record_order(OrderRecord {
+            time: now_ms(),
+            session_id: self.session_id,
+            order_id,
+            exchange_order_id: order.exchange_order_id(),
+            update_time: order.update_time(),
+            trade_id: order.fill().map_or(-1, |fill| fill.trade_id),
+            symbol: order.symbol().to_string(),
+            state: format!("{:?}", order.state()),
+            strategy: tagged.and_then(|t| t.strategy.clone()),
+            tags: tagged.map(|t| t.tags.clone()).unwrap_or_default(),
+            data,
+        });
+    }
+
     fn on_trade<T: OrderTrait>(&mut self, order: &T) -> anyhow::Result<()> {
-        let mut binding = Position {
-            symbol: order.symbol().into(),
-            net: 0.0,
+        let net = order.net()?;
+        let leg = Position {
+            side: order.position_side(),
+            ..Position::new(order.symbol(), 0.0)
+        };
+        // 多头一腿为正，空头一腿为负，两腿相加即净持仓
+        let position = self.positions.entry(leg.key()).or_insert(leg);
+
+        let quantity = match order.side() {
+            Side::BUY => net,
+            Side::SELL => -net,
         };
+        let trd_vol = order.trd_vol()?;
+        // 现货的手续费已从成交量中扣除，不再计入成本；BNB 等其他资产收取的手续费不计入成本
+        let fee = match order.fill() {
+            Some(fill) if net == trd_vol && is_quote(order.symbol(), &fill.commission_asset) => {
+                fill.commission
+            }
+            _ => 0.0,
+        };
+        position.on_fill(quantity, trd_vol * order.trd_prc(), fee);
+
+        let position = position.to_owned();
+        self.send(&position)?;
+        self.posdb.update(self.session_id, position);
+
+        Ok(())
+    }
+
+    /// 根据订单回报维护未完成订单
+    pub fn track_order(&mut self, order_id: u32, symbol: &str, state: State) {
+        if state.is_working() {
+            self.working.insert(order_id, symbol.into());
+        } else {
+            self.working.remove(&order_id);
+            self.deadlines.remove(&order_id);
+        }
+    }
+
+    /// 登记订单的到期时间
+    pub fn set_deadline(&mut self, order_id: u32, symbol: &str, deadline: Instant) {
+        self.deadlines.insert(order_id, (deadline, symbol.into()));
+    }
+
+    /// 取出已经到期的订单，这些订单的撤单回报会以 EXPIRED_BY_GATEWAY 推送
+    pub fn expire_orders(&mut self, now: Instant) -> Vec<(u32, String)> {
+        if self.deadlines.is_empty() {
+            return Vec::new();
+        }
+        let ids: Vec<u32> = self
+            .deadlines
+            .iter()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| {
+                let (_, symbol) = self.deadlines.remove(&id)?;
+                self.expired.insert(id);
+                Some((id, symbol))
+            })
+            .collect()
+    }
+
+    pub fn working_orders(&self) -> &HashMap<u32, String> {
+        &self.working
+    }
+
+    pub fn set_cancel_on_disconnect(&mut self, cancel_on_disconnect: bool) {
+        self.cancel_on_disconnect = cancel_on_disconnect;
+    }
+
+    pub fn set_fills(&mut self, fills: bool) {
+        self.fills = fills;
+    }
+
+    /// 连接断开时需要撤销的订单，未开启 `cancel_on_disconnect` 时为空
+    pub fn orders_to_cancel(&self) -> Vec<(u32, String)> {
+        if !self.cancel_on_disconnect {
+            return Vec::new();
+        }
+        self.working
+            .iter()
+            .map(|(id, symbol)| (*id, symbol.clone()))
+            .collect()
+    }
+
+    pub fn position(&self, key: &str) -> Option<&Position> {
+        self.positions.get(key)
+    }
+
+    /// 品种的净持仓，双向持仓时为多空两腿之和
+    pub fn net(&self, symbol: &str) -> Option<f64> {
+        self.positions
+            .values()
+            .filter(|p| p.symbol == symbol)
+            .map(|p| p.net)
+            .reduce(|a, b| a + b)
+    }
+
+    pub fn positions(&self) -> &HashMap<String, Position> {
+        &self.positions
+    }
+
+    /// 交割结算，把品种的持仓置零、写入持仓库并推送，返回结算前的净持仓；没有持仓时不推送
+    pub fn settle(&mut self, symbol: &str, delivery_time: i64) -> anyhow::Result<f64> {
+        let keys: Vec<String> = self
+            .positions
+            .iter()
+            .filter(|(_, p)| p.symbol == symbol && p.net != 0.0)
+            .map(|(key, _)| key.clone())
+            .collect();
+        if keys.is_empty() {
+            return Ok(0.0);
+        }
+        let mut net = 0.0;
+        for key in keys {
+            let Some(position) = self.positions.get_mut(&key) else {
+                continue;
+            };
+            net += position.net;
+            position.net = 0.0;
+            position.avg_price = None;
+            position.cost_basis = None;
+            let position = position.to_owned();
+            self.send(&position)?;
+            self.posdb.update(self.session_id, position);
+        }
+        info!(
+            "Session {} settled {} {} at delivery",
+            self.session_id, net, symbol
+        );
+        self.send(&SDelivery {
+            symbol: symbol.to_string(),
+            delivery_time,
+            remaining_ms: 0,
+            position: net,
+            settled: true,
+        })?;
+        Ok(net)
+    }
+
+    /// 把交易所与本地的持仓差额计入持仓键 `key`，写入持仓库并推送，返回计入后的持仓
+    pub fn adopt_position(&mut self, key: &str, diff: f64) -> anyhow::Result<Position> {
         let position = self
             .positions
-            .get_mut(order.symbol())
-            .unwrap_or(&mut binding);
+            .entry(key.to_string())
+            .or_insert_with(|| Position::from_key(key, 0.0));
+        position.net += diff;
+        // 差额的成本未知
+        position.avg_price = None;
+        position.cost_basis = None;
+        let position = position.to_owned();
+        info!(
+            "Session {} adopted {} {}, net {}",
+            self.session_id, diff, key, position.net
+        );
+        self.send(&position)?;
+        self.posdb.update(self.session_id, position.clone());
+        Ok(position)
+    }
 
-        let net = order.net()?;
-        match order.side() {
-            Side::BUY => position.net += net,
-            Side::SELL => position.net -= net,
+    /// 持有该品种时推送交割提醒
+    pub fn notify_delivery(
+        &self,
+        symbol: &str,
+        delivery_time: i64,
+        remaining_ms: i64,
+    ) -> anyhow::Result<()> {
+        let held = self
+            .positions
+            .values()
+            .any(|p| p.symbol == symbol && p.net != 0.0);
+        if !held {
+            return Ok(());
         }
+        self.send(&SDelivery {
+            symbol: symbol.to_string(),
+            delivery_time,
+            remaining_ms,
+            position: self.net(symbol).unwrap_or_default(),
+            settled: false,
+        })
+    }
 
-        if let Some(position) = self.positions.get(order.symbol()) {
-            self.send(position)?;
-            self.posdb.update(self.session_id, position.to_owned())
+    /// 持有 `roll.contract` 或在它上面有挂单时推送移仓提醒
+    pub fn notify_roll(&self, roll: &SRoll) -> anyhow::Result<()> {
+        let held = self
+            .positions
+            .values()
+            .any(|p| p.symbol == roll.contract && p.net != 0.0)
+            || self.working.values().any(|symbol| *symbol == roll.contract);
+        if !held {
+            return Ok(());
         }
-
-        Ok(())
+        self.send(roll)
     }
 
     fn send<T: Serialize>(&self, data: &T) -> anyhow::Result<()> {
-        let msg = serde_json::to_string(data)?;
         if let Some(tx) = &self.tx {
-            return Ok(tx.send(Message::Text(msg.into()))?);
+            return tx.reply(data);
         }
         Ok(())
     }
 
-    pub fn set_active(&mut self, tx: Option<UnboundedSender<Message>>) -> bool {
+    pub fn set_active(&mut self, tx: Option<ClientSender>) -> bool {
         info!(
             "Set session {} {} -> {}",
             self.session_id,
@@ -85,6 +432,207 @@ impl Session {
             tx.is_some()
         );
         self.tx = tx;
+        self.logged_in |= self.active();
         self.active()
     }
 }
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// `asset` 是否为交易对的计价资产，如 btcusdt 的 USDT
+fn is_quote(symbol: &str, asset: &str) -> bool {
+    !asset.is_empty() && symbol.to_uppercase().ends_with(&asset.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::Message;
+
+    #[tokio::test]
+    async fn test_orders_to_cancel() {
+        let path = std::env::temp_dir().join(format!("session-{}.db", std::process::id()));
+        let posdb = Arc::new(PositionDB::new(path.to_str().unwrap()).await.unwrap());
+        let (tx, _rx) = websocket::bounded_channel("test", &Default::default());
+        let mut session = Session::new(1, posdb, tx.into()).await.unwrap();
+
+        session.track_order(1, "btcusdt", State::NEW);
+        session.track_order(2, "ethusdt", State::PARTIALLY_FILLED);
+        session.track_order(3, "btcusdt", State::NEW);
+        session.track_order(3, "btcusdt", State::FILLED);
+        session.track_order(4, "btcusdt", State::REJECTED);
+        assert_eq!(session.working_orders().len(), 2);
+        // 未开启时断线不撤单
+        assert!(session.orders_to_cancel().is_empty());
+
+        session.set_cancel_on_disconnect(true);
+        let mut orders = session.orders_to_cancel();
+        orders.sort();
+        assert_eq!(
+            orders,
+            vec![(1, "btcusdt".to_string()), (2, "ethusdt".to_string())]
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_expire_orders() {
+        let path = std::env::temp_dir().join(format!("session-ttl-{}.db", std::process::id()));
+        let posdb = Arc::new(PositionDB::new(path.to_str().unwrap()).await.unwrap());
+        let (tx, _rx) = websocket::bounded_channel("test", &Default::default());
+        let mut session = Session::new(1, posdb, tx.into()).await.unwrap();
+
+        let now = Instant::now();
+        session.set_deadline(1, "btcusdt", now + std::time::Duration::from_millis(100));
+        session.set_deadline(2, "btcusdt", now + std::time::Duration::from_millis(100));
+        session.set_deadline(3, "btcusdt", now + std::time::Duration::from_millis(500));
+        // 到期前已经成交的订单不再撤销
+        session.track_order(2, "btcusdt", State::FILLED);
+
+        assert!(session.expire_orders(now).is_empty());
+        let later = now + std::time::Duration::from_millis(200);
+        assert_eq!(
+            session.expire_orders(later),
+            vec![(1, "btcusdt".to_string())]
+        );
+        assert!(session.expired.contains(&1));
+        assert!(session.expire_orders(later).is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_position_detail() {
+        let path = std::env::temp_dir().join(format!("session-pos-{}.db", std::process::id()));
+        let posdb = Arc::new(PositionDB::new(path.to_str().unwrap()).await.unwrap());
+        let (tx, mut rx) = websocket::bounded_channel("test", &Default::default());
+        let mut session = Session::new(1, posdb, tx.into()).await.unwrap();
+        session
+            .positions
+            .insert("btcusdt".into(), Position::new("btcusdt", 0.5));
+
+        // 未持有的品种不推送
+        let detail = Position {
+            entry_price: Some(64000.0),
+            leverage: Some(20),
+            ..Position::new("ethusdt", 0.0)
+        };
+        session.on_position_detail(&detail).unwrap();
+        assert!(rx.try_recv().is_err());
+
+        let detail = Position {
+            symbol: "btcusdt".into(),
+            ..detail
+        };
+        session.on_position_detail(&detail).unwrap();
+        let Ok(Message::Text(msg)) = rx.try_recv() else {
+            panic!("no position pushed");
+        };
+        let position: Position = serde_json::from_str(&msg).unwrap();
+        assert_eq!(position.net, 0.5);
+        assert_eq!(position.entry_price, Some(64000.0));
+        assert_eq!(session.position("btcusdt").unwrap().leverage, Some(20));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_hedge_legs() {
+        use crate::model::order::usdt::OrderUpdate;
+
+        let path = std::env::temp_dir().join(format!("session-hedge-{}.db", std::process::id()));
+        let posdb = Arc::new(PositionDB::new(path.to_str().unwrap()).await.unwrap());
+        let (tx, _rx) = websocket::bounded_channel("test", &Default::default());
+        let mut session = Session::new(1, posdb, tx.into()).await.unwrap();
+
+        let fill = |id: u32, side: &str, ps: &str, qty: &str| -> OrderUpdate {
+            serde_json::from_str(&format!(
+                r#"{{"e":"ORDER_TRADE_UPDATE","E":1,"T":1,"o":{{"s":"BTCUSDT","c":"{}","S":"{}","o":"LIMIT","f":"GTC",
+                "q":"{}","p":"100","ap":"100","sp":"0","x":"TRADE","X":"FILLED","i":{},"l":"{}","z":"{}","L":"100",
+                "N":"USDT","n":"0","T":1,"t":1,"b":"0","a":"0","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"LIMIT",
+                "ps":"{}","rp":"0"}}}}"#,
+                (1u64 << 32) | id as u64, side, qty, id, qty, qty, ps
+            ))
+            .unwrap()
+        };
+        session.on_order(1, &fill(1, "BUY", "LONG", "3")).unwrap();
+        session.on_order(2, &fill(2, "SELL", "SHORT", "1")).unwrap();
+        session.on_order(3, &fill(3, "SELL", "LONG", "1")).unwrap();
+
+        assert_eq!(session.position("btcusdt:LONG").unwrap().net, 2.0);
+        assert_eq!(session.position("btcusdt:SHORT").unwrap().net, -1.0);
+        // 平掉多头的一个，成本按比例减少
+        let long = session.position("btcusdt:LONG").unwrap();
+        assert_eq!(
+            (long.avg_price, long.cost_basis),
+            (Some(100.0), Some(200.0))
+        );
+        assert!(session.position("btcusdt").is_none());
+        assert_eq!(session.net("btcusdt"), Some(1.0));
+        assert_eq!(session.net("ethusdt"), None);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_fills() {
+        use crate::model::ExecutionReport;
+        use cryptoflow::chat::SFill;
+
+        let path = std::env::temp_dir().join(format!("session-fills-{}.db", std::process::id()));
+        let posdb = Arc::new(PositionDB::new(path.to_str().unwrap()).await.unwrap());
+        let (tx, mut rx) = websocket::bounded_channel("test", &Default::default());
+        let mut session = Session::new(1, posdb, tx.into()).await.unwrap();
+
+        let report = |x: &str, state: &str, qty: &str, trade_id: i64| -> ExecutionReport {
+            serde_json::from_str(&format!(
+                r#"{{"e":"executionReport","E":1,"s":"BTCUSDT","c":"4294967303","S":"BUY","o":"LIMIT","f":"GTC",
+                "q":"2","p":"100","P":"0","F":"0","g":-1,"C":"","x":"{}","X":"{}","r":"NONE","i":42,"l":"{}",
+                "z":"1","L":"99.5","n":"0.001","N":"BNB","T":5,"t":{},"I":1,"w":true,"m":true,"M":false,"O":1,
+                "Z":"0","Y":"0","Q":"0","W":1,"V":"NONE"}}"#,
+                x, state, qty, trade_id
+            ))
+            .unwrap()
+        };
+        let mut texts = || -> Vec<String> {
+            std::iter::from_fn(|| match rx.try_recv() {
+                Ok(Message::Text(text)) => Some(text.to_string()),
+                _ => None,
+            })
+            .collect()
+        };
+
+        // 未开启时只推送持仓与订单
+        session
+            .on_order(7, &report("TRADE", "PARTIALLY_FILLED", "1", 11))
+            .unwrap();
+        assert_eq!(texts().len(), 2);
+
+        session.set_fills(true);
+        session.on_order(7, &report("NEW", "NEW", "0", -1)).unwrap();
+        assert_eq!(texts().len(), 1);
+        session
+            .on_order(7, &report("TRADE", "PARTIALLY_FILLED", "0.5", 12))
+            .unwrap();
+        let texts = texts();
+        assert_eq!(texts.len(), 3);
+        let fill: SFill = serde_json::from_str(&texts[1]).unwrap();
+        assert_eq!(
+            (
+                fill.internal_id,
+                fill.order_id,
+                fill.trade_id,
+                fill.symbol.as_str()
+            ),
+            (7, 42, 12, "btcusdt")
+        );
+        assert_eq!((fill.price, fill.quantity, fill.maker), (99.5, 0.5, true));
+        assert_eq!(
+            (fill.commission, fill.commission_asset.as_str()),
+            (0.001, "BNB")
+        );
+        let _ = std::fs::remove_file(path);
+    }
+}
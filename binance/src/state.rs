@@ -0,0 +1,281 @@
+//! 网关状态的导出与导入
+//!
+//! 把网关迁移到另一台主机或升级时，运维先在旧网关上暂停交易并发送带运维 token（见 [`crate::admin`]）的
+//! `export_state`，回复的 [`SGatewayState`] 保存为状态文件；新网关启动后、策略端登录前发送 `import_state`。
+//! 状态包括：
+//!
+//! - 每个会话的持仓，登录过的会话带入场价等内存中的字段，其余为持仓库中的净持仓
+//! - 未终结订单的归属（order_id -> symbol），用于断线撤单、移仓与状态核对
+//! - 会话的登录选项 `cancel_on_disconnect`、`fills`
+//! - 网关自己下单使用的订单号计数器，新网关不会重复使用旧网关已发出的订单号
+//!
+//! 导入只能在策略端登录新网关之前进行，要么恢复全部会话并把持仓写入持仓库，要么一个也不恢复。状态文件是
+//! 导出时刻的快照，导入后用 `sanity_check` 与交易所核对这段时间内的变化。
+
+use crate::session::Session;
+use cryptoflow::chat::{SError, SGatewayState, SImportReport, SSessionState};
+use cryptoflow::error_code::INVALID_STATE;
+use cryptoflow::position::PositionDB;
+use log::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// 状态文件的格式版本
+pub const STATE_VERSION: u32 = 1;
+/// 定时平仓的订单号计数器，见 [`crate::flatten`]
+pub const FLATTEN: &str = "flatten";
+/// 移仓的订单号计数器，见 [`crate::roll`]
+pub const ROLL: &str = "roll";
+
+fn invalid(msg: String) -> SError {
+    SError {
+        code: INVALID_STATE,
+        msg,
+    }
+}
+
+/// 导出所有会话，登录过的会话用内存中的状态，其余用持仓库中的记录
+pub fn export(
+    venue: &str,
+    sessions: &HashMap<u16, Session>,
+    posdb: &PositionDB,
+    counters: BTreeMap<String, u32>,
+) -> SGatewayState {
+    let stored = posdb
+        .sessions()
+        .filter(|(session_id, _)| !sessions.contains_key(session_id))
+        .map(|(session_id, positions)| {
+            let mut positions: Vec<_> = positions.values().cloned().collect();
+            positions.sort_by_key(|position| position.key());
+            SSessionState {
+                session_id,
+                positions,
+                working: BTreeMap::new(),
+                cancel_on_disconnect: false,
+                fills: false,
+            }
+        });
+    let mut states: Vec<_> = sessions
+        .values()
+        .map(Session::export)
+        .chain(stored)
+        .collect();
+    states.sort_by_key(|state| state.session_id);
+    SGatewayState {
+        version: STATE_VERSION,
+        venue: venue.to_string(),
+        time: chrono::Utc::now().timestamp_millis(),
+        sessions: states,
+        counters,
+    }
+}
+
+/// 检查版本与交易场所，本网关上还没有会话登录过，要导入的会话不能重复，也不能已经导入过
+pub fn validate(
+    venue: &str,
+    state: &SGatewayState,
+    sessions: &HashMap<u16, Session>,
+) -> Result<(), SError> {
+    if state.version != STATE_VERSION {
+        return Err(invalid(format!(
+            "unsupported state version {}, expected {}",
+            state.version, STATE_VERSION
+        )));
+    }
+    if state.venue != venue {
+        return Err(invalid(format!(
+            "state of {} cannot be imported into {}",
+            state.venue, venue
+        )));
+    }
+    if let Some(session_id) = sessions
+        .iter()
+        .find_map(|(session_id, session)| session.logged_in().then_some(session_id))
+    {
+        return Err(invalid(format!(
+            "session {} has logged in, import state before any login",
+            session_id
+        )));
+    }
+    let mut seen = HashSet::new();
+    for session in state.sessions.iter() {
+        if !seen.insert(session.session_id) {
+            return Err(invalid(format!(
+                "session {} appears more than once",
+                session.session_id
+            )));
+        }
+        if sessions.contains_key(&session.session_id) {
+            return Err(invalid(format!(
+                "session {} already exists on this gateway",
+                session.session_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 校验后恢复所有会话，任何一个会话恢复失败时都不导入，也不写持仓库；计数器由调用方推进
+pub async fn import(
+    venue: &str,
+    state: &SGatewayState,
+    sessions: &mut HashMap<u16, Session>,
+    posdb: &Arc<PositionDB>,
+) -> Result<SImportReport, SError> {
+    validate(venue, state, sessions)?;
+    let mut restored = Vec::with_capacity(state.sessions.len());
+    for session_state in state.sessions.iter() {
+        let session = Session::restore(session_state, posdb.clone())
+            .await
+            .map_err(|e| invalid(format!("session {}: {}", session_state.session_id, e)))?;
+        restored.push((session_state, session));
+    }
+
+    let mut report = SImportReport {
+        time: chrono::Utc::now().timestamp_millis(),
+        ..Default::default()
+    };
+    for (session_state, session) in restored {
+        let session_id = session_state.session_id;
+        info!(
+            "Import session {}: {} positions, {} working orders",
+            session_id,
+            session_state.positions.len(),
+            session_state.working.len()
+        );
+        session.persist();
+        report.sessions.push(session_id);
+        report.positions += session_state.positions.len();
+        report.orders += session_state.working.len();
+        sessions.insert(session_id, session);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::{Position, State};
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let dir = std::env::temp_dir();
+        let source = dir.join(format!("state-source-{}.db", std::process::id()));
+        let target = dir.join(format!("state-target-{}.db", std::process::id()));
+        let posdb = Arc::new(PositionDB::new(source.to_str().unwrap()).await.unwrap());
+        let (tx, _rx) = websocket::bounded_channel("test", &Default::default());
+        let mut session = Session::new(3, posdb.clone(), tx.into()).await.unwrap();
+        session.adopt_position("btcusdt", 0.5).unwrap();
+        session.track_order(7, "btcusdt", State::NEW);
+        session.track_order(8, "ethusdt", State::FILLED);
+        session.set_cancel_on_disconnect(true);
+        let sessions = HashMap::from([(3, session)]);
+
+        let counters = BTreeMap::from([(ROLL.to_string(), 0xE000_0002)]);
+        let state = export("binance-usdt", &sessions, &posdb, counters);
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.sessions.len(), 1);
+        assert_eq!(
+            state.sessions[0].working,
+            BTreeMap::from([(7, "btcusdt".into())])
+        );
+
+        // 经过状态文件
+        let text = serde_json::to_string_pretty(&state).unwrap();
+        let state: SGatewayState = serde_json::from_str(&text).unwrap();
+
+        let posdb = Arc::new(PositionDB::new(target.to_str().unwrap()).await.unwrap());
+        let mut imported = HashMap::new();
+        let err = import("binance-spot", &state, &mut imported, &posdb)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, INVALID_STATE);
+        assert_eq!(
+            err.msg,
+            "state of binance-usdt cannot be imported into binance-spot"
+        );
+        let mut future = state.clone();
+        future.version = STATE_VERSION + 1;
+        assert!(import("binance-usdt", &future, &mut imported, &posdb)
+            .await
+            .unwrap_err()
+            .msg
+            .starts_with("unsupported state version"));
+
+        let report = import("binance-usdt", &state, &mut imported, &posdb)
+            .await
+            .unwrap();
+        assert_eq!(report.sessions, vec![3]);
+        assert_eq!((report.positions, report.orders), (1, 1));
+        let session = &imported[&3];
+        assert!(!session.active());
+        assert_eq!(session.net("btcusdt"), Some(0.5));
+        assert_eq!(session.orders_to_cancel(), vec![(7, "btcusdt".to_string())]);
+        let position: &Position = session.position("btcusdt").unwrap();
+        assert_eq!(position.net, 0.5);
+
+        // 已经导入过的会话不再覆盖
+        let err = import("binance-usdt", &state, &mut imported, &posdb)
+            .await
+            .unwrap_err();
+        assert_eq!(err.msg, "session 3 already exists on this gateway");
+
+        // 有会话登录之后不再导入
+        let (tx, _rx) = websocket::bounded_channel("test", &Default::default());
+        imported.get_mut(&3).unwrap().set_active(Some(tx.into()));
+        let mut other = state.clone();
+        other.sessions[0].session_id = 4;
+        let err = import("binance-usdt", &other, &mut imported, &posdb)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.msg,
+            "session 3 has logged in, import state before any login"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_is_atomic() {
+        let path = std::env::temp_dir().join(format!("state-atomic-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let posdb = Arc::new(PositionDB::new(path.to_str().unwrap()).await.unwrap());
+        let session = |session_id, symbol: &str| SSessionState {
+            session_id,
+            positions: vec![Position::new(symbol, 0.5)],
+            working: BTreeMap::from([(7, "btcusdt".into())]),
+            cancel_on_disconnect: false,
+            fills: false,
+        };
+        let mut state = SGatewayState {
+            version: STATE_VERSION,
+            venue: "binance-usdt".into(),
+            time: 0,
+            sessions: vec![session(1, "btcusdt"), session(2, "")],
+            counters: BTreeMap::new(),
+        };
+
+        // 第二个会话的持仓无效，第一个会话也不导入，持仓库中没有它的持仓
+        let mut sessions = HashMap::new();
+        let err = import("binance-usdt", &state, &mut sessions, &posdb)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, INVALID_STATE);
+        assert!(err.msg.starts_with("session 2: invalid position"));
+        assert!(sessions.is_empty());
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let reopened = PositionDB::new(path.to_str().unwrap()).await.unwrap();
+        assert!(reopened.get_positions(1).is_none_or(|p| p.is_empty()));
+
+        // 修正后可以完整导入
+        state.sessions[1] = session(2, "ethusdt");
+        let report = import("binance-usdt", &state, &mut sessions, &posdb)
+            .await
+            .unwrap();
+        assert_eq!(report.sessions, vec![1, 2]);
+        assert_eq!(sessions.len(), 2);
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let reopened = PositionDB::new(path.to_str().unwrap()).await.unwrap();
+        assert_eq!(reopened.get_positions(1).unwrap()["btcusdt"].net, 0.5);
+        assert_eq!(reopened.get_positions(2).unwrap()["ethusdt"].net, 0.5);
+    }
+}
@@ -0,0 +1,277 @@
+//! 行情连接的订阅预算
+//!
+//! 币安每条行情连接最多订阅 `max_streams` 条流（现货与合约都是 1024），每秒最多接收
+//! `max_messages_per_sec` 条订阅、退订等控制消息，超出时交易所拒绝订阅或断开连接。网关记录每条连接
+//! 上的流，超过 `warn_ratio` 时告警；每条连接上的控制消息按 `max_messages_per_sec` 排队发送。主连接订满
+//! 后，新的流依次放到连接池中另开的连接上，连接池与主连接使用同一个交易所的 combined 地址，总共最多
+//! `max_connections` 条连接，全部订满时拒绝新的订阅并回复 `-10014`。
+
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// ```json
+/// "stream_budget": {
+///     "max_streams": 1024,
+///     "warn_ratio": 0.8,
+///     "max_connections": 5,
+///     "max_messages_per_sec": 5
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StreamBudgetConfig {
+    /// 每条连接最多订阅的流
+    pub max_streams: usize,
+    /// 一条连接上的流超过 `max_streams` 的这个比例时告警
+    pub warn_ratio: f64,
+    /// 包括主连接在内最多使用的连接数
+    pub max_connections: usize,
+    /// 每条连接每秒最多发送的控制消息，超过时推迟发送
+    pub max_messages_per_sec: usize,
+}
+
+impl Default for StreamBudgetConfig {
+    fn default() -> Self {
+        Self {
+            max_streams: 1024,
+            warn_ratio: 0.8,
+            max_connections: 5,
+            max_messages_per_sec: 5,
+        }
+    }
+}
+
+/// 连接池的地址：与主连接同一个交易所的 combined 地址，`/ws` 换成 `/stream`
+pub fn pool_url(url: &str) -> String {
+    let base = url.trim_end_matches('/');
+    let base = base
+        .strip_suffix("/ws")
+        .or_else(|| base.strip_suffix("/stream"))
+        .unwrap_or(base);
+    format!("{}/stream", base)
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    streams: HashSet<String>,
+    /// 已经告警过接近上限，降回阈值以下后重新告警
+    warned: bool,
+    /// 最近 `max_messages_per_sec` 条控制消息的发送时间，可能在将来
+    sent: VecDeque<Instant>,
+}
+
+/// 连接 0 是主连接，其余是连接池中按需建立的连接
+#[derive(Debug)]
+pub struct StreamBudget {
+    config: StreamBudgetConfig,
+    connections: Vec<Connection>,
+    /// 流 -> 所在的连接
+    owners: HashMap<String, usize>,
+}
+
+impl Default for StreamBudget {
+    fn default() -> Self {
+        Self::new(&StreamBudgetConfig::default())
+    }
+}
+
+impl StreamBudget {
+    pub fn new(config: &StreamBudgetConfig) -> Self {
+        Self {
+            config: config.clone(),
+            connections: vec![Connection::default()],
+            owners: HashMap::default(),
+        }
+    }
+
+    /// 所有连接上的流
+    pub fn total(&self) -> usize {
+        self.owners.len()
+    }
+
+    /// 还能订阅的流
+    pub fn available(&self) -> usize {
+        (self.config.max_connections.max(1) * self.config.max_streams).saturating_sub(self.total())
+    }
+
+    pub fn connection_of(&self, stream: &str) -> Option<usize> {
+        self.owners.get(stream).copied()
+    }
+
+    /// 为新的流分配连接，按连接返回需要订阅的流，已经分配过的流不再返回；所有连接都订满时剩下的流
+    /// 放在第二个返回值中
+    pub fn assign(&mut self, streams: &[String]) -> (Vec<(usize, Vec<String>)>, Vec<String>) {
+        let mut assigned: Vec<(usize, Vec<String>)> = Vec::new();
+        let mut rejected = Vec::new();
+        for stream in streams {
+            if self.owners.contains_key(stream) {
+                continue;
+            }
+            let connection = match self
+                .connections
+                .iter()
+                .position(|c| c.streams.len() < self.config.max_streams)
+            {
+                Some(connection) => connection,
+                None if self.connections.len() < self.config.max_connections => {
+                    self.connections.push(Connection::default());
+                    self.connections.len() - 1
+                }
+                None => {
+                    rejected.push(stream.clone());
+                    continue;
+                }
+            };
+            self.connections[connection].streams.insert(stream.clone());
+            self.owners.insert(stream.clone(), connection);
+            match assigned.iter_mut().find(|(c, _)| *c == connection) {
+                Some((_, streams)) => streams.push(stream.clone()),
+                None => assigned.push((connection, vec![stream.clone()])),
+            }
+        }
+        for (connection, _) in assigned.iter() {
+            self.check_usage(*connection);
+        }
+        (assigned, rejected)
+    }
+
+    /// 释放流，按连接返回需要退订的流，未分配的流算在主连接上
+    pub fn release(&mut self, streams: &[String]) -> Vec<(usize, Vec<String>)> {
+        let threshold = self.threshold();
+        let mut released: Vec<(usize, Vec<String>)> = Vec::new();
+        for stream in streams {
+            let connection = match self.owners.remove(stream) {
+                Some(connection) => {
+                    let c = &mut self.connections[connection];
+                    c.streams.remove(stream);
+                    c.warned &= c.streams.len() as f64 >= threshold;
+                    connection
+                }
+                None => 0,
+            };
+            match released.iter_mut().find(|(c, _)| *c == connection) {
+                Some((_, streams)) => streams.push(stream.clone()),
+                None => released.push((connection, vec![stream.clone()])),
+            }
+        }
+        released
+    }
+
+    /// 为发往 `connection` 的一条控制消息安排发送时间，返回需要等待的时间：任意一秒内最多发送
+    /// `max_messages_per_sec` 条，排满时推迟到最早的一条满一秒之后
+    pub fn schedule(&mut self, connection: usize, now: Instant) -> Duration {
+        let limit = self.config.max_messages_per_sec.max(1);
+        let Some(c) = self.connections.get_mut(connection) else {
+            return Duration::ZERO;
+        };
+        let mut at = now.max(c.sent.back().copied().unwrap_or(now));
+        if c.sent.len() >= limit {
+            at = at.max(c.sent[c.sent.len() - limit] + Duration::from_secs(1));
+        }
+        c.sent.push_back(at);
+        while c.sent.len() > limit {
+            c.sent.pop_front();
+        }
+        let delay = at - now;
+        if !delay.is_zero() {
+            debug!(
+                "Market connection {} control message delayed {:?}, limit {} per second",
+                connection, delay, limit
+            );
+        }
+        delay
+    }
+
+    fn threshold(&self) -> f64 {
+        self.config.max_streams as f64 * self.config.warn_ratio
+    }
+
+    fn check_usage(&mut self, connection: usize) {
+        let threshold = self.threshold();
+        let max_streams = self.config.max_streams;
+        let c = &mut self.connections[connection];
+        if !c.warned && c.streams.len() as f64 >= threshold {
+            c.warned = true;
+            warn!(
+                "Market connection {} has {} of {} streams",
+                connection,
+                c.streams.len(),
+                max_streams
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_budget() {
+        let mut budget = StreamBudget::new(&StreamBudgetConfig {
+            max_streams: 2,
+            max_connections: 2,
+            max_messages_per_sec: 2,
+            ..Default::default()
+        });
+        let streams =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|s| s.to_string()).collect() };
+
+        let (assigned, rejected) = budget.assign(&streams(&["a", "b", "c"]));
+        assert_eq!(
+            assigned,
+            vec![(0, streams(&["a", "b"])), (1, streams(&["c"]))]
+        );
+        assert!(rejected.is_empty());
+        assert_eq!(budget.available(), 1);
+
+        // 已经分配的流不重复订阅，池满时拒绝
+        let (assigned, rejected) = budget.assign(&streams(&["a", "d", "e"]));
+        assert_eq!(assigned, vec![(1, streams(&["d"]))]);
+        assert_eq!(rejected, streams(&["e"]));
+        assert_eq!(budget.connection_of("d"), Some(1));
+
+        // 释放后空出的位置优先给主连接
+        assert_eq!(
+            budget.release(&streams(&["b", "d", "x"])),
+            vec![(0, streams(&["b", "x"])), (1, streams(&["d"]))]
+        );
+        let (assigned, _) = budget.assign(&streams(&["e"]));
+        assert_eq!(assigned, vec![(0, streams(&["e"]))]);
+
+        // 每秒最多两条，第三、四条推迟到一秒后，第五条推迟到两秒后
+        let now = Instant::now();
+        let delays: Vec<_> = (0..5).map(|_| budget.schedule(0, now)).collect();
+        let secs = Duration::from_secs;
+        assert_eq!(delays, vec![secs(0), secs(0), secs(1), secs(1), secs(2)]);
+        // 其他连接单独计数，空闲一段时间后不再推迟
+        assert_eq!(budget.schedule(1, now), Duration::ZERO);
+        assert_eq!(budget.schedule(0, now + secs(3)), Duration::ZERO);
+        assert_eq!(
+            budget.schedule(0, now + Duration::from_millis(3500)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            budget.schedule(0, now + Duration::from_millis(3600)),
+            Duration::from_millis(400)
+        );
+    }
+
+    #[test]
+    fn test_pool_url() {
+        assert_eq!(
+            pool_url("wss://stream.binance.com:9443/ws"),
+            "wss://stream.binance.com:9443/stream"
+        );
+        assert_eq!(
+            pool_url("wss://fstream.binance.com/stream"),
+            "wss://fstream.binance.com/stream"
+        );
+        assert_eq!(
+            pool_url("ws://127.0.0.1:9000"),
+            "ws://127.0.0.1:9000/stream"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! 行情连接的管理请求
+//!
+//! 行情连接除了 `SUBSCRIBE`/`UNSUBSCRIBE`，还支持 `SET_PROPERTY`、`GET_PROPERTY` 与 `LIST_SUBSCRIPTIONS`。
+//! `StreamGateway` 记录期望的连接属性（默认开启 combined，行情解析依赖 combined 格式），
+//! 每次建立连接后先于订阅重新设置，并按请求 id 跟踪交易所的确认；
+//! 应答的 `result` 并不是订阅应答的格式，需要在按 `Event` 解析之前交给 `on_response`。
+
+use crate::correlation::RequestIds;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// 行情连接的属性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamProperty {
+    /// 推送带上 `stream` 字段的 combined 格式
+    Combined,
+}
+
+impl StreamProperty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Combined => "combined",
+        }
+    }
+}
+
+/// 行情连接的管理请求
+#[derive(Debug, Clone, PartialEq)]
+pub enum GatewayCommand {
+    SetProperty(StreamProperty, bool),
+    GetProperty(StreamProperty),
+    ListSubscriptions,
+}
+
+impl GatewayCommand {
+    pub fn method(&self) -> &'static str {
+        match self {
+            Self::SetProperty(..) => "SET_PROPERTY",
+            Self::GetProperty(_) => "GET_PROPERTY",
+            Self::ListSubscriptions => "LIST_SUBSCRIPTIONS",
+        }
+    }
+
+    pub fn params(&self) -> Value {
+        match self {
+            Self::SetProperty(property, value) => serde_json::json!([property.name(), value]),
+            Self::GetProperty(property) => serde_json::json!([property.name()]),
+            Self::ListSubscriptions => serde_json::json!([]),
+        }
+    }
+}
+
+/// 交易所对管理请求的确认
+#[derive(Debug, Clone, PartialEq)]
+pub enum GatewayAck {
+    /// 设置或查询到的属性值
+    Property(StreamProperty, bool),
+    /// 连接上当前订阅的流
+    Subscriptions(Vec<String>),
+    /// 交易所拒绝了请求
+    Rejected(GatewayCommand, String),
+}
+
+/// 已发送的管理请求
+#[derive(Debug, Clone)]
+pub struct GatewayRequest {
+    pub id: i64,
+    pub command: GatewayCommand,
+}
+
+#[derive(Debug)]
+pub struct StreamGateway {
+    /// 期望的属性，每次连接后重新设置
+    properties: Vec<(StreamProperty, bool)>,
+    /// 交易所已确认的属性
+    acked: HashMap<StreamProperty, bool>,
+    /// 请求 id -> (请求, 发送时间)
+    pending: HashMap<i64, (GatewayCommand, Instant)>,
+    /// 最近一次 `LIST_SUBSCRIPTIONS` 的结果
+    subscriptions: Option<Vec<String>>,
+}
+
+impl Default for StreamGateway {
+    fn default() -> Self {
+        Self {
+            properties: vec![(StreamProperty::Combined, true)],
+            acked: HashMap::default(),
+            pending: HashMap::default(),
+            subscriptions: None,
+        }
+    }
+}
+
+impl StreamGateway {
+    /// 记录一个将要以 `id` 发送的请求，设置属性的同时更新期望值
+    pub fn request(&mut self, id: i64, command: GatewayCommand, now: Instant) -> GatewayRequest {
+        if let GatewayCommand::SetProperty(property, value) = &command {
+            match self.properties.iter_mut().find(|(p, _)| p == property) {
+                Some((_, v)) => *v = *value,
+                None => self.properties.push((*property, *value)),
+            }
+        }
+        self.pending.insert(id, (command.clone(), now));
+        GatewayRequest { id, command }
+    }
+
+    /// 新连接需要先发送的请求：重新设置所有属性，之前连接上的确认与未完成的请求作废
+    pub fn on_connect(&mut self, ids: &mut RequestIds, now: Instant) -> Vec<GatewayRequest> {
+        self.acked.clear();
+        self.pending.clear();
+        self.subscriptions = None;
+        self.properties
+            .clone()
+            .into_iter()
+            .map(|(property, value)| {
+                let id = ids.allocate().wire();
+                self.request(id, GatewayCommand::SetProperty(property, value), now)
+            })
+            .collect()
+    }
+
+    /// 交易所的应答，不是管理请求的应答时返回 None
+    pub fn on_response(&mut self, value: &Value) -> Option<GatewayAck> {
+        let id = value.get("id")?.as_i64()?;
+        let (command, sent) = self.pending.remove(&id)?;
+        info!(
+            "{} {} acknowledged in {}ms",
+            command.method(),
+            command.params(),
+            sent.elapsed().as_millis()
+        );
+        if value.get("result").is_none() {
+            let msg = value
+                .get("msg")
+                .or_else(|| value.pointer("/error/msg"))
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error")
+                .to_string();
+            warn!("{} rejected: {}", command.method(), msg);
+            return Some(GatewayAck::Rejected(command, msg));
+        }
+        let ack = match command {
+            GatewayCommand::SetProperty(property, value) => GatewayAck::Property(property, value),
+            GatewayCommand::GetProperty(property) => {
+                GatewayAck::Property(property, value["result"].as_bool().unwrap_or_default())
+            }
+            GatewayCommand::ListSubscriptions => {
+                let streams = serde_json::from_value(value["result"].clone()).unwrap_or_default();
+                self.subscriptions = Some(streams);
+                GatewayAck::Subscriptions(self.subscriptions.clone().unwrap_or_default())
+            }
+        };
+        if let GatewayAck::Property(property, value) = &ack {
+            self.acked.insert(*property, *value);
+        }
+        Some(ack)
+    }
+
+    /// 取出超时未确认的请求
+    pub fn expire(&mut self, now: Instant, timeout: Duration) -> Vec<GatewayRequest> {
+        let mut expired: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, sent))| now.duration_since(*sent) >= timeout)
+            .map(|(id, (command, _))| GatewayRequest {
+                id: *id,
+                command: command.clone(),
+            })
+            .collect();
+        expired.sort_unstable_by_key(|r| r.id);
+        for request in expired.iter() {
+            self.pending.remove(&request.id);
+        }
+        expired
+    }
+
+    /// 当前连接上交易所确认过的属性值
+    pub fn acked(&self, property: StreamProperty) -> Option<bool> {
+        self.acked.get(&property).copied()
+    }
+
+    /// 最近一次查询到的订阅，新连接上还没有查询时为空
+    pub fn subscriptions(&self) -> Option<&[String]> {
+        self.subscriptions.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_stream_gateway() {
+        let mut gateway = StreamGateway::default();
+        let now = Instant::now();
+        let mut ids = RequestIds::new(0);
+        let requests = gateway.on_connect(&mut ids, now);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].command.method(), "SET_PROPERTY");
+        assert_eq!(requests[0].command.params(), json!(["combined", true]));
+        let id = requests[0].id;
+        assert_eq!(gateway.acked(StreamProperty::Combined), None);
+
+        // 订阅的应答不经过这里
+        assert_eq!(gateway.on_response(&json!({"result": null, "id": 7})), None);
+        assert_eq!(
+            gateway.on_response(&json!({"result": null, "id": id})),
+            Some(GatewayAck::Property(StreamProperty::Combined, true))
+        );
+        assert_eq!(gateway.acked(StreamProperty::Combined), Some(true));
+
+        let list = gateway.request(2, GatewayCommand::ListSubscriptions, now);
+        assert_eq!(list.command.params(), json!([]));
+        let ack = gateway.on_response(&json!({"result": ["btcusdt@bookTicker"], "id": 2}));
+        assert_eq!(
+            ack,
+            Some(GatewayAck::Subscriptions(vec!["btcusdt@bookTicker".into()]))
+        );
+        assert_eq!(gateway.subscriptions().unwrap().len(), 1);
+
+        gateway.request(
+            3,
+            GatewayCommand::GetProperty(StreamProperty::Combined),
+            now,
+        );
+        let ack = gateway.on_response(&json!({"code": 0, "msg": "Unknown property", "id": 3}));
+        assert!(matches!(ack, Some(GatewayAck::Rejected(_, msg)) if msg == "Unknown property"));
+
+        // 修改后的属性在重连后重新设置
+        gateway.request(
+            4,
+            GatewayCommand::SetProperty(StreamProperty::Combined, false),
+            now,
+        );
+        assert!(gateway
+            .expire(now + Duration::from_secs(1), Duration::from_secs(10))
+            .is_empty());
+        let expired = gateway.expire(now + Duration::from_secs(10), Duration::from_secs(10));
+        assert_eq!(expired[0].id, 4);
+
+        let requests = gateway.on_connect(&mut ids, now);
+        assert_eq!(requests[0].command.params(), json!(["combined", false]));
+        assert_eq!(gateway.subscriptions(), None);
+    }
+}
@@ -1,21 +1,46 @@
-use cryptoflow::chat::{ErrorResponse, Response};
+use crate::wire::ClientSender;
+use cryptoflow::chat::{ErrorResponse, RawMode, Response, SResponse, SStreamResult};
 use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-use tokio::sync::mpsc::UnboundedSender;
-use tungstenite::Message;
+
+/// 订阅时附带的选项
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamOptions {
+    pub tag: Option<String>,
+    /// 深度的分档宽度，见 [`crate::dom`]
+    pub bucket: Option<f64>,
+    /// 是否转发交易所的原始帧
+    pub raw: RawMode,
+}
+
 pub struct Subscriber {
     symbols: HashSet<String>,
-    tx: UnboundedSender<Message>,
+    tx: ClientSender,
     /// 发送到交易所的请求id与策略放请求的映射
     exchange_reqid_to_client_reqid: HashMap<i64, i64>,
+    /// 订阅时附带的标签，转发该流的数据时带回
+    tags: HashMap<String, String>,
+    /// 订阅时指定的深度分档宽度
+    buckets: HashMap<String, f64>,
+    /// 订阅时要求转发原始帧的流
+    raws: HashMap<String, RawMode>,
+    /// 用连续合约别名订阅的流：交易所的流 -> 订阅时的流，转发时换回订阅时的流名
+    aliases: HashMap<String, String>,
+    /// 等待交易所确认的订阅：策略端请求 id -> (未确认的交易所请求数, 每个流的结果)
+    subscribe_results: HashMap<i64, (usize, Vec<SStreamResult>)>,
 }
 
 impl Subscriber {
-    pub fn new(tx: UnboundedSender<Message>) -> Self {
+    pub fn new(tx: ClientSender) -> Self {
         Self {
             symbols: HashSet::default(),
             tx,
             exchange_reqid_to_client_reqid: HashMap::default(),
+            tags: HashMap::default(),
+            buckets: HashMap::default(),
+            raws: HashMap::default(),
+            aliases: HashMap::default(),
+            subscribe_results: HashMap::default(),
         }
     }
 
@@ -25,17 +50,34 @@ impl Subscriber {
     ) -> anyhow::Result<()> {
         if let Some(client_req_id) = self.exchange_reqid_to_client_reqid.remove(&response.id) {
             response.id = client_req_id;
-            self.tx
-                .send(Message::Text(serde_json::to_string(&response)?.into()))?;
+            match self.subscribe_results.get_mut(&client_req_id) {
+                Some((waiting, _)) if *waiting > 1 => {
+                    *waiting -= 1;
+                    return Ok(());
+                }
+                Some(_) => {
+                    let (_, results) = self.subscribe_results.remove(&client_req_id).unwrap();
+                    let response = SResponse {
+                        id: client_req_id,
+                        result: results,
+                    };
+                    self.tx.reply(&response)?
+                }
+                None => self.tx.reply(&response)?,
+            }
         }
         Ok(())
     }
 
+    /// 订阅失败时整个请求回复交易所的错误，同一请求的其他交易所请求不再回复
     pub fn on_exchange_error(&mut self, mut response: ErrorResponse) -> anyhow::Result<()> {
         if let Some(client_req_id) = self.exchange_reqid_to_client_reqid.remove(&response.id) {
+            if self.subscribe_results.remove(&client_req_id).is_some() {
+                self.exchange_reqid_to_client_reqid
+                    .retain(|_, id| *id != client_req_id);
+            }
             response.id = client_req_id;
-            self.tx
-                .send(Message::Text(serde_json::to_string(&response)?.into()))?;
+            self.tx.reply(&response)?;
         }
         Ok(())
     }
@@ -46,9 +88,103 @@ impl Subscriber {
         client_req_id: i64,
         symbols: Vec<String>,
     ) {
+        self.on_exchange_request(exchange_req_id, client_req_id);
+        self.symbols.extend(symbols);
+    }
+
+    /// 记录代策略端发往交易所的请求，交易所应答时按策略端的请求 id 回复
+    pub fn on_exchange_request(&mut self, exchange_req_id: i64, client_req_id: i64) {
         self.exchange_reqid_to_client_reqid
             .insert(exchange_req_id, client_req_id);
-        self.symbols.extend(symbols);
+    }
+
+    /// 退订一条流，未订阅时返回 false
+    pub fn remove(&mut self, symbol: &str) -> bool {
+        self.tags.remove(symbol);
+        self.buckets.remove(symbol);
+        self.raws.remove(symbol);
+        self.aliases.remove(symbol);
+        self.symbols.remove(symbol)
+    }
+
+    /// 切换别名时直接登记新合约的流，不经过交易所的订阅确认
+    pub fn add(&mut self, symbol: &str) {
+        self.symbols.insert(symbol.to_string());
+    }
+
+    /// 记下交易所的流 `symbol` 是用别名的流 `alias` 订阅的
+    pub fn set_alias(&mut self, symbol: &str, alias: Option<String>) {
+        match alias {
+            Some(alias) => self.aliases.insert(symbol.to_string(), alias),
+            None => self.aliases.remove(symbol),
+        };
+    }
+
+    /// 用别名订阅的流当前对应的交易所的流
+    pub fn aliased(&self, alias: &str) -> Option<&String> {
+        self.aliases
+            .iter()
+            .find(|(_, a)| a.as_str() == alias)
+            .map(|(symbol, _)| symbol)
+    }
+
+    /// 交易所的流 -> 订阅时用的别名的流
+    pub fn aliases(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.aliases.iter()
+    }
+
+    /// 一条流当前的订阅选项
+    pub fn options(&self, symbol: &str) -> StreamOptions {
+        StreamOptions {
+            tag: self.tags.get(symbol).cloned(),
+            bucket: self.bucket(symbol),
+            raw: self.raw(symbol),
+        }
+    }
+
+    /// 订阅或退订请求发往了 `waiting` 个交易所请求，全部确认后回复每个流的结果
+    pub fn expect_subscribe_results(
+        &mut self,
+        client_req_id: i64,
+        waiting: usize,
+        results: Vec<SStreamResult>,
+    ) {
+        self.subscribe_results
+            .insert(client_req_id, (waiting, results));
+    }
+
+    /// 设置或清除一条流的标签
+    pub fn set_tag(&mut self, symbol: &str, tag: Option<String>) {
+        match tag {
+            Some(tag) => self.tags.insert(symbol.to_string(), tag),
+            None => self.tags.remove(symbol),
+        };
+    }
+
+    /// 按订阅选项设置标签、分档与原始帧，重复订阅时以最新的选项为准
+    pub fn set_options(&mut self, symbol: &str, options: StreamOptions) {
+        self.set_tag(symbol, options.tag);
+        match options.bucket {
+            Some(bucket) => self.buckets.insert(symbol.to_string(), bucket),
+            None => self.buckets.remove(symbol),
+        };
+        match options.raw {
+            RawMode::Off => self.raws.remove(symbol),
+            raw => self.raws.insert(symbol.to_string(), raw),
+        };
+    }
+
+    pub fn bucket(&self, symbol: &str) -> Option<f64> {
+        self.buckets.get(symbol).copied()
+    }
+
+    pub fn raw(&self, symbol: &str) -> RawMode {
+        self.raws.get(symbol).copied().unwrap_or_default()
+    }
+
+    /// 是否转发网关解析后的数据，只要原始帧的订阅不转发
+    pub fn wants_normalized(&self, symbol: &String) -> bool {
+        self.is_subscribed(symbol) && self.raw(symbol) != RawMode::Only
     }
 
     pub fn is_subscribed(&self, symbol: &String) -> bool {
@@ -57,11 +193,216 @@ impl Subscriber {
 
     pub fn forward_to_strategy_client(&self, data: &String) -> anyhow::Result<()> {
         tracing::info!("forward data: {:?}", data);
-        self.tx.send(Message::Text(data.clone().into()))?;
-        Ok(())
+        self.tx.push_lossy(data)
+    }
+
+    /// 转发一条流的数据，订阅时附带了标签的在数据中加上 `tag` 字段，用别名订阅的换回别名的流名
+    pub fn forward_stream(&self, symbol: &String, data: &String) -> anyhow::Result<()> {
+        let aliased;
+        let data = match self.aliases.get(symbol) {
+            Some(alias) => {
+                aliased = with_stream(data, alias)?;
+                &aliased
+            }
+            None => data,
+        };
+        match self.tags.get(symbol) {
+            Some(tag) => self.forward_to_strategy_client(&with_tag(data, tag)?),
+            None => self.forward_to_strategy_client(data),
+        }
+    }
+
+    /// 转发交易所的原始帧 `frame`，外面包上交易所 `venue` 与订阅时的流名，带上订阅的标签
+    pub fn forward_raw(&self, symbol: &String, venue: &str, frame: &str) -> anyhow::Result<()> {
+        let stream = self.aliases.get(symbol).unwrap_or(symbol);
+        let data = format!(
+            "{{\"venue\":{},\"stream\":{},\"raw\":{}}}",
+            serde_json::to_string(venue)?,
+            serde_json::to_string(stream)?,
+            frame
+        );
+        match self.tags.get(symbol) {
+            Some(tag) => self.forward_to_strategy_client(&with_tag(&data, tag)?),
+            None => self.forward_to_strategy_client(&data),
+        }
     }
 
     pub fn iter(&self) -> std::collections::hash_set::Iter<'_, std::string::String> {
         self.symbols.iter()
     }
 }
+
+fn with_tag(data: &str, tag: &str) -> anyhow::Result<String> {
+    insert_field(data, "tag", tag)
+}
+
+/// 替换数据中的 `stream` 字段，只有用别名订阅的流需要重新序列化
+fn with_stream(data: &str, stream: &str) -> anyhow::Result<String> {
+    let mut value: serde_json::Map<String, serde_json::Value> = serde_json::from_str(data)?;
+    value.insert("stream".into(), stream.into());
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// 在 JSON 对象的开头插入一个字段，避免为每个订阅者重新序列化
+pub(crate) fn insert_field<T: Serialize>(
+    data: &str,
+    key: &str,
+    value: T,
+) -> anyhow::Result<String> {
+    match data.strip_prefix('{') {
+        Some(rest) if rest.trim_start().starts_with('}') => Ok(format!(
+            "{{\"{}\":{}}}",
+            key,
+            serde_json::to_string(&value)?
+        )),
+        Some(rest) => Ok(format!(
+            "{{\"{}\":{},{}",
+            key,
+            serde_json::to_string(&value)?,
+            rest
+        )),
+        None => anyhow::bail!("Cannot add {} to non-object data {}", key, data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::Message;
+    use websocket::bounded_channel;
+
+    #[test]
+    fn test_forward_stream() {
+        let (tx, mut rx) = bounded_channel("subscriber", &Default::default());
+        let mut subscriber = Subscriber::new(tx.into());
+        let stream = "btcusdt@kline_1m".to_string();
+        let data = r#"{"symbol":"btcusdt"}"#.to_string();
+        subscriber.forward_stream(&stream, &data).unwrap();
+        assert_eq!(rx.try_recv().unwrap(), Message::Text(data.clone().into()));
+
+        subscriber.set_tag(&stream, Some("fast \"1m\"".into()));
+        subscriber.forward_stream(&stream, &data).unwrap();
+        let Message::Text(text) = rx.try_recv().unwrap() else {
+            panic!("not text");
+        };
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["tag"], "fast \"1m\"");
+        assert_eq!(value["symbol"], "btcusdt");
+        assert_eq!(with_tag("{}", "a").unwrap(), r#"{"tag":"a"}"#);
+
+        subscriber.set_tag(&stream, None);
+        subscriber.forward_stream(&stream, &data).unwrap();
+        assert_eq!(rx.try_recv().unwrap(), Message::Text(data.clone().into()));
+
+        // 用别名订阅的流换回别名的流名，合约仍是实际的合约
+        let data = r#"{"symbol":"btcusdt_261225","stream":"btcusdt_261225@kline:1m"}"#.to_string();
+        let stream = "btcusdt_261225@kline_1m".to_string();
+        subscriber.set_alias(&stream, Some("btcusdt_quarter@kline:1m".into()));
+        assert_eq!(
+            subscriber.aliased("btcusdt_quarter@kline:1m"),
+            Some(&stream)
+        );
+        subscriber.forward_stream(&stream, &data).unwrap();
+        let Message::Text(text) = rx.try_recv().unwrap() else {
+            panic!("not text");
+        };
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["stream"], "btcusdt_quarter@kline:1m");
+        assert_eq!(value["symbol"], "btcusdt_261225");
+        subscriber.remove(&stream);
+        assert!(subscriber.aliased("btcusdt_quarter@kline:1m").is_none());
+    }
+
+    #[test]
+    fn test_forward_raw() {
+        let (tx, mut rx) = bounded_channel("subscriber", &Default::default());
+        let mut subscriber = Subscriber::new(tx.into());
+        let stream = "btcusdt@depth5".to_string();
+        subscriber.add(&stream);
+        assert!(subscriber.wants_normalized(&stream));
+        subscriber.set_options(
+            &stream,
+            StreamOptions {
+                tag: Some("book".into()),
+                bucket: None,
+                raw: RawMode::Only,
+            },
+        );
+        assert!(!subscriber.wants_normalized(&stream));
+        assert_eq!(subscriber.options(&stream).raw, RawMode::Only);
+
+        let frame = r#"{"stream":"btcusdt@depth5","data":{"lastUpdateId":1,"bids":[["1.0","2"]]}}"#;
+        subscriber
+            .forward_raw(&stream, "binance-usdt", frame)
+            .unwrap();
+        let Message::Text(text) = rx.try_recv().unwrap() else {
+            panic!("not text");
+        };
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["tag"], "book");
+        assert_eq!(value["venue"], "binance-usdt");
+        assert_eq!(value["stream"], "btcusdt@depth5");
+        // 原样转发，价格仍是字符串
+        assert_eq!(value["raw"]["data"]["bids"][0][0], "1.0");
+
+        subscriber.set_options(&stream, StreamOptions::default());
+        assert!(subscriber.wants_normalized(&stream));
+        assert_eq!(subscriber.raw(&stream), RawMode::Off);
+    }
+
+    #[test]
+    fn test_subscribe_results() {
+        let (tx, mut rx) = bounded_channel("subscriber", &Default::default());
+        let mut subscriber = Subscriber::new(tx.into());
+        let error = cryptoflow::chat::SError {
+            code: -10003,
+            msg: "invalid symbol xxx@kline:1m".into(),
+        };
+        let results = vec![
+            SStreamResult::accepted("btcusdt@kline:1m"),
+            SStreamResult::rejected("xxx@kline:1m", error),
+            SStreamResult::accepted("btc-250627-100000-c@greeks"),
+        ];
+        subscriber.expect_subscribe_results(5, 2, results);
+        subscriber.on_strategy_client_subscribe(11, 5, vec![]);
+        subscriber.on_strategy_client_subscribe(12, 5, vec![]);
+
+        // 两个交易所请求都确认后才回复
+        let ok = |id| Response {
+            id,
+            result: None::<i64>,
+        };
+        subscriber.on_exchange_response(ok(12)).unwrap();
+        assert!(rx.try_recv().is_err());
+        subscriber.on_exchange_response(ok(11)).unwrap();
+        let Message::Text(text) = rx.try_recv().unwrap() else {
+            panic!("not text");
+        };
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["id"], 5);
+        assert_eq!(value["result"][0]["accepted"], true);
+        assert!(value["result"][0].get("error").is_none());
+        assert_eq!(value["result"][1]["error"]["code"], -10003);
+
+        // 交易所拒绝时回复错误，同一请求的另一个确认被忽略
+        subscriber.expect_subscribe_results(6, 2, vec![]);
+        subscriber.on_strategy_client_subscribe(13, 6, vec![]);
+        subscriber.on_strategy_client_subscribe(14, 6, vec![]);
+        let error = cryptoflow::chat::Error {
+            code: 2,
+            msg: "Invalid request".into(),
+        };
+        subscriber
+            .on_exchange_error(Response {
+                id: 13,
+                result: error,
+            })
+            .unwrap();
+        let Message::Text(text) = rx.try_recv().unwrap() else {
+            panic!("not text");
+        };
+        assert!(text.contains("Invalid request"));
+        subscriber.on_exchange_response(ok(14)).unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! 交易场所状态巡检
+//!
+//! 开启后网关按 `check_secs` 查询一次系统状态（`/sapi/v1/system/status`）与 exchangeInfo 中各交易对的
+//! 状态，与上一次比较，有变化时推送 [`SVenueStatus`] 给所有连接。第一次查询只记录交易对的状态，之后新上线的
+//! 交易对同样只记录；系统状态从 `NORMAL` 开始比较，启动时正在维护也会推送。
+//!
+//! 开启 `auto_halt` 时，交易对离开 `TRADING` 后网关按 [`crate::halt`] 暂停它的交易，`cancel` 时同时撤销
+//! 所有会话在该交易对上的挂单；回到 `TRADING` 后恢复。只恢复自动暂停的交易对，运维暂停的交易对仍需运维恢复。
+//!
+//! [`SVenueStatus`]: cryptoflow::chat::SVenueStatus
+
+use cryptoflow::chat::SVenueStatus;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// 交易对可以交易时的状态
+pub const TRADING: &str = "TRADING";
+
+const NORMAL: &str = "NORMAL";
+const MAINTENANCE: &str = "MAINTENANCE";
+
+/// ```json
+/// "venue_status": {
+///     "enabled": false,
+///     "check_secs": 60,
+///     "auto_halt": true,
+///     "cancel": false
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct VenueStatusConfig {
+    pub enabled: bool,
+    /// 两次查询的间隔
+    pub check_secs: u64,
+    /// 交易对离开 `TRADING` 时自动暂停交易，回到 `TRADING` 时恢复
+    pub auto_halt: bool,
+    /// 自动暂停时撤销所有会话在该交易对上的挂单
+    pub cancel: bool,
+}
+
+impl Default for VenueStatusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            check_secs: 60,
+            auto_halt: true,
+            cancel: false,
+        }
+    }
+}
+
+/// 查询的公开接口
+#[derive(Debug, Clone, Copy)]
+pub struct StatusUrls {
+    pub system_status: &'static str,
+    pub exchange_info: &'static str,
+}
+
+pub const SPOT: StatusUrls = StatusUrls {
+    system_status: "https://api.binance.com/sapi/v1/system/status",
+    exchange_info: "https://api.binance.com/api/v3/exchangeInfo",
+};
+
+pub const USDT: StatusUrls = StatusUrls {
+    system_status: "https://api.binance.com/sapi/v1/system/status",
+    exchange_info: "https://fapi.binance.com/fapi/v1/exchangeInfo",
+};
+
+#[derive(Debug, Default)]
+struct StatusBook {
+    /// 上一次的系统状态，还没有查询过时为 None
+    system: Option<String>,
+    /// 小写的交易对 -> 上一次的状态，还没有查询过时为 None
+    symbols: Option<HashMap<String, String>>,
+    /// 等待 handler 推送的变化
+    events: Vec<SVenueStatus>,
+}
+
+/// 巡检任务与 handler 共享
+#[derive(Debug, Clone, Default)]
+pub struct VenueStatus {
+    config: VenueStatusConfig,
+    venue: String,
+    book: Arc<Mutex<StatusBook>>,
+}
+
+impl VenueStatus {
+    pub fn new(config: VenueStatusConfig, venue: &str) -> Self {
+        Self {
+            config,
+            venue: venue.to_string(),
+            book: Arc::default(),
+        }
+    }
+
+    pub fn config(&self) -> &VenueStatusConfig {
+        &self.config
+    }
+
+    fn with_book<R>(&self, f: impl FnOnce(&mut StatusBook) -> R) -> R {
+        let mut book = self.book.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut book)
+    }
+
+    fn event(&self, symbol: Option<&str>, status: &str, previous: &str, time: i64) -> SVenueStatus {
+        SVenueStatus {
+            time,
+            venue: self.venue.clone(),
+            symbol: symbol.map(|s| s.to_string()),
+            status: status.to_string(),
+            previous: previous.to_string(),
+            msg: None,
+        }
+    }
+
+    /// 系统状态，`status` 为接口返回的 0（正常）或 1（维护）
+    pub fn on_system(&self, status: i64, msg: &str, time: i64) {
+        let status = if status == 0 { NORMAL } else { MAINTENANCE };
+        self.with_book(|book| {
+            let previous = book.system.replace(status.to_string());
+            let previous = previous.as_deref().unwrap_or(NORMAL);
+            if previous != status {
+                let mut event = self.event(None, status, previous, time);
+                event.msg = Some(msg.to_string()).filter(|m| !m.is_empty());
+                book.events.push(event);
+            }
+        })
+    }
+
+    /// 所有交易对的状态，交易对 -> 状态
+    pub fn on_symbols(&self, statuses: HashMap<String, String>, time: i64) {
+        self.with_book(|book| {
+            let Some(previous) = book.symbols.as_ref() else {
+                book.symbols = Some(statuses);
+                return;
+            };
+            let mut events: Vec<_> = statuses
+                .iter()
+                .filter_map(|(symbol, status)| {
+                    let previous = previous.get(symbol)?;
+                    (previous != status).then(|| self.event(Some(symbol), status, previous, time))
+                })
+                .collect();
+            events.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+            book.events.extend(events);
+            book.symbols = Some(statuses);
+        })
+    }
+
+    /// 取出等待推送的变化
+    pub fn take(&self) -> Vec<SVenueStatus> {
+        self.with_book(|book| std::mem::take(&mut book.events))
+    }
+
+    /// 查询一次系统状态，返回状态码与说明
+    pub async fn fetch_system(url: &str) -> anyhow::Result<(i64, String)> {
+        let value: Value = reqwest::get(url).await?.json().await?;
+        let status = value["status"]
+            .as_i64()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+        let msg = value["msg"].as_str().unwrap_or_default().to_string();
+        Ok((status, msg))
+    }
+
+    /// 查询一次 exchangeInfo 中各交易对的状态
+    pub async fn fetch_symbols(url: &str) -> anyhow::Result<HashMap<String, String>> {
+        let value: Value = reqwest::get(url).await?.json().await?;
+        symbol_statuses(&value)
+    }
+
+    /// 按 `check_secs` 定时查询，没有开启时不做任何事
+    pub fn spawn_refresh(&self, urls: StatusUrls) {
+        if !self.config.enabled {
+            return;
+        }
+        let status = self.clone();
+        let period = Duration::from_secs(self.config.check_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let now = chrono::Utc::now().timestamp_millis();
+                match Self::fetch_system(urls.system_status).await {
+                    Ok((code, msg)) => status.on_system(code, &msg, now),
+                    Err(e) => warn!("Query system status failed: {}", e),
+                }
+                match Self::fetch_symbols(urls.exchange_info).await {
+                    Ok(symbols) => {
+                        debug!("{} symbol statuses", symbols.len());
+                        status.on_symbols(symbols, now)
+                    }
+                    Err(e) => warn!("Query symbol statuses failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// exchangeInfo 中的交易对 -> 状态，合约的状态字段为 `contractStatus` 或 `status`
+fn symbol_statuses(value: &Value) -> anyhow::Result<HashMap<String, String>> {
+    let symbols = value["symbols"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("exchangeInfo without symbols"))?;
+    Ok(symbols
+        .iter()
+        .filter_map(|s| {
+            let symbol = s["symbol"].as_str()?.to_lowercase();
+            let status = s["status"].as_str().or(s["contractStatus"].as_str())?;
+            Some((symbol, status.to_string()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_venue_status() {
+        let status = VenueStatus::new(VenueStatusConfig::default(), "binance-spot");
+        let info = serde_json::json!({"symbols": [
+            {"symbol": "BTCUSDT", "status": "TRADING"},
+            {"symbol": "ETHUSDT", "status": "TRADING"},
+            {"symbol": "LUNAUSDT", "status": "BREAK"},
+        ]});
+        // 第一次查询只记录
+        status.on_symbols(symbol_statuses(&info).unwrap(), 1000);
+        status.on_system(0, "normal", 1000);
+        assert!(status.take().is_empty());
+
+        let info = serde_json::json!({"symbols": [
+            {"symbol": "BTCUSDT", "status": "TRADING"},
+            {"symbol": "ETHUSDT", "status": "HALT"},
+            {"symbol": "LUNAUSDT", "status": "TRADING"},
+            {"symbol": "NEWUSDT", "status": "PRE_TRADING"},
+        ]});
+        status.on_symbols(symbol_statuses(&info).unwrap(), 2000);
+        status.on_system(1, "system maintenance", 2000);
+        let events = status.take();
+        let changes: Vec<_> = events
+            .iter()
+            .map(|e| (e.symbol.as_deref(), e.previous.as_str(), e.status.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (Some("ethusdt"), "TRADING", "HALT"),
+                (Some("lunausdt"), "BREAK", "TRADING"),
+                (None, "NORMAL", "MAINTENANCE"),
+            ]
+        );
+        assert_eq!(events[2].msg.as_deref(), Some("system maintenance"));
+        assert_eq!(events[0].venue, "binance-spot");
+
+        // 没有变化时不推送
+        status.on_system(1, "system maintenance", 3000);
+        assert!(status.take().is_empty());
+        assert!(symbol_statuses(&serde_json::json!({})).is_err());
+    }
+}
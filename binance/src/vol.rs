@@ -0,0 +1,264 @@
+//! 波动率估计
+//!
+//! 网关为每条订阅的 K 线流保留最近 `bars` 根收线的 K 线，收线时更新三种年化波动率：收盘价对数收益率的
+//! 均方根（realized）、按最高最低价的 Parkinson 估计，以及按 `lambda` 衰减的 EWMA。策略用
+//! `get_volatility` 查询；开启 `publish` 时每根 K 线收线后以 `{symbol}@vol:{window}` 推送给该 K 线流的
+//! 订阅者。所有策略拿到同一组数字，按波动率缩放的仓位上限等风控口径一致。按一年 365 天年化。
+
+use crate::bar;
+use cryptoflow::chat::{SGeneralKline, SVolatility};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+const YEAR_MS: f64 = 365.0 * 24.0 * 3600.0 * 1000.0;
+
+/// ```json
+/// "volatility": {
+///     "enabled": true,
+///     "bars": 30,
+///     "lambda": 0.94,
+///     "publish": true
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct VolatilityConfig {
+    pub enabled: bool,
+    /// realized 与 Parkinson 使用的 K 线数
+    pub bars: usize,
+    /// EWMA 的衰减系数，越大越平滑
+    pub lambda: f64,
+    /// 收线时推送给 K 线流的订阅者
+    pub publish: bool,
+}
+
+impl Default for VolatilityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bars: 30,
+            lambda: 0.94,
+            publish: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bar {
+    time: i64,
+    high: f64,
+    low: f64,
+    /// 相对上一根收盘价的对数收益率，第一根为空
+    ret: Option<f64>,
+}
+
+#[derive(Debug)]
+struct Estimator {
+    symbol: String,
+    window: String,
+    /// 年化系数的平方，即一年的 K 线数
+    bars_per_year: f64,
+    bars: VecDeque<Bar>,
+    last_start: i64,
+    last_close: Option<f64>,
+    ewma: Option<f64>,
+}
+
+impl Estimator {
+    fn on_bar(&mut self, kline: &SGeneralKline, config: &VolatilityConfig) {
+        // 重连补齐与交易所重复推送的收线 K 线只计一次
+        if kline.start_time <= self.last_start || kline.close <= 0.0 {
+            return;
+        }
+        self.last_start = kline.start_time;
+        let ret = self.last_close.map(|last| (kline.close / last).ln());
+        self.last_close = Some(kline.close);
+        if let Some(ret) = ret {
+            self.ewma = Some(match self.ewma {
+                Some(var) => config.lambda * var + (1.0 - config.lambda) * ret * ret,
+                None => ret * ret,
+            });
+        }
+        self.bars.push_back(Bar {
+            time: kline.time,
+            high: kline.high,
+            low: kline.low,
+            ret,
+        });
+        while self.bars.len() > config.bars.max(1) {
+            self.bars.pop_front();
+        }
+    }
+
+    fn annualize(&self, var: f64) -> f64 {
+        (var * self.bars_per_year).sqrt()
+    }
+
+    fn estimate(&self) -> SVolatility {
+        let returns: Vec<f64> = self.bars.iter().filter_map(|b| b.ret).collect();
+        let realized = (!returns.is_empty()).then(|| {
+            self.annualize(returns.iter().map(|r| r * r).sum::<f64>() / returns.len() as f64)
+        });
+        let ranges: Vec<f64> = self
+            .bars
+            .iter()
+            .filter(|b| b.low > 0.0 && b.high >= b.low)
+            .map(|b| (b.high / b.low).ln().powi(2))
+            .collect();
+        let parkinson = (!ranges.is_empty()).then(|| {
+            let n = ranges.len() as f64;
+            self.annualize(ranges.iter().sum::<f64>() / (4.0 * n * 2f64.ln()))
+        });
+        SVolatility {
+            time: self.bars.back().map(|b| b.time).unwrap_or_default(),
+            symbol: self.symbol.clone(),
+            stream: format!("{}@vol:{}", self.symbol, self.window),
+            window: self.window.clone(),
+            bars: self.bars.len(),
+            realized,
+            parkinson,
+            ewma: self.ewma.map(|var| self.annualize(var)),
+        }
+    }
+}
+
+/// 按 K 线流名称（如 `btcusdt@kline_1m`）维护的波动率估计
+#[derive(Debug, Default)]
+pub struct Volatility {
+    config: VolatilityConfig,
+    estimators: HashMap<String, Estimator>,
+}
+
+impl Volatility {
+    pub fn new(config: &VolatilityConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn estimator(&mut self, stream: &str) -> Option<&mut Estimator> {
+        if !self.estimators.contains_key(stream) {
+            let (symbol, window) = stream.split_once("@kline_")?;
+            let (length, _) = bar::interval_ms(window)?;
+            self.estimators.insert(
+                stream.to_string(),
+                Estimator {
+                    symbol: symbol.to_string(),
+                    window: window.to_string(),
+                    bars_per_year: YEAR_MS / length as f64,
+                    bars: VecDeque::new(),
+                    last_start: i64::MIN,
+                    last_close: None,
+                    ewma: None,
+                },
+            );
+        }
+        self.estimators.get_mut(stream)
+    }
+
+    /// 收线的 K 线更新估计，开启推送时返回要推送的结果
+    pub fn on_kline(&mut self, stream: &str, kline: &SGeneralKline) -> Option<SVolatility> {
+        if !self.config.enabled || !kline.is_closed {
+            return None;
+        }
+        let config = self.config.clone();
+        let estimator = self.estimator(stream)?;
+        estimator.on_bar(kline, &config);
+        config.publish.then(|| estimator.estimate())
+    }
+
+    /// 一条 K 线流当前的估计，不是 K 线流或周期不支持时为 None
+    pub fn estimate(&mut self, stream: &str) -> Option<SVolatility> {
+        self.estimator(stream).map(|e| e.estimate())
+    }
+
+    pub fn untrack(&mut self, stream: &str) {
+        self.estimators.remove(stream);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(start: i64, high: f64, low: f64, close: f64) -> SGeneralKline {
+        SGeneralKline {
+            time: start + 59_999,
+            start_time: start,
+            symbol: "BTCUSDT".into(),
+            stream: "btcusdt@kline_1m".into(),
+            interval: "1m".into(),
+            open: close,
+            high,
+            low,
+            close,
+            volume: 1.0,
+            amount: close,
+            first_trade_id: 0,
+            last_trade_id: 0,
+            trade_count: 1,
+            is_closed: true,
+            buy_volume: 0.5,
+            buy_amount: close / 2.0,
+            backfill: false,
+        }
+    }
+
+    #[test]
+    fn test_volatility() {
+        let config = VolatilityConfig {
+            enabled: true,
+            bars: 2,
+            lambda: 0.5,
+            publish: true,
+        };
+        let mut vol = Volatility::new(&config);
+        let stream = "btcusdt@kline_1m";
+        let first = vol.on_kline(stream, &kline(0, 101.0, 99.0, 100.0)).unwrap();
+        assert_eq!((first.bars, first.realized, first.ewma), (1, None, None));
+        assert!(first.parkinson.is_some());
+
+        let up = (110.0f64 / 100.0).ln();
+        let down = (99.0f64 / 110.0).ln();
+        vol.on_kline(stream, &kline(60_000, 110.0, 100.0, 110.0));
+        // 重复的收线与未收线的 K 线不计入
+        assert!(vol
+            .on_kline(stream, &kline(60_000, 120.0, 90.0, 120.0))
+            .is_some());
+        let mut open = kline(120_000, 110.0, 99.0, 99.0);
+        open.is_closed = false;
+        assert!(vol.on_kline(stream, &open).is_none());
+        let v = vol
+            .on_kline(stream, &kline(120_000, 110.0, 99.0, 99.0))
+            .unwrap();
+
+        let year = 365.0 * 24.0 * 60.0;
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert_eq!(
+            (v.bars, v.time, v.stream.as_str()),
+            (2, 179_999, "btcusdt@vol:1m")
+        );
+        assert!(close(
+            v.realized.unwrap(),
+            ((up * up + down * down) / 2.0 * year).sqrt()
+        ));
+        let ranges = (110.0f64 / 100.0).ln().powi(2) + (110.0f64 / 99.0).ln().powi(2);
+        assert!(close(
+            v.parkinson.unwrap(),
+            (ranges / (8.0 * 2f64.ln()) * year).sqrt()
+        ));
+        assert!(close(
+            v.ewma.unwrap(),
+            ((0.5 * up * up + 0.5 * down * down) * year).sqrt()
+        ));
+
+        assert!(vol.estimate("btcusdt@kline_1M").is_none());
+        assert_eq!(vol.estimate("btcusdt@kline_5m").unwrap().bars, 0);
+        assert!(!Volatility::default().enabled());
+    }
+}
@@ -0,0 +1,357 @@
+//! 过期订单巡检
+//!
+//! 下单响应丢失或回报漏收时，网关与策略端都不知道交易所上还挂着订单。开启后网关按 `check_secs`
+//! 查询一次当前挂单，与本地记录的订单核对：
+//!
+//! - 挂在交易所、超过 `stale_ms` 没有下单、回报或改单的订单，撤单并告警
+//! - 挂在交易所但网关没有记录的订单（如网关重启前留下的），撤单并告警
+//! - 网关记录仍未完成、超过 `stale_ms` 没有回报且交易所已没有的订单，不再跟踪并告警
+//!
+//! `cancel` 为 false 时只告警不撤单。只核对网关下单格式的 clientOrderId，手工下的订单不受影响。
+
+use crate::rest::Rest;
+use cryptoflow::chat::State;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// ```json
+/// "watchdog": {
+///     "enabled": false,
+///     "stale_ms": 60000,
+///     "check_secs": 15,
+///     "cancel": true
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    pub enabled: bool,
+    /// 订单超过这个时间没有任何更新视为过期
+    pub stale_ms: u64,
+    /// 查询挂单的间隔
+    pub check_secs: u64,
+    /// 撤销过期订单，false 时只告警
+    pub cancel: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stale_ms: 60000,
+            check_secs: 15,
+            cancel: true,
+        }
+    }
+}
+
+/// 交易所上的一笔挂单
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenOrder {
+    pub session_id: u16,
+    pub order_id: u32,
+    pub symbol: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaleReason {
+    /// 超过 `stale_ms` 没有更新
+    Idle,
+    /// 网关没有记录
+    Unknown,
+    /// 交易所已没有这笔订单，结果未知
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleOrder {
+    pub session_id: u16,
+    pub order_id: u32,
+    pub symbol: String,
+    pub reason: StaleReason,
+}
+
+impl StaleOrder {
+    /// 还挂在交易所上，可以撤单
+    pub fn open(&self) -> bool {
+        self.reason != StaleReason::Missing
+    }
+}
+
+impl fmt::Display for StaleOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            StaleReason::Idle => "is open without updates",
+            StaleReason::Unknown => "is open but unknown to gateway",
+            StaleReason::Missing => "is not open on exchange and no report arrived",
+        };
+        write!(
+            f,
+            "Order {} {} of session {} {}",
+            self.order_id, self.symbol, self.session_id, reason
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Watched {
+    /// 尚未终结的订单 -> (symbol, 最近一次下单、回报或改单的时间)
+    orders: HashMap<(u16, u32), (String, Instant)>,
+    /// 最近终结的订单，避免查询之后才终结的订单被当作没有记录
+    finished: HashMap<(u16, u32), Instant>,
+    /// 已发出撤单的过期订单，`stale_ms` 内不重复处理
+    canceling: HashMap<(u16, u32), Instant>,
+    /// 尚未核对的挂单与查询时间
+    snapshot: Option<(Instant, Vec<OpenOrder>)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OrderWatchdog {
+    config: WatchdogConfig,
+    watched: Arc<Mutex<Watched>>,
+}
+
+impl OrderWatchdog {
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self {
+            config,
+            watched: Arc::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 是否撤销过期订单
+    pub fn cancels(&self) -> bool {
+        self.config.cancel
+    }
+
+    fn with_watched<R>(&self, f: impl FnOnce(&mut Watched) -> R) -> R {
+        let mut watched = self.watched.lock().unwrap_or_else(|p| p.into_inner());
+        f(&mut watched)
+    }
+
+    /// 订单已发往交易所
+    pub fn on_sent(&self, session_id: u16, order_id: u32, symbol: &str, now: Instant) {
+        if !self.enabled() {
+            return;
+        }
+        self.with_watched(|watched| {
+            watched
+                .orders
+                .insert((session_id, order_id), (symbol.to_string(), now));
+        });
+    }
+
+    /// 交易所的订单回报，未完成的订单刷新时间，终结的订单不再跟踪
+    pub fn on_update(
+        &self,
+        session_id: u16,
+        order_id: u32,
+        symbol: &str,
+        state: State,
+        now: Instant,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        let key = (session_id, order_id);
+        self.with_watched(|watched| {
+            if state.is_working() {
+                watched.orders.insert(key, (symbol.to_string(), now));
+            } else {
+                watched.orders.remove(&key);
+                watched.canceling.remove(&key);
+                watched.finished.insert(key, now);
+            }
+        });
+    }
+
+    /// 下单被拒绝，订单没有挂出
+    pub fn on_reject(&self, session_id: u16, order_id: u32) {
+        if !self.enabled() {
+            return;
+        }
+        self.with_watched(|watched| watched.orders.remove(&(session_id, order_id)));
+    }
+
+    /// 记下查询到的挂单，`fetched` 为发出查询的时间
+    pub fn on_snapshot(&self, open: Vec<OpenOrder>, fetched: Instant) {
+        self.with_watched(|watched| watched.snapshot = Some((fetched, open)));
+    }
+
+    /// 用最近一次查询到的挂单核对本地记录，没有新的挂单快照时返回空
+    pub fn check(&self, now: Instant) -> Vec<StaleOrder> {
+        let stale_after = Duration::from_millis(self.config.stale_ms);
+        self.with_watched(|watched| {
+            let Some((fetched, open)) = watched.snapshot.take() else {
+                return Vec::new();
+            };
+            watched
+                .finished
+                .retain(|_, t| now.saturating_duration_since(*t) < stale_after);
+            watched
+                .canceling
+                .retain(|_, t| now.saturating_duration_since(*t) < stale_after);
+
+            let mut stale = Vec::new();
+            let mut seen = HashSet::new();
+            for order in open {
+                let key = (order.session_id, order.order_id);
+                seen.insert(key);
+                if watched.canceling.contains_key(&key) || watched.finished.contains_key(&key) {
+                    continue;
+                }
+                let reason = match watched.orders.get(&key) {
+                    Some((_, last)) if fetched.saturating_duration_since(*last) >= stale_after => {
+                        StaleReason::Idle
+                    }
+                    Some(_) => continue,
+                    None => StaleReason::Unknown,
+                };
+                watched.canceling.insert(key, now);
+                stale.push(StaleOrder {
+                    session_id: order.session_id,
+                    order_id: order.order_id,
+                    symbol: order.symbol,
+                    reason,
+                });
+            }
+
+            let missing: Vec<_> = watched
+                .orders
+                .iter()
+                .filter(|(key, (_, last))| {
+                    !seen.contains(key) && fetched.saturating_duration_since(*last) >= stale_after
+                })
+                .map(|(key, (symbol, _))| (*key, symbol.clone()))
+                .collect();
+            for ((session_id, order_id), symbol) in missing {
+                watched.orders.remove(&(session_id, order_id));
+                stale.push(StaleOrder {
+                    session_id,
+                    order_id,
+                    symbol,
+                    reason: StaleReason::Missing,
+                });
+            }
+            stale
+        })
+    }
+
+    /// 查询一次当前挂单，`path` 为现货、杠杆或合约的 openOrders 接口
+    pub async fn fetch(rest: &Rest, path: &str) -> anyhow::Result<Vec<OpenOrder>> {
+        let rsp = rest.get(path, &[], true).await?;
+        let value: Value = serde_json::from_str(&rsp.text().await?)?;
+        let orders = value
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+        Ok(orders.iter().filter_map(open_order).collect())
+    }
+
+    /// 按 `check_secs` 定时查询挂单
+    pub fn spawn_refresh(&self, rest: Arc<Rest>, path: &'static str) {
+        let watchdog = self.clone();
+        let period = Duration::from_secs(self.config.check_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let fetched = Instant::now();
+                match Self::fetch(&rest, path).await {
+                    Ok(open) => {
+                        debug!("{} open orders", open.len());
+                        watchdog.on_snapshot(open, fetched);
+                    }
+                    Err(e) => warn!("Query open orders failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// 网关下单格式的挂单，clientOrderId 为 session_id 与订单号拼成的整数
+fn open_order(value: &Value) -> Option<OpenOrder> {
+    let client_order_id = value["clientOrderId"].as_str()?.parse::<u64>().ok()?;
+    Some(OpenOrder {
+        session_id: (client_order_id >> 32) as u16,
+        order_id: (client_order_id & 0xFFFFFFFF) as u32,
+        symbol: value["symbol"].as_str()?.to_lowercase(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        let watchdog = OrderWatchdog::new(WatchdogConfig {
+            enabled: true,
+            stale_ms: 1000,
+            ..Default::default()
+        });
+        let rsp: Value = serde_json::from_str(
+            r#"[{"symbol":"BTCUSDT","clientOrderId":"4294967297"},{"symbol":"BTCUSDT","clientOrderId":"web_abc"}]"#,
+        )
+        .unwrap();
+        let open: Vec<_> = rsp
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(open_order)
+            .collect();
+        assert_eq!(
+            open,
+            vec![OpenOrder {
+                session_id: 1,
+                order_id: 1,
+                symbol: "btcusdt".into()
+            }]
+        );
+
+        let t0 = Instant::now();
+        let order = |session_id, order_id| OpenOrder {
+            session_id,
+            order_id,
+            symbol: "btcusdt".into(),
+        };
+        // 1 的下单响应与回报都丢失，2 持续有回报，3 已成交但回报丢失，4 不是本次运行下的
+        for id in 1..=3 {
+            watchdog.on_sent(1, id, "btcusdt", t0);
+        }
+        let t1 = t0 + Duration::from_millis(1500);
+        watchdog.on_update(1, 2, "btcusdt", State::PARTIALLY_FILLED, t1);
+        assert!(watchdog.check(t1).is_empty());
+
+        watchdog.on_snapshot(vec![order(1, 1), order(1, 2), order(1, 4)], t1);
+        let stale = watchdog.check(t1);
+        let reasons: Vec<_> = stale.iter().map(|s| (s.order_id, s.reason)).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, StaleReason::Idle),
+                (4, StaleReason::Unknown),
+                (3, StaleReason::Missing)
+            ]
+        );
+        assert!(!stale[2].open());
+
+        // 已发出撤单的订单不重复处理，查询之后才终结的订单不算没有记录
+        watchdog.on_update(1, 2, "btcusdt", State::FILLED, t1);
+        watchdog.on_snapshot(vec![order(1, 1), order(1, 2)], t1);
+        assert!(watchdog.check(t1).is_empty());
+
+        let disabled = OrderWatchdog::default();
+        disabled.on_sent(1, 1, "btcusdt", t0);
+        disabled.on_snapshot(Vec::new(), t1);
+        assert!(disabled.check(t1).is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+//! 策略端连接的编码协商
+//!
+//! 网关按连接协商的编码（见 [`cryptoflow::codec`]）直接编解码：请求由 [`cryptoflow::parser::RequestParser`]
+//! 解码为请求类型，回复与推送经 [`ClientSender::reply`] 序列化。`login` 请求中的 `format` 不是网关开启的
+//! 编码时改为 `json`，回复中的 `format` 即为采用的编码；登录成功的回复以 JSON 发出后，这条连接之后的消息
+//! 都按协商的编码以二进制帧收发。行情流的数据按 JSON 生成一次，供所有订阅者与回放共用，发往二进制连接时
+//! 由 [`ClientSender::push_lossy`] 转换编码。
+//!
+//! 登录请求中的 `version` 同时确定连接的协议版本，版本较低的连接按 [`cryptoflow::compat`] 转换回复与推送。
+
+use cryptoflow::codec::{Codec, WireFormat};
+use cryptoflow::compat::{self, Shim, Shimmed};
+use serde::Serialize;
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+use tungstenite::Message;
+use websocket::{BoundedReceiver, BoundedSender};
+
+/// 新连接：地址、发往策略端的通道与来自策略端的通道
+pub type Connection = (SocketAddr, ClientSender, BoundedReceiver<Message>);
+
+/// 连接协商的编码，同一连接的 [`ClientSender`] 与 [`WireState`] 共用
+#[derive(Debug, Clone, Default)]
+pub struct SharedFormat(Arc<AtomicU8>);
+
+impl SharedFormat {
+    pub fn get(&self) -> WireFormat {
+        match self.0.load(Ordering::Relaxed) {
+            1 => WireFormat::Msgpack,
+            2 => WireFormat::Cbor,
+            _ => WireFormat::Json,
+        }
+    }
+
+    pub fn set(&self, format: WireFormat) {
+        self.0.store(format as u8, Ordering::Relaxed);
+    }
+}
+
+/// 按编码把一条回复或推送序列化为一帧，JSON 为文本帧，其余为二进制帧
+pub fn frame<T: Serialize>(format: WireFormat, value: &T) -> anyhow::Result<Message> {
+    match format {
+        WireFormat::Json => Ok(Message::Text(serde_json::to_string(value)?.into())),
+        format => Ok(Message::Binary(format.encode(value)?.into())),
+    }
+}
+
+/// 发往策略端的通道，网关各处持有的克隆共用连接协商的编码
+#[derive(Debug, Clone)]
+pub struct ClientSender {
+    tx: BoundedSender<Message>,
+    format: SharedFormat,
+}
+
+impl ClientSender {
+    pub fn new(tx: BoundedSender<Message>, format: SharedFormat) -> Self {
+        Self { tx, format }
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format.get()
+    }
+
+    /// 登录成功的回复进入队列后切换编码，之后的消息都按新的编码发出
+    pub fn set_format(&self, format: WireFormat) {
+        info!("Switch to {:?} after login", format);
+        self.format.set(format);
+    }
+
+    /// 回复与推送，按协商的编码直接序列化，队列满时放弃这条通道
+    pub fn reply<T: Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        Ok(self.tx.send(frame(self.format(), value)?)?)
+    }
+
+    /// 按 JSON 生成一次的行情数据，队列满时按通道的 `overflow` 处理
+    pub fn push_lossy(&self, data: &str) -> anyhow::Result<()> {
+        let msg = match self.format() {
+            WireFormat::Json => Message::Text(data.into()),
+            format => Message::Binary(format.encode(&serde_json::from_str::<Value>(data)?)?.into()),
+        };
+        Ok(self.tx.send_lossy(msg)?)
+    }
+}
+
+impl Deref for ClientSender {
+    type Target = BoundedSender<Message>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
+}
+
+impl From<BoundedSender<Message>> for ClientSender {
+    fn from(tx: BoundedSender<Message>) -> Self {
+        Self::new(tx, SharedFormat::default())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WireState {
+    format: SharedFormat,
+    shim: Shim,
+}
+
+impl WireState {
+    pub fn new(format: SharedFormat) -> Self {
+        Self {
+            format,
+            shim: Shim::default(),
+        }
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format.get()
+    }
+
+    /// 连接的协议版本
+    pub fn version(&self) -> u32 {
+        self.shim.version()
+    }
+
+    /// 策略端发来的消息，JSON 的登录请求改写协商的编码与版本，其余原样交给网关解码
+    pub fn on_client(&mut self, msg: Message) -> anyhow::Result<Message> {
+        let msg = match msg {
+            Message::Text(text) if self.format().is_json() && text.contains("\"login\"") => {
+                Message::Text(self.negotiate(&text)?.into())
+            }
+            msg => msg,
+        };
+        if !self.shim.current() {
+            match &msg {
+                Message::Text(text) => self.shim.on_request(&serde_json::from_str(text)?),
+                Message::Binary(data) => self.shim.on_request(&self.format().decode(data)?),
+                _ => (),
+            }
+        }
+        Ok(msg)
+    }
+
+    /// 发给策略端的消息，版本较低的连接按版本转换；连接的版本不支持的推送返回 None
+    pub fn on_server(&mut self, msg: Message) -> anyhow::Result<Option<Message>> {
+        if self.shim.current() {
+            return Ok(Some(msg));
+        }
+        let (mut value, format): (Value, _) = match &msg {
+            Message::Text(text) => (serde_json::from_str(text)?, WireFormat::Json),
+            Message::Binary(data) => (self.format().decode(data)?, self.format()),
+            _ => return Ok(Some(msg)),
+        };
+        match self.shim.on_message(&mut value) {
+            Shimmed::Keep => Ok(Some(msg)),
+            Shimmed::Rewritten => Ok(Some(frame(format, &value)?)),
+            Shimmed::Drop => Ok(None),
+        }
+    }
+
+    fn negotiate(&mut self, text: &str) -> anyhow::Result<String> {
+        let mut value: Value = serde_json::from_str(text)?;
+        if value["method"] != "login" {
+            return Ok(text.to_string());
+        }
+        if value["params"].get("format").is_some() {
+            let requested = value["params"]["format"].as_str().unwrap_or("json");
+            let format = WireFormat::parse(requested).unwrap_or_else(|| {
+                warn!("Unsupported format {}, use json", requested);
+                WireFormat::Json
+            });
+            value["params"]["format"] = serde_json::to_value(format)?;
+        }
+        // 登录的回复带上协商的版本，老客户端不带 version 时回复也不带
+        let requested = value["params"]["version"].as_u64();
+        let version = compat::negotiate(requested.map(|v| v.min(u32::MAX as u64) as u32));
+        if version != self.shim.version() {
+            info!("Use protocol v{}", version);
+        }
+        self.shim = Shim::new(version);
+        if requested.is_some() {
+            value["params"]["version"] = version.into();
+        }
+        Ok(serde_json::to_string(&value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::{SBarClose, SLogin, SRequest, SResponse};
+    use cryptoflow::parser::RequestParser;
+    use websocket::bounded_channel;
+
+    fn text(msg: &Message) -> String {
+        match msg {
+            Message::Text(text) => text.to_string(),
+            _ => panic!("not text: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_wire_state() {
+        let format = SharedFormat::default();
+        let mut wire = WireState::new(format.clone());
+        let products = r#"{"id":1,"method":"get_products","params":[]}"#;
+        assert_eq!(
+            text(&wire.on_client(Message::Text(products.into())).unwrap()),
+            products
+        );
+
+        // 未开启或未知的编码改为 json
+        let login =
+            r#"{"id":2,"method":"login","params":{"session_id":1,"trading":true,"format":"xml"}}"#;
+        let forwarded = text(&wire.on_client(Message::Text(login.into())).unwrap());
+        assert!(forwarded.contains(r#""format":"json""#));
+
+        let login = r#"{"id":3,"method":"login","params":{"session_id":1,"trading":true,"format":"msgpack","version":2}}"#;
+        let forwarded = text(&wire.on_client(Message::Text(login.into())).unwrap());
+        let req: SRequest<SLogin> = serde_json::from_str(&forwarded).unwrap();
+        assert_eq!(req.params.format, WireFormat::Msgpack);
+
+        // 登录成功的回复以 JSON 发出后切换，之后的回复直接按 MessagePack 序列化
+        let (tx, mut rx) = bounded_channel("wire", &Default::default());
+        let tx = ClientSender::new(tx, format);
+        tx.reply(&SResponse {
+            id: 3,
+            result: req.params.clone(),
+        })
+        .unwrap();
+        tx.set_format(req.params.format);
+        assert_eq!(wire.format(), WireFormat::Msgpack);
+        tx.reply(&SResponse { id: 4, result: 1.5 }).unwrap();
+        let reply = wire.on_server(rx.try_recv().unwrap()).unwrap().unwrap();
+        assert!(text(&reply).contains(r#""format":"msgpack""#));
+        let Some(Message::Binary(data)) = wire.on_server(rx.try_recv().unwrap()).unwrap() else {
+            panic!("not binary");
+        };
+        let rsp: SResponse<f64> = WireFormat::Msgpack.decode(&data).unwrap();
+        assert_eq!((rsp.id, rsp.result), (4, 1.5));
+
+        // 按 JSON 生成的行情数据在发出时转换编码
+        tx.push_lossy(r#"{"symbol":"btcusdt","close":1.5}"#)
+            .unwrap();
+        let Ok(Message::Binary(data)) = rx.try_recv() else {
+            panic!("not binary");
+        };
+        let value: Value = WireFormat::Msgpack.decode(&data).unwrap();
+        assert_eq!(value["close"], 1.5);
+
+        // 二进制的请求原样交给网关，按协商的编码直接解码为请求类型
+        let cancel = serde_json::json!({"id": 5, "method": "cancel", "params": {"order_id": 7}});
+        let data = WireFormat::Msgpack.encode(&cancel).unwrap();
+        let Message::Binary(data) = wire.on_client(Message::Binary(data.into())).unwrap() else {
+            panic!("not binary");
+        };
+        let parser = RequestParser::new(&data, tx.format()).unwrap();
+        assert_eq!((parser.id(), parser.method()), (Some(5), Some("cancel")));
+        assert_eq!(wire.version(), compat::PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_legacy_binary() {
+        let format = SharedFormat::default();
+        let mut wire = WireState::new(format.clone());
+        let login =
+            r#"{"id":1,"method":"login","params":{"session_id":1,"trading":true,"format":"cbor"}}"#;
+        wire.on_client(Message::Text(login.into())).unwrap();
+        assert_eq!(wire.version(), compat::LEGACY_VERSION);
+        format.set(WireFormat::Cbor);
+
+        // 版本较低的连接按版本转换二进制帧，不支持的推送不发
+        let close = SBarClose {
+            bar_close: 1672515840000,
+            symbol: "btcusdt".into(),
+            stream: "btcusdt@kline:1m".into(),
+            interval: "1m".into(),
+            kline: None,
+        };
+        let msg = frame(WireFormat::Cbor, &close).unwrap();
+        assert_eq!(wire.on_server(msg).unwrap(), None);
+        let msg = frame(WireFormat::Cbor, &SResponse { id: 2, result: 0 }).unwrap();
+        assert_eq!(wire.on_server(msg.clone()).unwrap(), Some(msg));
+    }
+}
@@ -0,0 +1,288 @@
+//! 通过 WS-API 下单与撤单
+//!
+//! 合约 WS-API 连接在 session.logon 之后复用同一条连接收发 `order.place` / `order.cancel`，
+//! 省去每笔 REST 请求的建连与签名开销。连接不可用（未登录、断开或请求超时）时调用方退回 REST，
+//! 两条路径的往返延迟分别统计，便于比较。
+
+use crate::market::LatencyStats;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::Receiver;
+use tracing::{error, info, warn};
+use websocket::{BinanceWsApiWebsocketClient, Credentials};
+
+/// ```json
+/// "wsapi": {
+///     "enabled": true,
+///     "url": "wss://ws-fapi.binance.com/ws-fapi/v1",
+///     "timeout_ms": 5000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WsApiConfig {
+    pub enabled: bool,
+    pub url: String,
+    /// 请求超过这么久没有响应，认为连接不可用并退回 REST
+    pub timeout_ms: u64,
+}
+
+impl Default for WsApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "wss://ws-fapi.binance.com/ws-fapi/v1".into(),
+            timeout_ms: 5000,
+        }
+    }
+}
+
+/// REST 与 WS-API 下单撤单的往返延迟
+#[derive(Debug, Clone, Default)]
+pub struct OrderLatency {
+    rest: Arc<Mutex<LatencyStats>>,
+    wsapi: Arc<Mutex<LatencyStats>>,
+}
+
+impl OrderLatency {
+    pub fn record_rest(&self, ms: u64) {
+        self.rest
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .record(ms);
+    }
+
+    pub fn record_wsapi(&self, ms: u64) {
+        self.wsapi
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .record(ms);
+    }
+
+    /// (REST, WS-API)
+    pub fn snapshot(&self) -> (LatencyStats, LatencyStats) {
+        let rest = self.rest.lock().unwrap_or_else(|p| p.into_inner()).clone();
+        let wsapi = self.wsapi.lock().unwrap_or_else(|p| p.into_inner()).clone();
+        (rest, wsapi)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WsApiEvent<P> {
+    /// 交易所接受，附带响应中的 `result`
+    Accepted(P, Value),
+    /// 交易所拒绝，附带错误信息
+    Rejected(P, String),
+    /// 超时未响应，请求可能已经生效
+    TimedOut(P),
+}
+
+#[derive(Debug, Deserialize)]
+struct WsApiError {
+    code: i64,
+    msg: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WsApiResponse {
+    id: Value,
+    status: u16,
+    error: Option<WsApiError>,
+    #[serde(default)]
+    result: Value,
+}
+
+/// 等待响应的请求，`P` 为调用方在响应到达时需要的上下文
+#[derive(Debug)]
+pub struct WsApiRequests<P> {
+    timeout: Duration,
+    logged_on: bool,
+    next_id: i64,
+    pending: HashMap<i64, (Instant, P)>,
+}
+
+impl<P> WsApiRequests<P> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            logged_on: false,
+            next_id: 1,
+            pending: HashMap::default(),
+        }
+    }
+
+    pub fn logged_on(&self) -> bool {
+        self.logged_on
+    }
+
+    fn insert(&mut self, payload: P, now: Instant) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, (now, payload));
+        id
+    }
+
+    /// 处理一条响应，登录响应更新登录状态，其余返回对应请求的结果与延迟
+    fn on_message(&mut self, msg: Value, now: Instant) -> Option<(WsApiEvent<P>, u64)> {
+        let rsp: WsApiResponse = match serde_json::from_value(msg) {
+            Ok(rsp) => rsp,
+            Err(e) => {
+                warn!("Unknown wsapi message: {}", e);
+                return None;
+            }
+        };
+        let Some(id) = rsp.id.as_i64() else {
+            // session.logon 使用字符串 id
+            self.logged_on = rsp.status == 200;
+            match &rsp.error {
+                Some(e) => error!("WS-API logon failed: {} {}", e.code, e.msg),
+                None => info!("WS-API logged on"),
+            }
+            return None;
+        };
+
+        let (sent, payload) = self.pending.remove(&id)?;
+        let ms = now.duration_since(sent).as_millis() as u64;
+        let event = match rsp.error {
+            Some(e) if rsp.status != 200 => {
+                WsApiEvent::Rejected(payload, format!("{} {}", e.code, e.msg))
+            }
+            _ => WsApiEvent::Accepted(payload, rsp.result),
+        };
+        Some((event, ms))
+    }
+
+    /// 取出超时的请求，有超时说明连接已不可用
+    fn expire(&mut self, now: Instant) -> Vec<P> {
+        let expired: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, (sent, _))| now.duration_since(*sent) >= self.timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        if !expired.is_empty() {
+            self.logged_on = false;
+        }
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id).map(|(_, payload)| payload))
+            .collect()
+    }
+}
+
+pub struct WsApiOrders<P> {
+    client: BinanceWsApiWebsocketClient,
+    rx: Receiver<Value>,
+    requests: WsApiRequests<P>,
+    latency: OrderLatency,
+}
+
+impl<P> WsApiOrders<P> {
+    /// 建立连接并发送 session.logon，登录响应到达前 `available` 为 false
+    pub async fn connect(
+        config: &WsApiConfig,
+        credentials: &Credentials,
+        latency: OrderLatency,
+    ) -> anyhow::Result<Self> {
+        let mut client = BinanceWsApiWebsocketClient::new_private("wsapi", credentials.clone());
+        client.set_url(&config.url);
+        let rx = client
+            .connect()
+            .await
+            .map_err(|e| anyhow::anyhow!("WS-API 连接失败: {}", e))?;
+        Ok(Self {
+            client,
+            rx,
+            requests: WsApiRequests::new(Duration::from_millis(config.timeout_ms)),
+            latency,
+        })
+    }
+
+    pub fn available(&self) -> bool {
+        self.requests.logged_on()
+    }
+
+    /// 发送签名请求，响应通过 `poll` 取回
+    pub fn send(&mut self, method: &str, params: Map<String, Value>, payload: P) {
+        let id = self.requests.insert(payload, Instant::now());
+        let client = self.client.clone();
+        let method = method.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = client.wsapi_call_signed(&method, params, id).await {
+                error!("{}", e);
+            }
+        });
+    }
+
+    /// 取回已到达的响应与超时的请求
+    pub fn poll(&mut self) -> Vec<WsApiEvent<P>> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => {
+                    if let Some((event, ms)) = self.requests.on_message(msg, now) {
+                        self.latency.record_wsapi(ms);
+                        events.push(event);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.requests.logged_on() {
+                        warn!("WS-API disconnected, fall back to REST");
+                    }
+                    self.requests.logged_on = false;
+                    break;
+                }
+            }
+        }
+        let expired = self.requests.expire(now);
+        if !expired.is_empty() {
+            warn!(
+                "{} WS-API requests timed out, fall back to REST",
+                expired.len()
+            );
+        }
+        events.extend(expired.into_iter().map(WsApiEvent::TimedOut));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_wsapi_requests() {
+        let mut requests = WsApiRequests::new(Duration::from_secs(5));
+        let now = Instant::now();
+        let logon = json!({"id": "session_logon_1", "status": 200, "result": {}});
+        assert!(requests.on_message(logon, now).is_none());
+        assert!(requests.logged_on());
+
+        let place = requests.insert("place", now);
+        let cancel = requests.insert("cancel", now);
+        let later = now + Duration::from_millis(20);
+        let ok = json!({"id": place, "status": 200, "result": {}});
+        assert_eq!(
+            requests.on_message(ok, later),
+            Some((WsApiEvent::Accepted("place", json!({})), 20))
+        );
+        let rej = json!({"id": cancel, "status": 400, "error": {"code": -2011, "msg": "Unknown order sent."}});
+        match requests.on_message(rej, later) {
+            Some((WsApiEvent::Rejected("cancel", msg), _)) => assert!(msg.contains("-2011")),
+            other => panic!("{:?}", other),
+        }
+
+        // 超时后不再使用 WS-API
+        requests.insert("slow", now);
+        assert!(requests.expire(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(requests.expire(now + Duration::from_secs(5)), vec!["slow"]);
+        assert!(!requests.logged_on());
+    }
+}
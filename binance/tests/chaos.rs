@@ -0,0 +1,313 @@
+//! 交易所故障下网关的恢复
+//!
+//! 网关连接本地模拟的交易所，通过 `websocket::chaos` 在网关收到的帧上注入丢帧、延迟、损坏的 JSON
+//! 与断线，检查行情重连后补订阅、订阅查询缺少的流被补订阅，以及 WS-API 响应超时后订单不会重复下单。
+
+use binance::market::Market;
+use binance::wsapi::{OrderLatency, WsApiConfig, WsApiEvent, WsApiOrders};
+use binance::StreamOptions;
+use cryptoflow::chat::{SLogin, SRequest, SStreamResult};
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::EncodePrivateKey;
+use ed25519_dalek::SigningKey;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::{sleep, timeout, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use websocket::chaos::{Chaos, Fault};
+use websocket::{bounded_channel, BoundedReceiver, Credentials};
+
+const STREAM: &str = "btcusdt@bookTicker";
+
+#[derive(Default)]
+struct Connection {
+    requests: Vec<Value>,
+    subscriptions: Vec<String>,
+    push: Option<UnboundedSender<String>>,
+}
+
+#[derive(Default)]
+struct State {
+    connections: Vec<Connection>,
+    /// 订阅查询返回空，模拟交易所丢失了订阅
+    forget: bool,
+}
+
+/// 模拟的交易所，应答行情的管理请求与 WS-API 请求
+#[derive(Clone)]
+struct FakeExchange {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeExchange {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let exchange = Self {
+            url: format!("ws://{}", listener.local_addr().unwrap()),
+            state: Arc::default(),
+        };
+        let state = exchange.state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+                    continue;
+                };
+                let (tx, mut rx) = unbounded_channel();
+                let index = {
+                    let mut state = state.lock().unwrap();
+                    state.connections.push(Connection {
+                        push: Some(tx),
+                        ..Default::default()
+                    });
+                    state.connections.len() - 1
+                };
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let (mut write, mut read) = ws.split();
+                    loop {
+                        let text = tokio::select! {
+                            Some(text) = rx.recv() => text,
+                            msg = read.next() => match msg {
+                                Some(Ok(Message::Text(text))) => {
+                                    let request: Value = serde_json::from_str(&text).unwrap();
+                                    reply(&mut state.lock().unwrap(), index, request).to_string()
+                                }
+                                Some(Ok(_)) => continue,
+                                _ => break,
+                            },
+                        };
+                        if write.send(Message::Text(text.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        exchange
+    }
+
+    fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections.len()
+    }
+
+    /// 第 `index` 条连接收到的某个方法的请求
+    fn requests(&self, index: usize, method: &str) -> Vec<Value> {
+        let state = self.state.lock().unwrap();
+        state.connections[index]
+            .requests
+            .iter()
+            .filter(|r| r["method"] == method)
+            .cloned()
+            .collect()
+    }
+
+    fn forget(&self, forget: bool) {
+        self.state.lock().unwrap().forget = forget;
+    }
+
+    /// 在最新的连接上推送 bookTicker
+    fn push_book_ticker(&self, bid: u32) {
+        let data = json!({
+            "stream": STREAM,
+            "data": {"s": "BTCUSDT", "b": bid.to_string(), "B": "1", "a": "100", "A": "1", "E": 1}
+        });
+        let state = self.state.lock().unwrap();
+        let push = state.connections.last().and_then(|c| c.push.as_ref());
+        push.unwrap().send(data.to_string()).unwrap();
+    }
+}
+
+fn reply(state: &mut State, index: usize, request: Value) -> Value {
+    let forget = state.forget;
+    let connection = &mut state.connections[index];
+    connection.requests.push(request.clone());
+    let id = request["id"].clone();
+    match request["method"].as_str().unwrap_or_default() {
+        "SUBSCRIBE" => {
+            for stream in request["params"].as_array().into_iter().flatten() {
+                connection
+                    .subscriptions
+                    .push(stream.as_str().unwrap().to_string());
+            }
+            json!({"result": null, "id": id})
+        }
+        "LIST_SUBSCRIPTIONS" => {
+            let streams = if forget {
+                Vec::new()
+            } else {
+                connection.subscriptions.clone()
+            };
+            json!({"result": streams, "id": id})
+        }
+        "session.logon" => json!({"id": id, "status": 200, "result": {}}),
+        "order.place" => json!({"id": id, "status": 200, "result": {"orderId": 1}}),
+        _ => json!({"result": null, "id": id}),
+    }
+}
+
+/// 驱动网关处理交易所的消息，收集策略端收到的消息，直到满足 `done`
+async fn pump(
+    market: &mut Market,
+    rx: &mut BoundedReceiver<Message>,
+    received: &mut Vec<Value>,
+    done: impl Fn(&[Value]) -> bool,
+) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done(received) {
+        assert!(
+            Instant::now() < deadline,
+            "timed out, received {:?}",
+            received
+        );
+        let _ = timeout(Duration::from_millis(50), market.process()).await;
+        while let Ok(msg) = rx.try_recv() {
+            if let Message::Text(text) = msg {
+                received.push(serde_json::from_str(&text).unwrap());
+            }
+        }
+    }
+}
+
+fn bids(received: &[Value]) -> Vec<(u64, u32)> {
+    received
+        .iter()
+        .filter(|v| v["stream"] == STREAM)
+        .map(|v| {
+            let bid = v["data"]["b"].as_str().unwrap().parse().unwrap();
+            (v["offset"].as_u64().unwrap(), bid)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_market_recovers_from_faults() {
+    let exchange = FakeExchange::start().await;
+    let mut market = Market::connect_to(Some(&exchange.url)).await.unwrap();
+    let chaos = Chaos::get("market");
+
+    let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+    let (tx, mut rx) = bounded_channel("strategy", &Default::default());
+    market.handle_strategy_client_connect(&addr, &tx.into());
+    let login = SRequest {
+        id: 1,
+        method: "login".into(),
+        params: SLogin {
+            session_id: 1,
+            name: None,
+            trading: false,
+            cancel_on_disconnect: false,
+            fills: false,
+            format: Default::default(),
+            heartbeat_ms: None,
+            tenant: None,
+            token: None,
+            version: None,
+        },
+    };
+    market.handle_strategy_client_login(&addr, &login).unwrap();
+    let mut subscribe = SRequest {
+        id: 2,
+        method: "subscribe".into(),
+        params: vec![STREAM.to_string()],
+    };
+    market
+        .handle_strategy_client_subscribe(
+            &addr,
+            &mut subscribe,
+            &[StreamOptions::default()],
+            vec![SStreamResult::accepted(STREAM)],
+        )
+        .await
+        .unwrap();
+    let mut received = Vec::new();
+    pump(&mut market, &mut rx, &mut received, |r| {
+        r.iter().any(|v| v["id"] == 2)
+    })
+    .await;
+
+    // 丢帧、损坏的帧被跳过，延迟的帧按顺序送达
+    exchange.push_book_ticker(1);
+    pump(&mut market, &mut rx, &mut received, |r| bids(r).len() == 1).await;
+    chaos.inject(Fault::Drop);
+    chaos.inject(Fault::Corrupt);
+    chaos.inject(Fault::Delay(Duration::from_millis(200)));
+    for bid in 2..=5 {
+        exchange.push_book_ticker(bid);
+    }
+    pump(&mut market, &mut rx, &mut received, |r| bids(r).len() == 3).await;
+
+    // 断线后重连并补订阅，只订阅一次
+    chaos.inject(Fault::Disconnect);
+    exchange.push_book_ticker(6);
+    pump(&mut market, &mut rx, &mut received, |_| {
+        exchange.connections() == 2 && !exchange.requests(1, "LIST_SUBSCRIPTIONS").is_empty()
+    })
+    .await;
+    let resubscribed = exchange.requests(1, "SUBSCRIBE");
+    assert_eq!(resubscribed.len(), 1);
+    assert_eq!(resubscribed[0]["params"], json!([STREAM]));
+    assert!(!market.disconnected());
+
+    exchange.push_book_ticker(7);
+    pump(&mut market, &mut rx, &mut received, |r| bids(r).len() == 4).await;
+    let bids = bids(&received);
+    assert_eq!(
+        bids.iter().map(|(_, bid)| *bid).collect::<Vec<_>>(),
+        vec![1, 4, 5, 7]
+    );
+    assert!(bids.windows(2).all(|w| w[0].0 < w[1].0));
+    assert_eq!(chaos.pending(), 0);
+
+    // 交易所丢失订阅时，订阅查询后补订阅缺少的流
+    exchange.forget(true);
+    market.list_subscriptions().await.unwrap();
+    pump(&mut market, &mut rx, &mut received, |_| {
+        exchange.requests(1, "SUBSCRIBE").len() == 2
+    })
+    .await;
+    assert_eq!(
+        exchange.requests(1, "SUBSCRIBE")[1]["params"],
+        json!([STREAM])
+    );
+}
+
+#[tokio::test]
+async fn test_wsapi_timeout_does_not_duplicate_orders() {
+    let exchange = FakeExchange::start().await;
+    let pem = SigningKey::from_bytes(&[7; 32])
+        .to_pkcs8_pem(LineEnding::LF)
+        .unwrap()
+        .to_string();
+    let credentials = Credentials::new("key".into(), pem, String::new(), "0");
+    let config = WsApiConfig {
+        enabled: true,
+        url: exchange.url.clone(),
+        timeout_ms: 200,
+    };
+    let mut orders = WsApiOrders::connect(&config, &credentials, OrderLatency::default())
+        .await
+        .unwrap();
+    assert!(orders.poll().is_empty());
+    assert!(orders.available());
+
+    // 响应晚于超时到达：请求超时后连接不可用，迟到的响应被忽略
+    let chaos = Chaos::get("wsapi");
+    chaos.inject(Fault::Delay(Duration::from_millis(600)));
+    let mut params = Map::new();
+    params.insert("symbol".into(), json!("BTCUSDT"));
+    orders.send("order.place", params, 1);
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(orders.poll(), vec![WsApiEvent::TimedOut(1)]);
+    assert!(!orders.available());
+
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(chaos.pending(), 0);
+    assert!(orders.poll().is_empty());
+    assert_eq!(exchange.requests(0, "order.place").len(), 1);
+}
@@ -0,0 +1,249 @@
+//! 老版本客户端的兼容
+//!
+//! `data/pyalgo_v1.jsonl` 是 v1 的 pyalgo 连接老网关时录下的流量：`send` 为客户端发出的请求，`recv` 为
+//! 老网关的回复与推送，`exchange` 为同一时刻交易所推送的帧。把录下的请求经 [`WireState`] 重放给当前的
+//! 网关，网关发出的每条消息都要能按 v1 客户端的类型解码，且与老网关发出的消息一致。
+
+use binance::market::Market;
+use binance::wire::WireState;
+use binance::StreamOptions;
+use cryptoflow::chat::{
+    SBarClose, SDelivery, SLogin, SRequest, SRoll, SStreamResult, SSubscription, SSymbolChanged,
+};
+use cryptoflow::compat::{LEGACY_VERSION, PROTOCOL_VERSION};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::{timeout, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use websocket::{bounded_channel, BoundedReceiver};
+
+/// v1 的 pyalgo 解码网关消息的类型，按顺序尝试，多出的字段忽略，缺少字段时解码失败
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum LegacyMessage {
+    Success { id: i64, result: Option<u8> },
+    Login { id: i64, result: LegacyLogin },
+    Error { id: i64, result: LegacyError },
+    Kline(LegacyKline),
+    Position { symbol: String, net: f64 },
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct LegacyLogin {
+    session_id: u16,
+    name: Option<String>,
+    trading: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+struct LegacyError {
+    code: i32,
+    msg: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct LegacyKline {
+    time: u64,
+    start_time: u64,
+    symbol: String,
+    interval: String,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    amount: f64,
+    first_trade_id: i64,
+    last_trade_id: i64,
+    trade_count: i64,
+    is_closed: bool,
+    buy_volume: f64,
+    buy_amount: f64,
+}
+
+/// 应答所有请求的模拟交易所，通过返回的 sender 推送帧
+async fn fake_exchange() -> (String, UnboundedSender<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (push, mut rx) = unbounded_channel::<String>();
+    tokio::spawn(async move {
+        let Ok((stream, _)) = listener.accept().await else {
+            return;
+        };
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        loop {
+            let text = tokio::select! {
+                Some(text) = rx.recv() => text,
+                msg = ws.next() => match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let request: Value = serde_json::from_str(&text).unwrap();
+                        json!({"result": null, "id": request["id"]}).to_string()
+                    }
+                    Some(Ok(_)) => continue,
+                    _ => break,
+                },
+            };
+            if ws.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+    });
+    (url, push)
+}
+
+/// 驱动网关直到收到一条发给客户端的消息，经 `wire` 转换后返回，被丢弃的消息跳过
+async fn next_message(
+    market: &mut Market,
+    rx: &mut BoundedReceiver<Message>,
+    wire: &mut WireState,
+) -> Value {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        assert!(Instant::now() < deadline, "timed out");
+        while let Ok(msg) = rx.try_recv() {
+            if let Some(Message::Text(text)) = wire.on_server(msg).unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+        let _ = timeout(Duration::from_millis(50), market.process()).await;
+    }
+}
+
+fn legacy(value: &Value) -> LegacyMessage {
+    serde_json::from_value(value.clone())
+        .unwrap_or_else(|e| panic!("v1 client can not decode {}: {}", value, e))
+}
+
+#[tokio::test]
+async fn test_replay_v1_client() {
+    let (url, exchange) = fake_exchange().await;
+    let mut market = Market::connect_to(Some(&url)).await.unwrap();
+    let addr: SocketAddr = "127.0.0.1:9100".parse().unwrap();
+    let (tx, mut rx) = bounded_channel("strategy", &Default::default());
+    market.handle_strategy_client_connect(&addr, &tx.into());
+    let mut wire = WireState::default();
+
+    let capture = include_str!("data/pyalgo_v1.jsonl");
+    for line in capture.lines().filter(|l| !l.trim().is_empty()) {
+        let line: Value = serde_json::from_str(line).unwrap();
+        if let Some(request) = line.get("send") {
+            let msg = wire
+                .on_client(Message::Text(request.to_string().into()))
+                .unwrap();
+            let Message::Text(text) = msg else {
+                panic!("not text: {:?}", msg);
+            };
+            // 老客户端的请求按当前的类型解码，缺少的字段取默认值
+            match request["method"].as_str().unwrap() {
+                "login" => {
+                    let login: SRequest<SLogin> = serde_json::from_str(&text).unwrap();
+                    assert_eq!(login.params.version, None);
+                    assert!(!login.params.cancel_on_disconnect);
+                    market.handle_strategy_client_login(&addr, &login).unwrap();
+                }
+                "subscribe" => {
+                    let req: SRequest<Vec<SSubscription>> = serde_json::from_str(&text).unwrap();
+                    let streams: Vec<String> =
+                        req.params.iter().map(|s| s.stream().clone()).collect();
+                    let results: Vec<_> =
+                        streams.iter().map(|s| SStreamResult::accepted(s)).collect();
+                    let options = vec![StreamOptions::default(); streams.len()];
+                    let mut req = SRequest {
+                        id: req.id,
+                        method: req.method,
+                        params: streams,
+                    };
+                    market
+                        .handle_strategy_client_subscribe(&addr, &mut req, &options, results)
+                        .await
+                        .unwrap();
+                }
+                method => panic!("unexpected method {}", method),
+            }
+        } else if let Some(frame) = line.get("exchange") {
+            exchange.send(frame.to_string()).unwrap();
+        } else if let Some(expected) = line.get("recv") {
+            let received = next_message(&mut market, &mut rx, &mut wire).await;
+            match (legacy(&received), legacy(expected)) {
+                (LegacyMessage::Success { id, result }, LegacyMessage::Success { id: e, .. }) => {
+                    assert_eq!((id, result), (e, None));
+                }
+                (
+                    LegacyMessage::Login { id, result },
+                    LegacyMessage::Login { id: e, result: r },
+                ) => {
+                    assert_eq!((id, result), (e, r));
+                }
+                (LegacyMessage::Kline(kline), LegacyMessage::Kline(e)) => assert_eq!(kline, e),
+                (received, expected) => {
+                    panic!("received {:?}, expected {:?}", received, expected)
+                }
+            }
+        }
+    }
+    assert_eq!(wire.version(), LEGACY_VERSION);
+}
+
+#[test]
+fn test_v1_client_skips_new_pushes() {
+    let pushes = [
+        serde_json::to_value(SBarClose {
+            bar_close: 1672515840000,
+            symbol: "btcusdt".into(),
+            stream: "btcusdt@kline:1m".into(),
+            interval: "1m".into(),
+            kline: None,
+        })
+        .unwrap(),
+        serde_json::to_value(SDelivery {
+            symbol: "btcusdt_261225".into(),
+            delivery_time: 1798185600000,
+            remaining_ms: 600000,
+            position: 0.1,
+            settled: false,
+        })
+        .unwrap(),
+        serde_json::to_value(SRoll {
+            alias: "btcusdt_quarter".into(),
+            contract: "btcusdt_261225".into(),
+            next: None,
+            delivery_time: 1798185600000,
+            remaining_ms: 600000,
+        })
+        .unwrap(),
+        serde_json::to_value(SSymbolChanged {
+            time: 1798185600000,
+            alias: "btcusdt_quarter".into(),
+            stream: "btcusdt_quarter@kline:1m".into(),
+            previous: "btcusdt_261225".into(),
+            symbol: "btcusdt_270326".into(),
+        })
+        .unwrap(),
+    ];
+    let login = |version: Option<u32>| {
+        let mut wire = WireState::default();
+        let mut params = json!({"session_id": 1, "trading": false});
+        if let Some(version) = version {
+            params["version"] = version.into();
+        }
+        let login = json!({"id": 0, "method": "login", "params": params});
+        wire.on_client(Message::Text(login.to_string().into()))
+            .unwrap();
+        wire
+    };
+
+    let mut v1 = login(None);
+    let mut current = login(Some(PROTOCOL_VERSION));
+    for push in pushes {
+        let msg = || Message::Text(push.to_string().into());
+        assert_eq!(v1.on_server(msg()).unwrap(), None, "{}", push);
+        assert!(current.on_server(msg()).unwrap().is_some(), "{}", push);
+    }
+}
@@ -0,0 +1,272 @@
+//! 网关的运维子命令：校验配置、探测连通性、导出交易规则
+
+use crate::rest::Rest;
+use crate::Config;
+use binance::apikey::{check_api_key, ApiKeyConfig, Permission, API_RESTRICTIONS_URI};
+use binance::capability::Capability;
+use binance::credential::{Credential, CredentialConfig};
+use binance::market::Market;
+use binance::model::symbol::BinanceSymbol;
+use binance::wsapi::WsApiOrders;
+use clap::Subcommand;
+use cryptoflow::alert::Alerter;
+use cryptoflow::chat::SError;
+use cryptoflow::trading_rules::TradingRules;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use websocket::Credentials;
+
+pub const FAPI_URI: &str = "https://fapi.binance.com";
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Run the gateway (default)")]
+    Run,
+    #[command(about = "Validate the config and API key without trading")]
+    CheckConfig,
+    #[command(about = "Test REST and websocket connectivity and latency")]
+    Probe {
+        #[arg(short = 'n', long, default_value_t = 5, help = "REST requests to send")]
+        count: usize,
+    },
+    #[command(about = "Print trading rules from exchangeInfo as csv")]
+    Products {
+        #[arg(help = "Only print these symbols")]
+        symbols: Vec<String>,
+    },
+}
+
+pub fn load_credential(config: &Config) -> anyhow::Result<Credential> {
+    CredentialConfig::or_plain(config.credential.clone(), &config.apikey, &config.pem).load()
+}
+
+pub fn rest(
+    uri: &str,
+    credential: &Credential,
+    capability: Capability,
+) -> anyhow::Result<Arc<Rest>> {
+    Ok(Arc::new(
+        Rest::from_pem(uri, &credential.apikey, credential.pem.as_bytes(), 3000)?
+            .with_capability(capability),
+    ))
+}
+
+pub async fn check_config(config: &Config) -> anyhow::Result<()> {
+    println!("Config parsed, listen on {}", config.local);
+    binance::flatten::Flatten::new(&config.flatten)?;
+    let credential = load_credential(config)?;
+    println!("Credential {:?} loaded", credential);
+
+    let restrictions = rest(API_RESTRICTIONS_URI, &credential, config.capability)?;
+    let api_key = ApiKeyConfig {
+        enabled: true,
+        check_secs: 0,
+        ..config.api_key.clone()
+    };
+    check_api_key(
+        restrictions,
+        &config
+            .capability
+            .permissions(vec![Permission::Reading, Permission::Futures]),
+        api_key,
+        Alerter::default(),
+    )
+    .await?;
+    println!("API key permissions verified");
+
+    // 签名请求验证私钥与合约账户
+    let rsp = rest(FAPI_URI, &credential, config.capability)?
+        .get("/fapi/v2/balance", &[], true)
+        .await?;
+    let text = rsp.text().await?;
+    if let Ok(e) = serde_json::from_str::<SError>(&text) {
+        anyhow::bail!("Signed request rejected: {:?}", e);
+    }
+    println!("Signed request accepted");
+    Ok(())
+}
+
+pub async fn probe(config: &Config, count: usize) -> anyhow::Result<()> {
+    let credential = load_credential(config)?;
+    let rest = rest(FAPI_URI, &credential, config.capability)?;
+
+    let mut elapsed = Vec::new();
+    for _ in 0..count.max(1) {
+        let start = Instant::now();
+        match rest.get("/fapi/v1/ping", &[], false).await {
+            Ok(_) => elapsed.push(start.elapsed()),
+            Err(e) => println!("REST ping failed: {}", e),
+        }
+    }
+    if let (Some(min), Some(max)) = (elapsed.iter().min(), elapsed.iter().max()) {
+        let avg = elapsed.iter().sum::<Duration>() / elapsed.len() as u32;
+        println!(
+            "REST ping {}/{}: min {:?}, avg {:?}, max {:?}",
+            elapsed.len(),
+            count.max(1),
+            min,
+            avg,
+            max
+        );
+    }
+
+    let start = Instant::now();
+    let rsp: serde_json::Value = rest.get("/fapi/v1/time", &[], false).await?.json().await?;
+    let rtt = start.elapsed();
+    if let Some(server) = rsp.get("serverTime").and_then(|t| t.as_i64()) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let local = now - rtt.as_millis() as i64 / 2;
+        println!("Clock offset {}ms (local - server)", local - server);
+    }
+
+    let start = Instant::now();
+    match Market::new().await {
+        Ok(_) => println!("Market websocket connected in {:?}", start.elapsed()),
+        Err(e) => println!("Market websocket failed: {}", e),
+    }
+
+    if config.wsapi.enabled {
+        let credentials = Credentials::new(credential.apikey, credential.pem, "".into(), "0");
+        let start = Instant::now();
+        let mut wsapi =
+            WsApiOrders::<()>::connect(&config.wsapi, &credentials, Default::default()).await?;
+        let deadline = start + Duration::from_millis(config.wsapi.timeout_ms);
+        while !wsapi.available() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            wsapi.poll();
+        }
+        match wsapi.available() {
+            true => println!("WS-API logged on in {:?}", start.elapsed()),
+            false => println!("WS-API logon timed out"),
+        }
+    }
+    Ok(())
+}
+
+pub async fn products(config: &Config, symbols: &[String]) -> anyhow::Result<()> {
+    let credential = load_credential(config)?;
+    let rest = rest(FAPI_URI, &credential, config.capability)?;
+    let products = crate::trade::get_positions(&rest).await?;
+    print!("{}", products_csv(products.into_values(), symbols));
+    Ok(())
+}
+
+/// 按交易对排序输出交易规则，`symbols` 不为空时只输出其中的交易对（不区分大小写）
+fn products_csv(products: impl IntoIterator<Item = BinanceSymbol>, symbols: &[String]) -> String {
+    let mut products: Vec<_> = products
+        .into_iter()
+        .filter(|p| symbols.is_empty() || symbols.iter().any(|s| s.eq_ignore_ascii_case(&p.symbol)))
+        .collect();
+    products.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    let mut csv = String::from(
+        "symbol,tick_size,min_price,max_price,lot_size,min_quantity,max_quantity,min_notional\n",
+    );
+    for p in products {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            p.symbol,
+            p.tick_size(),
+            p.min_price(),
+            p.max_price(),
+            p.lot_size(),
+            p.min_quantity(),
+            p.max_quantity(),
+            p.min_notional()
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Args {
+        #[command(subcommand)]
+        command: Option<Command>,
+    }
+
+    fn parse(args: &[&str]) -> Option<Command> {
+        Args::try_parse_from(std::iter::once("usdt").chain(args.iter().copied()))
+            .unwrap()
+            .command
+    }
+
+    fn product(symbol: &str, tick_size: &str, step_size: &str) -> BinanceSymbol {
+        serde_json::from_value(serde_json::json!({
+            "symbol": symbol,
+            "status": "TRADING",
+            "baseAsset": "BTC",
+            "baseAssetPrecision": 8,
+            "quoteAsset": "USDT",
+            "quotePrecision": 8,
+            "orderTypes": ["LIMIT", "MARKET"],
+            "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.1", "maxPrice": "100000", "tickSize": tick_size},
+                {"filterType": "LOT_SIZE", "minQty": step_size, "maxQty": "1000", "stepSize": step_size},
+                {"filterType": "MIN_NOTIONAL", "notional": "5"},
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert!(parse(&[]).is_none());
+        assert!(matches!(parse(&["run"]), Some(Command::Run)));
+        assert!(matches!(
+            parse(&["check-config"]),
+            Some(Command::CheckConfig)
+        ));
+        assert!(matches!(
+            parse(&["probe"]),
+            Some(Command::Probe { count: 5 })
+        ));
+        assert!(matches!(
+            parse(&["probe", "-n", "20"]),
+            Some(Command::Probe { count: 20 })
+        ));
+        match parse(&["products", "btcusdt", "ETHUSDT"]) {
+            Some(Command::Products { symbols }) => assert_eq!(symbols, vec!["btcusdt", "ETHUSDT"]),
+            command => panic!("unexpected {:?}", command),
+        }
+        assert!(Args::try_parse_from(["usdt", "probe", "-n", "x"]).is_err());
+        assert!(Args::try_parse_from(["usdt", "unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_products_csv() {
+        let products = || {
+            vec![
+                product("ETHUSDT", "0.01", "0.001"),
+                product("BTCUSDT", "0.1", "0.001"),
+                product("SOLUSDT", "0.01", "1"),
+            ]
+        };
+        let csv = products_csv(products(), &[]);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("symbol,tick_size"));
+        assert_eq!(lines[1], "btcusdt,0.1,0.1,100000,0.001,0.001,1000,5");
+        assert_eq!(lines[2], "ethusdt,0.01,0.1,100000,0.001,0.001,1000,5");
+        assert_eq!(lines[3], "solusdt,0.01,0.1,100000,1,1,1000,5");
+
+        // 只输出指定的交易对，不区分大小写
+        let csv = products_csv(products(), &["SOLUSDT".into(), "ethusdt".into()]);
+        let symbols: Vec<_> = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap())
+            .collect();
+        assert_eq!(symbols, vec!["ethusdt", "solusdt"]);
+        assert_eq!(
+            products_csv(products(), &["xrpusdt".into()])
+                .lines()
+                .count(),
+            1
+        );
+    }
+}
@@ -1,20 +1,134 @@
+mod command;
 mod trade;
 
-use crate::rest::Rest;
+use binance::admin::AdminConfig;
+use binance::apikey::{check_api_key, ApiKeyConfig, Permission, API_RESTRICTIONS_URI};
+use binance::bar::BarClockConfig;
+use binance::capability::Capability;
+use binance::credential::CredentialConfig;
+use binance::delivery::DeliveryConfig;
+use binance::depth_check::{DepthBooks, DepthCheckConfig};
+use binance::fat_finger::FatFingerConfig;
+use binance::flatten::{Flatten, FlattenConfig};
+use binance::gtx_retry::GtxRetryConfig;
+use binance::halt::Halts;
+use binance::heartbeat::HeartbeatConfig;
+use binance::hub::{Hub, HubConfig};
+use binance::idempotency::IdempotencyConfig;
+use binance::inflight::InFlightConfig;
+use binance::liquidity::LiquidityConfig;
+use binance::ops_report::{OpsReport, OpsReportConfig};
+use binance::order_caps::OrderCapsConfig;
+use binance::order_check::OrderCheckConfig;
+use binance::params::{ParamStore, ParamsConfig};
+use binance::peg::{BookTickers, PegConfig};
+use binance::pm::{AccountMode, PmUserStream, PortfolioMarginConfig};
+use binance::prefetch::{Prefetch, PrefetchConfig, PrefetchPaths};
+use binance::roll::{ContractAliases, RollConfig};
+use binance::venue_status::{self, VenueStatus, VenueStatusConfig};
+use binance::watchdog::WatchdogConfig;
+use binance::wsapi::WsApiConfig;
 use binance::{event_handlers::DefaultUserDataHandler, *};
 use clap::Parser;
-use cryptoflow::init_tracing;
+use command::Command;
+use cryptoflow::alert::{AlertConfig, Alerter};
+use cryptoflow::fx::{FxConfig, FxRates};
+use cryptoflow::income::IncomeConfig;
+use cryptoflow::my_trades::MyTradesConfig;
+use cryptoflow::portfolio::{Portfolio, PortfolioConfig};
+use cryptoflow::runtime::RuntimeConfig;
+use cryptoflow::storage::{self, StorageConfig};
+use cryptoflow::tenant::{TenantConfig, Tenants};
+use cryptoflow::{init_tracing_with_config, LogConfig};
 use serde::Deserialize;
-use std::sync::Arc;
 use tracing::{error, info};
 use trade::UsdtTrade;
-use websocket::Credentials;
+use websocket::{ChannelConfig, Credentials, ListenerConfig};
 
 #[derive(Debug, Deserialize)]
 struct Config {
+    #[serde(default)]
+    account_mode: AccountMode,
+    #[serde(default)]
+    portfolio_margin: PortfolioMarginConfig,
+    #[serde(default)]
     apikey: String,
+    #[serde(default)]
     pem: String,
+    credential: Option<CredentialConfig>,
     local: String,
+    #[serde(default)]
+    listener: ListenerConfig,
+    #[serde(default)]
+    channel: ChannelConfig,
+    #[serde(default)]
+    market: MarketConfig,
+    #[serde(default)]
+    alert: AlertConfig,
+    #[serde(default)]
+    portfolio: PortfolioConfig,
+    #[serde(default)]
+    tenants: Vec<TenantConfig>,
+    #[serde(default)]
+    storage: StorageConfig,
+    #[serde(default)]
+    fx: FxConfig,
+    #[serde(default)]
+    income: IncomeConfig,
+    #[serde(default)]
+    api_key: ApiKeyConfig,
+    #[serde(default)]
+    capability: Capability,
+    #[serde(default)]
+    in_flight: InFlightConfig,
+    #[serde(default)]
+    idempotency: IdempotencyConfig,
+    #[serde(default)]
+    peg: PegConfig,
+    #[serde(default)]
+    wsapi: WsApiConfig,
+    #[serde(default)]
+    log: LogConfig,
+    #[serde(default)]
+    bar_clock: BarClockConfig,
+    #[serde(default)]
+    runtime: RuntimeConfig,
+    #[serde(default)]
+    params: ParamsConfig,
+    #[serde(default)]
+    admin: AdminConfig,
+    #[serde(default)]
+    flatten: FlattenConfig,
+    #[serde(default)]
+    heartbeat: HeartbeatConfig,
+    #[serde(default)]
+    venue_status: VenueStatusConfig,
+    #[serde(default)]
+    watchdog: WatchdogConfig,
+    #[serde(default)]
+    liquidity: LiquidityConfig,
+    #[serde(default)]
+    order_check: OrderCheckConfig,
+    #[serde(default)]
+    fat_finger: FatFingerConfig,
+    #[serde(default)]
+    depth_check: DepthCheckConfig,
+    #[serde(default)]
+    order_caps: OrderCapsConfig,
+    #[serde(default)]
+    ops_report: OpsReportConfig,
+    #[serde(default)]
+    hub: HubConfig,
+    #[serde(default)]
+    gtx_retry: GtxRetryConfig,
+    #[serde(default)]
+    my_trades: MyTradesConfig,
+    #[serde(default)]
+    prefetch: PrefetchConfig,
+    #[serde(default)]
+    delivery: DeliveryConfig,
+    #[serde(default)]
+    roll: RollConfig,
 }
 
 #[derive(Debug, Parser)]
@@ -24,6 +138,10 @@ struct Args {
     config: String,
     #[arg(short, long, default_value_t = tracing::Level::INFO)]
     level: tracing::Level,
+    #[arg(long, help = "Run without sending orders to the exchange")]
+    dry_run: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
 impl Args {
@@ -35,32 +153,152 @@ impl Args {
     }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     let config = args.load()?;
+    config.runtime.build()?.block_on(start(args, config))
+}
 
+async fn start(args: Args, config: Config) -> anyhow::Result<()> {
     let path = std::env::current_exe()?;
     let filename = match path.file_name() {
         Some(name) => name.to_string_lossy(),
         None => "unknown".into(),
     };
 
-    let _guard = init_tracing(&filename, "log", &args.level.to_string().to_lowercase())?;
+    let _guard = init_tracing_with_config(
+        &filename,
+        &config.log,
+        &args.level.to_string().to_lowercase(),
+    )?;
+    config.runtime.log();
 
-    let app = Application::new(&config.local).await?;
-    let market = Market::new().await?;
+    match args.command.unwrap_or(Command::Run) {
+        Command::Run => run(config, args.dry_run).await,
+        Command::CheckConfig => command::check_config(&config).await,
+        Command::Probe { count } => command::probe(&config, count).await,
+        Command::Products { symbols } => command::products(&config, &symbols).await,
+    }
+}
 
-    let rest = Arc::new(Rest::new(
-        "https://fapi.binance.com",
-        &config.apikey,
-        &config.pem,
-        3000,
-    )?);
+async fn run(config: Config, dry_run: bool) -> anyhow::Result<()> {
+    let alerter = Alerter::new(config.alert.clone());
+    let fx = FxRates::new(config.fx.clone());
+    let tenants = Tenants::new(config.tenants.clone())?;
+    let portfolio = Portfolio::new(config.portfolio.clone())
+        .with_fx(fx.clone())
+        .with_tenants(tenants.clone());
+    let book_tickers = BookTickers::default();
+    let halts = Halts::default();
+    let depth_books = DepthBooks::default();
+    let venue_status = VenueStatus::new(config.venue_status.clone(), trade::VENUE);
+    venue_status.spawn_refresh(venue_status::USDT);
+    let ops_report = OpsReport::new(config.ops_report.clone());
+    let aliases = ContractAliases::default();
 
-    let credentials = Credentials::new(config.apikey, config.pem, "".to_string(), "0");
-    let account = Account::new(&credentials, DefaultUserDataHandler).await;
-    let trade = UsdtTrade::new(rest.clone(), account).await?;
+    let credential = command::load_credential(&config)?;
+    let rest = command::rest(command::FAPI_URI, &credential, config.capability)?;
+    // 连接行情与账户的同时预取，结束后才接受策略端连接
+    let prefetch = Prefetch::spawn(
+        &config.prefetch,
+        rest.clone(),
+        config.account_mode.trading_rest(&rest),
+        PrefetchPaths::usdt(config.account_mode.um().leverage_bracket),
+    );
+    let app = Application::new(&config.local)
+        .await?
+        .with_listener(&config.listener)?
+        .with_channel(config.channel.clone())
+        .with_idempotency(config.idempotency.clone())
+        .with_runtime(config.runtime.clone())
+        .with_alerter(alerter.clone())
+        .with_portfolio(portfolio.clone())
+        .with_params(ParamStore::open(&config.params)?)
+        .with_admin(config.admin.clone())
+        .with_flatten(Flatten::new(&config.flatten)?)
+        .with_heartbeat(config.heartbeat.clone())
+        .with_tenants(tenants.clone())
+        .with_halts(halts.clone())
+        .with_venue_status(venue_status)
+        .with_ops_report(ops_report.clone())
+        .with_prefetch(prefetch.clone());
+    let storage = storage::open(&config.storage.url).await?;
+    ops_report.spawn(storage.clone(), alerter.clone(), trade::VENUE);
+    let mut market = Market::new()
+        .await?
+        .with_config(&config.market)
+        .with_storage(storage.clone(), &config.storage)
+        .with_prefetched(prefetch.prefetched())
+        .with_bar_clock(&config.bar_clock)
+        .with_book_tickers(book_tickers.clone())
+        .with_depth_check(&config.depth_check, depth_books.clone())
+        .with_venue(trade::VENUE)
+        .with_aliases(aliases.clone())
+        .with_fx(fx.clone())
+        .await?
+        .with_options(&config.market.options)
+        .await?
+        .with_basis(&config.market.basis)
+        .await?;
+
+    // 行情中心不交易，不检查 API Key 的交易权限，也不连接账户推送
+    if config.hub.enabled {
+        let products = trade::get_positions(&rest).await?;
+        let hub = Hub::new(products, trade::validate_stream);
+        hub.warm(&mut market, &config.hub.streams).await?;
+        if let Err(e) = app.keep_running(market, hub).await {
+            error!("{}", e);
+        }
+        return Ok(());
+    }
+
+    let restrictions = command::rest(API_RESTRICTIONS_URI, &credential, config.capability)?;
+    let mut required = vec![Permission::Reading, Permission::Futures];
+    if config.account_mode == AccountMode::PortfolioMargin {
+        required.push(Permission::PortfolioMargin);
+    }
+    let required = config.capability.permissions(required);
+    check_api_key(restrictions, &required, config.api_key, alerter.clone()).await?;
+
+    let credentials = Credentials::new(credential.apikey, credential.pem, "".to_string(), "0");
+    let account = match config.account_mode {
+        AccountMode::Classic => Account::new(&credentials, DefaultUserDataHandler)
+            .await
+            .into(),
+        AccountMode::PortfolioMargin => {
+            PmUserStream::connect(config.account_mode.trading_rest(&rest))
+                .await?
+                .into()
+        }
+    };
+    let trade = UsdtTrade::new(rest.clone(), account, config.account_mode)
+        .await?
+        .with_storage(storage.clone(), &config.storage, tenants.clone())
+        .await?
+        .with_portfolio_margin(config.portfolio_margin)
+        .with_alerter(alerter)
+        .with_dry_run(dry_run)
+        .with_in_flight(config.in_flight)
+        .with_pegs(config.peg, book_tickers)
+        .with_watchdog(config.watchdog)
+        .with_liquidity(config.liquidity)
+        .with_order_check(config.order_check)
+        .with_fat_finger(config.fat_finger)
+        .with_gtx_retry(config.gtx_retry)
+        .with_halts(halts)
+        .with_depth_check(config.depth_check, depth_books)
+        .with_order_caps(config.order_caps)
+        .with_ops_report(ops_report)
+        .with_delivery(config.delivery)
+        .with_roll(config.roll, aliases)
+        .with_fx(fx)
+        .with_portfolio(portfolio)
+        .with_income(config.income)
+        .await?
+        .with_my_trades(config.my_trades)
+        .await?
+        .with_wsapi(&config.wsapi, &credentials)
+        .await;
 
     if let Err(e) = app.keep_running(market, trade).await {
         error!("{}", e);
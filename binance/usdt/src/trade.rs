@@ -1,27 +1,67 @@
-use crate::rest::Rest;
-use binance::event_handlers::DefaultUserDataHandler;
+use crate::rest::{order_params, Rest};
+use binance::capability::Capability;
+use binance::dedup::{EventKey, OrderEvents};
+use binance::delivery::{Deliveries, DeliveryConfig};
+use binance::depth_check::{self, DepthBooks, DepthCheckConfig, Verdict};
+use binance::dryrun::{self, DryRun};
+use binance::fat_finger::{self, FatFingerConfig};
+use binance::gtx_retry::{self, GtxRetries, GtxRetryConfig};
+use binance::halt::Halts;
+use binance::inflight::{InFlight, InFlightConfig, InFlightGuard};
+use binance::lifecycle::{correlation_id, order_span, Lifecycle, Stage};
+use binance::liquidity::{Liquidity, LiquidityConfig};
+use binance::margin::{LoanType, SLoan};
+use binance::model::income::BinanceIncome;
+use binance::model::leverage::{self, Bracket, LeverageBrackets};
 use binance::model::order::usdt::OrderUpdate;
 use binance::model::order::BinanceCancel;
-use binance::model::order::BinanceOrder;
+use binance::model::order::{BinanceOrder, BinanceQuote};
 use binance::model::symbol::BinanceSymbol;
-use binance::model::Event;
+use binance::model::{Event, PositionRisk};
+use binance::my_trades::MyTrades;
+use binance::ops_report::OpsReport;
+use binance::order_caps::{Admission, Limits, OrderCaps, OrderCapsConfig};
+use binance::order_check::{self, OrderCheckConfig};
+use binance::paginate::Paginator;
+use binance::peg::{BookTickers, PegAction, PegConfig, Pegs};
+use binance::pm::{AccountMode, PmRisk, PortfolioMarginConfig, UmEndpoints};
+use binance::post_only;
+use binance::quote::{QuoteAction, Quotes};
+use binance::replace::{ReplaceAction, Replaces};
+use binance::roll::{self, ContractAliases, RollConfig, Rolls};
+use binance::sanity::{self, working_orders, ExchangeState, LocalState};
+use binance::state;
+use binance::watchdog::{OrderWatchdog, WatchdogConfig};
+use binance::wire::ClientSender;
+use binance::wsapi::{OrderLatency, WsApiConfig, WsApiEvent, WsApiOrders};
 use binance::*;
+use cryptoflow::alert::{Alerter, ReconcileKind};
 use cryptoflow::chat::*;
 use cryptoflow::error_code;
 use cryptoflow::error_code::DUPLICATE_LOGIN;
-use cryptoflow::parser::JsonParser;
+use cryptoflow::fx::FxRates;
+use cryptoflow::income::{Income, IncomeConfig, IncomeDB, SIncomeReq, INCOME_TYPES};
+use cryptoflow::my_trades::{MyTrade, MyTradesConfig, SMyTradesReq};
+use cryptoflow::parser::RequestParser;
+use cryptoflow::portfolio::Portfolio;
 use cryptoflow::position::PositionDB;
-use native_json::Deserialize;
+use cryptoflow::storage::{Storage, StorageConfig};
+use cryptoflow::tenant::Tenants;
+use cryptoflow::trading_rules::TradingRules;
+use futures::TryStreamExt;
 use serde::Serialize;
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::mpsc::UnboundedSender;
-use tracing::{debug, error, info, warn};
-use tungstenite::Message;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn, Instrument};
+use websocket::Credentials;
 
-async fn get_positions(rest: &Arc<Rest>) -> anyhow::Result<HashMap<String, BinanceSymbol>> {
+pub(crate) async fn get_positions(
+    rest: &Arc<Rest>,
+) -> anyhow::Result<HashMap<String, BinanceSymbol>> {
     let rsp = rest.get("/fapi/v1/exchangeInfo", &[], false).await?;
     let results: serde_json::Value = serde_json::from_str(&rsp.text().await?)?;
 
@@ -37,35 +77,793 @@ async fn get_positions(rest: &Arc<Rest>) -> anyhow::Result<HashMap<String, Binan
     Ok(products)
 }
 
+/// 交易对之后的流名称是否可以订阅，行情中心模式也用它校验，见 [`binance::hub`]
+pub fn validate_stream(stream: &str) -> bool {
+    match stream.split_once(":") {
+        Some((stream, interval)) => match stream {
+            "kline" => matches!(
+                interval,
+                "1s" | "1m"
+                    | "3m"
+                    | "5m"
+                    | "15m"
+                    | "30m"
+                    | "1h"
+                    | "2h"
+                    | "4h"
+                    | "6h"
+                    | "8h"
+                    | "12h"
+                    | "1d"
+                    | "3d"
+                    | "1w"
+                    | "1M"
+            ),
+            "depth" => matches!(interval, "100ms"),
+            "cvd" => binance::flow::window_ms(interval).is_some(),
+            _ => false,
+        },
+        // 基差由行情模块在未开启时拒绝
+        None => matches!(stream, "depth" | "bbo" | "basis"),
+    }
+}
+
+/// 账户级别的持仓方向、入场价、保证金模式与杠杆
+async fn get_position_risk(
+    rest: &Rest,
+    endpoints: &UmEndpoints,
+) -> anyhow::Result<HashMap<String, Position>> {
+    let rsp = rest.get(endpoints.position_risk, &[], true).await?;
+    let risks: Vec<PositionRisk> = serde_json::from_str(&rsp.text().await?)?;
+    Ok(risks
+        .iter()
+        .map(Position::from)
+        .map(|p| (p.key(), p))
+        .collect())
+}
+
+/// 交易所的净持仓，持仓键 -> positionAmt
+async fn get_position_amounts(
+    rest: &Rest,
+    endpoints: &UmEndpoints,
+) -> anyhow::Result<HashMap<String, f64>> {
+    let rsp = rest.get(endpoints.position_risk, &[], true).await?;
+    let risks: Vec<PositionRisk> = serde_json::from_str(&rsp.text().await?)?;
+    Ok(risks
+        .iter()
+        .filter_map(|risk| {
+            let net: f64 = risk.positionAmt.parse().ok()?;
+            (net != 0.0).then(|| (Position::from(risk).key(), net))
+        })
+        .collect())
+}
+
+/// 合约账户每个资产的钱包余额
+async fn get_balances(
+    rest: &Rest,
+    endpoints: &UmEndpoints,
+) -> anyhow::Result<BTreeMap<String, f64>> {
+    let rsp = rest.get(endpoints.balance, &[], true).await?;
+    let value: Value = serde_json::from_str(&rsp.text().await?)?;
+    let balances = value
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))?;
+    Ok(balances
+        .iter()
+        .filter_map(|b| {
+            let balance = b[endpoints.wallet_balance].as_str()?.parse::<f64>().ok()?;
+            Some((b["asset"].as_str()?.to_string(), balance))
+        })
+        .collect())
+}
+
+/// 账户所有交易对的杠杆分层，按小写的交易对索引
+async fn get_leverage_brackets(
+    rest: &Rest,
+    endpoints: &UmEndpoints,
+) -> anyhow::Result<HashMap<String, Vec<Bracket>>> {
+    let rsp = rest.get(endpoints.leverage_bracket, &[], true).await?;
+    let brackets: Vec<_> = serde_json::from_str::<LeverageBrackets>(&rsp.text().await?)?.into();
+    Ok(brackets
+        .into_iter()
+        .map(|b| (b.symbol, b.brackets))
+        .collect())
+}
+
+/// 把杠杆分层附到交易规则上，随 `get_products` 返回给策略端
+fn attach_brackets(
+    products: &mut HashMap<String, BinanceSymbol>,
+    brackets: &HashMap<String, Vec<Bracket>>,
+) {
+    for (symbol, product) in products.iter_mut() {
+        if let Some(brackets) = brackets.get(symbol) {
+            product.leverageBrackets = brackets.clone();
+        }
+    }
+}
+
+/// 账户是否为双向持仓模式
+async fn get_dual_side(rest: &Rest, endpoints: &UmEndpoints) -> anyhow::Result<bool> {
+    let rsp = rest.get(endpoints.dual_side, &[], true).await?;
+    let value: Value = serde_json::from_str(&rsp.text().await?)?;
+    value["dualSidePosition"]
+        .as_bool()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response {}", value))
+}
+
+const INCOME_LIMIT: usize = 1000;
+
+/// 从库中最新一条流水开始分页同步，返回新写入的条数
+async fn sync_income(
+    rest: &Rest,
+    endpoints: &UmEndpoints,
+    db: &IncomeDB,
+    lookback_days: i64,
+) -> anyhow::Result<u64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let start = match db.last_time().await? {
+        Some(time) => time,
+        None => now - lookback_days * 24 * 60 * 60 * 1000,
+    };
+
+    // 库中最新一毫秒的流水会再取一次，重复的由库去重
+    let pages = Paginator::since(endpoints.income, start, |i: &BinanceIncome| i.time)
+        .with_signature(true)
+        .with_limit(INCOME_LIMIT)
+        .pages(rest);
+    futures::pin_mut!(pages);
+
+    let mut rows = 0;
+    while let Some(page) = pages.try_next().await? {
+        let incomes: Vec<Income> = page
+            .into_iter()
+            .filter(|i| INCOME_TYPES.contains(&i.incomeType.as_str()))
+            .map(Income::from)
+            .collect();
+        rows += db.insert(&incomes).await?;
+    }
+    Ok(rows)
+}
+
+/// 组合敞口中的交易场所名
+pub const VENUE: &str = "binance-usdt";
+
+/// 等待 WS-API 响应的请求，响应到达时释放在途名额
+#[derive(Debug)]
+enum WsApiPending {
+    Place {
+        tx: ClientSender,
+        order: Box<BinanceOrder>,
+        _guard: InFlightGuard,
+        sent: Instant,
+    },
+    Cancel {
+        _guard: InFlightGuard,
+    },
+}
+
 pub struct UsdtTrade {
+    /// 交易与账户接口
     rest: Arc<Rest>,
-    txs: HashMap<SocketAddr, UnboundedSender<Message>>,
-    account: Account<DefaultUserDataHandler>,
+    /// 交易规则等公开接口
+    public: Arc<Rest>,
+    mode: AccountMode,
+    endpoints: UmEndpoints,
+    /// 统一账户的保证金检查，经典账户时为空
+    pm: Option<PmRisk>,
+    txs: HashMap<SocketAddr, ClientSender>,
+    account: UserStream,
     // addr -> session_id
     session_id: HashMap<SocketAddr, u16>,
     // session_id -> session
     session: HashMap<u16, Session>,
     posdb: Arc<PositionDB>,
+    /// 资产 -> 账户推送中的钱包余额
+    balances: BTreeMap<String, f64>,
+    /// 持仓键 -> 交易所账户级别的持仓，用于补充会话持仓的入场价、杠杆等字段
+    position_details: HashMap<String, Position>,
+    /// 双向持仓模式，下单必须指定 LONG 或 SHORT
+    dual_side: bool,
     products: HashMap<String, BinanceSymbol>,
+    /// 交易对 -> 杠杆分层，下单前按当前杠杆检查持仓名义价值的上限
+    leverage_brackets: HashMap<String, Vec<Bracket>>,
+    alerter: Alerter,
+    portfolio: Portfolio,
+    in_flight: InFlight,
+    quotes: Quotes,
+    pegs: Pegs,
+    replaces: Replaces,
+    watchdog: OrderWatchdog,
+    deliveries: Deliveries,
+    /// 季度合约的连续合约别名与移仓
+    rolls: Rolls,
+    book_tickers: BookTickers,
+    income: Option<Arc<IncomeDB>>,
+    my_trades: Option<MyTrades>,
+    wsapi: Option<WsApiOrders<WsApiPending>>,
+    order_latency: OrderLatency,
+    ops_report: OpsReport,
+    lifecycle: Lifecycle,
+    /// 合并 WS-API 响应与账户推送中重复的订单回报
+    events: OrderEvents,
+    liquidity: Liquidity,
+    order_check: OrderCheckConfig,
+    fat_finger: FatFingerConfig,
+    gtx_retries: GtxRetries,
+    halts: Halts,
+    depth_check: DepthCheckConfig,
+    depth_books: DepthBooks,
+    order_caps: OrderCaps,
+    dry_run: Option<DryRun>,
+    latency_report: Instant,
 }
 
 impl UsdtTrade {
     pub async fn new(
-        rest: Arc<Rest>,
-        account: Account<DefaultUserDataHandler>,
+        public: Arc<Rest>,
+        account: UserStream,
+        mode: AccountMode,
     ) -> anyhow::Result<Self> {
-        let products = get_positions(&rest).await?;
+        let rest = mode.trading_rest(&public);
+        let endpoints = mode.um();
+        // 几个查询互不依赖，并发进行
+        let (products, position_details, dual_side, leverage_brackets) = tokio::join!(
+            get_positions(&public),
+            get_position_risk(&rest, &endpoints),
+            get_dual_side(&rest, &endpoints),
+            get_leverage_brackets(&rest, &endpoints)
+        );
+        let mut products = products?;
+        let leverage_brackets = leverage_brackets.unwrap_or_else(|e| {
+            warn!("Get leverage brackets failed: {}", e);
+            HashMap::default()
+        });
+        attach_brackets(&mut products, &leverage_brackets);
+        let position_details = position_details.unwrap_or_else(|e| {
+            warn!("Get position risk failed: {}", e);
+            HashMap::default()
+        });
+        let dual_side = dual_side.unwrap_or_else(|e| {
+            warn!("Get position mode failed: {}, assume one-way mode", e);
+            false
+        });
+        info!("Dual side position {}", dual_side);
 
         Ok(Self {
             rest,
+            public,
+            mode,
+            endpoints,
+            pm: None,
             txs: HashMap::default(),
             account,
             session_id: HashMap::default(),
             session: HashMap::default(),
             posdb: Arc::new(PositionDB::new("pos.db").await?),
+            balances: BTreeMap::default(),
+            position_details,
+            dual_side,
             products,
+            leverage_brackets,
+            alerter: Alerter::default(),
+            portfolio: Portfolio::default(),
+            in_flight: InFlight::default(),
+            quotes: Quotes::default(),
+            pegs: Pegs::default(),
+            replaces: Replaces::default(),
+            watchdog: OrderWatchdog::default(),
+            deliveries: Deliveries::default(),
+            rolls: Rolls::default(),
+            book_tickers: BookTickers::default(),
+            income: None,
+            my_trades: None,
+            wsapi: None,
+            order_latency: OrderLatency::default(),
+            ops_report: OpsReport::default(),
+            lifecycle: Lifecycle::default(),
+            events: OrderEvents::default(),
+            liquidity: Liquidity::default(),
+            order_check: OrderCheckConfig::default(),
+            fat_finger: FatFingerConfig::default(),
+            gtx_retries: GtxRetries::default(),
+            halts: Halts::default(),
+            depth_check: DepthCheckConfig::default(),
+            depth_books: DepthBooks::default(),
+            order_caps: OrderCaps::default(),
+            dry_run: None,
+            latency_report: Instant::now(),
         })
     }
+
+    /// 开启后优先通过 WS-API 下单撤单，连接失败或不可用时使用 REST
+    /// 统一账户按 uniMMR 检查新订单，并开始定时查询统一账户
+    pub fn with_portfolio_margin(mut self, config: PortfolioMarginConfig) -> Self {
+        if self.mode == AccountMode::PortfolioMargin {
+            let pm = PmRisk::new(config);
+            pm.spawn_refresh(self.rest.clone());
+            self.pm = Some(pm);
+        }
+        self
+    }
+
+    pub async fn with_wsapi(mut self, config: &WsApiConfig, credentials: &Credentials) -> Self {
+        if config.enabled && self.pm.is_some() {
+            warn!("WS-API does not support portfolio margin, place orders via REST");
+        } else if config.enabled && self.rest.capability() == Capability::ReadOnly {
+            // REST 按能力拒绝交易请求，WS-API 不经过 Rest，只读凭证不连接
+            warn!("Read-only credential, WS-API orders disabled");
+        } else if config.enabled {
+            match WsApiOrders::connect(config, credentials, self.order_latency.clone()).await {
+                Ok(wsapi) => self.wsapi = Some(wsapi),
+                Err(e) => warn!("{}, place orders via REST", e),
+            }
+        }
+        self
+    }
+
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = alerter;
+        self
+    }
+
+    /// 演练模式：下单与撤单不发往交易所，以模拟回报应答策略端
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        if enabled {
+            warn!("Dry run, orders are not sent to the exchange");
+            self.dry_run = Some(DryRun::default());
+        }
+        self
+    }
+
+    /// 限制每个会话的在途请求数
+    pub fn with_in_flight(mut self, config: InFlightConfig) -> Self {
+        self.in_flight = InFlight::new(config);
+        self
+    }
+
+    /// 钉住订单按 market 维护的最优买卖价改价
+    pub fn with_pegs(mut self, config: PegConfig, book_tickers: BookTickers) -> Self {
+        self.pegs = Pegs::new(config);
+        self.book_tickers = book_tickers;
+        self
+    }
+
+    /// 交割合约在交割前提醒持有的会话，交割后结算会话持仓
+    pub fn with_delivery(mut self, config: DeliveryConfig) -> Self {
+        self.deliveries = Deliveries::new(&config);
+        self.deliveries.on_products(&self.products);
+        self
+    }
+
+    /// 连续合约别名，交割前提醒移仓，按策略请求移仓；`aliases` 与行情模块共享
+    pub fn with_roll(mut self, config: RollConfig, aliases: ContractAliases) -> Self {
+        self.rolls = Rolls::new(&config, aliases);
+        self.rolls.on_products(&self.products);
+        self
+    }
+
+    /// 挂单与吃单成交统计划分自然日的时区与保留的天数
+    pub fn with_liquidity(mut self, config: LiquidityConfig) -> Self {
+        self.liquidity = Liquidity::new(config);
+        self
+    }
+
+    /// 下单前按交易所的过滤器校验
+    pub fn with_order_check(mut self, config: OrderCheckConfig) -> Self {
+        self.order_check = config;
+        self
+    }
+
+    pub fn with_fat_finger(mut self, config: FatFingerConfig) -> Self {
+        self.fat_finger = config;
+        self
+    }
+
+    /// 只做 maker 的订单因为会立即成交被拒绝时改价重发
+    pub fn with_gtx_retry(mut self, config: GtxRetryConfig) -> Self {
+        self.gtx_retries = GtxRetries::new(config);
+        self
+    }
+
+    /// 与 handler 共享暂停交易的交易对，暂停期间拒绝这些交易对的新订单
+    pub fn with_halts(mut self, halts: Halts) -> Self {
+        self.halts = halts;
+        self
+    }
+
+    /// 吃单前按 market 维护的深度检查流动性
+    pub fn with_depth_check(mut self, config: DepthCheckConfig, books: DepthBooks) -> Self {
+        self.depth_check = config;
+        self.depth_books = books;
+        self
+    }
+
+    /// 订单会超过交易所的挂单数上限时排队下单，合约没有账户的上限
+    pub fn with_order_caps(mut self, config: OrderCapsConfig) -> Self {
+        self.order_caps = OrderCaps::new(config);
+        self.order_caps
+            .set_products(&self.products, Limits::default());
+        self
+    }
+
+    /// 统计下单、回报与成交，写入每日运维报告
+    pub fn with_ops_report(mut self, ops_report: OpsReport) -> Self {
+        self.ops_report = ops_report;
+        self
+    }
+
+    /// 定时查询挂单，撤销或告警过期的订单
+    pub fn with_watchdog(mut self, config: WatchdogConfig) -> Self {
+        self.watchdog = OrderWatchdog::new(config);
+        if self.watchdog.enabled() {
+            self.watchdog
+                .spawn_refresh(self.rest.clone(), self.endpoints.open_orders);
+        }
+        self
+    }
+
+    /// 登记所有交易对的基础资产与计价资产，供汇率表换算
+    pub fn with_fx(self, fx: FxRates) -> Self {
+        for product in self.products.values() {
+            fx.register(&product.symbol, &product.baseAsset, &product.quoteAsset);
+        }
+        self
+    }
+
+    /// 持仓按租户分表保存到 `storage`，开启 `orders` 时同时保存订单回报与成交；需要在 `with_portfolio`
+    /// 与会话登录之前设置
+    pub async fn with_storage(
+        mut self,
+        storage: Arc<dyn Storage>,
+        config: &StorageConfig,
+        tenants: Tenants,
+    ) -> anyhow::Result<Self> {
+        let posdb = PositionDB::with_storage(storage, tenants).await?;
+        self.posdb = Arc::new(posdb.with_orders(config.orders));
+        Ok(self)
+    }
+
+    /// 设置组合敞口，并用已持久化的持仓初始化
+    pub fn with_portfolio(mut self, portfolio: Portfolio) -> Self {
+        for (session_id, positions) in self.posdb.sessions() {
+            // 双向持仓按两腿之和计入敞口
+            let mut nets: HashMap<&str, f64> = HashMap::new();
+            for position in positions.values() {
+                *nets.entry(&position.symbol).or_default() += position.net;
+            }
+            for (symbol, net) in nets {
+                portfolio.update_position(VENUE, session_id, symbol, &self.underlying(symbol), net);
+            }
+        }
+        self.portfolio = portfolio;
+        self
+    }
+
+    /// 启动资金流水同步任务，每次同步后与本地计算的已实现盈亏对账
+    pub async fn with_income(mut self, config: IncomeConfig) -> anyhow::Result<Self> {
+        if !config.enabled {
+            return Ok(self);
+        }
+        let db = Arc::new(IncomeDB::new(&config.db).await?);
+        let rest = self.rest.clone();
+        let endpoints = self.endpoints;
+        let alerter = self.alerter.clone();
+        let income = db.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.sync_secs.max(1)));
+            loop {
+                interval.tick().await;
+                match sync_income(&rest, &endpoints, &income, config.lookback_days).await {
+                    Ok(rows) => debug!("Sync {} incomes", rows),
+                    Err(e) => {
+                        error!("Sync income failed: {}", e);
+                        continue;
+                    }
+                }
+                match income.reconcile(config.tolerance).await {
+                    Ok(mismatches) => {
+                        for m in mismatches {
+                            alerter.on_reconcile_mismatch(
+                                ReconcileKind::Pnl,
+                                &m.symbol,
+                                m.local,
+                                m.remote,
+                            );
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+        });
+
+        self.income = Some(db);
+        Ok(self)
+    }
+
+    /// 开启成交历史查询
+    pub async fn with_my_trades(mut self, config: MyTradesConfig) -> anyhow::Result<Self> {
+        let window = 7 * 24 * 3600 * 1000;
+        self.my_trades = MyTrades::open(config, self.endpoints.user_trades, window).await?;
+        Ok(self)
+    }
+
+    /// 交易对的标的资产，未知交易对按交易对本身计
+    fn underlying(&self, symbol: &str) -> String {
+        match self.products.get(&symbol.to_lowercase()) {
+            Some(product) => product.baseAsset.clone(),
+            None => symbol.to_uppercase(),
+        }
+    }
+
+    fn cancel_order(&mut self, session_id: u16, order_id: u32, symbol: &str) {
+        if let Some((addr, order)) = self.order_caps.cancel(session_id, order_id) {
+            self.cancel_queued(&addr, &order);
+            return;
+        }
+        if let Some(dry_run) = self.dry_run.as_mut() {
+            if let Some((tx, order)) = dry_run.cancel(session_id, order_id) {
+                self.on_dry_run(&tx, &order, State::CANCELED);
+            }
+            return;
+        }
+        let symbol = symbol.to_uppercase();
+        let orig = u64::from(session_id) << 32 | u64::from(order_id);
+        // 撤单只登记不设上限，避免拥塞时连撤单也被拒
+        let guard = self.in_flight.acquire_unchecked(session_id);
+
+        if let Some(wsapi) = self.wsapi.as_mut().filter(|w| w.available()) {
+            let mut params = Map::new();
+            params.insert("symbol".into(), Value::String(symbol));
+            params.insert("origClientOrderId".into(), Value::String(orig.to_string()));
+            wsapi.send(
+                "order.cancel",
+                params,
+                WsApiPending::Cancel { _guard: guard },
+            );
+            return;
+        }
+
+        let rest = self.rest.clone();
+        let path = self.endpoints.order;
+        let latency = self.order_latency.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let start = Instant::now();
+            let result = rest.cancel(path, symbol, orig).await;
+            latency.record_rest(start.elapsed().as_millis() as u64);
+            if let Err(e) = result {
+                error!("{}", e)
+            }
+        });
+    }
+
+    /// 告警并撤销过期订单巡检发现的订单
+    fn cancel_stale_orders(&mut self, now: Instant) {
+        for stale in self.watchdog.check(now) {
+            warn!("{}", stale);
+            self.alerter
+                .on_stale_order(stale.session_id, stale.order_id, stale.to_string());
+            if stale.open() && self.watchdog.cancels() {
+                self.pegs.on_cancel(stale.session_id, stale.order_id);
+                self.replaces.on_cancel(stale.session_id, stale.order_id);
+                self.cancel_order(stale.session_id, stale.order_id, &stale.symbol);
+            }
+        }
+    }
+
+    /// 撤销排队中的订单，与演练模式一样由网关给出撤单回报
+    fn cancel_queued(&mut self, addr: &SocketAddr, order: &BinanceOrder) {
+        info!(
+            "Cancel queued order {} of session {}",
+            order.id, order.session_id
+        );
+        if let Some(tx) = self.txs.get(addr).cloned() {
+            self.on_dry_run(&tx, order, State::CANCELED);
+        }
+    }
+
+    /// 发出挂单数降到上限以下的排队订单，拒绝排队超时的订单
+    fn release_queued(&mut self) {
+        let (ready, expired) = self.order_caps.release(Instant::now());
+        for (addr, order) in expired {
+            warn!("Reject order {:?}: queued too long", order);
+            if let Some(tx) = self.txs.get(&addr).cloned() {
+                self.alerter
+                    .on_order_state(order.session_id, State::REJECTED);
+                self.reject(&tx, &order);
+            }
+        }
+        for (addr, order, cap) in ready {
+            info!(
+                "Release queued order {} of session {}",
+                order.id, order.session_id
+            );
+            let Some(tx) = self.txs.get(&addr) else {
+                self.order_caps.on_reject(order.session_id, order.id);
+                continue;
+            };
+            self.notify(tx, &cap);
+            if let Err(e) = self.add_order(&addr, &order) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    fn notify<T: Serialize>(&self, tx: &ClientSender, message: &T) {
+        if let Err(e) = tx.reply(message) {
+            error!("{}", e);
+        }
+    }
+
+    /// 处理 WS-API 响应，并每分钟输出一次两条下单路径的延迟
+    fn poll_wsapi(&mut self) {
+        let Some(wsapi) = self.wsapi.as_mut() else {
+            return;
+        };
+        for event in wsapi.poll() {
+            match event {
+                WsApiEvent::Accepted(WsApiPending::Place { order, sent, .. }, result) => {
+                    self.lifecycle
+                        .mark(correlation_id(order.session_id, order.id), Stage::Acked);
+                    self.ops_report.on_latency(sent.elapsed());
+                    // 先于账户推送到达时作为 NEW 回报转发，见 [`binance::dedup`]
+                    if let Some(update) = OrderUpdate::from_wsapi(&result) {
+                        if !self
+                            .events
+                            .seen(update.symbol(), update.exchange_order_id())
+                        {
+                            self.on_order(&update);
+                        }
+                    }
+                }
+                WsApiEvent::Accepted(WsApiPending::Cancel { .. }, _) => (),
+                WsApiEvent::Rejected(
+                    WsApiPending::Place {
+                        tx, order, sent, ..
+                    },
+                    e,
+                ) => {
+                    self.order_caps.on_reject(order.session_id, order.id);
+                    self.ops_report.on_latency(sent.elapsed());
+                    self.ops_report.on_reject(&e);
+                    let addr = self
+                        .session_id
+                        .iter()
+                        .find(|(_, id)| **id == order.session_id)
+                        .map(|(addr, _)| *addr);
+                    let tick = self
+                        .products
+                        .get(&order.symbol.to_lowercase())
+                        .map(|p| p.tick_size())
+                        .unwrap_or_default();
+                    let (code, msg) = gtx_retry::split_wsapi_error(&e);
+                    let retried = addr.is_some_and(|addr| {
+                        self.gtx_retries.on_reject(addr, &order, tick, code, msg)
+                    });
+                    if retried {
+                        warn!("Post-only order rejected, retry: {}", e);
+                        continue;
+                    }
+                    error!("Reject order {:?}: {}", order, e);
+                    self.alerter
+                        .on_order_state(order.session_id, State::REJECTED);
+                    self.reject(&tx, &order);
+                }
+                WsApiEvent::Rejected(WsApiPending::Cancel { .. }, e) => error!("{}", e),
+                WsApiEvent::TimedOut(pending) => {
+                    warn!("WS-API request timed out: {:?}", pending)
+                }
+            }
+        }
+
+        if self.latency_report.elapsed() >= Duration::from_secs(60) {
+            self.latency_report = Instant::now();
+            let (rest, wsapi) = self.order_latency.snapshot();
+            info!(
+                "Order latency rest {:.1}ms x{} (max {}ms), wsapi {:.1}ms x{} (max {}ms)",
+                rest.mean_ms(),
+                rest.count,
+                rest.max_ms,
+                wsapi.mean_ms(),
+                wsapi.count,
+                wsapi.max_ms
+            );
+        }
+    }
+
+    fn apply_quotes(&mut self, actions: Vec<QuoteAction>) {
+        for action in actions {
+            match action {
+                QuoteAction::Place(addr, order) => {
+                    if let Err(e) = self.add_order(&addr, &order) {
+                        error!("{}", e);
+                    }
+                }
+                QuoteAction::Cancel {
+                    session_id,
+                    order_id,
+                    symbol,
+                } => self.cancel_order(session_id, order_id, &symbol),
+            }
+        }
+    }
+
+    fn apply_pegs(&mut self, actions: Vec<PegAction>) {
+        for action in actions {
+            match action {
+                PegAction::Place(addr, order) => {
+                    if let Err(e) = self.add_order(&addr, &order) {
+                        error!("{}", e);
+                    }
+                }
+                PegAction::Cancel {
+                    session_id,
+                    order_id,
+                    symbol,
+                } => self.cancel_order(session_id, order_id, &symbol),
+            }
+        }
+    }
+
+    fn apply_replaces(&mut self, actions: Vec<ReplaceAction>) {
+        for action in actions {
+            match action {
+                ReplaceAction::Place(addr, order) => {
+                    if let Err(e) = self.add_order(&addr, &order) {
+                        error!("{}", e);
+                    }
+                }
+                ReplaceAction::Cancel {
+                    session_id,
+                    order_id,
+                    symbol,
+                } => self.cancel_order(session_id, order_id, &symbol),
+            }
+        }
+    }
+
+    /// 演练模式下的模拟回报，与交易所回报一样经过钉住、改单与报价的处理
+    fn on_dry_run(&mut self, tx: &ClientSender, order: &BinanceOrder, state: State) {
+        let (session_id, order_id) = (order.session_id, order.id);
+        self.alerter.on_order_state(session_id, state);
+        self.order_caps.on_order(session_id, order_id, state);
+        let (forward, actions) = self.pegs.on_order(session_id, order_id, state, 0.0);
+        self.apply_pegs(actions);
+        if !forward {
+            return;
+        }
+        let (forward, actions) = self.replaces.on_order(session_id, order_id, state, 0.0);
+        self.apply_replaces(actions);
+        if !forward {
+            return;
+        }
+        dryrun::notify(tx, order, state);
+        let actions = self.quotes.on_order(session_id, order_id, state);
+        self.apply_quotes(actions);
+    }
+
+    fn reject(&self, tx: &ClientSender, order: &BinanceOrder) {
+        self.order_caps.on_reject(order.session_id, order.id);
+        self.quotes.on_reject(order.session_id, order.id);
+        self.watchdog.on_reject(order.session_id, order.id);
+        self.pegs.on_reject(order.session_id, order.id);
+        self.replaces.on_reject(order.session_id, order.id);
+        let order = SOrder::new(
+            order.id,
+            order.symbol.clone(),
+            order.side,
+            State::REJECTED,
+            order.order_type,
+            order.tif,
+            order.quantity,
+            order.price,
+        );
+
+        if let Err(e) = tx.reply(&order) {
+            error!("{}", e);
+        }
+    }
 }
 impl Trade for UsdtTrade {
     fn disconnected(&self) -> bool {
@@ -76,21 +874,214 @@ impl Trade for UsdtTrade {
         &self.products
     }
 
+    /// 持仓差额计入 `adopt_session`，该会话需要已登录
+    async fn sanity_check(&mut self, req: &SSanityCheck) -> anyhow::Result<SSanityReport> {
+        if self.dry_run.is_some() {
+            anyhow::bail!("sanity_check is not available in dry run");
+        }
+        let (orders, positions, balances) = tokio::join!(
+            sanity::fetch_open_orders(&self.rest, self.endpoints.open_orders),
+            get_position_amounts(&self.rest, &self.endpoints),
+            get_balances(&self.rest, &self.endpoints)
+        );
+        let exchange = ExchangeState {
+            orders: orders?,
+            positions: Some(positions?),
+            balances: Some(balances?),
+        };
+        let mut local = LocalState {
+            orders: working_orders(&self.session),
+            balances: self.balances.clone(),
+            ..Default::default()
+        };
+        // 没有登录过的会话用持仓库中的记录
+        let stored = self
+            .posdb
+            .sessions()
+            .filter(|(session_id, _)| !self.session.contains_key(session_id))
+            .map(|(_, positions)| positions);
+        let sessions = self.session.values().map(Session::positions).chain(stored);
+        for position in sessions.flat_map(|positions| positions.values()) {
+            *local.positions.entry(position.key()).or_default() += position.net;
+        }
+        let mut report = sanity::compare(&local, &exchange);
+        sanity::alert(&report, &self.alerter);
+        if !req.heal {
+            return Ok(report);
+        }
+
+        report.canceled =
+            sanity::cancel_orders(&self.rest, self.endpoints.order, &report.unknown_orders).await;
+        let Some(session_id) = req.adopt_session else {
+            return Ok(report);
+        };
+        let diffs: Vec<_> = report
+            .positions
+            .iter()
+            .map(|d| (d.key.clone(), d.exchange - d.local))
+            .collect();
+        for (key, diff) in diffs {
+            let Some(session) = self.session.get_mut(&session_id) else {
+                warn!("Session {} to adopt positions is not logged in", session_id);
+                break;
+            };
+            let position = session.adopt_position(&key, diff)?;
+            let net = session.net(&position.symbol).unwrap_or_default();
+            let underlying = self.underlying(&position.symbol);
+            self.portfolio
+                .update_position(VENUE, session_id, &position.symbol, &underlying, net);
+            report.adopted.push(key);
+        }
+        Ok(report)
+    }
+
+    fn get_liquidity_stats(&self, req: &SLiquidityReq) -> Vec<SLiquidity> {
+        self.liquidity.report(req)
+    }
+
+    fn export_state(&self) -> Result<SGatewayState, SError> {
+        let counters = BTreeMap::from([(state::ROLL.to_string(), self.rolls.next_id())]);
+        Ok(state::export(VENUE, &self.session, &self.posdb, counters))
+    }
+
+    /// 导入的持仓同时计入组合敞口
+    async fn import_state(&mut self, gateway: &SGatewayState) -> Result<SImportReport, SError> {
+        let mut report = state::import(VENUE, gateway, &mut self.session, &self.posdb).await?;
+        if let Some(next_id) = gateway.counters.get(state::ROLL) {
+            self.rolls.advance_id(*next_id);
+        }
+        report
+            .counters
+            .insert(state::ROLL.to_string(), self.rolls.next_id());
+        for session_id in report.sessions.iter() {
+            let Some(session) = self.session.get(session_id) else {
+                continue;
+            };
+            let symbols: HashSet<&str> = session
+                .positions()
+                .values()
+                .map(|p| p.symbol.as_str())
+                .collect();
+            for symbol in symbols {
+                let net = session.net(symbol).unwrap_or_default();
+                self.portfolio.update_position(
+                    VENUE,
+                    *session_id,
+                    symbol,
+                    &self.underlying(symbol),
+                    net,
+                );
+            }
+        }
+        Ok(report)
+    }
+
+    fn get_attribution(&self, session_id: u16) -> Option<Vec<SAttribution>> {
+        self.session
+            .get(&session_id)
+            .map(|session| session.attribution())
+    }
+
+    /// 已登录过的会话返回带入场价、杠杆等字段的实时持仓，否则返回持仓库中的记录
     fn get_positions(&self, session_id: u16) -> Option<&HashMap<String, Position>> {
-        self.posdb.get_positions(session_id)
+        match self.session.get(&session_id) {
+            Some(session) => Some(session.positions()),
+            None => self.posdb.get_positions(session_id),
+        }
     }
 
     async fn get_products(&mut self) -> anyhow::Result<()> {
-        self.products = get_positions(&self.rest).await?;
+        self.products = get_positions(&self.public).await?;
+        self.order_caps
+            .set_products(&self.products, Limits::default());
+        match get_leverage_brackets(&self.rest, &self.endpoints).await {
+            Ok(brackets) => self.leverage_brackets = brackets,
+            Err(e) => warn!("Get leverage brackets failed: {}", e),
+        }
+        attach_brackets(&mut self.products, &self.leverage_brackets);
+        self.deliveries.on_products(&self.products);
+        self.rolls.on_products(&self.products);
         Ok(())
     }
 
-    async fn process(&mut self) -> anyhow::Result<bool> {
-        let msg = self.account.process().await?;
+    async fn borrow_repay(
+        &mut self,
+        _loan_type: LoanType,
+        _loan: &SLoan,
+    ) -> anyhow::Result<Option<SError>> {
+        Ok(Some(SError {
+            code: error_code::UNSUPPORTED,
+            msg: "margin loan is not supported by usdt futures".into(),
+        }))
+    }
 
-        if let Some(s) = msg {
-            if let Event::OrderUpdate(order) = serde_json::from_str::<Event>(&s)? {
-                self.on_order(&order);
+    async fn get_income(&mut self, req: &SIncomeReq) -> anyhow::Result<Vec<Income>> {
+        match &self.income {
+            Some(db) => db.query(req).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_my_trades(&mut self, req: &SMyTradesReq) -> Result<Vec<MyTrade>, SError> {
+        let Some(my_trades) = &self.my_trades else {
+            return Err(SError {
+                code: error_code::UNSUPPORTED,
+                msg: "trade history is disabled".into(),
+            });
+        };
+        if !self.products.contains_key(&req.symbol.to_lowercase()) {
+            return Err(SError {
+                code: error_code::INVALID_SYMBOL,
+                msg: format!("unknown symbol {}", req.symbol),
+            });
+        }
+        my_trades.get(&self.rest, req).await
+    }
+
+    async fn process(&mut self) -> anyhow::Result<bool> {
+        self.poll_wsapi();
+        if let Some(event) = self.account.process().await? {
+            match event {
+                Event::OrderUpdate(order) => self.on_order(&order),
+                Event::AccountUpdate(update) => {
+                    for asset in update.a.B.iter() {
+                        if let Ok(balance) = asset.wb.parse() {
+                            self.balances.insert(asset.a.clone(), balance);
+                        }
+                    }
+                    for position in update.a.P.iter() {
+                        let amount = position.pa.parse().unwrap_or(f64::NAN);
+                        self.deliveries.on_account_position(
+                            &position.s.to_lowercase(),
+                            amount,
+                            update.E,
+                        );
+                        self.on_position_detail(Position::from(position));
+                    }
+                }
+                Event::AccountConfigUpdate(update) => {
+                    self.on_position_detail(Position {
+                        leverage: Some(update.ac.l),
+                        ..Position::new(&update.ac.s.to_lowercase(), 0.0)
+                    });
+                }
+                Event::RiskLevelChange(change) => {
+                    if let Some(pm) = self.pm.as_ref() {
+                        if pm.on_risk_level(&change) {
+                            self.alerter
+                                .on_margin_call(format!("uniMMR {} status {}", change.u, change.s));
+                        }
+                    }
+                }
+                Event::MarginCall(call) => {
+                    warn!("{:?}", call);
+                    self.alerter.on_margin_call(format!(
+                        "cross wallet balance {}, {} positions at risk",
+                        call.cw,
+                        call.p.len()
+                    ));
+                }
+                _ => (),
             }
         }
 
@@ -98,39 +1089,259 @@ impl Trade for UsdtTrade {
     }
 
     fn add_order(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+        let resolved;
+        let order = match self.rolls.resolve(&order.symbol) {
+            Some(symbol) => {
+                resolved = BinanceOrder {
+                    symbol,
+                    ..order.clone()
+                };
+                &resolved
+            }
+            None => order,
+        };
         match self.txs.get_mut(addr) {
             Some(tx) => {
-                let rest = self.rest.clone();
                 let tx = tx.clone();
+                let cid = correlation_id(order.session_id, order.id);
+                let span = order_span(cid);
+                let _enter = span.enter();
+                self.lifecycle.mark(cid, Stage::Request);
+                if let Err(e) = self.halts.check(&order.symbol) {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let tick = self
+                    .products
+                    .get(&order.symbol.to_lowercase())
+                    .map(|p| p.tick_size())
+                    .unwrap_or_default();
+                let bbo = self.book_tickers.get(&order.symbol);
+                let pegged;
+                let order = match order.peg {
+                    Some(_) => {
+                        pegged = self.pegs.register(*addr, order, tick, bbo);
+                        &pegged
+                    }
+                    None => order,
+                };
+                let posted;
+                let order = match post_only::prepare(order, bbo, tick, true) {
+                    Ok(Some(prepared)) => {
+                        posted = prepared;
+                        &posted
+                    }
+                    Ok(None) => order,
+                    Err(e) => {
+                        warn!("Reject order {:?}: {}", order, e);
+                        self.reject(&tx, order);
+                        return Ok(());
+                    }
+                };
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as i64)
+                    .unwrap_or_default();
+                let book = self.depth_books.get(&order.symbol);
+                let clamped;
+                let order =
+                    match depth_check::check(&self.depth_check, order, book.as_ref(), tick, now) {
+                        Verdict::Pass => order,
+                        Verdict::Warn(e) => {
+                            warn!("Thin book for order {:?}: {}", order, e);
+                            order
+                        }
+                        Verdict::Clamp(clipped, e) => {
+                            warn!("Clamp order {:?} at {}: {}", order, clipped.price, e);
+                            clamped = clipped;
+                            &clamped
+                        }
+                        Verdict::Reject(e) => {
+                            warn!("Reject order {:?}: {}", order, e);
+                            self.alerter.on_risk_limit_breach(order.session_id, e);
+                            self.reject(&tx, order);
+                            return Ok(());
+                        }
+                    };
+                if let Err(e) = order
+                    .check_position_side(self.dual_side)
+                    .and_then(|_| order.check_side_effect(false))
+                    .and_then(|_| self.check_filters(order))
+                {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let reference = fat_finger::reference(bbo);
+                if let Err(e) = fat_finger::check(&self.fat_finger, order, reference) {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.alerter.on_risk_limit_breach(order.session_id, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                if let Some(Err(e)) = self.pm.as_ref().map(|pm| pm.check_order()) {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.alerter.on_risk_limit_breach(order.session_id, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                let quantity = match order.side {
+                    Side::BUY => order.quantity,
+                    Side::SELL => -order.quantity,
+                };
+                if let Err(e) = self.check_leverage_bracket(order, quantity) {
+                    warn!("Reject order {:?}: {}", order, e);
+                    self.alerter.on_risk_limit_breach(order.session_id, e);
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                if let Err(breach) = self.portfolio.check_order(
+                    order.session_id,
+                    VENUE,
+                    &order.symbol,
+                    &self.underlying(&order.symbol),
+                    quantity,
+                    order.price,
+                ) {
+                    warn!("Reject order {:?}: {}", order, breach);
+                    self.alerter
+                        .on_risk_limit_breach(order.session_id, breach.to_string());
+                    self.reject(&tx, order);
+                    return Ok(());
+                }
+                match self.order_caps.admit(*addr, order, Instant::now()) {
+                    Admission::Send => (),
+                    Admission::Queue(cap) => {
+                        warn!(
+                            "Queue order {:?}: {} of {} reached {}",
+                            order, cap.filter, cap.symbol, cap.limit
+                        );
+                        self.notify(&tx, &cap);
+                        return Ok(());
+                    }
+                    Admission::Reject(e) => {
+                        warn!("Reject order {:?}: {}", order, e);
+                        self.alerter.on_risk_limit_breach(order.session_id, e);
+                        self.reject(&tx, order);
+                        return Ok(());
+                    }
+                }
+
+                let Some(guard) = self.in_flight.acquire(order.session_id) else {
+                    warn!(
+                        "Reject order {:?}: {} requests in flight",
+                        order,
+                        self.in_flight.max()
+                    );
+                    self.alerter
+                        .on_order_state(order.session_id, State::REJECTED);
+                    self.reject(&tx, order);
+                    return Ok(());
+                };
+                self.lifecycle.mark(cid, Stage::Validated);
+
+                if let Some(session) = self.session.get_mut(&order.session_id) {
+                    session.tag_order(order, attribution::arrival_price(order, bbo));
+                }
+                if let Some(ttl_ms) = order.ttl_ms {
+                    if let Some(session) = self.session.get_mut(&order.session_id) {
+                        let deadline = Instant::now() + Duration::from_millis(ttl_ms);
+                        session.set_deadline(order.id, &order.symbol, deadline);
+                    }
+                }
+
+                if let Some(dry_run) = self.dry_run.as_mut() {
+                    drop(guard);
+                    dry_run.place(&tx, order);
+                    self.on_dry_run(&tx, order, State::NEW);
+                    return Ok(());
+                }
+
+                self.watchdog
+                    .on_sent(order.session_id, order.id, &order.symbol, Instant::now());
+                self.rolls.on_sent(order);
+                let rest = self.rest.clone();
+                let path = self.endpoints.order;
+                let alerter = self.alerter.clone();
+                let quotes = self.quotes.clone();
+                let pegs = self.pegs.clone();
+                let replaces = self.replaces.clone();
+                let watchdog = self.watchdog.clone();
+                let gtx_retries = self.gtx_retries.clone();
+                let order_caps = self.order_caps.clone();
+                let ops_report = self.ops_report.clone();
+                let (addr, retry) = (*addr, order.clone());
 
                 let symbol = order.symbol.clone();
                 let price = order.price;
                 let quantity = order.quantity;
-                let side = order.side.clone();
-                let order_type = order.order_type.clone();
-                let tif = order.tif.clone();
+                let side = order.side;
+                let order_type = order.order_type;
+                let tif = order.tif;
                 let session_id = order.session_id;
                 let id = order.id;
 
-                tokio::spawn(async move {
-                    match rest
-                        .add_order(
-                            "/fapi/v1/order",
-                            symbol.clone().to_uppercase(),
-                            price.to_string(),
-                            quantity.to_string(),
-                            format!("{:?}", side),
-                            format!("{:?}", order_type),
-                            format!("{:?}", tif),
-                            session_id,
-                            id,
-                        )
-                        .await
-                    {
+                let mut params = order_params(
+                    symbol.to_uppercase(),
+                    price.to_string(),
+                    quantity.to_string(),
+                    format!("{:?}", side),
+                    format!("{:?}", order_type),
+                    format!("{:?}", tif),
+                    session_id,
+                    id,
+                );
+                if let Some(position_side) = order.position_side {
+                    params.push(("positionSide".into(), format!("{:?}", position_side)));
+                }
+
+                if let Some(wsapi) = self.wsapi.as_mut().filter(|w| w.available()) {
+                    let params = params
+                        .into_iter()
+                        .map(|(k, v)| (k, Value::String(v)))
+                        .collect();
+                    let pending = WsApiPending::Place {
+                        tx,
+                        order: Box::new(order.clone()),
+                        _guard: guard,
+                        sent: Instant::now(),
+                    };
+                    wsapi.send("order.place", params, pending);
+                    self.lifecycle.mark(cid, Stage::Sent);
+                    self.ops_report.on_placed();
+                    return Ok(());
+                }
+
+                let latency = self.order_latency.clone();
+                let lifecycle = self.lifecycle.clone();
+                self.lifecycle.mark(cid, Stage::Sent);
+                self.ops_report.on_placed();
+                let task = async move {
+                    // 收到响应后释放在途名额
+                    let _guard = guard;
+                    let start = Instant::now();
+                    let result = rest.post(path, &params, true).await;
+                    latency.record_rest(start.elapsed().as_millis() as u64);
+                    ops_report.on_latency(start.elapsed());
+                    match result {
                         Ok(rsp) => {
+                            lifecycle.mark(cid, Stage::Acked);
                             // exchange rej
                             if let Ok(e) = rsp.json::<cryptoflow::chat::SError>().await {
+                                order_caps.on_reject(session_id, id);
+                                ops_report.on_reject(&format!("{} {}", e.code, e.msg));
+                                if gtx_retries.on_reject(addr, &retry, tick, e.code.into(), &e.msg)
+                                {
+                                    warn!("Post-only order rejected, retry: {:?}", e);
+                                    return;
+                                }
                                 error!("{:?}", e);
+                                alerter.on_order_state(session_id, State::REJECTED);
+                                quotes.on_reject(session_id, id);
+                                pegs.on_reject(session_id, id);
+                                replaces.on_reject(session_id, id);
+                                watchdog.on_reject(session_id, id);
                                 let order = SOrder::new(
                                     id,
                                     symbol,
@@ -142,19 +1353,20 @@ impl Trade for UsdtTrade {
                                     price,
                                 );
 
-                                match serde_json::to_string(&order) {
-                                    Ok(s) => {
-                                        if let Err(e) = tx.send(Message::Text(s.into())) {
-                                            error!("{}", e);
-                                        }
-                                    }
-                                    Err(e) => error!("{}", e),
+                                if let Err(e) = tx.reply(&order) {
+                                    error!("{}", e);
                                 }
                             }
                         }
-                        // network error
+                        // network error，订单可能已经挂出，留给过期订单巡检核对
                         Err(e) => {
                             error!("{:?}", e);
+                            order_caps.on_reject(session_id, id);
+                            ops_report.on_reject("network");
+                            alerter.on_order_state(session_id, State::REJECTED);
+                            quotes.on_reject(session_id, id);
+                            pegs.on_reject(session_id, id);
+                            replaces.on_reject(session_id, id);
                             let order = SOrder::new(
                                 id,
                                 symbol,
@@ -166,17 +1378,13 @@ impl Trade for UsdtTrade {
                                 price,
                             );
 
-                            match serde_json::to_string(&order) {
-                                Ok(s) => {
-                                    if let Err(e) = tx.send(Message::Text(s.into())) {
-                                        error!("{}", e);
-                                    }
-                                }
-                                Err(e) => error!("{}", e),
+                            if let Err(e) = tx.reply(&order) {
+                                error!("{}", e);
                             }
                         }
                     }
-                });
+                };
+                tokio::spawn(task.instrument(span.clone()));
             }
             None => warn!("Missing session {}, maybe a bug", addr),
         }
@@ -187,30 +1395,72 @@ impl Trade for UsdtTrade {
     fn cancel(&mut self, addr: &SocketAddr, cancel: &BinanceCancel) -> anyhow::Result<()> {
         match self.txs.get_mut(addr) {
             Some(_) => {
-                let rest = self.rest.clone();
-
-                let symbol = cancel.symbol.clone().to_uppercase();
-                let session_id = cancel.session_id;
-                let order_id = cancel.order_id;
-
-                let orig = u64::from(session_id) << 32 | u64::from(order_id);
-                tokio::spawn(async move {
-                    if let Err(e) = rest.cancel("/fapi/v1/order", symbol, orig).await {
-                        error!("{}", e)
-                    }
-                });
+                let symbol = self
+                    .rolls
+                    .resolve(&cancel.symbol)
+                    .unwrap_or_else(|| cancel.symbol.clone());
+                self.pegs.on_cancel(cancel.session_id, cancel.order_id);
+                self.replaces.on_cancel(cancel.session_id, cancel.order_id);
+                self.cancel_order(cancel.session_id, cancel.order_id, &symbol);
             }
             None => warn!("Missing session {}, maybe a bug", addr),
         }
         Ok(())
     }
 
+    /// 双边报价，只撤改与上一次报价相比发生变化的一侧
+    fn quote(&mut self, addr: &SocketAddr, quote: &BinanceQuote) -> anyhow::Result<()> {
+        if !self.txs.contains_key(addr) {
+            warn!("Missing session {}, maybe a bug", addr);
+            return Ok(());
+        }
+        let actions = self.quotes.on_quote(*addr, quote);
+        self.apply_quotes(actions);
+        Ok(())
+    }
+
+    fn replace(&mut self, addr: &SocketAddr, order: &BinanceOrder) -> anyhow::Result<()> {
+        if !self.txs.contains_key(addr) {
+            warn!("Missing session {}, maybe a bug", addr);
+            return Ok(());
+        }
+        let working = self
+            .session
+            .get(&order.session_id)
+            .is_some_and(|s| s.working_orders().contains_key(&order.id))
+            || self
+                .dry_run
+                .as_ref()
+                .is_some_and(|d| d.contains(order.session_id, order.id));
+        let actions = self.replaces.on_replace(*addr, order, working);
+        self.apply_replaces(actions);
+        Ok(())
+    }
+
+    /// 登录时开启了 cancel_on_disconnect 的会话，先撤销它所有未完成的订单
     fn handle_strategy_client_close(&mut self, addr: &SocketAddr) -> anyhow::Result<()> {
-        if let Some(_) = self.txs.remove(addr) {
+        let queued = self.order_caps.drain(|a, _| a == addr);
+        if !queued.is_empty() {
+            warn!("{} disconnected, drop {} queued orders", addr, queued.len());
+        }
+        if self.txs.remove(addr).is_some() {
             match self.session_id.remove(addr) {
                 Some(id) => match self.session.get_mut(&id) {
                     Some(session) => {
+                        let orders = session.orders_to_cancel();
                         session.set_active(None);
+                        if !orders.is_empty() {
+                            warn!(
+                                "Session {} disconnected, cancel {} orders",
+                                id,
+                                orders.len()
+                            );
+                        }
+                        for (order_id, symbol) in orders {
+                            self.pegs.on_cancel(id, order_id);
+                            self.replaces.on_cancel(id, order_id);
+                            self.cancel_order(id, order_id, &symbol);
+                        }
                     }
                     None => warn!("Session used by {} isn't exist, maybe a bug", addr),
                 },
@@ -220,11 +1470,152 @@ impl Trade for UsdtTrade {
         Ok(())
     }
 
+    fn cancel_all(&mut self, session_id: u16) -> usize {
+        let orders: Vec<_> = match self.session.get(&session_id) {
+            Some(session) => session
+                .working_orders()
+                .iter()
+                .map(|(id, symbol)| (*id, symbol.clone()))
+                .collect(),
+            None => return 0,
+        };
+        for (order_id, symbol) in orders.iter() {
+            self.pegs.on_cancel(session_id, *order_id);
+            self.replaces.on_cancel(session_id, *order_id);
+            self.cancel_order(session_id, *order_id, symbol);
+        }
+        let queued = self
+            .order_caps
+            .drain(|_, order| order.session_id == session_id);
+        for (addr, order) in queued.iter() {
+            self.cancel_queued(addr, order);
+        }
+        orders.len() + queued.len()
+    }
+
+    fn cancel_symbol(&mut self, symbol: &str) -> usize {
+        let mut orders = Vec::new();
+        for (session_id, session) in self.session.iter() {
+            for (order_id, s) in session.working_orders().iter() {
+                if s.eq_ignore_ascii_case(symbol) {
+                    orders.push((*session_id, *order_id, s.clone()));
+                }
+            }
+        }
+        for (session_id, order_id, symbol) in orders.iter() {
+            self.pegs.on_cancel(*session_id, *order_id);
+            self.replaces.on_cancel(*session_id, *order_id);
+            self.cancel_order(*session_id, *order_id, symbol);
+        }
+        let queued = self
+            .order_caps
+            .drain(|_, order| order.symbol.eq_ignore_ascii_case(symbol));
+        for (addr, order) in queued.iter() {
+            self.cancel_queued(addr, order);
+        }
+        orders.len() + queued.len()
+    }
+
+    fn expire_orders(&mut self) {
+        let now = Instant::now();
+        let mut orders = Vec::new();
+        for (session_id, session) in self.session.iter_mut() {
+            for (order_id, symbol) in session.expire_orders(now) {
+                orders.push((*session_id, order_id, symbol));
+            }
+        }
+        for (session_id, order_id, symbol) in orders {
+            info!(
+                "Order {} of session {} expired, cancel it",
+                order_id, session_id
+            );
+            self.pegs.on_cancel(session_id, order_id);
+            self.replaces.on_cancel(session_id, order_id);
+            self.cancel_order(session_id, order_id, &symbol);
+        }
+        self.cancel_stale_orders(now);
+        self.check_deliveries();
+        self.check_rolls();
+    }
+
+    fn reprice_orders(&mut self) {
+        let actions = self.pegs.reprice(&self.book_tickers, Instant::now());
+        self.apply_pegs(actions);
+        self.release_queued();
+        for (addr, order) in self.gtx_retries.take() {
+            info!(
+                "Retry post-only order {} of session {} at {}",
+                order.id, order.session_id, order.price
+            );
+            if let Err(e) = self.add_order(&addr, &order) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    fn roll_contracts(&self, alias: &str) -> Option<(String, String)> {
+        let chain = self.rolls.chain(alias)?;
+        Some((chain.contract.clone(), chain.next.clone()?))
+    }
+
+    fn roll(&mut self, addr: &SocketAddr, req: &SRollReq) -> Result<SRollResult, SError> {
+        let Some((session_id, session)) = self
+            .session_id
+            .get(addr)
+            .and_then(|id| Some((*id, self.session.get(id)?)))
+        else {
+            return Err(SError {
+                code: error_code::NOT_LOGIN,
+                msg: "login before rolling".into(),
+            });
+        };
+        let spread = match (req.spread, self.rolls.chain(&req.alias)) {
+            (Some(spread), _) => Some(spread),
+            (None, Some(chain)) => roll::mid_spread(
+                self.book_tickers.get(&chain.contract),
+                chain.next.as_ref().and_then(|to| self.book_tickers.get(to)),
+            ),
+            (None, None) => None,
+        };
+        let tick = self
+            .roll_contracts(&req.alias)
+            .and_then(|(_, to)| self.products.get(&to))
+            .map(|p| p.tick_size())
+            .unwrap_or_default();
+        let plan = self
+            .rolls
+            .plan(session_id, &req.alias, session.positions(), spread, tick)
+            .map_err(|msg| SError {
+                code: error_code::INVALID_ROLL,
+                msg,
+            })?;
+        info!(
+            "Session {} rolls {} from {} to {}: cancel {:?}, {} orders",
+            session_id,
+            req.alias,
+            plan.from,
+            plan.to,
+            plan.cancels,
+            plan.orders.len()
+        );
+        for (order_id, symbol) in plan.cancels.iter() {
+            self.pegs.on_cancel(session_id, *order_id);
+            self.replaces.on_cancel(session_id, *order_id);
+            self.cancel_order(session_id, *order_id, symbol);
+        }
+        for order in plan.orders.iter() {
+            if let Err(e) = self.add_order(addr, order) {
+                error!("Roll order {:?} failed: {}", order, e);
+            }
+        }
+        Ok(plan.result(&req.alias))
+    }
+
     async fn handle_strategy_client_login(
         &mut self,
         addr: &SocketAddr,
         req: &SRequest<SLogin>,
-        tx: &UnboundedSender<Message>,
+        tx: &ClientSender,
     ) -> anyhow::Result<Option<SError>> {
         let login = &req.params;
         let session_id = login.session_id;
@@ -238,15 +1629,25 @@ impl Trade for UsdtTrade {
                     }));
                 } else {
                     session.set_active(Some(tx.clone()));
+                    session.set_cancel_on_disconnect(login.cancel_on_disconnect);
+                    session.set_fills(login.fills);
                 }
             }
             None => {
-                let session = Session::new(session_id, self.posdb.clone(), tx.clone()).await?;
+                let mut session = Session::new(session_id, self.posdb.clone(), tx.clone())
+                    .await?
+                    .with_position_details(self.position_details.values());
+                // 交割后才登录的会话补做结算
+                for (symbol, delivery_time) in self.deliveries.settled() {
+                    session.settle(symbol, *delivery_time)?;
+                }
+                session.set_cancel_on_disconnect(login.cancel_on_disconnect);
+                session.set_fills(login.fills);
                 self.session.insert(session_id, session);
             }
         }
-        self.txs.insert(addr.clone(), tx.clone());
-        self.session_id.insert(addr.clone(), session_id);
+        self.txs.insert(*addr, tx.clone());
+        self.session_id.insert(*addr, session_id);
 
         info!("session addr {} -> {}", addr, session_id);
         Ok(None)
@@ -255,73 +1656,55 @@ impl Trade for UsdtTrade {
     #[allow(unused)]
     fn handle_strategy_client_subscribe(
         &mut self,
-        addr: &SocketAddr,
+        _addr: &SocketAddr,
         req: &SRequest<Vec<String>>,
-    ) -> Option<SError> {
-        let mut params = Vec::new();
-
-        for symbol in req.params.iter() {
-            let symbol = symbol.to_lowercase();
-            match symbol.split_once("@") {
-                Some((name, stream)) => {
-                    if !self.products.contains_key(name) {
-                        return Some(SError {
-                            code: error_code::INVALID_SYMBOL,
-                            msg: format!("invalid symbol {}", symbol),
-                        });
+    ) -> Vec<SStreamResult> {
+        req.params
+            .iter()
+            .map(|symbol| {
+                let error = |code, kind| {
+                    SStreamResult::rejected(
+                        symbol,
+                        SError {
+                            code,
+                            msg: format!("invalid {} {}", kind, symbol),
+                        },
+                    )
+                };
+                // 期权的流由行情模块校验
+                if binance::options::exchange_stream(symbol).is_some() {
+                    return SStreamResult::accepted(symbol);
+                }
+                match symbol.to_lowercase().split_once("@") {
+                    Some((name, _))
+                        if !self.products.contains_key(name)
+                            && self.rolls.resolve(name).is_none() =>
+                    {
+                        error(error_code::INVALID_SYMBOL, "symbol")
                     }
-                    if !self.validate_symbol(name, stream) {
-                        return Some(SError {
-                            code: error_code::INVALID_STREAM,
-                            msg: format!("invalid stream {}", symbol),
-                        });
+                    Some((name, stream)) if !self.validate_symbol(name, stream) => {
+                        error(error_code::INVALID_STREAM, "stream")
                     }
+                    Some(_) => SStreamResult::accepted(symbol),
+                    None => error(error_code::INVALID_SYMBOL, "symbol"),
                 }
-                None => {
-                    return Some(SError {
-                        code: error_code::INVALID_SYMBOL,
-                        msg: format!("invalid symbol {}", symbol),
-                    });
-                }
-            }
-            params.push(symbol);
-        }
-        None
+            })
+            .collect()
     }
 
-    #[allow(unused)]
-    fn validate_symbol(&self, symbol: &str, stream: &str) -> bool {
-        match stream.split_once(":") {
-            Some((stream, interval)) => {
-                match stream {
-                    "kline" => match interval {
-                        "1s" | "1m" | "3m" | "5m" | "15m" | "30m" | "1h" | "2h" | "4h" | "6h"
-                        | "8h" | "12h" | "1d" | "3d" | "1w" | "1M" => return true,
-                        _ => return false,
-                    },
-                    "depth" => match interval {
-                        "100ms" => return true,
-                        _ => return false,
-                    },
-                    _ => return false,
-                };
-            }
-            None => match stream {
-                "depth" | "bbo" => true,
-                _ => false,
-            },
-        }
+    fn validate_symbol(&self, _symbol: &str, stream: &str) -> bool {
+        validate_stream(stream)
     }
 
     fn handle_strategy_client_disconnect(
         &mut self,
         addr: &SocketAddr,
-        parser: &JsonParser,
+        parser: &RequestParser,
     ) -> anyhow::Result<()> {
-        if let Some(id) = parser.get("id") {
+        if let Some(id) = parser.id() {
             self.reply(
                 addr,
-                i64::deserialize(id)?,
+                id,
                 SError {
                     code: error_code::DISCONNECTED,
                     msg: "trade disconnected".into(),
@@ -337,37 +1720,234 @@ impl Trade for UsdtTrade {
         result: T,
     ) -> anyhow::Result<()> {
         if let Some(tx) = self.txs.get_mut(addr) {
-            let response = SResponse {
-                id: id,
-                result: result,
-            };
+            let response = SResponse { id, result };
 
             debug!("{:?}", response);
-            let rsp = Message::Text(serde_json::to_string(&response)?.into());
-            tx.send(rsp)?;
+            tx.reply(&response)?;
         }
         Ok(())
     }
 }
 
+impl UsdtTrade {
+    /// 按交易对的过滤器校验，合约没有 MAX_POSITION
+    fn check_filters(&self, order: &BinanceOrder) -> Result<(), String> {
+        let Some(product) = self.products.get(&order.symbol.to_lowercase()) else {
+            return Ok(());
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        let reference = order_check::reference(product, &self.book_tickers, now);
+        order_check::check(&self.order_check, product, order, reference, None)
+    }
+
+    /// 按账户当前的杠杆检查下单后持仓的名义价值，超过分层上限时交易所会拒绝
+    ///
+    /// 持仓为所有已登录会话在该交易对（双向持仓时为同一腿）上的持仓之和；市价单按最优价的中间价估算，
+    /// 没有杠杆、分层或价格时不检查
+    fn check_leverage_bracket(&self, order: &BinanceOrder, quantity: f64) -> Result<(), String> {
+        let symbol = order.symbol.to_lowercase();
+        let Some(brackets) = self.leverage_brackets.get(&symbol) else {
+            return Ok(());
+        };
+        let leg =
+            |p: &Position| p.symbol == symbol && (!self.dual_side || p.side == order.position_side);
+        let Some(leverage) = self
+            .position_details
+            .values()
+            .filter(|p| leg(p))
+            .find_map(|p| p.leverage)
+        else {
+            return Ok(());
+        };
+        let position: f64 = self
+            .session
+            .values()
+            .flat_map(|s| s.positions().values())
+            .filter(|p| leg(p))
+            .map(|p| p.net)
+            .sum();
+        let price = match order.price > 0.0 {
+            true => order.price,
+            false => self
+                .book_tickers
+                .get(&symbol)
+                .map(|(bid, ask)| (bid + ask) / 2.0)
+                .unwrap_or_default(),
+        };
+        leverage::check_notional(brackets, leverage, position, quantity, price)
+    }
+}
+
+impl UsdtTrade {
+    /// 推送进入提醒时间的移仓提醒
+    fn check_rolls(&mut self) {
+        if !self.rolls.enabled() {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        for notice in self.rolls.notices(now) {
+            info!(
+                "{} rolls from {} to {:?} in {}s",
+                notice.alias,
+                notice.contract,
+                notice.next,
+                notice.remaining_ms / 1000
+            );
+            for session in self.session.values() {
+                if let Err(e) = session.notify_roll(&notice) {
+                    error!("{}", e);
+                }
+            }
+        }
+    }
+
+    /// 推送到点的交割提醒，结算已交割合约的会话持仓
+    fn check_deliveries(&mut self) {
+        if !self.deliveries.enabled() {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        for notice in self.deliveries.notices(now) {
+            info!(
+                "{} delivers in {}s",
+                notice.symbol,
+                notice.remaining_ms / 1000
+            );
+            for session in self.session.values() {
+                if let Err(e) = session.notify_delivery(
+                    &notice.symbol,
+                    notice.delivery_time,
+                    notice.remaining_ms,
+                ) {
+                    error!("{}", e);
+                }
+            }
+        }
+        for (symbol, delivery_time) in self.deliveries.settlements(now) {
+            info!("{} delivered, settle session positions", symbol);
+            for session in self.session.values_mut() {
+                if let Err(e) = session.settle(&symbol, delivery_time) {
+                    error!("{}", e);
+                }
+            }
+        }
+    }
+}
+
 // callback
 impl UsdtTrade {
+    /// 更新账户级别的持仓，推送给持有该品种的会话
+    fn on_position_detail(&mut self, detail: Position) {
+        let detail = match self.position_details.get_mut(&detail.key()) {
+            Some(position) => {
+                position.apply(&detail);
+                position
+            }
+            None => self.position_details.entry(detail.key()).or_insert(detail),
+        };
+        for session in self.session.values_mut() {
+            if let Err(e) = session.on_position_detail(detail) {
+                error!("{}", e);
+            }
+        }
+    }
+
     fn on_order(&mut self, order: &OrderUpdate) {
         info!("{:?}", order);
+        if !self.events.first(EventKey::of(order)) {
+            debug!("Duplicate order update {} of {}", order.o.i, order.o.s);
+            return;
+        }
         let client_order_id = order.o.c.parse::<u64>();
 
         match client_order_id {
             Ok(client_order_id) => {
+                let span = order_span(client_order_id);
+                let _enter = span.enter();
+                self.lifecycle.mark(client_order_id, Stage::Report);
                 let session_id = (client_order_id >> 32) as u16;
+                self.alerter.on_order_state(session_id, order.state());
+                if let Some(db) = &self.income {
+                    db.record_pnl(order.symbol(), order.o.rp.parse().unwrap_or_default());
+                }
+
+                let order_id = (client_order_id & 0xFFFFFFFF) as u32;
+                self.watchdog.on_update(
+                    session_id,
+                    order_id,
+                    order.symbol(),
+                    order.state(),
+                    Instant::now(),
+                );
+                self.rolls.on_update(
+                    session_id,
+                    order_id,
+                    order.state(),
+                    order.o.z.parse().unwrap_or_default(),
+                );
+                self.ops_report.on_order(order.state());
+                if let Some(fill) = order.fill() {
+                    self.liquidity.on_fill(session_id, &fill);
+                    self.ops_report.on_fill(&fill);
+                }
+                self.gtx_retries
+                    .on_order(session_id, order_id, order.state());
+                self.order_caps
+                    .on_order(session_id, order_id, order.state());
+                let trd_vol = order.trd_vol().unwrap_or_default();
+                let (forward, actions) =
+                    self.pegs
+                        .on_order(session_id, order_id, order.state(), trd_vol);
+                self.apply_pegs(actions);
+                if !forward {
+                    debug!(
+                        "Reprice pegged order {} of session {}",
+                        order_id, session_id
+                    );
+                    return;
+                }
+                let (forward, actions) =
+                    self.replaces
+                        .on_order(session_id, order_id, order.state(), trd_vol);
+                self.apply_replaces(actions);
+                if !forward {
+                    debug!("Replace order {} of session {}", order_id, session_id);
+                    return;
+                }
 
+                let underlying = self.underlying(order.symbol());
                 match self.session.get_mut(&session_id) {
                     Some(session) => {
-                        if let Err(e) = session.on_order(order) {
-                            error!("{}", e);
+                        match session.on_order(order_id, order) {
+                            Ok(()) => self.lifecycle.mark(client_order_id, Stage::Notified),
+                            Err(e) => error!("{}", e),
+                        }
+                        if let Some(net) = session.net(order.symbol()) {
+                            self.portfolio
+                                .update_price(VENUE, order.symbol(), order.trd_prc());
+                            self.portfolio.update_position(
+                                VENUE,
+                                session_id,
+                                order.symbol(),
+                                &underlying,
+                                net,
+                            );
                         }
                     }
                     None => warn!("Missing session {}, maybe a bug", session_id),
                 }
+
+                let actions = self.quotes.on_order(session_id, order_id, order.state());
+                self.apply_quotes(actions);
             }
             Err(_) => info!("Extrnal order:{:?} ", order),
         }
@@ -0,0 +1,98 @@
+use clap::Parser;
+use cryptoflow::{init_tracing_with_config, LogConfig};
+use cryptoflow_cli::conformance::Conformance;
+use futures_util::{SinkExt, StreamExt};
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tracing::{error, info};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Stand in for the gateway and check the login, subscribe and order flows of a strategy client"
+)]
+struct Args {
+    #[arg(
+        short,
+        long,
+        default_value = "127.0.0.1:8111",
+        help = "Address to listen on"
+    )]
+    addr: String,
+    #[arg(
+        long,
+        default_value_t = 60,
+        help = "Seconds to wait for the client to finish the flows"
+    )]
+    timeout: u64,
+    #[arg(long, help = "Print the report as JSON")]
+    json: bool,
+    #[arg(short, long, default_value_t = tracing::Level::INFO)]
+    level: tracing::Level,
+}
+
+/// 接受一个客户端连接，直到走完所有流程或客户端断开
+async fn run(args: &Args, test: &mut Conformance) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&args.addr).await?;
+    info!("Listening on {}", args.addr);
+    let (stream, addr) = listener.accept().await?;
+    let ws = accept_async(stream).await?;
+    let (mut write, mut read) = ws.split();
+    info!("Client {} connected", addr);
+
+    while !test.finished() {
+        match read.next().await {
+            Some(Ok(Message::Text(text))) => {
+                info!("Recv {}", text);
+                for reply in test.on_text(&text)? {
+                    info!("Send {}", reply);
+                    write.send(Message::Text(reply.into())).await?;
+                }
+            }
+            Some(Ok(Message::Close(_))) | None => break,
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e.into()),
+        }
+    }
+    let _ = write.close().await;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    let _guard = init_tracing_with_config(
+        "gateway-conformance",
+        &LogConfig::default(),
+        &args.level.to_string().to_lowercase(),
+    )?;
+
+    let mut test = Conformance::new();
+    let timeout = Duration::from_secs(args.timeout);
+    match tokio::time::timeout(timeout, run(&args, &mut test)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("{}", e),
+        Err(_) => error!("Client did not finish in {}s", args.timeout),
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(test.checks())?);
+    } else {
+        for check in test.checks() {
+            println!(
+                "{} {:<10} {}",
+                if check.passed { "PASS" } else { "FAIL" },
+                format!("{:?}", check.flow),
+                check.detail
+            );
+        }
+    }
+    if test.passed() {
+        println!("PASS");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("FAIL");
+        Ok(ExitCode::FAILURE)
+    }
+}
@@ -0,0 +1,159 @@
+use clap::Parser;
+use cryptoflow::chat::Side;
+use cryptoflow::{init_tracing_with_config, LogConfig};
+use cryptoflow_cli::smoketest::{Budgets, SmokeConfig, SmokeTest};
+use futures_util::{SinkExt, StreamExt};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Run one order through its full lifecycle on a gateway and report pass or fail"
+)]
+struct Args {
+    #[arg(
+        short,
+        long,
+        default_value = "ws://localhost:8111",
+        help = "Gateway address"
+    )]
+    addr: String,
+    #[arg(short, long, help = "Session id that no live strategy uses")]
+    session_id: u16,
+    #[arg(long, default_value = "btcusdt")]
+    symbol: String,
+    #[arg(long, default_value = "BUY", help = "BUY or SELL")]
+    side: String,
+    #[arg(
+        long,
+        default_value_t = 0.05,
+        help = "Distance from the best price, 0.05 is 5%"
+    )]
+    offset: f64,
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "Ticks to move the order away on amend"
+    )]
+    amend_ticks: u32,
+    #[arg(long, help = "Order quantity, the smallest valid one by default")]
+    quantity: Option<f64>,
+    #[arg(long, default_value_t = 1)]
+    order_id: u32,
+    #[arg(long, default_value_t = 1000)]
+    login_ms: u64,
+    #[arg(long, default_value_t = 1000)]
+    request_ms: u64,
+    #[arg(long, default_value_t = 3000)]
+    market_ms: u64,
+    #[arg(long, default_value_t = 2000)]
+    order_ms: u64,
+    #[arg(long, default_value_t = 10, help = "Seconds to wait for any step")]
+    timeout: u64,
+    #[arg(long, help = "Print the report as JSON")]
+    json: bool,
+    #[arg(short, long, default_value_t = tracing::Level::INFO)]
+    level: tracing::Level,
+}
+
+impl Args {
+    fn config(&self) -> anyhow::Result<SmokeConfig> {
+        let side = match self.side.to_uppercase().as_str() {
+            "BUY" => Side::BUY,
+            "SELL" => Side::SELL,
+            side => anyhow::bail!("Invalid side {}", side),
+        };
+        Ok(SmokeConfig {
+            side,
+            offset: self.offset,
+            amend_ticks: self.amend_ticks,
+            quantity: self.quantity,
+            order_id: self.order_id,
+            budgets: Budgets {
+                login: Duration::from_millis(self.login_ms),
+                request: Duration::from_millis(self.request_ms),
+                market: Duration::from_millis(self.market_ms),
+                order: Duration::from_millis(self.order_ms),
+                timeout: Duration::from_secs(self.timeout),
+            },
+            ..SmokeConfig::new(&self.symbol)
+        })
+    }
+}
+
+async fn run(args: &Args) -> anyhow::Result<SmokeTest> {
+    let mut test = SmokeTest::new(args.config()?, args.session_id);
+    let (ws, _) = connect_async(args.addr.as_str()).await?;
+    let (mut write, mut read) = ws.split();
+    info!("Connected to {}", args.addr);
+
+    let mut requests = test.start(Instant::now())?;
+    let mut tick = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        for request in requests.drain(..) {
+            info!("Send {}", request);
+            write.send(Message::Text(request.into())).await?;
+        }
+        if test.finished() {
+            break;
+        }
+        tokio::select! {
+            msg = read.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    requests = test.on_text(&text, Instant::now())?;
+                }
+                Some(Ok(Message::Close(_))) | None => anyhow::bail!("Closed by gateway"),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            _ = tick.tick() => {
+                requests = test.on_tick(Instant::now())?;
+            }
+        }
+    }
+    let _ = write.close().await;
+    Ok(test)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    let _guard = init_tracing_with_config(
+        "gateway-smoketest",
+        &LogConfig::default(),
+        &args.level.to_string().to_lowercase(),
+    )?;
+
+    let test = match run(&args).await {
+        Ok(test) => test,
+        Err(e) => {
+            error!("{}", e);
+            println!("FAIL {}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(test.checks())?);
+    } else {
+        for check in test.checks() {
+            println!(
+                "{} {:<10} {:>6}ms / {:>6}ms  {}",
+                if check.passed { "PASS" } else { "FAIL" },
+                format!("{:?}", check.step),
+                check.elapsed_ms,
+                check.budget_ms,
+                check.detail
+            );
+        }
+    }
+    if test.passed() {
+        println!("PASS");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("FAIL");
+        Ok(ExitCode::FAILURE)
+    }
+}
@@ -0,0 +1,26 @@
+use binance::protocol;
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Write the JSON schema of the strategy protocol, one file per request and message"
+)]
+struct Args {
+    #[arg(short, long, default_value = "doc/schema", help = "Output directory")]
+    out: PathBuf,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    for (path, content) in protocol::files() {
+        let path = args.out.join(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, content)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
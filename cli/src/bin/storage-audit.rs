@@ -0,0 +1,61 @@
+use clap::Parser;
+use cryptoflow::storage;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Check stored orders, fills and market snapshots for missing fills, gaps and duplicates"
+)]
+struct Args {
+    #[arg(short, long, default_value = "sqlite://pos.db", help = "Storage url")]
+    url: String,
+    #[arg(long, default_value_t = 0, help = "Start time in milliseconds")]
+    start: i64,
+    #[arg(long, help = "End time in milliseconds, now by default")]
+    end: Option<i64>,
+    #[arg(long, help = "Print the report as JSON")]
+    json: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    let end = match args.end {
+        Some(end) => end,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64,
+    };
+    let storage = storage::open(&args.url).await?;
+    let audit = storage.audit(args.start, end).await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&audit)?);
+    } else {
+        println!(
+            "{} orders, {} fills, {} snapshots",
+            audit.orders, audit.fills, audit.snapshots
+        );
+        for missing in audit.missing_fills.iter() {
+            println!(
+                "missing fill {} order {} trade {}",
+                missing.symbol, missing.order_id, missing.trade_id
+            );
+        }
+        for gap in audit.gaps.iter() {
+            println!(
+                "gap in {} after {}, {} snapshots missing",
+                gap.stream,
+                gap.after,
+                gap.before - gap.after - 1
+            );
+        }
+        if audit.duplicates > 0 {
+            println!("{} duplicate rows not merged yet", audit.duplicates);
+        }
+    }
+    Ok(match audit.clean() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
@@ -0,0 +1,79 @@
+use binance::rules_check::{self, RulesCheck};
+use clap::Parser;
+use std::collections::{BTreeMap, HashMap};
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Check the trading rules of every symbol in exchangeInfo against the exchange filters"
+)]
+struct Args {
+    #[arg(short, long, default_value = "usdt", help = "spot or usdt")]
+    market: String,
+    #[arg(
+        long,
+        help = "Read exchangeInfo from this file instead of the exchange"
+    )]
+    file: Option<String>,
+    #[arg(
+        long,
+        help = "Do not fetch reference prices, which skips the PERCENT_PRICE checks"
+    )]
+    no_prices: bool,
+    #[arg(
+        long,
+        default_value_t = 200,
+        help = "Prices and quantities sampled per symbol"
+    )]
+    samples: usize,
+    #[arg(long, default_value_t = 1, help = "Seed of the samples")]
+    seed: u64,
+    #[arg(long, default_value_t = 5, help = "Symbols listed per check")]
+    limit: usize,
+    #[arg(long, help = "Print the report as JSON")]
+    json: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    let venue = match args.market.as_str() {
+        "spot" => rules_check::SPOT,
+        "usdt" => rules_check::USDT,
+        market => anyhow::bail!("Unknown market {}", market),
+    };
+    let (info, prices) = match &args.file {
+        Some(file) => (
+            serde_json::from_str(&std::fs::read_to_string(file)?)?,
+            HashMap::new(),
+        ),
+        None => rules_check::fetch(&venue, !args.no_prices).await?,
+    };
+    let report = RulesCheck::new(args.seed, args.samples).run(&info, &prices)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!(
+            "{} symbols, {} samples, {} violations",
+            report.symbols,
+            report.samples,
+            report.violations.len()
+        );
+        let mut checks: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for violation in report.violations.iter() {
+            checks.entry(violation.check).or_default().push(violation);
+        }
+        for (check, violations) in checks {
+            println!("{}: {} symbols", check, violations.len());
+            for violation in violations.iter().take(args.limit) {
+                println!("  {} {}", violation.symbol, violation.detail);
+            }
+        }
+    }
+    Ok(match report.clean() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
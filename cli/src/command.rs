@@ -0,0 +1,293 @@
+//! 终端输入的命令
+
+use anyhow::{anyhow, bail};
+use cryptoflow::chat::{OrderType, Side, TimeInForce};
+use serde_json::Value;
+
+pub const HELP: &str = "\
+login                                       重新登录
+sub <stream>...                             订阅，如 sub btcusdt@depth5 btcusdt@kline_1m
+unsub <stream>...                           退订
+book <stream> [levels]                      显示订单簿
+watch [<stream> [levels]]                   订单簿每次更新时刷新显示，不带参数时停止
+positions                                   查询并显示持仓
+order <symbol> <buy|sell> <quantity> <price> [order_type] [tif]
+                                            下单，默认 LIMIT GTC
+cancel <symbol> <internal_id>               撤单
+orders                                      本会话的订单
+last <stream>                               该流最近的一条推送
+state                                       会话状态
+raw <method> [params]                       发送任意请求，params 为 JSON，默认 []
+param <session_id> <key> <value>            修改策略参数，value 为 JSON，null 删除，需要 --admin-token
+sanity [heal [adopt_session]]               核对本地与交易所的挂单、持仓与余额，heal 撤销未知挂单并把持仓
+                                            差额计入 adopt_session，需要 --admin-token
+halt <symbol> [cancel] [reason]             暂停交易对的交易，cancel 撤销所有会话在该交易对上的挂单，
+                                            需要 --admin-token
+resume <symbol>                             恢复交易对的交易，需要 --admin-token
+export <path>                               导出网关的持仓、订单归属与订单号计数器到状态文件，需要 --admin-token
+import <path>                               把状态文件导入网关，在策略端登录之前进行，需要 --admin-token
+help                                        显示帮助
+quit                                        退出";
+
+/// 订单簿默认显示的档数
+const DEFAULT_LEVELS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Login,
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+    Book(String, usize),
+    Watch(Option<(String, usize)>),
+    Positions,
+    Order {
+        symbol: String,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        order_type: OrderType,
+        tif: TimeInForce,
+    },
+    Cancel {
+        symbol: String,
+        internal_id: u32,
+    },
+    Orders,
+    Last(String),
+    State,
+    Raw(String, Value),
+    Param {
+        session_id: u16,
+        key: String,
+        value: Value,
+    },
+    Sanity {
+        heal: bool,
+        adopt_session: Option<u16>,
+    },
+    Halt {
+        symbol: String,
+        cancel: bool,
+        reason: Option<String>,
+    },
+    Resume(String),
+    /// 状态文件的路径
+    Export(String),
+    Import(String),
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// 解析一行输入，空行为 None
+    pub fn parse(line: &str) -> anyhow::Result<Option<Self>> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+        let args: Vec<&str> = words.collect();
+        let streams = || -> anyhow::Result<Vec<String>> {
+            if args.is_empty() {
+                bail!("{} requires at least one stream", name);
+            }
+            Ok(args.iter().map(|s| s.to_string()).collect())
+        };
+        let levels = |i: usize| -> anyhow::Result<usize> {
+            match args.get(i) {
+                Some(n) => Ok(n.parse()?),
+                None => Ok(DEFAULT_LEVELS),
+            }
+        };
+        let arg = |i: usize| -> anyhow::Result<&str> {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| anyhow!("{} requires more arguments, see help", name))
+        };
+
+        let command = match name {
+            "login" => Self::Login,
+            "sub" | "subscribe" => Self::Subscribe(streams()?),
+            "unsub" | "unsubscribe" => Self::Unsubscribe(streams()?),
+            "book" => Self::Book(arg(0)?.to_string(), levels(1)?),
+            "watch" => match args.first() {
+                Some(stream) => Self::Watch(Some((stream.to_string(), levels(1)?))),
+                None => Self::Watch(None),
+            },
+            "positions" => Self::Positions,
+            "order" => Self::Order {
+                symbol: arg(0)?.to_lowercase(),
+                side: parse_name(&arg(1)?.to_uppercase())?,
+                quantity: arg(2)?.parse()?,
+                price: arg(3)?.parse()?,
+                order_type: match args.get(4) {
+                    Some(s) => parse_name(&s.to_uppercase())?,
+                    None => OrderType::LIMIT,
+                },
+                tif: match args.get(5) {
+                    Some(s) => parse_name(&s.to_uppercase())?,
+                    None => TimeInForce::GTC,
+                },
+            },
+            "cancel" => Self::Cancel {
+                symbol: arg(0)?.to_lowercase(),
+                internal_id: arg(1)?.parse()?,
+            },
+            "orders" => Self::Orders,
+            "last" => Self::Last(arg(0)?.to_string()),
+            "state" => Self::State,
+            "raw" => {
+                let method = arg(0)?.to_string();
+                // params 中可能有空格，取方法名之后的原文
+                let params = line.trim_start()[name.len()..]
+                    .trim_start()
+                    .strip_prefix(method.as_str())
+                    .unwrap_or_default()
+                    .trim();
+                let params = match params {
+                    "" => Value::Array(vec![]),
+                    params => serde_json::from_str(params)?,
+                };
+                Self::Raw(method, params)
+            }
+            "param" => {
+                // 不是合法 JSON 的值按字符串处理
+                let value = args.get(2..).unwrap_or_default().join(" ");
+                if value.is_empty() {
+                    bail!("{} requires more arguments, see help", name);
+                }
+                Self::Param {
+                    session_id: arg(0)?.parse()?,
+                    key: arg(1)?.to_string(),
+                    value: serde_json::from_str(&value).unwrap_or(Value::String(value)),
+                }
+            }
+            "sanity" => match args.first() {
+                None => Self::Sanity {
+                    heal: false,
+                    adopt_session: None,
+                },
+                Some(&"heal") => Self::Sanity {
+                    heal: true,
+                    adopt_session: args.get(1).map(|s| s.parse()).transpose()?,
+                },
+                Some(arg) => bail!("Invalid {}, see help", arg),
+            },
+            "halt" => {
+                let cancel = args.get(1) == Some(&"cancel");
+                let reason = args.get(if cancel { 2.. } else { 1.. }).unwrap_or_default();
+                Self::Halt {
+                    symbol: arg(0)?.to_lowercase(),
+                    cancel,
+                    reason: Some(reason.join(" ")).filter(|r| !r.is_empty()),
+                }
+            }
+            "resume" => Self::Resume(arg(0)?.to_lowercase()),
+            "export" => Self::Export(arg(0)?.to_string()),
+            "import" => Self::Import(arg(0)?.to_string()),
+            "help" | "?" => Self::Help,
+            "quit" | "exit" => Self::Quit,
+            _ => bail!("Unknown command {}, see help", name),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// 按协议中的写法解析枚举，如 BUY、LIMIT_MAKER、GTX
+fn parse_name<T: serde::de::DeserializeOwned>(name: &str) -> anyhow::Result<T> {
+    serde_json::from_value(Value::String(name.to_string())).map_err(|_| anyhow!("Invalid {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("  ").unwrap(), None);
+        assert_eq!(
+            Command::parse("sub btcusdt@depth5 ethusdt@bookTicker").unwrap(),
+            Some(Command::Subscribe(vec![
+                "btcusdt@depth5".into(),
+                "ethusdt@bookTicker".into()
+            ]))
+        );
+        assert!(Command::parse("sub").is_err());
+        assert_eq!(
+            Command::parse("book btcusdt@depth20 10").unwrap(),
+            Some(Command::Book("btcusdt@depth20".into(), 10))
+        );
+        assert_eq!(Command::parse("watch").unwrap(), Some(Command::Watch(None)));
+        assert_eq!(
+            Command::parse("order BTCUSDT buy 0.01 60000 limit gtx").unwrap(),
+            Some(Command::Order {
+                symbol: "btcusdt".into(),
+                side: Side::BUY,
+                quantity: 0.01,
+                price: 60000.0,
+                order_type: OrderType::LIMIT,
+                tif: TimeInForce::GTX,
+            })
+        );
+        assert!(Command::parse("order btcusdt hold 1 1").is_err());
+        assert!(Command::parse("cancel btcusdt").is_err());
+        assert_eq!(
+            Command::parse(r#"raw get_option_chain {"underlying": "btcusdt"}"#).unwrap(),
+            Some(Command::Raw(
+                "get_option_chain".into(),
+                serde_json::json!({"underlying": "btcusdt"})
+            ))
+        );
+        assert_eq!(
+            Command::parse("raw get_products").unwrap(),
+            Some(Command::Raw("get_products".into(), serde_json::json!([])))
+        );
+        assert_eq!(
+            Command::parse("param 1 spread 0.8").unwrap(),
+            Some(Command::Param {
+                session_id: 1,
+                key: "spread".into(),
+                value: serde_json::json!(0.8),
+            })
+        );
+        assert_eq!(
+            Command::parse("param 1 mode passive").unwrap(),
+            Some(Command::Param {
+                session_id: 1,
+                key: "mode".into(),
+                value: serde_json::json!("passive"),
+            })
+        );
+        assert!(Command::parse("param 1 spread").is_err());
+        assert_eq!(
+            Command::parse("sanity heal 3").unwrap(),
+            Some(Command::Sanity {
+                heal: true,
+                adopt_session: Some(3),
+            })
+        );
+        assert!(Command::parse("sanity fix").is_err());
+        assert_eq!(
+            Command::parse("halt BTCUSDT cancel CPI release").unwrap(),
+            Some(Command::Halt {
+                symbol: "btcusdt".into(),
+                cancel: true,
+                reason: Some("CPI release".into()),
+            })
+        );
+        assert_eq!(
+            Command::parse("halt btcusdt").unwrap(),
+            Some(Command::Halt {
+                symbol: "btcusdt".into(),
+                cancel: false,
+                reason: None,
+            })
+        );
+        assert!(Command::parse("resume").is_err());
+        assert_eq!(
+            Command::parse("export /tmp/gateway.json").unwrap(),
+            Some(Command::Export("/tmp/gateway.json".into()))
+        );
+        assert!(Command::parse("import").is_err());
+        assert!(Command::parse("fly").is_err());
+    }
+}
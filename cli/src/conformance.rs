@@ -0,0 +1,380 @@
+//! 策略端一致性测试
+//!
+//! 代替网关接受一个策略端连接，按 [`binance::protocol`] 的 schema 校验客户端发来的每条请求，并检查
+//! 登录、订阅、下单、撤单的流程：登录之前不能订阅或下单，订单的 session_id 与登录一致且登录时开启了
+//! 交易，同一会话的订单号不重复，撤单的订单必须是未完成的订单。应答与网关相同：订阅深度流后推送
+//! 一条深度，下单回报 NEW，撤单回报 CANCELED。没有覆盖的方法回复 UNSUPPORTED，不计入结果。
+
+use binance::model::order::{BinanceCancel, BinanceOrder};
+use binance::protocol;
+use cryptoflow::chat::{
+    SError, SLogin, SOrder, SRequest, SResponse, SStreamResult, SSubscription, State,
+};
+use cryptoflow::codec::WireFormat;
+use cryptoflow::error_code::{DUPLICATE_LOGIN, INVALID_STREAM, NOT_LOGIN, UNSUPPORTED};
+use cryptoflow::schema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flow {
+    /// 与方法无关的检查：JSON 格式与请求 id
+    Message,
+    Login,
+    Subscribe,
+    Order,
+    Cancel,
+}
+
+impl Flow {
+    fn of(method: &str) -> Self {
+        match method {
+            "login" => Self::Login,
+            "subscribe" | "unsubscribe" => Self::Subscribe,
+            "order" | "orders" => Self::Order,
+            "cancel" => Self::Cancel,
+            _ => Self::Message,
+        }
+    }
+}
+
+/// 一条请求的检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub flow: Flow,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Default)]
+pub struct Conformance {
+    login: Option<SLogin>,
+    request_ids: HashSet<i64>,
+    streams: HashSet<String>,
+    /// 未完成的订单
+    orders: HashMap<u32, BinanceOrder>,
+    order_ids: HashSet<u32>,
+    next_order_id: i64,
+    checks: Vec<Check>,
+}
+
+impl Conformance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// 登录、订阅、下单、撤单都至少通过了一次
+    pub fn finished(&self) -> bool {
+        [Flow::Login, Flow::Subscribe, Flow::Order, Flow::Cancel]
+            .iter()
+            .all(|flow| self.checks.iter().any(|c| c.flow == *flow && c.passed))
+    }
+
+    pub fn passed(&self) -> bool {
+        self.finished() && self.checks.iter().all(|c| c.passed)
+    }
+
+    fn check(&mut self, flow: Flow, passed: bool, detail: String) {
+        self.checks.push(Check {
+            flow,
+            passed,
+            detail,
+        });
+    }
+
+    /// 处理客户端发来的一条消息，返回发给客户端的消息
+    pub fn on_text(&mut self, text: &str) -> anyhow::Result<Vec<String>> {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => {
+                self.check(Flow::Message, false, format!("invalid JSON: {}", e));
+                return Ok(Vec::new());
+            }
+        };
+        let method = value["method"].as_str().unwrap_or_default().to_string();
+        let flow = Flow::of(&method);
+        let Some(schema) = protocol::request(&method) else {
+            let id = value["id"].as_i64().unwrap_or_default();
+            let msg = format!("{} is not covered by the conformance test", method);
+            return Ok(vec![error(id, UNSUPPORTED, msg)?]);
+        };
+        // 与网关相同，格式不对的请求没有应答
+        if let Err(e) = schema::validate(&schema, &value) {
+            self.check(flow, false, format!("{}: {}", method, e));
+            return Ok(Vec::new());
+        }
+        let id = value["id"].as_i64().unwrap_or_default();
+        if !self.request_ids.insert(id) {
+            self.check(Flow::Message, false, format!("request id {} reused", id));
+        }
+
+        match flow {
+            Flow::Login => self.on_login(decode(&method, value)?),
+            Flow::Subscribe if method == "subscribe" => self.on_subscribe(decode(&method, value)?),
+            Flow::Subscribe => self.on_unsubscribe(decode(&method, value)?),
+            Flow::Order if method == "order" => self.on_order(decode(&method, value)?),
+            Flow::Order => self.on_orders(decode(&method, value)?),
+            Flow::Cancel => self.on_cancel(decode(&method, value)?),
+            Flow::Message => Ok(Vec::new()),
+        }
+    }
+
+    /// 登录的会话，没有登录或 session_id 与登录不一致时记录失败
+    fn session(&mut self, flow: Flow, id: i64, session_id: u16) -> Option<SLogin> {
+        let Some(login) = self.login.clone() else {
+            self.check(flow, false, format!("request {} before login", id));
+            return None;
+        };
+        if login.session_id != session_id {
+            let detail = format!(
+                "session_id {} does not match login {}",
+                session_id, login.session_id
+            );
+            self.check(flow, false, detail);
+        }
+        Some(login)
+    }
+
+    fn on_login(&mut self, req: SRequest<SLogin>) -> anyhow::Result<Vec<String>> {
+        if self.login.is_some() {
+            self.check(Flow::Login, false, "duplicate login".into());
+            return Ok(vec![error(
+                req.id,
+                DUPLICATE_LOGIN,
+                "already login".into(),
+            )?]);
+        }
+        let login = req.params;
+        match login.format {
+            WireFormat::Json => {
+                let detail = format!("session {} trading {}", login.session_id, login.trading);
+                self.check(Flow::Login, true, detail);
+            }
+            format => {
+                let detail = format!("format {:?} is not covered, login with json", format);
+                self.check(Flow::Login, false, detail);
+            }
+        }
+        self.login = Some(login.clone());
+        Ok(vec![reply(req.id, login)?])
+    }
+
+    fn on_subscribe(&mut self, req: SRequest<Vec<SSubscription>>) -> anyhow::Result<Vec<String>> {
+        let Some(login) = self.login.as_ref() else {
+            self.check(Flow::Subscribe, false, "subscribe before login".into());
+            return Ok(vec![error(req.id, NOT_LOGIN, "please login first".into())?]);
+        };
+        let session_id = login.session_id;
+        let mut results = Vec::new();
+        let mut pushes = Vec::new();
+        for subscription in &req.params {
+            let stream = subscription.stream();
+            results.push(SStreamResult::accepted(stream));
+            self.streams.insert(stream.clone());
+            if stream.contains("depth") {
+                pushes.push(depth(stream, subscription.tag()).to_string());
+            }
+        }
+        let detail = format!("session {} subscribe {} streams", session_id, results.len());
+        self.check(Flow::Subscribe, true, detail);
+        let mut messages = vec![reply(req.id, results)?];
+        messages.extend(pushes);
+        Ok(messages)
+    }
+
+    fn on_unsubscribe(&mut self, req: SRequest<Vec<String>>) -> anyhow::Result<Vec<String>> {
+        if self.login.is_none() {
+            self.check(Flow::Subscribe, false, "unsubscribe before login".into());
+            return Ok(vec![error(req.id, NOT_LOGIN, "please login first".into())?]);
+        }
+        let results: Vec<_> = req
+            .params
+            .iter()
+            .map(|stream| match self.streams.remove(stream) {
+                true => SStreamResult::accepted(stream),
+                false => SStreamResult::rejected(
+                    stream,
+                    SError {
+                        code: INVALID_STREAM,
+                        msg: "not subscribed".into(),
+                    },
+                ),
+            })
+            .collect();
+        let detail = format!("unsubscribe {} streams", results.len());
+        self.check(Flow::Subscribe, true, detail);
+        Ok(vec![reply(req.id, results)?])
+    }
+
+    fn on_order(&mut self, req: SRequest<BinanceOrder>) -> anyhow::Result<Vec<String>> {
+        let order = req.params;
+        let Some(login) = self.session(Flow::Order, req.id, order.session_id) else {
+            return Ok(vec![error(req.id, NOT_LOGIN, "please login first".into())?]);
+        };
+        let rejected = if !login.trading {
+            Some("trading is disabled at login".to_string())
+        } else if !self.order_ids.insert(order.id) {
+            Some(format!("order id {} reused", order.id))
+        } else {
+            None
+        };
+        if let Some(detail) = rejected {
+            self.check(Flow::Order, false, detail);
+            return Ok(vec![report(&order, State::REJECTED, -1)?]);
+        }
+
+        self.next_order_id += 1;
+        let detail = format!("order {} {:?} {}", order.id, order.side, order.symbol);
+        self.check(Flow::Order, true, detail);
+        let report = report(&order, State::NEW, self.next_order_id)?;
+        self.orders.insert(order.id, order);
+        Ok(vec![report])
+    }
+
+    /// 批量下单按顺序逐笔检查
+    fn on_orders(&mut self, req: SRequest<Vec<BinanceOrder>>) -> anyhow::Result<Vec<String>> {
+        let mut messages = Vec::new();
+        for order in req.params {
+            messages.extend(self.on_order(SRequest {
+                id: req.id,
+                method: req.method.clone(),
+                params: order,
+            })?);
+        }
+        Ok(messages)
+    }
+
+    fn on_cancel(&mut self, req: SRequest<BinanceCancel>) -> anyhow::Result<Vec<String>> {
+        let cancel = req.params;
+        if self
+            .session(Flow::Cancel, req.id, cancel.session_id)
+            .is_none()
+        {
+            return Ok(vec![error(req.id, NOT_LOGIN, "please login first".into())?]);
+        }
+        let Some(order) = self.orders.remove(&cancel.order_id) else {
+            let detail = format!("order {} is not open", cancel.order_id);
+            self.check(Flow::Cancel, false, detail);
+            return Ok(Vec::new());
+        };
+        self.check(Flow::Cancel, true, format!("cancel {}", cancel.order_id));
+        Ok(vec![report(&order, State::CANCELED, self.next_order_id)?])
+    }
+}
+
+fn decode<T: DeserializeOwned>(method: &str, value: Value) -> anyhow::Result<SRequest<T>> {
+    serde_json::from_value(value).map_err(|e| anyhow::anyhow!("decode {}: {}", method, e))
+}
+
+fn reply<T: Serialize>(id: i64, result: T) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&SResponse { id, result })?)
+}
+
+fn error(id: i64, code: i32, msg: String) -> anyhow::Result<String> {
+    reply(id, SError { code, msg })
+}
+
+fn report(order: &BinanceOrder, state: State, order_id: i64) -> anyhow::Result<String> {
+    let mut report = SOrder::new(
+        order.id,
+        order.symbol.clone(),
+        order.side,
+        state,
+        order.order_type,
+        order.tif,
+        order.quantity,
+        order.price,
+    );
+    report.order_id = order_id;
+    Ok(serde_json::to_string(&report)?)
+}
+
+/// 订阅后推送的一条深度，字段与网关转发的深度相同
+fn depth(stream: &str, tag: Option<&String>) -> Value {
+    let symbol = stream.split('@').next().unwrap_or_default();
+    let mut depth = json!({"offset": 1, "time": 0, "symbol": symbol, "stream": stream,
+        "bids": [{"price": 99.0, "quantity": 1.0}], "asks": [{"price": 101.0, "quantity": 1.0}]});
+    if let Some(tag) = tag {
+        depth["tag"] = json!(tag);
+    }
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u32, session_id: u16) -> String {
+        json!({"id": 10 + id, "method": "order", "params": {"id": id, "symbol": "btcusdt",
+            "price": 100.0, "quantity": 0.1, "side": "BUY", "order_type": "LIMIT", "tif": "GTC",
+            "session_id": session_id}})
+        .to_string()
+    }
+
+    #[test]
+    fn test_conformance() {
+        let mut test = Conformance::new();
+        let early = test.on_text(&order(1, 7)).unwrap();
+        assert!(early[0].contains("-10001"));
+        // 格式不对的请求没有应答
+        assert!(test
+            .on_text(r#"{"id":1,"method":"login","params":{"session_id":7}}"#)
+            .unwrap()
+            .is_empty());
+
+        let login = r#"{"id":2,"method":"login","params":{"session_id":7,"trading":true}}"#;
+        assert!(test.on_text(login).unwrap()[0].contains(r#""session_id":7"#));
+        let subscribe = r#"{"id":3,"method":"subscribe","params":["btcusdt@depth5",
+            {"stream":"btcusdt@kline:1m","tag":"k"}]}"#;
+        let replies = test.on_text(subscribe).unwrap();
+        assert_eq!(replies.len(), 2);
+        let depth: Value = serde_json::from_str(&replies[1]).unwrap();
+        let (_, message) = protocol::messages()
+            .into_iter()
+            .find(|m| m.0 == "depth")
+            .unwrap();
+        assert_eq!(schema::validate(&message, &depth), Ok(()));
+
+        assert!(test.on_text(&order(1, 7)).unwrap()[0].contains("NEW"));
+        assert!(test.on_text(&order(1, 8)).unwrap()[0].contains("REJECTED"));
+        let cancel = r#"{"id":20,"method":"cancel","params":{"symbol":"btcusdt","session_id":7,"order_id":1}}"#;
+        assert!(test.on_text(cancel).unwrap()[0].contains("CANCELED"));
+        let params =
+            |id: u32| serde_json::from_str::<Value>(&order(id, 7)).unwrap()["params"].clone();
+        let orders = json!({"id": 22, "method": "orders", "params": [params(2), params(3)]});
+        let replies = test.on_text(&orders.to_string()).unwrap();
+        assert_eq!(replies.len(), 2);
+        assert!(replies.iter().all(|r| r.contains("NEW")));
+        assert!(test
+            .on_text(r#"{"id":21,"method":"get_products","params":null}"#)
+            .unwrap()[0]
+            .contains("-10008"));
+        assert!(test.finished());
+
+        let failed: Vec<_> = test
+            .checks()
+            .iter()
+            .filter(|c| !c.passed)
+            .map(|c| (c.flow, c.detail.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                (Flow::Order, "request 11 before login"),
+                (Flow::Login, "login: /params: missing trading"),
+                (Flow::Message, "request id 11 reused"),
+                (Flow::Message, "request id 11 reused"),
+                (Flow::Order, "session_id 8 does not match login 7"),
+                (Flow::Order, "order id 1 reused"),
+            ]
+        );
+        assert!(!test.passed());
+    }
+}
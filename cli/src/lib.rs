@@ -0,0 +1,4 @@
+pub mod command;
+pub mod conformance;
+pub mod smoketest;
+pub mod state;
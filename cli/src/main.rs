@@ -0,0 +1,228 @@
+use clap::Parser;
+use cryptoflow::{init_tracing_with_config, LogConfig};
+use cryptoflow_cli::command::{Command, HELP};
+use cryptoflow_cli::state::{Event, Session};
+use futures_util::{SinkExt, StreamExt};
+use std::io::Write;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Interactive client that connects to a gateway as a strategy"
+)]
+struct Args {
+    #[arg(
+        short,
+        long,
+        default_value = "ws://localhost:8111",
+        help = "Gateway address"
+    )]
+    addr: String,
+    #[arg(short, long, default_value_t = 1)]
+    session_id: u16,
+    #[arg(short, long, default_value = "cryptoflow-cli")]
+    name: String,
+    #[arg(short, long, help = "Login with trading enabled to place test orders")]
+    trading: bool,
+    #[arg(
+        long,
+        help = "Gateway admin token, required by the param, sanity, halt, resume, export and import commands"
+    )]
+    admin_token: Option<String>,
+    #[arg(short, long, default_value_t = tracing::Level::INFO)]
+    level: tracing::Level,
+}
+
+/// 正在刷新显示的订单簿
+struct Watch {
+    stream: String,
+    levels: usize,
+}
+
+fn prompt() {
+    print!("> ");
+    let _ = std::io::stdout().flush();
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    // 终端用于交互，日志只写文件
+    let _guard = init_tracing_with_config(
+        "cryptoflow-cli",
+        &LogConfig::default(),
+        &args.level.to_string().to_lowercase(),
+    )?;
+
+    let url = url::Url::parse(&args.addr)?;
+    info!("Connect to {}", url);
+    let (ws, _) = connect_async(url.as_str()).await?;
+    let (mut write, mut read) = ws.split();
+    println!("Connected to {}, type help for commands", args.addr);
+
+    let mut session = Session::new(args.session_id, &args.name, args.trading);
+    write.send(Message::Text(session.login()?.into())).await?;
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut watch: Option<Watch> = None;
+    prompt();
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(frame))) => {
+                        println!("\nClosed by gateway: {:?}", frame);
+                        break;
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        error!("{}", e);
+                        println!("\nDisconnected: {}", e);
+                        break;
+                    }
+                    None => {
+                        println!("\nDisconnected");
+                        break;
+                    }
+                };
+                match session.on_text(&text) {
+                    Ok(Event::Print(text)) => {
+                        println!("\n{}", text);
+                        prompt();
+                    }
+                    Ok(Event::Book(stream)) => {
+                        if let Some(w) = watch.as_ref().filter(|w| w.stream == stream) {
+                            // 清屏后从左上角重新显示
+                            print!("\x1b[2J\x1b[H");
+                            println!("{}", session.render_book(&w.stream, w.levels));
+                            prompt();
+                        }
+                    }
+                    Ok(Event::Quiet) => {}
+                    Err(e) => error!("Failed to handle {}: {}", text, e),
+                }
+            }
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                let command = match Command::parse(&line) {
+                    Ok(Some(command)) => command,
+                    Ok(None) => {
+                        prompt();
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("{}", e);
+                        prompt();
+                        continue;
+                    }
+                };
+                let request = match command {
+                    Command::Login => Some(session.login()?),
+                    Command::Subscribe(streams) => Some(session.request("subscribe", streams)?),
+                    Command::Unsubscribe(streams) => Some(session.request("unsubscribe", streams)?),
+                    Command::Book(stream, levels) => {
+                        println!("{}", session.render_book(&stream, levels));
+                        None
+                    }
+                    Command::Watch(w) => {
+                        watch = w.map(|(stream, levels)| Watch { stream, levels });
+                        None
+                    }
+                    Command::Positions => Some(session.get_positions()?),
+                    Command::Order { symbol, side, quantity, price, order_type, tif } => {
+                        let (id, request) =
+                            session.order(&symbol, side, quantity, price, order_type, tif)?;
+                        println!("Order {} sent", id);
+                        Some(request)
+                    }
+                    Command::Cancel { symbol, internal_id } => {
+                        Some(session.cancel(&symbol, internal_id)?)
+                    }
+                    Command::Orders => {
+                        println!("{}", session.render_orders());
+                        None
+                    }
+                    Command::Last(stream) => {
+                        println!("{}", session.render_last(&stream));
+                        None
+                    }
+                    Command::State => {
+                        println!("{}", session.render_state());
+                        None
+                    }
+                    Command::Raw(method, params) => Some(session.request(&method, params)?),
+                    Command::Param { session_id, key, value } => match &args.admin_token {
+                        Some(token) => Some(session.set_param(token, session_id, &key, value)?),
+                        None => {
+                            println!("param requires --admin-token");
+                            None
+                        }
+                    },
+                    Command::Sanity { heal, adopt_session } => match &args.admin_token {
+                        Some(token) => Some(session.sanity_check(token, heal, adopt_session)?),
+                        None => {
+                            println!("sanity requires --admin-token");
+                            None
+                        }
+                    },
+                    Command::Halt { symbol, cancel, reason } => match &args.admin_token {
+                        Some(token) => {
+                            Some(session.switch_trading(token, false, &symbol, cancel, reason)?)
+                        }
+                        None => {
+                            println!("halt requires --admin-token");
+                            None
+                        }
+                    },
+                    Command::Resume(symbol) => match &args.admin_token {
+                        Some(token) => {
+                            Some(session.switch_trading(token, true, &symbol, false, None)?)
+                        }
+                        None => {
+                            println!("resume requires --admin-token");
+                            None
+                        }
+                    },
+                    Command::Export(path) => match &args.admin_token {
+                        Some(token) => Some(session.export_state(token, &path)?),
+                        None => {
+                            println!("export requires --admin-token");
+                            None
+                        }
+                    },
+                    Command::Import(path) => match &args.admin_token {
+                        Some(token) => match session.import_state(token, &path) {
+                            Ok(request) => Some(request),
+                            Err(e) => {
+                                println!("{}", e);
+                                None
+                            }
+                        },
+                        None => {
+                            println!("import requires --admin-token");
+                            None
+                        }
+                    },
+                    Command::Help => {
+                        println!("{}", HELP);
+                        None
+                    }
+                    Command::Quit => break,
+                };
+                if let Some(request) = request {
+                    info!("Send {}", request);
+                    write.send(Message::Text(request.into())).await?;
+                }
+                prompt();
+            }
+        }
+    }
+    let _ = write.close().await;
+    Ok(())
+}
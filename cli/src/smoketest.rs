@@ -0,0 +1,500 @@
+//! 网关冒烟测试
+//!
+//! 按顺序走完一笔订单的生命周期：登录、查询合约、订阅深度、在远离盘口的价格挂一笔最小的限价单、
+//! 改价、撤单。每一步记录从发出请求到收到确认的耗时并与预算比较；订单回报必须按
+//! NEW -> 改价后的 NEW -> CANCELED 的顺序到达，中间的 PENDING_NEW、PENDING_CANCEL 忽略。
+//! 挂单之后的步骤失败时仍会撤单，测试不留下挂单。
+
+use crate::state::{Order, Session};
+use binance::model::symbol::BinanceSymbol;
+use cryptoflow::chat::{OrderType, SError, SStreamResult, Side, State, TimeInForce};
+use cryptoflow::trading_rules::TradingRules;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// 各步骤的耗时预算
+#[derive(Debug, Clone, Copy)]
+pub struct Budgets {
+    pub login: Duration,
+    /// 查询合约与订阅的应答
+    pub request: Duration,
+    /// 订阅后第一条深度推送
+    pub market: Duration,
+    /// 挂单、改单、撤单的回报
+    pub order: Duration,
+    /// 等待一步完成的最长时间，超过时测试失败
+    pub timeout: Duration,
+}
+
+impl Default for Budgets {
+    fn default() -> Self {
+        Self {
+            login: Duration::from_millis(1000),
+            request: Duration::from_millis(1000),
+            market: Duration::from_millis(3000),
+            order: Duration::from_millis(2000),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmokeConfig {
+    pub symbol: String,
+    pub side: Side,
+    /// 挂单价偏离最优价的比例
+    pub offset: f64,
+    /// 改单时再远离盘口的 tick 数
+    pub amend_ticks: u32,
+    /// 不填时按合约的最小数量与最小名义价值计算
+    pub quantity: Option<f64>,
+    pub order_id: u32,
+    pub budgets: Budgets,
+}
+
+impl SmokeConfig {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_lowercase(),
+            side: Side::BUY,
+            offset: 0.05,
+            amend_ticks: 10,
+            quantity: None,
+            order_id: 1,
+            budgets: Budgets::default(),
+        }
+    }
+
+    fn stream(&self) -> String {
+        format!("{}@depth5", self.symbol)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Login,
+    Products,
+    Subscribe,
+    Depth,
+    Place,
+    Amend,
+    Cancel,
+}
+
+impl Step {
+    fn budget(&self, budgets: &Budgets) -> Duration {
+        match self {
+            Self::Login => budgets.login,
+            Self::Products | Self::Subscribe => budgets.request,
+            Self::Depth => budgets.market,
+            Self::Place | Self::Amend | Self::Cancel => budgets.order,
+        }
+    }
+}
+
+/// 一步的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub step: Step,
+    pub passed: bool,
+    pub elapsed_ms: u64,
+    pub budget_ms: u64,
+    pub detail: String,
+}
+
+pub struct SmokeTest {
+    config: SmokeConfig,
+    session: Session,
+    step: Step,
+    /// 当前步骤开始等待的时间与等待的请求 id
+    started: Instant,
+    request_id: i64,
+    rules: Option<BinanceSymbol>,
+    price: f64,
+    quantity: f64,
+    /// 收到的本订单回报的状态
+    reports: Vec<State>,
+    checks: Vec<Check>,
+    finished: bool,
+}
+
+impl SmokeTest {
+    pub fn new(config: SmokeConfig, session_id: u16) -> Self {
+        let mut session = Session::new(session_id, "gateway-smoketest", true);
+        session.set_order_id(config.order_id);
+        Self {
+            config,
+            session,
+            step: Step::Login,
+            started: Instant::now(),
+            request_id: 0,
+            rules: None,
+            price: 0.0,
+            quantity: 0.0,
+            reports: Vec::new(),
+            checks: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn passed(&self) -> bool {
+        self.finished
+            && self.checks.iter().all(|c| c.passed)
+            && self.checks.last().map(|c| c.step) == Some(Step::Cancel)
+    }
+
+    /// 开始测试，返回登录请求
+    pub fn start(&mut self, now: Instant) -> anyhow::Result<Vec<String>> {
+        let login = self.session.login()?;
+        self.wait(Step::Login, now);
+        Ok(vec![login])
+    }
+
+    fn wait(&mut self, step: Step, now: Instant) {
+        self.step = step;
+        self.started = now;
+        self.request_id = self.session.last_id();
+    }
+
+    /// 记录当前步骤通过，超出预算时记为失败但继续后面的步骤
+    fn pass(&mut self, now: Instant, detail: String) {
+        let elapsed = now.duration_since(self.started);
+        let budget = self.step.budget(&self.config.budgets);
+        self.checks.push(Check {
+            step: self.step,
+            passed: elapsed <= budget,
+            elapsed_ms: elapsed.as_millis() as u64,
+            budget_ms: budget.as_millis() as u64,
+            detail,
+        });
+    }
+
+    /// 当前步骤失败，已经挂单时撤单
+    fn fail(&mut self, now: Instant, detail: String) -> anyhow::Result<Vec<String>> {
+        let elapsed = now.duration_since(self.started);
+        self.checks.push(Check {
+            step: self.step,
+            passed: false,
+            elapsed_ms: elapsed.as_millis() as u64,
+            budget_ms: self.step.budget(&self.config.budgets).as_millis() as u64,
+            detail,
+        });
+        self.finished = true;
+        match self.step {
+            Step::Place | Step::Amend => Ok(vec![self.cancel()?]),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn cancel(&mut self) -> anyhow::Result<String> {
+        self.session
+            .cancel(&self.config.symbol, self.config.order_id)
+    }
+
+    /// 没有按时完成当前步骤时失败
+    pub fn on_tick(&mut self, now: Instant) -> anyhow::Result<Vec<String>> {
+        if self.finished || now.duration_since(self.started) < self.config.budgets.timeout {
+            return Ok(Vec::new());
+        }
+        let detail = format!("timed out, reports {:?}", self.reports);
+        self.fail(now, detail)
+    }
+
+    /// 处理网关的一条消息，返回需要发送的请求
+    pub fn on_text(&mut self, text: &str, now: Instant) -> anyhow::Result<Vec<String>> {
+        if self.finished {
+            return Ok(Vec::new());
+        }
+        let value: Value = serde_json::from_str(text)?;
+        if let (Some(id), Some(result)) = (value["id"].as_i64(), value.get("result")) {
+            if result.get("code").is_some() && result.get("msg").is_some() {
+                let error: SError = serde_json::from_value(result.clone())?;
+                return self.fail(now, format!("request {} failed: {}", id, error.msg));
+            }
+            if id != self.request_id {
+                return Ok(Vec::new());
+            }
+            return self.on_response(result, now);
+        }
+        if value.get("bids").is_some() && self.step == Step::Depth {
+            return self.on_depth(&value, now);
+        }
+        if value.get("internal_id").is_some() && value.get("state").is_some() {
+            let order: Order = serde_json::from_value(value)?;
+            if order.internal_id == self.config.order_id && order.symbol == self.config.symbol {
+                return self.on_order(order, now);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn on_response(&mut self, result: &Value, now: Instant) -> anyhow::Result<Vec<String>> {
+        match self.step {
+            Step::Login => {
+                self.pass(now, "logged in".into());
+                let request = self
+                    .session
+                    .request("get_products", [&self.config.symbol])?;
+                self.wait(Step::Products, now);
+                Ok(vec![request])
+            }
+            Step::Products => {
+                let products: Vec<Value> = serde_json::from_value(result.clone())?;
+                let rules = products
+                    .into_iter()
+                    .filter_map(|p| serde_json::from_value::<BinanceSymbol>(p).ok())
+                    .find(|p| p.symbol.eq_ignore_ascii_case(&self.config.symbol));
+                let Some(rules) = rules else {
+                    return self.fail(now, format!("{} is not traded", self.config.symbol));
+                };
+                self.pass(
+                    now,
+                    format!("tick {} lot {}", rules.tick_size(), rules.lot_size()),
+                );
+                self.rules = Some(rules);
+                let request = self.session.request("subscribe", [self.config.stream()])?;
+                self.wait(Step::Subscribe, now);
+                Ok(vec![request])
+            }
+            Step::Subscribe => {
+                let results: Vec<SStreamResult> = serde_json::from_value(result.clone())?;
+                match results.into_iter().find(|r| !r.accepted) {
+                    Some(r) => {
+                        let msg = r.error.map(|e| e.msg).unwrap_or_default();
+                        self.fail(now, format!("{} rejected: {}", r.stream, msg))
+                    }
+                    None => {
+                        self.pass(now, self.config.stream());
+                        self.started = now;
+                        self.step = Step::Depth;
+                        Ok(Vec::new())
+                    }
+                }
+            }
+            // 挂单、改单、撤单成功时没有应答，以回报为准
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn on_depth(&mut self, value: &Value, now: Instant) -> anyhow::Result<Vec<String>> {
+        if value["stream"].as_str() != Some(self.config.stream().as_str()) {
+            return Ok(Vec::new());
+        }
+        let best = match self.config.side {
+            Side::BUY => value.pointer("/bids/0/price"),
+            Side::SELL => value.pointer("/asks/0/price"),
+        };
+        let Some(best) = best.and_then(Value::as_f64) else {
+            return Ok(Vec::new());
+        };
+        let Some(rules) = self.rules.clone() else {
+            return self.fail(now, "no trading rules".into());
+        };
+
+        let price = match self.config.side {
+            Side::BUY => best * (1.0 - self.config.offset),
+            Side::SELL => best * (1.0 + self.config.offset),
+        };
+        self.price = step_round(rules.adjust_price(price), rules.tick_size());
+        self.quantity = match self.config.quantity {
+            Some(quantity) => quantity,
+            None => min_quantity(&rules, self.price),
+        };
+        self.pass(now, format!("best {}", best));
+
+        let (_, request) = self.session.order(
+            &self.config.symbol,
+            self.config.side,
+            self.quantity,
+            self.price,
+            OrderType::LIMIT,
+            TimeInForce::GTC,
+        )?;
+        self.wait(Step::Place, now);
+        Ok(vec![request])
+    }
+
+    fn on_order(&mut self, order: Order, now: Instant) -> anyhow::Result<Vec<String>> {
+        self.reports.push(order.state);
+        if matches!(order.state, State::PENDING_NEW | State::PENDING_CANCEL) {
+            return Ok(Vec::new());
+        }
+        match (self.step, order.state) {
+            (Step::Place, State::NEW) => {
+                self.pass(now, format!("{}@{}", order.quantity, order.price));
+                let tick = self
+                    .rules
+                    .as_ref()
+                    .map(|r| r.tick_size())
+                    .unwrap_or_default();
+                let ticks = tick * self.config.amend_ticks as f64;
+                let price = match self.config.side {
+                    Side::BUY if self.price > ticks => self.price - ticks,
+                    _ => self.price + ticks,
+                };
+                self.price = step_round(price, tick);
+                let request = self.session.replace(
+                    self.config.order_id,
+                    &self.config.symbol,
+                    self.config.side,
+                    self.quantity,
+                    self.price,
+                    OrderType::LIMIT,
+                    TimeInForce::GTC,
+                )?;
+                self.wait(Step::Amend, now);
+                Ok(vec![request])
+            }
+            (Step::Amend, State::NEW) if (order.price - self.price).abs() < 1e-12 => {
+                self.pass(now, format!("{}@{}", order.quantity, order.price));
+                let request = self.cancel()?;
+                self.wait(Step::Cancel, now);
+                Ok(vec![request])
+            }
+            (Step::Cancel, State::CANCELED) => {
+                self.pass(now, format!("reports {:?}", self.reports));
+                self.finished = true;
+                let request = self
+                    .session
+                    .request("unsubscribe", [self.config.stream()])?;
+                Ok(vec![request])
+            }
+            (_, state) => {
+                let detail = format!("unexpected {:?}, reports {:?}", state, self.reports);
+                // 订单已经结束时不再撤单
+                if !state.is_working() {
+                    self.step = Step::Cancel;
+                }
+                self.fail(now, detail)
+            }
+        }
+    }
+}
+
+/// 按步长的小数位数取整，避免 0.1 + 0.2 这样的尾数
+fn step_round(value: f64, step: f64) -> f64 {
+    if step <= 0.0 {
+        return value;
+    }
+    let decimals = (-step.log10()).ceil().max(0.0) as i32;
+    let scale = 10f64.powi(decimals);
+    ((value / step).round() * step * scale).round() / scale
+}
+
+/// 满足最小数量与最小名义价值的数量
+fn min_quantity<T: TradingRules>(rules: &T, price: f64) -> f64 {
+    let lot = rules.lot_size();
+    let mut quantity = rules
+        .min_quantity()
+        .max(rules.min_notional() * 1.05 / price);
+    if lot > 0.0 {
+        quantity = (quantity / lot).ceil() * lot;
+    }
+    step_round(quantity, lot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn order(state: &str, price: f64) -> String {
+        json!({"state": state, "order_id": 9, "symbol": "btcusdt", "side": "BUY",
+            "order_type": "LIMIT", "tif": "GTC", "price": price, "quantity": 0.002,
+            "internal_id": 7, "trade_time": 0, "trade_price": 0.0, "trade_quantity": 0.0,
+            "acc": 0.0, "making": false})
+        .to_string()
+    }
+
+    fn product() -> Value {
+        json!({"symbol": "BTCUSDT", "status": "TRADING", "baseAsset": "BTC", "baseAssetPrecision": 8,
+            "quoteAsset": "USDT", "quotePrecision": 8, "orderTypes": ["LIMIT"], "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.01", "maxPrice": "1000000", "tickSize": "0.10"},
+                {"filterType": "LOT_SIZE", "minQty": "0.001", "maxQty": "100", "stepSize": "0.001"},
+                {"filterType": "MIN_NOTIONAL", "notional": "100"}]})
+    }
+
+    #[test]
+    fn test_smoketest() {
+        let mut config = SmokeConfig::new("BTCUSDT");
+        config.order_id = 7;
+        let mut test = SmokeTest::new(config, 9);
+        let now = Instant::now();
+        let ms = |n| now + Duration::from_millis(n);
+
+        let login = test.start(now).unwrap();
+        assert!(login[0].contains(r#""trading":true"#));
+        let products = test
+            .on_text(
+                r#"{"id":1,"result":{"session_id":9,"trading":true}}"#,
+                ms(10),
+            )
+            .unwrap();
+        assert!(products[0].contains("get_products"));
+        let reply = json!({"id": 2, "result": [product()]}).to_string();
+        let subscribe = test.on_text(&reply, ms(20)).unwrap();
+        assert!(subscribe[0].contains("btcusdt@depth5"));
+        let reply = r#"{"id":3,"result":[{"stream":"btcusdt@depth5","accepted":true}]}"#;
+        assert!(test.on_text(reply, ms(30)).unwrap().is_empty());
+
+        let depth = json!({"time": 1, "symbol": "btcusdt", "stream": "btcusdt@depth5",
+            "bids": [{"price": 60000.0, "quantity": 1.0}], "asks": [{"price": 60000.1, "quantity": 1.0}]});
+        let place = test.on_text(&depth.to_string(), ms(40)).unwrap();
+        let place: Value = serde_json::from_str(&place[0]).unwrap();
+        assert_eq!(place["method"], "order");
+        assert_eq!(place["params"]["price"], 57000.0);
+        // 最小名义价值 100 / 57000 向上取到 0.002
+        assert_eq!(place["params"]["quantity"], 0.002);
+
+        assert!(test
+            .on_text(&order("PENDING_NEW", 57000.0), ms(50))
+            .unwrap()
+            .is_empty());
+        let amend = test.on_text(&order("NEW", 57000.0), ms(60)).unwrap();
+        let amend: Value = serde_json::from_str(&amend[0]).unwrap();
+        assert_eq!(amend["method"], "replace");
+        assert_eq!(amend["params"]["price"], 56999.0);
+
+        // 改单确认超出预算，记为失败但继续撤单
+        let cancel = test.on_text(&order("NEW", 56999.0), ms(3000)).unwrap();
+        assert!(cancel[0].contains(r#""method":"cancel""#));
+        let unsubscribe = test.on_text(&order("CANCELED", 56999.0), ms(3010)).unwrap();
+        assert!(unsubscribe[0].contains("unsubscribe"));
+        assert!(test.finished());
+        assert!(!test.passed());
+        let failed: Vec<_> = test.checks().iter().filter(|c| !c.passed).collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].step, Step::Amend);
+        assert_eq!(test.checks().len(), 7);
+
+        // 挂单被拒绝，不再撤单
+        let mut test = SmokeTest::new(SmokeConfig::new("btcusdt"), 9);
+        test.start(now).unwrap();
+        test.wait(Step::Place, now);
+        let mut rejected = order("REJECTED", 1.0);
+        rejected = rejected.replace(r#""internal_id":7"#, r#""internal_id":1"#);
+        assert!(test.on_text(&rejected, ms(10)).unwrap().is_empty());
+        assert!(test.finished() && !test.passed());
+
+        // 改单期间超时，撤单后结束
+        let mut test = SmokeTest::new(SmokeConfig::new("btcusdt"), 9);
+        test.start(now).unwrap();
+        test.wait(Step::Amend, now);
+        assert!(test.on_tick(ms(1000)).unwrap().is_empty());
+        let cancel = test.on_tick(ms(10_000)).unwrap();
+        assert!(cancel[0].contains(r#""method":"cancel""#));
+        assert!(test.checks()[0].detail.starts_with("timed out"));
+
+        assert_eq!(step_round(0.1 + 0.2, 0.1), 0.3);
+    }
+}
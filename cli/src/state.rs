@@ -0,0 +1,670 @@
+//! 会话状态
+//!
+//! 记录未应答的请求、订阅、订单簿、持仓与订单，并把网关的应答与推送整理成终端上显示的文字。
+
+use binance::model::order::{BinanceCancel, BinanceOrder};
+use cryptoflow::chat::{
+    OrderType, Position, SError, SExportState, SGatewayState, SImportReport, SImportState, SLogin,
+    SPositionReq, SPositionRsp, SRequest, SSanityCheck, SSanityReport, SSetParams, SStreamResult,
+    STradingSwitch, Side, State, TimeInForce,
+};
+use cryptoflow::compat::PROTOCOL_VERSION;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Level {
+    pub price: f64,
+    pub quantity: f64,
+}
+
+/// 深度推送，其余字段忽略
+#[derive(Debug, Clone, Deserialize)]
+pub struct Book {
+    pub time: i64,
+    pub symbol: String,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+/// 订单回报，其余字段忽略
+#[derive(Debug, Clone, Deserialize)]
+pub struct Order {
+    pub internal_id: u32,
+    pub order_id: i64,
+    pub symbol: String,
+    pub side: Side,
+    pub state: State,
+    pub order_type: OrderType,
+    pub tif: TimeInForce,
+    pub price: f64,
+    pub quantity: f64,
+    pub acc: f64,
+    pub trade_price: f64,
+}
+
+/// 一条消息处理后需要终端做的事
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// 显示一段文字
+    Print(String),
+    /// 该流的订单簿有更新
+    Book(String),
+    /// 不需要显示
+    Quiet,
+}
+
+pub struct Session {
+    pub session_id: u16,
+    pub name: String,
+    pub trading: bool,
+    pub logged_in: bool,
+    id: i64,
+    /// 下单使用的订单号，从 1 开始
+    order_id: u32,
+    /// 请求 id -> (方法, 发送时间)
+    pending: BTreeMap<i64, (String, Instant)>,
+    subscriptions: BTreeSet<String>,
+    /// 被拒绝的流 -> 原因
+    rejected: BTreeMap<String, String>,
+    books: HashMap<String, Book>,
+    positions: BTreeMap<String, Position>,
+    orders: BTreeMap<u32, Order>,
+    /// 每个流最近的一条推送与推送的条数
+    last: HashMap<String, (Value, u64)>,
+    /// 请求 id -> 状态文件的路径，`export_state` 的应答写入该文件
+    exports: HashMap<i64, String>,
+}
+
+impl Session {
+    pub fn new(session_id: u16, name: &str, trading: bool) -> Self {
+        Self {
+            session_id,
+            name: name.to_string(),
+            trading,
+            logged_in: false,
+            id: 0,
+            order_id: 0,
+            pending: BTreeMap::default(),
+            subscriptions: BTreeSet::default(),
+            rejected: BTreeMap::default(),
+            books: HashMap::default(),
+            positions: BTreeMap::default(),
+            orders: BTreeMap::default(),
+            last: HashMap::default(),
+            exports: HashMap::default(),
+        }
+    }
+
+    /// 生成请求的 JSON，并记录等待应答
+    pub fn request<T: Serialize>(&mut self, method: &str, params: T) -> anyhow::Result<String> {
+        self.id += 1;
+        let text = serde_json::to_string(&SRequest {
+            id: self.id,
+            method: method.to_string(),
+            params,
+        })?;
+        self.pending
+            .insert(self.id, (method.to_string(), Instant::now()));
+        Ok(text)
+    }
+
+    /// 最近一次请求的 id
+    pub fn last_id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn login(&mut self) -> anyhow::Result<String> {
+        let login = SLogin {
+            session_id: self.session_id,
+            name: Some(self.name.clone()),
+            trading: self.trading,
+            cancel_on_disconnect: false,
+            fills: false,
+            format: Default::default(),
+            heartbeat_ms: None,
+            tenant: None,
+            token: None,
+            version: Some(PROTOCOL_VERSION),
+        };
+        self.request("login", login)
+    }
+
+    pub fn get_positions(&mut self) -> anyhow::Result<String> {
+        let req = SPositionReq {
+            session_id: self.session_id,
+            symbols: Vec::new(),
+        };
+        self.request("get_positions", req)
+    }
+
+    pub fn order(
+        &mut self,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        order_type: OrderType,
+        tif: TimeInForce,
+    ) -> anyhow::Result<(u32, String)> {
+        self.order_id += 1;
+        let order = self.binance_order(
+            self.order_id,
+            symbol,
+            side,
+            quantity,
+            price,
+            order_type,
+            tif,
+        );
+        Ok((self.order_id, self.request("order", order)?))
+    }
+
+    /// 修改订单 `internal_id` 的价格或数量
+    #[allow(clippy::too_many_arguments)]
+    pub fn replace(
+        &mut self,
+        internal_id: u32,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        order_type: OrderType,
+        tif: TimeInForce,
+    ) -> anyhow::Result<String> {
+        let order = self.binance_order(internal_id, symbol, side, quantity, price, order_type, tif);
+        self.request("replace", order)
+    }
+
+    /// 下一笔订单使用的订单号从 `id` 开始
+    pub fn set_order_id(&mut self, id: u32) {
+        self.order_id = id.saturating_sub(1);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn binance_order(
+        &self,
+        id: u32,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        order_type: OrderType,
+        tif: TimeInForce,
+    ) -> BinanceOrder {
+        BinanceOrder {
+            id,
+            symbol: symbol.to_string(),
+            price,
+            quantity,
+            side,
+            order_type,
+            tif,
+            session_id: self.session_id,
+            idempotency_key: None,
+            ttl_ms: None,
+            peg: None,
+            position_side: None,
+            side_effect_type: None,
+            reprice_passive: false,
+            force: false,
+            strategy: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn cancel(&mut self, symbol: &str, internal_id: u32) -> anyhow::Result<String> {
+        let cancel = BinanceCancel {
+            symbol: symbol.to_string(),
+            session_id: self.session_id,
+            order_id: internal_id,
+            idempotency_key: None,
+        };
+        self.request("cancel", cancel)
+    }
+
+    /// 以运维身份修改一个会话的一个参数
+    pub fn set_param(
+        &mut self,
+        token: &str,
+        session_id: u16,
+        key: &str,
+        value: Value,
+    ) -> anyhow::Result<String> {
+        let req = SSetParams {
+            session_id,
+            params: BTreeMap::from([(key.to_string(), value)]),
+            token: token.to_string(),
+        };
+        self.request("set_params", req)
+    }
+
+    /// 以运维身份核对网关本地与交易所的状态
+    pub fn sanity_check(
+        &mut self,
+        token: &str,
+        heal: bool,
+        adopt_session: Option<u16>,
+    ) -> anyhow::Result<String> {
+        let req = SSanityCheck {
+            token: token.to_string(),
+            heal,
+            adopt_session,
+        };
+        self.request("sanity_check", req)
+    }
+
+    /// 以运维身份暂停或恢复一个交易对的交易
+    pub fn switch_trading(
+        &mut self,
+        token: &str,
+        trading: bool,
+        symbol: &str,
+        cancel: bool,
+        reason: Option<String>,
+    ) -> anyhow::Result<String> {
+        let req = STradingSwitch {
+            token: token.to_string(),
+            symbol: symbol.to_string(),
+            cancel,
+            reason,
+        };
+        let method = if trading {
+            "resume_trading"
+        } else {
+            "halt_trading"
+        };
+        self.request(method, req)
+    }
+
+    /// 以运维身份导出网关的状态，应答写入 `path`
+    pub fn export_state(&mut self, token: &str, path: &str) -> anyhow::Result<String> {
+        let req = SExportState {
+            token: token.to_string(),
+        };
+        let text = self.request("export_state", req)?;
+        self.exports.insert(self.id, path.to_string());
+        Ok(text)
+    }
+
+    /// 以运维身份把 `path` 中的状态文件导入网关
+    pub fn import_state(&mut self, token: &str, path: &str) -> anyhow::Result<String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+        let state: SGatewayState = serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid state file {}: {}", path, e))?;
+        let req = SImportState {
+            token: token.to_string(),
+            state,
+        };
+        self.request("import_state", req)
+    }
+
+    /// 处理网关发来的一条消息
+    pub fn on_text(&mut self, text: &str) -> anyhow::Result<Event> {
+        let value: Value = serde_json::from_str(text)?;
+        if let (Some(id), Some(result)) = (value["id"].as_i64(), value.get("result")) {
+            return self.on_response(id, result.clone());
+        }
+
+        if value.get("bids").is_some() && value.get("asks").is_some() {
+            let stream = value["stream"].as_str().unwrap_or_default().to_string();
+            self.track(&stream, &value);
+            self.books
+                .insert(stream.clone(), serde_json::from_value(value)?);
+            return Ok(Event::Book(stream));
+        }
+        if value.get("internal_id").is_some() && value.get("state").is_some() {
+            let order: Order = serde_json::from_value(value)?;
+            let line = format_order(&order);
+            self.orders.insert(order.internal_id, order);
+            return Ok(Event::Print(line));
+        }
+        if value.get("net").is_some() {
+            let position: Position = serde_json::from_value(value)?;
+            let line = format!("Position {} {}", position.key(), position.net);
+            self.positions.insert(position.key(), position);
+            return Ok(Event::Print(line));
+        }
+        match value["stream"].as_str() {
+            Some(stream) => {
+                let stream = stream.to_string();
+                self.track(&stream, &value);
+                Ok(Event::Quiet)
+            }
+            None => Ok(Event::Print(text.to_string())),
+        }
+    }
+
+    fn track(&mut self, stream: &str, value: &Value) {
+        let count = self.last.get(stream).map(|(_, n)| *n).unwrap_or_default();
+        self.last
+            .insert(stream.to_string(), (value.clone(), count + 1));
+    }
+
+    fn on_response(&mut self, id: i64, result: Value) -> anyhow::Result<Event> {
+        let (method, sent) = match self.pending.remove(&id) {
+            Some(pending) => pending,
+            None => ("unknown".to_string(), Instant::now()),
+        };
+        let elapsed = sent.elapsed().as_millis();
+        let export = self.exports.remove(&id);
+        if result.get("code").is_some() && result.get("msg").is_some() {
+            let error: SError = serde_json::from_value(result)?;
+            return Ok(Event::Print(format!(
+                "{} #{} failed in {}ms: {} {}",
+                method, id, elapsed, error.code, error.msg
+            )));
+        }
+
+        let text = match method.as_str() {
+            "login" => {
+                self.logged_in = true;
+                format!("Logged in as session {} in {}ms", self.session_id, elapsed)
+            }
+            "subscribe" | "unsubscribe" => {
+                let results: Vec<SStreamResult> = serde_json::from_value(result)?;
+                let mut text = String::new();
+                for r in results {
+                    match (r.accepted, r.error) {
+                        (true, _) => {
+                            self.rejected.remove(&r.stream);
+                            if method == "subscribe" {
+                                self.subscriptions.insert(r.stream.clone());
+                            } else {
+                                self.subscriptions.remove(&r.stream);
+                            }
+                            writeln!(text, "{} {} ok", method, r.stream)?;
+                        }
+                        (false, error) => {
+                            let msg = error.map(|e| e.msg).unwrap_or_default();
+                            writeln!(text, "{} {} rejected: {}", method, r.stream, msg)?;
+                            self.rejected.insert(r.stream, msg);
+                        }
+                    }
+                }
+                text.trim_end().to_string()
+            }
+            "get_positions" => {
+                let rsp: SPositionRsp = serde_json::from_value(result)?;
+                self.positions = rsp.positions.into_iter().map(|p| (p.key(), p)).collect();
+                self.render_positions()
+            }
+            "sanity_check" => format_sanity(&serde_json::from_value(result)?)?,
+            "export_state" => {
+                let state: SGatewayState = serde_json::from_value(result)?;
+                let path = export.unwrap_or_else(|| "gateway-state.json".to_string());
+                match std::fs::write(&path, serde_json::to_string_pretty(&state)?) {
+                    Ok(()) => format!(
+                        "Exported {} sessions of {} to {}, counters {:?}",
+                        state.sessions.len(),
+                        state.venue,
+                        path,
+                        state.counters
+                    ),
+                    Err(e) => format!("Failed to write {}: {}", path, e),
+                }
+            }
+            "import_state" => {
+                let report: SImportReport = serde_json::from_value(result)?;
+                format!(
+                    "Imported sessions {:?}: {} positions, {} working orders, counters {:?}",
+                    report.sessions, report.positions, report.orders, report.counters
+                )
+            }
+            _ if result.is_null() => format!("{} #{} ok in {}ms", method, id, elapsed),
+            _ => format!("{} #{} in {}ms: {}", method, id, elapsed, result),
+        };
+        Ok(Event::Print(text))
+    }
+
+    /// 订单簿，卖盘在上、买盘在下，各显示 `levels` 档
+    pub fn render_book(&self, stream: &str, levels: usize) -> String {
+        let Some(book) = self.books.get(stream) else {
+            return format!("No depth of {}", stream);
+        };
+        let mut text = format!("{} {} @ {}\n", book.symbol, stream, book.time);
+        for level in book.asks.iter().take(levels).rev() {
+            let _ = writeln!(text, "{:>16} {:>16}  ask", level.price, level.quantity);
+        }
+        if let (Some(bid), Some(ask)) = (book.bids.first(), book.asks.first()) {
+            let _ = writeln!(text, "{:>16} spread", ask.price - bid.price);
+        }
+        for level in book.bids.iter().take(levels) {
+            let _ = writeln!(text, "{:>16} {:>16}  bid", level.price, level.quantity);
+        }
+        text.trim_end().to_string()
+    }
+
+    pub fn render_positions(&self) -> String {
+        if self.positions.is_empty() {
+            return "No positions".into();
+        }
+        let mut text = String::new();
+        for (key, p) in self.positions.iter() {
+            let _ = write!(text, "{:<24} {:>16}", key, p.net);
+            if let Some(price) = p.avg_price {
+                let _ = write!(text, " avg {}", price);
+            }
+            if let Some(price) = p.entry_price {
+                let _ = write!(text, " entry {}", price);
+            }
+            if let Some(pnl) = p.unrealized_pnl {
+                let _ = write!(text, " upnl {}", pnl);
+            }
+            text.push('\n');
+        }
+        text.trim_end().to_string()
+    }
+
+    pub fn render_orders(&self) -> String {
+        if self.orders.is_empty() {
+            return "No orders".into();
+        }
+        self.orders
+            .values()
+            .map(format_order)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn render_last(&self, stream: &str) -> String {
+        match self.last.get(stream) {
+            Some((value, count)) => format!("#{} {}", count, value),
+            None => format!("Nothing received on {}", stream),
+        }
+    }
+
+    pub fn render_state(&self) -> String {
+        let mut text = format!(
+            "session {} ({}) trading={} logged_in={}\n",
+            self.session_id, self.name, self.trading, self.logged_in
+        );
+        let _ = writeln!(text, "subscriptions:");
+        for stream in self.subscriptions.iter() {
+            let count = self.last.get(stream).map(|(_, n)| *n).unwrap_or_default();
+            let _ = writeln!(text, "  {} {} messages", stream, count);
+        }
+        for (stream, msg) in self.rejected.iter() {
+            let _ = writeln!(text, "  {} rejected: {}", stream, msg);
+        }
+        let working = self
+            .orders
+            .values()
+            .filter(|o| o.state.is_working())
+            .count();
+        let _ = writeln!(
+            text,
+            "orders: {} working of {}, positions: {}",
+            working,
+            self.orders.len(),
+            self.positions.len()
+        );
+        let _ = writeln!(text, "pending requests:");
+        for (id, (method, sent)) in self.pending.iter() {
+            let _ = writeln!(
+                text,
+                "  #{} {} {}ms",
+                id,
+                method,
+                sent.elapsed().as_millis()
+            );
+        }
+        text.trim_end().to_string()
+    }
+}
+
+fn format_order(order: &Order) -> String {
+    format!(
+        "Order {} [{}] {} {:?} {:?} {:?} {}@{} {:?} filled {}@{}",
+        order.internal_id,
+        order.order_id,
+        order.symbol,
+        order.side,
+        order.order_type,
+        order.tif,
+        order.quantity,
+        order.price,
+        order.state,
+        order.acc,
+        order.trade_price
+    )
+}
+
+/// 核对结果，每个差异一行
+fn format_sanity(report: &SSanityReport) -> anyhow::Result<String> {
+    if report.clean {
+        return Ok("Sanity check clean".to_string());
+    }
+    let mut text = String::new();
+    for order in report.unknown_orders.iter() {
+        let canceled = match report.canceled.contains(&order.order_id) {
+            true => " canceled",
+            false => "",
+        };
+        writeln!(
+            text,
+            "unknown order {} [{}] {}{}",
+            order.symbol, order.order_id, order.client_order_id, canceled
+        )?;
+    }
+    for order in report.missing_orders.iter() {
+        writeln!(
+            text,
+            "missing order {} of session {} {}",
+            order.order_id, order.session_id, order.symbol
+        )?;
+    }
+    for (kind, diffs) in [
+        ("position", &report.positions),
+        ("balance", &report.balances),
+    ] {
+        for diff in diffs.iter() {
+            let adopted = match report.adopted.contains(&diff.key) {
+                true => " adopted",
+                false => "",
+            };
+            writeln!(
+                text,
+                "{} {} local {} exchange {}{}",
+                kind, diff.key, diff.local, diff.exchange, adopted
+            )?;
+        }
+    }
+    Ok(text.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::new(1, "cli", true);
+        let login: Value = serde_json::from_str(&session.login().unwrap()).unwrap();
+        assert_eq!(login["method"], "login");
+        let reply = r#"{"id":1,"result":{"session_id":1,"trading":true}}"#;
+        session.on_text(reply).unwrap();
+        assert!(session.logged_in);
+
+        let sub = session
+            .request("subscribe", ["btcusdt@depth5", "foo@bar"])
+            .unwrap();
+        assert!(sub.contains(r#""id":2"#));
+        let reply = r#"{"id":2,"result":[{"stream":"btcusdt@depth5","accepted":true},
+            {"stream":"foo@bar","accepted":false,"error":{"code":-10003,"msg":"invalid symbol"}}]}"#;
+        let Event::Print(text) = session.on_text(reply).unwrap() else {
+            panic!("no reply");
+        };
+        assert!(text.contains("foo@bar rejected: invalid symbol"));
+        assert!(session.render_state().contains("btcusdt@depth5 0 messages"));
+
+        let depth = r#"{"time":1,"symbol":"btcusdt","stream":"btcusdt@depth5",
+            "bids":[{"price":99.0,"quantity":1.0}],"asks":[{"price":101.0,"quantity":2.0}]}"#;
+        assert_eq!(
+            session.on_text(depth).unwrap(),
+            Event::Book("btcusdt@depth5".into())
+        );
+        let book = session.render_book("btcusdt@depth5", 5);
+        assert!(book.find("101").unwrap() < book.find("99").unwrap());
+        assert!(book.contains("2 spread"));
+
+        let (id, _) = session
+            .order(
+                "btcusdt",
+                Side::BUY,
+                1.0,
+                99.0,
+                OrderType::LIMIT,
+                TimeInForce::GTC,
+            )
+            .unwrap();
+        assert_eq!(id, 1);
+        let error = r#"{"id":3,"result":{"code":-20001,"msg":"not trading"}}"#;
+        let Event::Print(text) = session.on_text(error).unwrap() else {
+            panic!("no reply");
+        };
+        assert!(text.starts_with("order #3 failed"));
+
+        let order = r#"{"state":"NEW","order_id":9,"symbol":"btcusdt","side":"BUY","order_type":"LIMIT",
+            "tif":"GTC","price":99.0,"quantity":1.0,"internal_id":1,"trade_time":0,"trade_price":0.0,
+            "trade_quantity":0.0,"acc":0.0,"making":false}"#;
+        session.on_text(order).unwrap();
+        assert!(session.render_state().contains("orders: 1 working of 1"));
+
+        session.get_positions().unwrap();
+        let reply =
+            r#"{"id":4,"result":{"session_id":1,"positions":[{"symbol":"btcusdt","net":0.5}]}}"#;
+        let Event::Print(text) = session.on_text(reply).unwrap() else {
+            panic!("no reply");
+        };
+        assert!(text.starts_with("btcusdt"));
+        assert!(session.render_state().contains("pending requests:"));
+        assert!(!session.render_state().contains("#4"));
+
+        // 导出的状态写入文件，再从文件导入
+        let path = std::env::temp_dir().join(format!("cli-state-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        session.export_state("secret", path).unwrap();
+        let reply = r#"{"id":5,"result":{"version":1,"venue":"binance-usdt","time":1,
+            "sessions":[{"session_id":1,"positions":[{"symbol":"btcusdt","net":0.5}],"working":{"7":"btcusdt"}}],
+            "counters":{"roll":3758096386}}}"#;
+        let Event::Print(text) = session.on_text(reply).unwrap() else {
+            panic!("no reply");
+        };
+        assert!(text.starts_with("Exported 1 sessions of binance-usdt"));
+        let import: Value =
+            serde_json::from_str(&session.import_state("secret", path).unwrap()).unwrap();
+        assert_eq!(import["method"], "import_state");
+        assert_eq!(
+            import["params"]["state"]["sessions"][0]["working"]["7"],
+            "btcusdt"
+        );
+        assert!(session
+            .import_state("secret", "/nonexistent/state.json")
+            .is_err());
+    }
+}
@@ -0,0 +1,6 @@
+//! OKX 交易所
+//!
+//! 目前只有私有频道 `account`、`positions`、`orders` 的数据模型，以及到网关统一的余额、持仓与订单结构的
+//! 转换，供之后的 OKX 交易模块与只读的组合监控使用。连接与登录见 `websocket::OkxWebsocketClient`。
+
+pub mod model;
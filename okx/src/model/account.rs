@@ -0,0 +1,80 @@
+//! 账户频道，每种币的余额
+//! see: https://www.okx.com/docs-v5/zh/#trading-account-websocket-account-channel
+
+use super::number;
+use cryptoflow::chat::SBalance;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct OkxAccount {
+    pub uTime: String,
+    /// 美元计的总权益
+    #[serde(default)]
+    pub totalEq: String,
+    pub details: Vec<OkxBalance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct OkxBalance {
+    pub ccy: String,
+    pub availBal: String,
+    pub frozenBal: String,
+    /// 币种权益
+    #[serde(default)]
+    pub eq: String,
+    pub uTime: String,
+}
+
+impl OkxAccount {
+    /// 统一的余额
+    pub fn balances(&self) -> Vec<SBalance> {
+        self.details.iter().map(SBalance::from).collect()
+    }
+}
+
+impl From<&OkxBalance> for SBalance {
+    fn from(value: &OkxBalance) -> Self {
+        Self {
+            asset: value.ccy.clone(),
+            free: number(&value.availBal),
+            locked: number(&value.frozenBal),
+            time: value.uTime.parse().unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_okx_account() {
+        let s = r#"{
+                        "uTime": "1705564223311",
+                        "totalEq": "41624.32",
+                        "isoEq": "0",
+                        "details": [{
+                            "ccy": "USDT",
+                            "availBal": "1000.5",
+                            "frozenBal": "200",
+                            "cashBal": "1200.5",
+                            "eq": "1200.5",
+                            "uTime": "1705564213903"
+                        }, {
+                            "ccy": "BTC",
+                            "availBal": "",
+                            "frozenBal": "0.1",
+                            "eq": "0.1",
+                            "uTime": "1705564213903"
+                        }]
+                    }"#;
+        let account: OkxAccount = serde_json::from_str(s).unwrap();
+        let balances = account.balances();
+        assert_eq!(balances[0].asset, "USDT");
+        assert_eq!(balances[0].total(), 1200.5);
+        assert_eq!(balances[0].time, 1705564213903);
+        assert_eq!((balances[1].free, balances[1].locked), (0.0, 0.1));
+    }
+}
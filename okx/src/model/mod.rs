@@ -0,0 +1,94 @@
+//! 私有频道的推送
+//!
+//! 推送是 `{"arg": {"channel": "orders", ...}, "data": [...]}`，按 `arg.channel` 选择数据的类型；
+//! 登录、订阅的应答带 `event` 字段，不是推送。
+//! see: https://www.okx.com/docs-v5/zh/#trading-account-websocket
+
+pub mod account;
+pub mod order;
+pub mod position;
+
+use account::OkxAccount;
+use order::OkxOrder;
+use position::OkxPosition;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 推送所属的频道
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct OkxArg {
+    pub channel: String,
+    #[serde(default)]
+    pub instType: String,
+    #[serde(default)]
+    pub uid: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum PrivateEvent {
+    Account(Vec<OkxAccount>),
+    Positions(Vec<OkxPosition>),
+    Orders(Vec<OkxOrder>),
+}
+
+/// 解码私有频道的推送，应答与其他频道返回 None
+pub fn decode(mut value: Value) -> anyhow::Result<Option<PrivateEvent>> {
+    if value.get("event").is_some() {
+        return Ok(None);
+    }
+    let Some(arg) = value.get("arg") else {
+        return Ok(None);
+    };
+    let arg = OkxArg::deserialize(arg)?;
+    let data = value.get_mut("data").map(Value::take).unwrap_or_default();
+    let event = match arg.channel.as_str() {
+        "account" => PrivateEvent::Account(serde_json::from_value(data)?),
+        "positions" => PrivateEvent::Positions(serde_json::from_value(data)?),
+        "orders" => PrivateEvent::Orders(serde_json::from_value(data)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+/// OKX 的数值是字符串，没有值时为空字符串
+pub(crate) fn number(s: &str) -> f64 {
+    s.parse().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode() {
+        let push = json!({
+            "arg": {"channel": "positions", "uid": "77982378738415879", "instType": "ANY"},
+            "data": [{
+                "instId": "BTC-USDT-SWAP",
+                "instType": "SWAP",
+                "mgnMode": "cross",
+                "posSide": "net",
+                "pos": "-2",
+                "avgPx": "43000.5",
+                "upl": "-12.3",
+                "lever": "10",
+                "uTime": "1704876947000"
+            }]
+        });
+        match decode(push).unwrap() {
+            Some(PrivateEvent::Positions(positions)) => {
+                assert_eq!(positions[0].instId, "BTC-USDT-SWAP")
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+
+        let ack = json!({"event": "subscribe", "arg": {"channel": "orders"}, "connId": "a4d3ae55"});
+        assert!(decode(ack).unwrap().is_none());
+        let other = json!({"arg": {"channel": "balance_and_position"}, "data": []});
+        assert!(decode(other).unwrap().is_none());
+        let invalid = json!({"arg": {"channel": "orders"}, "data": [{"instId": 1}]});
+        assert!(decode(invalid).is_err());
+    }
+}
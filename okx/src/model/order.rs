@@ -0,0 +1,175 @@
+//! 订单频道
+//! see: https://www.okx.com/docs-v5/zh/#order-book-trading-trade-ws-order-channel
+
+use super::number;
+use cryptoflow::chat::{OrderType, SFill, SOrder, Side, State, TimeInForce};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct OkxOrder {
+    pub instId: String,
+    pub ordId: String,
+    /// 网关下单时低 32 位是内部 id，与币安的 clientOrderId 相同
+    #[serde(default)]
+    pub clOrdId: String,
+    pub side: String,
+    pub ordType: String,
+    pub px: String,
+    pub sz: String,
+    pub state: String,
+    #[serde(default)]
+    pub tradeId: String,
+    #[serde(default)]
+    pub fillPx: String,
+    #[serde(default)]
+    pub fillSz: String,
+    #[serde(default)]
+    pub fillTime: String,
+    /// 最近一笔成交的手续费，扣费为负，返佣为正
+    #[serde(default)]
+    pub fillFee: String,
+    #[serde(default)]
+    pub fillFeeCcy: String,
+    pub accFillSz: String,
+    /// `M` 为挂单成交，`T` 为吃单成交，没有成交时为空
+    #[serde(default)]
+    pub execType: String,
+    pub uTime: String,
+}
+
+impl OkxOrder {
+    fn side(&self) -> Side {
+        match self.side.as_str() {
+            "sell" => Side::SELL,
+            _ => Side::BUY,
+        }
+    }
+
+    fn internal_id(&self) -> u32 {
+        (self.clOrdId.parse::<u64>().unwrap_or_default() & 0xFFFFFFFF) as u32
+    }
+
+    /// 这次推送带来的成交，没有成交时为 None
+    pub fn fill(&self) -> Option<SFill> {
+        let quantity = number(&self.fillSz);
+        if quantity <= 0.0 {
+            return None;
+        }
+        Some(SFill {
+            internal_id: self.internal_id(),
+            order_id: self.ordId.parse().unwrap_or_default(),
+            symbol: self.instId.to_lowercase(),
+            side: self.side(),
+            trade_id: self.tradeId.parse().unwrap_or_default(),
+            time: self.fillTime.parse().unwrap_or_default(),
+            price: number(&self.fillPx),
+            quantity,
+            maker: self.execType == "M",
+            commission: -number(&self.fillFee),
+            commission_asset: self.fillFeeCcy.clone(),
+        })
+    }
+}
+
+/// OKX 的订单类型同时表示有效方式，未知的类型按限价单处理
+pub fn order_type(ord_type: &str) -> (OrderType, TimeInForce) {
+    match ord_type {
+        "limit" | "mmp" => (OrderType::LIMIT, TimeInForce::GTC),
+        "market" => (OrderType::MARKET, TimeInForce::GTC),
+        "post_only" | "mmp_and_post_only" => (OrderType::LIMIT_MAKER, TimeInForce::GTX),
+        "fok" => (OrderType::LIMIT, TimeInForce::FOK),
+        "ioc" => (OrderType::LIMIT, TimeInForce::IOC),
+        "optimal_limit_ioc" => (OrderType::MARKET, TimeInForce::IOC),
+        _ => {
+            warn!("Unknown order type {}", ord_type);
+            (OrderType::LIMIT, TimeInForce::GTC)
+        }
+    }
+}
+
+impl From<&OkxOrder> for SOrder {
+    fn from(value: &OkxOrder) -> Self {
+        let (order_type, tif) = order_type(&value.ordType);
+        let state = State::from_okx_str(&value.state).unwrap_or_else(|| {
+            warn!("Unknown order state {}", value.state);
+            State::LIVE
+        });
+        Self {
+            internal_id: value.internal_id(),
+            state,
+            order_id: value.ordId.parse().unwrap_or_default(),
+            symbol: value.instId.to_lowercase(),
+            side: value.side(),
+            order_type,
+            tif,
+            price: number(&value.px),
+            quantity: number(&value.sz),
+            trade_time: value.fillTime.parse().unwrap_or_default(),
+            trade_price: number(&value.fillPx),
+            trade_quantity: number(&value.fillSz),
+            acc: number(&value.accFillSz),
+            making: value.execType == "M",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_okx_order() {
+        let s = r#"{
+                        "instId": "BTC-USDT",
+                        "ordId": "312269865356374016",
+                        "clOrdId": "4294967303",
+                        "side": "sell",
+                        "ordType": "post_only",
+                        "px": "43100",
+                        "sz": "0.5",
+                        "state": "partially_filled",
+                        "tradeId": "242589207",
+                        "fillPx": "43100",
+                        "fillSz": "0.2",
+                        "fillTime": "1704876947000",
+                        "fillFee": "-0.862",
+                        "fillFeeCcy": "USDT",
+                        "accFillSz": "0.3",
+                        "execType": "M",
+                        "uTime": "1704876947001"
+                    }"#;
+        let okx: OkxOrder = serde_json::from_str(s).unwrap();
+        let order = SOrder::from(&okx);
+        assert_eq!(order.internal_id, 7);
+        assert_eq!(order.order_id, 312269865356374016);
+        assert_eq!(order.symbol, "btc-usdt");
+        assert_eq!(order.side, Side::SELL);
+        assert!(matches!(order.state, State::PARTIALLY_FILLED));
+        assert_eq!(
+            (order.order_type, order.tif),
+            (OrderType::LIMIT_MAKER, TimeInForce::GTX)
+        );
+        assert_eq!(
+            (order.quantity, order.trade_quantity, order.acc),
+            (0.5, 0.2, 0.3)
+        );
+        assert!(order.making);
+
+        let fill = okx.fill().unwrap();
+        assert_eq!(fill.trade_id, 242589207);
+        assert_eq!(fill.commission, 0.862);
+        assert_eq!(fill.commission_asset, "USDT");
+
+        // 新订单没有成交
+        let live = OkxOrder {
+            state: "live".into(),
+            fillSz: "0".into(),
+            execType: "".into(),
+            ..okx
+        };
+        assert!(live.fill().is_none());
+        assert!(matches!(SOrder::from(&live).state, State::LIVE));
+    }
+}
@@ -0,0 +1,99 @@
+//! 持仓频道
+//! see: https://www.okx.com/docs-v5/zh/#trading-account-websocket-positions-channel
+
+use super::number;
+use cryptoflow::chat::{Position, PositionSide};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct OkxPosition {
+    pub instId: String,
+    pub instType: String,
+    /// `cross` 或 `isolated`
+    pub mgnMode: String,
+    /// 买卖模式为 `net`，开平仓模式为 `long`、`short`
+    pub posSide: String,
+    /// 买卖模式下空头为负，开平仓模式下总为正
+    pub pos: String,
+    pub avgPx: String,
+    pub upl: String,
+    pub lever: String,
+    pub uTime: String,
+}
+
+impl OkxPosition {
+    /// 空头为负的持仓数量，单位与 `pos` 相同（合约为张）
+    pub fn amount(&self) -> f64 {
+        let pos = number(&self.pos);
+        match self.posSide.as_str() {
+            "short" => -pos.abs(),
+            _ => pos,
+        }
+    }
+
+    fn side(&self) -> Option<PositionSide> {
+        match self.posSide.as_str() {
+            "net" => Some(PositionSide::BOTH),
+            "long" => Some(PositionSide::LONG),
+            "short" => Some(PositionSide::SHORT),
+            _ => None,
+        }
+    }
+}
+
+/// 与币安相同，`net` 是会话的持仓，这里为 0，账户的持仓数量见 [`OkxPosition::amount`]
+impl From<&OkxPosition> for Position {
+    fn from(value: &OkxPosition) -> Self {
+        Self {
+            side: value.side(),
+            entry_price: value.avgPx.parse().ok(),
+            unrealized_pnl: value.upl.parse().ok(),
+            margin_type: serde_json::from_value(value.mgnMode.as_str().into()).ok(),
+            leverage: value.lever.parse::<f64>().ok().map(|l| l as u16),
+            ..Position::new(&value.instId.to_lowercase(), 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::MarginType;
+
+    #[test]
+    fn test_okx_position() {
+        let s = r#"{
+                        "instId": "BTC-USDT-SWAP",
+                        "instType": "SWAP",
+                        "mgnMode": "isolated",
+                        "posSide": "short",
+                        "pos": "3",
+                        "avgPx": "43000.5",
+                        "upl": "-12.3",
+                        "lever": "5",
+                        "uTime": "1704876947000"
+                    }"#;
+        let okx: OkxPosition = serde_json::from_str(s).unwrap();
+        assert_eq!(okx.amount(), -3.0);
+        let position = Position::from(&okx);
+        assert_eq!(position.key(), "btc-usdt-swap:SHORT");
+        assert_eq!(position.entry_price, Some(43000.5));
+        assert_eq!(position.unrealized_pnl, Some(-12.3));
+        assert_eq!(position.margin_type, Some(MarginType::ISOLATED));
+        assert_eq!(position.leverage, Some(5));
+
+        let net = OkxPosition {
+            posSide: "net".into(),
+            pos: "-2".into(),
+            mgnMode: "cross".into(),
+            lever: "".into(),
+            ..okx
+        };
+        assert_eq!(net.amount(), -2.0);
+        let position = Position::from(&net);
+        assert_eq!(position.key(), "btc-usdt-swap");
+        assert_eq!(position.margin_type, Some(MarginType::CROSSED));
+        assert_eq!(position.leverage, None);
+    }
+}
@@ -2,6 +2,8 @@ import sys
 from .pyalgo import *
 from pyalgo.core.trd import SmartOrder, DepthSubscription, BarSubscription
 from pyalgo.core.engine import Engine
+from pyalgo.core.backtest import BacktestEngine
+from pyalgo.core.sweep import sweep
 from pyalgo.core.context import Context
 
 
@@ -10,11 +12,14 @@ __all__ = [
     "DepthSubscription",
     "BarSubscription",
     "Engine",
+    "BacktestEngine",
+    "sweep",
     "Context",
     "SmartOrder",
     "Side",
     "OrderType",
     "Tif",
+    "PositionSide",
     "State",
     "Phase",
     "EventType",
@@ -22,6 +27,9 @@ __all__ = [
     "Order",
     "Subscription",
     "Session",
+    "BacktestSession",
+    "ResearchSession",
+    "BacktestReport",
     "Kline",
     "Event",
     "TradingPhase",
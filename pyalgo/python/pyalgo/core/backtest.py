@@ -0,0 +1,67 @@
+from pyalgo import BacktestSession, BacktestReport
+from .context import Context
+from typing import Dict, List, Optional, Tuple
+
+
+class BacktestEngine:
+    """用录制数据回放策略，接口与 Engine 一致"""
+
+    def __init__(
+        self,
+        data_dir: str = "data/csv",
+        maker_fee: float = 0.0,
+        taker_fee: float = 0.0,
+        products: Optional[str] = None,
+        start: Optional[int] = None,
+        end: Optional[int] = None,
+        fill_model: str = "touch",
+        participation: float = 0.0,
+        slippage: Optional[List[Tuple[float, float]]] = None,
+        seed: int = 0,
+    ):
+        self.data_dir = data_dir
+        # 只回放 [start, end) 毫秒时间内的行情
+        self.start = start
+        self.end = end
+        # 成交模型，见 BacktestSession.set_fill_model
+        self.fill_model = fill_model
+        self.participation = participation
+        self.slippage = slippage or []
+        self.seed = seed
+        self.maker_fee = maker_fee
+        self.taker_fee = taker_fee
+        self.products = products
+
+        self.contexts: Dict[int, Context] = {}
+
+    def make_session(
+        self, session_id: int = 0, name: str = "backtest", trading: bool = True, **_
+    ) -> Context:
+        if session_id in self.contexts:
+            return self.contexts[session_id]
+
+        session = BacktestSession(self.data_dir, session_id, name, trading)
+        session.set_fee(self.maker_fee, self.taker_fee)
+        session.set_fill_model(self.fill_model, self.participation, self.slippage, self.seed)
+        if self.products:
+            session.set_products(self.products)
+        if self.start is not None or self.end is not None:
+            session.set_window(self.start or 0, self.end or 2**64 - 1)
+
+        context = Context(self.data_dir, session_id, name, trading, session=session)
+        self.contexts[session_id] = context
+
+        context.connect()
+
+        return context
+
+    def run(self) -> Dict[int, BacktestReport]:
+        """回放到数据结束，返回每个会话的回测结果"""
+        while not all(ctx.session.finished for ctx in self.contexts.values()):
+            for context in self.contexts.values():
+                context.process()
+
+        reports = {id: ctx.session.report for id, ctx in self.contexts.items()}
+        for id, report in reports.items():
+            print(f"[BACKTEST] session {id}: {report}")
+        return reports
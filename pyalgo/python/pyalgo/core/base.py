@@ -1,5 +1,6 @@
 from abc import ABC
-from pyalgo import Side, OrderType, Tif
+from typing import List, Optional, Sequence, Tuple
+from pyalgo import Side, OrderType, Tif, PositionSide
 
 
 # avoid circular import
@@ -14,8 +15,40 @@ class ContextBase(ABC):
         side: Side,
         order_type: OrderType,
         tif: Tif,
+        idempotency_key: Optional[str] = None,
+        ttl_ms: Optional[int] = None,
+        peg_offset_ticks: Optional[int] = None,
+        position_side: Optional[PositionSide] = None,
+        reprice_passive: bool = False,
+        strategy: Optional[str] = None,
+        tags: Optional[List[str]] = None,
+        force: bool = False,
     ):
         raise NotImplemented
 
-    def cancel(self, symbol: str, order_id: int):
+    def add_orders(
+        self,
+        symbols: Sequence[str],
+        sides: Sequence[Side],
+        prices: Sequence[float],
+        quantities: Sequence[float],
+        order_type: OrderType,
+        tif: Tif,
+        strategy: Optional[str] = None,
+        tags: Optional[List[str]] = None,
+    ):
+        raise NotImplemented
+
+    def replace(self, order_id: int, price: float, quantity: Optional[float] = None) -> bool:
+        raise NotImplemented
+
+    def cancel(self, symbol: str, order_id: int, idempotency_key: Optional[str] = None):
+        raise NotImplemented
+
+    def quote(
+        self,
+        symbol: str,
+        bid: Optional[Tuple[float, float]] = None,
+        ask: Optional[Tuple[float, float]] = None,
+    ):
         raise NotImplemented
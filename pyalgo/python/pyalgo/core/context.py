@@ -1,13 +1,33 @@
 from pyalgo import *
-from typing import Union, Dict
+from typing import Union, Dict, List, Optional, Sequence, Tuple
 from .trd import *
 
 
 class Context(ContextBase):
     """"""
 
-    def __init__(self, addr: str, session_id: int, name: str, trading: bool):
-        self.session = Session(addr, session_id, name, trading)
+    def __init__(
+        self,
+        addr: str,
+        session_id: int,
+        name: str,
+        trading: bool,
+        session: Optional[Union[Session, BacktestSession]] = None,
+        cancel_on_disconnect: bool = False,
+        fills: bool = False,
+        heartbeat_ms: Optional[int] = None,
+        tenant: Optional[str] = None,
+        token: Optional[str] = None,
+    ):
+        # 回测时传入 BacktestSession，接口与 Session 一致
+        if session is None:
+            session = Session(addr, session_id, name, trading)
+            session.set_cancel_on_disconnect(cancel_on_disconnect)
+            session.set_fills(fills)
+            session.set_heartbeat(heartbeat_ms)
+            if tenant is not None:
+                session.set_tenant(tenant, token)
+        self.session = session
 
         self.tradings: Dict[str, Tradable] = {}
         self.subscriptions: Dict[str, Union[DepthSubscription, BarSubscription]] = {}
@@ -35,18 +55,83 @@ class Context(ContextBase):
         if sub := self.subscriptions.get(data.stream):
             sub.on_market(data)
 
+    def on_bar_close(self, data: BarClose):
+        if sub := self.subscriptions.get(data.stream):
+            if isinstance(sub, BarSubscription):
+                sub.on_bar_close(data)
+
+    def on_derived(self, data: Derived):
+        # 派生流没有对应的订阅对象，需要的策略覆盖这个方法
+        pass
+
+    def on_trade_flow(self, data: TradeFlow):
+        # 成交流向只有统计值，需要的策略覆盖这个方法
+        pass
+
+    def on_volatility(self, data: Volatility):
+        # get_volatility 的结果与收线时推送的估计，需要的策略覆盖这个方法
+        pass
+
+    def on_basis(self, data: Basis):
+        # get_basis 的结果与订阅 basis 后的推送，做期现套利的策略覆盖这个方法
+        pass
+
+    def on_params(self, data: Params):
+        # 启动时 get_params 的结果与运维修改参数的推送，需要在线调参的策略覆盖这个方法
+        pass
+
+    def on_my_trades(self, data: MyTrades):
+        # get_my_trades 的结果，做成交分析的策略覆盖这个方法
+        pass
+
+    def on_depth_snapshot(self, snapshot: DepthSnapshot):
+        # get_depth_snapshot 的结果，维护本地订单簿的策略覆盖这个方法
+        pass
+
+    def on_delivery(self, data: Delivery):
+        # 交割合约的交割提醒与结算，持有季度合约的策略覆盖这个方法移仓或平仓
+        pass
+
+    def on_roll(self, data: Roll):
+        # 季度合约交割前的移仓提醒，需要移仓的策略覆盖这个方法并调用 session.roll
+        pass
+
+    def on_roll_result(self, data: RollResult):
+        # session.roll 的结果
+        pass
+
+    def on_symbol_changed(self, data: SymbolChanged):
+        # 用连续合约别名订阅的流换到了新的合约，按合约累积 K 线或深度的策略覆盖这个方法重置状态
+        pass
+
+    def on_trading_status(self, data: TradingStatus):
+        # 运维暂停或恢复了一个交易对的交易，暂停期间该交易对的新订单会被拒绝
+        pass
+
+    def on_venue_status(self, data: VenueStatus):
+        # 交易场所维护或交易对状态变化，按交易对暂停交易时另有 on_trading_status
+        pass
+
+    def on_order_cap(self, data: OrderCap):
+        # 订单会超过交易所的挂单数上限，网关排队（queued 为 True）或发出了排队的订单
+        pass
+
     def on_order(self, order: Order):
         if trading := self.tradings.get(order.symbol):
             trading.on_order(order)
 
+    def on_fill(self, fill: Fill):
+        if trading := self.tradings.get(fill.symbol):
+            trading.on_fill(fill)
+
     def subscribe(
-        self, symbol: str, stream: str
+        self, symbol: str, stream: str, tag: Optional[str] = None
     ) -> Union[DepthSubscription, BarSubscription]:
         key = symbol + "@" + stream
         if key in self.tradings:
             raise Exception(f"Duplicate subscribe {key}")
 
-        sub = self.session.subscribe(symbol, stream)
+        sub = self.session.subscribe(symbol, stream, tag)
         
 
         if stream.startswith("kline"):
@@ -63,20 +148,100 @@ class Context(ContextBase):
 
             return depth
 
+        elif stream.startswith("cvd"):
+            # 数据以 EventType.TradeFlow 推送给 on_trade_flow
+            return sub
+
         else:
             raise Exception(f"Unsupported stream {stream}")
 
+    def derive(self, symbol: str, stream: str, name: str, expr: str):
+        self.session.derive(symbol, stream, name, expr)
+
+    def get_volatility(self, symbol: str, window: str):
+        self.session.get_volatility(symbol, window)
+
+    def get_basis(self, symbol: str):
+        self.session.get_basis(symbol)
+
+    def get_depth_snapshot(self, symbol: str, limit: int):
+        self.session.get_depth_snapshot(symbol, limit)
+
+    def get_my_trades(self, symbol: str, start: int, end: int):
+        self.session.get_my_trades(symbol, start, end)
+
+    def get_params(self):
+        self.session.get_params()
+
     def process(self):
         if event := self.session.process():
-            print("[CTX] event:", event.event_type)
-            match event.event_type:
-                case EventType.Depth | EventType.Kline:
-                    self.on_market(event.data)
+            self.dispatch(event)
+            return event
 
-                case EventType.Order:
-                    self.on_order(event.data)
+    def poll(self, max_n: Optional[int] = None, timeout_ms: Optional[int] = None):
+        """一次取出并处理多个事件，见 `Session.poll_events`"""
+        events = self.session.poll_events(max_n, timeout_ms)
+        for event in events:
+            self.dispatch(event)
+        return events
 
-            return event
+    def dispatch(self, event):
+        """把一个事件交给对应的回调"""
+        print("[CTX] event:", event.event_type)
+        match event.event_type:
+            case EventType.Depth | EventType.Kline:
+                self.on_market(event.data)
+
+            case EventType.BarClose:
+                self.on_bar_close(event.data)
+
+            case EventType.Derived:
+                self.on_derived(event.data)
+
+            case EventType.TradeFlow:
+                self.on_trade_flow(event.data)
+
+            case EventType.Volatility:
+                self.on_volatility(event.data)
+
+            case EventType.Basis:
+                self.on_basis(event.data)
+
+            case EventType.Params:
+                self.on_params(event.data)
+
+            case EventType.Order:
+                self.on_order(event.data)
+
+            case EventType.Fill:
+                self.on_fill(event.data)
+
+            case EventType.MyTrades:
+                self.on_my_trades(event.data)
+
+            case EventType.DepthSnapshot:
+                self.on_depth_snapshot(event.data)
+
+            case EventType.Delivery:
+                self.on_delivery(event.data)
+
+            case EventType.Roll:
+                self.on_roll(event.data)
+
+            case EventType.RollResult:
+                self.on_roll_result(event.data)
+
+            case EventType.SymbolChanged:
+                self.on_symbol_changed(event.data)
+
+            case EventType.TradingStatus:
+                self.on_trading_status(event.data)
+
+            case EventType.VenueStatus:
+                self.on_venue_status(event.data)
+
+            case EventType.OrderCap:
+                self.on_order_cap(event.data)
 
     def add_order(
         self,
@@ -86,8 +251,58 @@ class Context(ContextBase):
         side: Side,
         order_type: OrderType,
         tif: Tif,
+        idempotency_key: Optional[str] = None,
+        ttl_ms: Optional[int] = None,
+        peg_offset_ticks: Optional[int] = None,
+        position_side: Optional[PositionSide] = None,
+        reprice_passive: bool = False,
+        strategy: Optional[str] = None,
+        tags: Optional[List[str]] = None,
+        force: bool = False,
     ) -> Optional[Order]:
-        return self.session.add_order(symbol, price, quantity, side, order_type, tif)
+        return self.session.add_order(
+            symbol,
+            price,
+            quantity,
+            side,
+            order_type,
+            tif,
+            idempotency_key,
+            ttl_ms,
+            peg_offset_ticks,
+            position_side,
+            reprice_passive,
+            strategy,
+            tags,
+            force,
+        )
 
-    def cancel(self, symbol: str, order_id: int):
-        self.session.cancel(symbol, order_id)
+    def add_orders(
+        self,
+        symbols: Sequence[str],
+        sides: Sequence[Side],
+        prices: Sequence[float],
+        quantities: Sequence[float],
+        order_type: OrderType,
+        tif: Tif,
+        strategy: Optional[str] = None,
+        tags: Optional[List[str]] = None,
+    ) -> List[Order]:
+        """批量下单，各列可以是列表或 numpy 数组，一次调用发出所有订单"""
+        return self.session.add_orders(
+            symbols, sides, prices, quantities, order_type, tif, strategy, tags
+        )
+
+    def replace(self, order_id: int, price: float, quantity: Optional[float] = None) -> bool:
+        return self.session.replace(order_id, price, quantity)
+
+    def cancel(self, symbol: str, order_id: int, idempotency_key: Optional[str] = None):
+        self.session.cancel(symbol, order_id, idempotency_key)
+
+    def quote(
+        self,
+        symbol: str,
+        bid: Optional[Tuple[float, float]] = None,
+        ask: Optional[Tuple[float, float]] = None,
+    ):
+        self.session.quote(symbol, bid, ask)
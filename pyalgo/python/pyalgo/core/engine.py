@@ -1,5 +1,5 @@
 from .context import Context
-from typing import Dict, Tuple
+from typing import Dict, Optional, Tuple
 from time import sleep
 import sys
 import signal
@@ -20,13 +20,32 @@ class Engine:
         signal.signal(signal.SIGINT, self.stop)
 
     def make_session(
-        self, addr: str, session_id: int, name: str, trading: bool = False
+        self,
+        addr: str,
+        session_id: int,
+        name: str,
+        trading: bool = False,
+        cancel_on_disconnect: bool = False,
+        fills: bool = False,
+        heartbeat_ms: Optional[int] = None,
+        tenant: Optional[str] = None,
+        token: Optional[str] = None,
     ) -> Context:
         key = (addr, session_id)
         if key in self.contexts:
             return self.contexts[key]
 
-        context = Context(addr, session_id, name, trading)
+        context = Context(
+            addr,
+            session_id,
+            name,
+            trading,
+            cancel_on_disconnect=cancel_on_disconnect,
+            fills=fills,
+            heartbeat_ms=heartbeat_ms,
+            tenant=tenant,
+            token=token,
+        )
         self.contexts[key] = context
 
         context.connect()
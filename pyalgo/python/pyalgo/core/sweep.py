@@ -0,0 +1,95 @@
+"""参数扫描与滚动验证，与 Rust 侧 `pyalgo::sweep` 的择优规则和结果表一致"""
+
+import csv
+import itertools
+import json
+from concurrent.futures import ProcessPoolExecutor
+from functools import partial
+from pathlib import Path
+from typing import Any, Callable, Dict, List, Optional, Tuple
+
+from pyalgo import walk_forward_windows
+
+HEADER = ["fold", "stage", "start", "end", "params", "pnl", "max_drawdown", "sharpe", "trades"]
+
+# run(params, start, end) -> BacktestReport，必须是模块级函数以便传给子进程
+Runner = Callable[[Dict[str, Any], int, int], Any]
+
+
+def grid(space: Dict[str, List[Any]]) -> List[Dict[str, Any]]:
+    """参数网格的笛卡尔积，按参数名排序"""
+    names = sorted(space)
+    return [dict(zip(names, values)) for values in itertools.product(*(space[n] for n in names))]
+
+
+def _evaluate(run: Runner, task: Tuple[Dict[str, Any], int, int]) -> Optional[Dict[str, Any]]:
+    params, start, end = task
+    try:
+        report = run(params, start, end)
+    except Exception as e:
+        print(f"[SWEEP] {params} [{start}, {end}) failed: {e}")
+        return None
+    return {
+        "pnl": report.pnl,
+        "max_drawdown": report.max_drawdown,
+        "sharpe": report.sharpe,
+        "trades": report.trades,
+    }
+
+
+def _better(a: Dict[str, Any], b: Dict[str, Any]) -> bool:
+    return (a["sharpe"], a["pnl"]) > (b["sharpe"], b["pnl"])
+
+
+def sweep(
+    run: Runner,
+    space: Dict[str, List[Any]],
+    start: int,
+    end: int,
+    train: Optional[int] = None,
+    test: Optional[int] = None,
+    workers: int = 1,
+    output: Optional[str] = None,
+) -> List[Dict[str, Any]]:
+    """
+    在 [start, end) 毫秒区间上滚动验证参数网格。
+
+    每个折叠在样本内窗口上评估全部组合，按夏普（相同时按盈亏）选出最优组合，
+    再在随后的样本外窗口上评估。不指定 train/test 时只在整个区间上扫描一次。
+    """
+    if train and test:
+        windows = walk_forward_windows(start, end, train, test)
+    else:
+        windows = [(start, end, end)]
+
+    combos = grid(space)
+    evaluate = partial(_evaluate, run)
+    rows: List[Dict[str, Any]] = []
+
+    with ProcessPoolExecutor(max_workers=max(workers, 1)) as pool:
+        for fold, (train_start, train_end, test_end) in enumerate(windows):
+            tasks = [(params, train_start, train_end) for params in combos]
+
+            best = None
+            for params, metrics in zip(combos, pool.map(evaluate, tasks)):
+                if metrics is None:
+                    continue
+                if best is None or _better(metrics, best[1]):
+                    best = (params, metrics)
+                rows.append(dict(fold=fold, stage="train", start=train_start, end=train_end, params=params, **metrics))
+
+            if best is None or test_end <= train_end:
+                continue
+            if metrics := evaluate((best[0], train_end, test_end)):
+                rows.append(dict(fold=fold, stage="test", start=train_end, end=test_end, params=best[0], **metrics))
+
+    if output:
+        path = Path(output)
+        path.parent.mkdir(parents=True, exist_ok=True)
+        with open(path, "w", newline="") as f:
+            writer = csv.DictWriter(f, fieldnames=HEADER)
+            writer.writeheader()
+            for row in rows:
+                writer.writerow({**row, "params": json.dumps(row["params"], separators=(",", ":"))})
+
+    return rows
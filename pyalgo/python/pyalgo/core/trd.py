@@ -1,7 +1,7 @@
 from pyalgo import *
 from datetime import datetime
 from .base import ContextBase
-from typing import Optional
+from typing import List, Optional, Tuple
 
 
 class Tradable:
@@ -12,6 +12,8 @@ class Tradable:
 
         self.subscription = subscription
         self.on_order = lambda x: None
+        # 登录时开启 fills 才会收到逐笔成交
+        self.on_fill = lambda x: None
 
     @property
     def symbol(self) -> str:
@@ -80,11 +82,46 @@ class Tradable:
         side: Side,
         order_type: OrderType,
         tif: Tif,
+        idempotency_key: Optional[str] = None,
+        ttl_ms: Optional[int] = None,
+        peg_offset_ticks: Optional[int] = None,
+        position_side: Optional[PositionSide] = None,
+        reprice_passive: bool = False,
+        strategy: Optional[str] = None,
+        tags: Optional[List[str]] = None,
+        force: bool = False,
     ) -> Optional[Order]:
-        return self.ctx.add_order(self.symbol, price, quantity, side, order_type, tif)
-
-    def cancel(self, order_id: int):
-        self.ctx.cancel(self.symbol, order_id)
+        return self.ctx.add_order(
+            self.symbol,
+            price,
+            quantity,
+            side,
+            order_type,
+            tif,
+            idempotency_key,
+            ttl_ms,
+            peg_offset_ticks,
+            position_side,
+            reprice_passive,
+            strategy,
+            tags,
+            force,
+        )
+
+    def replace(self, order_id: int, price: float, quantity: Optional[float] = None) -> bool:
+        """改价，数量缺省为未成交部分"""
+        return self.ctx.replace(order_id, price, quantity)
+
+    def cancel(self, order_id: int, idempotency_key: Optional[str] = None):
+        self.ctx.cancel(self.symbol, order_id, idempotency_key)
+
+    def quote(
+        self,
+        bid: Optional[Tuple[float, float]] = None,
+        ask: Optional[Tuple[float, float]] = None,
+    ):
+        """bid、ask 为 (price, quantity)，None 表示撤掉这一侧"""
+        self.ctx.quote(self.symbol, bid, ask)
 
 
 class DepthSubscription(Tradable):
@@ -138,6 +175,8 @@ class BarSubscription(Tradable):
     def __init__(self, subscription: Subscription, ctx: ContextBase):
         super().__init__(subscription, ctx)
         self.on_data = lambda x: None
+        # 网关按时钟推送的收线，交易所 K 线断流时也会触发
+        self.on_close = lambda x: None
         self.data: Kline = None
 
     @property
@@ -216,6 +255,11 @@ class BarSubscription(Tradable):
         self.data = data
         self.on_data(data)
 
+    def on_bar_close(self, data: BarClose):
+        if data.kline:
+            self.data = data.kline
+        self.on_close(data)
+
 
 class SmartOrder:
     """"""
@@ -5,6 +5,251 @@ import builtins
 import typing
 from enum import Enum
 
+class BacktestReport:
+    r"""
+    回测结果
+    """
+    @property
+    def start_time(self) -> builtins.int: ...
+    @property
+    def end_time(self) -> builtins.int: ...
+    @property
+    def events(self) -> builtins.int: ...
+    @property
+    def trades(self) -> builtins.int: ...
+    @property
+    def volume(self) -> builtins.float: ...
+    @property
+    def fees(self) -> builtins.float: ...
+    @property
+    def realized(self) -> builtins.float: ...
+    @property
+    def unrealized(self) -> builtins.float: ...
+    @property
+    def pnl(self) -> builtins.float:
+        r"""
+        扣除手续费后的总盈亏
+        """
+    @property
+    def max_drawdown(self) -> builtins.float:
+        r"""
+        权益曲线的最大回撤（金额）
+        """
+    @property
+    def sharpe(self) -> builtins.float:
+        r"""
+        按日度盈亏计算的年化夏普
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class BacktestSession:
+    @property
+    def clock(self) -> builtins.int:
+        r"""
+        回放时钟，毫秒，为最近一条行情的时间
+        """
+    @property
+    def id(self) -> builtins.int: ...
+    @property
+    def name(self) -> builtins.str: ...
+    @property
+    def is_login(self) -> builtins.bool: ...
+    @property
+    def trading(self) -> builtins.bool: ...
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        当前模拟时间（毫秒）
+        """
+    @property
+    def finished(self) -> builtins.bool:
+        r"""
+        所有数据已回放完毕
+        """
+    @property
+    def report(self) -> BacktestReport: ...
+    def __new__(cls, data_dir:builtins.str, session_id:builtins.int, name:builtins.str, trading:builtins.bool) -> BacktestSession: ...
+    def set_fee(self, maker_fee:builtins.float, taker_fee:builtins.float) -> None:
+        r"""
+        设置挂单、吃单费率，须在下单前调用
+        """
+    def set_fill_model(self, model:builtins.str, participation:builtins.float, slippage:typing.Sequence[tuple[builtins.float, builtins.float]], seed:builtins.int) -> None:
+        r"""
+        设置成交模型，须在下单前调用
+        
+        - model: `touch` 触价即成交，`queue` 按排队位置成交
+        - participation: 每根 K 线最多成交其成交量的比例，0 为不限制
+        - slippage: 吃单滑点曲线 [(下单数量, 滑点 bps)]，按数量线性插值
+        - seed: 随机种子，相同种子的结果可复现
+        """
+    def set_products(self, path:builtins.str) -> None:
+        r"""
+        从 exchangeInfo 响应或产品数组加载交易规则，须在 connect 前调用；
+        不设置时使用最小价格/数量步长为 1e-8 的默认规则
+        """
+    def set_window(self, start:builtins.int, end:builtins.int) -> None:
+        r"""
+        只回放 [start, end) 毫秒时间内的行情，须在 subscribe 前调用
+        """
+    def connect(self) -> None: ...
+    def subscribe(self, symbol:builtins.str, stream:builtins.str, tag:typing.Optional[builtins.str]=None) -> Subscription:
+        r"""
+        `tag` 不为空时回放的每条数据都带上这个标签
+        """
+    def unsubscribe(self, streams:typing.Sequence[builtins.str]) -> None:
+        r"""
+        与实盘接口一致，停止回放这些 `symbol@stream` 形式的流
+        """
+    def derive(self, symbol:builtins.str, stream:builtins.str, name:builtins.str, expr:builtins.str) -> None:
+        r"""
+        与网关相同，回放每条行情时计算 `expr`，结果在行情事件之后推送
+        """
+    def add_order(self, symbol:builtins.str, price:builtins.float, quantity:builtins.float, side:Side, order_type:OrderType, tif:Tif, idempotency_key:typing.Optional[builtins.str]=None, ttl_ms:typing.Optional[builtins.int]=None, peg_offset_ticks:typing.Optional[builtins.int]=None, position_side:typing.Optional[PositionSide]=None, reprice_passive:typing.Optional[builtins.bool]=None, strategy:typing.Optional[builtins.str]=None, tags:typing.Optional[typing.Sequence[builtins.str]]=None, force:typing.Optional[builtins.bool]=None) -> typing.Optional[Order]:
+        r"""
+        回测不会重发请求，`idempotency_key` 仅为与实盘接口一致；`ttl_ms` 按回放时钟到期撤单
+        回测不模拟钉住订单改价，`peg_offset_ticks` 被忽略，按 `price` 撮合
+        回测按单向持仓统计，`position_side` 被忽略
+        回测按交易所的规则处理只做 maker 的订单，`reprice_passive` 被忽略
+        回测不做归因，`strategy`、`tags` 只记录在订单上
+        回测不做价格与名义价值的合理性校验，`force` 被忽略
+        """
+    def add_orders(self, symbols:typing.Sequence[builtins.str], sides:typing.Sequence[Side], prices:typing.Sequence[builtins.float], quantities:typing.Sequence[builtins.float], order_type:OrderType, tif:Tif, strategy:typing.Optional[builtins.str]=None, tags:typing.Optional[typing.Sequence[builtins.str]]=None) -> builtins.list[Order]:
+        r"""
+        与实盘相同的批量下单，回测按顺序逐笔撮合
+        """
+    def quote(self, symbol:builtins.str, bid:typing.Optional[tuple[builtins.float, builtins.float]]=None, ask:typing.Optional[tuple[builtins.float, builtins.float]]=None) -> None:
+        r"""
+        双边报价，撮合是同步的，直接撤掉变化的一侧再挂新单
+        """
+    def replace(self, order_id:builtins.int, price:builtins.float, quantity:typing.Optional[builtins.float]=None) -> builtins.bool:
+        r"""
+        撮合是同步的，直接撤掉原订单并以同一个订单号挂出新单，中间的撤单不推送给策略
+        """
+    def cancel(self, symbol:builtins.str, order_id:builtins.int, idempotency_key:typing.Optional[builtins.str]=None) -> None: ...
+    def poll_events(self, max_n:typing.Optional[builtins.int]=None, timeout_ms:typing.Optional[builtins.int]=None) -> builtins.list[typing.Any]:
+        r"""
+        与实盘接口一致，一次取出最多 `max_n` 个事件；回放不需要等待，`timeout_ms` 被忽略
+        """
+    def poll_quotes(self, buffer:typing.Any, max_n:typing.Optional[builtins.int]=None, timeout_ms:typing.Optional[builtins.int]=None) -> tuple[builtins.list[builtins.str], builtins.list[typing.Any]]:
+        r"""
+        与实盘接口一致，深度的最优一档写入 `buffer`，其余的事件返回
+        """
+    def process(self) -> typing.Optional[typing.Any]: ...
+
+class BarClose:
+    r"""
+    网关按时钟推送的收线事件，交易所 K 线断流时也会按时到达
+    """
+    @property
+    def time(self) -> builtins.int: ...
+    @property
+    def datetime(self) -> builtins.str: ...
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def stream(self) -> builtins.str: ...
+    @property
+    def interval(self) -> builtins.str: ...
+    @property
+    def kline(self) -> typing.Optional[Kline]: ...
+    @property
+    def tag(self) -> typing.Optional[builtins.str]:
+        r"""
+        订阅时附带的标签
+        """
+    @property
+    def offset(self) -> typing.Optional[builtins.int]:
+        r"""
+        网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据；回测中为空
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Basis:
+    r"""
+    合约网关计算的永续合约与现货的基差，订阅 `{symbol}@basis` 后推送，或由 `Session.get_basis` 查询
+    """
+    @property
+    def time(self) -> builtins.int: ...
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def stream(self) -> builtins.str: ...
+    @property
+    def spot(self) -> builtins.float:
+        r"""
+        现货买一卖一的中间价
+        """
+    @property
+    def perp(self) -> builtins.float:
+        r"""
+        永续合约买一卖一的中间价
+        """
+    @property
+    def basis(self) -> builtins.float:
+        r"""
+        `perp - spot`
+        """
+    @property
+    def basis_rate(self) -> builtins.float:
+        r"""
+        基差相对现货价格的比例
+        """
+    @property
+    def funding_rate(self) -> typing.Optional[builtins.float]:
+        r"""
+        当期资金费率，还没有收到标记价格时为空
+        """
+    @property
+    def next_funding_time(self) -> builtins.int:
+        r"""
+        下次收取资金费的时间
+        """
+    @property
+    def annualized_funding(self) -> typing.Optional[builtins.float]:
+        r"""
+        按当期资金费率年化的收益，持有现货、做空永续时为正
+        """
+    @property
+    def tag(self) -> typing.Optional[builtins.str]:
+        r"""
+        订阅时附带的标签
+        """
+    @property
+    def offset(self) -> typing.Optional[builtins.int]:
+        r"""
+        网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Delivery:
+    r"""
+    交割合约的交割提醒与结算，持有该合约时推送；`settled` 为 True 时网关已把持仓置零
+    """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def delivery_time(self) -> builtins.int:
+        r"""
+        交割时间（毫秒）
+        """
+    @property
+    def remaining_ms(self) -> builtins.int:
+        r"""
+        距交割的毫秒数，结算时为 0
+        """
+    @property
+    def position(self) -> builtins.float:
+        r"""
+        净持仓，结算时为结算前的持仓
+        """
+    @property
+    def settled(self) -> builtins.bool: ...
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class Depth:
     @property
     def time(self) -> builtins.int: ...
@@ -18,11 +263,69 @@ class Depth:
     def bid_level(self) -> builtins.int: ...
     @property
     def ask_level(self) -> builtins.int: ...
+    @property
+    def tag(self) -> typing.Optional[builtins.str]:
+        r"""
+        订阅时附带的标签
+        """
+    @property
+    def offset(self) -> typing.Optional[builtins.int]:
+        r"""
+        网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据；回测中为空
+        """
     def bid_prc(self, level:builtins.int) -> builtins.float: ...
     def bid_vol(self, level:builtins.int) -> builtins.float: ...
     def ask_prc(self, level:builtins.int) -> builtins.float: ...
     def ask_vol(self, level:builtins.int) -> builtins.float: ...
 
+class DepthSnapshot:
+    r"""
+    `Session.get_depth_snapshot` 查询到的交易所深度快照，用于初始化本地订单簿
+    """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        网关从交易所取得快照的时间，返回缓存时早于查询时间
+        """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def last_update_id(self) -> builtins.int:
+        r"""
+        本地订单簿从这个编号之后的增量开始更新
+        """
+    @property
+    def bids(self) -> builtins.list[tuple[builtins.float, builtins.float]]:
+        r"""
+        买盘的 (价格, 数量)，价格从高到低
+        """
+    @property
+    def asks(self) -> builtins.list[tuple[builtins.float, builtins.float]]:
+        r"""
+        卖盘的 (价格, 数量)，价格从低到高
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Derived:
+    r"""
+    网关按表达式计算的派生流结果
+    """
+    @property
+    def time(self) -> builtins.int: ...
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def stream(self) -> builtins.str: ...
+    @property
+    def values(self) -> builtins.dict[builtins.str, builtins.float]:
+        r"""
+        表达式中各命名语句的结果
+        """
+    def get(self, name:builtins.str) -> typing.Optional[builtins.float]: ...
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class Event:
     @property
     def event_type(self) -> EventType: ...
@@ -31,6 +334,83 @@ class Event:
     def __repr__(self) -> builtins.str: ...
     def __str__(self) -> builtins.str: ...
 
+class Fill:
+    r"""
+    订单的一笔成交，登录时开启 `fills` 后在订单回报之外逐笔推送
+    """
+    @property
+    def id(self) -> builtins.int:
+        r"""
+        订单号，与 `Order.id` 相同
+        """
+    @property
+    def order_id(self) -> builtins.int:
+        r"""
+        交易所的订单号
+        """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def side(self) -> Side: ...
+    @property
+    def trade_id(self) -> builtins.int: ...
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        成交时间
+        """
+    @property
+    def price(self) -> builtins.float: ...
+    @property
+    def quantity(self) -> builtins.float: ...
+    @property
+    def maker(self) -> builtins.bool:
+        r"""
+        作为挂单成交
+        """
+    @property
+    def commission(self) -> builtins.float: ...
+    @property
+    def commission_asset(self) -> builtins.str: ...
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Greeks:
+    r"""
+    期权的标记价格与希腊值，由 `{symbol}@greeks` 或 `{asset}@mark` 推送
+    """
+    @property
+    def time(self) -> builtins.int: ...
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def stream(self) -> builtins.str: ...
+    @property
+    def mark_price(self) -> builtins.float: ...
+    @property
+    def mark_iv(self) -> builtins.float:
+        r"""
+        隐含波动率均为小数
+        """
+    @property
+    def bid_iv(self) -> builtins.float: ...
+    @property
+    def ask_iv(self) -> builtins.float: ...
+    @property
+    def delta(self) -> builtins.float: ...
+    @property
+    def gamma(self) -> builtins.float: ...
+    @property
+    def theta(self) -> builtins.float: ...
+    @property
+    def vega(self) -> builtins.float: ...
+    @property
+    def tag(self) -> typing.Optional[builtins.str]: ...
+    @property
+    def offset(self) -> typing.Optional[builtins.int]: ...
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class Kline:
     @property
     def time(self) -> builtins.int: ...
@@ -70,14 +450,78 @@ class Kline:
     def buy_volume(self) -> builtins.float: ...
     @property
     def buy_amount(self) -> builtins.float: ...
+    @property
+    def backfill(self) -> builtins.bool: ...
+    @property
+    def tag(self) -> typing.Optional[builtins.str]:
+        r"""
+        订阅时附带的标签
+        """
+    @property
+    def offset(self) -> typing.Optional[builtins.int]:
+        r"""
+        网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据；回测中为空
+        """
     def __str__(self) -> builtins.str: ...
     def __repr__(self) -> builtins.str: ...
 
-class Order:
+class MyTrade:
+    r"""
+    账户的一笔历史成交
+    """
     @property
-    def time(self) -> builtins.int: ...
+    def symbol(self) -> builtins.str: ...
     @property
-    def datetime(self) -> builtins.str: ...
+    def trade_id(self) -> builtins.int: ...
+    @property
+    def order_id(self) -> builtins.int:
+        r"""
+        交易所的订单号
+        """
+    @property
+    def side(self) -> Side: ...
+    @property
+    def price(self) -> builtins.float: ...
+    @property
+    def quantity(self) -> builtins.float: ...
+    @property
+    def quote_quantity(self) -> builtins.float:
+        r"""
+        成交金额
+        """
+    @property
+    def commission(self) -> builtins.float: ...
+    @property
+    def commission_asset(self) -> builtins.str: ...
+    @property
+    def maker(self) -> builtins.bool:
+        r"""
+        作为挂单成交
+        """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        成交时间
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class MyTrades:
+    r"""
+    `Session.get_my_trades` 查询到的一个交易对 [start, end) 内的成交，按时间排序
+    """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def start(self) -> builtins.int: ...
+    @property
+    def end(self) -> builtins.int: ...
+    @property
+    def trades(self) -> builtins.list[MyTrade]: ...
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Order:
     @property
     def symbol(self) -> builtins.str: ...
     @property
@@ -107,12 +551,114 @@ class Order:
     @property
     def making(self) -> typing.Optional[builtins.bool]: ...
     @property
+    def position_side(self) -> typing.Optional[PositionSide]: ...
+    @property
+    def strategy(self) -> typing.Optional[builtins.str]: ...
+    @property
+    def tags(self) -> builtins.list[builtins.str]: ...
+    @property
     def is_active(self) -> builtins.bool: ...
     def __str__(self) -> builtins.str: ...
     def __repr__(self) -> builtins.str: ...
 
+class OrderCap:
+    r"""
+    订单会超过交易所的挂单数上限时网关排队下单，排队与发出时推送给下单的会话
+    """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        推送的时间（毫秒）
+        """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def order_id(self) -> builtins.int: ...
+    @property
+    def filter(self) -> builtins.str:
+        r"""
+        挡住订单的过滤器，如 `MAX_NUM_ORDERS`、`MAX_NUM_ALGO_ORDERS`
+        """
+    @property
+    def limit(self) -> builtins.int:
+        r"""
+        过滤器的上限
+        """
+    @property
+    def open(self) -> builtins.int:
+        r"""
+        网关记录的该交易对已发出、尚未结束的订单数
+        """
+    @property
+    def queued(self) -> builtins.bool:
+        r"""
+        为 True 时订单开始排队，为 False 时已经发出
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Params:
+    r"""
+    会话的策略参数，由 `Session.get_params` 查询，运维修改参数时推送
+    """
+    @property
+    def session_id(self) -> builtins.int: ...
+    @property
+    def params(self) -> typing.Any:
+        r"""
+        全部参数
+        """
+    @property
+    def changed(self) -> builtins.list[builtins.str]:
+        r"""
+        这次推送中变化了的键，查询的结果为空
+        """
+    def get(self, key:builtins.str) -> typing.Optional[typing.Any]: ...
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class Position:
-    ...
+    r"""
+    `side` 等字段只有合约网关推送，现货为 None
+    """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def net(self) -> builtins.float: ...
+    @property
+    def side(self) -> typing.Optional[PositionSide]:
+        r"""
+        双向持仓时为 LONG 或 SHORT，`net` 是这一腿的持仓
+        """
+    @property
+    def avg_price(self) -> typing.Optional[builtins.float]:
+        r"""
+        网关按本会话的成交计算的开仓均价，已计入手续费，成本未知时为 None
+        """
+    @property
+    def cost_basis(self) -> typing.Optional[builtins.float]:
+        r"""
+        多头为买入花费、空头为卖出收到的计价资产
+        """
+    @property
+    def entry_price(self) -> typing.Optional[builtins.float]:
+        r"""
+        交易所账户级别的入场价，多个会话交易同一个品种时与 `avg_price` 不同
+        """
+    @property
+    def unrealized_pnl(self) -> typing.Optional[builtins.float]: ...
+    @property
+    def margin_type(self) -> typing.Optional[builtins.str]:
+        r"""
+        CROSSED 或 ISOLATED
+        """
+    @property
+    def leverage(self) -> typing.Optional[builtins.int]: ...
+    def unrealized(self, price:builtins.float) -> typing.Optional[builtins.float]:
+        r"""
+        按 `avg_price` 与给定价格估算的未实现盈亏
+        """
+    def __repr__(self) -> builtins.str: ...
 
 class PremiumIndex:
     @property
@@ -138,6 +684,51 @@ class PremiumIndex:
     def __repr__(self) -> builtins.str: ...
     def __str__(self) -> builtins.str: ...
 
+class ResearchSession:
+    r"""
+    后台线程维护订阅并缓存最新的行情与持仓，适合在 notebook 中同步地查看；不能下单
+    """
+    @property
+    def is_connected(self) -> builtins.bool:
+        r"""
+        后台连接是否已登录，断线重连期间为 False
+        """
+    @property
+    def symbols(self) -> builtins.list[builtins.str]:
+        r"""
+        网关的所有合约
+        """
+    def __new__(cls, addr:builtins.str, session_id:builtins.int, name:typing.Optional[builtins.str]=None, tenant:typing.Optional[builtins.str]=None, token:typing.Optional[builtins.str]=None, max_bars:typing.Optional[builtins.int]=None) -> ResearchSession:
+        r"""
+        连接并登录后返回，失败时抛出异常；`max_bars` 为每条 K 线流保留的根数，缺省 1000
+        """
+    def subscribe(self, symbol:builtins.str, stream:builtins.str) -> None:
+        r"""
+        订阅 `symbol@stream`，如 `depth`、`kline:1m`；数据由后台线程缓存
+        """
+    def last_depth(self, symbol:builtins.str, timeout_ms:typing.Optional[builtins.int]=None) -> typing.Optional[Depth]:
+        r"""
+        最新的深度；还没有收到时最多等待 `timeout_ms`（缺省 0）毫秒，仍没有则为 None
+        """
+    def klines(self, symbol:builtins.str, interval:typing.Optional[builtins.str]=None) -> builtins.list[Kline]:
+        r"""
+        `symbol@kline:interval` 缓存的 K 线，`interval` 缺省为 1m，最后一根可能未完结
+        """
+    def ohlcv_df(self, symbol:builtins.str, interval:typing.Optional[builtins.str]=None) -> typing.Any:
+        r"""
+        与 `klines` 相同，转为以起始时间为索引的 pandas DataFrame，
+        列为 open、high、low、close、volume、amount、is_closed；需要安装 pandas
+        """
+    def position(self, symbol:builtins.str) -> typing.Optional[Position]:
+        r"""
+        网关上本会话的持仓，没有时为 None
+        """
+    def positions(self) -> builtins.list[Position]: ...
+    def close(self) -> None:
+        r"""
+        断开连接并结束后台线程，之后只能读取已缓存的数据
+        """
+
 class Rest:
     def __new__(cls, base_uri:builtins.str, apikey:builtins.str, pem:builtins.str, recvwindow:builtins.int) -> Rest: ...
     def sign(self, data:builtins.str) -> builtins.str: ...
@@ -148,6 +739,67 @@ class Rest:
     def patch(self, path:builtins.str, params:dict, authenticate:builtins.bool) -> builtins.str: ...
     def get_premium_index(self) -> builtins.list[PremiumIndex]: ...
 
+class Roll:
+    r"""
+    季度合约交割前的移仓提醒，持有 `contract` 或在它上面有挂单时推送；用 `Session.roll` 移仓
+    """
+    @property
+    def alias(self) -> builtins.str:
+        r"""
+        连续合约别名，如 `btcusdt_quarter`
+        """
+    @property
+    def contract(self) -> builtins.str:
+        r"""
+        别名当前对应的合约
+        """
+    @property
+    def next(self) -> typing.Optional[builtins.str]:
+        r"""
+        到期后接替的合约
+        """
+    @property
+    def delivery_time(self) -> builtins.int:
+        r"""
+        `contract` 的交割时间（毫秒）
+        """
+    @property
+    def remaining_ms(self) -> builtins.int:
+        r"""
+        距交割的毫秒数
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class RollResult:
+    r"""
+    `Session.roll` 的结果：撤销的挂单与网关发出的订单，订单回报照常推送
+    """
+    @property
+    def alias(self) -> builtins.str: ...
+    @property
+    def contract(self) -> builtins.str:
+        r"""
+        移出的合约
+        """
+    @property
+    def next(self) -> builtins.str:
+        r"""
+        移入的合约
+        """
+    @property
+    def canceled(self) -> builtins.list[builtins.int]:
+        r"""
+        在 `contract` 上撤销的挂单编号
+        """
+    @property
+    def orders(self) -> builtins.list[builtins.int]:
+        r"""
+        网关发出的订单编号，包括在 `next` 上重挂的订单与移持仓的市价单
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class Session:
     @property
     def id(self) -> builtins.int: ...
@@ -156,14 +808,169 @@ class Session:
     @property
     def is_login(self) -> builtins.bool: ...
     @property
+    def rejected_streams(self) -> builtins.dict[builtins.str, builtins.str]:
+        r"""
+        被网关拒绝的订阅及原因，之后订阅成功的流会被移除
+        """
+    @property
     def trading(self) -> builtins.bool: ...
     def __new__(cls, addr:builtins.str, session_id:builtins.int, name:builtins.str, trading:builtins.bool) -> Session: ...
+    def set_cancel_on_disconnect(self, enable:builtins.bool) -> None:
+        r"""
+        开启后连接断开时网关会撤销本会话所有未完成的订单，需要在 connect 之前设置
+        """
+    def set_fills(self, enable:builtins.bool) -> None:
+        r"""
+        开启后网关在订单回报之外逐笔推送成交，以 `EventType.Fill` 返回，需要在 connect 之前设置
+        """
+    def set_heartbeat(self, interval_ms:typing.Optional[builtins.int]=None) -> None:
+        r"""
+        声明心跳间隔（毫秒），登录后 `process` 至少每隔这个时间发送一次心跳；网关开启心跳巡检时，
+        策略卡住超时后会撤销本会话的订单，需要在 connect 之前设置
+        """
+    def set_tenant(self, tenant:builtins.str, token:typing.Optional[builtins.str]=None) -> None:
+        r"""
+        网关配置了租户时登录需要带上会话所属的租户，租户配置了 token 时还需要 token，需要在 connect 之前设置
+        """
+    def set_wire_format(self, format:builtins.str) -> None:
+        r"""
+        登录后的编码：`json`、`msgpack` 或 `cbor`，需要在 connect 之前设置；
+        网关未开启该编码时仍使用 JSON
+        """
     def connect(self) -> None: ...
-    def subscribe(self, symbol:builtins.str, stream:builtins.str) -> Subscription: ...
-    def add_order(self, symbol:builtins.str, price:builtins.float, quantity:builtins.float, side:Side, order_type:OrderType, tif:Tif) -> typing.Optional[Order]: ...
-    def cancel(self, symbol:builtins.str, order_id:builtins.int) -> None: ...
+    def subscribe(self, symbol:builtins.str, stream:builtins.str, tag:typing.Optional[builtins.str]=None, bucket:typing.Optional[builtins.float]=None) -> Subscription:
+        r"""
+        `tag` 不为空时网关在该流的每条数据上带回这个标签；
+        深度流可以指定 `bucket`，网关按该宽度的价格带合并档位后推送
+        """
+    def unsubscribe(self, streams:typing.Sequence[builtins.str]) -> None:
+        r"""
+        退订 `symbol@stream` 形式的流，网关在没有其他策略订阅时向交易所退订
+        """
+    def derive(self, symbol:builtins.str, stream:builtins.str, name:builtins.str, expr:builtins.str) -> None:
+        r"""
+        在网关为已订阅的 `symbol@stream` 注册派生流，每个 tick 计算 `expr`，
+        结果以 `EventType.Derived` 推送，流名称为 `symbol@derived:name`
+        """
+    def resume(self, symbol:builtins.str, stream:builtins.str, offset:builtins.int) -> None:
+        r"""
+        断线重连并重新订阅后，请求网关补发 `symbol@stream` 在 `offset` 之后缓存的数据，
+        补发完后网关回复；`offset` 已超出缓存窗口时回复错误，需要重新取快照
+        """
+    def get_option_chain(self, underlying:builtins.str) -> None:
+        r"""
+        请求标的（如 btcusdt）下的期权合约，返回后与其他合约一样可以 `subscribe(symbol, "greeks")`；
+        网关需要开启期权行情
+        """
+    def get_volatility(self, symbol:builtins.str, window:builtins.str) -> None:
+        r"""
+        查询网关按 `symbol@kline:window` 估计的波动率，需要已订阅该 K 线流且网关开启了波动率估计；
+        结果以 `EventType.Volatility` 返回
+        """
+    def get_basis(self, symbol:builtins.str) -> None:
+        r"""
+        查询合约网关计算的基差，需要已订阅 `symbol@basis` 且网关开启了 `basis`；结果以 `EventType.Basis` 返回
+        """
+    def get_depth_snapshot(self, symbol:builtins.str, limit:builtins.int) -> None:
+        r"""
+        查询交易所前 `limit` 档的深度快照，结果以 `EventType.DepthSnapshot` 返回；由网关代为查询并短暂缓存，
+        订阅深度的增量后用快照初始化本地订单簿
+        """
+    def get_my_trades(self, symbol:builtins.str, start:builtins.int, end:builtins.int) -> None:
+        r"""
+        查询账户一个交易对 [start, end)（毫秒）内的历史成交，结果以 `EventType.MyTrades` 返回；
+        网关需要开启 `my_trades`，本地没有的时间段由网关从交易所补齐
+        """
+    def get_params(self) -> None:
+        r"""
+        读取本会话在网关保存的策略参数，结果以 `EventType.Params` 返回；之后运维修改参数时同样推送
+        """
+    def add_order(self, symbol:builtins.str, price:builtins.float, quantity:builtins.float, side:Side, order_type:OrderType, tif:Tif, idempotency_key:typing.Optional[builtins.str]=None, ttl_ms:typing.Optional[builtins.int]=None, peg_offset_ticks:typing.Optional[builtins.int]=None, position_side:typing.Optional[PositionSide]=None, reprice_passive:typing.Optional[builtins.bool]=None, strategy:typing.Optional[builtins.str]=None, tags:typing.Optional[typing.Sequence[builtins.str]]=None, force:typing.Optional[builtins.bool]=None) -> typing.Optional[Order]:
+        r"""
+        `idempotency_key` 在重发同一笔订单时保持不变，网关在时间窗口内只转发一次
+        `ttl_ms` 到期仍未完成的订单由网关撤销，状态为 EXPIRED_BY_GATEWAY
+        `peg_offset_ticks` 不为空时由网关把订单钉在最优买卖价外若干个 tick，`price` 只在还没有盘口时使用
+        `position_side` 只用于双向持仓模式的合约账户，开平多头为 LONG，开平空头为 SHORT
+        `reprice_passive` 为 True 时，只做 maker 的订单会立即成交则由网关改为不会成交的最优价格，而不是拒绝
+        `strategy`、`tags` 随订单保存，网关按它们统计成交与盈亏，用 `get_attribution` 请求查询
+        `force` 为 True 时跳过网关对价格偏离与名义价值上限的校验
+        """
+    def add_orders(self, symbols:typing.Sequence[builtins.str], sides:typing.Sequence[Side], prices:typing.Sequence[builtins.float], quantities:typing.Sequence[builtins.float], order_type:OrderType, tif:Tif, strategy:typing.Optional[builtins.str]=None, tags:typing.Optional[typing.Sequence[builtins.str]]=None) -> builtins.list[Order]:
+        r"""
+        批量下单，`symbols`、`sides`、`prices`、`quantities` 按位置对应，可以是列表或 numpy 数组
+        所有订单在一个 `orders` 请求中发给网关，编码与发送时释放 GIL；返回的订单与输入一一对应
+        一批最多 255 笔，订单号与 `add_order` 共用
+        """
+    def quote(self, symbol:builtins.str, bid:typing.Optional[tuple[builtins.float, builtins.float]]=None, ask:typing.Optional[tuple[builtins.float, builtins.float]]=None) -> None:
+        r"""
+        双边报价，`bid`、`ask` 为 (price, quantity)，None 表示撤掉这一侧
+        网关只对变化的一侧撤单重挂，新挂出的报价通过订单事件推送
+        """
+    def roll(self, alias:builtins.str, spread:typing.Optional[builtins.float]=None) -> None:
+        r"""
+        把挂单与持仓从连续合约别名（如 `btcusdt_quarter`）当前对应的合约移到下一个合约，结果以
+        `EventType.RollResult` 返回；`spread` 为挂单平移的价差，缺省按两个合约的中间价计算
+        """
+    def replace(self, order_id:builtins.int, price:builtins.float, quantity:typing.Optional[builtins.float]=None) -> builtins.bool:
+        r"""
+        修改挂单的价格，`quantity` 缺省为未成交数量；网关在上一次改单完成前只保留最新一笔
+        """
+    def cancel(self, symbol:builtins.str, order_id:builtins.int, idempotency_key:typing.Optional[builtins.str]=None) -> None: ...
+    def poll_events(self, max_n:typing.Optional[builtins.int]=None, timeout_ms:typing.Optional[builtins.int]=None) -> builtins.list[typing.Any]:
+        r"""
+        一次取出最多 `max_n`（缺省 256）个事件，一个也没有时最多等待 `timeout_ms`（缺省 0）毫秒
+        读取与解码时释放 GIL，取完后一次生成所有事件，行情密集时用来代替逐个调用 `process`
+        """
+    def poll_quotes(self, buffer:typing.Any, max_n:typing.Optional[builtins.int]=None, timeout_ms:typing.Optional[builtins.int]=None) -> tuple[builtins.list[builtins.str], builtins.list[typing.Any]]:
+        r"""
+        与 `poll_events` 相同，但深度的最优一档按 [time, bid, bid_qty, ask, ask_qty] 逐行写入 `buffer`，
+        不再生成事件；`buffer` 为 C 连续、形状为 (n, 5) 的 float64 numpy 数组，由调用方预先分配并重复使用
+        返回写入的各行的交易对与其余的事件；数组写满后的深度、买卖盘有一侧为空的深度照常作为事件返回
+        """
     def process(self) -> typing.Optional[typing.Any]: ...
 
+class Strategy:
+    r"""
+    策略基类，继承后重写需要的回调，再用 `run` 驱动
+    """
+    @property
+    def timer_ms(self) -> typing.Optional[builtins.int]:
+        r"""
+        `on_timer` 的间隔，毫秒，为空时不调用
+        """
+    @property
+    def running(self) -> builtins.bool: ...
+    @timer_ms.setter
+    def timer_ms(self, value: typing.Optional[builtins.int]) -> None:
+        r"""
+        `on_timer` 的间隔，毫秒，为空时不调用
+        """
+    def __new__(cls, *args, **kwargs) -> Strategy:
+        r"""
+        子类的 `__init__` 可以接受任意参数
+        """
+    def on_depth(self, depth:typing.Any) -> None: ...
+    def on_kline(self, kline:typing.Any) -> None: ...
+    def on_order(self, order:typing.Any) -> None: ...
+    def on_fill(self, fill:typing.Any) -> None: ...
+    def on_timer(self, now:builtins.int) -> None:
+        r"""
+        `now` 为毫秒时间戳
+        """
+    def on_event(self, event:typing.Any) -> None:
+        r"""
+        没有单独回调的事件
+        """
+    def stop(self) -> None:
+        r"""
+        处理完当前这批事件后停止 `run`
+        """
+    def run(self, session:typing.Any, max_n:typing.Optional[builtins.int]=None, timeout_ms:typing.Optional[builtins.int]=None) -> None:
+        r"""
+        用 `session`（`Session` 或 `BacktestSession`，须已连接）驱动策略，直到 `stop` 或回测结束
+        `max_n`、`timeout_ms` 传给 `poll_events`，实盘的 `timeout_ms` 缺省为 10
+        """
+
 class Subscription:
     @property
     def symbol(self) -> builtins.str: ...
@@ -182,8 +989,50 @@ class Subscription:
     @property
     def min_notional(self) -> builtins.float: ...
     @property
-    def net(self) -> builtins.float: ...
+    def strike(self) -> typing.Optional[builtins.float]:
+        r"""
+        期权的行权价，不是期权时为空
+        """
+    @property
+    def expiry(self) -> typing.Optional[builtins.int]:
+        r"""
+        期权的到期时间（毫秒）
+        """
+    @property
+    def option_type(self) -> typing.Optional[builtins.str]:
+        r"""
+        期权类型，CALL 或 PUT
+        """
+    @property
+    def underlying(self) -> typing.Optional[builtins.str]:
+        r"""
+        期权的标的
+        """
+    @property
+    def net(self) -> builtins.float:
+        r"""
+        净持仓，双向持仓时为多空两腿之和
+        """
+    @property
+    def position(self) -> typing.Optional[Position]:
+        r"""
+        最近一次推送的持仓，合约带入场价、杠杆等字段；双向持仓时见 `long`、`short`
+        """
+    @property
+    def long(self) -> typing.Optional[Position]:
+        r"""
+        双向持仓的多头一腿
+        """
+    @property
+    def short(self) -> typing.Optional[Position]:
+        r"""
+        双向持仓的空头一腿，`net` 为负
+        """
     def order_support(self, order_type:OrderType) -> builtins.bool: ...
+    def max_notional(self, leverage:typing.Optional[builtins.int]=None) -> typing.Optional[builtins.float]:
+        r"""
+        杠杆 `leverage` 下持仓名义价值的上限，缺省使用持仓推送的杠杆；没有杠杆分层时为空
+        """
     def floor_to_lot_size(self, vol:builtins.float) -> builtins.float: ...
     def round_price(self, price:builtins.float) -> builtins.float: ...
     def tick_up(self, price:builtins.float, n:builtins.int) -> builtins.float: ...
@@ -191,6 +1040,86 @@ class Subscription:
     def add_phase(self, hour:builtins.int, minute:builtins.int, second:builtins.int, phase:Phase) -> None: ...
     def determine(self, mills:builtins.int) -> Phase: ...
 
+class SymbolChanged:
+    r"""
+    用连续合约别名订阅的流换到了新的合约，之后 `stream` 的数据来自 `symbol`；K 线等按合约累积的状态需要重新开始
+    """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        网关切换的时间（毫秒）
+        """
+    @property
+    def alias(self) -> builtins.str:
+        r"""
+        连续合约别名，如 `btcusdt_quarter`
+        """
+    @property
+    def stream(self) -> builtins.str:
+        r"""
+        订阅时的流，如 `btcusdt_quarter@kline:1m`
+        """
+    @property
+    def previous(self) -> builtins.str:
+        r"""
+        切换前的合约
+        """
+    @property
+    def symbol(self) -> builtins.str:
+        r"""
+        切换后的合约
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class TradeFlow:
+    r"""
+    网关按归集成交统计的成交流向，由 `{symbol}@cvd:{window}` 推送
+    """
+    @property
+    def time(self) -> builtins.int: ...
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def stream(self) -> builtins.str: ...
+    @property
+    def cvd(self) -> builtins.float:
+        r"""
+        网关开始统计以来主动买入量减主动卖出量的累计值
+        """
+    @property
+    def buy_volume(self) -> builtins.float:
+        r"""
+        窗口内的主动买入量
+        """
+    @property
+    def sell_volume(self) -> builtins.float:
+        r"""
+        窗口内的主动卖出量
+        """
+    @property
+    def delta(self) -> builtins.float:
+        r"""
+        窗口内的主动买卖差
+        """
+    @property
+    def imbalance(self) -> builtins.float:
+        r"""
+        窗口内 (买 - 卖) / (买 + 卖)，取值 -1 到 1，没有成交时为 0
+        """
+    @property
+    def tag(self) -> typing.Optional[builtins.str]:
+        r"""
+        订阅时附带的标签
+        """
+    @property
+    def offset(self) -> typing.Optional[builtins.int]:
+        r"""
+        网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class TradingPhase:
     def __new__(cls) -> TradingPhase: ...
     def keys(self) -> builtins.list[builtins.int]: ...
@@ -200,12 +1129,136 @@ class TradingPhase:
     def determine(self, mills:builtins.int) -> Phase: ...
     def to_datetime(self, mills:builtins.int) -> builtins.str: ...
 
+class TradingStatus:
+    r"""
+    运维暂停或恢复了一个交易对的交易，暂停期间网关拒绝该交易对的新订单；登录时推送当前暂停的交易对
+    """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        暂停或恢复的时间（毫秒）
+        """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def trading(self) -> builtins.bool:
+        r"""
+        为 False 时该交易对的新订单会被拒绝
+        """
+    @property
+    def reason(self) -> typing.Optional[builtins.str]:
+        r"""
+        运维填写的原因
+        """
+    @property
+    def canceled(self) -> builtins.int:
+        r"""
+        暂停时网关撤销的挂单笔数，包括其他会话的
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class VenueStatus:
+    r"""
+    交易场所或交易对的状态变化，网关开启 `venue_status` 后推送
+    """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        网关发现变化的时间（毫秒）
+        """
+    @property
+    def venue(self) -> builtins.str:
+        r"""
+        交易场所，如 `binance-usdt`
+        """
+    @property
+    def symbol(self) -> typing.Optional[builtins.str]:
+        r"""
+        交易对，为 None 时是整个交易场所
+        """
+    @property
+    def status(self) -> builtins.str:
+        r"""
+        交易场所为 `NORMAL` 或 `MAINTENANCE`，交易对为 `TRADING`、`BREAK`、`HALT` 等
+        """
+    @property
+    def previous(self) -> builtins.str:
+        r"""
+        变化前的状态
+        """
+    @property
+    def msg(self) -> typing.Optional[builtins.str]:
+        r"""
+        交易所给出的说明
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
+class Volatility:
+    r"""
+    网关按收线 K 线估计的年化波动率，由 `Session.get_volatility` 查询或收线时以 `{symbol}@vol:{window}` 推送
+    """
+    @property
+    def time(self) -> builtins.int:
+        r"""
+        最近一根参与估计的 K 线的结束时间
+        """
+    @property
+    def symbol(self) -> builtins.str: ...
+    @property
+    def stream(self) -> builtins.str: ...
+    @property
+    def window(self) -> builtins.str:
+        r"""
+        K 线周期，如 `1m`
+        """
+    @property
+    def bars(self) -> builtins.int:
+        r"""
+        参与估计的 K 线数
+        """
+    @property
+    def realized(self) -> typing.Optional[builtins.float]:
+        r"""
+        收盘价对数收益率的均方根，样本不足时为空
+        """
+    @property
+    def parkinson(self) -> typing.Optional[builtins.float]:
+        r"""
+        按最高最低价的 Parkinson 估计
+        """
+    @property
+    def ewma(self) -> typing.Optional[builtins.float]:
+        r"""
+        收益率平方的指数加权平均
+        """
+    def __str__(self) -> builtins.str: ...
+    def __repr__(self) -> builtins.str: ...
+
 class EventType(Enum):
     Login = ...
     Depth = ...
     Kline = ...
+    BarClose = ...
+    Derived = ...
+    TradeFlow = ...
+    Volatility = ...
+    Basis = ...
+    Params = ...
+    Greeks = ...
     Order = ...
+    Fill = ...
+    MyTrades = ...
+    DepthSnapshot = ...
     Position = ...
+    Delivery = ...
+    Roll = ...
+    RollResult = ...
+    SymbolChanged = ...
+    TradingStatus = ...
+    VenueStatus = ...
+    OrderCap = ...
 
 class OrderType(Enum):
     LIMIT = ...
@@ -258,6 +1311,23 @@ class Phase(Enum):
     CLOSE = ...
     UNDEF = ...
 
+class PositionSide(Enum):
+    r"""
+    The position side of a futures order or position
+    """
+    BOTH = ...
+    r"""
+    One-way mode
+    """
+    LONG = ...
+    r"""
+    Long leg in hedge mode
+    """
+    SHORT = ...
+    r"""
+    Short leg in hedge mode
+    """
+
 class Side(Enum):
     r"""
     The side of an order
@@ -279,6 +1349,10 @@ class State(Enum):
     REJECTED = ...
     EXPIRED = ...
     EXPIRED_IN_MATCH = ...
+    EXPIRED_BY_GATEWAY = ...
+    r"""
+    超过 ttl_ms 未完成，由网关撤销
+    """
     UNDEF = ...
 
 class Tif(Enum):
@@ -296,7 +1370,7 @@ class Tif(Enum):
     """
     GTX = ...
     r"""
-    Good till date
+    Post only
     """
     GTD = ...
     r"""
@@ -307,3 +1381,8 @@ class Tif(Enum):
     Undefined
     """
 
+def walk_forward_windows(start:builtins.int, end:builtins.int, train:builtins.int, test:builtins.int) -> builtins.list[tuple[builtins.int, builtins.int, builtins.int]]:
+    r"""
+    按样本外长度滚动切分 [start, end)，返回 (train_start, train_end, test_end) 列表
+    """
+
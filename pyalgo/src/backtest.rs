@@ -0,0 +1,1068 @@
+//! 回测会话
+//!
+//! `BacktestSession` 与 `Session` 暴露相同的接口（connect / subscribe / add_order /
+//! cancel / process），但行情来自数据服务录制的 CSV（见 `pyalgo.data`），订单由
+//! 纸面撮合引擎成交，同一份策略代码可以直接用于回测与实盘。
+//!
+//! 多个订阅按事件时间归并回放，模拟时钟随回放推进；每条行情先撮合挂单再推送给策略。
+
+use crate::chat::{Derived, Message, Product};
+use crate::constant::*;
+use crate::matching::{FillConfig, MatchingEngine, Quote, Report, SimOrder};
+use crate::metrics::EquityCurve;
+use crate::poll::{QuoteBuffer, MAX_EVENTS};
+use crate::session::check_bulk;
+use crate::subscription::Subscription;
+use crate::{Event, EventType, Order, Position};
+use cryptoflow::expr::Program;
+use log::*;
+use pyo3::prelude::*;
+use pyo3_stub_gen::derive::{gen_stub_pyclass, gen_stub_pymethods};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+/// 一条待回放的行情
+#[derive(Debug)]
+struct Record {
+    time: u64,
+    quote: Option<Quote>,
+    message: Message,
+}
+
+/// 按列名读取 CSV，支持数据服务写入的带引号 JSON 数组字段
+struct Table<'a> {
+    columns: HashMap<&'a str, usize>,
+    rows: Vec<Vec<String>>,
+}
+
+impl<'a> Table<'a> {
+    fn parse(csv: &'a str) -> Self {
+        let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+        let columns = lines
+            .next()
+            .map(|header| {
+                header
+                    .split(',')
+                    .enumerate()
+                    .map(|(i, name)| (name.trim(), i))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let rows = lines.map(split_row).collect();
+        Self { columns, rows }
+    }
+
+    fn get<'r>(&self, row: &'r [String], column: &str) -> anyhow::Result<&'r str> {
+        self.columns
+            .get(column)
+            .and_then(|i| row.get(*i))
+            .map(|s| s.as_str())
+            .ok_or_else(|| anyhow::anyhow!("missing column {}", column))
+    }
+
+    fn f64(&self, row: &[String], column: &str) -> anyhow::Result<f64> {
+        Ok(self.get(row, column)?.trim().parse()?)
+    }
+
+    fn u64(&self, row: &[String], column: &str) -> anyhow::Result<u64> {
+        Ok(self.get(row, column)?.trim().parse()?)
+    }
+}
+
+fn split_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// 读取 `klines/{symbol}_{interval}.csv`
+fn parse_klines(csv: &str, symbol: &str, stream: &str) -> anyhow::Result<Vec<Record>> {
+    let table = Table::parse(csv);
+    let mut records = Vec::with_capacity(table.rows.len());
+
+    for row in table.rows.iter() {
+        let (open, high, low, close) = (
+            table.f64(row, "open")?,
+            table.f64(row, "high")?,
+            table.f64(row, "low")?,
+            table.f64(row, "close")?,
+        );
+        let time = table.u64(row, "close_time")?;
+        let volume = table.f64(row, "volume")?;
+        let kline = json!({
+            "time": time,
+            "start_time": table.u64(row, "open_time")?,
+            "symbol": symbol,
+            "stream": stream,
+            "interval": table.get(row, "interval")?,
+            "open": open,
+            "high": high,
+            "low": low,
+            "close": close,
+            "volume": volume,
+            "amount": table.f64(row, "quote_volume")?,
+            "first_trade_id": 0,
+            "last_trade_id": 0,
+            "trade_count": table.u64(row, "trade_count")?,
+            "is_closed": table.get(row, "is_closed")?.trim().eq_ignore_ascii_case("true"),
+            "buy_volume": table.f64(row, "taker_buy_volume")?,
+            "buy_amount": table.f64(row, "taker_buy_quote_volume")?,
+        });
+
+        records.push(Record {
+            time,
+            quote: Some(Quote::Bar {
+                open,
+                high,
+                low,
+                close,
+                volume,
+            }),
+            message: Message::Kline(serde_json::from_value(kline)?),
+        });
+    }
+
+    Ok(records)
+}
+
+/// 读取 `depths/{symbol}_depths.csv`
+fn parse_depths(csv: &str, symbol: &str, stream: &str) -> anyhow::Result<Vec<Record>> {
+    let table = Table::parse(csv);
+    let mut records = Vec::with_capacity(table.rows.len());
+
+    let levels = |row: &[String], prices: &str, volumes: &str| -> anyhow::Result<Vec<_>> {
+        let prices: Vec<f64> = serde_json::from_str(table.get(row, prices)?)?;
+        let volumes: Vec<f64> = serde_json::from_str(table.get(row, volumes)?)?;
+        Ok(prices
+            .into_iter()
+            .zip(volumes)
+            .map(|(price, quantity)| json!({"price": price, "quantity": quantity}))
+            .collect())
+    };
+
+    for row in table.rows.iter() {
+        let time = table.u64(row, "timestamp")?;
+        let bids = levels(row, "bid_prices", "bid_volumes")?;
+        let asks = levels(row, "ask_prices", "ask_volumes")?;
+
+        let quote = match (bids.first(), asks.first()) {
+            (Some(bid), Some(ask)) => Some(Quote::Book {
+                bid: bid["price"].as_f64().unwrap_or_default(),
+                bid_qty: bid["quantity"].as_f64().unwrap_or_default(),
+                ask: ask["price"].as_f64().unwrap_or_default(),
+                ask_qty: ask["quantity"].as_f64().unwrap_or_default(),
+            }),
+            _ => None,
+        };
+        let depth = json!({
+            "time": time,
+            "symbol": symbol,
+            "stream": stream,
+            "bids": bids,
+            "asks": asks,
+        });
+
+        records.push(Record {
+            time,
+            quote,
+            message: Message::Depth(serde_json::from_value(depth)?),
+        });
+    }
+
+    Ok(records)
+}
+
+/// 没有提供交易规则时使用的默认产品
+fn default_product(symbol: &str) -> anyhow::Result<Product> {
+    Ok(serde_json::from_value(json!({
+        "symbol": symbol,
+        "status": "TRADING",
+        "baseAsset": "",
+        "baseAssetPrecision": 8,
+        "quoteAsset": "",
+        "quotePrecision": 8,
+        "orderTypes": ["LIMIT", "LIMIT_MAKER", "MARKET"],
+        "filters": [
+            {"filterType": "PRICE_FILTER", "tickSize": "0.00000001", "maxPrice": "1000000000", "minPrice": "0.00000001"},
+            {"filterType": "LOT_SIZE", "stepSize": "0.00000001", "maxQty": "1000000000", "minQty": "0.00000001"},
+        ],
+    }))?)
+}
+
+/// 读取交易规则，支持 exchangeInfo 原始响应或产品数组
+fn load_products(path: &Path) -> anyhow::Result<Vec<Product>> {
+    let value: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let symbols = match value {
+        serde_json::Value::Object(mut info) => info
+            .remove("symbols")
+            .ok_or_else(|| anyhow::anyhow!("no symbols in {}", path.display()))?,
+        other => other,
+    };
+    Ok(serde_json::from_value(symbols)?)
+}
+
+/// 回测结果
+#[gen_stub_pyclass]
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct BacktestReport {
+    start_time: u64,
+    end_time: u64,
+    events: u64,
+    trades: u64,
+    volume: f64,
+    fees: f64,
+    realized: f64,
+    unrealized: f64,
+    max_drawdown: f64,
+    sharpe: f64,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl BacktestReport {
+    #[getter]
+    fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    #[getter]
+    fn end_time(&self) -> u64 {
+        self.end_time
+    }
+
+    #[getter]
+    fn events(&self) -> u64 {
+        self.events
+    }
+
+    #[getter]
+    fn trades(&self) -> u64 {
+        self.trades
+    }
+
+    #[getter]
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    #[getter]
+    fn fees(&self) -> f64 {
+        self.fees
+    }
+
+    #[getter]
+    fn realized(&self) -> f64 {
+        self.realized
+    }
+
+    #[getter]
+    fn unrealized(&self) -> f64 {
+        self.unrealized
+    }
+
+    /// 扣除手续费后的总盈亏
+    #[getter]
+    fn pnl(&self) -> f64 {
+        self.realized + self.unrealized - self.fees
+    }
+
+    /// 权益曲线的最大回撤（金额）
+    #[getter]
+    fn max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+
+    /// 按日度盈亏计算的年化夏普
+    #[getter]
+    fn sharpe(&self) -> f64 {
+        self.sharpe
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 回测中挂着的报价 (order_id, price, quantity)
+type LiveQuote = (u32, f64, f64);
+
+#[gen_stub_pyclass]
+#[pyclass(unsendable)]
+pub struct BacktestSession {
+    data_dir: PathBuf,
+    products_path: Option<PathBuf>,
+    session_id: u16,
+    name: String,
+    trading: bool,
+    login: bool,
+    products: HashMap<String, Product>,
+    subscription: HashMap<String, Py<Subscription>>,
+    /// 每个订阅一条按时间排序的队列
+    streams: HashMap<String, VecDeque<Record>>,
+    orders: HashMap<u32, Py<Order>>,
+    pending: VecDeque<Py<PyAny>>,
+    engine: MatchingEngine,
+    fees: (f64, f64),
+    fill: FillConfig,
+    id: u32,
+    clock: u64,
+    /// order_id -> 到期时间，模拟网关的 ttl_ms 撤单
+    deadlines: HashMap<u32, u64>,
+    /// symbol -> 买卖两侧报价
+    quotes: HashMap<String, [Option<LiveQuote>; 2]>,
+    /// 只回放 [start, end) 内的行情
+    window: (u64, u64),
+    equity: EquityCurve,
+    report: BacktestReport,
+    /// 订阅 -> 注册在它上面的派生流
+    derived: HashMap<String, Vec<(String, Program)>>,
+}
+
+impl BacktestSession {
+    fn product(&self, symbol: &str) -> anyhow::Result<Product> {
+        match self.products.get(symbol) {
+            Some(product) => Ok(product.clone()),
+            None if self.products_path.is_none() => default_product(symbol),
+            None => anyhow::bail!("Invalid symbol {}", symbol),
+        }
+    }
+
+    fn load(&self, symbol: &str, stream: &str) -> anyhow::Result<Vec<Record>> {
+        let key = format!("{}@{}", symbol, stream);
+        if let Some(interval) = stream.strip_prefix("kline:") {
+            let path = self
+                .data_dir
+                .join("klines")
+                .join(format!("{}_{}.csv", symbol, interval));
+            parse_klines(&std::fs::read_to_string(&path)?, symbol, &key)
+        } else if stream.starts_with("depth") || stream == "bbo" {
+            let path = self
+                .data_dir
+                .join("depths")
+                .join(format!("{}_depths.csv", symbol));
+            parse_depths(&std::fs::read_to_string(&path)?, symbol, &key)
+        } else {
+            anyhow::bail!("Unsupported stream {}", stream)
+        }
+    }
+
+    /// 把撮合回报应用到策略持有的订单上，并同步持仓
+    fn on_reports(&mut self, reports: Vec<Report>) {
+        for report in reports {
+            let Some(pyorder) = self.orders.get(&report.id) else {
+                continue;
+            };
+
+            let event = Python::attach(|py| {
+                let mut order = pyorder.borrow_mut(py);
+                order.on_report(&report);
+                Event::new(EventType::Order, pyorder.clone_ref(py))
+            });
+            self.pending.push_back(event);
+
+            if !matches!(report.state, State::NEW | State::PARTIALLY_FILLED) {
+                self.orders.remove(&report.id);
+            }
+
+            if report.trade_quantity > 0.0 {
+                self.report.trades += 1;
+                if let (Some(sub), Some(book)) = (
+                    self.subscription.get(&report.symbol),
+                    self.engine.book(&report.symbol),
+                ) {
+                    // 回测的手续费单独统计，不计入均价
+                    let avg_price = (book.net != 0.0).then_some(book.avg_price);
+                    let position = Position {
+                        symbol: report.symbol.clone(),
+                        net: book.net,
+                        avg_price,
+                        cost_basis: avg_price.map(|avg| avg * book.net.abs()),
+                        ..Default::default()
+                    };
+                    Python::attach(|py| sub.borrow_mut(py).on_position(position));
+                }
+            }
+        }
+    }
+
+    /// 撤销到期的订单，回报状态为 EXPIRED_BY_GATEWAY
+    fn expire_orders(&mut self) {
+        if self.deadlines.is_empty() {
+            return;
+        }
+        let clock = self.clock;
+        let ids: Vec<u32> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= clock)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.deadlines.remove(&id);
+            if let Some(mut report) = self.engine.cancel(id, clock as i64) {
+                report.state = State::EXPIRED_BY_GATEWAY;
+                self.on_reports(vec![report]);
+            }
+        }
+    }
+
+    /// 调整一侧报价，返回调整后仍在挂着的报价
+    fn requote(
+        &mut self,
+        symbol: &str,
+        side: Side,
+        live: Option<LiveQuote>,
+        desired: Option<(f64, f64)>,
+    ) -> Option<LiveQuote> {
+        if let Some((id, price, quantity)) = live.filter(|(id, ..)| self.orders.contains_key(id)) {
+            if desired == Some((price, quantity)) {
+                return live;
+            }
+            if let Some(report) = self.engine.cancel(id, self.clock as i64) {
+                self.on_reports(vec![report]);
+            }
+        }
+
+        let (price, quantity) = desired?;
+        let order = self.add_order(
+            symbol,
+            price,
+            quantity,
+            &side,
+            &OrderType::LIMIT,
+            &Tif::GTC,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )?;
+        let id = Python::attach(|py| order.borrow(py).id());
+        self.orders
+            .contains_key(&id)
+            .then_some((id, price, quantity))
+    }
+
+    /// 取出时间最早的一条行情
+    fn next_record(&mut self) -> Option<Record> {
+        let key = self
+            .streams
+            .iter()
+            .filter_map(|(key, records)| records.front().map(|r| (r.time, key)))
+            .min()
+            .map(|(_, key)| key.clone())?;
+        self.streams.get_mut(&key)?.pop_front()
+    }
+
+    fn update_report(&mut self) {
+        let books = self.engine.books().values();
+        let (mut volume, mut fees, mut realized, mut unrealized) = (0.0, 0.0, 0.0, 0.0);
+        for book in books {
+            volume += book.volume;
+            fees += book.fees;
+            realized += book.realized;
+            unrealized += book.unrealized();
+        }
+        self.report.volume = volume;
+        self.report.fees = fees;
+        self.report.realized = realized;
+        self.report.unrealized = unrealized;
+        self.report.end_time = self.clock;
+
+        let metrics = self.equity.metrics(self.report.trades);
+        self.report.max_drawdown = metrics.max_drawdown;
+        self.report.sharpe = metrics.sharpe;
+    }
+
+    fn equity(&self) -> f64 {
+        self.engine
+            .books()
+            .values()
+            .map(|book| book.realized + book.unrealized() - book.fees)
+            .sum()
+    }
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl BacktestSession {
+    #[new]
+    fn new(data_dir: String, session_id: u16, name: String, trading: bool) -> Self {
+        Self {
+            data_dir: PathBuf::from(data_dir),
+            products_path: None,
+            session_id,
+            name,
+            trading,
+            login: false,
+            products: HashMap::default(),
+            subscription: HashMap::default(),
+            streams: HashMap::default(),
+            orders: HashMap::default(),
+            pending: VecDeque::default(),
+            engine: MatchingEngine::default(),
+            fees: (0.0, 0.0),
+            fill: FillConfig::default(),
+            id: 0,
+            clock: 0,
+            deadlines: HashMap::default(),
+            quotes: HashMap::default(),
+            window: (0, u64::MAX),
+            equity: EquityCurve::default(),
+            report: BacktestReport::default(),
+            derived: HashMap::default(),
+        }
+    }
+
+    /// 回放时钟，毫秒，为最近一条行情的时间
+    #[getter]
+    fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// 设置挂单、吃单费率，须在下单前调用
+    fn set_fee(&mut self, maker_fee: f64, taker_fee: f64) {
+        self.fees = (maker_fee, taker_fee);
+        self.engine = MatchingEngine::new(maker_fee, taker_fee).with_fill(self.fill.clone());
+    }
+
+    /// 设置成交模型，须在下单前调用
+    ///
+    /// - model: `touch` 触价即成交，`queue` 按排队位置成交
+    /// - participation: 每根 K 线最多成交其成交量的比例，0 为不限制
+    /// - slippage: 吃单滑点曲线 [(下单数量, 滑点 bps)]，按数量线性插值
+    /// - seed: 随机种子，相同种子的结果可复现
+    fn set_fill_model(
+        &mut self,
+        model: &str,
+        participation: f64,
+        slippage: Vec<(f64, f64)>,
+        seed: u64,
+    ) -> PyResult<()> {
+        let model = model
+            .parse()
+            .map_err(|e: anyhow::Error| pyo3::exceptions::PyException::new_err(e.to_string()))?;
+        let mut slippage = slippage;
+        slippage.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.fill = FillConfig {
+            model,
+            participation,
+            slippage,
+            seed,
+        };
+        self.engine = MatchingEngine::new(self.fees.0, self.fees.1).with_fill(self.fill.clone());
+        Ok(())
+    }
+
+    /// 从 exchangeInfo 响应或产品数组加载交易规则，须在 connect 前调用；
+    /// 不设置时使用最小价格/数量步长为 1e-8 的默认规则
+    fn set_products(&mut self, path: String) {
+        self.products_path = Some(PathBuf::from(path));
+    }
+
+    /// 只回放 [start, end) 毫秒时间内的行情，须在 subscribe 前调用
+    fn set_window(&mut self, start: u64, end: u64) {
+        self.window = (start, end);
+    }
+
+    #[getter]
+    fn id(&self) -> u16 {
+        self.session_id
+    }
+
+    #[getter]
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    #[getter]
+    fn is_login(&self) -> bool {
+        self.login
+    }
+
+    #[getter]
+    fn trading(&self) -> bool {
+        self.trading
+    }
+
+    /// 当前模拟时间（毫秒）
+    #[getter]
+    fn time(&self) -> u64 {
+        self.clock
+    }
+
+    /// 所有数据已回放完毕
+    #[getter]
+    fn finished(&self) -> bool {
+        self.pending.is_empty() && self.streams.values().all(|s| s.is_empty())
+    }
+
+    #[getter]
+    fn report(&mut self) -> BacktestReport {
+        self.update_report();
+        self.report.clone()
+    }
+
+    fn connect(&mut self) -> PyResult<()> {
+        if self.login {
+            return Ok(());
+        }
+        if let Some(path) = self.products_path.as_ref() {
+            let products = load_products(path)
+                .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?;
+            info!("Total products {}", products.len());
+            for product in products {
+                self.products.insert(product.symbol().clone(), product);
+            }
+        }
+        info!(
+            "Backtest session {} is ready, data {}",
+            self.session_id,
+            self.data_dir.display()
+        );
+        self.login = true;
+        Ok(())
+    }
+
+    /// `tag` 不为空时回放的每条数据都带上这个标签
+    #[pyo3(signature = (symbol, stream, tag=None))]
+    fn subscribe(
+        &mut self,
+        symbol: &str,
+        stream: &str,
+        tag: Option<String>,
+    ) -> PyResult<Py<Subscription>> {
+        if !self.login {
+            return Err(pyo3::exceptions::PyException::new_err("Please login first"));
+        }
+        let key = format!("{}@{}", symbol, stream);
+        if self.streams.contains_key(&key) {
+            return Err(pyo3::exceptions::PyException::new_err(format!(
+                "Duplicate subscribe {}",
+                key
+            )));
+        }
+
+        let to_py = |e: anyhow::Error| pyo3::exceptions::PyException::new_err(e.to_string());
+        let product = self.product(symbol).map_err(to_py)?;
+        let mut records = self.load(symbol, stream).map_err(to_py)?;
+        // 中途订阅时跳过模拟时钟之前的数据
+        let (start, end) = self.window;
+        records.retain(|r| r.time >= self.clock.max(start) && r.time < end);
+        records.sort_by_key(|r| r.time);
+        if let Some(tag) = tag.as_deref() {
+            for record in records.iter_mut() {
+                match &mut record.message {
+                    Message::Kline(kline) => kline.set_tag(tag),
+                    Message::Depth(depth) => depth.set_tag(tag),
+                    _ => (),
+                }
+            }
+        }
+        info!("Load {} records for {}", records.len(), key);
+
+        if self.report.start_time == 0
+            || records
+                .first()
+                .is_some_and(|r| r.time < self.report.start_time)
+        {
+            self.report.start_time = records.first().map(|r| r.time).unwrap_or_default();
+        }
+        self.streams.insert(key, records.into());
+
+        let sub = match self.subscription.get(symbol) {
+            Some(sub) => Python::attach(|py| sub.clone_ref(py)),
+            None => {
+                let sub = Python::attach(|py| Py::new(py, Subscription::new(product)))?;
+                self.subscription
+                    .insert(symbol.into(), Python::attach(|py| sub.clone_ref(py)));
+                sub
+            }
+        };
+        Ok(sub)
+    }
+
+    /// 与实盘接口一致，停止回放这些 `symbol@stream` 形式的流
+    fn unsubscribe(&mut self, streams: Vec<String>) -> PyResult<()> {
+        for stream in streams {
+            if self.streams.remove(&stream).is_none() {
+                warn!("{} is not subscribed", stream);
+            }
+        }
+        Ok(())
+    }
+
+    /// 与网关相同，回放每条行情时计算 `expr`，结果在行情事件之后推送
+    fn derive(&mut self, symbol: &str, stream: &str, name: &str, expr: &str) -> PyResult<()> {
+        let key = format!("{}@{}", symbol, stream);
+        if !self.streams.contains_key(&key) {
+            return Err(pyo3::exceptions::PyException::new_err(format!(
+                "Subscribe {} first",
+                key
+            )));
+        }
+        let program = Program::parse(expr)
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))?;
+        let derived = self.derived.entry(key).or_default();
+        let stream = format!("{}@derived:{}", symbol, name);
+        derived.retain(|(s, _)| *s != stream);
+        derived.push((stream, program));
+        Ok(())
+    }
+
+    /// 回测不会重发请求，`idempotency_key` 仅为与实盘接口一致；`ttl_ms` 按回放时钟到期撤单
+    /// 回测不模拟钉住订单改价，`peg_offset_ticks` 被忽略，按 `price` 撮合
+    /// 回测按单向持仓统计，`position_side` 被忽略
+    /// 回测按交易所的规则处理只做 maker 的订单，`reprice_passive` 被忽略
+    /// 回测不做归因，`strategy`、`tags` 只记录在订单上
+    /// 回测不做价格与名义价值的合理性校验，`force` 被忽略
+    #[pyo3(signature = (symbol, price, quantity, side, order_type, tif, idempotency_key=None, ttl_ms=None, peg_offset_ticks=None, position_side=None, reprice_passive=None, strategy=None, tags=None, force=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_order(
+        &mut self,
+        symbol: &str,
+        price: f64,
+        quantity: f64,
+        side: &Side,
+        order_type: &OrderType,
+        tif: &Tif,
+        idempotency_key: Option<String>,
+        ttl_ms: Option<u64>,
+        peg_offset_ticks: Option<i64>,
+        position_side: Option<PositionSide>,
+        reprice_passive: Option<bool>,
+        strategy: Option<String>,
+        tags: Option<Vec<String>>,
+        force: Option<bool>,
+    ) -> Option<Py<Order>> {
+        let _ = (
+            idempotency_key,
+            peg_offset_ticks,
+            position_side,
+            reprice_passive,
+            force,
+        );
+        if !self.login || !self.trading {
+            return None;
+        }
+
+        let id = self.id;
+        self.id = self.id.wrapping_add(1);
+        let order = Order::new(id, symbol, price, quantity, *side, *order_type, *tif)
+            .with_tags(strategy, tags.unwrap_or_default());
+        info!("Add order: {:?}", order);
+
+        let pyorder = Python::attach(|py| Py::new(py, order).unwrap());
+
+        let reports = self.engine.submit(
+            SimOrder {
+                id,
+                symbol: symbol.into(),
+                side: *side,
+                order_type: *order_type,
+                tif: *tif,
+                price,
+                quantity,
+            },
+            self.clock as i64,
+        );
+        // 被拒的订单不登记，即使与某笔挂单同号也不会覆盖它
+        if let Some(report) = reports
+            .first()
+            .filter(|r| matches!(r.state, State::REJECTED))
+        {
+            let event = Python::attach(|py| {
+                pyorder.borrow_mut(py).on_report(report);
+                Event::new(EventType::Order, pyorder.clone_ref(py))
+            });
+            self.pending.push_back(event);
+            return Some(pyorder);
+        }
+        self.orders
+            .insert(id, Python::attach(|py| pyorder.clone_ref(py)));
+        if let Some(ttl_ms) = ttl_ms {
+            if reports
+                .iter()
+                .all(|r| matches!(r.state, State::NEW | State::PARTIALLY_FILLED))
+            {
+                self.deadlines.insert(id, self.clock + ttl_ms);
+            }
+        }
+        self.on_reports(reports);
+        Some(pyorder)
+    }
+
+    /// 与实盘相同的批量下单，回测按顺序逐笔撮合
+    #[pyo3(signature = (symbols, sides, prices, quantities, order_type, tif, strategy=None, tags=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_orders(
+        &mut self,
+        symbols: Vec<String>,
+        sides: Vec<Side>,
+        prices: Vec<f64>,
+        quantities: Vec<f64>,
+        order_type: &OrderType,
+        tif: &Tif,
+        strategy: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> PyResult<Vec<Py<Order>>> {
+        check_bulk(&symbols, &sides, &prices, &quantities)?;
+        Ok(symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(i, symbol)| {
+                self.add_order(
+                    symbol,
+                    prices[i],
+                    quantities[i],
+                    &sides[i],
+                    order_type,
+                    tif,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    strategy.clone(),
+                    tags.clone(),
+                    None,
+                )
+            })
+            .collect())
+    }
+
+    /// 双边报价，撮合是同步的，直接撤掉变化的一侧再挂新单
+    #[pyo3(signature = (symbol, bid=None, ask=None))]
+    fn quote(&mut self, symbol: &str, bid: Option<(f64, f64)>, ask: Option<(f64, f64)>) {
+        if !self.login || !self.trading {
+            return;
+        }
+        let [live_bid, live_ask] = self.quotes.remove(symbol).unwrap_or_default();
+        let bid = self.requote(symbol, Side::BUY, live_bid, bid);
+        let ask = self.requote(symbol, Side::SELL, live_ask, ask);
+        self.quotes.insert(symbol.into(), [bid, ask]);
+    }
+
+    /// 撮合是同步的，直接撤掉原订单并以同一个订单号挂出新单，中间的撤单不推送给策略
+    #[pyo3(signature = (order_id, price, quantity=None))]
+    fn replace(&mut self, order_id: u32, price: f64, quantity: Option<f64>) -> bool {
+        if !self.login || !self.trading {
+            return false;
+        }
+        let Some(order) = self.orders.get(&order_id) else {
+            warn!("Cannot replace unknown order {}", order_id);
+            return false;
+        };
+        let request = Python::attach(|py| {
+            let mut order = order.borrow_mut(py);
+            let request = order.replace_request(0, price, quantity);
+            order.on_replace(request.price, request.quantity);
+            request
+        });
+        if self.engine.cancel(order_id, self.clock as i64).is_none() {
+            warn!("Cannot replace {} order {}", request.symbol, order_id);
+            return false;
+        }
+        self.deadlines.remove(&order_id);
+
+        let reports = self.engine.submit(
+            SimOrder {
+                id: order_id,
+                symbol: request.symbol,
+                side: request.side,
+                order_type: request.order_type,
+                tif: request.tif,
+                price: request.price,
+                quantity: request.quantity,
+            },
+            self.clock as i64,
+        );
+        self.on_reports(reports);
+        true
+    }
+
+    #[pyo3(signature = (symbol, order_id, idempotency_key=None))]
+    fn cancel(&mut self, symbol: String, order_id: u32, idempotency_key: Option<String>) {
+        let _ = idempotency_key;
+        if !self.login || !self.trading {
+            return;
+        }
+        match self.engine.cancel(order_id, self.clock as i64) {
+            Some(report) => self.on_reports(vec![report]),
+            None => warn!("Cannot cancel {} order {}", symbol, order_id),
+        }
+    }
+
+    /// 与实盘接口一致，一次取出最多 `max_n` 个事件；回放不需要等待，`timeout_ms` 被忽略
+    #[pyo3(signature = (max_n=None, timeout_ms=None))]
+    fn poll_events(&mut self, max_n: Option<usize>, timeout_ms: Option<u64>) -> Vec<Py<PyAny>> {
+        let _ = timeout_ms;
+        std::iter::from_fn(|| self.process())
+            .take(max_n.unwrap_or(MAX_EVENTS))
+            .collect()
+    }
+
+    /// 与实盘接口一致，深度的最优一档写入 `buffer`，其余的事件返回
+    #[pyo3(signature = (buffer, max_n=None, timeout_ms=None))]
+    fn poll_quotes(
+        &mut self,
+        py: Python<'_>,
+        buffer: &Bound<'_, PyAny>,
+        max_n: Option<usize>,
+        timeout_ms: Option<u64>,
+    ) -> PyResult<(Vec<String>, Vec<Py<PyAny>>)> {
+        let mut quotes = QuoteBuffer::new(buffer)?;
+        let events = self
+            .poll_events(max_n, timeout_ms)
+            .into_iter()
+            .filter(|event| !quotes.push_event(py, event))
+            .collect();
+        Ok((quotes.into_symbols(), events))
+    }
+
+    fn process(&mut self) -> Option<Py<PyAny>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+
+        let record = self.next_record()?;
+        self.clock = record.time;
+        self.report.events += 1;
+        self.expire_orders();
+
+        let mut derived = Vec::new();
+        let (symbol, event) = match record.message {
+            Message::Kline(kline) => {
+                if let Some(programs) = self.derived.get_mut(kline.stream()) {
+                    derived = eval_derived(programs, kline.symbol(), record.time, |n| kline.var(n));
+                }
+                (kline.symbol().clone(), Event::new(EventType::Kline, kline))
+            }
+            Message::Depth(depth) => {
+                if let Some(programs) = self.derived.get_mut(depth.stream()) {
+                    derived = eval_derived(programs, depth.symbol(), record.time, |n| depth.var(n));
+                }
+                (depth.symbol().clone(), Event::new(EventType::Depth, depth))
+            }
+            _ => return None,
+        };
+        if let Some(quote) = record.quote {
+            let reports = self.engine.on_market(&symbol, quote, self.clock as i64);
+            self.on_reports(reports);
+        }
+        let equity = self.equity();
+        self.equity.push(self.clock, equity);
+        self.pending.push_back(event);
+        self.pending.extend(
+            derived
+                .into_iter()
+                .map(|d| Event::new(EventType::Derived, d)),
+        );
+
+        self.pending.pop_front()
+    }
+}
+
+fn eval_derived(
+    programs: &mut [(String, Program)],
+    symbol: &str,
+    time: u64,
+    vars: impl Fn(&str) -> Option<f64>,
+) -> Vec<Derived> {
+    programs
+        .iter_mut()
+        .filter_map(|(stream, program)| {
+            let values = program.eval(&vars);
+            (!values.is_empty()).then(|| Derived::new(time, symbol, stream, values))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_row() {
+        assert_eq!(split_row("a,b,,c"), vec!["a", "b", "", "c"]);
+        assert_eq!(
+            split_row(r#"btcusdt,1,"[1.0, 2.0]","say ""hi""""#),
+            vec!["btcusdt", "1", "[1.0, 2.0]", r#"say "hi""#]
+        );
+    }
+
+    #[test]
+    fn test_parse_klines() {
+        let csv = "symbol,interval,open_time,close_time,open,high,low,close,volume,quote_volume,trade_count,taker_buy_volume,taker_buy_quote_volume,is_closed,datetime\n\
+                   btcusdt,1m,1704153600000,1704153659999,42283.58,42298.62,42261.02,42298.61,35.92724,1519124.78,1327,20.35272,860648.55,True,2024-01-02 00:00:00\n";
+        let records = parse_klines(csv, "btcusdt", "btcusdt@kline:1m").unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].time, 1704153659999);
+        assert_eq!(
+            records[0].quote,
+            Some(Quote::Bar {
+                open: 42283.58,
+                high: 42298.62,
+                low: 42261.02,
+                close: 42298.61,
+                volume: 35.92724
+            })
+        );
+        assert!(
+            matches!(&records[0].message, Message::Kline(k) if k.stream() == "btcusdt@kline:1m")
+        );
+    }
+
+    #[test]
+    fn test_parse_depths() {
+        let csv = "symbol,timestamp,datetime,bid_prices,bid_volumes,ask_prices,ask_volumes,stored_at,stored_at_ms\n\
+                   btcusdt,1704153600000,2024-01-02 00:00:00,\"[100.0, 99.5]\",\"[1.0, 2.0]\",\"[100.5]\",\"[3.0]\",x,1\n\
+                   btcusdt,1704153601000,2024-01-02 00:00:01,[],[],\"[100.5]\",\"[3.0]\",x,1\n";
+        let records = parse_depths(csv, "btcusdt", "btcusdt@depth").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].quote,
+            Some(Quote::Book {
+                bid: 100.0,
+                bid_qty: 1.0,
+                ask: 100.5,
+                ask_qty: 3.0
+            })
+        );
+        assert_eq!(records[1].quote, None);
+        assert!(matches!(&records[0].message, Message::Depth(d) if d.stream() == "btcusdt@depth"));
+
+        assert!(parse_depths("symbol,timestamp\nbtcusdt,1\n", "btcusdt", "btcusdt@depth").is_err());
+    }
+
+    #[test]
+    fn test_default_product() {
+        let product = default_product("btcusdt").unwrap();
+        assert_eq!(product.symbol(), "btcusdt");
+        assert_eq!(product.lot(), 0.00000001);
+    }
+}
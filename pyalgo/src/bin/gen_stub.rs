@@ -1,8 +1,21 @@
+//! 生成 `python/pyalgo/pyalgo.pyi`，带 `--check` 时只比较，已提交的存根过期时返回失败
+
 use pyo3_stub_gen::Result;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     env_logger::Builder::from_env(env_logger::Env::default().filter_or("RUST_LOG", "info")).init();
     let stub = pyalgo::stub_info()?;
+    if std::env::args().any(|arg| arg == "--check") {
+        let stale = pyalgo::stale_stubs(&stub);
+        for path in stale.iter() {
+            log::error!("{} is out of date, run gen_stub", path.display());
+        }
+        return Ok(match stale.is_empty() {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        });
+    }
     stub.generate()?;
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
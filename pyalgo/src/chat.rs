@@ -1,221 +1,1537 @@
 use crate::constant::*;
+use crate::matching::Report;
+use binance::model::leverage::Bracket;
+use binance::model::option::OptionContract;
 use binance::model::symbol::BinanceSymbol;
 use chrono::DateTime;
 use chrono_tz::{Asia::Shanghai, Tz};
-use cryptoflow::chat::{ErrorResponse, Response, SLoginResponse, Success};
+use cryptoflow::chat::{ErrorResponse, Response, SLoginResponse, SSubscribeResponse, Success};
 use cryptoflow::trading_rules::TradingRules;
 use pyo3::prelude::*;
 use pyo3::{conversion::IntoPyObject, IntoPyObjectExt};
 use pyo3_stub_gen::derive::{gen_stub_pyclass, gen_stub_pyclass_enum, gen_stub_pymethods};
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 struct Quote {
     pub price: f64,
     pub quantity: f64,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 #[gen_stub_pyclass]
 #[pyclass]
 pub struct Depth {
     time: u64,
     symbol: String,
-    stream: String,
-    bids: Vec<Quote>,
-    asks: Vec<Quote>,
+    stream: String,
+    bids: Vec<Quote>,
+    asks: Vec<Quote>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    offset: Option<u64>,
+}
+
+impl Depth {
+    pub(crate) fn set_tag(&mut self, tag: &str) {
+        self.tag = Some(tag.to_string());
+    }
+
+    /// 最优一档，[time, bid, bid_qty, ask, ask_qty]，买卖盘有一侧为空时为 None
+    pub(crate) fn best(&self) -> Option<[f64; 5]> {
+        let (bid, ask) = (self.bids.first()?, self.asks.first()?);
+        Some([
+            self.time as f64,
+            bid.price,
+            bid.quantity,
+            ask.price,
+            ask.quantity,
+        ])
+    }
+
+    /// 派生流表达式中的 `bid0`、`askvol3` 等变量
+    pub(crate) fn var(&self, name: &str) -> Option<f64> {
+        let (quotes, rest) = match name.strip_prefix("bid") {
+            Some(rest) => (&self.bids, rest),
+            None => (&self.asks, name.strip_prefix("ask")?),
+        };
+        match rest.strip_prefix("vol") {
+            Some(level) => quotes.get(level.parse::<usize>().ok()?).map(|q| q.quantity),
+            None => quotes.get(rest.parse::<usize>().ok()?).map(|q| q.price),
+        }
+    }
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Depth {
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn datetime(&self) -> String {
+        DateTime::from_timestamp_millis(self.time as i64)
+            .unwrap()
+            .with_timezone(&Shanghai)
+            .to_string()
+    }
+
+    #[getter]
+    pub fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    pub fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    #[getter]
+    fn bid_level(&self) -> usize {
+        self.bids.len()
+    }
+
+    #[getter]
+    fn ask_level(&self) -> usize {
+        self.asks.len()
+    }
+
+    /// 订阅时附带的标签
+    #[getter]
+    fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    /// 网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据；回测中为空
+    #[getter]
+    fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    fn bid_prc(&self, level: usize) -> f64 {
+        match self.bids.get(level) {
+            Some(quote) => quote.price,
+            None => 0.0,
+        }
+    }
+
+    fn bid_vol(&self, level: usize) -> f64 {
+        match self.bids.get(level) {
+            Some(quote) => quote.quantity,
+            None => 0.0,
+        }
+    }
+
+    fn ask_prc(&self, level: usize) -> f64 {
+        match self.asks.get(level) {
+            Some(quote) => quote.price,
+            None => 0.0,
+        }
+    }
+
+    fn ask_vol(&self, level: usize) -> f64 {
+        match self.asks.get(level) {
+            Some(quote) => quote.quantity,
+            None => 0.0,
+        }
+    }
+}
+
+/// `Session.get_depth_snapshot` 查询到的交易所深度快照，用于初始化本地订单簿
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct DepthSnapshot {
+    time: u64,
+    symbol: String,
+    last_update_id: i64,
+    bids: Vec<Quote>,
+    asks: Vec<Quote>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl DepthSnapshot {
+    /// 网关从交易所取得快照的时间，返回缓存时早于查询时间
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    /// 本地订单簿从这个编号之后的增量开始更新
+    #[getter]
+    fn last_update_id(&self) -> i64 {
+        self.last_update_id
+    }
+
+    /// 买盘的 (价格, 数量)，价格从高到低
+    #[getter]
+    fn bids(&self) -> Vec<(f64, f64)> {
+        self.bids.iter().map(|q| (q.price, q.quantity)).collect()
+    }
+
+    /// 卖盘的 (价格, 数量)，价格从低到高
+    #[getter]
+    fn asks(&self) -> Vec<(f64, f64)> {
+        self.asks.iter().map(|q| (q.price, q.quantity)).collect()
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Kline {
+    time: u64,       // K线结束时间
+    start_time: u64, // K线起始时间
+    symbol: String,
+    stream: String,
+    interval: String,    // K线间隔
+    open: f64,           // 开盘价
+    high: f64,           // 最高价
+    low: f64,            // 最低价
+    close: f64,          // 收盘价
+    volume: f64,         // 成交量
+    amount: f64,         // 成交额
+    first_trade_id: i64, // 第一笔成交ID
+    last_trade_id: i64,  // 最后一笔成交ID
+    trade_count: i64,    // 成交数量
+    is_closed: bool,     // K线是否完结
+    buy_volume: f64,     // 主动买入成交量
+    buy_amount: f64,     // 主动买入成交额
+    #[serde(default)]
+    backfill: bool, // 重连后补齐的K线
+    #[serde(default)]
+    tag: Option<String>, // 订阅时附带的标签
+    #[serde(default)]
+    offset: Option<u64>, // 网关为这条流的数据编的号
+}
+
+impl Kline {
+    pub(crate) fn set_tag(&mut self, tag: &str) {
+        self.tag = Some(tag.to_string());
+    }
+
+    /// 派生流表达式中的 `open`、`close` 等变量
+    pub(crate) fn var(&self, name: &str) -> Option<f64> {
+        Some(match name {
+            "open" => self.open,
+            "high" => self.high,
+            "low" => self.low,
+            "close" => self.close,
+            "volume" => self.volume,
+            "amount" => self.amount,
+            "buy_volume" => self.buy_volume,
+            "buy_amount" => self.buy_amount,
+            "trade_count" => self.trade_count as f64,
+            _ => return None,
+        })
+    }
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Kline {
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn datetime(&self) -> String {
+        DateTime::from_timestamp_millis(self.time as i64)
+            .unwrap()
+            .with_timezone(&Shanghai)
+            .to_string()
+    }
+
+    #[getter]
+    pub fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    pub fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    #[getter]
+    fn open(&self) -> f64 {
+        self.open
+    }
+
+    #[getter]
+    fn high(&self) -> f64 {
+        self.high
+    }
+
+    #[getter]
+    fn low(&self) -> f64 {
+        self.low
+    }
+
+    #[getter]
+    fn close(&self) -> f64 {
+        self.close
+    }
+
+    #[getter]
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    #[getter]
+    fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    #[getter]
+    pub fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    #[getter]
+    fn start_datetime(&self) -> String {
+        DateTime::from_timestamp_millis(self.start_time as i64)
+            .unwrap()
+            .with_timezone(&Shanghai)
+            .to_string()
+    }
+
+    #[getter]
+    fn interval(&self) -> &String {
+        &self.interval
+    }
+
+    #[getter]
+    fn first_trade_id(&self) -> i64 {
+        self.first_trade_id
+    }
+
+    #[getter]
+    fn last_trade_id(&self) -> i64 {
+        self.last_trade_id
+    }
+
+    #[getter]
+    fn trade_count(&self) -> i64 {
+        self.trade_count
+    }
+
+    #[getter]
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    #[getter]
+    fn buy_volume(&self) -> f64 {
+        self.buy_volume
+    }
+
+    #[getter]
+    fn buy_amount(&self) -> f64 {
+        self.buy_amount
+    }
+
+    #[getter]
+    fn backfill(&self) -> bool {
+        self.backfill
+    }
+
+    /// 订阅时附带的标签
+    #[getter]
+    fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    /// 网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据；回测中为空
+    #[getter]
+    fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 网关按时钟推送的收线事件，交易所 K 线断流时也会按时到达
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct BarClose {
+    bar_close: u64, // 收线时刻
+    symbol: String,
+    stream: String,
+    interval: String,
+    kline: Option<Kline>, // 收线时已知的最近一根K线
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    offset: Option<u64>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl BarClose {
+    #[getter]
+    fn time(&self) -> u64 {
+        self.bar_close
+    }
+
+    #[getter]
+    fn datetime(&self) -> String {
+        DateTime::from_timestamp_millis(self.bar_close as i64)
+            .unwrap()
+            .with_timezone(&Shanghai)
+            .to_string()
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    #[getter]
+    fn interval(&self) -> &String {
+        &self.interval
+    }
+
+    #[getter]
+    fn kline(&self) -> Option<Kline> {
+        self.kline.clone()
+    }
+
+    /// 订阅时附带的标签
+    #[getter]
+    fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    /// 网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据；回测中为空
+    #[getter]
+    fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 网关按表达式计算的派生流结果
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Derived {
+    time: u64,
+    symbol: String,
+    stream: String, // {symbol}@derived:{name}
+    values: BTreeMap<String, f64>,
+}
+
+impl Derived {
+    pub(crate) fn new(
+        time: u64,
+        symbol: &str,
+        stream: &str,
+        values: BTreeMap<String, f64>,
+    ) -> Self {
+        Self {
+            time,
+            symbol: symbol.to_string(),
+            stream: stream.to_string(),
+            values,
+        }
+    }
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Derived {
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    /// 表达式中各命名语句的结果
+    #[getter]
+    fn values(&self) -> BTreeMap<String, f64> {
+        self.values.clone()
+    }
+
+    fn get(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 网关按归集成交统计的成交流向，由 `{symbol}@cvd:{window}` 推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct TradeFlow {
+    time: u64,
+    symbol: String,
+    stream: String, // {symbol}@cvd:{window}
+    cvd: f64,
+    buy_volume: f64,
+    sell_volume: f64,
+    imbalance: f64,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    offset: Option<u64>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl TradeFlow {
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    /// 网关开始统计以来主动买入量减主动卖出量的累计值
+    #[getter]
+    fn cvd(&self) -> f64 {
+        self.cvd
+    }
+
+    /// 窗口内的主动买入量
+    #[getter]
+    fn buy_volume(&self) -> f64 {
+        self.buy_volume
+    }
+
+    /// 窗口内的主动卖出量
+    #[getter]
+    fn sell_volume(&self) -> f64 {
+        self.sell_volume
+    }
+
+    /// 窗口内的主动买卖差
+    #[getter]
+    fn delta(&self) -> f64 {
+        self.buy_volume - self.sell_volume
+    }
+
+    /// 窗口内 (买 - 卖) / (买 + 卖)，取值 -1 到 1，没有成交时为 0
+    #[getter]
+    fn imbalance(&self) -> f64 {
+        self.imbalance
+    }
+
+    /// 订阅时附带的标签
+    #[getter]
+    fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    /// 网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据
+    #[getter]
+    fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 网关按收线 K 线估计的年化波动率，由 `Session.get_volatility` 查询或收线时以 `{symbol}@vol:{window}` 推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Volatility {
+    time: u64,
+    symbol: String,
+    stream: String,
+    window: String,
+    bars: usize,
+    realized: Option<f64>,
+    parkinson: Option<f64>,
+    ewma: Option<f64>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Volatility {
+    /// 最近一根参与估计的 K 线的结束时间
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    /// K 线周期，如 `1m`
+    #[getter]
+    fn window(&self) -> &String {
+        &self.window
+    }
+
+    /// 参与估计的 K 线数
+    #[getter]
+    fn bars(&self) -> usize {
+        self.bars
+    }
+
+    /// 收盘价对数收益率的均方根，样本不足时为空
+    #[getter]
+    fn realized(&self) -> Option<f64> {
+        self.realized
+    }
+
+    /// 按最高最低价的 Parkinson 估计
+    #[getter]
+    fn parkinson(&self) -> Option<f64> {
+        self.parkinson
+    }
+
+    /// 收益率平方的指数加权平均
+    #[getter]
+    fn ewma(&self) -> Option<f64> {
+        self.ewma
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 合约网关计算的永续合约与现货的基差，订阅 `{symbol}@basis` 后推送，或由 `Session.get_basis` 查询
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Basis {
+    time: u64,
+    symbol: String,
+    stream: String,
+    spot: f64,
+    perp: f64,
+    basis: f64,
+    basis_rate: f64,
+    funding_rate: Option<f64>,
+    next_funding_time: i64,
+    annualized_funding: Option<f64>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    offset: Option<u64>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Basis {
+    #[getter]
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    /// 现货买一卖一的中间价
+    #[getter]
+    fn spot(&self) -> f64 {
+        self.spot
+    }
+
+    /// 永续合约买一卖一的中间价
+    #[getter]
+    fn perp(&self) -> f64 {
+        self.perp
+    }
+
+    /// `perp - spot`
+    #[getter]
+    fn basis(&self) -> f64 {
+        self.basis
+    }
+
+    /// 基差相对现货价格的比例
+    #[getter]
+    fn basis_rate(&self) -> f64 {
+        self.basis_rate
+    }
+
+    /// 当期资金费率，还没有收到标记价格时为空
+    #[getter]
+    fn funding_rate(&self) -> Option<f64> {
+        self.funding_rate
+    }
+
+    /// 下次收取资金费的时间
+    #[getter]
+    fn next_funding_time(&self) -> i64 {
+        self.next_funding_time
+    }
+
+    /// 按当期资金费率年化的收益，持有现货、做空永续时为正
+    #[getter]
+    fn annualized_funding(&self) -> Option<f64> {
+        self.annualized_funding
+    }
+
+    /// 订阅时附带的标签
+    #[getter]
+    fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    /// 网关为这条流的数据编的号，断线重连后用 `Session.resume` 补发之后的数据
+    #[getter]
+    fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 交割合约的交割提醒与结算，持有该合约时推送；`settled` 为 True 时网关已把持仓置零
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Delivery {
+    symbol: String,
+    delivery_time: i64,
+    remaining_ms: i64,
+    position: f64,
+    settled: bool,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Delivery {
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    /// 交割时间（毫秒）
+    #[getter]
+    fn delivery_time(&self) -> i64 {
+        self.delivery_time
+    }
+
+    /// 距交割的毫秒数，结算时为 0
+    #[getter]
+    fn remaining_ms(&self) -> i64 {
+        self.remaining_ms
+    }
+
+    /// 净持仓，结算时为结算前的持仓
+    #[getter]
+    fn position(&self) -> f64 {
+        self.position
+    }
+
+    #[getter]
+    fn settled(&self) -> bool {
+        self.settled
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 季度合约交割前的移仓提醒，持有 `contract` 或在它上面有挂单时推送；用 `Session.roll` 移仓
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Roll {
+    alias: String,
+    contract: String,
+    next: Option<String>,
+    delivery_time: i64,
+    remaining_ms: i64,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Roll {
+    /// 连续合约别名，如 `btcusdt_quarter`
+    #[getter]
+    fn alias(&self) -> &String {
+        &self.alias
+    }
+
+    /// 别名当前对应的合约
+    #[getter]
+    fn contract(&self) -> &String {
+        &self.contract
+    }
+
+    /// 到期后接替的合约
+    #[getter]
+    fn next(&self) -> Option<&String> {
+        self.next.as_ref()
+    }
+
+    /// `contract` 的交割时间（毫秒）
+    #[getter]
+    fn delivery_time(&self) -> i64 {
+        self.delivery_time
+    }
+
+    /// 距交割的毫秒数
+    #[getter]
+    fn remaining_ms(&self) -> i64 {
+        self.remaining_ms
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// `Session.roll` 的结果：撤销的挂单与网关发出的订单，订单回报照常推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct RollResult {
+    alias: String,
+    contract: String,
+    next: String,
+    canceled: Vec<u32>,
+    orders: Vec<u32>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl RollResult {
+    #[getter]
+    fn alias(&self) -> &String {
+        &self.alias
+    }
+
+    /// 移出的合约
+    #[getter]
+    fn contract(&self) -> &String {
+        &self.contract
+    }
+
+    /// 移入的合约
+    #[getter]
+    fn next(&self) -> &String {
+        &self.next
+    }
+
+    /// 在 `contract` 上撤销的挂单编号
+    #[getter]
+    fn canceled(&self) -> Vec<u32> {
+        self.canceled.clone()
+    }
+
+    /// 网关发出的订单编号，包括在 `next` 上重挂的订单与移持仓的市价单
+    #[getter]
+    fn orders(&self) -> Vec<u32> {
+        self.orders.clone()
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 用连续合约别名订阅的流换到了新的合约，之后 `stream` 的数据来自 `symbol`；K 线等按合约累积的状态需要重新开始
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct SymbolChanged {
+    time: i64,
+    pub alias: String,
+    stream: String,
+    previous: String,
+    pub symbol: String,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl SymbolChanged {
+    /// 网关切换的时间（毫秒）
+    #[getter]
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// 连续合约别名，如 `btcusdt_quarter`
+    #[getter]
+    fn alias(&self) -> &String {
+        &self.alias
+    }
+
+    /// 订阅时的流，如 `btcusdt_quarter@kline:1m`
+    #[getter]
+    fn stream(&self) -> &String {
+        &self.stream
+    }
+
+    /// 切换前的合约
+    #[getter]
+    fn previous(&self) -> &String {
+        &self.previous
+    }
+
+    /// 切换后的合约
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 运维暂停或恢复了一个交易对的交易，暂停期间网关拒绝该交易对的新订单；登录时推送当前暂停的交易对
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct TradingStatus {
+    time: i64,
+    pub symbol: String,
+    pub trading: bool,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    canceled: usize,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl TradingStatus {
+    /// 暂停或恢复的时间（毫秒）
+    #[getter]
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    /// 为 False 时该交易对的新订单会被拒绝
+    #[getter]
+    fn trading(&self) -> bool {
+        self.trading
+    }
+
+    /// 运维填写的原因
+    #[getter]
+    fn reason(&self) -> Option<String> {
+        self.reason.clone()
+    }
+
+    /// 暂停时网关撤销的挂单笔数，包括其他会话的
+    #[getter]
+    fn canceled(&self) -> usize {
+        self.canceled
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 交易场所或交易对的状态变化，网关开启 `venue_status` 后推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct VenueStatus {
+    time: i64,
+    venue: String,
+    #[serde(default)]
+    symbol: Option<String>,
+    status: String,
+    previous: String,
+    #[serde(default)]
+    msg: Option<String>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl VenueStatus {
+    /// 网关发现变化的时间（毫秒）
+    #[getter]
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// 交易场所，如 `binance-usdt`
+    #[getter]
+    fn venue(&self) -> &String {
+        &self.venue
+    }
+
+    /// 交易对，为 None 时是整个交易场所
+    #[getter]
+    fn symbol(&self) -> Option<String> {
+        self.symbol.clone()
+    }
+
+    /// 交易场所为 `NORMAL` 或 `MAINTENANCE`，交易对为 `TRADING`、`BREAK`、`HALT` 等
+    #[getter]
+    fn status(&self) -> &String {
+        &self.status
+    }
+
+    /// 变化前的状态
+    #[getter]
+    fn previous(&self) -> &String {
+        &self.previous
+    }
+
+    /// 交易所给出的说明
+    #[getter]
+    fn msg(&self) -> Option<String> {
+        self.msg.clone()
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 订单会超过交易所的挂单数上限时网关排队下单，排队与发出时推送给下单的会话
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct OrderCap {
+    time: i64,
+    symbol: String,
+    order_id: u32,
+    filter: String,
+    limit: i64,
+    open: i64,
+    pub queued: bool,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl OrderCap {
+    /// 推送的时间（毫秒）
+    #[getter]
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn order_id(&self) -> u32 {
+        self.order_id
+    }
+
+    /// 挡住订单的过滤器，如 `MAX_NUM_ORDERS`、`MAX_NUM_ALGO_ORDERS`
+    #[getter]
+    fn filter(&self) -> &String {
+        &self.filter
+    }
+
+    /// 过滤器的上限
+    #[getter]
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    /// 网关记录的该交易对已发出、尚未结束的订单数
+    #[getter]
+    fn open(&self) -> i64 {
+        self.open
+    }
+
+    /// 为 True 时订单开始排队，为 False 时已经发出
+    #[getter]
+    fn queued(&self) -> bool {
+        self.queued
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 订单的一笔成交，登录时开启 `fills` 后在订单回报之外逐笔推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Fill {
+    internal_id: u32,
+    order_id: i64,
+    symbol: String,
+    side: Side,
+    trade_id: i64,
+    time: i64,
+    price: f64,
+    quantity: f64,
+    maker: bool,
+    commission: f64,
+    #[serde(default)]
+    commission_asset: String,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Fill {
+    /// 订单号，与 `Order.id` 相同
+    #[getter]
+    fn id(&self) -> u32 {
+        self.internal_id
+    }
+
+    /// 交易所的订单号
+    #[getter]
+    fn order_id(&self) -> i64 {
+        self.order_id
+    }
+
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    #[getter]
+    fn side(&self) -> Side {
+        self.side
+    }
+
+    #[getter]
+    fn trade_id(&self) -> i64 {
+        self.trade_id
+    }
+
+    /// 成交时间
+    #[getter]
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    #[getter]
+    fn price(&self) -> f64 {
+        self.price
+    }
+
+    #[getter]
+    fn quantity(&self) -> f64 {
+        self.quantity
+    }
+
+    /// 作为挂单成交
+    #[getter]
+    fn maker(&self) -> bool {
+        self.maker
+    }
+
+    #[getter]
+    fn commission(&self) -> f64 {
+        self.commission
+    }
+
+    #[getter]
+    fn commission_asset(&self) -> &String {
+        &self.commission_asset
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
+}
+
+/// 账户的一笔历史成交
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct MyTrade {
+    symbol: String,
+    trade_id: i64,
+    order_id: i64,
+    side: Side,
+    price: f64,
+    quantity: f64,
+    quote_quantity: f64,
+    commission: f64,
+    commission_asset: String,
+    maker: bool,
+    time: i64,
 }
 
 #[gen_stub_pymethods]
 #[pymethods]
-impl Depth {
+impl MyTrade {
     #[getter]
-    fn time(&self) -> u64 {
-        self.time
+    fn symbol(&self) -> &String {
+        &self.symbol
     }
 
     #[getter]
-    fn datetime(&self) -> String {
-        DateTime::from_timestamp_millis(self.time as i64)
-            .unwrap()
-            .with_timezone(&Shanghai)
-            .to_string()
+    fn trade_id(&self) -> i64 {
+        self.trade_id
     }
 
+    /// 交易所的订单号
     #[getter]
-    fn symbol(&self) -> &String {
-        &self.symbol
+    fn order_id(&self) -> i64 {
+        self.order_id
     }
 
     #[getter]
-    fn stream(&self) -> &String {
-        &self.stream
+    fn side(&self) -> Side {
+        self.side
     }
 
     #[getter]
-    fn bid_level(&self) -> usize {
-        self.bids.len()
+    fn price(&self) -> f64 {
+        self.price
     }
 
     #[getter]
-    fn ask_level(&self) -> usize {
-        self.asks.len()
+    fn quantity(&self) -> f64 {
+        self.quantity
     }
 
-    fn bid_prc(&self, level: usize) -> f64 {
-        match self.bids.get(level) {
-            Some(quote) => quote.price,
-            None => 0.0,
-        }
+    /// 成交金额
+    #[getter]
+    fn quote_quantity(&self) -> f64 {
+        self.quote_quantity
     }
 
-    fn bid_vol(&self, level: usize) -> f64 {
-        match self.bids.get(level) {
-            Some(quote) => quote.quantity,
-            None => 0.0,
-        }
+    #[getter]
+    fn commission(&self) -> f64 {
+        self.commission
     }
 
-    fn ask_prc(&self, level: usize) -> f64 {
-        match self.asks.get(level) {
-            Some(quote) => quote.price,
-            None => 0.0,
-        }
+    #[getter]
+    fn commission_asset(&self) -> &String {
+        &self.commission_asset
     }
 
-    fn ask_vol(&self, level: usize) -> f64 {
-        match self.asks.get(level) {
-            Some(quote) => quote.quantity,
-            None => 0.0,
-        }
+    /// 作为挂单成交
+    #[getter]
+    fn maker(&self) -> bool {
+        self.maker
+    }
+
+    /// 成交时间
+    #[getter]
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
     }
 }
 
+/// `Session.get_my_trades` 查询到的一个交易对 [start, end) 内的成交，按时间排序
 #[derive(Debug, Deserialize, PartialEq)]
 #[gen_stub_pyclass]
 #[pyclass]
-pub struct Kline {
-    time: u64,       // K线结束时间
-    start_time: u64, // K线起始时间
+pub struct MyTrades {
     symbol: String,
-    stream: String,
-    interval: String,    // K线间隔
-    open: f64,           // 开盘价
-    high: f64,           // 最高价
-    low: f64,            // 最低价
-    close: f64,          // 收盘价
-    volume: f64,         // 成交量
-    amount: f64,         // 成交额
-    first_trade_id: i64, // 第一笔成交ID
-    last_trade_id: i64,  // 最后一笔成交ID
-    trade_count: i64,    // 成交数量
-    is_closed: bool,     // K线是否完结
-    buy_volume: f64,     // 主动买入成交量
-    buy_amount: f64,     // 主动买入成交额
+    start: i64,
+    end: i64,
+    trades: Vec<MyTrade>,
 }
 
 #[gen_stub_pymethods]
 #[pymethods]
-impl Kline {
+impl MyTrades {
     #[getter]
-    fn time(&self) -> u64 {
-        self.time
+    fn symbol(&self) -> &String {
+        &self.symbol
     }
 
     #[getter]
-    fn datetime(&self) -> String {
-        DateTime::from_timestamp_millis(self.time as i64)
-            .unwrap()
-            .with_timezone(&Shanghai)
-            .to_string()
+    fn start(&self) -> i64 {
+        self.start
     }
 
     #[getter]
-    fn symbol(&self) -> &String {
-        &self.symbol
+    fn end(&self) -> i64 {
+        self.end
     }
 
     #[getter]
-    fn stream(&self) -> &String {
-        &self.stream
+    fn trades(&self) -> Vec<MyTrade> {
+        self.trades.clone()
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
     }
+}
+
+/// 会话的策略参数，由 `Session.get_params` 查询，运维修改参数时推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Params {
+    session_id: u16,
+    params: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    changed: Vec<String>,
+}
+
+fn to_python(value: &impl Serialize) -> PyResult<Py<PyAny>> {
+    let text = serde_json::to_string(value)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    Python::attach(|py| Ok(py.import("json")?.call_method1("loads", (text,))?.unbind()))
+}
 
+#[gen_stub_pymethods]
+#[pymethods]
+impl Params {
     #[getter]
-    fn open(&self) -> f64 {
-        self.open
+    fn session_id(&self) -> u16 {
+        self.session_id
     }
 
+    /// 全部参数
     #[getter]
-    fn high(&self) -> f64 {
-        self.high
+    fn params(&self) -> PyResult<Py<PyAny>> {
+        to_python(&self.params)
     }
 
+    /// 这次推送中变化了的键，查询的结果为空
     #[getter]
-    fn low(&self) -> f64 {
-        self.low
+    fn changed(&self) -> Vec<String> {
+        self.changed.clone()
+    }
+
+    fn get(&self, key: &str) -> PyResult<Option<Py<PyAny>>> {
+        self.params.get(key).map(to_python).transpose()
+    }
+
+    fn __str__(&self) -> String {
+        format!("{:#?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
     }
+}
+
+/// 期权的标记价格与希腊值，由 `{symbol}@greeks` 或 `{asset}@mark` 推送
+#[derive(Debug, Deserialize, PartialEq)]
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct Greeks {
+    time: u64,
+    symbol: String,
+    stream: String,
+    mark_price: f64,
+    mark_iv: f64,
+    bid_iv: f64,
+    ask_iv: f64,
+    delta: f64,
+    gamma: f64,
+    theta: f64,
+    vega: f64,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    offset: Option<u64>,
+}
 
+#[gen_stub_pymethods]
+#[pymethods]
+impl Greeks {
     #[getter]
-    fn close(&self) -> f64 {
-        self.close
+    fn time(&self) -> u64 {
+        self.time
     }
 
     #[getter]
-    fn volume(&self) -> f64 {
-        self.volume
+    fn symbol(&self) -> &String {
+        &self.symbol
     }
 
     #[getter]
-    fn amount(&self) -> f64 {
-        self.amount
+    fn stream(&self) -> &String {
+        &self.stream
     }
 
     #[getter]
-    fn start_time(&self) -> u64 {
-        self.start_time
+    fn mark_price(&self) -> f64 {
+        self.mark_price
     }
 
+    /// 隐含波动率均为小数
     #[getter]
-    fn start_datetime(&self) -> String {
-        DateTime::from_timestamp_millis(self.start_time as i64)
-            .unwrap()
-            .with_timezone(&Shanghai)
-            .to_string()
+    fn mark_iv(&self) -> f64 {
+        self.mark_iv
     }
 
     #[getter]
-    fn interval(&self) -> &String {
-        &self.interval
+    fn bid_iv(&self) -> f64 {
+        self.bid_iv
     }
 
     #[getter]
-    fn first_trade_id(&self) -> i64 {
-        self.first_trade_id
+    fn ask_iv(&self) -> f64 {
+        self.ask_iv
     }
 
     #[getter]
-    fn last_trade_id(&self) -> i64 {
-        self.last_trade_id
+    fn delta(&self) -> f64 {
+        self.delta
     }
 
     #[getter]
-    fn trade_count(&self) -> i64 {
-        self.trade_count
+    fn gamma(&self) -> f64 {
+        self.gamma
     }
 
     #[getter]
-    fn is_closed(&self) -> bool {
-        self.is_closed
+    fn theta(&self) -> f64 {
+        self.theta
     }
 
     #[getter]
-    fn buy_volume(&self) -> f64 {
-        self.buy_volume
+    fn vega(&self) -> f64 {
+        self.vega
     }
 
     #[getter]
-    fn buy_amount(&self) -> f64 {
-        self.buy_amount
+    fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    #[getter]
+    fn offset(&self) -> Option<u64> {
+        self.offset
     }
 
     fn __str__(&self) -> String {
@@ -323,6 +1639,20 @@ impl Product {
         }
     }
 
+    /// 期权要素，现货与合约为空
+    pub fn option(&self) -> Option<&OptionContract> {
+        match self {
+            Product::Binance(b) => b.option.as_ref(),
+        }
+    }
+
+    /// 杠杆分层，只有 U 本位合约有
+    pub fn leverage_brackets(&self) -> &[Bracket] {
+        match self {
+            Product::Binance(b) => &b.leverageBrackets,
+        }
+    }
+
     pub fn order_support(&self, order_type: &OrderType) -> bool {
         match self {
             Product::Binance(b) => b.orderTypes.contains(&order_type.to_string()), // Product::Okx(o) => { /* OKX 实现 */ }
@@ -365,11 +1695,33 @@ pub enum Message {
     Success(Success),
     Login(SLoginResponse),
     Error(ErrorResponse),
+    SymbolChanged(SymbolChanged),
+    TradingStatus(TradingStatus),
+    VenueStatus(VenueStatus),
+    OrderCap(OrderCap),
     Depth(Depth),
     Kline(Kline),
+    BarClose(BarClose),
+    Derived(Derived),
+    TradeFlow(TradeFlow),
+    Volatility(Volatility),
+    VolatilityRsp(Response<Volatility>),
+    Basis(Basis),
+    BasisRsp(Response<Basis>),
+    Params(Params),
+    ParamsRsp(Response<Params>),
+    Greeks(Greeks),
     Order(Order),
+    Fill(Fill),
+    MyTrades(Response<MyTrades>),
+    DepthSnapshot(Response<DepthSnapshot>),
     Products(Products),
+    /// 放在 Products 之后，空列表按合约列表处理
+    Subscribed(SSubscribeResponse),
     Positions(Response<PositionRsp>),
+    Delivery(Delivery),
+    Roll(Roll),
+    RollRsp(Response<RollResult>),
     Position(Position),
     Close,
 }
@@ -381,8 +1733,25 @@ pub enum EventType {
     Login,
     Depth,
     Kline,
+    BarClose,
+    Derived,
+    TradeFlow,
+    Volatility,
+    Basis,
+    Params,
+    Greeks,
     Order,
+    Fill,
+    MyTrades,
+    DepthSnapshot,
     Position,
+    Delivery,
+    Roll,
+    RollResult,
+    SymbolChanged,
+    TradingStatus,
+    VenueStatus,
+    OrderCap,
 }
 
 #[derive(Debug)]
@@ -394,6 +1763,7 @@ pub struct Event {
 }
 
 impl Event {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<T: for<'a> IntoPyObject<'a>>(event_type: EventType, data: T) -> Py<PyAny> {
         Python::attach(|py| {
             Self {
@@ -410,12 +1780,12 @@ impl Event {
 #[pymethods]
 impl Event {
     #[getter]
-    fn event_type(&self) -> EventType {
+    pub(crate) fn event_type(&self) -> EventType {
         self.event_type
     }
 
     #[getter]
-    fn data(&self) -> &Py<PyAny> {
+    pub(crate) fn data(&self) -> &Py<PyAny> {
         &self.data
     }
 
@@ -441,17 +1811,25 @@ pub struct Order {
     price: f64,
     #[allow(unused)]
     order_id: i64,
-    internal_id: u8,
+    internal_id: u32,
     trade_time: i64,
     trade_price: f64,
     trade_quantity: f64,
     acc: f64,
     making: Option<bool>,
+    /// 下单时指定的持仓方向，网关的回报不带这个字段
+    #[serde(default)]
+    position_side: Option<PositionSide>,
+    /// 下单时指定的策略与标签，网关的回报不带这两个字段
+    #[serde(default)]
+    strategy: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 impl Order {
     pub fn new(
-        id: u8,
+        id: u32,
         symbol: &str,
         price: f64,
         quantity: f64,
@@ -474,11 +1852,78 @@ impl Order {
             trade_quantity: 0.0,
             acc: 0.0,
             making: None,
+            position_side: None,
+            strategy: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// 改单沿用原订单的交易对、方向与类型，数量缺省为未成交部分
+    pub(crate) fn replace_request(
+        &self,
+        session_id: u16,
+        price: f64,
+        quantity: Option<f64>,
+    ) -> OrderRequest {
+        OrderRequest {
+            id: self.internal_id,
+            symbol: self.symbol.clone(),
+            price,
+            quantity: quantity.unwrap_or(self.quantity - self.acc),
+            side: self.side,
+            order_type: self.order_type,
+            tif: self.tif,
+            session_id,
+            idempotency_key: None,
+            ttl_ms: None,
+            peg: None,
+            position_side: self.position_side,
+            reprice_passive: false,
+            force: false,
+            strategy: self.strategy.clone(),
+            tags: self.tags.clone(),
         }
     }
 
+    /// 回测改单后更新价格与数量
+    pub(crate) fn on_replace(&mut self, price: f64, quantity: f64) {
+        self.price = price;
+        self.quantity = quantity;
+    }
+
+    pub fn with_position_side(mut self, position_side: Option<PositionSide>) -> Self {
+        self.position_side = position_side;
+        self
+    }
+
+    pub fn with_tags(mut self, strategy: Option<String>, tags: Vec<String>) -> Self {
+        self.strategy = strategy;
+        self.tags = tags;
+        self
+    }
+
     pub fn on_update(&mut self, other: Self) {
-        *self = other
+        let position_side = self.position_side;
+        let strategy = self.strategy.take();
+        let tags = std::mem::take(&mut self.tags);
+        *self = Self {
+            position_side: other.position_side.or(position_side),
+            strategy,
+            tags,
+            ..other
+        }
+    }
+
+    /// 回测时由撮合回报更新订单
+    pub(crate) fn on_report(&mut self, report: &Report) {
+        self.state = report.state;
+        self.trade_time = report.time;
+        self.trade_price = report.trade_price;
+        self.trade_quantity = report.trade_quantity;
+        self.acc = report.acc;
+        if report.trade_quantity > 0.0 {
+            self.making = Some(report.making);
+        }
     }
 }
 
@@ -514,7 +1959,7 @@ impl Order {
         self.price
     }
     #[getter]
-    pub fn id(&self) -> u8 {
+    pub fn id(&self) -> u32 {
         self.internal_id
     }
     #[getter]
@@ -523,7 +1968,7 @@ impl Order {
     }
     #[getter]
     fn trade_dt(&self) -> String {
-        DateTime::from_timestamp_millis(self.trade_time as i64)
+        DateTime::from_timestamp_millis(self.trade_time)
             .unwrap()
             .with_timezone(&Shanghai)
             .to_string()
@@ -546,6 +1991,21 @@ impl Order {
         self.making
     }
 
+    #[getter]
+    fn position_side(&self) -> Option<PositionSide> {
+        self.position_side
+    }
+
+    #[getter]
+    fn strategy(&self) -> Option<String> {
+        self.strategy.clone()
+    }
+
+    #[getter]
+    fn tags(&self) -> Vec<String> {
+        self.tags.clone()
+    }
+
     #[getter]
     pub fn is_active(&self) -> bool {
         matches!(self.state, State::NEW | State::PARTIALLY_FILLED)
@@ -562,7 +2022,7 @@ impl Order {
 
 #[derive(Debug, Serialize)]
 pub struct OrderRequest {
-    pub id: u8,
+    pub id: u32,
     pub symbol: String,
     pub price: f64,
     pub quantity: f64,
@@ -570,6 +2030,43 @@ pub struct OrderRequest {
     pub order_type: OrderType,
     pub tif: Tif,
     pub session_id: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peg: Option<PegRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_side: Option<PositionSide>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reprice_passive: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub force: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// 钉住订单，价格由网关按最优买卖价维护
+#[derive(Debug, Serialize)]
+pub struct PegRequest {
+    pub offset_ticks: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteLevel {
+    pub id: u32,
+    pub price: f64,
+    pub quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteRequest {
+    pub symbol: String,
+    pub session_id: u16,
+    pub bid: Option<QuoteLevel>,
+    pub ask: Option<QuoteLevel>,
 }
 
 #[derive(Debug, Serialize)]
@@ -577,14 +2074,84 @@ pub struct CancelRequest {
     pub symbol: String,
     pub session_id: u16,
     pub order_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// `side` 等字段只有合约网关推送，现货为 None
+#[derive(Debug, Deserialize, Clone, Default)]
 #[gen_stub_pyclass]
 #[pyclass]
 pub struct Position {
     pub symbol: String,
     pub net: f64,
+    #[serde(default)]
+    pub side: Option<PositionSide>,
+    #[serde(default)]
+    pub avg_price: Option<f64>,
+    #[serde(default)]
+    pub cost_basis: Option<f64>,
+    #[serde(default)]
+    pub entry_price: Option<f64>,
+    #[serde(default)]
+    pub unrealized_pnl: Option<f64>,
+    #[serde(default)]
+    pub margin_type: Option<String>,
+    #[serde(default)]
+    pub leverage: Option<u16>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Position {
+    #[getter]
+    fn symbol(&self) -> &String {
+        &self.symbol
+    }
+    #[getter]
+    fn net(&self) -> f64 {
+        self.net
+    }
+    /// 双向持仓时为 LONG 或 SHORT，`net` 是这一腿的持仓
+    #[getter]
+    fn side(&self) -> Option<PositionSide> {
+        self.side
+    }
+    /// 网关按本会话的成交计算的开仓均价，已计入手续费，成本未知时为 None
+    #[getter]
+    fn avg_price(&self) -> Option<f64> {
+        self.avg_price
+    }
+    /// 多头为买入花费、空头为卖出收到的计价资产
+    #[getter]
+    fn cost_basis(&self) -> Option<f64> {
+        self.cost_basis
+    }
+    /// 按 `avg_price` 与给定价格估算的未实现盈亏
+    fn unrealized(&self, price: f64) -> Option<f64> {
+        self.avg_price.map(|avg| (price - avg) * self.net)
+    }
+    /// 交易所账户级别的入场价，多个会话交易同一个品种时与 `avg_price` 不同
+    #[getter]
+    fn entry_price(&self) -> Option<f64> {
+        self.entry_price
+    }
+    #[getter]
+    fn unrealized_pnl(&self) -> Option<f64> {
+        self.unrealized_pnl
+    }
+    /// CROSSED 或 ISOLATED
+    #[getter]
+    fn margin_type(&self) -> Option<String> {
+        self.margin_type.clone()
+    }
+    #[getter]
+    fn leverage(&self) -> Option<u16> {
+        self.leverage
+    }
+    fn __repr__(&self) -> String {
+        format!("{:#?}", self)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -648,7 +2215,7 @@ impl PremiumIndex {
     }
     #[getter]
     fn next_funding_dt(&self) -> String {
-        DateTime::from_timestamp_millis(self.nextFundingTime as i64)
+        DateTime::from_timestamp_millis(self.nextFundingTime)
             .unwrap()
             .with_timezone(&Shanghai)
             .to_string()
@@ -1,3 +1,4 @@
+use cryptoflow::chat;
 use pyo3::prelude::*;
 use pyo3_stub_gen::derive::gen_stub_pyclass_enum;
 use serde::{Deserialize, Serialize};
@@ -29,6 +30,7 @@ pub enum Side {
 #[gen_stub_pyclass_enum]
 #[pyclass(eq)]
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(from = "chat::OrderType", into = "chat::OrderType")]
 #[allow(non_camel_case_types)]
 pub enum OrderType {
     #[doc = "Limit order"]
@@ -77,6 +79,7 @@ impl Display for OrderType {
 #[gen_stub_pyclass_enum]
 #[pyclass(eq)]
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(from = "chat::TimeInForce", into = "chat::TimeInForce")]
 pub enum Tif {
     #[doc = "Good till cancel"]
     GTC,
@@ -84,7 +87,7 @@ pub enum Tif {
     IOC,
     #[doc = "Fill or kill"]
     FOK,
-    #[doc = "Good till date"]
+    #[doc = "Post only"]
     GTX,
     #[doc = "Good till date"]
     GTD,
@@ -92,6 +95,103 @@ pub enum Tif {
     UNDEF,
 }
 
+#[gen_stub_pyclass_enum]
+#[pyclass(eq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(from = "chat::PositionSide", into = "chat::PositionSide")]
+#[doc = "The position side of a futures order or position"]
+pub enum PositionSide {
+    #[doc = "One-way mode"]
+    BOTH,
+    #[doc = "Long leg in hedge mode"]
+    LONG,
+    #[doc = "Short leg in hedge mode"]
+    SHORT,
+}
+
+impl From<chat::PositionSide> for PositionSide {
+    fn from(value: chat::PositionSide) -> Self {
+        match value {
+            chat::PositionSide::BOTH => Self::BOTH,
+            chat::PositionSide::LONG => Self::LONG,
+            chat::PositionSide::SHORT => Self::SHORT,
+        }
+    }
+}
+
+impl From<PositionSide> for chat::PositionSide {
+    fn from(value: PositionSide) -> Self {
+        match value {
+            PositionSide::BOTH => Self::BOTH,
+            PositionSide::LONG => Self::LONG,
+            PositionSide::SHORT => Self::SHORT,
+        }
+    }
+}
+
+/// 与网关的订单类型、有效方式互相转换；序列化也经由网关的枚举，两边接受的写法一致
+impl From<chat::OrderType> for OrderType {
+    fn from(value: chat::OrderType) -> Self {
+        match value {
+            chat::OrderType::LIMIT => Self::LIMIT,
+            chat::OrderType::LIMIT_MAKER => Self::LIMIT_MAKER,
+            chat::OrderType::MARKET => Self::MARKET,
+            chat::OrderType::STOP => Self::STOP,
+            chat::OrderType::STOP_MARKET => Self::STOP_MARKET,
+            chat::OrderType::STOP_LOSS => Self::STOP_LOSS,
+            chat::OrderType::STOP_LOSS_LIMIT => Self::STOP_LOSS_LIMIT,
+            chat::OrderType::TAKE_PROFIT => Self::TAKE_PROFIT,
+            chat::OrderType::TAKE_PROFIT_LIMIT => Self::TAKE_PROFIT_LIMIT,
+            chat::OrderType::TAKE_PROFIT_MARKET => Self::TAKE_PROFIT_MARKET,
+            chat::OrderType::TRAILING_STOP_MARKET => Self::TRAILING_STOP_MARKET,
+        }
+    }
+}
+
+impl From<OrderType> for chat::OrderType {
+    fn from(value: OrderType) -> Self {
+        match value {
+            OrderType::LIMIT => Self::LIMIT,
+            OrderType::LIMIT_MAKER => Self::LIMIT_MAKER,
+            OrderType::MARKET => Self::MARKET,
+            OrderType::STOP => Self::STOP,
+            OrderType::STOP_MARKET => Self::STOP_MARKET,
+            OrderType::STOP_LOSS => Self::STOP_LOSS,
+            OrderType::STOP_LOSS_LIMIT => Self::STOP_LOSS_LIMIT,
+            OrderType::TAKE_PROFIT => Self::TAKE_PROFIT,
+            OrderType::TAKE_PROFIT_LIMIT => Self::TAKE_PROFIT_LIMIT,
+            OrderType::TAKE_PROFIT_MARKET => Self::TAKE_PROFIT_MARKET,
+            OrderType::TRAILING_STOP_MARKET => Self::TRAILING_STOP_MARKET,
+        }
+    }
+}
+
+impl From<chat::TimeInForce> for Tif {
+    fn from(value: chat::TimeInForce) -> Self {
+        match value {
+            chat::TimeInForce::GTC => Self::GTC,
+            chat::TimeInForce::IOC => Self::IOC,
+            chat::TimeInForce::FOK => Self::FOK,
+            chat::TimeInForce::GTX => Self::GTX,
+            chat::TimeInForce::GTD => Self::GTD,
+        }
+    }
+}
+
+/// UNDEF 按交易所的默认值 GTC 发送
+impl From<Tif> for chat::TimeInForce {
+    fn from(value: Tif) -> Self {
+        match value {
+            Tif::GTC => Self::GTC,
+            Tif::IOC => Self::IOC,
+            Tif::FOK => Self::FOK,
+            Tif::GTX => Self::GTX,
+            Tif::GTD => Self::GTD,
+            Tif::UNDEF => Self::GTC,
+        }
+    }
+}
+
 #[gen_stub_pyclass_enum]
 #[pyclass(eq)]
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -105,5 +205,8 @@ pub enum State {
     EXPIRED,
     #[allow(non_camel_case_types)]
     EXPIRED_IN_MATCH,
+    /// 超过 ttl_ms 未完成，由网关撤销
+    #[allow(non_camel_case_types)]
+    EXPIRED_BY_GATEWAY,
     UNDEF,
 }
@@ -1,37 +1,98 @@
+pub mod backtest;
 pub mod chat;
 pub mod constant;
+pub mod matching;
+pub mod metrics;
 pub mod phase;
+pub mod poll;
+pub mod research;
 pub mod rest;
 pub mod session;
+pub mod strategy;
 pub mod subscription;
+pub mod sweep;
 pub mod ws;
 
+use backtest::{BacktestReport, BacktestSession};
 use chat::*;
 use constant::*;
 use phase::TradingPhase;
 use pyo3::prelude::*;
 use pyo3_stub_gen::define_stub_info_gatherer;
+use research::ResearchSession;
 use rest::*;
 use session::*;
+use strategy::Strategy;
 use subscription::Subscription;
+use sweep::walk_forward_windows;
 
 define_stub_info_gatherer!(stub_info);
 
+/// 与生成结果不一致的存根文件
+pub fn stale_stubs(stub: &pyo3_stub_gen::StubInfo) -> Vec<std::path::PathBuf> {
+    stub.modules
+        .iter()
+        .map(|(name, module)| {
+            let path = name.replace('-', "_").replace('.', "/");
+            (stub.python_root.join(format!("{}.pyi", path)), module)
+        })
+        .filter(|(path, module)| {
+            std::fs::read_to_string(path).ok() != Some(module.to_string())
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
 #[pymodule]
 fn pyalgo(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Kline>()?;
+    m.add_class::<BarClose>()?;
+    m.add_class::<Derived>()?;
+    m.add_class::<TradeFlow>()?;
+    m.add_class::<Volatility>()?;
+    m.add_class::<Basis>()?;
+    m.add_class::<Delivery>()?;
+    m.add_class::<Roll>()?;
+    m.add_class::<RollResult>()?;
+    m.add_class::<SymbolChanged>()?;
+    m.add_class::<TradingStatus>()?;
+    m.add_class::<VenueStatus>()?;
+    m.add_class::<OrderCap>()?;
+    m.add_class::<Params>()?;
+    m.add_class::<Greeks>()?;
     m.add_class::<Depth>()?;
+    m.add_class::<DepthSnapshot>()?;
     m.add_class::<Order>()?;
+    m.add_class::<Fill>()?;
+    m.add_class::<MyTrade>()?;
+    m.add_class::<MyTrades>()?;
     m.add_class::<Rest>()?;
     m.add_class::<Session>()?;
+    m.add_class::<BacktestSession>()?;
+    m.add_class::<ResearchSession>()?;
+    m.add_class::<BacktestReport>()?;
     m.add_class::<TradingPhase>()?;
     m.add_class::<Phase>()?;
     m.add_class::<Side>()?;
     m.add_class::<OrderType>()?;
     m.add_class::<Tif>()?;
+    m.add_class::<PositionSide>()?;
     m.add_class::<State>()?;
     m.add_class::<EventType>()?;
     m.add_class::<Event>()?;
     m.add_class::<Subscription>()?;
+    m.add_class::<Strategy>()?;
+    m.add_function(wrap_pyfunction!(walk_forward_windows, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stubs_up_to_date() {
+        let stub = stub_info().unwrap();
+        assert_eq!(stale_stubs(&stub), Vec::<std::path::PathBuf>::new());
+    }
+}
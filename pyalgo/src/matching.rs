@@ -0,0 +1,767 @@
+//! 纸面撮合引擎
+//!
+//! 回测时代替交易所：按行情（K线或盘口）撮合模拟订单，产生成交回报，
+//! 并维护每个交易对的持仓、均价、已实现/未实现盈亏和手续费。
+//!
+//! 成交模型见 [`FillConfig`]，随机部分使用固定种子，相同数据与参数的回测结果可复现。
+
+use crate::constant::{OrderType, Side, State, Tif};
+use std::collections::{BTreeMap, HashMap};
+
+/// 某个交易对最新的行情
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quote {
+    Bar {
+        open: f64,
+        high: f64,
+        low: f64,
+        close: f64,
+        volume: f64,
+    },
+    Book {
+        bid: f64,
+        bid_qty: f64,
+        ask: f64,
+        ask_qty: f64,
+    },
+}
+
+impl Quote {
+    /// 主动成交价：买单吃卖一，卖单吃买一；K线以收盘价近似
+    fn taker_price(&self, side: Side) -> f64 {
+        match (self, side) {
+            (Quote::Bar { close, .. }, _) => *close,
+            (Quote::Book { ask, .. }, Side::BUY) => *ask,
+            (Quote::Book { bid, .. }, Side::SELL) => *bid,
+        }
+    }
+
+    /// 限价单在这份行情下能否成交
+    fn crosses(&self, side: Side, price: f64) -> bool {
+        match (self, side) {
+            (Quote::Bar { low, .. }, Side::BUY) => *low <= price,
+            (Quote::Bar { high, .. }, Side::SELL) => *high >= price,
+            (Quote::Book { ask, .. }, Side::BUY) => *ask <= price,
+            (Quote::Book { bid, .. }, Side::SELL) => *bid >= price,
+        }
+    }
+
+    /// 价格越过限价，挂单无论排在哪里都已成交
+    fn through(&self, side: Side, price: f64) -> bool {
+        match (self, side) {
+            (Quote::Bar { low, .. }, Side::BUY) => *low < price,
+            (Quote::Bar { high, .. }, Side::SELL) => *high > price,
+            _ => self.crosses(side, price),
+        }
+    }
+
+    /// 同方向最优价及挂量，K线没有挂量
+    fn level(&self, side: Side) -> Option<(f64, f64)> {
+        match (self, side) {
+            (Quote::Book { bid, bid_qty, .. }, Side::BUY) => Some((*bid, *bid_qty)),
+            (Quote::Book { ask, ask_qty, .. }, Side::SELL) => Some((*ask, *ask_qty)),
+            _ => None,
+        }
+    }
+
+    /// 用于盯市的价格
+    pub fn mark(&self) -> f64 {
+        match self {
+            Quote::Bar { close, .. } => *close,
+            Quote::Book { bid, ask, .. } => (bid + ask) / 2.0,
+        }
+    }
+}
+
+/// 挂单的被动成交方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FillModel {
+    /// 价格触及限价即全部成交
+    #[default]
+    Touch,
+    /// 按排队位置成交：价格越过限价时全部成交；只触及限价时，需先消耗排在前面的挂量。
+    /// 盘口行情以同价位挂量的减少作为成交量，K线行情随机取 K 线成交量的一部分
+    Queue,
+}
+
+impl std::str::FromStr for FillModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "touch" => Ok(Self::Touch),
+            "queue" => Ok(Self::Queue),
+            _ => anyhow::bail!("Unknown fill model {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FillConfig {
+    pub model: FillModel,
+    /// 每根 K 线最多成交其成交量的比例，0 为不限制；盘口行情不限制
+    pub participation: f64,
+    /// 吃单滑点曲线，(下单数量, 滑点 bps) 按数量升序，点之间线性插值，
+    /// 第一个点之前从 (0, 0) 插值，最后一个点之后保持不变
+    pub slippage: Vec<(f64, f64)>,
+    pub seed: u64,
+}
+
+impl FillConfig {
+    fn slippage_bps(&self, quantity: f64) -> f64 {
+        let mut prev = (0.0, 0.0);
+        for &(qty, bps) in self.slippage.iter() {
+            if quantity <= qty {
+                if qty <= prev.0 {
+                    return bps;
+                }
+                return prev.1 + (bps - prev.1) * (quantity - prev.0) / (qty - prev.0);
+            }
+            prev = (qty, bps);
+        }
+        prev.1
+    }
+}
+
+/// splitmix64，保证同一种子的回测结果一致
+#[derive(Debug, Default)]
+struct Rng(u64);
+
+impl Rng {
+    /// [0, 1) 均匀分布
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimOrder {
+    pub id: u32,
+    pub symbol: String,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub tif: Tif,
+    pub price: f64,
+    pub quantity: f64,
+}
+
+impl SimOrder {
+    /// 只挂单不吃单
+    fn post_only(&self) -> bool {
+        self.order_type == OrderType::LIMIT_MAKER || self.tif == Tif::GTX
+    }
+}
+
+/// 挂单及其排队状态
+#[derive(Debug)]
+struct Resting {
+    order: SimOrder,
+    filled: f64,
+    /// 排在前面的挂量，未知时为 None，首次触价时确定
+    ahead: Option<f64>,
+}
+
+/// 订单回报
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub id: u32,
+    pub symbol: String,
+    pub state: State,
+    pub time: i64,
+    pub trade_price: f64,
+    pub trade_quantity: f64,
+    pub acc: f64,
+    pub making: bool,
+    pub fee: f64,
+}
+
+impl Report {
+    fn new(order: &SimOrder, state: State, time: i64) -> Self {
+        Self {
+            id: order.id,
+            symbol: order.symbol.clone(),
+            state,
+            time,
+            trade_price: 0.0,
+            trade_quantity: 0.0,
+            acc: 0.0,
+            making: false,
+            fee: 0.0,
+        }
+    }
+}
+
+/// 单个交易对的持仓与盈亏
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Book {
+    pub net: f64,
+    pub avg_price: f64,
+    pub realized: f64,
+    pub fees: f64,
+    pub volume: f64,
+    pub trades: u64,
+    pub mark: f64,
+}
+
+impl Book {
+    pub fn unrealized(&self) -> f64 {
+        (self.mark - self.avg_price) * self.net
+    }
+
+    fn on_fill(&mut self, side: Side, price: f64, quantity: f64, fee: f64) {
+        let signed = match side {
+            Side::BUY => quantity,
+            Side::SELL => -quantity,
+        };
+
+        if self.net == 0.0 || self.net.signum() == signed.signum() {
+            // 开仓或加仓，更新均价
+            let net = self.net + signed;
+            self.avg_price = (self.avg_price * self.net.abs() + price * quantity) / net.abs();
+            self.net = net;
+        } else {
+            // 平仓，超出部分反向开仓
+            let closed = quantity.min(self.net.abs());
+            self.realized += (price - self.avg_price) * closed * self.net.signum();
+            self.net += signed;
+            if self.net.abs() < 1e-12 {
+                self.net = 0.0;
+                self.avg_price = 0.0;
+            } else if closed < quantity {
+                self.avg_price = price;
+            }
+        }
+
+        self.fees += fee;
+        self.volume += price * quantity;
+        self.trades += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MatchingEngine {
+    maker_fee: f64,
+    taker_fee: f64,
+    fill: FillConfig,
+    rng: Rng,
+    /// 挂单，按下单顺序撮合
+    orders: BTreeMap<u32, Resting>,
+    quotes: HashMap<String, Quote>,
+    books: HashMap<String, Book>,
+}
+
+impl MatchingEngine {
+    pub fn new(maker_fee: f64, taker_fee: f64) -> Self {
+        Self {
+            maker_fee,
+            taker_fee,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_fill(mut self, fill: FillConfig) -> Self {
+        self.rng = Rng(fill.seed);
+        self.fill = fill;
+        self
+    }
+
+    pub fn books(&self) -> &HashMap<String, Book> {
+        &self.books
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&Book> {
+        self.books.get(symbol)
+    }
+
+    pub fn open_orders(&self) -> usize {
+        self.orders.len()
+    }
+
+    fn fill(
+        &mut self,
+        order: &SimOrder,
+        price: f64,
+        quantity: f64,
+        acc: f64,
+        making: bool,
+        time: i64,
+    ) -> Report {
+        let fee_rate = if making {
+            self.maker_fee
+        } else {
+            self.taker_fee
+        };
+        let fee = price * quantity * fee_rate;
+
+        let book = self.books.entry(order.symbol.clone()).or_default();
+        book.on_fill(order.side, price, quantity, fee);
+        if let Some(quote) = self.quotes.get(&order.symbol) {
+            book.mark = quote.mark();
+        }
+
+        let state = if acc >= order.quantity - 1e-12 {
+            State::FILLED
+        } else {
+            State::PARTIALLY_FILLED
+        };
+        Report {
+            trade_price: price,
+            trade_quantity: quantity,
+            acc,
+            making,
+            fee,
+            ..Report::new(order, state, time)
+        }
+    }
+
+    /// 下单，可能立即成交
+    pub fn submit(&mut self, order: SimOrder, time: i64) -> Vec<Report> {
+        if order.quantity <= 0.0 || self.orders.contains_key(&order.id) {
+            return vec![Report::new(&order, State::REJECTED, time)];
+        }
+        let Some(quote) = self.quotes.get(&order.symbol).copied() else {
+            // 还没有行情，无法判断成交价
+            return vec![Report::new(&order, State::REJECTED, time)];
+        };
+
+        let marketable =
+            order.order_type == OrderType::MARKET || quote.crosses(order.side, order.price);
+
+        if !marketable {
+            return match order.tif {
+                Tif::IOC | Tif::FOK => vec![Report::new(&order, State::EXPIRED, time)],
+                _ => {
+                    let report = Report::new(&order, State::NEW, time);
+                    // 与最优价同价则排在该价位已有挂量的随机位置，优于最优价则排在最前
+                    let ahead = quote.level(order.side).and_then(|(best, qty)| {
+                        if best == order.price {
+                            Some(qty * self.rng.next_f64())
+                        } else if order.side == Side::BUY && order.price > best
+                            || order.side == Side::SELL && order.price < best
+                        {
+                            Some(0.0)
+                        } else {
+                            None
+                        }
+                    });
+                    self.orders.insert(
+                        order.id,
+                        Resting {
+                            order,
+                            filled: 0.0,
+                            ahead,
+                        },
+                    );
+                    vec![report]
+                }
+            };
+        }
+
+        if order.post_only() {
+            // 与交易所一致：LIMIT_MAKER 被拒，GTX 过期
+            let state = match order.order_type {
+                OrderType::LIMIT_MAKER => State::REJECTED,
+                _ => State::EXPIRED,
+            };
+            return vec![Report::new(&order, state, time)];
+        }
+
+        let slippage = self.fill.slippage_bps(order.quantity) / 10000.0;
+        let taker = match order.side {
+            Side::BUY => quote.taker_price(order.side) * (1.0 + slippage),
+            Side::SELL => quote.taker_price(order.side) * (1.0 - slippage),
+        };
+        let price = match order.order_type {
+            OrderType::MARKET => taker,
+            // 限价单按更优的价格成交，滑点不超过限价
+            _ => match order.side {
+                Side::BUY => taker.min(order.price),
+                Side::SELL => taker.max(order.price),
+            },
+        };
+        vec![
+            Report::new(&order, State::NEW, time),
+            self.fill(&order, price, order.quantity, order.quantity, false, time),
+        ]
+    }
+
+    pub fn cancel(&mut self, id: u32, time: i64) -> Option<Report> {
+        self.orders.remove(&id).map(|resting| Report {
+            acc: resting.filled,
+            ..Report::new(&resting.order, State::CANCELED, time)
+        })
+    }
+
+    /// 这份行情下挂单可成交的数量
+    fn available(&mut self, id: u32, quote: &Quote, prev: Option<&Quote>) -> f64 {
+        let model = self.fill.model;
+        let Some(resting) = self.orders.get_mut(&id) else {
+            return 0.0;
+        };
+        let (side, price) = (resting.order.side, resting.order.price);
+        let remaining = resting.order.quantity - resting.filled;
+
+        match model {
+            FillModel::Touch if quote.crosses(side, price) => remaining,
+            FillModel::Touch => 0.0,
+            FillModel::Queue if quote.through(side, price) => remaining,
+            FillModel::Queue => {
+                // 同价位成交量
+                let traded = match (quote, quote.level(side)) {
+                    (
+                        Quote::Bar {
+                            low, high, volume, ..
+                        },
+                        _,
+                    ) => {
+                        let touched = match side {
+                            Side::BUY => *low == price,
+                            Side::SELL => *high == price,
+                        };
+                        if !touched {
+                            return 0.0;
+                        }
+                        let ahead = resting
+                            .ahead
+                            .get_or_insert_with(|| volume * self.rng.next_f64());
+                        let traded = volume * self.rng.next_f64();
+                        *ahead -= traded;
+                        return if *ahead < 0.0 {
+                            let available = -*ahead;
+                            *ahead = 0.0;
+                            available.min(remaining)
+                        } else {
+                            0.0
+                        };
+                    }
+                    (_, Some((best, qty))) if best == price => {
+                        if resting.ahead.is_none() {
+                            // 价位刚变成最优价，排在已有挂量之后的随机位置
+                            resting.ahead = Some(qty * self.rng.next_f64());
+                            return 0.0;
+                        }
+                        match prev.and_then(|p| p.level(side)) {
+                            Some((prev_best, prev_qty)) if prev_best == price => {
+                                (prev_qty - qty).max(0.0)
+                            }
+                            _ => 0.0,
+                        }
+                    }
+                    _ => return 0.0,
+                };
+
+                let ahead = resting.ahead.get_or_insert(0.0);
+                *ahead -= traded;
+                if *ahead < 0.0 {
+                    let available = -*ahead;
+                    *ahead = 0.0;
+                    available.min(remaining)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// 新行情到达时撮合挂单，挂单按限价被动成交
+    pub fn on_market(&mut self, symbol: &str, quote: Quote, time: i64) -> Vec<Report> {
+        let prev = self.quotes.insert(symbol.to_string(), quote);
+        if let Some(book) = self.books.get_mut(symbol) {
+            book.mark = quote.mark();
+        }
+
+        // 所有挂单共享这根 K 线的可成交量
+        let mut capacity = match quote {
+            Quote::Bar { volume, .. } if self.fill.participation > 0.0 => {
+                volume * self.fill.participation
+            }
+            _ => f64::INFINITY,
+        };
+
+        let ids: Vec<u32> = self
+            .orders
+            .values()
+            .filter(|r| r.order.symbol == symbol)
+            .map(|r| r.order.id)
+            .collect();
+
+        let mut reports = Vec::new();
+        for id in ids {
+            let quantity = self.available(id, &quote, prev.as_ref()).min(capacity);
+            if quantity <= 0.0 {
+                continue;
+            }
+            capacity -= quantity;
+
+            let Some(resting) = self.orders.get_mut(&id) else {
+                continue;
+            };
+            resting.filled += quantity;
+            let (order, acc) = (resting.order.clone(), resting.filled);
+
+            let report = self.fill(&order, order.price, quantity, acc, true, time);
+            if report.state == State::FILLED {
+                self.orders.remove(&id);
+            }
+            reports.push(report);
+        }
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u32, side: Side, price: f64, tif: Tif) -> SimOrder {
+        SimOrder {
+            id,
+            symbol: "btcusdt".into(),
+            side,
+            order_type: OrderType::LIMIT,
+            tif,
+            price,
+            quantity: 1.0,
+        }
+    }
+
+    fn bar(low: f64, high: f64, close: f64) -> Quote {
+        Quote::Bar {
+            open: close,
+            high,
+            low,
+            close,
+            volume: 10.0,
+        }
+    }
+
+    fn book(bid: f64, bid_qty: f64, ask: f64, ask_qty: f64) -> Quote {
+        Quote::Book {
+            bid,
+            bid_qty,
+            ask,
+            ask_qty,
+        }
+    }
+
+    #[test]
+    fn test_reject_without_quote() {
+        let mut engine = MatchingEngine::default();
+        let reports = engine.submit(order(1, Side::BUY, 100.0, Tif::GTC), 0);
+        assert_eq!(reports[0].state, State::REJECTED);
+    }
+
+    #[test]
+    fn test_resting_order_fills_on_bar() {
+        let mut engine = MatchingEngine::new(0.001, 0.002);
+        engine.on_market("btcusdt", bar(100.0, 110.0, 105.0), 1);
+
+        let reports = engine.submit(order(1, Side::BUY, 99.0, Tif::GTC), 1);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].state, State::NEW);
+        assert_eq!(engine.open_orders(), 1);
+
+        // 没有触及限价
+        assert!(engine
+            .on_market("btcusdt", bar(99.5, 106.0, 100.0), 2)
+            .is_empty());
+
+        let reports = engine.on_market("btcusdt", bar(98.0, 101.0, 100.0), 3);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].state, State::FILLED);
+        assert_eq!(reports[0].trade_price, 99.0);
+        assert!(reports[0].making);
+        assert!((reports[0].fee - 0.099).abs() < 1e-9);
+        assert_eq!(engine.open_orders(), 0);
+
+        let book = engine.book("btcusdt").unwrap();
+        assert_eq!(book.net, 1.0);
+        assert_eq!(book.avg_price, 99.0);
+        assert_eq!(book.unrealized(), 1.0);
+    }
+
+    #[test]
+    fn test_marketable_orders() {
+        let mut engine = MatchingEngine::default();
+        engine.on_market("btcusdt", book(99.0, 1.0, 100.0, 1.0), 1);
+
+        // 限价买单穿过卖一，按卖一成交
+        let reports = engine.submit(order(1, Side::BUY, 101.0, Tif::GTC), 1);
+        assert_eq!(reports[1].state, State::FILLED);
+        assert_eq!(reports[1].trade_price, 100.0);
+        assert!(!reports[1].making);
+
+        // IOC 不能成交则过期
+        let reports = engine.submit(order(2, Side::BUY, 98.0, Tif::IOC), 1);
+        assert_eq!(reports[0].state, State::EXPIRED);
+
+        // GTX 会吃单则过期
+        let reports = engine.submit(order(3, Side::SELL, 98.0, Tif::GTX), 1);
+        assert_eq!(reports[0].state, State::EXPIRED);
+
+        let mut market = order(4, Side::SELL, 0.0, Tif::GTC);
+        market.order_type = OrderType::MARKET;
+        let reports = engine.submit(market, 1);
+        assert_eq!(reports[1].trade_price, 99.0);
+        assert_eq!(engine.book("btcusdt").unwrap().net, 0.0);
+        assert_eq!(engine.book("btcusdt").unwrap().realized, -1.0);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut engine = MatchingEngine::default();
+        engine.on_market("btcusdt", bar(100.0, 110.0, 105.0), 1);
+        engine.submit(order(1, Side::SELL, 120.0, Tif::GTC), 1);
+
+        assert_eq!(engine.cancel(1, 2).unwrap().state, State::CANCELED);
+        assert!(engine.cancel(1, 2).is_none());
+        assert!(engine
+            .on_market("btcusdt", bar(100.0, 130.0, 125.0), 3)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_book_pnl() {
+        let mut book = Book::default();
+        book.on_fill(Side::BUY, 100.0, 2.0, 0.0);
+        book.on_fill(Side::BUY, 110.0, 2.0, 0.0);
+        assert_eq!(book.avg_price, 105.0);
+
+        // 平掉 3 个，再反手 1 个
+        book.on_fill(Side::SELL, 120.0, 5.0, 0.0);
+        assert_eq!(book.realized, 60.0);
+        assert_eq!(book.net, -1.0);
+        assert_eq!(book.avg_price, 120.0);
+
+        book.mark = 110.0;
+        assert_eq!(book.unrealized(), 10.0);
+    }
+
+    #[test]
+    fn test_slippage_curve() {
+        let fill = FillConfig {
+            slippage: vec![(1.0, 10.0), (3.0, 30.0)],
+            ..Default::default()
+        };
+        assert_eq!(fill.slippage_bps(0.5), 5.0);
+        assert_eq!(fill.slippage_bps(2.0), 20.0);
+        assert_eq!(fill.slippage_bps(10.0), 30.0);
+        assert_eq!(FillConfig::default().slippage_bps(10.0), 0.0);
+
+        let mut engine = MatchingEngine::default().with_fill(fill);
+        engine.on_market("btcusdt", book(99.0, 1.0, 100.0, 1.0), 1);
+        let mut market = order(1, Side::BUY, 0.0, Tif::GTC);
+        market.order_type = OrderType::MARKET;
+        let reports = engine.submit(market, 1);
+        assert!((reports[1].trade_price - 100.1).abs() < 1e-9);
+
+        // 限价单的滑点不超过限价
+        let reports = engine.submit(order(2, Side::BUY, 100.05, Tif::GTC), 1);
+        assert_eq!(reports[1].trade_price, 100.05);
+    }
+
+    #[test]
+    fn test_participation_cap() {
+        let mut engine = MatchingEngine::default().with_fill(FillConfig {
+            participation: 0.1,
+            ..Default::default()
+        });
+        engine.on_market("btcusdt", bar(100.0, 110.0, 105.0), 1);
+        let mut big = order(1, Side::BUY, 99.0, Tif::GTC);
+        big.quantity = 1.5;
+        engine.submit(big, 1);
+
+        // 每根 K 线成交量 10，最多成交 1
+        let reports = engine.on_market("btcusdt", bar(98.0, 101.0, 100.0), 2);
+        assert_eq!(reports[0].state, State::PARTIALLY_FILLED);
+        assert_eq!(reports[0].trade_quantity, 1.0);
+
+        let reports = engine.on_market("btcusdt", bar(98.0, 101.0, 100.0), 3);
+        assert_eq!(reports[0].state, State::FILLED);
+        assert!((reports[0].trade_quantity - 0.5).abs() < 1e-12);
+        assert_eq!(reports[0].acc, 1.5);
+        assert_eq!(engine.book("btcusdt").unwrap().net, 1.5);
+    }
+
+    #[test]
+    fn test_queue_model_book() {
+        let fill = FillConfig {
+            model: FillModel::Queue,
+            seed: 7,
+            ..Default::default()
+        };
+        let mut engine = MatchingEngine::default().with_fill(fill);
+        engine.on_market("btcusdt", book(99.0, 10.0, 100.0, 5.0), 1);
+        engine.submit(order(1, Side::BUY, 99.0, Tif::GTC), 1);
+
+        // 同价位挂量只减少很少，仍在排队
+        assert!(engine
+            .on_market("btcusdt", book(99.0, 9.99, 100.0, 5.0), 2)
+            .is_empty());
+
+        // 整个价位被吃掉后成交
+        let reports = engine.on_market("btcusdt", book(99.0, 0.0, 100.0, 5.0), 3);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].state, State::FILLED);
+
+        // 卖价跌到限价，直接成交
+        engine.submit(order(2, Side::BUY, 98.0, Tif::GTC), 4);
+        let reports = engine.on_market("btcusdt", book(97.0, 1.0, 98.0, 1.0), 5);
+        assert_eq!(reports[0].state, State::FILLED);
+    }
+
+    #[test]
+    fn test_queue_model_is_deterministic() {
+        let run = |seed| {
+            let mut engine = MatchingEngine::default().with_fill(FillConfig {
+                model: FillModel::Queue,
+                seed,
+                ..Default::default()
+            });
+            engine.on_market("btcusdt", bar(100.0, 110.0, 105.0), 0);
+            let mut big = order(1, Side::BUY, 99.0, Tif::GTC);
+            big.quantity = 100.0;
+            engine.submit(big, 0);
+
+            (1..20)
+                .flat_map(|t| engine.on_market("btcusdt", bar(99.0, 101.0, 100.0), t))
+                .map(|r| r.trade_quantity)
+                .collect::<Vec<_>>()
+        };
+
+        let fills = run(1);
+        assert!(!fills.is_empty());
+        // 只触及限价时按排队成交，不会整单成交
+        assert!(fills.iter().all(|q| *q <= 10.0));
+        assert_eq!(fills, run(1));
+        assert_ne!(fills, run(2));
+    }
+
+    #[test]
+    fn test_more_than_256_orders() {
+        let mut engine = MatchingEngine::default();
+        engine.on_market("btcusdt", bar(100.0, 110.0, 105.0), 1);
+        for id in 0..300 {
+            let reports = engine.submit(order(id, Side::SELL, 120.0, Tif::GTC), 1);
+            assert_eq!(reports[0].id, id);
+            assert_eq!(reports[0].state, State::NEW);
+        }
+        assert_eq!(engine.open_orders(), 300);
+
+        // 重复的订单号被拒，不影响挂着的同号订单
+        let reports = engine.submit(order(299, Side::SELL, 120.0, Tif::GTC), 1);
+        assert_eq!(reports[0].state, State::REJECTED);
+        assert_eq!(engine.open_orders(), 300);
+
+        // 299 与 43 在 u8 下同号，撤掉一个不影响另一个
+        assert_eq!(engine.cancel(299, 2).unwrap().state, State::CANCELED);
+        assert!(engine.cancel(299, 2).is_none());
+        assert_eq!(engine.cancel(43, 2).unwrap().id, 43);
+        assert_eq!(engine.open_orders(), 298);
+    }
+}
@@ -0,0 +1,89 @@
+//! 回测绩效统计
+//!
+//! 权益 = 已实现 + 未实现 - 手续费。回撤按每个采样点计算，
+//! 夏普按 UTC 日末权益的日度变化计算（一年 365 天），不足两天时为 0。
+
+const DAY: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    pub pnl: f64,
+    pub max_drawdown: f64,
+    pub sharpe: f64,
+    pub trades: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EquityCurve {
+    last: f64,
+    peak: f64,
+    max_drawdown: f64,
+    /// 每个自然日最后一个采样点的权益
+    daily: Vec<(u64, f64)>,
+}
+
+impl EquityCurve {
+    pub fn push(&mut self, time: u64, equity: f64) {
+        self.last = equity;
+        self.peak = self.peak.max(equity);
+        self.max_drawdown = self.max_drawdown.max(self.peak - equity);
+
+        let day = time / DAY;
+        match self.daily.last_mut() {
+            Some((d, e)) if *d == day => *e = equity,
+            _ => self.daily.push((day, equity)),
+        }
+    }
+
+    pub fn sharpe(&self) -> f64 {
+        if self.daily.len() < 2 {
+            return 0.0;
+        }
+        let returns: Vec<f64> = self.daily.windows(2).map(|w| w[1].1 - w[0].1).collect();
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let var = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n;
+        if var <= 0.0 {
+            return 0.0;
+        }
+        mean / var.sqrt() * 365f64.sqrt()
+    }
+
+    pub fn metrics(&self, trades: u64) -> Metrics {
+        Metrics {
+            pnl: self.last,
+            max_drawdown: self.max_drawdown,
+            sharpe: self.sharpe(),
+            trades,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drawdown() {
+        let mut curve = EquityCurve::default();
+        for (i, equity) in [0.0, 10.0, 4.0, 12.0, 7.0, 15.0].into_iter().enumerate() {
+            curve.push(i as u64, equity);
+        }
+        let metrics = curve.metrics(3);
+        assert_eq!(metrics.pnl, 15.0);
+        assert_eq!(metrics.max_drawdown, 6.0);
+        // 同一天内没有日度收益
+        assert_eq!(metrics.sharpe, 0.0);
+    }
+
+    #[test]
+    fn test_sharpe() {
+        let mut curve = EquityCurve::default();
+        for (day, equity) in [0.0, 1.0, 3.0, 4.0, 6.0].into_iter().enumerate() {
+            curve.push(day as u64 * DAY, equity - 0.5);
+            curve.push(day as u64 * DAY + DAY - 1, equity);
+        }
+        // 日收益 1,2,1,2：均值 1.5，标准差 0.5
+        assert!((curve.sharpe() - 3.0 * 365f64.sqrt()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,128 @@
+//! 批量取事件
+//!
+//! 行情密集时逐个调用 `process` 每条消息都要在 Python 与 Rust 之间往返一次。`poll_events` 在释放 GIL
+//! 的情况下一次读取并解码多条消息，再一次生成所有事件；`poll_quotes` 另外把深度的最优一档按行写入调用方
+//! 预先分配的 numpy 数组，这些深度不再生成 Python 对象。
+
+use crate::chat::{Depth, Event, EventType, Message};
+use crate::ws::WebSocketClient;
+use pyo3::buffer::PyBuffer;
+use pyo3::prelude::*;
+use std::time::{Duration, Instant};
+
+/// 不指定 `max_n` 时一次最多取出的事件数
+pub const MAX_EVENTS: usize = 256;
+
+/// 每行为 [time, bid, bid_qty, ask, ask_qty]
+pub const QUOTE_COLUMNS: usize = 5;
+
+/// 没有消息时两次读取之间的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// 读取最多 `max_n` 条消息，一条也没有时最多等待 `timeout_ms` 毫秒，调用时不需要持有 GIL
+pub fn read(ws: &mut WebSocketClient, max_n: usize, timeout_ms: u64) -> Vec<Message> {
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut messages = Vec::new();
+    while messages.len() < max_n {
+        match ws.read() {
+            Some(Message::Close) => {
+                messages.push(Message::Close);
+                break;
+            }
+            Some(message) => messages.push(message),
+            None if ws.is_closed() => break,
+            None if messages.is_empty() && Instant::now() < deadline => {
+                std::thread::sleep(POLL_INTERVAL)
+            }
+            None => break,
+        }
+    }
+    messages
+}
+
+/// 调用方传入的 (n, 5) float64 数组，按顺序写入深度的最优一档
+pub struct QuoteBuffer {
+    buffer: PyBuffer<f64>,
+    rows: usize,
+    /// 已写入的各行的交易对
+    symbols: Vec<String>,
+}
+
+impl QuoteBuffer {
+    pub fn new(buffer: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let buffer = PyBuffer::<f64>::get(buffer)?;
+        if buffer.readonly()
+            || !buffer.is_c_contiguous()
+            || buffer.dimensions() != 2
+            || buffer.shape()[1] != QUOTE_COLUMNS
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "buffer must be a writable C-contiguous float64 array of shape (n, {})",
+                QUOTE_COLUMNS
+            )));
+        }
+        let rows = buffer.shape()[0];
+        Ok(Self {
+            buffer,
+            rows,
+            symbols: Vec::new(),
+        })
+    }
+
+    /// 写入一行，数组已写满或深度有一侧为空时返回 false
+    pub fn push(&mut self, py: Python<'_>, depth: &Depth) -> bool {
+        if self.symbols.len() >= self.rows {
+            return false;
+        }
+        let (Some(row), Some(cells)) = (depth.best(), self.buffer.as_mut_slice(py)) else {
+            return false;
+        };
+        let offset = self.symbols.len() * QUOTE_COLUMNS;
+        for (cell, value) in cells[offset..offset + QUOTE_COLUMNS].iter().zip(row) {
+            cell.set(value);
+        }
+        self.symbols.push(depth.symbol().clone());
+        true
+    }
+
+    /// 事件为深度并且写入了数组时返回 true
+    pub fn push_event(&mut self, py: Python<'_>, event: &Py<PyAny>) -> bool {
+        let Ok(event) = event.extract::<PyRef<Event>>(py) else {
+            return false;
+        };
+        if event.event_type() != EventType::Depth {
+            return false;
+        }
+        match event.data().extract::<PyRef<Depth>>(py) {
+            Ok(depth) => self.push(py, &depth),
+            Err(_) => false,
+        }
+    }
+
+    pub fn into_symbols(self) -> Vec<String> {
+        self.symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best() {
+        let depth: Depth = serde_json::from_str(
+            r#"{"time": 1, "symbol": "btcusdt", "stream": "btcusdt@depth5",
+            "bids": [{"price": 99.0, "quantity": 2.0}, {"price": 98.0, "quantity": 1.0}],
+            "asks": [{"price": 101.0, "quantity": 3.0}]}"#,
+        )
+        .unwrap();
+        assert_eq!(depth.best(), Some([1.0, 99.0, 2.0, 101.0, 3.0]));
+
+        let depth: Depth = serde_json::from_str(
+            r#"{"time": 1, "symbol": "btcusdt", "stream": "btcusdt@depth5", "bids": [],
+            "asks": [{"price": 101.0, "quantity": 3.0}]}"#,
+        )
+        .unwrap();
+        assert_eq!(depth.best(), None);
+    }
+}
@@ -0,0 +1,440 @@
+//! 研究用的同步会话
+//!
+//! `Session` 需要策略不停地调用 `process` 取事件，不适合在 notebook 里随手查看行情。`ResearchSession`
+//! 在后台线程中连接网关、登录（不交易）并维护订阅，收到的最新深度、K 线与持仓缓存在内存中，Python 随时
+//! 用 `last_depth`、`klines`、`ohlcv_df`、`position` 同步地读取快照。连接断开时后台线程自动重连并重新订阅。
+
+use crate::chat::{Depth, Kline, Message, Position, Product};
+use crate::ws::WebSocketClient;
+use crossbeam_channel::{Receiver, Sender};
+use cryptoflow::chat::{SLogin, SPositionReq, SRequest, SSubscription};
+use cryptoflow::codec::WireFormat;
+use cryptoflow::compat::PROTOCOL_VERSION;
+use log::*;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyDict};
+use pyo3_stub_gen::derive::{gen_stub_pyclass, gen_stub_pymethods};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// 每条 K 线流缺省保留的根数
+const MAX_BARS: usize = 1000;
+
+/// 连接到登录完成的最长时间
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 断线后重连的间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 没有消息时两次读取之间的间隔
+const IDLE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// 后台线程收到的最新数据
+#[derive(Debug, Default)]
+struct Cache {
+    /// symbol -> 最新的深度
+    depths: HashMap<String, Depth>,
+    /// 流 -> 按起始时间排列的 K 线，最后一根可能未完结
+    klines: HashMap<String, VecDeque<Kline>>,
+    positions: HashMap<String, Position>,
+    symbols: Vec<String>,
+    connected: bool,
+}
+
+impl Cache {
+    /// 同一根 K 线的推送覆盖之前的，超过 `max_bars` 时丢掉最早的
+    fn on_kline(&mut self, kline: Kline, max_bars: usize) {
+        let bars = self.klines.entry(kline.stream().clone()).or_default();
+        match bars.back_mut() {
+            Some(last) if last.start_time() == kline.start_time() => *last = kline,
+            Some(last) if last.start_time() > kline.start_time() => (),
+            _ => bars.push_back(kline),
+        }
+        while bars.len() > max_bars {
+            bars.pop_front();
+        }
+    }
+}
+
+enum Command {
+    Subscribe(SSubscription),
+    Stop,
+}
+
+/// 后台线程，独占连接
+struct Worker {
+    ws: WebSocketClient,
+    session_id: u16,
+    name: String,
+    tenant: Option<String>,
+    token: Option<String>,
+    cache: Arc<Mutex<Cache>>,
+    commands: Receiver<Command>,
+    max_bars: usize,
+    /// 重连后重新订阅
+    streams: Vec<SSubscription>,
+    id: i64,
+    login: bool,
+}
+
+impl Worker {
+    fn send<T: Debug + Serialize>(&mut self, method: &str, params: T) -> anyhow::Result<()> {
+        let req = SRequest {
+            id: self.id,
+            method: method.into(),
+            params,
+        };
+        debug!("research send {:?}", req);
+        self.ws.send(req)?;
+        self.id += 1;
+        Ok(())
+    }
+
+    /// 连接、取合约与持仓、登录，再重新订阅之前的流
+    fn connect(&mut self) -> anyhow::Result<()> {
+        self.login = false;
+        self.ws.connect()?;
+        self.ws.set_nonblocking(true)?;
+        self.send("get_products", Vec::<String>::new())?;
+        let deadline = Instant::now() + LOGIN_TIMEOUT;
+        while !self.login {
+            match self.ws.read() {
+                Some(msg) => self.on_message(msg)?,
+                None if self.ws.is_closed() => anyhow::bail!("connection closed before login"),
+                None if Instant::now() > deadline => anyhow::bail!("login timed out"),
+                None => std::thread::sleep(IDLE_INTERVAL),
+            }
+        }
+        if !self.streams.is_empty() {
+            self.send("subscribe", self.streams.clone())?;
+        }
+        self.cache.lock().unwrap().connected = true;
+        info!("Research session {} is ready", self.session_id);
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: Message) -> anyhow::Result<()> {
+        match msg {
+            Message::Depth(depth) => {
+                let mut cache = self.cache.lock().unwrap();
+                cache.depths.insert(depth.symbol().clone(), depth);
+            }
+            Message::Kline(kline) => self.cache.lock().unwrap().on_kline(kline, self.max_bars),
+            Message::Position(position) => {
+                let mut cache = self.cache.lock().unwrap();
+                cache.positions.insert(position.symbol.clone(), position);
+            }
+            Message::Products(rsp) => {
+                self.cache.lock().unwrap().symbols = rsp
+                    .result
+                    .iter()
+                    .map(|p: &Product| p.symbol().clone())
+                    .collect();
+                self.send(
+                    "get_positions",
+                    SPositionReq {
+                        session_id: self.session_id,
+                        symbols: Vec::new(),
+                    },
+                )?;
+            }
+            Message::Positions(rsp) => {
+                {
+                    let mut cache = self.cache.lock().unwrap();
+                    for position in rsp.result.positions {
+                        cache.positions.insert(position.symbol.clone(), position);
+                    }
+                }
+                if !self.login {
+                    self.send(
+                        "login",
+                        SLogin {
+                            session_id: self.session_id,
+                            name: Some(self.name.clone()),
+                            trading: false,
+                            cancel_on_disconnect: false,
+                            fills: false,
+                            format: WireFormat::Json,
+                            heartbeat_ms: None,
+                            tenant: self.tenant.clone(),
+                            token: self.token.clone(),
+                            version: Some(PROTOCOL_VERSION),
+                        },
+                    )?;
+                }
+            }
+            Message::Login(rsp) => {
+                info!("{:?}", rsp);
+                self.ws.set_format(rsp.result.format);
+                self.login = true;
+            }
+            Message::Subscribed(rsp) => {
+                for result in rsp.result {
+                    match result.error {
+                        Some(e) if !result.accepted => {
+                            warn!("subscribe {} rejected: {}", result.stream, e.msg)
+                        }
+                        _ => info!("subscribed {}", result.stream),
+                    }
+                }
+            }
+            Message::Error(rsp) => error!("{:?}", rsp),
+            Message::Close => self.cache.lock().unwrap().connected = false,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// 处理 Python 的请求，收到 Stop 或 Python 一侧已释放时返回 false
+    fn on_commands(&mut self) -> bool {
+        loop {
+            match self.commands.try_recv() {
+                Ok(Command::Subscribe(subscription)) => {
+                    self.streams.push(subscription.clone());
+                    if let Err(e) = self.send("subscribe", vec![subscription]) {
+                        error!("{}", e);
+                    }
+                }
+                Ok(Command::Stop) | Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                    return false
+                }
+                Err(crossbeam_channel::TryRecvError::Empty) => return true,
+            }
+        }
+    }
+
+    fn run(mut self, ready: Sender<Result<(), String>>) {
+        if let Err(e) = self.connect() {
+            let _ = ready.send(Err(e.to_string()));
+            return;
+        }
+        let _ = ready.send(Ok(()));
+        while self.on_commands() {
+            if let Some(msg) = self.ws.read() {
+                if let Err(e) = self.on_message(msg) {
+                    error!("{}", e);
+                }
+                continue;
+            }
+            if !self.ws.is_closed() {
+                std::thread::sleep(IDLE_INTERVAL);
+                continue;
+            }
+            self.cache.lock().unwrap().connected = false;
+            warn!("research ws closed, reconnecting...");
+            std::thread::sleep(RECONNECT_INTERVAL);
+            if let Err(e) = self.connect() {
+                error!("{}", e);
+            }
+        }
+        if let Err(e) = self.ws.close() {
+            error!("{}", e);
+        }
+        info!("Research session {} is closed", self.session_id);
+    }
+}
+
+/// 后台线程维护订阅并缓存最新的行情与持仓，适合在 notebook 中同步地查看；不能下单
+#[gen_stub_pyclass]
+#[pyclass]
+pub struct ResearchSession {
+    cache: Arc<Mutex<Cache>>,
+    commands: Sender<Command>,
+    worker: Option<JoinHandle<()>>,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl ResearchSession {
+    /// 连接并登录后返回，失败时抛出异常；`max_bars` 为每条 K 线流保留的根数，缺省 1000
+    #[new]
+    #[pyo3(signature = (addr, session_id, name=None, tenant=None, token=None, max_bars=None))]
+    fn new(
+        py: Python<'_>,
+        addr: String,
+        session_id: u16,
+        name: Option<String>,
+        tenant: Option<String>,
+        token: Option<String>,
+        max_bars: Option<usize>,
+    ) -> PyResult<Self> {
+        let cache = Arc::new(Mutex::new(Cache::default()));
+        let (commands, receiver) = crossbeam_channel::unbounded();
+        let (ready, connected) = crossbeam_channel::bounded(1);
+        let worker = Worker {
+            ws: WebSocketClient::new(addr),
+            session_id,
+            name: name.unwrap_or_else(|| "research".into()),
+            tenant,
+            token,
+            cache: cache.clone(),
+            commands: receiver,
+            max_bars: max_bars.unwrap_or(MAX_BARS).max(1),
+            streams: Vec::new(),
+            id: 0,
+            login: false,
+        };
+        let worker = std::thread::spawn(move || worker.run(ready));
+        match py.detach(|| connected.recv()) {
+            Ok(Ok(())) => Ok(Self {
+                cache,
+                commands,
+                worker: Some(worker),
+            }),
+            Ok(Err(e)) => Err(pyo3::exceptions::PyConnectionError::new_err(e)),
+            Err(e) => Err(pyo3::exceptions::PyConnectionError::new_err(e.to_string())),
+        }
+    }
+
+    /// 订阅 `symbol@stream`，如 `depth`、`kline:1m`；数据由后台线程缓存
+    fn subscribe(&self, symbol: &str, stream: &str) -> PyResult<()> {
+        let subscription = SSubscription::Stream(format!("{}@{}", symbol, stream));
+        self.commands
+            .send(Command::Subscribe(subscription))
+            .map_err(|_| pyo3::exceptions::PyException::new_err("research session is closed"))
+    }
+
+    /// 后台连接是否已登录，断线重连期间为 False
+    #[getter]
+    fn is_connected(&self) -> bool {
+        self.cache.lock().unwrap().connected
+    }
+
+    /// 网关的所有合约
+    #[getter]
+    fn symbols(&self) -> Vec<String> {
+        self.cache.lock().unwrap().symbols.clone()
+    }
+
+    /// 最新的深度；还没有收到时最多等待 `timeout_ms`（缺省 0）毫秒，仍没有则为 None
+    #[pyo3(signature = (symbol, timeout_ms=None))]
+    fn last_depth(&self, py: Python<'_>, symbol: &str, timeout_ms: Option<u64>) -> Option<Depth> {
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms.unwrap_or_default());
+        let cache = &self.cache;
+        py.detach(|| loop {
+            if let Some(depth) = cache.lock().unwrap().depths.get(symbol) {
+                return Some(depth.clone());
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        })
+    }
+
+    /// `symbol@kline:interval` 缓存的 K 线，`interval` 缺省为 1m，最后一根可能未完结
+    #[pyo3(signature = (symbol, interval=None))]
+    fn klines(&self, symbol: &str, interval: Option<&str>) -> Vec<Kline> {
+        let stream = format!("{}@kline:{}", symbol, interval.unwrap_or("1m"));
+        self.cache
+            .lock()
+            .unwrap()
+            .klines
+            .get(&stream)
+            .map(|bars| bars.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// 与 `klines` 相同，转为以起始时间为索引的 pandas DataFrame，
+    /// 列为 open、high、low、close、volume、amount、is_closed；需要安装 pandas
+    #[pyo3(signature = (symbol, interval=None))]
+    fn ohlcv_df(
+        &self,
+        py: Python<'_>,
+        symbol: &str,
+        interval: Option<&str>,
+    ) -> PyResult<Py<PyAny>> {
+        let bars = self.klines(symbol, interval);
+        let pd = py.import("pandas")?;
+        let columns = PyDict::new(py);
+        for name in ["open", "high", "low", "close", "volume", "amount"] {
+            let values: Vec<f64> = bars.iter().filter_map(|k| k.var(name)).collect();
+            columns.set_item(name, values)?;
+        }
+        let closed: Vec<bool> = bars.iter().map(|k| k.is_closed()).collect();
+        columns.set_item("is_closed", closed)?;
+        let times: Vec<u64> = bars.iter().map(|k| k.start_time()).collect();
+        let index = pd.call_method(
+            "to_datetime",
+            (times,),
+            Some(&[("unit", "ms")].into_py_dict(py)?),
+        )?;
+        index.setattr("name", "datetime")?;
+        let kwargs = [("index", index)].into_py_dict(py)?;
+        Ok(pd
+            .call_method("DataFrame", (columns,), Some(&kwargs))?
+            .unbind())
+    }
+
+    /// 网关上本会话的持仓，没有时为 None
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.cache.lock().unwrap().positions.get(symbol).cloned()
+    }
+
+    fn positions(&self) -> Vec<Position> {
+        self.cache
+            .lock()
+            .unwrap()
+            .positions
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// 断开连接并结束后台线程，之后只能读取已缓存的数据
+    fn close(&mut self, py: Python<'_>) {
+        let _ = self.commands.send(Command::Stop);
+        if let Some(worker) = self.worker.take() {
+            py.detach(|| {
+                let _ = worker.join();
+            });
+        }
+    }
+}
+
+impl Drop for ResearchSession {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(start_time: u64, close: f64, is_closed: bool) -> Kline {
+        serde_json::from_value(serde_json::json!({
+            "time": start_time + 59_999, "start_time": start_time, "symbol": "btcusdt",
+            "stream": "btcusdt@kline:1m", "interval": "1m", "open": 1.0, "high": 2.0,
+            "low": 0.5, "close": close, "volume": 10.0, "amount": 10.0, "first_trade_id": 1,
+            "last_trade_id": 2, "trade_count": 2, "is_closed": is_closed, "buy_volume": 5.0,
+            "buy_amount": 5.0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_klines() {
+        let mut cache = Cache::default();
+        cache.on_kline(kline(0, 1.0, false), 2);
+        cache.on_kline(kline(0, 1.5, true), 2);
+        cache.on_kline(kline(60_000, 1.6, false), 2);
+        let bars = &cache.klines["btcusdt@kline:1m"];
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].var("close"), Some(1.5));
+        assert!(bars[0].is_closed());
+
+        // 迟到的推送不改变顺序，超过根数时丢掉最早的
+        cache.on_kline(kline(0, 1.4, true), 2);
+        cache.on_kline(kline(120_000, 1.7, false), 2);
+        let bars = &cache.klines["btcusdt@kline:1m"];
+        assert_eq!(
+            bars.iter().map(|k| k.start_time()).collect::<Vec<_>>(),
+            vec![60_000, 120_000]
+        );
+    }
+}
@@ -1,9 +1,20 @@
-use crate::chat::{CancelRequest, Message, OrderRequest, Product};
+use crate::chat::{
+    CancelRequest, Message, OrderRequest, PegRequest, Product, QuoteLevel, QuoteRequest,
+    SymbolChanged,
+};
+use crate::poll::{self, QuoteBuffer};
 use crate::subscription::Subscription;
 use crate::ws::WebSocketClient;
 use crate::{constant::*, Order, PositionRsp};
 use crate::{Event, Position};
-use cryptoflow::chat::{Error, Response, SLogin, SLoginResponse, SPositionReq, SRequest};
+use cryptoflow::chat::{
+    Error, Response, SBasisReq, SDepthSnapshotReq, SDerive, SLogin, SLoginResponse,
+    SOptionChainReq, SParamsReq, SPositionReq, SRequest, SResume, SRollReq, SSubscribeResponse,
+    SSubscription, SVolatilityReq,
+};
+use cryptoflow::codec::WireFormat;
+use cryptoflow::compat::PROTOCOL_VERSION;
+use cryptoflow::my_trades::SMyTradesReq;
 use log::*;
 use pyo3::prelude::*;
 use pyo3_stub_gen::derive::{gen_stub_pyclass, gen_stub_pymethods};
@@ -13,6 +24,35 @@ use std::fmt::Debug;
 use std::time::{Duration, Instant};
 use std::vec;
 
+/// 一批订单的上限
+pub(crate) const MAX_BULK_ORDERS: usize = 255;
+
+/// 检查批量下单的各列长度一致且不超过上限
+pub(crate) fn check_bulk(
+    symbols: &[String],
+    sides: &[Side],
+    prices: &[f64],
+    quantities: &[f64],
+) -> PyResult<()> {
+    let n = symbols.len();
+    if sides.len() != n || prices.len() != n || quantities.len() != n {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "symbols, sides, prices and quantities have different lengths: {}, {}, {}, {}",
+            n,
+            sides.len(),
+            prices.len(),
+            quantities.len()
+        )));
+    }
+    if n > MAX_BULK_ORDERS {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "{} orders, at most {} per batch",
+            n, MAX_BULK_ORDERS
+        )));
+    }
+    Ok(())
+}
+
 #[gen_stub_pyclass]
 #[pyclass(unsendable)]
 pub struct Session {
@@ -20,11 +60,27 @@ pub struct Session {
     session_id: u16,
     name: String,
     subscription: HashMap<String, Py<Subscription>>,
-    orders: HashMap<u8, Py<Order>>,
+    orders: HashMap<u32, Py<Order>>,
+    /// 为报价预留的订单号，网关实际挂单后才有回报
+    quote_ids: HashSet<u32>,
     symbols: HashSet<String>,
+    /// 被网关拒绝的订阅：流 -> 原因
+    rejected: HashMap<String, String>,
+    /// 未回复的退订请求 id
+    unsubscribing: HashSet<i64>,
     login: bool,
     trading: bool,
-    id: u8,
+    cancel_on_disconnect: bool,
+    fills: bool,
+    /// 登录时声明的心跳间隔
+    heartbeat_ms: Option<u64>,
+    last_heartbeat: Option<Instant>,
+    /// 网关配置了租户时登录带上的租户与 token
+    tenant: Option<String>,
+    token: Option<String>,
+    /// 登录时请求的编码
+    format: WireFormat,
+    id: u32,
     connection_time: Option<Instant>,
 }
 
@@ -36,6 +92,13 @@ impl Session {
                 session_id: self.session_id,
                 name: Some(self.name.clone()),
                 trading: self.trading,
+                cancel_on_disconnect: self.cancel_on_disconnect,
+                fills: self.fills,
+                format: self.format,
+                heartbeat_ms: self.heartbeat_ms,
+                tenant: self.tenant.clone(),
+                token: self.token.clone(),
+                version: Some(PROTOCOL_VERSION),
             },
         )?;
         Ok(())
@@ -44,6 +107,7 @@ impl Session {
     fn on_login(&mut self, login: SLoginResponse) -> Option<Py<PyAny>> {
         info!("{:?}", login);
         self.login = true;
+        self.ws.set_format(login.result.format);
 
         Some(Event::new(crate::EventType::Login, self.login))
     }
@@ -67,6 +131,9 @@ impl Session {
     fn on_products(&mut self, rsp: Response<Vec<Product>>) -> anyhow::Result<()> {
         let mut products = rsp.result;
         let cnt = products.len();
+        let chains = binance::roll::chains(products.iter().map(|p| match p {
+            Product::Binance(product) => product,
+        }));
         while let Some(product) = products.pop() {
             info!("{:?}", product);
             let symbol = product.symbol().clone();
@@ -74,6 +141,10 @@ impl Session {
             self.subscription.insert(symbol, sub);
         }
         info!("Total products {}", cnt);
+        // 连续合约别名指向当前的合约，可以像合约一样订阅
+        for (alias, chain) in chains {
+            self.alias(&alias, &chain.contract);
+        }
         self.get_positions()?;
 
         Ok(())
@@ -99,6 +170,31 @@ impl Session {
         Ok(())
     }
 
+    /// 部分流被拒绝时其余的流照常推送，被拒绝的流记录原因
+    fn on_subscribed(&mut self, rsp: SSubscribeResponse) {
+        if self.unsubscribing.remove(&rsp.id) {
+            for result in rsp.result {
+                match result.error {
+                    Some(e) => warn!("unsubscribe {} rejected: {}", result.stream, e.msg),
+                    None => info!("unsubscribed {}", result.stream),
+                }
+            }
+            return;
+        }
+        for result in rsp.result {
+            match result.error {
+                Some(e) if !result.accepted => {
+                    warn!("subscribe {} rejected: {}", result.stream, e.msg);
+                    self.rejected.insert(result.stream, e.msg);
+                }
+                _ => {
+                    info!("subscribed {}", result.stream);
+                    self.rejected.remove(&result.stream);
+                }
+            }
+        }
+    }
+
     fn on_error(&mut self, response: Response<Error>) {
         panic!("{:?}", response);
     }
@@ -121,6 +217,16 @@ impl Session {
                 }
                 return e;
             }
+            None if self.quote_ids.remove(&id) => {
+                let pyorder = Python::attach(|py| Py::new(py, order)).ok()?;
+                let e = Python::attach(|py| {
+                    Some(Event::new(crate::EventType::Order, pyorder.clone_ref(py)))
+                });
+                if active {
+                    self.orders.insert(id, pyorder);
+                }
+                return e;
+            }
             None => warn!("Cannot find order, maybe a bug"),
         }
         None
@@ -136,6 +242,20 @@ impl Session {
         }
     }
 
+    /// 别名指向 `symbol` 的订阅
+    fn alias(&mut self, alias: &str, symbol: &str) {
+        if let Some(sub) = self.subscription.get(symbol) {
+            let sub = Python::attach(|py| sub.clone_ref(py));
+            self.subscription.insert(alias.to_string(), sub);
+        }
+    }
+
+    fn on_symbol_changed(&mut self, changed: SymbolChanged) -> Option<Py<PyAny>> {
+        info!("{:?}", changed);
+        self.alias(&changed.alias, &changed.symbol);
+        Some(Event::new(crate::EventType::SymbolChanged, changed))
+    }
+
     fn on_close(&mut self) {
         info!("Session {} is closed", self.id);
     }
@@ -150,24 +270,116 @@ impl Session {
                     error!("{}", e);
                 }
             }
+            Message::Subscribed(rsp) => self.on_subscribed(rsp),
             Message::Positions(rsp) => {
                 if let Err(e) = self.on_positions(rsp) {
                     error!("{}", e);
                 }
             }
             Message::Kline(kline) => return Some(Event::new(crate::EventType::Kline, kline)),
+            Message::BarClose(close) => return Some(Event::new(crate::EventType::BarClose, close)),
+            Message::Derived(derived) => {
+                return Some(Event::new(crate::EventType::Derived, derived))
+            }
+            Message::TradeFlow(flow) => return Some(Event::new(crate::EventType::TradeFlow, flow)),
+            Message::Volatility(vol) => return Some(Event::new(crate::EventType::Volatility, vol)),
+            Message::VolatilityRsp(rsp) => {
+                return Some(Event::new(crate::EventType::Volatility, rsp.result))
+            }
+            Message::Basis(basis) => return Some(Event::new(crate::EventType::Basis, basis)),
+            Message::BasisRsp(rsp) => return Some(Event::new(crate::EventType::Basis, rsp.result)),
+            Message::Params(params) => return Some(Event::new(crate::EventType::Params, params)),
+            Message::ParamsRsp(rsp) => {
+                return Some(Event::new(crate::EventType::Params, rsp.result))
+            }
+            Message::SymbolChanged(changed) => return self.on_symbol_changed(changed),
+            Message::TradingStatus(status) => {
+                match status.trading {
+                    true => info!("Trading of {} is resumed", status.symbol),
+                    false => warn!("Trading of {} is halted: {:?}", status.symbol, status),
+                }
+                return Some(Event::new(crate::EventType::TradingStatus, status));
+            }
+            Message::VenueStatus(status) => {
+                warn!("{:?}", status);
+                return Some(Event::new(crate::EventType::VenueStatus, status));
+            }
+            Message::OrderCap(cap) => {
+                match cap.queued {
+                    true => warn!("{:?}", cap),
+                    false => info!("{:?}", cap),
+                }
+                return Some(Event::new(crate::EventType::OrderCap, cap));
+            }
             Message::Depth(depth) => return Some(Event::new(crate::EventType::Depth, depth)),
+            Message::Greeks(greeks) => return Some(Event::new(crate::EventType::Greeks, greeks)),
             Message::Order(order) => return self.on_order(order),
+            Message::Fill(fill) => return Some(Event::new(crate::EventType::Fill, fill)),
+            Message::MyTrades(rsp) => {
+                return Some(Event::new(crate::EventType::MyTrades, rsp.result))
+            }
+            Message::DepthSnapshot(rsp) => {
+                return Some(Event::new(crate::EventType::DepthSnapshot, rsp.result))
+            }
+            Message::Delivery(delivery) => {
+                return Some(Event::new(crate::EventType::Delivery, delivery))
+            }
+            Message::Roll(roll) => return Some(Event::new(crate::EventType::Roll, roll)),
+            Message::RollRsp(rsp) => {
+                return Some(Event::new(crate::EventType::RollResult, rsp.result))
+            }
             Message::Position(position) => self.on_position(position),
             Message::Close => self.on_close(),
         }
         None
     }
 
+    /// 登录后按声明的间隔发送心跳；心跳由 `process` 驱动，策略卡住时随之停止
+    fn heartbeat(&mut self) {
+        let Some(interval) = self.heartbeat_ms.filter(|_| self.login) else {
+            return;
+        };
+        let interval = Duration::from_millis(interval);
+        if self.last_heartbeat.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.last_heartbeat = Some(Instant::now());
+        // 心跳不占用请求 id，也不打印
+        let req = SRequest {
+            id: self.id as i64,
+            method: "heartbeat".into(),
+            params: (),
+        };
+        if let Err(e) = self.ws.send(req) {
+            error!("{}", e);
+        }
+    }
+
+    /// 释放 GIL 读取一批消息，连接断开时重连
+    fn poll(
+        &mut self,
+        py: Python<'_>,
+        max_n: Option<usize>,
+        timeout_ms: Option<u64>,
+    ) -> Vec<Message> {
+        self.heartbeat();
+        let (max_n, timeout_ms) = (
+            max_n.unwrap_or(poll::MAX_EVENTS),
+            timeout_ms.unwrap_or_default(),
+        );
+        let ws = &mut self.ws;
+        let messages = py.detach(|| poll::read(ws, max_n, timeout_ms));
+        if self.ws.is_closed() {
+            warn!("ws closed, reconnecting...");
+            self.connect()
+        }
+        messages
+    }
+
     fn send<T: Debug + Serialize>(&mut self, method: &str, params: T) -> anyhow::Result<i64> {
         let id = self.id as i64;
         let req = SRequest {
-            id: id,
+            id,
             method: method.into(),
             params,
         };
@@ -190,9 +402,19 @@ impl Session {
             name,
             subscription: HashMap::default(),
             orders: HashMap::default(),
+            quote_ids: HashSet::default(),
             symbols: HashSet::default(),
+            rejected: HashMap::default(),
+            unsubscribing: HashSet::default(),
             login: false,
             trading,
+            cancel_on_disconnect: false,
+            fills: false,
+            heartbeat_ms: None,
+            last_heartbeat: None,
+            tenant: None,
+            token: None,
+            format: WireFormat::Json,
             id: 0,
             connection_time: None,
         }
@@ -213,11 +435,56 @@ impl Session {
         self.login
     }
 
+    /// 被网关拒绝的订阅及原因，之后订阅成功的流会被移除
+    #[getter]
+    fn rejected_streams(&self) -> HashMap<String, String> {
+        self.rejected.clone()
+    }
+
     #[getter]
     fn trading(&self) -> bool {
         self.trading
     }
 
+    /// 开启后连接断开时网关会撤销本会话所有未完成的订单，需要在 connect 之前设置
+    fn set_cancel_on_disconnect(&mut self, enable: bool) {
+        self.cancel_on_disconnect = enable;
+    }
+
+    /// 开启后网关在订单回报之外逐笔推送成交，以 `EventType.Fill` 返回，需要在 connect 之前设置
+    fn set_fills(&mut self, enable: bool) {
+        self.fills = enable;
+    }
+
+    /// 声明心跳间隔（毫秒），登录后 `process` 至少每隔这个时间发送一次心跳；网关开启心跳巡检时，
+    /// 策略卡住超时后会撤销本会话的订单，需要在 connect 之前设置
+    #[pyo3(signature = (interval_ms=None))]
+    fn set_heartbeat(&mut self, interval_ms: Option<u64>) {
+        self.heartbeat_ms = interval_ms;
+    }
+
+    /// 网关配置了租户时登录需要带上会话所属的租户，租户配置了 token 时还需要 token，需要在 connect 之前设置
+    #[pyo3(signature = (tenant, token=None))]
+    fn set_tenant(&mut self, tenant: String, token: Option<String>) {
+        self.tenant = Some(tenant);
+        self.token = token;
+    }
+
+    /// 登录后的编码：`json`、`msgpack` 或 `cbor`，需要在 connect 之前设置；
+    /// 网关未开启该编码时仍使用 JSON
+    fn set_wire_format(&mut self, format: &str) -> PyResult<()> {
+        match WireFormat::parse(format) {
+            Some(format) => {
+                self.format = format;
+                Ok(())
+            }
+            None => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Unsupported format {}",
+                format
+            ))),
+        }
+    }
+
     fn connect(&mut self) {
         match self.connection_time {
             Some(t) => {
@@ -244,7 +511,16 @@ impl Session {
         }
     }
 
-    fn subscribe(&mut self, symbol: &str, stream: &str) -> PyResult<Py<Subscription>> {
+    /// `tag` 不为空时网关在该流的每条数据上带回这个标签；
+    /// 深度流可以指定 `bucket`，网关按该宽度的价格带合并档位后推送
+    #[pyo3(signature = (symbol, stream, tag=None, bucket=None))]
+    fn subscribe(
+        &mut self,
+        symbol: &str,
+        stream: &str,
+        tag: Option<String>,
+        bucket: Option<f64>,
+    ) -> PyResult<Py<Subscription>> {
         if !self.login {
             return Err(pyo3::exceptions::PyException::new_err("Please login first"));
         }
@@ -254,13 +530,25 @@ impl Session {
             .get(symbol)
             .map(|s| Python::attach(|py| s.clone_ref(py)));
         match sub {
-            Some(inner) => match self.send("subscribe", vec![format!("{}@{}", symbol, stream)]) {
-                Ok(_) => {
-                    self.symbols.insert(symbol.into());
-                    return Ok(inner);
+            Some(inner) => {
+                let stream = format!("{}@{}", symbol, stream);
+                let subscription = match (bucket, tag) {
+                    (Some(bucket), tag) => SSubscription::Bucketed {
+                        stream,
+                        bucket,
+                        tag,
+                    },
+                    (None, Some(tag)) => SSubscription::Tagged { stream, tag },
+                    (None, None) => SSubscription::Stream(stream),
+                };
+                match self.send("subscribe", vec![subscription]) {
+                    Ok(_) => {
+                        self.symbols.insert(symbol.into());
+                        Ok(inner)
+                    }
+                    Err(e) => Err(pyo3::exceptions::PyException::new_err(e.to_string())),
                 }
-                Err(e) => return Err(pyo3::exceptions::PyException::new_err(e.to_string())),
-            },
+            }
             None => Err(pyo3::exceptions::PyException::new_err(format!(
                 "Invalid symbol {}",
                 symbol
@@ -268,6 +556,122 @@ impl Session {
         }
     }
 
+    /// 退订 `symbol@stream` 形式的流，网关在没有其他策略订阅时向交易所退订
+    fn unsubscribe(&mut self, streams: Vec<String>) -> PyResult<()> {
+        if !self.login {
+            return Err(pyo3::exceptions::PyException::new_err("Please login first"));
+        }
+        match self.send("unsubscribe", streams) {
+            Ok(id) => {
+                self.unsubscribing.insert(id);
+                Ok(())
+            }
+            Err(e) => Err(pyo3::exceptions::PyException::new_err(e.to_string())),
+        }
+    }
+
+    /// 在网关为已订阅的 `symbol@stream` 注册派生流，每个 tick 计算 `expr`，
+    /// 结果以 `EventType.Derived` 推送，流名称为 `symbol@derived:name`
+    fn derive(&mut self, symbol: &str, stream: &str, name: &str, expr: &str) -> PyResult<()> {
+        let params = SDerive {
+            name: name.into(),
+            source: format!("{}@{}", symbol, stream),
+            expr: expr.into(),
+        };
+        self.send("derive", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 断线重连并重新订阅后，请求网关补发 `symbol@stream` 在 `offset` 之后缓存的数据，
+    /// 补发完后网关回复；`offset` 已超出缓存窗口时回复错误，需要重新取快照
+    fn resume(&mut self, symbol: &str, stream: &str, offset: u64) -> PyResult<()> {
+        let params = SResume {
+            stream: format!("{}@{}", symbol, stream),
+            offset,
+        };
+        self.send("resume", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 请求标的（如 btcusdt）下的期权合约，返回后与其他合约一样可以 `subscribe(symbol, "greeks")`；
+    /// 网关需要开启期权行情
+    fn get_option_chain(&mut self, underlying: &str) -> PyResult<()> {
+        let params = SOptionChainReq {
+            underlying: underlying.into(),
+        };
+        self.send("get_option_chain", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 查询网关按 `symbol@kline:window` 估计的波动率，需要已订阅该 K 线流且网关开启了波动率估计；
+    /// 结果以 `EventType.Volatility` 返回
+    fn get_volatility(&mut self, symbol: &str, window: &str) -> PyResult<()> {
+        let params = SVolatilityReq {
+            symbol: symbol.into(),
+            window: window.into(),
+        };
+        self.send("get_volatility", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 查询合约网关计算的基差，需要已订阅 `symbol@basis` 且网关开启了 `basis`；结果以 `EventType.Basis` 返回
+    fn get_basis(&mut self, symbol: &str) -> PyResult<()> {
+        let params = SBasisReq {
+            symbol: symbol.into(),
+        };
+        self.send("get_basis", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 查询交易所前 `limit` 档的深度快照，结果以 `EventType.DepthSnapshot` 返回；由网关代为查询并短暂缓存，
+    /// 订阅深度的增量后用快照初始化本地订单簿
+    fn get_depth_snapshot(&mut self, symbol: &str, limit: u32) -> PyResult<()> {
+        let params = SDepthSnapshotReq {
+            symbol: symbol.into(),
+            limit,
+        };
+        self.send("get_depth_snapshot", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 查询账户一个交易对 [start, end)（毫秒）内的历史成交，结果以 `EventType.MyTrades` 返回；
+    /// 网关需要开启 `my_trades`，本地没有的时间段由网关从交易所补齐
+    fn get_my_trades(&mut self, symbol: &str, start: i64, end: i64) -> PyResult<()> {
+        let params = SMyTradesReq {
+            symbol: symbol.into(),
+            start,
+            end,
+        };
+        self.send("get_my_trades", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 读取本会话在网关保存的策略参数，结果以 `EventType.Params` 返回；之后运维修改参数时同样推送
+    fn get_params(&mut self) -> PyResult<()> {
+        let params = SParamsReq {
+            session_id: self.session_id,
+        };
+        self.send("get_params", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// `idempotency_key` 在重发同一笔订单时保持不变，网关在时间窗口内只转发一次
+    /// `ttl_ms` 到期仍未完成的订单由网关撤销，状态为 EXPIRED_BY_GATEWAY
+    /// `peg_offset_ticks` 不为空时由网关把订单钉在最优买卖价外若干个 tick，`price` 只在还没有盘口时使用
+    /// `position_side` 只用于双向持仓模式的合约账户，开平多头为 LONG，开平空头为 SHORT
+    /// `reprice_passive` 为 True 时，只做 maker 的订单会立即成交则由网关改为不会成交的最优价格，而不是拒绝
+    /// `strategy`、`tags` 随订单保存，网关按它们统计成交与盈亏，用 `get_attribution` 请求查询
+    /// `force` 为 True 时跳过网关对价格偏离与名义价值上限的校验
+    #[pyo3(signature = (symbol, price, quantity, side, order_type, tif, idempotency_key=None, ttl_ms=None, peg_offset_ticks=None, position_side=None, reprice_passive=None, strategy=None, tags=None, force=None))]
+    #[allow(clippy::too_many_arguments)]
     fn add_order(
         &mut self,
         symbol: &str,
@@ -276,25 +680,42 @@ impl Session {
         side: &Side,
         order_type: &OrderType,
         tif: &Tif,
+        idempotency_key: Option<String>,
+        ttl_ms: Option<u64>,
+        peg_offset_ticks: Option<i64>,
+        position_side: Option<PositionSide>,
+        reprice_passive: Option<bool>,
+        strategy: Option<String>,
+        tags: Option<Vec<String>>,
+        force: Option<bool>,
     ) -> Option<Py<Order>> {
         if !self.login || !self.trading {
             return None;
         }
 
         let id = self.id;
+        self.quote_ids.remove(&id);
         let params = OrderRequest {
             id,
             symbol: symbol.into(),
             price,
             quantity,
-            side: side.clone(),
-            order_type: order_type.clone(),
-            tif: tif.clone(),
+            side: *side,
+            order_type: *order_type,
+            tif: *tif,
             session_id: self.session_id,
+            idempotency_key,
+            ttl_ms,
+            peg: peg_offset_ticks.map(|offset_ticks| PegRequest { offset_ticks }),
+            position_side,
+            reprice_passive: reprice_passive.unwrap_or_default(),
+            force: force.unwrap_or_default(),
+            strategy: strategy.clone(),
+            tags: tags.clone().unwrap_or_default(),
         };
 
         info!("Add order: {:?}", params);
-        if let Ok(_) = self.send("order", params) {
+        if self.send("order", params).is_ok() {
             let order = Order::new(
                 id,
                 symbol,
@@ -303,17 +724,177 @@ impl Session {
                 side.to_owned(),
                 order_type.to_owned(),
                 tif.to_owned(),
-            );
+            )
+            .with_position_side(position_side)
+            .with_tags(strategy, tags.unwrap_or_default());
 
             let pyorder = Python::attach(|py| Py::new(py, order).unwrap());
             self.orders
                 .insert(id, Python::attach(|py| pyorder.clone_ref(py)));
             return Some(pyorder);
         }
-        return None;
+        None
     }
 
-    fn cancel(&mut self, symbol: String, order_id: u32) {
+    /// 批量下单，`symbols`、`sides`、`prices`、`quantities` 按位置对应，可以是列表或 numpy 数组
+    /// 所有订单在一个 `orders` 请求中发给网关，编码与发送时释放 GIL；返回的订单与输入一一对应
+    /// 一批最多 255 笔，订单号与 `add_order` 共用
+    #[pyo3(signature = (symbols, sides, prices, quantities, order_type, tif, strategy=None, tags=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_orders(
+        &mut self,
+        py: Python<'_>,
+        symbols: Vec<String>,
+        sides: Vec<Side>,
+        prices: Vec<f64>,
+        quantities: Vec<f64>,
+        order_type: &OrderType,
+        tif: &Tif,
+        strategy: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> PyResult<Vec<Py<Order>>> {
+        check_bulk(&symbols, &sides, &prices, &quantities)?;
+        if !self.login || !self.trading || symbols.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let tags = tags.unwrap_or_default();
+        let mut params = Vec::with_capacity(symbols.len());
+        for (i, symbol) in symbols.into_iter().enumerate() {
+            let id = self.id;
+            self.id = self.id.wrapping_add(1);
+            self.quote_ids.remove(&id);
+            params.push(OrderRequest {
+                id,
+                symbol,
+                price: prices[i],
+                quantity: quantities[i],
+                side: sides[i],
+                order_type: *order_type,
+                tif: *tif,
+                session_id: self.session_id,
+                idempotency_key: None,
+                ttl_ms: None,
+                peg: None,
+                position_side: None,
+                reprice_passive: false,
+                force: false,
+                strategy: strategy.clone(),
+                tags: tags.clone(),
+            });
+        }
+        let req = SRequest {
+            id: self.id as i64,
+            method: "orders".into(),
+            params,
+        };
+        self.id = self.id.wrapping_add(1);
+
+        info!("Add {} orders", req.params.len());
+        let ws = &mut self.ws;
+        if let Err(e) = py.detach(|| ws.send(&req)) {
+            error!("{:?}", e);
+            return Ok(Vec::new());
+        }
+        let mut orders = Vec::with_capacity(req.params.len());
+        for params in req.params {
+            let order = Order::new(
+                params.id,
+                &params.symbol,
+                params.price,
+                params.quantity,
+                params.side,
+                params.order_type,
+                params.tif,
+            )
+            .with_tags(params.strategy, params.tags);
+            let pyorder = Py::new(py, order)?;
+            self.orders.insert(params.id, pyorder.clone_ref(py));
+            orders.push(pyorder);
+        }
+        Ok(orders)
+    }
+
+    /// 双边报价，`bid`、`ask` 为 (price, quantity)，None 表示撤掉这一侧
+    /// 网关只对变化的一侧撤单重挂，新挂出的报价通过订单事件推送
+    #[pyo3(signature = (symbol, bid=None, ask=None))]
+    fn quote(&mut self, symbol: &str, bid: Option<(f64, f64)>, ask: Option<(f64, f64)>) {
+        if !self.login || !self.trading {
+            return;
+        }
+
+        let mut level = |side: Option<(f64, f64)>| {
+            side.map(|(price, quantity)| {
+                let id = self.id;
+                self.id = self.id.wrapping_add(1);
+                self.quote_ids.insert(id);
+                QuoteLevel {
+                    id,
+                    price,
+                    quantity,
+                }
+            })
+        };
+        let (bid, ask) = (level(bid), level(ask));
+        let params = QuoteRequest {
+            symbol: symbol.into(),
+            session_id: self.session_id,
+            bid,
+            ask,
+        };
+
+        info!("Quote: {:?}", params);
+        if let Err(e) = self.send("quote", params) {
+            error!("{:?}", e);
+        }
+    }
+
+    /// 把挂单与持仓从连续合约别名（如 `btcusdt_quarter`）当前对应的合约移到下一个合约，结果以
+    /// `EventType.RollResult` 返回；`spread` 为挂单平移的价差，缺省按两个合约的中间价计算
+    #[pyo3(signature = (alias, spread=None))]
+    fn roll(&mut self, alias: &str, spread: Option<f64>) -> PyResult<()> {
+        if !self.login || !self.trading {
+            return Err(pyo3::exceptions::PyException::new_err(
+                "login with trading before rolling",
+            ));
+        }
+        let params = SRollReq {
+            alias: alias.into(),
+            spread,
+        };
+        self.send("roll", params)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyException::new_err(e.to_string()))
+    }
+
+    /// 修改挂单的价格，`quantity` 缺省为未成交数量；网关在上一次改单完成前只保留最新一笔
+    #[pyo3(signature = (order_id, price, quantity=None))]
+    fn replace(&mut self, order_id: u32, price: f64, quantity: Option<f64>) -> bool {
+        if !self.login || !self.trading {
+            return false;
+        }
+        let Some(order) = self.orders.get(&order_id) else {
+            warn!("Cannot replace unknown order {}", order_id);
+            return false;
+        };
+        let params = Python::attach(|py| {
+            order
+                .borrow(py)
+                .replace_request(self.session_id, price, quantity)
+        });
+
+        info!("Replace order: {:?}", params);
+        match self.send("replace", params) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("{:?}", e);
+                false
+            }
+        }
+    }
+
+    #[pyo3(signature = (symbol, order_id, idempotency_key=None))]
+    fn cancel(&mut self, symbol: String, order_id: u32, idempotency_key: Option<String>) {
         if !self.login || !self.trading {
             return;
         }
@@ -322,6 +903,7 @@ impl Session {
             symbol,
             session_id: self.session_id,
             order_id,
+            idempotency_key,
         };
 
         if let Err(e) = self.send("cancel", params) {
@@ -329,7 +911,46 @@ impl Session {
         }
     }
 
+    /// 一次取出最多 `max_n`（缺省 256）个事件，一个也没有时最多等待 `timeout_ms`（缺省 0）毫秒
+    /// 读取与解码时释放 GIL，取完后一次生成所有事件，行情密集时用来代替逐个调用 `process`
+    #[pyo3(signature = (max_n=None, timeout_ms=None))]
+    fn poll_events(
+        &mut self,
+        py: Python<'_>,
+        max_n: Option<usize>,
+        timeout_ms: Option<u64>,
+    ) -> Vec<Py<PyAny>> {
+        self.poll(py, max_n, timeout_ms)
+            .into_iter()
+            .filter_map(|msg| self.on_message(msg))
+            .collect()
+    }
+
+    /// 与 `poll_events` 相同，但深度的最优一档按 [time, bid, bid_qty, ask, ask_qty] 逐行写入 `buffer`，
+    /// 不再生成事件；`buffer` 为 C 连续、形状为 (n, 5) 的 float64 numpy 数组，由调用方预先分配并重复使用
+    /// 返回写入的各行的交易对与其余的事件；数组写满后的深度、买卖盘有一侧为空的深度照常作为事件返回
+    #[pyo3(signature = (buffer, max_n=None, timeout_ms=None))]
+    fn poll_quotes(
+        &mut self,
+        py: Python<'_>,
+        buffer: &Bound<'_, PyAny>,
+        max_n: Option<usize>,
+        timeout_ms: Option<u64>,
+    ) -> PyResult<(Vec<String>, Vec<Py<PyAny>>)> {
+        let mut quotes = QuoteBuffer::new(buffer)?;
+        let mut events = Vec::new();
+        for msg in self.poll(py, max_n, timeout_ms) {
+            let msg = match msg {
+                Message::Depth(depth) if quotes.push(py, &depth) => continue,
+                msg => msg,
+            };
+            events.extend(self.on_message(msg));
+        }
+        Ok((quotes.into_symbols(), events))
+    }
+
     fn process(&mut self) -> Option<Py<PyAny>> {
+        self.heartbeat();
         if let Some(msg) = self.ws.read() {
             return self.on_message(msg);
         }
@@ -0,0 +1,183 @@
+//! 策略基类
+//!
+//! Python 中继承 `Strategy` 并重写需要的回调，`run` 在 Rust 中批量取出事件并调用对应的回调：深度
+//! `on_depth`、K 线 `on_kline`、订单 `on_order`、成交 `on_fill`，其余的事件 `on_event`。设置了
+//! `timer_ms` 时每隔这么久调用一次 `on_timer`，实盘按系统时钟，回测按回放时钟。回调中调用 `stop`
+//! 后 `run` 在处理完当前这批事件后返回；回测回放完所有行情后也会返回。
+
+use crate::backtest::BacktestSession;
+use crate::chat::{Event, EventType};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use pyo3_stub_gen::derive::{gen_stub_pyclass, gen_stub_pymethods};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 没有设置 `timeout_ms` 时实盘每次取事件最多等待的毫秒数
+const POLL_TIMEOUT_MS: u64 = 10;
+
+/// 下一次调用 `on_timer` 的时间
+#[derive(Debug, Default)]
+struct Timer {
+    next: Option<u64>,
+}
+
+impl Timer {
+    /// 到了调用时间返回 true，第一次调用只记录起点
+    fn due(&mut self, interval: Option<u64>, now: u64) -> bool {
+        let Some(interval) = interval.filter(|i| *i > 0) else {
+            return false;
+        };
+        match self.next {
+            Some(next) if now >= next => {
+                // 落后多个周期时只调用一次
+                self.next = Some(now - (now - next) % interval + interval);
+                true
+            }
+            Some(_) => false,
+            None => {
+                self.next = Some(now + interval);
+                false
+            }
+        }
+    }
+}
+
+/// 策略基类，继承后重写需要的回调，再用 `run` 驱动
+#[gen_stub_pyclass]
+#[pyclass(subclass)]
+#[derive(Debug, Default)]
+pub struct Strategy {
+    /// `on_timer` 的间隔，毫秒，为空时不调用
+    #[pyo3(get, set)]
+    timer_ms: Option<u64>,
+    running: bool,
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl Strategy {
+    /// 子类的 `__init__` 可以接受任意参数
+    #[new]
+    #[pyo3(signature = (*args, **kwargs))]
+    fn new(args: &Bound<'_, PyTuple>, kwargs: Option<&Bound<'_, PyDict>>) -> Self {
+        let _ = (args, kwargs);
+        Self::default()
+    }
+
+    fn on_depth(&self, depth: Py<PyAny>) {
+        let _ = depth;
+    }
+
+    fn on_kline(&self, kline: Py<PyAny>) {
+        let _ = kline;
+    }
+
+    fn on_order(&self, order: Py<PyAny>) {
+        let _ = order;
+    }
+
+    fn on_fill(&self, fill: Py<PyAny>) {
+        let _ = fill;
+    }
+
+    /// `now` 为毫秒时间戳
+    fn on_timer(&self, now: u64) {
+        let _ = now;
+    }
+
+    /// 没有单独回调的事件
+    fn on_event(&self, event: Py<PyAny>) {
+        let _ = event;
+    }
+
+    /// 处理完当前这批事件后停止 `run`
+    fn stop(&mut self) {
+        self.running = false;
+    }
+
+    #[getter]
+    fn running(&self) -> bool {
+        self.running
+    }
+
+    /// 用 `session`（`Session` 或 `BacktestSession`，须已连接）驱动策略，直到 `stop` 或回测结束
+    /// `max_n`、`timeout_ms` 传给 `poll_events`，实盘的 `timeout_ms` 缺省为 10
+    #[pyo3(signature = (session, max_n=None, timeout_ms=None))]
+    fn run(
+        slf: &Bound<'_, Self>,
+        session: &Bound<'_, PyAny>,
+        max_n: Option<usize>,
+        timeout_ms: Option<u64>,
+    ) -> PyResult<()> {
+        let py = slf.py();
+        let backtest = session.is_instance_of::<BacktestSession>();
+        let timeout_ms = timeout_ms.unwrap_or(POLL_TIMEOUT_MS);
+        let mut timer = Timer::default();
+        slf.borrow_mut().running = true;
+        while slf.borrow().running {
+            py.check_signals()?;
+            let events: Vec<Py<PyAny>> = session
+                .call_method1("poll_events", (max_n, timeout_ms))?
+                .extract()?;
+            if backtest && events.is_empty() {
+                break;
+            }
+            for event in events {
+                dispatch(slf, event)?;
+            }
+            let now = match backtest {
+                true => session.getattr("clock")?.extract()?,
+                false => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as u64)
+                    .unwrap_or_default(),
+            };
+            let interval = slf.borrow().timer_ms;
+            if timer.due(interval, now) {
+                slf.call_method1("on_timer", (now,))?;
+            }
+        }
+        slf.borrow_mut().running = false;
+        Ok(())
+    }
+}
+
+/// 按事件类型调用回调，调用时不持有策略的借用，回调中可以修改策略
+fn dispatch(slf: &Bound<'_, Strategy>, event: Py<PyAny>) -> PyResult<()> {
+    let py = slf.py();
+    let (event_type, data) = {
+        let event = event.extract::<PyRef<Event>>(py)?;
+        (event.event_type(), event.data().clone_ref(py))
+    };
+    let hook = match event_type {
+        EventType::Depth => "on_depth",
+        EventType::Kline => "on_kline",
+        EventType::Order => "on_order",
+        EventType::Fill => "on_fill",
+        _ => {
+            slf.call_method1("on_event", (event,))?;
+            return Ok(());
+        }
+    };
+    slf.call_method1(hook, (data,))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer() {
+        let mut timer = Timer::default();
+        assert!(!timer.due(None, 0));
+        assert!(!timer.due(Some(100), 1000));
+        assert!(!timer.due(Some(100), 1099));
+        assert!(timer.due(Some(100), 1100));
+        assert!(!timer.due(Some(100), 1150));
+        // 落后多个周期时只调用一次，之后回到原来的节拍
+        assert!(timer.due(Some(100), 1430));
+        assert!(!timer.due(Some(100), 1499));
+        assert!(timer.due(Some(100), 1500));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{chat::Product, phase::TradingPhase, OrderType, Phase, Position};
+use crate::{chat::Product, phase::TradingPhase, OrderType, Phase, Position, PositionSide};
+use binance::model::leverage::max_notional;
 use pyo3::prelude::*;
 use pyo3_stub_gen::derive::{gen_stub_pyclass, gen_stub_pymethods};
 use rust_decimal::prelude::*;
@@ -9,6 +10,9 @@ use rust_decimal::{prelude::FromPrimitive, Decimal};
 pub struct Subscription {
     product: Product,
     position: Option<Position>,
+    /// 双向持仓模式下的多空两腿
+    long: Option<Position>,
+    short: Option<Position>,
     phase: TradingPhase,
 }
 
@@ -17,12 +21,18 @@ impl Subscription {
         Self {
             product,
             position: None,
+            long: None,
+            short: None,
             phase: TradingPhase::default(),
         }
     }
 
     pub fn on_position(&mut self, position: Position) {
-        self.position = Some(position);
+        match position.side {
+            Some(PositionSide::LONG) => self.long = Some(position),
+            Some(PositionSide::SHORT) => self.short = Some(position),
+            _ => self.position = Some(position),
+        }
     }
 }
 
@@ -69,18 +79,74 @@ impl Subscription {
         self.product.min_notional()
     }
 
+    /// 期权的行权价，不是期权时为空
+    #[getter]
+    pub fn strike(&self) -> Option<f64> {
+        self.product.option().map(|o| o.strike)
+    }
+
+    /// 期权的到期时间（毫秒）
+    #[getter]
+    pub fn expiry(&self) -> Option<u64> {
+        self.product.option().map(|o| o.expiry)
+    }
+
+    /// 期权类型，CALL 或 PUT
+    #[getter]
+    pub fn option_type(&self) -> Option<String> {
+        self.product.option().map(|o| format!("{:?}", o.kind))
+    }
+
+    /// 期权的标的
+    #[getter]
+    pub fn underlying(&self) -> Option<String> {
+        self.product.option().map(|o| o.underlying.clone())
+    }
+
+    /// 净持仓，双向持仓时为多空两腿之和
     #[getter]
     fn net(&self) -> f64 {
-        match &self.position {
-            Some(position) => position.net,
-            None => 0.0,
-        }
+        [&self.position, &self.long, &self.short]
+            .into_iter()
+            .flatten()
+            .map(|position| position.net)
+            .sum()
+    }
+
+    /// 最近一次推送的持仓，合约带入场价、杠杆等字段；双向持仓时见 `long`、`short`
+    #[getter]
+    fn position(&self) -> Option<Position> {
+        self.position.clone()
+    }
+
+    /// 双向持仓的多头一腿
+    #[getter]
+    fn long(&self) -> Option<Position> {
+        self.long.clone()
+    }
+
+    /// 双向持仓的空头一腿，`net` 为负
+    #[getter]
+    fn short(&self) -> Option<Position> {
+        self.short.clone()
     }
 
     pub fn order_support(&self, order_type: &OrderType) -> bool {
         self.product.order_support(order_type)
     }
 
+    /// 杠杆 `leverage` 下持仓名义价值的上限，缺省使用持仓推送的杠杆；没有杠杆分层时为空
+    #[pyo3(signature = (leverage=None))]
+    pub fn max_notional(&self, leverage: Option<u16>) -> Option<f64> {
+        let leverage = leverage.or_else(|| {
+            [&self.position, &self.long, &self.short]
+                .into_iter()
+                .flatten()
+                .find_map(|position| position.leverage)
+        })?;
+        max_notional(self.product.leverage_brackets(), leverage)
+    }
+
     pub fn floor_to_lot_size(&self, vol: f64) -> f64 {
         let mut vol = Decimal::from_f64(vol).unwrap();
         let lot = Decimal::from_f64(self.lot()).unwrap();
@@ -0,0 +1,296 @@
+//! 参数扫描与滚动（walk-forward）验证
+//!
+//! 每个折叠先在样本内窗口上评估参数网格的全部组合，按夏普（相同时按盈亏）
+//! 选出最优组合，再在紧随其后的样本外窗口上评估该组合。评估函数由调用方提供，
+//! Rust 侧用线程并行；Python 策略由 `pyalgo.core.sweep` 用多进程并行，
+//! 结果表的列与 `HEADER` 一致。
+
+use crate::metrics::Metrics;
+use pyo3::prelude::*;
+use pyo3_stub_gen::derive::gen_stub_pyfunction;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+pub type Params = BTreeMap<String, Value>;
+
+pub const HEADER: &str = "fold,stage,start,end,params,pnl,max_drawdown,sharpe,trades";
+
+/// 一个折叠的样本内 [train_start, train_end) 与样本外 [train_end, test_end) 区间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub train_start: u64,
+    pub train_end: u64,
+    pub test_end: u64,
+}
+
+/// 按样本外长度滚动切分 [start, end)，最后一个不完整的样本外区间丢弃
+pub fn walk_forward(start: u64, end: u64, train: u64, test: u64) -> Vec<Window> {
+    let mut windows = Vec::new();
+    if train == 0 || test == 0 {
+        return windows;
+    }
+    let mut train_start = start;
+    while train_start + train + test <= end {
+        windows.push(Window {
+            train_start,
+            train_end: train_start + train,
+            test_end: train_start + train + test,
+        });
+        train_start += test;
+    }
+    windows
+}
+
+/// 参数网格的笛卡尔积
+pub fn grid(space: &BTreeMap<String, Vec<Value>>) -> Vec<Params> {
+    let mut combos = vec![Params::new()];
+    for (name, values) in space.iter() {
+        combos = combos
+            .into_iter()
+            .flat_map(|combo| {
+                values.iter().map(move |value| {
+                    let mut combo = combo.clone();
+                    combo.insert(name.clone(), value.clone());
+                    combo
+                })
+            })
+            .collect();
+    }
+    combos
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Train,
+    Test,
+}
+
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Train => "train",
+            Self::Test => "test",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepRow {
+    pub fold: usize,
+    pub stage: Stage,
+    pub start: u64,
+    pub end: u64,
+    pub params: Params,
+    pub metrics: Metrics,
+}
+
+fn better(a: &Metrics, b: &Metrics) -> bool {
+    (a.sharpe, a.pnl) > (b.sharpe, b.pnl)
+}
+
+/// 用 `workers` 个线程并行评估，结果保持输入顺序
+fn evaluate<F>(tasks: &[(Params, u64, u64)], workers: usize, f: &F) -> Vec<anyhow::Result<Metrics>>
+where
+    F: Fn(&Params, u64, u64) -> anyhow::Result<Metrics> + Sync,
+{
+    let chunk = tasks.len().div_ceil(workers.max(1)).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = tasks
+            .chunks(chunk)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(params, start, end)| f(params, *start, *end))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("sweep worker panicked"))
+            .collect()
+    })
+}
+
+/// 滚动验证，评估失败的组合不参与择优，整折都失败时跳过样本外评估
+pub fn run<F>(
+    space: &BTreeMap<String, Vec<Value>>,
+    windows: &[Window],
+    workers: usize,
+    f: F,
+) -> Vec<SweepRow>
+where
+    F: Fn(&Params, u64, u64) -> anyhow::Result<Metrics> + Sync,
+{
+    let combos = grid(space);
+    let mut rows = Vec::new();
+
+    for (fold, window) in windows.iter().enumerate() {
+        let tasks: Vec<_> = combos
+            .iter()
+            .map(|p| (p.clone(), window.train_start, window.train_end))
+            .collect();
+
+        let results = evaluate(&tasks, workers, &f);
+
+        let mut best: Option<(Params, Metrics)> = None;
+        for ((params, start, end), result) in tasks.into_iter().zip(results) {
+            let Ok(metrics) = result else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|(_, b)| better(&metrics, b)) {
+                best = Some((params.clone(), metrics));
+            }
+            rows.push(SweepRow {
+                fold,
+                stage: Stage::Train,
+                start,
+                end,
+                params,
+                metrics,
+            });
+        }
+
+        let Some((params, _)) = best else {
+            continue;
+        };
+        if let Ok(metrics) = f(&params, window.train_end, window.test_end) {
+            rows.push(SweepRow {
+                fold,
+                stage: Stage::Test,
+                start: window.train_end,
+                end: window.test_end,
+                params,
+                metrics,
+            });
+        }
+    }
+
+    rows
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn write_csv(path: &Path, rows: &[SweepRow]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", HEADER)?;
+    for row in rows {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            row.fold,
+            row.stage.as_str(),
+            row.start,
+            row.end,
+            csv_field(&serde_json::to_string(&row.params)?),
+            row.metrics.pnl,
+            row.metrics.max_drawdown,
+            row.metrics.sharpe,
+            row.metrics.trades
+        )?;
+    }
+    Ok(())
+}
+
+/// 按样本外长度滚动切分 [start, end)，返回 (train_start, train_end, test_end) 列表
+#[gen_stub_pyfunction]
+#[pyfunction]
+pub fn walk_forward_windows(start: u64, end: u64, train: u64, test: u64) -> Vec<(u64, u64, u64)> {
+    walk_forward(start, end, train, test)
+        .into_iter()
+        .map(|w| (w.train_start, w.train_end, w.test_end))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_walk_forward() {
+        let windows = walk_forward(0, 100, 40, 20);
+        assert_eq!(
+            windows,
+            vec![
+                Window {
+                    train_start: 0,
+                    train_end: 40,
+                    test_end: 60
+                },
+                Window {
+                    train_start: 20,
+                    train_end: 60,
+                    test_end: 80
+                },
+                Window {
+                    train_start: 40,
+                    train_end: 80,
+                    test_end: 100
+                },
+            ]
+        );
+        assert!(walk_forward(0, 50, 40, 20).is_empty());
+        assert!(walk_forward(0, 100, 0, 20).is_empty());
+    }
+
+    #[test]
+    fn test_grid() {
+        let space = BTreeMap::from([
+            ("fast".to_string(), vec![json!(5), json!(10)]),
+            ("slow".to_string(), vec![json!(20), json!(30), json!(60)]),
+        ]);
+        let combos = grid(&space);
+        assert_eq!(combos.len(), 6);
+        assert_eq!(combos[0]["fast"], json!(5));
+        assert_eq!(combos[0]["slow"], json!(20));
+        assert_eq!(combos[5]["fast"], json!(10));
+        assert_eq!(combos[5]["slow"], json!(60));
+        assert_eq!(grid(&BTreeMap::new()).len(), 1);
+    }
+
+    #[test]
+    fn test_run() {
+        let space = BTreeMap::from([("k".to_string(), vec![json!(1), json!(2), json!(3)])]);
+        let windows = walk_forward(0, 30, 10, 10);
+        let rows = run(&space, &windows, 2, |params, start, _| {
+            let k = params["k"].as_f64().unwrap();
+            if k == 3.0 {
+                anyhow::bail!("bad params");
+            }
+            // 第一折 k=2 最优，第二折 k=1 最优
+            let sharpe = if start < 10 { k } else { -k };
+            Ok(Metrics {
+                pnl: k,
+                sharpe,
+                ..Default::default()
+            })
+        });
+
+        let tests: Vec<_> = rows.iter().filter(|r| r.stage == Stage::Test).collect();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(tests.len(), 2);
+        assert_eq!((tests[0].start, tests[0].end), (10, 20));
+        assert_eq!(tests[0].params["k"], json!(2));
+        assert_eq!(tests[1].params["k"], json!(1));
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("1"), "1");
+        assert_eq!(csv_field(r#"{"a":1,"b":2}"#), r#""{""a"":1,""b"":2}""#);
+    }
+}
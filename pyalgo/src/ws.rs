@@ -1,4 +1,5 @@
 use crate::chat;
+use cryptoflow::codec::{Codec, WireFormat};
 use log::{debug, error, info, warn};
 use serde::Serialize;
 use std::fmt::Debug;
@@ -10,11 +11,22 @@ use websocket::OwnedMessage;
 pub struct WebSocketClient {
     addr: String,
     inner: Option<Client<TcpStream>>,
+    /// 登录后协商的编码，每次连接从 JSON 开始
+    format: WireFormat,
 }
 
 impl WebSocketClient {
     pub fn new(addr: String) -> Self {
-        WebSocketClient { addr, inner: None }
+        WebSocketClient {
+            addr,
+            inner: None,
+            format: WireFormat::Json,
+        }
+    }
+
+    pub fn set_format(&mut self, format: WireFormat) {
+        info!("ws switch to {:?}", format);
+        self.format = format;
     }
 
     pub fn is_closed(&self) -> bool {
@@ -26,6 +38,7 @@ impl WebSocketClient {
         let client = ClientBuilder::new(&self.addr)?.connect_insecure()?;
         info!("ws connected");
         self.inner.replace(client);
+        self.format = WireFormat::Json;
 
         Ok(())
     }
@@ -45,7 +58,7 @@ impl WebSocketClient {
                 Ok(message) => match &message {
                     OwnedMessage::Text(text) => {
                         debug!("ws text {} bytes", text.len());
-                        let event = serde_json::from_str::<chat::Message>(&text);
+                        let event = serde_json::from_str::<chat::Message>(text);
                         match event {
                             Ok(event) => {
                                 debug!("{:?}", event);
@@ -57,6 +70,16 @@ impl WebSocketClient {
                             }
                         }
                     }
+                    OwnedMessage::Binary(data) => {
+                        debug!("ws binary {} bytes", data.len());
+                        match self.format.decode::<chat::Message>(data) {
+                            Ok(event) => Some(event),
+                            Err(e) => {
+                                error!("{}, {} bytes", e, data.len());
+                                None
+                            }
+                        }
+                    }
                     OwnedMessage::Close(_) => {
                         self.inner.take();
                         warn!("Remote connection closed");
@@ -79,8 +102,10 @@ impl WebSocketClient {
 
     pub fn send<T: Debug + Serialize>(&mut self, data: T) -> anyhow::Result<()> {
         if let Some(ws) = self.inner.as_mut() {
-            let message = serde_json::to_string(&data)?;
-            let message = OwnedMessage::Text(message);
+            let message = match self.format {
+                WireFormat::Json => OwnedMessage::Text(serde_json::to_string(&data)?),
+                format => OwnedMessage::Binary(format.encode(&data)?),
+            };
             debug!("ws send {:?}", message);
             ws.send_message(&message)?;
             debug!("ws sent");
@@ -95,3 +120,37 @@ impl WebSocketClient {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoflow::chat::{SLogin, SResponse};
+
+    #[test]
+    fn test_decode_binary() {
+        let login = SResponse {
+            id: 3,
+            result: SLogin {
+                session_id: 1,
+                name: Some("demo".into()),
+                trading: true,
+                cancel_on_disconnect: false,
+                fills: false,
+                format: WireFormat::Msgpack,
+                heartbeat_ms: None,
+                tenant: None,
+                token: None,
+                version: Some(2),
+            },
+        };
+        for format in [WireFormat::Msgpack, WireFormat::Cbor] {
+            if !format.is_supported() {
+                continue;
+            }
+            // 网关直接按编码序列化的回复，不经过 JSON 解码为推送类型
+            let data = format.encode(&login).unwrap();
+            let message = format.decode::<chat::Message>(&data).unwrap();
+            assert!(matches!(message, chat::Message::Login(_)), "{:?}", message);
+        }
+    }
+}
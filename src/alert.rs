@@ -0,0 +1,710 @@
+//! 关键事件告警
+//!
+//! 通过可插拔的告警通道（Webhook、Telegram Bot、Slack）推送网关运行中的关键事件。
+//! 触发条件可配置：交易所断线超过 N 秒、连续订单拒绝、追加保证金、风控限额触发、
+//! 持仓、余额或已实现盈亏对账不一致、手续费抵扣资产余额不足、API Key 权限失效或即将到期。
+//! 每日运维报告也经同样的通道发送。
+
+use crate::chat::State;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 告警级别
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertLevel {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// 一条待发送的告警
+#[derive(Debug, Serialize, Clone)]
+pub struct Alert {
+    pub level: AlertLevel,
+    /// 告警去重/冷却使用的键，例如 `disconnect:market`
+    pub key: String,
+    pub title: String,
+    pub message: String,
+    /// 毫秒时间戳
+    pub time: i64,
+}
+
+impl Alert {
+    pub fn new(level: AlertLevel, key: String, title: String, message: String) -> Self {
+        Self {
+            level,
+            key,
+            title,
+            message,
+            time: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// 纯文本格式，供 Telegram / Slack 使用
+    pub fn text(&self) -> String {
+        format!("[{:?}] {}\n{}", self.level, self.title, self.message)
+    }
+}
+
+/// 对账的对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcileKind {
+    /// 开盘前核对的持仓
+    Position,
+    /// 开盘前核对的余额
+    Balance,
+    /// 资金流水中的已实现盈亏
+    Pnl,
+}
+
+impl ReconcileKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Position => "position",
+            Self::Balance => "balance",
+            Self::Pnl => "pnl",
+        }
+    }
+}
+
+/// 触发告警的事件
+#[derive(Debug, Clone)]
+pub enum AlertEvent {
+    /// 交易所连接断开持续时间超过阈值
+    ExchangeDisconnected { source: String, secs: u64 },
+    /// 某个 session 连续被拒单
+    OrderRejectStreak { session_id: u16, count: u32 },
+    /// 追加保证金通知
+    MarginCall { detail: String },
+    /// 风控限额触发
+    RiskLimitBreach { session_id: u16, detail: String },
+    /// 本地状态与交易所对账不一致，`key` 为持仓键、资产或交易对
+    ReconcileMismatch {
+        kind: ReconcileKind,
+        key: String,
+        local: f64,
+        remote: f64,
+    },
+    /// 资产可用余额低于阈值
+    LowBalance {
+        asset: String,
+        free: f64,
+        threshold: f64,
+    },
+    /// API Key 权限缺失或即将到期
+    ApiKey { detail: String },
+    /// 挂单过期或与交易所挂单不一致
+    StaleOrder {
+        session_id: u16,
+        order_id: u32,
+        detail: String,
+    },
+    /// 策略超过声明的心跳间隔没有发送请求
+    HeartbeatTimeout { session_id: u16, detail: String },
+    /// 前一天的运维报告
+    DailyReport { day: String, summary: String },
+}
+
+impl AlertEvent {
+    fn into_alert(self) -> Alert {
+        match self {
+            Self::ExchangeDisconnected { source, secs } => Alert::new(
+                AlertLevel::Critical,
+                format!("disconnect:{}", source),
+                format!("{} disconnected", source),
+                format!("{} has been disconnected for {}s", source, secs),
+            ),
+            Self::OrderRejectStreak { session_id, count } => Alert::new(
+                AlertLevel::Warning,
+                format!("reject:{}", session_id),
+                format!("session {} order rejects", session_id),
+                format!("{} consecutive orders rejected", count),
+            ),
+            Self::MarginCall { detail } => Alert::new(
+                AlertLevel::Critical,
+                "margin_call".into(),
+                "margin call".into(),
+                detail,
+            ),
+            Self::RiskLimitBreach { session_id, detail } => Alert::new(
+                AlertLevel::Critical,
+                format!("risk:{}", session_id),
+                format!("session {} risk limit breached", session_id),
+                detail,
+            ),
+            Self::ReconcileMismatch {
+                kind,
+                key,
+                local,
+                remote,
+            } => Alert::new(
+                AlertLevel::Warning,
+                format!("reconcile:{}:{}", kind.name(), key),
+                format!("{} {} mismatch", key, kind.name()),
+                format!("local {} != exchange {}", local, remote),
+            ),
+            Self::LowBalance {
+                asset,
+                free,
+                threshold,
+            } => Alert::new(
+                AlertLevel::Warning,
+                format!("balance:{}", asset),
+                format!("{} balance low", asset),
+                format!("free {} below {}", free, threshold),
+            ),
+            Self::ApiKey { detail } => Alert::new(
+                AlertLevel::Critical,
+                "api_key".into(),
+                "api key check".into(),
+                detail,
+            ),
+            Self::StaleOrder {
+                session_id,
+                order_id,
+                detail,
+            } => Alert::new(
+                AlertLevel::Warning,
+                format!("stale:{}:{}", session_id, order_id),
+                format!("session {} stale order", session_id),
+                detail,
+            ),
+            Self::HeartbeatTimeout { session_id, detail } => Alert::new(
+                AlertLevel::Critical,
+                format!("heartbeat:{}", session_id),
+                format!("session {} heartbeat lost", session_id),
+                detail,
+            ),
+            Self::DailyReport { day, summary } => Alert::new(
+                AlertLevel::Info,
+                format!("report:{}", day),
+                format!("daily report {}", day),
+                summary,
+            ),
+        }
+    }
+}
+
+/// 告警通道
+///
+/// 每个通道把告警转换成一个 HTTP 请求，由 [`Alerter`] 统一异步发送。
+/// 新增通道只需实现这个 trait。
+pub trait AlertSink: Send + Sync + Debug {
+    fn request(&self, client: &reqwest::Client, alert: &Alert) -> reqwest::RequestBuilder;
+}
+
+/// 通用 Webhook，POST 告警 JSON
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    pub url: String,
+}
+
+impl AlertSink for WebhookSink {
+    fn request(&self, client: &reqwest::Client, alert: &Alert) -> reqwest::RequestBuilder {
+        client.post(&self.url).json(alert)
+    }
+}
+
+/// Telegram Bot sendMessage
+#[derive(Debug, Clone)]
+pub struct TelegramSink {
+    pub token: String,
+    pub chat_id: String,
+}
+
+impl AlertSink for TelegramSink {
+    fn request(&self, client: &reqwest::Client, alert: &Alert) -> reqwest::RequestBuilder {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.token);
+        client.post(url).json(&serde_json::json!({
+            "chat_id": self.chat_id,
+            "text": alert.text(),
+        }))
+    }
+}
+
+/// Slack Incoming Webhook
+#[derive(Debug, Clone)]
+pub struct SlackSink {
+    pub webhook_url: String,
+}
+
+impl AlertSink for SlackSink {
+    fn request(&self, client: &reqwest::Client, alert: &Alert) -> reqwest::RequestBuilder {
+        client
+            .post(&self.webhook_url)
+            .json(&serde_json::json!({ "text": alert.text() }))
+    }
+}
+
+/// 配置文件中的告警通道
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    Webhook { url: String },
+    Telegram { token: String, chat_id: String },
+    Slack { webhook_url: String },
+}
+
+impl SinkConfig {
+    fn build(&self) -> Arc<dyn AlertSink> {
+        match self {
+            Self::Webhook { url } => Arc::new(WebhookSink { url: url.clone() }),
+            Self::Telegram { token, chat_id } => Arc::new(TelegramSink {
+                token: token.clone(),
+                chat_id: chat_id.clone(),
+            }),
+            Self::Slack { webhook_url } => Arc::new(SlackSink {
+                webhook_url: webhook_url.clone(),
+            }),
+        }
+    }
+}
+
+/// 告警配置
+///
+/// ```json
+/// "alert": {
+///     "sinks": [{"type": "telegram", "token": "xxx", "chat_id": "123"}],
+///     "disconnect_secs": 30,
+///     "reject_streak": 5
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub sinks: Vec<SinkConfig>,
+    /// 断线多少秒后告警，0 表示不告警
+    pub disconnect_secs: u64,
+    /// 连续拒单多少次后告警，0 表示不告警
+    pub reject_streak: u32,
+    pub margin_call: bool,
+    pub risk_limit: bool,
+    pub reconcile: bool,
+    pub low_balance: bool,
+    pub api_key: bool,
+    pub stale_order: bool,
+    pub heartbeat: bool,
+    /// 同一个告警键的最小发送间隔
+    pub cooldown_secs: u64,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            disconnect_secs: 30,
+            reject_streak: 5,
+            margin_call: true,
+            risk_limit: true,
+            reconcile: true,
+            low_balance: true,
+            api_key: true,
+            stale_order: true,
+            heartbeat: true,
+            cooldown_secs: 60,
+        }
+    }
+}
+
+/// 触发器状态
+#[derive(Debug, Default)]
+struct AlertState {
+    /// source -> (断线开始时间, 是否已告警)
+    disconnects: HashMap<String, (Instant, bool)>,
+    /// session_id -> 连续拒单次数
+    reject_streaks: HashMap<u16, u32>,
+    /// 告警键 -> 上次发送时间
+    last_sent: HashMap<String, Instant>,
+}
+
+impl AlertState {
+    fn on_disconnect_state(
+        &mut self,
+        config: &AlertConfig,
+        source: &str,
+        disconnected: bool,
+        now: Instant,
+    ) -> Option<AlertEvent> {
+        if !disconnected {
+            if self.disconnects.remove(source).is_some() {
+                info!("{} reconnected", source);
+            }
+            return None;
+        }
+        if config.disconnect_secs == 0 {
+            return None;
+        }
+
+        let (since, fired) = self
+            .disconnects
+            .entry(source.to_string())
+            .or_insert((now, false));
+        let secs = now.duration_since(*since).as_secs();
+        if !*fired && secs >= config.disconnect_secs {
+            *fired = true;
+            return Some(AlertEvent::ExchangeDisconnected {
+                source: source.to_string(),
+                secs,
+            });
+        }
+        None
+    }
+
+    fn on_order_state(
+        &mut self,
+        config: &AlertConfig,
+        session_id: u16,
+        state: State,
+    ) -> Option<AlertEvent> {
+        match state {
+            State::REJECTED => {
+                let count = self.reject_streaks.entry(session_id).or_default();
+                *count += 1;
+                // 只在达到阈值的那一次告警，避免同一轮连续拒单反复推送
+                if config.reject_streak > 0 && *count == config.reject_streak {
+                    return Some(AlertEvent::OrderRejectStreak {
+                        session_id,
+                        count: *count,
+                    });
+                }
+            }
+            State::NEW | State::LIVE | State::PARTIALLY_FILLED | State::FILLED => {
+                self.reject_streaks.remove(&session_id);
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// 冷却期内的同键告警会被丢弃
+    fn allow(&mut self, config: &AlertConfig, key: &str, now: Instant) -> bool {
+        let cooldown = Duration::from_secs(config.cooldown_secs);
+        match self.last_sent.get(key) {
+            Some(last) if now.duration_since(*last) < cooldown => false,
+            _ => {
+                self.last_sent.insert(key.to_string(), now);
+                true
+            }
+        }
+    }
+}
+
+struct AlerterInner {
+    config: AlertConfig,
+    sinks: Vec<Arc<dyn AlertSink>>,
+    client: reqwest::Client,
+    state: Mutex<AlertState>,
+}
+
+/// 告警器
+///
+/// 可以廉价 clone，在 handler、trade、下单任务之间共享。
+/// 没有配置任何通道时所有调用都是空操作。
+#[derive(Clone)]
+pub struct Alerter {
+    inner: Arc<AlerterInner>,
+}
+
+impl Default for Alerter {
+    fn default() -> Self {
+        Self::new(AlertConfig::default())
+    }
+}
+
+impl Debug for Alerter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Alerter")
+            .field("sinks", &self.inner.sinks)
+            .finish()
+    }
+}
+
+impl Alerter {
+    pub fn new(config: AlertConfig) -> Self {
+        let sinks = config.sinks.iter().map(SinkConfig::build).collect();
+        Self::with_sinks(config, sinks)
+    }
+
+    /// 使用自定义通道创建告警器
+    pub fn with_sinks(config: AlertConfig, sinks: Vec<Arc<dyn AlertSink>>) -> Self {
+        Self {
+            inner: Arc::new(AlerterInner {
+                config,
+                sinks,
+                client: reqwest::Client::new(),
+                state: Mutex::new(AlertState::default()),
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.inner.sinks.is_empty()
+    }
+
+    /// 周期性地上报某个连接的状态，断线超过阈值时告警一次
+    pub fn on_disconnect_state(&self, source: &str, disconnected: bool) {
+        if !self.enabled() {
+            return;
+        }
+        let event = self.with_state(|state, config| {
+            state.on_disconnect_state(config, source, disconnected, Instant::now())
+        });
+        if let Some(event) = event {
+            self.notify(event);
+        }
+    }
+
+    /// 上报订单状态，用于统计连续拒单
+    pub fn on_order_state(&self, session_id: u16, state: State) {
+        if !self.enabled() {
+            return;
+        }
+        let event = self.with_state(|alert_state, config| {
+            alert_state.on_order_state(config, session_id, state)
+        });
+        if let Some(event) = event {
+            self.notify(event);
+        }
+    }
+
+    pub fn on_margin_call(&self, detail: String) {
+        if self.inner.config.margin_call {
+            self.notify(AlertEvent::MarginCall { detail });
+        }
+    }
+
+    pub fn on_risk_limit_breach(&self, session_id: u16, detail: String) {
+        if self.inner.config.risk_limit {
+            self.notify(AlertEvent::RiskLimitBreach { session_id, detail });
+        }
+    }
+
+    pub fn on_reconcile_mismatch(&self, kind: ReconcileKind, key: &str, local: f64, remote: f64) {
+        if self.inner.config.reconcile {
+            self.notify(AlertEvent::ReconcileMismatch {
+                kind,
+                key: key.to_string(),
+                local,
+                remote,
+            });
+        }
+    }
+
+    pub fn on_low_balance(&self, asset: &str, free: f64, threshold: f64) {
+        if self.inner.config.low_balance {
+            self.notify(AlertEvent::LowBalance {
+                asset: asset.to_string(),
+                free,
+                threshold,
+            });
+        }
+    }
+
+    pub fn on_api_key(&self, detail: String) {
+        if self.inner.config.api_key {
+            self.notify(AlertEvent::ApiKey { detail });
+        }
+    }
+
+    pub fn on_stale_order(&self, session_id: u16, order_id: u32, detail: String) {
+        if self.inner.config.stale_order {
+            self.notify(AlertEvent::StaleOrder {
+                session_id,
+                order_id,
+                detail,
+            });
+        }
+    }
+
+    pub fn on_heartbeat_timeout(&self, session_id: u16, detail: String) {
+        if self.inner.config.heartbeat {
+            self.notify(AlertEvent::HeartbeatTimeout { session_id, detail });
+        }
+    }
+
+    /// 发送每日运维报告，是否发送由报告的配置决定
+    pub fn on_daily_report(&self, day: &str, summary: String) {
+        self.notify(AlertEvent::DailyReport {
+            day: day.to_string(),
+            summary,
+        });
+    }
+
+    /// 发送告警，在后台任务中逐个通道推送
+    pub fn notify(&self, event: AlertEvent) {
+        if !self.enabled() {
+            return;
+        }
+        let alert = event.into_alert();
+        let now = Instant::now();
+        if !self.with_state(|state, config| state.allow(config, &alert.key, now)) {
+            debug!("Alert {} suppressed by cooldown", alert.key);
+            return;
+        }
+        warn!("Alert {:?}", alert);
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            error!("No tokio runtime, drop alert {}", alert.key);
+            return;
+        };
+        for sink in self.inner.sinks.iter() {
+            let request = sink.request(&self.inner.client, &alert);
+            runtime.spawn(async move {
+                match request.send().await {
+                    Ok(rsp) if !rsp.status().is_success() => {
+                        error!("Alert sink responded {}", rsp.status())
+                    }
+                    Ok(_) => (),
+                    Err(e) => error!("Send alert failed: {}", e),
+                }
+            });
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut AlertState, &AlertConfig) -> R) -> R {
+        let mut state = self
+            .inner
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut state, &self.inner.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disconnect_trigger() {
+        let config = AlertConfig {
+            disconnect_secs: 10,
+            ..Default::default()
+        };
+        let mut state = AlertState::default();
+        let t0 = Instant::now();
+
+        assert!(
+            state
+                .on_disconnect_state(&config, "market", true, t0)
+                .is_none()
+        );
+        assert!(
+            state
+                .on_disconnect_state(&config, "market", true, t0 + Duration::from_secs(5))
+                .is_none()
+        );
+        assert!(matches!(
+            state.on_disconnect_state(&config, "market", true, t0 + Duration::from_secs(10)),
+            Some(AlertEvent::ExchangeDisconnected { secs: 10, .. })
+        ));
+        // 同一次断线只告警一次
+        assert!(
+            state
+                .on_disconnect_state(&config, "market", true, t0 + Duration::from_secs(20))
+                .is_none()
+        );
+
+        // 重连后重新计时
+        assert!(
+            state
+                .on_disconnect_state(&config, "market", false, t0 + Duration::from_secs(21))
+                .is_none()
+        );
+        let t1 = t0 + Duration::from_secs(30);
+        assert!(
+            state
+                .on_disconnect_state(&config, "market", true, t1)
+                .is_none()
+        );
+        assert!(
+            state
+                .on_disconnect_state(&config, "market", true, t1 + Duration::from_secs(10))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_reject_streak() {
+        let config = AlertConfig {
+            reject_streak: 3,
+            ..Default::default()
+        };
+        let mut state = AlertState::default();
+
+        assert!(state.on_order_state(&config, 1, State::REJECTED).is_none());
+        assert!(state.on_order_state(&config, 1, State::REJECTED).is_none());
+        // 其它 session 不影响计数
+        assert!(state.on_order_state(&config, 2, State::REJECTED).is_none());
+        assert!(matches!(
+            state.on_order_state(&config, 1, State::REJECTED),
+            Some(AlertEvent::OrderRejectStreak {
+                session_id: 1,
+                count: 3
+            })
+        ));
+        assert!(state.on_order_state(&config, 1, State::REJECTED).is_none());
+
+        // 成功下单后清零
+        assert!(state.on_order_state(&config, 1, State::NEW).is_none());
+        assert!(state.on_order_state(&config, 1, State::REJECTED).is_none());
+        assert!(state.on_order_state(&config, 1, State::REJECTED).is_none());
+        assert!(state.on_order_state(&config, 1, State::REJECTED).is_some());
+    }
+
+    #[test]
+    fn test_reconcile_key() {
+        let alert = |kind, key: &str| {
+            AlertEvent::ReconcileMismatch {
+                kind,
+                key: key.to_string(),
+                local: 1.0,
+                remote: 0.5,
+            }
+            .into_alert()
+        };
+        // 同一个交易对的持仓与盈亏不一致分别冷却
+        let position = alert(ReconcileKind::Position, "btcusdt");
+        let pnl = alert(ReconcileKind::Pnl, "btcusdt");
+        assert_eq!(position.key, "reconcile:position:btcusdt");
+        assert_eq!(pnl.key, "reconcile:pnl:btcusdt");
+        assert_eq!(pnl.title, "btcusdt pnl mismatch");
+        assert_eq!(
+            alert(ReconcileKind::Balance, "BNB").message,
+            "local 1 != exchange 0.5"
+        );
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let config = AlertConfig::default();
+        let mut state = AlertState::default();
+        let t0 = Instant::now();
+
+        assert!(state.allow(&config, "margin_call", t0));
+        assert!(!state.allow(&config, "margin_call", t0 + Duration::from_secs(1)));
+        assert!(state.allow(&config, "risk:1", t0 + Duration::from_secs(1)));
+        assert!(state.allow(&config, "margin_call", t0 + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_sink_config() {
+        let s = r#"{"sinks": [{"type": "webhook", "url": "http://localhost/alert"},
+                              {"type": "telegram", "token": "t", "chat_id": "1"},
+                              {"type": "slack", "webhook_url": "https://hooks.slack.com/x"}],
+                    "reject_streak": 2}"#;
+        let config: AlertConfig = serde_json::from_str(s).unwrap();
+        assert_eq!(config.sinks.len(), 3);
+        assert_eq!(config.reject_streak, 2);
+        assert_eq!(config.disconnect_secs, 30);
+
+        let alerter = Alerter::new(config);
+        assert!(alerter.enabled());
+        assert!(!Alerter::default().enabled());
+    }
+}
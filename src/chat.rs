@@ -1,5 +1,6 @@
+use crate::codec::WireFormat;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, str::FromStr};
+use std::{collections::BTreeMap, fmt::Debug, str::FromStr};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Request<T> {
@@ -40,6 +41,136 @@ pub struct SError {
     pub msg: String,
 }
 
+/// 订阅参数，可以是流名称，或附带标签的 `{"stream": ..., "tag": ...}`；
+/// 网关在该流转发的每条数据上原样带回标签。深度流还可以带上 `bucket`，
+/// 由网关按该宽度的价格带合并档位后转发。带上 `raw` 时网关还转发交易所的原始帧，见 [`RawMode`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SSubscription {
+    Stream(String),
+    /// 放在最前，带 `raw` 的订阅不会按其他形式解析
+    Raw {
+        stream: String,
+        raw: RawMode,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bucket: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+    /// 放在 Tagged 之前，带 `bucket` 的订阅不会按 Tagged 解析
+    Bucketed {
+        stream: String,
+        bucket: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+    Tagged {
+        stream: String,
+        tag: String,
+    },
+}
+
+impl SSubscription {
+    pub fn stream(&self) -> &String {
+        match self {
+            Self::Stream(stream) => stream,
+            Self::Raw { stream, .. } => stream,
+            Self::Bucketed { stream, .. } => stream,
+            Self::Tagged { stream, .. } => stream,
+        }
+    }
+
+    pub fn tag(&self) -> Option<&String> {
+        match self {
+            Self::Stream(_) => None,
+            Self::Raw { tag, .. } => tag.as_ref(),
+            Self::Bucketed { tag, .. } => tag.as_ref(),
+            Self::Tagged { tag, .. } => Some(tag),
+        }
+    }
+
+    /// 深度的分档宽度
+    pub fn bucket(&self) -> Option<f64> {
+        match self {
+            Self::Raw { bucket, .. } => *bucket,
+            Self::Bucketed { bucket, .. } => Some(*bucket),
+            _ => None,
+        }
+    }
+
+    pub fn raw(&self) -> RawMode {
+        match self {
+            Self::Raw { raw, .. } => *raw,
+            _ => RawMode::Off,
+        }
+    }
+}
+
+/// 原始帧的转发方式。`"raw": true` 与 `"only"` 相同，只转发原始帧；`"both"` 同时转发原始帧与
+/// 网关解析后的数据。原始帧为 `{"venue": ..., "stream": ..., "raw": <交易所的帧>}`，订阅带了标签时
+/// 同样带上 `tag`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase", from = "RawFlag")]
+pub enum RawMode {
+    #[default]
+    Off,
+    Both,
+    Only,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawFlag {
+    Flag(bool),
+    Mode(RawModeName),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawModeName {
+    Off,
+    Both,
+    Only,
+}
+
+impl From<RawFlag> for RawMode {
+    fn from(flag: RawFlag) -> Self {
+        match flag {
+            RawFlag::Flag(false) | RawFlag::Mode(RawModeName::Off) => Self::Off,
+            RawFlag::Flag(true) | RawFlag::Mode(RawModeName::Only) => Self::Only,
+            RawFlag::Mode(RawModeName::Both) => Self::Both,
+        }
+    }
+}
+
+/// 订阅请求中单个流的结果，校验失败的流不会订阅，其余的流照常订阅
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SStreamResult {
+    pub stream: String,
+    pub accepted: bool,
+    /// 被拒绝的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<SError>,
+}
+
+impl SStreamResult {
+    pub fn accepted(stream: &str) -> Self {
+        Self {
+            stream: stream.to_string(),
+            accepted: true,
+            error: None,
+        }
+    }
+
+    pub fn rejected(stream: &str, error: SError) -> Self {
+        Self {
+            stream: stream.to_string(),
+            accepted: false,
+            error: Some(error),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SPositionReq {
     pub session_id: u16,
@@ -52,12 +183,104 @@ pub struct SPositionRsp {
     pub positions: Vec<Position>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SAttributionReq {
+    pub session_id: u16,
+}
+
+/// 一个归因分组在本次网关运行期间的表现，分组为 `strategy:{name}`、`tag:{name}` 或 `untagged`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SAttribution {
+    pub name: String,
+    pub orders: u64,
+    pub fills: u64,
+    pub volume: f64,
+    /// 成交额，价格乘以数量
+    pub notional: f64,
+    /// 手续费资产 -> 手续费
+    pub commissions: BTreeMap<String, f64>,
+    /// 按移动平均成本计算的已实现盈亏，计价资产
+    pub realized_pnl: f64,
+    /// 按成交额加权的相对到达价滑点，正数为成本
+    pub slippage_bps: f64,
+    /// 分组中不为零的持仓
+    pub positions: Vec<SAttributionPosition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SAttributionPosition {
+    pub symbol: String,
+    pub net: f64,
+    pub avg_price: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SAttributionRsp {
+    pub session_id: u16,
+    pub attribution: Vec<SAttribution>,
+}
+
+/// 查询会话的挂单与吃单成交统计，`symbol` 为空时返回所有交易对，`days` 为空时返回保留的所有日期
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SLiquidityReq {
+    pub session_id: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// 最近的几个自然日，包括当天
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+}
+
+/// 一个交易对一个自然日的挂单（maker）与吃单（taker）成交
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SLiquidity {
+    /// 按配置的时区划分的自然日，如 2024-01-31
+    pub date: String,
+    pub symbol: String,
+    pub maker_fills: u64,
+    pub taker_fills: u64,
+    pub maker_volume: f64,
+    pub taker_volume: f64,
+    /// 成交额，价格乘以数量
+    pub maker_notional: f64,
+    pub taker_notional: f64,
+    /// 挂单成交额占总成交额的比例
+    pub maker_ratio: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SLiquidityRsp {
+    pub session_id: u16,
+    pub stats: Vec<SLiquidity>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SLogin {
     pub session_id: u16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     pub trading: bool,
+    /// 连接断开时由网关撤销该会话所有未完成的订单
+    #[serde(default)]
+    pub cancel_on_disconnect: bool,
+    /// 在订单回报之外逐笔推送成交，见 [`SFill`]
+    #[serde(default)]
+    pub fills: bool,
+    /// 协商的编码，见 [`crate::codec`]
+    #[serde(default, skip_serializing_if = "WireFormat::is_json")]
+    pub format: WireFormat,
+    /// 承诺至少每隔这个时间发送一次请求，空闲时发送 `heartbeat`；网关开启心跳巡检时超时会保护该会话
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_ms: Option<u64>,
+    /// 会话所属的租户，网关配置了租户时必填，见 [`crate::tenant`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// 租户的 token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// 客户端实现的协议版本，不带时按最初的版本处理，见 [`crate::compat`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -88,6 +311,433 @@ pub struct SGeneralKline {
     pub is_closed: bool,     // 这根K线是否完结 (x)
     pub buy_volume: f64,     // 主动买入成交量 (V)
     pub buy_amount: f64,     // 主动买入成交额 (Q)
+    pub backfill: bool,      // 重连后通过 REST 补齐的K线
+}
+
+/// 网关按时钟推送的收线事件，`kline` 为收线时已知的最近一根 K 线
+#[derive(Debug, Clone, Serialize)]
+pub struct SBarClose {
+    /// 收线时刻，即下一根 K 线的起始时间
+    pub bar_close: i64,
+    pub symbol: String,
+    pub stream: String,
+    pub interval: String,
+    pub kline: Option<SGeneralKline>,
+}
+
+/// 注册派生流请求，`source` 为策略端订阅的流，`expr` 见 [`crate::expr`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SDerive {
+    pub name: String,
+    pub source: String,
+    pub expr: String,
+}
+
+/// 断线重连后请求补发 `offset` 之后的行情，`stream` 为策略端订阅的流
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SResume {
+    pub stream: String,
+    pub offset: u64,
+}
+
+/// 派生流在一个 tick 上的计算结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SDerived {
+    pub time: i64,
+    pub symbol: String,
+    /// `{symbol}@derived:{name}`
+    pub stream: String,
+    pub values: BTreeMap<String, f64>,
+}
+
+/// 按逐笔归集成交统计的主动买卖，见 `{symbol}@cvd:{window}`
+#[derive(Debug, Clone, Serialize)]
+pub struct STradeFlow {
+    pub time: i64,
+    pub symbol: String,
+    /// `{symbol}@cvd:{window}`
+    pub stream: String,
+    /// 开始统计以来主动买入量减主动卖出量的累计值
+    pub cvd: f64,
+    /// 窗口内的主动买入量
+    pub buy_volume: f64,
+    /// 窗口内的主动卖出量
+    pub sell_volume: f64,
+    /// 窗口内 (买 - 卖) / (买 + 卖)，没有成交时为 0
+    pub imbalance: f64,
+}
+
+/// 查询会话的策略参数，查询的连接之后会收到该会话参数的变化
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SParamsReq {
+    pub session_id: u16,
+}
+
+/// 运维修改会话的策略参数，值为 null 的键被删除；`token` 须与网关配置的 `admin_token` 一致
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SSetParams {
+    pub session_id: u16,
+    pub params: BTreeMap<String, serde_json::Value>,
+    pub token: String,
+}
+
+/// 运维核对本地与交易所的状态，`token` 须与网关配置的 `admin_token` 一致；`heal` 时撤销本地不认识的挂单，
+/// 并把持仓差额计入 `adopt_session`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SSanityCheck {
+    pub token: String,
+    #[serde(default)]
+    pub heal: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adopt_session: Option<u16>,
+}
+
+/// 交易所有、本地没有记录的挂单，`client_order_id` 不是网关的格式时为外部下单
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SUnknownOrder {
+    pub symbol: String,
+    pub order_id: i64,
+    pub client_order_id: String,
+}
+
+/// 本地认为还在挂单、交易所已经没有的订单
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SMissingOrder {
+    pub session_id: u16,
+    pub order_id: u32,
+    pub symbol: String,
+}
+
+/// 本地与交易所不一致的持仓或余额，持仓的 `key` 为持仓键，余额的为资产名
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SSanityDiff {
+    pub key: String,
+    pub local: f64,
+    pub exchange: f64,
+}
+
+/// 核对的结果，`clean` 为修复之前是否一致
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SSanityReport {
+    pub time: i64,
+    pub unknown_orders: Vec<SUnknownOrder>,
+    pub missing_orders: Vec<SMissingOrder>,
+    pub positions: Vec<SSanityDiff>,
+    pub balances: Vec<SSanityDiff>,
+    /// 修复时撤销的交易所订单 id
+    pub canceled: Vec<i64>,
+    /// 修复时计入 `adopt_session` 的持仓键
+    pub adopted: Vec<String>,
+    pub clean: bool,
+}
+
+/// 运维暂停（`halt_trading`）或恢复（`resume_trading`）一个交易对的交易，`token` 须与网关配置的
+/// `admin_token` 一致；暂停时 `cancel` 撤销所有会话在该交易对上的挂单
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct STradingSwitch {
+    pub token: String,
+    pub symbol: String,
+    #[serde(default)]
+    pub cancel: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// 交易对的交易状态，暂停与恢复时推送给所有连接，登录时推送当前暂停的交易对；同时作为
+/// `halt_trading`、`resume_trading` 的回复
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct STradingStatus {
+    /// 暂停或恢复的时间
+    pub time: i64,
+    pub symbol: String,
+    /// 为 false 时网关拒绝该交易对的新订单
+    pub trading: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// 暂停时撤销的挂单笔数
+    #[serde(default)]
+    pub canceled: usize,
+}
+
+/// 运维导出网关的状态（`export_state`），`token` 须与网关配置的 `admin_token` 一致，回复 [`SGatewayState`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SExportState {
+    pub token: String,
+}
+
+/// 运维把另一个网关导出的状态导入本网关（`import_state`），`token` 须与网关配置的 `admin_token` 一致
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SImportState {
+    pub token: String,
+    pub state: SGatewayState,
+}
+
+/// 迁移或升级网关时保存的状态文件，新网关导入后策略端重新登录即可接着交易
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SGatewayState {
+    /// 文件格式的版本，导入时须与网关支持的版本一致
+    pub version: u32,
+    pub venue: String,
+    /// 导出的时间
+    pub time: i64,
+    pub sessions: Vec<SSessionState>,
+    /// 网关自己下单使用的订单号计数器，如 `flatten`、`roll`，导入后不会重复使用已发出的订单号
+    #[serde(default)]
+    pub counters: BTreeMap<String, u32>,
+}
+
+/// 一个会话的持仓、未终结订单的归属与登录选项
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SSessionState {
+    pub session_id: u16,
+    pub positions: Vec<Position>,
+    /// order_id -> symbol，导出时尚未终结的订单
+    #[serde(default)]
+    pub working: BTreeMap<u32, String>,
+    #[serde(default)]
+    pub cancel_on_disconnect: bool,
+    #[serde(default)]
+    pub fills: bool,
+}
+
+/// 导入的结果，`counters` 为导入后的计数器
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SImportReport {
+    pub time: i64,
+    pub sessions: Vec<u16>,
+    pub positions: usize,
+    pub orders: usize,
+    pub counters: BTreeMap<String, u32>,
+}
+
+/// 交易场所或交易对的状态变化，推送给所有连接。`symbol` 为空时是整个交易场所，`status` 为 `NORMAL` 或
+/// `MAINTENANCE`；否则为 exchangeInfo 中交易对的状态，如 `TRADING`、`BREAK`、`HALT`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SVenueStatus {
+    /// 网关发现变化的时间
+    pub time: i64,
+    pub venue: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    pub status: String,
+    /// 变化前的状态
+    pub previous: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+}
+
+/// 订单会超过交易所的挂单数上限时网关排队下单，排队与发出时推送给下单的会话，见网关的 `order_caps`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SOrderCap {
+    pub time: i64,
+    pub symbol: String,
+    pub order_id: u32,
+    /// 挡住订单的过滤器，如 `MAX_NUM_ORDERS`、`MAX_NUM_ALGO_ORDERS`、`EXCHANGE_MAX_NUM_ORDERS`
+    pub filter: String,
+    pub limit: i64,
+    /// 网关记录的该交易对已发出、尚未结束的订单数
+    pub open: i64,
+    /// 为 true 时订单开始排队，为 false 时已经发出
+    pub queued: bool,
+}
+
+/// 会话的全部策略参数，参数变化时推送，`changed` 为这次变化的键
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SParams {
+    pub session_id: u16,
+    pub params: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub changed: Vec<String>,
+}
+
+/// 查询网关估计的波动率，`window` 为 K 线周期（如 `1m`），需要已订阅 `{symbol}@kline:{window}`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SVolatilityReq {
+    pub symbol: String,
+    pub window: String,
+}
+
+/// 查询网关缓存的 K 线，需要已订阅 `{symbol}@kline:{interval}`；`start`、`end` 为毫秒时间，不填时不限，
+/// K 线数超过 `max_points` 时合并相邻的 K 线
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SCandlesReq {
+    pub symbol: String,
+    pub interval: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_points: Option<usize>,
+}
+
+/// 按列返回的 K 线，第 i 个点由各列的第 i 个值组成，`time` 为起始时间
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SCandles {
+    pub symbol: String,
+    pub interval: String,
+    /// 每个点合并的 K 线数，为 1 时没有降采样
+    pub bars_per_point: usize,
+    pub time: Vec<i64>,
+    pub open: Vec<f64>,
+    pub high: Vec<f64>,
+    pub low: Vec<f64>,
+    pub close: Vec<f64>,
+    pub volume: Vec<f64>,
+}
+
+/// 网关按收线 K 线估计的年化波动率，样本不足时对应的估计为空
+#[derive(Debug, Clone, Serialize)]
+pub struct SVolatility {
+    /// 最近一根参与估计的 K 线的结束时间，还没有 K 线时为 0
+    pub time: i64,
+    pub symbol: String,
+    /// `{symbol}@vol:{window}`
+    pub stream: String,
+    pub window: String,
+    /// 参与估计的 K 线数
+    pub bars: usize,
+    /// 收盘价对数收益率的均方根
+    pub realized: Option<f64>,
+    /// 按最高最低价的 Parkinson 估计
+    pub parkinson: Option<f64>,
+    /// 收益率平方的指数加权平均
+    pub ewma: Option<f64>,
+}
+
+/// 查询合约网关计算的基差，需要已订阅 `{symbol}@basis`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SBasisReq {
+    pub symbol: String,
+}
+
+/// 同一交易对永续合约与现货的基差，订阅 `{symbol}@basis` 后推送，价格均为买一卖一的中间价
+#[derive(Debug, Clone, Serialize)]
+pub struct SBasis {
+    /// 网关计算的时间
+    pub time: i64,
+    pub symbol: String,
+    /// `{symbol}@basis`
+    pub stream: String,
+    pub spot: f64,
+    pub perp: f64,
+    /// `perp - spot`
+    pub basis: f64,
+    /// 基差相对现货价格的比例
+    pub basis_rate: f64,
+    /// 当期资金费率，还没有收到标记价格时为空
+    pub funding_rate: Option<f64>,
+    /// 下次收取资金费的时间，还没有收到标记价格时为 0
+    pub next_funding_time: i64,
+    /// 按当期资金费率年化的收益，持有现货、做空永续时为正
+    pub annualized_funding: Option<f64>,
+}
+
+/// 交割合约的交割提醒与交割结算，推送给持有该合约的会话
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SDelivery {
+    pub symbol: String,
+    /// 交割时间
+    pub delivery_time: i64,
+    /// 距交割的毫秒数，结算时为 0
+    pub remaining_ms: i64,
+    /// 会话在该合约上的净持仓，结算时为结算前的持仓
+    pub position: f64,
+    /// false 为交割前的提醒，true 为交割后网关已把会话持仓置零
+    pub settled: bool,
+}
+
+/// 季度合约交割前的移仓提醒，推送给持有当季合约的会话
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SRoll {
+    /// 连续合约别名，如 `btcusdt_quarter`
+    pub alias: String,
+    /// 别名当前对应的合约
+    pub contract: String,
+    /// 到期后接替的合约
+    pub next: Option<String>,
+    /// `contract` 的交割时间
+    pub delivery_time: i64,
+    /// 距交割的毫秒数
+    pub remaining_ms: i64,
+}
+
+/// 用连续合约别名订阅的流换到了新的合约，推送给该流的订阅者；之后该流的数据来自 `symbol`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SSymbolChanged {
+    /// 网关切换的时间
+    pub time: i64,
+    /// 连续合约别名，如 `btcusdt_quarter`
+    pub alias: String,
+    /// 订阅时的流，如 `btcusdt_quarter@kline:1m`
+    pub stream: String,
+    /// 切换前的合约
+    pub previous: String,
+    /// 切换后的合约
+    pub symbol: String,
+}
+
+/// 把会话的挂单与持仓从别名当前对应的合约移到下一个合约
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SRollReq {
+    pub alias: String,
+    /// 挂单平移的价差 `next - contract`，不填时按两个合约的中间价计算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f64>,
+}
+
+/// 移仓撤销的挂单与网关发出的订单，订单回报照常推送
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SRollResult {
+    pub alias: String,
+    /// 移出的合约
+    pub contract: String,
+    /// 移入的合约
+    pub next: String,
+    pub canceled: Vec<u32>,
+    pub orders: Vec<u32>,
+}
+
+/// 查询交易所的深度快照，`limit` 为档位数
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SDepthSnapshotReq {
+    pub symbol: String,
+    pub limit: u32,
+}
+
+/// 交易所 REST 接口的深度快照，本地订单簿从 `last_update_id` 之后的增量开始更新
+#[derive(Debug, Clone, Serialize)]
+pub struct SDepthSnapshot<T> {
+    /// 网关从交易所取得快照的时间，返回缓存时早于查询时间
+    pub time: i64,
+    pub symbol: String,
+    pub last_update_id: i64,
+    pub bids: Vec<T>,
+    pub asks: Vec<T>,
+}
+
+/// 查询标的下的期权合约
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SOptionChainReq {
+    /// 标的，如 btcusdt
+    pub underlying: String,
+}
+
+/// 期权的标记价格与希腊值，隐含波动率为小数
+#[derive(Debug, Clone, Serialize)]
+pub struct SOptionGreeks {
+    pub time: i64,
+    pub symbol: String,
+    /// `{symbol}@greeks`，按标的订阅时为 `{asset}@mark`
+    pub stream: String,
+    pub mark_price: f64,
+    pub mark_iv: f64,
+    pub bid_iv: f64,
+    pub ask_iv: f64,
+    pub delta: f64,
+    pub gamma: f64,
+    pub theta: f64,
+    pub vega: f64,
 }
 
 /// 订单信息
@@ -110,6 +760,7 @@ pub struct SOrder {
 }
 
 impl SOrder {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: u32,
         symbol: String,
@@ -138,6 +789,43 @@ impl SOrder {
         }
     }
 }
+
+/// 订单的一笔成交，`SOrder` 只带最近一笔成交与累计成交量
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SFill {
+    pub internal_id: u32,
+    pub order_id: i64,
+    pub symbol: String,
+    pub side: Side,
+    pub trade_id: i64,
+    pub time: i64,
+    pub price: f64,
+    pub quantity: f64,
+    /// 作为挂单成交
+    pub maker: bool,
+    pub commission: f64,
+    /// 手续费资产，交易所没有给出时为空
+    #[serde(default)]
+    pub commission_asset: String,
+}
+
+/// 一种资产的余额
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SBalance {
+    pub asset: String,
+    /// 可用余额
+    pub free: f64,
+    /// 挂单等冻结的余额
+    pub locked: f64,
+    /// 交易所更新余额的时间
+    pub time: i64,
+}
+
+impl SBalance {
+    pub fn total(&self) -> f64 {
+        self.free + self.locked
+    }
+}
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[allow(non_camel_case_types)]
 pub enum State {
@@ -159,6 +847,10 @@ pub enum State {
     LIVE, // 等待成交 (OKX)
     #[allow(non_camel_case_types)]
     MMP_CANCELED, // 做市商保护机制导致的自动撤单 (OKX)
+
+    // 网关状态
+    #[allow(non_camel_case_types)]
+    EXPIRED_BY_GATEWAY, // 超过 ttl_ms 未完成，由网关撤销
 }
 
 impl std::str::FromStr for State {
@@ -184,12 +876,27 @@ impl std::str::FromStr for State {
             "filled" => Ok(State::FILLED),
             "mmp_canceled" => Ok(State::MMP_CANCELED),
 
+            // 网关状态
+            "EXPIRED_BY_GATEWAY" => Ok(State::EXPIRED_BY_GATEWAY),
+
             _ => Err(()),
         }
     }
 }
 
 impl State {
+    /// 订单仍在交易所挂着，可能继续成交
+    pub fn is_working(&self) -> bool {
+        matches!(
+            self,
+            State::NEW
+                | State::PENDING_NEW
+                | State::PARTIALLY_FILLED
+                | State::PENDING_CANCEL
+                | State::LIVE
+        )
+    }
+
     /// 将状态转换为 Binance 格式的字符串
     pub fn to_binance_str(&self) -> &'static str {
         match self {
@@ -204,6 +911,7 @@ impl State {
             State::EXPIRED_IN_MATCH => "EXPIRED_IN_MATCH",
             State::LIVE => "NEW", // OKX 的 live 对应 Binance 的 NEW
             State::MMP_CANCELED => "CANCELED", // OKX 的 mmp_canceled 对应 Binance 的 CANCELED
+            State::EXPIRED_BY_GATEWAY => "CANCELED",
         }
     }
 
@@ -221,40 +929,41 @@ impl State {
             State::EXPIRED_IN_MATCH => "canceled",
             State::LIVE => "live",
             State::MMP_CANCELED => "mmp_canceled",
+            State::EXPIRED_BY_GATEWAY => "canceled",
         }
     }
 
     /// 从 Binance 状态字符串创建状态
-    pub fn from_binance_str(s: &str) -> Result<Self, ()> {
+    pub fn from_binance_str(s: &str) -> Option<Self> {
         match s {
-            "NEW" => Ok(State::NEW),
-            "PENDING_NEW" => Ok(State::PENDING_NEW),
-            "PARTIALLY_FILLED" => Ok(State::PARTIALLY_FILLED),
-            "FILLED" => Ok(State::FILLED),
-            "CANCELED" => Ok(State::CANCELED),
-            "PENDING_CANCEL" => Ok(State::PENDING_CANCEL),
-            "REJECTED" => Ok(State::REJECTED),
-            "EXPIRED" => Ok(State::EXPIRED),
-            "EXPIRED_IN_MATCH" => Ok(State::EXPIRED_IN_MATCH),
-            _ => Err(()),
+            "NEW" => Some(State::NEW),
+            "PENDING_NEW" => Some(State::PENDING_NEW),
+            "PARTIALLY_FILLED" => Some(State::PARTIALLY_FILLED),
+            "FILLED" => Some(State::FILLED),
+            "CANCELED" => Some(State::CANCELED),
+            "PENDING_CANCEL" => Some(State::PENDING_CANCEL),
+            "REJECTED" => Some(State::REJECTED),
+            "EXPIRED" => Some(State::EXPIRED),
+            "EXPIRED_IN_MATCH" => Some(State::EXPIRED_IN_MATCH),
+            _ => None,
         }
     }
 
     /// 从 OKX 状态字符串创建状态
-    pub fn from_okx_str(s: &str) -> Result<Self, ()> {
+    pub fn from_okx_str(s: &str) -> Option<Self> {
         match s {
-            "live" => Ok(State::LIVE),
-            "canceled" => Ok(State::CANCELED),
-            "partially_filled" => Ok(State::PARTIALLY_FILLED),
-            "filled" => Ok(State::FILLED),
-            "mmp_canceled" => Ok(State::MMP_CANCELED),
-            _ => Err(()),
+            "live" => Some(State::LIVE),
+            "canceled" => Some(State::CANCELED),
+            "partially_filled" => Some(State::PARTIALLY_FILLED),
+            "filled" => Some(State::FILLED),
+            "mmp_canceled" => Some(State::MMP_CANCELED),
+            _ => None,
         }
     }
 }
 
 /// Side of an order, Buy or Sell
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 pub enum Side {
     BUY,
     SELL,
@@ -274,16 +983,33 @@ impl FromStr for Side {
 /// Type of an order, Limit, Market, Stop, etc.
 /// Reference:
 /// https://developers.binance.com/docs/zh-CN/binance-spot-api-docs/testnet/websocket-api/trading-requests#place-new-order-trade
+/// https://developers.binance.com/docs/zh-CN/derivatives/usds-margined-futures/trade/rest-api
 ///
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+/// 现货与合约的订单类型取并集，序列化为币安的写法；反序列化同时接受 OKX 的写法，
+/// 如 `limit`、`post_only`、`trigger`、`move_order_stop`
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 #[allow(non_camel_case_types)]
 pub enum OrderType {
+    #[serde(alias = "limit")]
     LIMIT,
+    #[serde(alias = "market")]
     MARKET,
+    /// 合约止损限价单
+    #[serde(alias = "trigger")]
+    STOP,
+    /// 合约止损市价单
+    #[serde(alias = "conditional")]
+    STOP_MARKET,
     STOP_LOSS,
     STOP_LOSS_LIMIT,
     TAKE_PROFIT,
     TAKE_PROFIT_LIMIT,
+    /// 合约止盈市价单
+    TAKE_PROFIT_MARKET,
+    /// 合约跟踪止损单
+    #[serde(alias = "move_order_stop")]
+    TRAILING_STOP_MARKET,
+    #[serde(alias = "post_only")]
     LIMIT_MAKER,
 }
 
@@ -291,47 +1017,318 @@ impl FromStr for OrderType {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "LIMIT" => Ok(Self::LIMIT),
-            "MARKET" => Ok(Self::MARKET),
-            "STOP_LOSS" => Ok(Self::STOP_LOSS),
-            "STOP_LOSS_LIMIT" => Ok(Self::STOP_LOSS_LIMIT),
-            "TAKE_PROFIT" => Ok(Self::TAKE_PROFIT),
-            "TAKE_PROFIT_LIMIT" => Ok(Self::TAKE_PROFIT_LIMIT),
-            "LIMIT_MAKER" => Ok(Self::LIMIT_MAKER),
-            _ => unreachable!(),
-        }
+        from_name(s)
     }
 }
 
 /// Reference:
 /// https://developers.binance.com/docs/zh-CN/binance-spot-api-docs/enums#%E7%94%9F%E6%95%88%E6%97%B6%E9%97%B4-timeinforce
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+///
+/// GTX 与 GTD 只用于合约；反序列化同时接受小写的写法
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum TimeInForce {
+    #[serde(alias = "gtc")]
     GTC,
+    #[serde(alias = "ioc")]
     IOC,
+    #[serde(alias = "fok")]
     FOK,
+    /// 只做 maker，会立即成交时被拒绝
+    #[serde(alias = "gtx", alias = "post_only")]
+    GTX,
+    /// 到期自动撤销
+    #[serde(alias = "gtd")]
+    GTD,
 }
 
 impl FromStr for TimeInForce {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "GTC" => Ok(Self::GTC),
-            "IOC" => Ok(Self::IOC),
-            "FOK" => Ok(Self::FOK),
-            _ => unreachable!(),
-        }
+        from_name(s)
     }
 }
 
+/// 按 serde 的名称与别名解析单元枚举，`FromStr` 与反序列化接受的写法保持一致
+fn from_name<'de, T: Deserialize<'de>>(s: &'de str) -> Result<T, ()> {
+    use serde::de::value::{BorrowedStrDeserializer, Error};
+    T::deserialize(BorrowedStrDeserializer::<Error>::new(s)).map_err(|_| ())
+}
+
+/// 持仓方向，单向持仓模式下为 BOTH，双向持仓模式下多空两腿分别为 LONG、SHORT
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum PositionSide {
+    BOTH,
+    LONG,
+    SHORT,
+}
+
+impl FromStr for PositionSide {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_name(s)
+    }
+}
+
+/// 保证金模式，同时接受账户推送中小写的写法
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MarginType {
+    #[serde(alias = "cross", alias = "crossed")]
+    CROSSED,
+    #[serde(alias = "isolated")]
+    ISOLATED,
+}
+
+/// 会话的净持仓，`net` 由网关按成交统计并持久化
+///
+/// `avg_price` 与 `cost_basis` 是网关按本会话的成交计算的开仓均价与成本，见 [`Position::on_fill`]，
+/// 只保存在内存中。合约另外带上交易所账户级别的持仓方向、入场价、未实现盈亏、保证金模式与杠杆，
+/// 这些字段没有时不序列化也不入库，旧的策略端与持仓库不受影响
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Position {
     pub symbol: String,
     pub net: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub side: Option<PositionSide>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub avg_price: Option<f64>,
+    /// 持仓的成本，多头为买入花费的计价资产，空头为卖出收到的计价资产，均已扣除手续费
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub cost_basis: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub entry_price: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub unrealized_pnl: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub margin_type: Option<MarginType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub leverage: Option<u16>,
+}
+
+impl Position {
+    pub fn new(symbol: &str, net: f64) -> Self {
+        Self {
+            symbol: symbol.into(),
+            net,
+            side: None,
+            avg_price: None,
+            cost_basis: None,
+            entry_price: None,
+            unrealized_pnl: None,
+            margin_type: None,
+            leverage: None,
+        }
+    }
+
+    /// 持仓的键：单向持仓为 symbol，双向持仓的多空两腿为 `symbol:LONG`、`symbol:SHORT`，持仓库按键保存
+    pub fn key(&self) -> String {
+        match self.side {
+            Some(side @ (PositionSide::LONG | PositionSide::SHORT)) => {
+                format!("{}:{:?}", self.symbol, side)
+            }
+            _ => self.symbol.clone(),
+        }
+    }
+
+    /// 按持仓键还原，`key` 的格式见 [`Position::key`]
+    pub fn from_key(key: &str, net: f64) -> Self {
+        match key.split_once(':') {
+            Some((symbol, side)) => Self {
+                side: side.parse().ok(),
+                ..Self::new(symbol, net)
+            },
+            None => Self::new(key, net),
+        }
+    }
+
+    /// 按一笔成交更新净持仓、开仓均价与成本，`quantity` 买入为正、卖出为负，`notional` 为成交额，
+    /// `fee` 为以计价资产收取的手续费
+    ///
+    /// 开仓与加仓时成交额与手续费计入成本；减仓时成本按比例减少，均价不变；反手时超出的部分按这笔成交的
+    /// 价格开仓，手续费按数量分摊。成本未知的持仓，例如网关重启前留下的持仓，在平仓或反手之前均价为 None
+    pub fn on_fill(&mut self, quantity: f64, notional: f64, fee: f64) {
+        let flat = |net: f64| net.abs() < 1e-12;
+        if flat(quantity) {
+            return;
+        }
+        let before = self.net;
+        let increase = !flat(before) && before.signum() == quantity.signum();
+        let mut opened = quantity.abs();
+        if !flat(before) && !increase {
+            let closed = opened.min(before.abs());
+            self.cost_basis = self
+                .cost_basis
+                .map(|cost| cost * (before.abs() - closed) / before.abs());
+            opened -= closed;
+        }
+        if opened > 0.0 {
+            // 多头的手续费增加成本，空头的手续费减少收到的计价资产
+            let cost = (notional + quantity.signum() * fee) * opened / quantity.abs();
+            self.cost_basis = match increase {
+                true => self.cost_basis.map(|basis| basis + cost),
+                false => Some(cost),
+            };
+        }
+        self.net += quantity;
+        if flat(self.net) {
+            self.cost_basis = None;
+        }
+        self.avg_price = self.cost_basis.map(|cost| cost / self.net.abs());
+    }
+
+    /// 用交易所推送的持仓更新方向、入场价等字段，`net` 不变，`detail` 中没有的字段保持原值
+    pub fn apply(&mut self, detail: &Position) {
+        self.side = detail.side.or(self.side);
+        self.entry_price = detail.entry_price.or(self.entry_price);
+        self.unrealized_pnl = detail.unrealized_pnl.or(self.unrealized_pnl);
+        self.margin_type = detail.margin_type.or(self.margin_type);
+        self.leverage = detail.leverage.or(self.leverage);
+    }
 }
 
 pub type Success = Response<Option<u8>>;
 pub type ErrorResponse = Response<Error>;
 
 pub type SLoginResponse = SResponse<SLogin>;
+
+/// 订阅请求的回复，按请求中的顺序给出每个流的结果
+pub type SSubscribeResponse = SResponse<Vec<SStreamResult>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription() {
+        let subs: Vec<SSubscription> = serde_json::from_str(
+            r#"["a@depth5", {"stream": "b@depth5", "tag": "t"},
+            {"stream": "c@depth20", "bucket": 0.5}, {"stream": "d@depth5", "tag": "t", "bucket": 1}]"#,
+        )
+        .unwrap();
+        assert_eq!(subs[0].bucket(), None);
+        assert!(matches!(&subs[1], SSubscription::Tagged { tag, .. } if tag == "t"));
+        assert_eq!((subs[2].bucket(), subs[2].tag()), (Some(0.5), None));
+        assert_eq!(subs[3].bucket(), Some(1.0));
+        assert_eq!(subs[3].tag().map(String::as_str), Some("t"));
+        assert_eq!(subs[3].raw(), RawMode::Off);
+
+        let subs: Vec<SSubscription> = serde_json::from_str(
+            r#"[{"stream": "a@depth5", "raw": true}, {"stream": "b@depth20", "raw": "both",
+            "bucket": 0.5, "tag": "t"}, {"stream": "c@aggTrade", "raw": false}]"#,
+        )
+        .unwrap();
+        assert_eq!(subs[0].raw(), RawMode::Only);
+        assert_eq!(subs[1].raw(), RawMode::Both);
+        assert_eq!(
+            (subs[1].bucket(), subs[1].tag().map(String::as_str)),
+            (Some(0.5), Some("t"))
+        );
+        assert_eq!(subs[2].raw(), RawMode::Off);
+        assert_eq!(
+            serde_json::to_string(&subs[1]).unwrap(),
+            r#"{"stream":"b@depth20","raw":"both","bucket":0.5,"tag":"t"}"#
+        );
+    }
+
+    #[test]
+    fn test_order_enums() {
+        for (s, t) in [
+            ("STOP_MARKET", OrderType::STOP_MARKET),
+            ("TRAILING_STOP_MARKET", OrderType::TRAILING_STOP_MARKET),
+            ("post_only", OrderType::LIMIT_MAKER),
+            ("limit", OrderType::LIMIT),
+        ] {
+            assert_eq!(s.parse(), Ok(t));
+            let json = format!("\"{}\"", s);
+            assert_eq!(serde_json::from_str::<OrderType>(&json).unwrap(), t);
+        }
+        assert_eq!(
+            serde_json::to_string(&OrderType::LIMIT_MAKER).unwrap(),
+            "\"LIMIT_MAKER\""
+        );
+        assert_eq!("??".parse::<OrderType>(), Err(()));
+
+        assert_eq!("GTX".parse(), Ok(TimeInForce::GTX));
+        assert_eq!("gtd".parse(), Ok(TimeInForce::GTD));
+        assert_eq!("ioc".parse(), Ok(TimeInForce::IOC));
+        assert_eq!("".parse::<TimeInForce>(), Err(()));
+    }
+
+    #[test]
+    fn test_position() {
+        let mut position: Position =
+            serde_json::from_str(r#"{"symbol":"btcusdt","net":0.5}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&position).unwrap(),
+            r#"{"symbol":"btcusdt","net":0.5}"#
+        );
+
+        let detail: Position = serde_json::from_str(
+            r#"{"symbol":"btcusdt","net":0.0,"side":"BOTH","entry_price":64000.0,"margin_type":"cross"}"#,
+        )
+        .unwrap();
+        position.apply(&detail);
+        position.apply(&Position {
+            leverage: Some(20),
+            ..Position::new("btcusdt", 0.0)
+        });
+        assert_eq!(position.net, 0.5);
+        assert_eq!(position.entry_price, Some(64000.0));
+        assert_eq!(position.margin_type, Some(MarginType::CROSSED));
+        assert_eq!(position.leverage, Some(20));
+        assert!(position.unrealized_pnl.is_none());
+    }
+
+    #[test]
+    fn test_position_on_fill() {
+        let mut position = Position::new("btcusdt", 0.0);
+        // 分两笔买入，手续费计入成本
+        position.on_fill(1.0, 100.0, 0.1);
+        position.on_fill(1.0, 110.0, 0.1);
+        assert_eq!(position.cost_basis, Some(210.2));
+        assert_eq!(position.avg_price, Some(105.1));
+        // 部分平仓，均价不变
+        position.on_fill(-0.5, 60.0, 0.05);
+        assert_eq!(position.net, 1.5);
+        assert!((position.avg_price.unwrap() - 105.1).abs() < 1e-9);
+        // 卖出 3 个，反手开空 1.5 个，手续费按数量分摊
+        position.on_fill(-3.0, 360.0, 0.3);
+        assert_eq!(position.net, -1.5);
+        assert!((position.cost_basis.unwrap() - 179.85).abs() < 1e-9);
+        assert!((position.avg_price.unwrap() - 119.9).abs() < 1e-9);
+        position.on_fill(1.5, 150.0, 0.0);
+        assert_eq!((position.avg_price, position.cost_basis), (None, None));
+
+        // 成本未知的持仓，反手之后才有均价
+        let mut position = Position::new("btcusdt", 2.0);
+        position.on_fill(1.0, 100.0, 0.0);
+        assert!(position.avg_price.is_none());
+        position.on_fill(-4.0, 400.0, 0.0);
+        assert_eq!(position.avg_price, Some(100.0));
+    }
+
+    #[test]
+    fn test_position_key() {
+        let long = Position {
+            side: Some(PositionSide::LONG),
+            ..Position::new("btcusdt", 0.5)
+        };
+        assert_eq!(long.key(), "btcusdt:LONG");
+        let restored = Position::from_key(&long.key(), 0.5);
+        assert_eq!(restored.symbol, "btcusdt");
+        assert_eq!(restored.side, Some(PositionSide::LONG));
+
+        let both = Position {
+            side: Some(PositionSide::BOTH),
+            ..Position::new("btcusdt", 0.5)
+        };
+        assert_eq!(both.key(), "btcusdt");
+        assert!(Position::from_key("btcusdt", 0.5).side.is_none());
+    }
+}
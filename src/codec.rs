@@ -0,0 +1,161 @@
+//! 策略协议的编码
+//!
+//! 协议的消息结构不变，只是换一种编码：默认 JSON（文本帧），可选 MessagePack（`msgpack` feature）与
+//! CBOR（`cbor` feature），二者都是二进制帧。策略端在 `login` 中带上 `format` 协商，
+//! `login` 请求与回复总是 JSON，回复中的 `format` 为网关实际采用的编码，之后双方都按它收发。
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+/// 消息的编码与解码
+pub trait Codec {
+    fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> anyhow::Result<T>;
+}
+
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> anyhow::Result<T> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPackCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        // 结构体按字段名编码为 map，与 JSON 的结构一致
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> anyhow::Result<T> {
+        Ok(rmp_serde::from_slice(data)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        ciborium::into_writer(value, &mut data)?;
+        Ok(data)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> anyhow::Result<T> {
+        Ok(ciborium::from_reader(data)?)
+    }
+}
+
+/// 连接采用的编码
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    #[default]
+    Json,
+    Msgpack,
+    Cbor,
+}
+
+impl WireFormat {
+    pub fn is_json(&self) -> bool {
+        *self == Self::Json
+    }
+
+    /// 编译时是否开启了该编码
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Self::Json => true,
+            Self::Msgpack => cfg!(feature = "msgpack"),
+            Self::Cbor => cfg!(feature = "cbor"),
+        }
+    }
+
+    /// 按名称解析，未知或未开启的编码为 None
+    pub fn parse(name: &str) -> Option<Self> {
+        let format = match name {
+            "json" => Self::Json,
+            "msgpack" => Self::Msgpack,
+            "cbor" => Self::Cbor,
+            _ => return None,
+        };
+        format.is_supported().then_some(format)
+    }
+}
+
+impl Codec for WireFormat {
+    fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Json => JsonCodec.encode(value),
+            #[cfg(feature = "msgpack")]
+            Self::Msgpack => MsgPackCodec.encode(value),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => CborCodec.encode(value),
+            #[allow(unreachable_patterns)]
+            _ => anyhow::bail!("{:?} is not enabled", self),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> anyhow::Result<T> {
+        match self {
+            Self::Json => JsonCodec.decode(data),
+            #[cfg(feature = "msgpack")]
+            Self::Msgpack => MsgPackCodec.decode(data),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => CborCodec.decode(data),
+            #[allow(unreachable_patterns)]
+            _ => anyhow::bail!("{:?} is not enabled", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{SLogin, SRequest};
+
+    #[test]
+    fn test_wire_format() {
+        assert_eq!(WireFormat::parse("json"), Some(WireFormat::Json));
+        assert_eq!(WireFormat::parse("xml"), None);
+        let format: WireFormat = serde_json::from_str(r#""msgpack""#).unwrap();
+        assert_eq!(format, WireFormat::Msgpack);
+
+        let req = SRequest {
+            id: 3,
+            method: "login".to_string(),
+            params: SLogin {
+                session_id: 1,
+                name: Some("demo".into()),
+                trading: true,
+                cancel_on_disconnect: false,
+                fills: false,
+                format: WireFormat::Json,
+                heartbeat_ms: None,
+                tenant: None,
+                token: None,
+                version: None,
+            },
+        };
+        let json = serde_json::to_value(&req).unwrap();
+        for format in [WireFormat::Json, WireFormat::Msgpack, WireFormat::Cbor] {
+            if !format.is_supported() {
+                continue;
+            }
+            // 直接编解码请求类型，结构与 JSON 一致
+            let data = format.encode(&req).unwrap();
+            let decoded: SRequest<SLogin> = format.decode(&data).unwrap();
+            assert_eq!(decoded.params.name.as_deref(), Some("demo"));
+            let value: serde_json::Value = format.decode(&data).unwrap();
+            assert_eq!(value, json);
+        }
+    }
+}
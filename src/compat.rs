@@ -0,0 +1,221 @@
+//! 策略端协议的版本与兼容
+//!
+//! 登录请求中的 `version` 为客户端实现的协议版本，不带 `version` 的老客户端按 [`LEGACY_VERSION`]
+//! 处理。网关内部只处理当前版本的请求与消息，版本较低的连接由 [`Shim`] 在收发时转换：
+//!
+//! - 请求新增的字段都带默认值，老客户端缺少的字段按默认值处理，请求不做转换
+//! - 格式有变化的回复从当前版本依次降级到连接的版本
+//! - 连接的版本之后才有的推送直接丢弃
+//!
+//! 与当前版本相同的连接不经过转换。各版本的变化：
+//!
+//! | 版本 | 变化 |
+//! | --- | --- |
+//! | 1 | 最初的协议，订阅的回复为 `null` |
+//! | 2 | 订阅按流回复结果；新增 `bar_close`、交割、换月与合约切换的推送 |
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::{debug, warn};
+
+/// 网关实现的协议版本
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// 登录时不带 `version` 的客户端
+pub const LEGACY_VERSION: u32 = 1;
+
+/// 把回复降级到上一版本
+type Downgrade = fn(&mut Value);
+
+/// 一个版本引入的变化
+struct Change {
+    version: u32,
+    /// 回复格式有变化的方法
+    replies: &'static [(&'static str, Downgrade)],
+    /// 这个版本新增的推送，见 [`push_kind`]
+    pushes: &'static [&'static str],
+}
+
+const CHANGES: &[Change] = &[Change {
+    version: 2,
+    replies: &[("subscribe", subscribe_v1)],
+    pushes: &["bar_close", "delivery", "roll", "symbol_changed"],
+}];
+
+/// 订阅的回复从按流的结果改回 `null`，被拒绝的流只在网关记录
+fn subscribe_v1(reply: &mut Value) {
+    if let Some(results) = reply["result"].as_array() {
+        for result in results.iter().filter(|r| r["accepted"] == false) {
+            warn!(
+                "Stream {} rejected for legacy client: {}",
+                result["stream"], result["error"]["msg"]
+            );
+        }
+        reply["result"] = Value::Null;
+    }
+}
+
+/// 协商连接的版本，高于网关的版本按网关的版本处理
+pub fn negotiate(requested: Option<u32>) -> u32 {
+    requested
+        .unwrap_or(LEGACY_VERSION)
+        .clamp(LEGACY_VERSION, PROTOCOL_VERSION)
+}
+
+/// 按字段识别不带 `id` 的推送
+pub fn push_kind(message: &Value) -> Option<&'static str> {
+    let has = |key: &str| message.get(key).is_some();
+    if has("id") {
+        None
+    } else if has("bar_close") {
+        Some("bar_close")
+    } else if has("alias") && has("previous") {
+        Some("symbol_changed")
+    } else if has("alias") && has("next") {
+        Some("roll")
+    } else if has("delivery_time") && has("settled") {
+        Some("delivery")
+    } else {
+        None
+    }
+}
+
+/// 发给客户端的消息的处理结果
+#[derive(Debug, PartialEq)]
+pub enum Shimmed {
+    /// 原样发出
+    Keep,
+    /// 已转换为连接的版本
+    Rewritten,
+    /// 连接的版本没有这种推送
+    Drop,
+}
+
+/// 一条连接的转换状态
+#[derive(Debug)]
+pub struct Shim {
+    version: u32,
+    /// 等待回复的请求 id 与方法，只记录回复格式有变化的方法
+    pending: HashMap<i64, &'static str>,
+}
+
+impl Default for Shim {
+    fn default() -> Self {
+        Self::new(PROTOCOL_VERSION)
+    }
+}
+
+impl Shim {
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            pending: HashMap::default(),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// 连接使用当前版本，不需要转换
+    pub fn current(&self) -> bool {
+        self.version >= PROTOCOL_VERSION
+    }
+
+    fn changes(&self) -> impl Iterator<Item = &'static Change> + '_ {
+        CHANGES.iter().rev().filter(|c| c.version > self.version)
+    }
+
+    /// 客户端发来的请求，记录之后需要降级的回复
+    pub fn on_request(&mut self, request: &Value) {
+        let (Some(id), Some(method)) = (request["id"].as_i64(), request["method"].as_str()) else {
+            return;
+        };
+        let changed = self
+            .changes()
+            .flat_map(|c| c.replies.iter())
+            .find(|(m, _)| *m == method);
+        if let Some((method, _)) = changed {
+            self.pending.insert(id, method);
+        }
+    }
+
+    /// 发给客户端的消息，需要转换时原地改写
+    pub fn on_message(&mut self, message: &mut Value) -> Shimmed {
+        if let Some(method) = message["id"]
+            .as_i64()
+            .and_then(|id| self.pending.remove(&id))
+        {
+            for change in self.changes() {
+                for (_, downgrade) in change.replies.iter().filter(|(m, _)| *m == method) {
+                    downgrade(message);
+                }
+            }
+            return Shimmed::Rewritten;
+        }
+        if let Some(kind) = push_kind(message) {
+            if self.changes().any(|c| c.pushes.contains(&kind)) {
+                debug!("Drop {} for protocol v{}", kind, self.version);
+                return Shimmed::Drop;
+            }
+        }
+        Shimmed::Keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_shim() {
+        assert_eq!(negotiate(None), LEGACY_VERSION);
+        assert_eq!(negotiate(Some(0)), LEGACY_VERSION);
+        assert_eq!(negotiate(Some(99)), PROTOCOL_VERSION);
+
+        let results = json!({"id": 2, "result": [
+            {"stream": "btcusdt@kline_1m", "accepted": true},
+            {"stream": "btcusdt@depth:x", "accepted": false, "error": {"code": -1, "msg": "bad"}}
+        ]});
+        let bar_close = json!({"offset": 3, "bar_close": 1, "symbol": "btcusdt"});
+
+        let mut legacy = Shim::new(LEGACY_VERSION);
+        assert!(!legacy.current());
+        legacy.on_request(&json!({"id": 2, "method": "subscribe", "params": []}));
+        legacy.on_request(&json!({"id": 3, "method": "get_positions", "params": {}}));
+        let mut reply = results.clone();
+        assert_eq!(legacy.on_message(&mut reply), Shimmed::Rewritten);
+        assert_eq!(reply, json!({"id": 2, "result": null}));
+        // 只降级记录过的回复
+        let mut reply = results.clone();
+        assert_eq!(legacy.on_message(&mut reply), Shimmed::Keep);
+        assert_eq!(reply, results);
+        assert_eq!(legacy.on_message(&mut bar_close.clone()), Shimmed::Drop);
+        let mut kline = json!({"offset": 4, "symbol": "btcusdt", "close": 1.0});
+        assert_eq!(legacy.on_message(&mut kline), Shimmed::Keep);
+
+        let mut current = Shim::default();
+        assert!(current.current());
+        current.on_request(&json!({"id": 2, "method": "subscribe", "params": []}));
+        assert_eq!(current.on_message(&mut results.clone()), Shimmed::Keep);
+        assert_eq!(current.on_message(&mut bar_close.clone()), Shimmed::Keep);
+
+        assert_eq!(
+            push_kind(&json!({"alias": "btcusdt_cq", "previous": "a", "symbol": "b"})),
+            Some("symbol_changed")
+        );
+        assert_eq!(
+            push_kind(&json!({"alias": "btcusdt_cq", "contract": "a", "next": "b"})),
+            Some("roll")
+        );
+        assert_eq!(
+            push_kind(&json!({"symbol": "a", "delivery_time": 1, "settled": true})),
+            Some("delivery")
+        );
+        assert_eq!(
+            push_kind(&json!({"id": 1, "result": {"alias": 1, "next": 2}})),
+            None
+        );
+    }
+}
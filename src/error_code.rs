@@ -4,5 +4,17 @@ pub const DUPLICATE_LOGIN: i32 = -10002;
 pub const INVALID_SYMBOL: i32 = -10003;
 pub const INVALID_STREAM: i32 = -10004;
 pub const NONTRADING: i32 = -10005;
+pub const INVALID_EXPR: i32 = -10006;
+pub const OFFSET_EXPIRED: i32 = -10007;
 pub const DISCONNECTED: i32 = -30002;
 pub const UNDEF_ERROR: i32 = -30003;
+pub const TIMEOUT: i32 = -30004;
+pub const UNSUPPORTED: i32 = -10008;
+pub const INVALID_LOAN: i32 = -10009;
+pub const PERMISSION_DENIED: i32 = -10010;
+pub const INVALID_RANGE: i32 = -10011;
+pub const RATE_LIMITED: i32 = -10012;
+pub const INVALID_ROLL: i32 = -10013;
+pub const STREAM_LIMIT: i32 = -10014;
+pub const QUOTA_EXCEEDED: i32 = -10015;
+pub const INVALID_STATE: i32 = -10016;
@@ -0,0 +1,368 @@
+//! 行情表达式
+//!
+//! 派生流用一段小程序描述，语句之间用 `;` 或换行分隔，例如
+//! `mid = (bid0 + ask0) / 2; fast = ema(mid, 20)`。每个 tick 计算一次，命名语句的结果作为输出，
+//! 后面的语句可以引用前面的结果；没有名字的表达式输出为 `value`。
+//!
+//! 支持四则运算、括号、一元负号，以及函数 `abs(x)`、`sqrt(x)`、`ln(x)`、`min(a, b)`、`max(a, b)`、
+//! `ema(x, n)`、`sma(x, n)`。`ema` 与 `sma` 跨 tick 保存状态，`n` 必须是正的常数。
+//! 变量缺失、除以零等得不到有限值时，这条语句在本次 tick 没有输出。
+
+use std::collections::{BTreeMap, VecDeque};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+    Assign,
+    Semi,
+}
+
+fn tokenize(src: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' | ';' => {
+                chars.next();
+                tokens.push(Token::Semi);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    s.push(c);
+                    chars.next();
+                }
+                let n = s
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid number {}", s))?;
+                tokens.push(Token::Num(n));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(s));
+            }
+            '+' | '-' | '*' | '/' => {
+                chars.next();
+                tokens.push(Token::Op(c));
+            }
+            '(' | ')' | ',' | '=' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ',' => Token::Comma,
+                    _ => Token::Assign,
+                });
+            }
+            _ => anyhow::bail!("Unexpected character {:?}", c),
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Abs,
+    Sqrt,
+    Ln,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Num(f64),
+    Var(String),
+    Neg(Box<Node>),
+    Bin(char, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+    Ema {
+        arg: Box<Node>,
+        alpha: f64,
+        value: Option<f64>,
+    },
+    Sma {
+        arg: Box<Node>,
+        len: usize,
+        window: VecDeque<f64>,
+    },
+}
+
+impl Node {
+    /// 节点数与 `sma` 窗口长度之和
+    fn size(&self) -> (usize, usize) {
+        let add = |(nodes, window): (usize, usize), (n, w): (usize, usize)| (nodes + n, window + w);
+        match self {
+            Self::Num(_) | Self::Var(_) => (1, 0),
+            Self::Neg(arg) | Self::Ema { arg, .. } => add((1, 0), arg.size()),
+            Self::Bin(_, lhs, rhs) => add(add((1, 0), lhs.size()), rhs.size()),
+            Self::Call(_, args) => args.iter().map(Node::size).fold((1, 0), add),
+            Self::Sma { arg, len, .. } => add((1, *len), arg.size()),
+        }
+    }
+
+    fn eval(
+        &mut self,
+        vars: &dyn Fn(&str) -> Option<f64>,
+        locals: &BTreeMap<String, f64>,
+    ) -> Option<f64> {
+        let value = match self {
+            Self::Num(n) => *n,
+            Self::Var(name) => locals.get(name).copied().or_else(|| vars(name))?,
+            Self::Neg(arg) => -arg.eval(vars, locals)?,
+            Self::Bin(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(vars, locals)?, rhs.eval(vars, locals)?);
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    _ => lhs / rhs,
+                }
+            }
+            Self::Call(func, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter_mut() {
+                    values.push(arg.eval(vars, locals)?);
+                }
+                match func {
+                    Func::Abs => values[0].abs(),
+                    Func::Sqrt => values[0].sqrt(),
+                    Func::Ln => values[0].ln(),
+                    Func::Min => values[0].min(values[1]),
+                    Func::Max => values[0].max(values[1]),
+                }
+            }
+            Self::Ema { arg, alpha, value } => {
+                let x = arg.eval(vars, locals)?;
+                let ema = match value {
+                    Some(v) => *v + *alpha * (x - *v),
+                    None => x,
+                };
+                *value = Some(ema);
+                ema
+            }
+            Self::Sma { arg, len, window } => {
+                window.push_back(arg.eval(vars, locals)?);
+                if window.len() > *len {
+                    window.pop_front();
+                }
+                window.iter().sum::<f64>() / window.len() as f64
+            }
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> anyhow::Result<()> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            other => anyhow::bail!("Expected {:?}, found {:?}", token, other),
+        }
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Node> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            lhs = Node::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> anyhow::Result<Node> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            lhs = Node::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Node> {
+        if self.peek() == Some(&Token::Op('-')) {
+            self.pos += 1;
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Node> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Node::Num(n)),
+            Some(Token::LParen) => {
+                let node = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::LParen) => {
+                self.pos += 1;
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    args.push(self.expr()?);
+                }
+                self.expect(Token::RParen)?;
+                call(&name, args)
+            }
+            Some(Token::Ident(name)) => Ok(Node::Var(name)),
+            other => anyhow::bail!("Unexpected {:?}", other),
+        }
+    }
+}
+
+fn call(name: &str, mut args: Vec<Node>) -> anyhow::Result<Node> {
+    let (func, arity) = match name {
+        "abs" => (Some(Func::Abs), 1),
+        "sqrt" => (Some(Func::Sqrt), 1),
+        "ln" => (Some(Func::Ln), 1),
+        "min" => (Some(Func::Min), 2),
+        "max" => (Some(Func::Max), 2),
+        "ema" | "sma" => (None, 2),
+        _ => anyhow::bail!("Unknown function {}", name),
+    };
+    if args.len() != arity {
+        anyhow::bail!("{} takes {} arguments", name, arity);
+    }
+    if let Some(func) = func {
+        return Ok(Node::Call(func, args));
+    }
+
+    let n = match args.pop() {
+        Some(Node::Num(n)) if n >= 1.0 => n,
+        _ => anyhow::bail!("{} length must be a constant >= 1", name),
+    };
+    let arg = Box::new(args.remove(0));
+    Ok(match name {
+        "ema" => Node::Ema {
+            arg,
+            alpha: 2.0 / (n + 1.0),
+            value: None,
+        },
+        _ => Node::Sma {
+            arg,
+            len: n as usize,
+            window: VecDeque::new(),
+        },
+    })
+}
+
+/// 编译后的表达式程序
+#[derive(Debug, Clone)]
+pub struct Program {
+    statements: Vec<(String, Node)>,
+}
+
+impl Program {
+    pub fn parse(src: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(src)?;
+        let mut statements = Vec::new();
+        for statement in tokens.split(|t| *t == Token::Semi) {
+            if statement.is_empty() {
+                continue;
+            }
+            let (name, tokens) = match statement {
+                [Token::Ident(name), Token::Assign, rest @ ..] => (name.clone(), rest.to_vec()),
+                _ => ("value".to_string(), statement.to_vec()),
+            };
+            let mut parser = Parser { tokens, pos: 0 };
+            let node = parser.expr()?;
+            if let Some(token) = parser.peek() {
+                anyhow::bail!("Unexpected {:?} in {}", token, name);
+            }
+            statements.push((name, node));
+        }
+        if statements.is_empty() {
+            anyhow::bail!("Empty expression");
+        }
+        Ok(Self { statements })
+    }
+
+    /// 所有语句的节点数，每个 tick 的计算量与之成正比
+    pub fn nodes(&self) -> usize {
+        self.statements.iter().map(|(_, node)| node.size().0).sum()
+    }
+
+    /// 所有 `sma` 的窗口长度之和，即需要保存的历史值个数
+    pub fn window(&self) -> usize {
+        self.statements.iter().map(|(_, node)| node.size().1).sum()
+    }
+
+    /// 用一个 tick 的变量计算所有语句，返回得到有限值的输出
+    pub fn eval(&mut self, vars: impl Fn(&str) -> Option<f64>) -> BTreeMap<String, f64> {
+        let mut outputs = BTreeMap::new();
+        for (name, node) in self.statements.iter_mut() {
+            if let Some(value) = node.eval(&vars, &outputs) {
+                outputs.insert(name.clone(), value);
+            }
+        }
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program() {
+        let mut program =
+            Program::parse("mid = (bid0 + ask0) / 2\nfast = ema(mid, 3); -mid * 2").unwrap();
+        let tick = |bid: f64, ask: f64| {
+            move |name: &str| match name {
+                "bid0" => Some(bid),
+                "ask0" => Some(ask),
+                _ => None,
+            }
+        };
+        let outputs = program.eval(tick(99.0, 101.0));
+        assert_eq!(outputs["mid"], 100.0);
+        assert_eq!(outputs["fast"], 100.0);
+        assert_eq!(outputs["value"], -200.0);
+        // alpha = 2 / (3 + 1)
+        let outputs = program.eval(tick(103.0, 105.0));
+        assert_eq!(outputs["fast"], 102.0);
+
+        let mut sma = Program::parse("s = sma(x, 2); r = 1 / x").unwrap();
+        let outputs = sma.eval(|_| Some(0.0));
+        assert_eq!(outputs.get("r"), None);
+        assert_eq!(sma.eval(|_| Some(2.0))["s"], 1.0);
+        assert_eq!(sma.eval(|_| Some(4.0))["s"], 3.0);
+        assert!(sma.eval(|_| None).is_empty());
+        // sma(x, 2) 为 2 个节点，1 / x 为 3 个
+        assert_eq!((sma.nodes(), sma.window()), (5, 2));
+
+        assert!(Program::parse("ema(x, n)").is_err());
+        assert!(Program::parse("foo(x)").is_err());
+        assert!(Program::parse("(x + 1").is_err());
+        assert!(Program::parse("x y").is_err());
+        assert!(Program::parse(" ; ").is_err());
+    }
+}
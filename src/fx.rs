@@ -0,0 +1,238 @@
+//! 汇率换算
+//!
+//! 由 bookTicker 的中间价维护各资产之间的汇率，把余额、名义价值、盈亏等
+//! 以不同计价资产表示的金额统一换算到报告币种（默认 USDT）。
+//! 没有直接交易对时，经由一个中间资产换算，例如 ETH/BTC * BTC/USDT。
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// 汇率配置
+///
+/// ```json
+/// "fx": {
+///     "reporting": "USDT",
+///     "symbols": ["btcusdt", "bnbusdt", "ethbtc"]
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FxConfig {
+    /// 报告币种
+    pub reporting: String,
+    /// 启动时订阅 bookTicker 的交易对，策略端订阅的 bbo 也会用于更新汇率
+    pub symbols: Vec<String>,
+}
+
+impl Default for FxConfig {
+    fn default() -> Self {
+        Self {
+            reporting: "USDT".into(),
+            symbols: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FxState {
+    /// 交易对 -> (基础资产, 计价资产)
+    pairs: HashMap<String, (String, String)>,
+    /// (基础资产, 计价资产) -> 中间价
+    rates: HashMap<(String, String), f64>,
+}
+
+impl FxState {
+    fn direct(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        let key = (from.to_string(), to.to_string());
+        if let Some(rate) = self.rates.get(&key) {
+            return Some(*rate);
+        }
+        let key = (to.to_string(), from.to_string());
+        self.rates.get(&key).map(|rate| 1.0 / rate)
+    }
+
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if let Some(rate) = self.direct(from, to) {
+            return Some(rate);
+        }
+
+        // 经由一个中间资产，按资产名排序保证结果稳定
+        let mut bridges: Vec<&str> = self
+            .rates
+            .keys()
+            .flat_map(|(base, quote)| [base.as_str(), quote.as_str()])
+            .filter(|asset| *asset != from && *asset != to)
+            .collect();
+        bridges.sort_unstable();
+        bridges.dedup();
+
+        bridges
+            .into_iter()
+            .find_map(|bridge| Some(self.direct(from, bridge)? * self.direct(bridge, to)?))
+    }
+}
+
+/// 汇率表
+///
+/// 可以廉价 clone，在 market、trade、portfolio 之间共享。
+#[derive(Clone)]
+pub struct FxRates {
+    config: Arc<FxConfig>,
+    state: Arc<Mutex<FxState>>,
+}
+
+impl Default for FxRates {
+    fn default() -> Self {
+        Self::new(FxConfig::default())
+    }
+}
+
+impl Debug for FxRates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FxRates")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl FxRates {
+    pub fn new(mut config: FxConfig) -> Self {
+        config.reporting = config.reporting.to_uppercase();
+        Self {
+            config: Arc::new(config),
+            state: Arc::default(),
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut FxState) -> R) -> R {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut state)
+    }
+
+    pub fn reporting(&self) -> &str {
+        &self.config.reporting
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.config.symbols
+    }
+
+    /// 登记交易对的基础资产与计价资产，只有登记过的交易对的行情会更新汇率
+    pub fn register(&self, symbol: &str, base: &str, quote: &str) {
+        self.with_state(|state| {
+            state.pairs.insert(
+                symbol.to_lowercase(),
+                (base.to_uppercase(), quote.to_uppercase()),
+            );
+        })
+    }
+
+    /// 用最优买卖价的中间价更新汇率
+    pub fn on_book_ticker(&self, symbol: &str, bid: f64, ask: f64) {
+        if bid <= 0.0 || ask <= 0.0 {
+            return;
+        }
+        self.with_state(|state| {
+            if let Some(pair) = state.pairs.get(&symbol.to_lowercase()).cloned() {
+                state.rates.insert(pair, (bid + ask) / 2.0);
+            }
+        })
+    }
+
+    /// 1 单位 `from` 值多少 `to`
+    pub fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        let from = from.to_uppercase();
+        let to = to.to_uppercase();
+        self.with_state(|state| state.rate(&from, &to))
+    }
+
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> Option<f64> {
+        Some(amount * self.rate(from, to)?)
+    }
+
+    /// 换算到报告币种
+    pub fn to_reporting(&self, amount: f64, asset: &str) -> Option<f64> {
+        self.convert(amount, asset, &self.config.reporting)
+    }
+
+    /// 一组资产余额按报告币种计的总值，缺少汇率的资产返回 Err 并带上资产名
+    pub fn value<'a>(
+        &self,
+        balances: impl IntoIterator<Item = (&'a str, f64)>,
+    ) -> Result<f64, String> {
+        balances
+            .into_iter()
+            .try_fold(0.0, |total, (asset, amount)| {
+                match self.to_reporting(amount, asset) {
+                    Some(value) => Ok(total + value),
+                    None => Err(asset.to_string()),
+                }
+            })
+    }
+
+    /// 以报告币种计的最小名义价值换算成交易对计价资产下的最小名义价值，
+    /// 取与交易所规则中的 `min_notional` 较大者
+    pub fn min_notional(&self, min_notional: f64, quote: &str, reporting_min: f64) -> f64 {
+        match self.convert(reporting_min, &self.config.reporting, quote) {
+            Some(min) => min_notional.max(min),
+            None => min_notional,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fx() -> FxRates {
+        let fx = FxRates::new(FxConfig {
+            reporting: "usdt".into(),
+            symbols: vec![],
+        });
+        fx.register("btcusdt", "BTC", "USDT");
+        fx.register("ethbtc", "ETH", "BTC");
+        fx.register("bnbusdt", "BNB", "USDT");
+        fx.on_book_ticker("BTCUSDT", 99_990.0, 100_010.0);
+        fx.on_book_ticker("ETHBTC", 0.0399, 0.0401);
+        fx.on_book_ticker("bnbusdt", 599.0, 601.0);
+        fx
+    }
+
+    #[test]
+    fn test_rate() {
+        let fx = fx();
+        assert_eq!(fx.rate("usdt", "USDT"), Some(1.0));
+        assert_eq!(fx.rate("BTC", "USDT"), Some(100_000.0));
+        assert_eq!(fx.rate("USDT", "BTC"), Some(1.0 / 100_000.0));
+        // ETH -> BTC -> USDT
+        assert!((fx.rate("ETH", "USDT").unwrap() - 4_000.0).abs() < 1e-6);
+        // ETH -> USDT -> BNB，经两个中间资产，不支持
+        assert!(fx.rate("ETH", "BNB").is_none());
+        assert!((fx.rate("BTC", "BNB").unwrap() - 100_000.0 / 600.0).abs() < 1e-6);
+        assert!(fx.rate("DOGE", "USDT").is_none());
+
+        // 未登记的交易对不更新汇率
+        fx.on_book_ticker("dogeusdt", 0.1, 0.1);
+        assert!(fx.rate("DOGE", "USDT").is_none());
+    }
+
+    #[test]
+    fn test_value() {
+        let fx = fx();
+        let value = fx.value([("BTC", 0.5), ("USDT", 1_000.0), ("BNB", 10.0)]);
+        assert_eq!(value, Ok(50_000.0 + 1_000.0 + 6_000.0));
+        assert_eq!(fx.value([("BTC", 1.0), ("DOGE", 1.0)]), Err("DOGE".into()));
+
+        // ETHBTC 的最小名义价值 0.0001 BTC，报告币种下至少 20 USDT
+        assert!((fx.min_notional(0.0001, "BTC", 20.0) - 0.0002).abs() < 1e-12);
+        assert_eq!(fx.min_notional(0.0001, "BTC", 5.0), 0.0001);
+    }
+}
@@ -0,0 +1,310 @@
+//! 资金流水（income history）存储与对账
+//!
+//! 网关定期把交易所的资金流水（已实现盈亏、资金费、手续费、划转）分页同步到
+//! sqlite，供策略端与面板按时间段、类型查询；同时记录网关根据成交回报计算的已实现盈亏，
+//! 与交易所流水中的 REALIZED_PNL 对账。
+//!
+//! 流水库与持仓库分开存放，持仓库里每张表都代表一个 session。
+
+use log::*;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const REALIZED_PNL: &str = "REALIZED_PNL";
+pub const FUNDING_FEE: &str = "FUNDING_FEE";
+pub const COMMISSION: &str = "COMMISSION";
+pub const TRANSFER: &str = "TRANSFER";
+
+/// 同步的流水类型
+pub const INCOME_TYPES: [&str; 4] = [REALIZED_PNL, FUNDING_FEE, COMMISSION, TRANSFER];
+
+/// ```json
+/// "income": {
+///     "enabled": true,
+///     "db": "income.db",
+///     "sync_secs": 300,
+///     "lookback_days": 7,
+///     "tolerance": 0.01
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct IncomeConfig {
+    pub enabled: bool,
+    pub db: String,
+    /// 同步间隔
+    pub sync_secs: u64,
+    /// 库为空时向前同步多少天
+    pub lookback_days: i64,
+    /// 对账允许的误差
+    pub tolerance: f64,
+}
+
+impl Default for IncomeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            db: "income.db".into(),
+            sync_secs: 300,
+            lookback_days: 7,
+            tolerance: 0.01,
+        }
+    }
+}
+
+/// 一条资金流水
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, sqlx::FromRow)]
+pub struct Income {
+    pub tran_id: i64,
+    pub symbol: String,
+    pub income_type: String,
+    pub income: f64,
+    pub asset: String,
+    /// 毫秒时间戳
+    pub time: i64,
+}
+
+/// 策略端查询 [start, end) 内的流水，`income_type` 为空时返回全部类型
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SIncomeReq {
+    pub start: i64,
+    pub end: i64,
+    #[serde(default)]
+    pub income_type: Option<String>,
+}
+
+/// 对账不一致的交易对
+#[derive(Debug, Clone, PartialEq)]
+pub struct PnlMismatch {
+    pub symbol: String,
+    pub local: f64,
+    pub remote: f64,
+}
+
+/// 按交易对比较本地与交易所的已实现盈亏，任一侧出现的交易对都参与比较
+pub fn reconcile(
+    local: &HashMap<String, f64>,
+    remote: &HashMap<String, f64>,
+    tolerance: f64,
+) -> Vec<PnlMismatch> {
+    let mut symbols: Vec<&String> = local.keys().chain(remote.keys()).collect();
+    symbols.sort_unstable();
+    symbols.dedup();
+
+    symbols
+        .into_iter()
+        .filter_map(|symbol| {
+            let local = local.get(symbol).copied().unwrap_or_default();
+            let remote = remote.get(symbol).copied().unwrap_or_default();
+            ((local - remote).abs() > tolerance).then(|| PnlMismatch {
+                symbol: symbol.clone(),
+                local,
+                remote,
+            })
+        })
+        .collect()
+}
+
+pub struct IncomeDB {
+    conn: Arc<Pool<Sqlite>>,
+    /// 网关启动时间，对账只比较此后的盈亏
+    since: i64,
+    /// symbol -> 本地计算的已实现盈亏
+    local: Mutex<HashMap<String, f64>>,
+}
+
+impl IncomeDB {
+    pub async fn new(db: &str) -> anyhow::Result<Self> {
+        Self::with_options(SqliteConnectOptions::new().filename(db)).await
+    }
+
+    async fn with_options(options: SqliteConnectOptions) -> anyhow::Result<Self> {
+        let conn = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.create_if_missing(true))
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS income (tran_id INTEGER NOT NULL,
+            symbol TEXT NOT NULL, income_type TEXT NOT NULL, income REAL NOT NULL,
+            asset TEXT NOT NULL, time INTEGER NOT NULL,
+            PRIMARY KEY (tran_id, income_type, symbol))",
+        )
+        .execute(&conn)
+        .await?;
+
+        Ok(Self {
+            conn: Arc::new(conn),
+            since: chrono::Utc::now().timestamp_millis(),
+            local: Mutex::default(),
+        })
+    }
+
+    /// 写入流水，已存在的流水忽略，返回新写入的条数
+    ///
+    /// 同一笔成交的已实现盈亏与手续费共用 tran_id，因此以 (tran_id, 类型, 交易对) 去重
+    pub async fn insert(&self, incomes: &[Income]) -> anyhow::Result<u64> {
+        let mut rows = 0;
+        for income in incomes {
+            rows += sqlx::query(
+                "INSERT OR IGNORE INTO income (tran_id, symbol, income_type, income, asset, time)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(income.tran_id)
+            .bind(&income.symbol)
+            .bind(&income.income_type)
+            .bind(income.income)
+            .bind(&income.asset)
+            .bind(income.time)
+            .execute(self.conn.as_ref())
+            .await?
+            .rows_affected();
+        }
+        Ok(rows)
+    }
+
+    /// 最新一条流水的时间
+    pub async fn last_time(&self) -> anyhow::Result<Option<i64>> {
+        let time: Option<i64> = sqlx::query_scalar("SELECT MAX(time) FROM income")
+            .fetch_one(self.conn.as_ref())
+            .await?;
+        Ok(time)
+    }
+
+    pub async fn query(&self, req: &SIncomeReq) -> anyhow::Result<Vec<Income>> {
+        let rows = match &req.income_type {
+            Some(income_type) => sqlx::query_as(
+                "SELECT * FROM income WHERE time >= $1 AND time < $2 AND income_type = $3 \
+                ORDER BY time, tran_id",
+            )
+            .bind(req.start)
+            .bind(req.end)
+            .bind(income_type.to_uppercase()),
+            None => sqlx::query_as(
+                "SELECT * FROM income WHERE time >= $1 AND time < $2 ORDER BY time, tran_id",
+            )
+            .bind(req.start)
+            .bind(req.end),
+        };
+        Ok(rows.fetch_all(self.conn.as_ref()).await?)
+    }
+
+    /// 记录一笔根据成交回报计算的已实现盈亏
+    pub fn record_pnl(&self, symbol: &str, pnl: f64) {
+        if pnl != 0.0 {
+            let mut local = self.local.lock().unwrap_or_else(|p| p.into_inner());
+            *local.entry(symbol.to_lowercase()).or_default() += pnl;
+        }
+    }
+
+    /// 网关启动以来本地与交易所的已实现盈亏对账
+    pub async fn reconcile(&self, tolerance: f64) -> anyhow::Result<Vec<PnlMismatch>> {
+        let incomes = self
+            .query(&SIncomeReq {
+                start: self.since,
+                end: i64::MAX,
+                income_type: Some(REALIZED_PNL.into()),
+            })
+            .await?;
+
+        let mut remote: HashMap<String, f64> = HashMap::new();
+        for income in incomes {
+            *remote.entry(income.symbol.to_lowercase()).or_default() += income.income;
+        }
+        let local = self.local.lock().unwrap_or_else(|p| p.into_inner()).clone();
+
+        let mismatches = reconcile(&local, &remote, tolerance);
+        for mismatch in mismatches.iter() {
+            warn!("Realized pnl mismatch {:?}", mismatch);
+        }
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn income(tran_id: i64, symbol: &str, income_type: &str, income: f64, time: i64) -> Income {
+        Income {
+            tran_id,
+            symbol: symbol.into(),
+            income_type: income_type.into(),
+            income,
+            asset: "USDT".into(),
+            time,
+        }
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let local = HashMap::from([("btcusdt".to_string(), 10.0), ("ethusdt".to_string(), 1.0)]);
+        let remote = HashMap::from([
+            ("btcusdt".to_string(), 10.001),
+            ("dogeusdt".to_string(), -2.0),
+        ]);
+        assert_eq!(
+            reconcile(&local, &remote, 0.01),
+            vec![
+                PnlMismatch {
+                    symbol: "dogeusdt".into(),
+                    local: 0.0,
+                    remote: -2.0
+                },
+                PnlMismatch {
+                    symbol: "ethusdt".into(),
+                    local: 1.0,
+                    remote: 0.0
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_income_db() {
+        let db = IncomeDB::with_options(SqliteConnectOptions::new().in_memory(true))
+            .await
+            .unwrap();
+        assert_eq!(db.last_time().await.unwrap(), None);
+
+        let incomes = vec![
+            income(1, "BTCUSDT", REALIZED_PNL, 5.0, i64::MAX - 3),
+            income(1, "BTCUSDT", COMMISSION, -0.1, i64::MAX - 3),
+            income(3, "", TRANSFER, 100.0, 1_000),
+        ];
+        assert_eq!(db.insert(&incomes).await.unwrap(), 3);
+        // 重复同步的流水被忽略
+        assert_eq!(db.insert(&incomes[..1]).await.unwrap(), 0);
+        assert_eq!(db.last_time().await.unwrap(), Some(i64::MAX - 3));
+
+        let all = db
+            .query(&SIncomeReq {
+                start: 0,
+                end: i64::MAX,
+                income_type: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0], incomes[2]);
+
+        let pnl = db
+            .query(&SIncomeReq {
+                start: 0,
+                end: i64::MAX,
+                income_type: Some("realized_pnl".into()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(pnl, incomes[..1]);
+
+        db.record_pnl("BTCUSDT", 3.0);
+        db.record_pnl("btcusdt", 2.0);
+        assert!(db.reconcile(0.01).await.unwrap().is_empty());
+        db.record_pnl("btcusdt", 1.0);
+        assert_eq!(db.reconcile(0.01).await.unwrap().len(), 1);
+    }
+}
@@ -1,9 +1,24 @@
+pub mod alert;
 pub mod chat;
+pub mod codec;
+pub mod compat;
 pub mod error_code;
+pub mod expr;
+pub mod fx;
+pub mod income;
+pub mod my_trades;
 pub mod parser;
+pub mod portfolio;
 pub mod position;
+pub mod runtime;
+pub mod schema;
+pub mod storage;
+pub mod tenant;
 pub mod tracing_init;
 pub mod trading_rules;
 
 // 重新导出 tracing 相关功能
-pub use tracing_init::{init_default_if_none, init_tracing, init_tracing_with_spans};
+pub use tracing_init::{
+    LogConfig, LogGuard, OtlpConfig, init_default_if_none, init_tracing, init_tracing_with_config,
+    init_tracing_with_spans,
+};
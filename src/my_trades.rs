@@ -0,0 +1,248 @@
+//! 账户成交历史存储
+//!
+//! 策略端用 `get_my_trades` 按交易对与时间段查询账户的历史成交，网关先把本地库中没有覆盖的时间段
+//! 从交易所分页补齐，再从库中返回。每个交易对记录一段已同步的连续区间，之后的查询只向交易所请求
+//! 区间以外的部分，网关重启后历史成交仍在库中，TCA 等分析可以拿到网关运行之前的成交。
+
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
+
+/// ```json
+/// "my_trades": {
+///     "enabled": true,
+///     "db": "trades.db",
+///     "max_days": 30
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MyTradesConfig {
+    pub enabled: bool,
+    pub db: String,
+    /// 单次查询的最大时间跨度
+    pub max_days: i64,
+}
+
+impl Default for MyTradesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            db: "trades.db".into(),
+            max_days: 30,
+        }
+    }
+}
+
+/// 一笔账户成交
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, sqlx::FromRow)]
+pub struct MyTrade {
+    pub symbol: String,
+    pub trade_id: i64,
+    pub order_id: i64,
+    pub side: String,
+    pub price: f64,
+    pub quantity: f64,
+    pub quote_quantity: f64,
+    pub commission: f64,
+    pub commission_asset: String,
+    pub maker: bool,
+    /// 毫秒时间戳
+    pub time: i64,
+}
+
+/// 策略端查询一个交易对 [start, end) 内的成交
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SMyTradesReq {
+    pub symbol: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+/// 对 [`SMyTradesReq`] 的应答
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SMyTrades {
+    pub symbol: String,
+    pub start: i64,
+    pub end: i64,
+    pub trades: Vec<MyTrade>,
+}
+
+/// 已同步区间 `synced` 以外、需要向交易所查询的时间段
+///
+/// 补齐后已同步区间扩展为两者的并集，因此与已同步区间不相邻的查询会连同中间的空隙一起查询
+pub fn missing_ranges(synced: Option<(i64, i64)>, start: i64, end: i64) -> Vec<(i64, i64)> {
+    let Some((from, to)) = synced else {
+        return vec![(start, end)];
+    };
+    let mut ranges = Vec::new();
+    if start < from {
+        ranges.push((start, from));
+    }
+    if end > to {
+        ranges.push((to, end));
+    }
+    ranges
+}
+
+pub struct MyTradeDB {
+    conn: Arc<Pool<Sqlite>>,
+}
+
+impl MyTradeDB {
+    pub async fn new(db: &str) -> anyhow::Result<Self> {
+        Self::with_options(SqliteConnectOptions::new().filename(db)).await
+    }
+
+    async fn with_options(options: SqliteConnectOptions) -> anyhow::Result<Self> {
+        let conn = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.create_if_missing(true))
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS trades (symbol TEXT NOT NULL, trade_id INTEGER NOT NULL,
+            order_id INTEGER NOT NULL, side TEXT NOT NULL, price REAL NOT NULL,
+            quantity REAL NOT NULL, quote_quantity REAL NOT NULL, commission REAL NOT NULL,
+            commission_asset TEXT NOT NULL, maker BOOLEAN NOT NULL, time INTEGER NOT NULL,
+            PRIMARY KEY (symbol, trade_id))",
+        )
+        .execute(&conn)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS synced (symbol TEXT PRIMARY KEY,
+            start INTEGER NOT NULL, end INTEGER NOT NULL)",
+        )
+        .execute(&conn)
+        .await?;
+
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// 写入成交，已存在的成交忽略，返回新写入的条数
+    pub async fn insert(&self, trades: &[MyTrade]) -> anyhow::Result<u64> {
+        let mut rows = 0;
+        for trade in trades {
+            rows += sqlx::query(
+                "INSERT OR IGNORE INTO trades (symbol, trade_id, order_id, side, price, quantity,
+                quote_quantity, commission, commission_asset, maker, time)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            )
+            .bind(trade.symbol.to_lowercase())
+            .bind(trade.trade_id)
+            .bind(trade.order_id)
+            .bind(&trade.side)
+            .bind(trade.price)
+            .bind(trade.quantity)
+            .bind(trade.quote_quantity)
+            .bind(trade.commission)
+            .bind(&trade.commission_asset)
+            .bind(trade.maker)
+            .bind(trade.time)
+            .execute(self.conn.as_ref())
+            .await?
+            .rows_affected();
+        }
+        Ok(rows)
+    }
+
+    /// 交易对已同步的区间 [start, end)
+    pub async fn synced(&self, symbol: &str) -> anyhow::Result<Option<(i64, i64)>> {
+        let range: Option<(i64, i64)> =
+            sqlx::query_as("SELECT start, end FROM synced WHERE symbol = $1")
+                .bind(symbol.to_lowercase())
+                .fetch_optional(self.conn.as_ref())
+                .await?;
+        Ok(range)
+    }
+
+    /// [start, end) 已补齐，与原来的已同步区间合并
+    pub async fn mark_synced(&self, symbol: &str, start: i64, end: i64) -> anyhow::Result<()> {
+        let (start, end) = match self.synced(symbol).await? {
+            Some((from, to)) => (start.min(from), end.max(to)),
+            None => (start, end),
+        };
+        sqlx::query("INSERT OR REPLACE INTO synced (symbol, start, end) VALUES ($1, $2, $3)")
+            .bind(symbol.to_lowercase())
+            .bind(start)
+            .bind(end)
+            .execute(self.conn.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn query(&self, req: &SMyTradesReq) -> anyhow::Result<Vec<MyTrade>> {
+        let rows = sqlx::query_as(
+            "SELECT * FROM trades WHERE symbol = $1 AND time >= $2 AND time < $3 \
+            ORDER BY time, trade_id",
+        )
+        .bind(req.symbol.to_lowercase())
+        .bind(req.start)
+        .bind(req.end)
+        .fetch_all(self.conn.as_ref())
+        .await?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(trade_id: i64, time: i64) -> MyTrade {
+        MyTrade {
+            symbol: "BTCUSDT".into(),
+            trade_id,
+            order_id: 7,
+            side: "BUY".into(),
+            price: 100.0,
+            quantity: 0.5,
+            quote_quantity: 50.0,
+            commission: 0.01,
+            commission_asset: "USDT".into(),
+            maker: true,
+            time,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_my_trade_db() {
+        assert_eq!(missing_ranges(None, 10, 20), vec![(10, 20)]);
+        assert_eq!(missing_ranges(Some((10, 20)), 12, 18), vec![]);
+        assert_eq!(
+            missing_ranges(Some((10, 20)), 5, 25),
+            vec![(5, 10), (20, 25)]
+        );
+        // 不相邻的查询连同空隙一起补齐
+        assert_eq!(missing_ranges(Some((10, 20)), 30, 40), vec![(20, 40)]);
+
+        let db = MyTradeDB::with_options(SqliteConnectOptions::new().in_memory(true))
+            .await
+            .unwrap();
+        assert_eq!(db.synced("btcusdt").await.unwrap(), None);
+        db.mark_synced("BTCUSDT", 1_000, 2_000).await.unwrap();
+        db.mark_synced("btcusdt", 2_000, 3_000).await.unwrap();
+        assert_eq!(db.synced("btcusdt").await.unwrap(), Some((1_000, 3_000)));
+
+        let trades = vec![trade(2, 1_500), trade(1, 1_000), trade(3, 3_000)];
+        assert_eq!(db.insert(&trades).await.unwrap(), 3);
+        // 重复补齐的成交被忽略
+        assert_eq!(db.insert(&trades[..1]).await.unwrap(), 0);
+
+        let req = SMyTradesReq {
+            symbol: "btcusdt".into(),
+            start: 1_000,
+            end: 3_000,
+        };
+        let found = db.query(&req).await.unwrap();
+        assert_eq!(
+            found.iter().map(|t| t.trade_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(found[0].symbol, "btcusdt");
+        assert!(found[0].maker);
+    }
+}
@@ -1,49 +1,69 @@
+use crate::codec::{Codec, WireFormat};
+use serde::Deserialize;
 use serde::de::DeserializeOwned;
-use serde_json::Value;
 
+/// 请求中用于分发的字段
+#[derive(Debug, Default, Deserialize)]
+struct Head {
+    id: Option<i64>,
+    method: Option<String>,
+}
+
+/// 策略端的一条请求，按连接协商的编码直接解码为请求类型
 #[derive(Debug)]
-pub struct JsonParser {
-    value: Value,
+pub struct RequestParser {
+    data: Vec<u8>,
+    format: WireFormat,
+    head: Head,
 }
 
-impl JsonParser {
-    pub fn new(s: &str) -> anyhow::Result<Self> {
-        Ok({
-            Self {
-                value: serde_json::from_str(s)?,
-            }
+impl RequestParser {
+    pub fn new(data: &[u8], format: WireFormat) -> anyhow::Result<Self> {
+        Ok(Self {
+            head: format.decode(data)?,
+            data: data.to_vec(),
+            format,
         })
     }
 
-    pub fn contains(&self, key: &str) -> bool {
-        self.value.get(key).is_some()
+    pub fn id(&self) -> Option<i64> {
+        self.head.id
     }
 
-    pub fn get(&self, key: &str) -> Option<&Value> {
-        self.value.get(key)
+    pub fn method(&self) -> Option<&str> {
+        self.head.method.as_deref()
     }
 
     pub fn decode<T>(&self) -> anyhow::Result<T>
     where
         T: DeserializeOwned,
     {
-        Ok(T::deserialize(&self.value)?)
-    }
-
-    pub fn as_str(&self) -> Option<&str> {
-        self.value.as_str()
+        self.format.decode(&self.data)
     }
 }
 
-pub fn decode<T>(value: Value) -> anyhow::Result<T>
-where
-    T: DeserializeOwned,
-{
-    Ok(T::deserialize(value)?)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{SLogin, SRequest};
+
+    #[test]
+    fn test_request_parser() {
+        let login = serde_json::json!({"id": 4, "method": "login",
+                                       "params": {"session_id": 7, "trading": true}});
+        for format in [WireFormat::Json, WireFormat::Msgpack, WireFormat::Cbor] {
+            if !format.is_supported() {
+                continue;
+            }
+            let parser = RequestParser::new(&format.encode(&login).unwrap(), format).unwrap();
+            assert_eq!((parser.id(), parser.method()), (Some(4), Some("login")));
+            let req: SRequest<SLogin> = parser.decode().unwrap();
+            assert_eq!((req.params.session_id, req.params.trading), (7, true));
+        }
 
-impl From<Value> for JsonParser {
-    fn from(value: Value) -> Self {
-        Self { value }
+        // 没有 id 与 method 的消息也能解析，由调用方忽略
+        let parser = RequestParser::new(b"{}", WireFormat::Json).unwrap();
+        assert_eq!((parser.id(), parser.method()), (None, None));
+        assert!(RequestParser::new(b"not json", WireFormat::Json).is_err());
     }
 }
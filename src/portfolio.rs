@@ -0,0 +1,443 @@
+//! 组合层面的敞口汇总与限额
+//!
+//! 汇总所有会话（以及未来的多个交易场所）的持仓，按标的资产（例如 BTC）计算净敞口与总名义价值，
+//! 下单前检查组合限额：单个资产的净持仓上限、全组合总名义价值上限。
+//! 名义价值优先按汇率表换算到报告币种，没有汇率时取最近一次成交价，没有成交时取下单价。
+//! 配置了租户时每个租户只汇总本租户会话的持仓，按租户自己的限额检查，见 [`crate::tenant`]。
+
+use crate::fx::FxRates;
+use crate::tenant::Tenants;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display};
+use std::sync::{Arc, Mutex};
+
+/// 组合限额配置
+///
+/// ```json
+/// "portfolio": {
+///     "net_limits": {"BTC": 2.5, "ETH": 40},
+///     "gross_notional": 500000
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PortfolioConfig {
+    /// 标的资产 -> 净持仓绝对值上限（资产数量），未配置的资产不限制
+    pub net_limits: HashMap<String, f64>,
+    /// 全组合总名义价值上限（报告币种），0 表示不限制
+    pub gross_notional: f64,
+}
+
+/// 一个会话在某个交易场所某个交易对上的持仓
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct PositionKey {
+    venue: String,
+    session_id: u16,
+    symbol: String,
+}
+
+#[derive(Debug, Clone)]
+struct Holding {
+    underlying: String,
+    net: f64,
+}
+
+/// 单个标的资产的敞口
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Exposure {
+    pub underlying: String,
+    /// 净持仓（资产数量）
+    pub net: f64,
+    /// 净名义价值
+    pub notional: f64,
+    /// 各持仓名义价值绝对值之和
+    pub gross_notional: f64,
+}
+
+/// 组合快照，供行情面板展示
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct PortfolioSnapshot {
+    pub exposures: Vec<Exposure>,
+    pub gross_notional: f64,
+}
+
+/// 下单会突破的限额
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitBreach {
+    Net {
+        underlying: String,
+        net: f64,
+        limit: f64,
+    },
+    Gross {
+        notional: f64,
+        limit: f64,
+    },
+}
+
+impl Display for LimitBreach {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Net {
+                underlying,
+                net,
+                limit,
+            } => write!(f, "{} net {} exceeds limit {}", underlying, net, limit),
+            Self::Gross { notional, limit } => {
+                write!(f, "gross notional {} exceeds limit {}", notional, limit)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PortfolioState {
+    holdings: BTreeMap<PositionKey, Holding>,
+    /// (venue, symbol) -> 最新价格
+    prices: HashMap<(String, String), f64>,
+}
+
+impl PortfolioState {
+    fn price(&self, fx: &FxRates, venue: &str, symbol: &str, underlying: &str) -> f64 {
+        if let Some(rate) = fx.rate(underlying, fx.reporting()) {
+            return rate;
+        }
+        self.prices
+            .get(&(venue.to_string(), symbol.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// 只汇总 `visible` 的会话
+    fn snapshot(&self, fx: &FxRates, visible: impl Fn(u16) -> bool) -> PortfolioSnapshot {
+        let mut exposures: BTreeMap<&str, Exposure> = BTreeMap::new();
+        let holdings = self
+            .holdings
+            .iter()
+            .filter(|(key, _)| visible(key.session_id));
+        for (key, holding) in holdings {
+            let price = self.price(fx, &key.venue, &key.symbol, &holding.underlying);
+            let notional = holding.net * price;
+            let exposure = exposures
+                .entry(holding.underlying.as_str())
+                .or_insert_with(|| Exposure {
+                    underlying: holding.underlying.clone(),
+                    net: 0.0,
+                    notional: 0.0,
+                    gross_notional: 0.0,
+                });
+            exposure.net += holding.net;
+            exposure.notional += notional;
+            exposure.gross_notional += notional.abs();
+        }
+
+        let exposures: Vec<_> = exposures.into_values().collect();
+        let gross_notional = exposures.iter().map(|e| e.gross_notional).sum();
+        PortfolioSnapshot {
+            exposures,
+            gross_notional,
+        }
+    }
+}
+
+/// 组合敞口
+///
+/// 可以廉价 clone，在 handler、trade 之间共享；默认不设任何限额。
+#[derive(Clone, Default)]
+pub struct Portfolio {
+    config: Arc<PortfolioConfig>,
+    state: Arc<Mutex<PortfolioState>>,
+    fx: FxRates,
+    tenants: Tenants,
+}
+
+impl Debug for Portfolio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Portfolio")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Portfolio {
+    pub fn new(config: PortfolioConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state: Arc::default(),
+            fx: FxRates::default(),
+            tenants: Tenants::default(),
+        }
+    }
+
+    /// 按租户分别汇总与检查限额
+    pub fn with_tenants(mut self, tenants: Tenants) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// 设置汇率表，名义价值按报告币种计算
+    pub fn with_fx(mut self, fx: FxRates) -> Self {
+        self.fx = fx;
+        self
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut PortfolioState) -> R) -> R {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut state)
+    }
+
+    /// 更新某个会话的持仓，`underlying` 为标的资产，例如 BTCUSDT 的 BTC
+    pub fn update_position(
+        &self,
+        venue: &str,
+        session_id: u16,
+        symbol: &str,
+        underlying: &str,
+        net: f64,
+    ) {
+        let key = PositionKey {
+            venue: venue.to_string(),
+            session_id,
+            symbol: symbol.to_lowercase(),
+        };
+        self.with_state(|state| {
+            if net == 0.0 {
+                state.holdings.remove(&key);
+            } else {
+                state.holdings.insert(
+                    key,
+                    Holding {
+                        underlying: underlying.to_uppercase(),
+                        net,
+                    },
+                );
+            }
+        })
+    }
+
+    pub fn update_price(&self, venue: &str, symbol: &str, price: f64) {
+        if price > 0.0 {
+            self.with_state(|state| {
+                state
+                    .prices
+                    .insert((venue.to_string(), symbol.to_lowercase()), price);
+            })
+        }
+    }
+
+    pub fn exposure(&self, underlying: &str) -> Option<Exposure> {
+        let underlying = underlying.to_uppercase();
+        self.snapshot()
+            .exposures
+            .into_iter()
+            .find(|e| e.underlying == underlying)
+    }
+
+    pub fn snapshot(&self) -> PortfolioSnapshot {
+        self.with_state(|state| state.snapshot(&self.fx, |_| true))
+    }
+
+    /// 会话所属租户的组合快照，没有配置租户时为全部会话
+    pub fn snapshot_for(&self, session_id: u16) -> PortfolioSnapshot {
+        self.with_state(|state| {
+            state.snapshot(&self.fx, |id| self.tenants.same_tenant(id, session_id))
+        })
+    }
+
+    /// 下单前检查：假设 `session_id` 的 `quantity`（买为正、卖为负）全部成交后是否突破组合限额。
+    /// 只检查会扩大敞口的订单，减仓方向的订单总是放行；配置了租户时只汇总同一租户的会话，按租户的限额检查。
+    pub fn check_order(
+        &self,
+        session_id: u16,
+        venue: &str,
+        symbol: &str,
+        underlying: &str,
+        quantity: f64,
+        price: f64,
+    ) -> Result<(), LimitBreach> {
+        let underlying = underlying.to_uppercase();
+        let symbol = symbol.to_lowercase();
+
+        self.with_state(|state| {
+            let price = match state.price(&self.fx, venue, &symbol, &underlying) {
+                p if p > 0.0 => p,
+                _ => price,
+            };
+            let snapshot = state.snapshot(&self.fx, |id| self.tenants.same_tenant(id, session_id));
+            let config = match self.tenants.enabled() {
+                true => self.tenants.limits(session_id).cloned().unwrap_or_default(),
+                false => self.config.as_ref().clone(),
+            };
+            let current = snapshot
+                .exposures
+                .iter()
+                .find(|e| e.underlying == underlying)
+                .map(|e| e.net)
+                .unwrap_or_default();
+            let projected = current + quantity;
+            if projected.abs() <= current.abs() {
+                return Ok(());
+            }
+
+            if let Some(limit) = config.net_limits.get(&underlying) {
+                if projected.abs() > *limit {
+                    return Err(LimitBreach::Net {
+                        underlying,
+                        net: projected,
+                        limit: *limit,
+                    });
+                }
+            }
+
+            if config.gross_notional > 0.0 {
+                let notional = snapshot.gross_notional + (quantity * price).abs();
+                if notional > config.gross_notional {
+                    return Err(LimitBreach::Gross {
+                        notional,
+                        limit: config.gross_notional,
+                    });
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn portfolio() -> Portfolio {
+        Portfolio::new(PortfolioConfig {
+            net_limits: HashMap::from([("BTC".to_string(), 2.0)]),
+            gross_notional: 250_000.0,
+        })
+    }
+
+    #[test]
+    fn test_aggregate_across_sessions_and_venues() {
+        let portfolio = portfolio();
+        portfolio.update_position("spot", 1, "BTCUSDT", "btc", 1.5);
+        portfolio.update_position("usdt", 2, "btcusdt", "BTC", -0.5);
+        portfolio.update_position("usdt", 2, "ethusdt", "ETH", 10.0);
+        portfolio.update_price("spot", "btcusdt", 100_000.0);
+        portfolio.update_price("usdt", "btcusdt", 100_100.0);
+        portfolio.update_price("usdt", "ethusdt", 4_000.0);
+
+        let btc = portfolio.exposure("btc").unwrap();
+        assert_eq!(btc.net, 1.0);
+        assert_eq!(btc.notional, 150_000.0 - 50_050.0);
+        assert_eq!(btc.gross_notional, 150_000.0 + 50_050.0);
+
+        let snapshot = portfolio.snapshot();
+        assert_eq!(snapshot.exposures.len(), 2);
+        assert_eq!(snapshot.gross_notional, 200_050.0 + 40_000.0);
+
+        // 平仓后移除
+        portfolio.update_position("usdt", 2, "ethusdt", "ETH", 0.0);
+        assert!(portfolio.exposure("eth").is_none());
+    }
+
+    #[test]
+    fn test_check_order() {
+        let portfolio = portfolio();
+        portfolio.update_position("spot", 1, "btcusdt", "BTC", 1.5);
+        portfolio.update_price("spot", "btcusdt", 100_000.0);
+
+        assert!(
+            portfolio
+                .check_order(1, "spot", "btcusdt", "BTC", 0.4, 0.0)
+                .is_ok()
+        );
+        assert!(matches!(
+            portfolio.check_order(1, "spot", "btcusdt", "BTC", 0.6, 0.0),
+            Err(LimitBreach::Net { .. })
+        ));
+        // 减仓总是放行
+        assert!(
+            portfolio
+                .check_order(1, "spot", "btcusdt", "BTC", -3.0, 0.0)
+                .is_ok()
+        );
+
+        // 没有成交价时使用下单价
+        assert!(matches!(
+            portfolio.check_order(1, "spot", "ethusdt", "ETH", 30.0, 4_000.0),
+            Err(LimitBreach::Gross { .. })
+        ));
+        assert!(
+            portfolio
+                .check_order(1, "spot", "ethusdt", "ETH", 20.0, 4_000.0)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_tenants() {
+        use crate::tenant::{TenantConfig, Tenants};
+        let tenants = Tenants::new(vec![
+            TenantConfig {
+                id: "alpha".into(),
+                sessions: vec![1],
+                limits: PortfolioConfig {
+                    net_limits: HashMap::from([("BTC".to_string(), 1.0)]),
+                    gross_notional: 0.0,
+                },
+                ..Default::default()
+            },
+            TenantConfig {
+                id: "beta".into(),
+                sessions: vec![2],
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let portfolio = portfolio().with_tenants(tenants);
+        portfolio.update_position("spot", 1, "btcusdt", "BTC", 0.8);
+        portfolio.update_position("usdt", 2, "btcusdt", "BTC", 5.0);
+        portfolio.update_price("spot", "btcusdt", 100_000.0);
+
+        // 每个租户只看到自己的持仓，按自己的限额检查
+        assert_eq!(portfolio.snapshot_for(1).exposures[0].net, 0.8);
+        assert_eq!(portfolio.snapshot_for(2).exposures[0].net, 5.0);
+        assert_eq!(portfolio.snapshot().exposures[0].net, 5.8);
+        assert!(
+            portfolio
+                .check_order(1, "spot", "btcusdt", "BTC", 0.2, 0.0)
+                .is_ok()
+        );
+        assert!(
+            portfolio
+                .check_order(1, "spot", "btcusdt", "BTC", 0.3, 0.0)
+                .is_err()
+        );
+        assert!(
+            portfolio
+                .check_order(2, "usdt", "btcusdt", "BTC", 10.0, 0.0)
+                .is_ok()
+        );
+        // 不属于任何租户的会话没有限额，也看不到其他租户的持仓
+        assert!(portfolio.snapshot_for(3).exposures.is_empty());
+    }
+
+    #[test]
+    fn test_reporting_currency() {
+        let fx = FxRates::default();
+        fx.register("ethbtc", "ETH", "BTC");
+        fx.register("btcusdt", "BTC", "USDT");
+        fx.on_book_ticker("ethbtc", 0.04, 0.04);
+        fx.on_book_ticker("btcusdt", 100_000.0, 100_000.0);
+
+        // ETHBTC 的成交价以 BTC 计，名义价值按 USDT 计
+        let portfolio = portfolio().with_fx(fx);
+        portfolio.update_position("spot", 1, "ethbtc", "ETH", 10.0);
+        portfolio.update_price("spot", "ethbtc", 0.04);
+        let eth = portfolio.exposure("ETH").unwrap();
+        assert!((eth.notional - 40_000.0).abs() < 1e-6);
+    }
+}
@@ -1,76 +1,96 @@
 use crate::chat::Position;
+use crate::storage::{self, FillRecord, OrderRecord, Storage};
+use crate::tenant::Tenants;
 use log::*;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
-use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::{borrow::Borrow, collections::HashMap};
 
 type Positions = HashMap<String, Position>;
 pub struct PositionDB {
-    conn: Arc<Pool<Sqlite>>,
+    storage: Arc<dyn Storage>,
     positions: HashMap<u16, Positions>,
+    /// 按租户分表，不属于租户的会话表名为 session_id
+    tenants: Tenants,
+    /// 保存订单回报与成交
+    orders: bool,
 }
 
 impl PositionDB {
+    /// `db` 为存储的 url 或 SQLite 文件路径，见 [`crate::storage`]
     pub async fn new(db: &str) -> anyhow::Result<Self> {
-        let conn = Arc::new(
-            SqlitePoolOptions::new()
-                .max_connections(1)
-                .connect_with(
-                    SqliteConnectOptions::new()
-                        .create_if_missing(true)
-                        .filename(db),
-                )
-                .await?,
-        );
+        Self::open(db, Tenants::default()).await
+    }
+
+    pub async fn open(db: &str, tenants: Tenants) -> anyhow::Result<Self> {
+        Self::with_storage(storage::open(db).await?, tenants).await
+    }
 
+    /// 属于租户的会话使用 `{tenant}/{session_id}` 表，还没有分表的会话先读原来的表，建表时迁移
+    pub async fn with_storage(storage: Arc<dyn Storage>, tenants: Tenants) -> anyhow::Result<Self> {
         let mut session_positions = HashMap::default();
-        let query = "SELECT name FROM sqlite_master WHERE type='table';";
-        let rows = sqlx::query(query).fetch_all(conn.borrow()).await?;
+        let tables = storage.partitions().await?;
 
-        for row in rows {
-            let session_id: String = row.get(0);
-            let session_id: u16 = session_id.parse()?;
-            let positions = Self::load(conn.clone(), session_id).await?;
+        for table in tables.iter() {
+            let id = table.rsplit_once('/').map_or(table.as_str(), |(_, id)| id);
+            let session_id: u16 = id.parse()?;
+            let expected = tenants.table(session_id);
+            let legacy = *table == session_id.to_string() && !tables.contains(&expected);
+            if *table != expected && !legacy {
+                warn!(
+                    "Skip table {} of session {}, expected {}",
+                    table, session_id, expected
+                );
+                continue;
+            }
+            let positions = Self::load(storage.as_ref(), table, session_id).await?;
 
             session_positions.insert(session_id, positions);
         }
         Ok(Self {
-            conn,
+            storage,
             positions: session_positions,
+            tenants,
+            orders: false,
         })
     }
 
+    /// 同时保存订单回报与成交
+    pub fn with_orders(mut self, orders: bool) -> Self {
+        self.orders = orders;
+        self
+    }
+
     async fn load(
-        conn: Arc<Pool<Sqlite>>,
+        storage: &dyn Storage,
+        table: &str,
         session_id: u16,
     ) -> anyhow::Result<HashMap<String, Position>> {
         let mut positions = HashMap::new();
 
-        let query = format!("SELECT * FROM \"{}\" WHERE net <> 0", session_id);
-        let rows: Vec<Position> = sqlx::query_as(&query).fetch_all(conn.borrow()).await?;
-
-        for row in rows {
+        for (key, net) in storage.load_positions(table).await? {
+            // symbol 列保存的是持仓键，双向持仓的两腿各占一行
+            let row = Position::from_key(&key, net);
             info!("Session {} {:?}", session_id, row);
-            positions.insert(row.symbol.clone(), row);
+            positions.insert(row.key(), row);
         }
 
         Ok(positions)
     }
 
-    pub fn update(&self, session_id: u16, position: Position) {
-        let conn = self.conn.clone();
+    /// 所有会话的持仓
+    pub fn sessions(&self) -> impl Iterator<Item = (u16, &Positions)> {
+        self.positions
+            .iter()
+            .map(|(id, positions)| (*id, positions))
+    }
 
-        let query = format!(
-            "REPLACE INTO \"{}\" (symbol, net) VALUES ($1, $2)",
-            session_id
-        );
+    pub fn update(&self, session_id: u16, position: Position) {
+        let storage = self.storage.clone();
+        let table = self.tenants.table(session_id);
 
         tokio::spawn(async move {
-            match sqlx::query(&query)
-                .bind(position.symbol.clone())
-                .bind(position.net)
-                .execute(conn.borrow())
+            match storage
+                .save_position(&table, &position.key(), position.net)
                 .await
             {
                 Ok(_) => info!("Update {:?}", position),
@@ -79,17 +99,45 @@ impl PositionDB {
         });
     }
 
+    /// 开启 `orders` 时在后台保存订单回报
+    pub fn record_order(&self, order: OrderRecord) {
+        if !self.orders {
+            return;
+        }
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            if let Err(e) = storage.save_order(&order).await {
+                error!("Save order {}: {}", order.order_id, e);
+            }
+        });
+    }
+
+    /// 开启 `orders` 时在后台保存成交
+    pub fn record_fill(&self, fill: FillRecord) {
+        if !self.orders {
+            return;
+        }
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            if let Err(e) = storage.save_fill(&fill).await {
+                error!("Save fill {}: {}", fill.fill.trade_id, e);
+            }
+        });
+    }
+
     pub fn get_positions(&self, session_id: u16) -> Option<&Positions> {
         self.positions.get(&session_id)
     }
 
     pub async fn create_table(&self, session_id: u16) -> anyhow::Result<()> {
-        let query = format!(
-            "CREATE TABLE IF NOT EXISTS \"{}\" (symbol TEXT PRIMARY KEY NOT NULL,  
-            net REAL NOT NULL )",
-            session_id
-        );
-        sqlx::query(&query).execute(self.conn.borrow()).await?;
+        let table = self.tenants.table(session_id);
+        self.storage.create_partition(&table).await?;
+
+        // 会话分配给租户之前的持仓迁移到租户的表
+        let legacy = session_id.to_string();
+        if table != legacy && self.storage.move_partition(&legacy, &table).await? {
+            info!("Move positions of session {} to {}", session_id, table);
+        }
         Ok(())
     }
 }
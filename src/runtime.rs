@@ -0,0 +1,164 @@
+//! 运行时调优
+//!
+//! 默认使用 tokio 多线程运行时，工作线程数等于 CPU 核数，所有任务共享同一组线程。
+//! 网关的热路径是行情扇出：交易所推送经 handler 转发给每个策略端，这条路径上的任何一次跨线程唤醒都会增加延迟。
+//!
+//! - `worker_threads` 减少工作线程可以降低调度与缓存抖动，热路径更稳定；增加则提高 REST、账户推送等并发任务的吞吐。
+//! - `worker_cores` 把运行时的线程（含 blocking 线程池）轮流绑定到指定的核，避免被操作系统迁移，
+//!   适合与其他进程隔离核心的机器；核心被其他负载占用时反而会增加延迟。
+//! - `market_thread` 把 handler（行情扇出与策略端请求处理）放到独立线程上的单线程运行时，可用 `market_core` 绑核。
+//!   handler 不再与其他任务争抢工作线程，尾延迟更低；代价是 handler 只能使用一个核，
+//!   策略端很多、行情很密时吞吐受限于单核。
+//!
+//! 绑核使用 `core_affinity`，支持 Linux、Windows 与 macOS；配置了本机不存在的核时记录警告。
+
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::runtime::{Builder, Runtime};
+use tracing::{info, warn};
+
+/// ```json
+/// "runtime": {
+///     "worker_threads": 4,
+///     "worker_cores": [2, 3, 4, 5],
+///     "market_thread": true,
+///     "market_core": 1
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// 工作线程数，为空时等于 CPU 核数
+    pub worker_threads: Option<usize>,
+    /// 运行时线程轮流绑定的核
+    pub worker_cores: Vec<usize>,
+    /// 在独立的单线程运行时上运行行情扇出
+    pub market_thread: bool,
+    /// 行情扇出线程绑定的核
+    pub market_core: Option<usize>,
+}
+
+impl RuntimeConfig {
+    /// 按配置创建多线程运行时
+    pub fn build(&self) -> std::io::Result<Runtime> {
+        // 在任何线程绑核之前取得可用的核
+        available_cores();
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(n) = self.worker_threads {
+            builder.worker_threads(n.max(1));
+        }
+        if !self.worker_cores.is_empty() {
+            let cores = self.worker_cores.clone();
+            let next = Arc::new(AtomicUsize::new(0));
+            builder.on_thread_start(move || {
+                let core = cores[next.fetch_add(1, Ordering::Relaxed) % cores.len()];
+                if let Err(e) = pin_current_thread(core) {
+                    eprintln!("Pin runtime thread to core {} failed: {}", core, e);
+                }
+            });
+        }
+        builder.build()
+    }
+
+    /// 运行时在日志初始化之前创建，初始化日志后再记录运行时配置
+    pub fn log(&self) {
+        info!(
+            "Runtime worker threads {:?}, cores {:?}, market thread {} on core {:?}",
+            self.worker_threads, self.worker_cores, self.market_thread, self.market_core
+        );
+        let available = available_cores();
+        for core in self.worker_cores.iter().chain(self.market_core.iter()) {
+            if !available.contains(core) {
+                warn!(
+                    "Core {} is not available, available cores {:?}",
+                    core, available
+                );
+            }
+        }
+    }
+
+    /// `market_thread` 开启时在独立线程的单线程运行时上运行 `future`，否则交给当前运行时
+    pub fn spawn_market<F>(&self, future: F) -> std::io::Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        if !self.market_thread {
+            tokio::spawn(future);
+            return Ok(());
+        }
+
+        let core = self.market_core;
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        std::thread::Builder::new()
+            .name("market".into())
+            .spawn(move || {
+                if let Some(core) = core {
+                    match pin_current_thread(core) {
+                        Ok(()) => info!("Market thread pinned to core {}", core),
+                        Err(e) => warn!("Pin market thread to core {} failed: {}", core, e),
+                    }
+                }
+                runtime.block_on(future);
+            })?;
+        Ok(())
+    }
+}
+
+/// 进程启动时可用的核；线程会继承创建者的绑核，所以只在第一次调用时查询
+fn available_cores() -> &'static [usize] {
+    static CORES: OnceLock<Vec<usize>> = OnceLock::new();
+    CORES.get_or_init(|| {
+        core_affinity::get_core_ids()
+            .unwrap_or_default()
+            .iter()
+            .map(|id| id.id)
+            .collect()
+    })
+}
+
+/// 把当前线程绑定到核 `core`
+pub fn pin_current_thread(core: usize) -> anyhow::Result<()> {
+    // core_affinity 对超出范围的核会 panic
+    if !available_cores().contains(&core) {
+        anyhow::bail!("Invalid core {}", core);
+    }
+    match core_affinity::set_for_current(core_affinity::CoreId { id: core }) {
+        true => Ok(()),
+        false => Err(std::io::Error::last_os_error().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_config() {
+        let config: RuntimeConfig =
+            serde_json::from_str(r#"{"worker_threads": 2, "market_thread": true}"#).unwrap();
+        assert_eq!(config.worker_threads, Some(2));
+        assert!(config.worker_cores.is_empty());
+
+        let runtime = config.build().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        runtime.block_on(async {
+            config
+                .spawn_market(async move {
+                    let name = std::thread::current().name().map(String::from);
+                    tx.send(name).unwrap();
+                })
+                .unwrap();
+        });
+        let name = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(name.as_deref(), Some("market"));
+
+        assert!(pin_current_thread(usize::MAX).is_err());
+        let core = available_cores()[0];
+        std::thread::spawn(move || pin_current_thread(core).unwrap())
+            .join()
+            .unwrap();
+        config.log();
+    }
+}
@@ -0,0 +1,505 @@
+//! 策略端协议的 JSON Schema
+//!
+//! 第三方实现策略端时以 schema 为准。结构体的 schema 由 [`object_schema!`] 按字段生成，宏展开时
+//! 解构结构体的全部字段并检查字段类型，结构体增删字段或改了类型而 schema 没有跟着改时编译失败；
+//! 单元枚举由 [`enum_schema!`] 生成，取值为 serde 序列化的名称，漏掉的变体同样编译失败。
+//! [`validate`] 只支持这里生成的关键字，供一致性测试校验客户端发来的消息。
+
+use crate::chat::{
+    OrderType, PositionSide, RawMode, SError, SFill, SGeneralDepth, SGeneralKline, SLogin, SOrder,
+    SOrderCap, SRequest, SResponse, SStreamResult, SSubscription, STradingStatus, SVenueStatus,
+    Side, State, TimeInForce,
+};
+use crate::codec::WireFormat;
+pub use serde_json::Value;
+use serde_json::json;
+
+pub trait Schema {
+    fn schema() -> Value;
+
+    /// 反序列化时是否必须出现
+    fn required() -> bool {
+        true
+    }
+}
+
+/// 按字段生成结构体的 schema，`#[default]` 标记带 `#[serde(default)]` 的字段
+///
+/// ```text
+/// object_schema!(SLogin {
+///     session_id: u16,
+///     name: Option<String>,
+///     #[default] fills: bool,
+/// });
+/// ```
+#[macro_export]
+macro_rules! object_schema {
+    ($name:ident $(<$g:ident>)? { $($(#[$default:ident])? $field:ident: $ty:ty),* $(,)? }) => {
+        impl$(<$g: $crate::schema::Schema>)? $crate::schema::Schema for $name$(<$g>)? {
+            fn schema() -> $crate::schema::Value {
+                #[allow(dead_code)]
+                fn check$(<$g>)?(value: &$name$(<$g>)?) {
+                    let $name { $($field),* } = value;
+                    $(let _: &$ty = $field;)*
+                }
+                $crate::schema::object(&[$((
+                    stringify!($field),
+                    <$ty as $crate::schema::Schema>::schema(),
+                    <$ty as $crate::schema::Schema>::required()
+                        $(&& { let _ = stringify!($default); false })?,
+                )),*])
+            }
+        }
+    };
+}
+
+/// 按变体生成单元枚举的 schema
+#[macro_export]
+macro_rules! enum_schema {
+    ($name:ident { $($variant:ident),* $(,)? }) => {
+        impl $crate::schema::Schema for $name {
+            fn schema() -> $crate::schema::Value {
+                #[allow(dead_code)]
+                fn check(value: $name) {
+                    match value {
+                        $($name::$variant => ()),*
+                    }
+                }
+                $crate::schema::enumeration(&[$(&$name::$variant),*])
+            }
+        }
+    };
+}
+
+/// `fields` 为 (名称, schema, 是否必须出现)
+pub fn object(fields: &[(&str, Value, bool)]) -> Value {
+    let properties: serde_json::Map<_, _> = fields
+        .iter()
+        .map(|(name, schema, _)| (name.to_string(), schema.clone()))
+        .collect();
+    let required: Vec<_> = fields
+        .iter()
+        .filter(|(_, _, required)| *required)
+        .map(|(name, _, _)| *name)
+        .collect();
+    json!({"type": "object", "properties": properties, "required": required})
+}
+
+pub fn enumeration<T: serde::Serialize>(variants: &[&T]) -> Value {
+    let values: Vec<_> = variants
+        .iter()
+        .filter_map(|v| serde_json::to_value(v).ok())
+        .collect();
+    json!({"type": "string", "enum": values})
+}
+
+macro_rules! integer_schema {
+    ($($ty:ty),*) => {
+        $(impl Schema for $ty {
+            fn schema() -> Value {
+                json!({"type": "integer", "minimum": <$ty>::MIN, "maximum": <$ty>::MAX})
+            }
+        })*
+    };
+}
+
+integer_schema!(i32, u16, u32, u64, usize);
+
+impl Schema for i64 {
+    fn schema() -> Value {
+        json!({"type": "integer"})
+    }
+}
+
+impl Schema for f64 {
+    fn schema() -> Value {
+        json!({"type": "number"})
+    }
+}
+
+impl Schema for bool {
+    fn schema() -> Value {
+        json!({"type": "boolean"})
+    }
+}
+
+impl Schema for String {
+    fn schema() -> Value {
+        json!({"type": "string"})
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Value {
+        json!({"anyOf": [T::schema(), {"type": "null"}]})
+    }
+
+    fn required() -> bool {
+        false
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({"type": "array", "items": T::schema()})
+    }
+}
+
+/// 请求的 schema，`method` 固定为给定的方法
+pub fn request<T: Schema>(method: &str) -> Value {
+    let mut schema = SRequest::<T>::schema();
+    schema["properties"]["method"] = json!({"const": method});
+    schema
+}
+
+/// 按 [`Schema`] 生成的 schema 校验 `value`，出错时返回出错的位置与原因
+pub fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+    validate_at(schema, value, "")
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let fail = |msg: String| {
+        let path = if path.is_empty() { "/" } else { path };
+        Err(format!("{}: {}", path, msg))
+    };
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            return fail(format!("expected {}, got {}", expected, value));
+        }
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        if !values.contains(value) {
+            return fail(format!("{} is not one of {}", value, schema["enum"]));
+        }
+    }
+    if let Some(alternatives) = schema.get("anyOf").and_then(Value::as_array) {
+        if !alternatives
+            .iter()
+            .any(|s| validate_at(s, value, path).is_ok())
+        {
+            return fail(format!("{} matches none of the alternatives", value));
+        }
+    }
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matched = match expected {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => true,
+        };
+        if !matched {
+            return fail(format!("expected {}, got {}", expected, value));
+        }
+    }
+    if let (Some(min), Some(v)) = (schema.get("minimum"), value.as_f64()) {
+        if min.as_f64().is_some_and(|min| v < min) {
+            return fail(format!("{} is less than {}", value, min));
+        }
+    }
+    if let (Some(max), Some(v)) = (schema.get("maximum"), value.as_f64()) {
+        if max.as_f64().is_some_and(|max| v > max) {
+            return fail(format!("{} is greater than {}", value, max));
+        }
+    }
+    if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in values.iter().enumerate() {
+            validate_at(items, item, &format!("{}/{}", path, i))?;
+        }
+    }
+    if let Some(object) = value.as_object() {
+        let required = schema.get("required").and_then(Value::as_array);
+        for name in required.into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                return fail(format!("missing {}", name));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, schema) in properties.into_iter().flatten() {
+            if let Some(value) = object.get(name) {
+                validate_at(schema, value, &format!("{}/{}", path, name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+object_schema!(SRequest<T> {
+    id: i64,
+    method: String,
+    params: T,
+});
+
+object_schema!(SResponse<T> { id: i64, result: T });
+
+object_schema!(SError {
+    code: i32,
+    msg: String
+});
+
+object_schema!(SLogin {
+    session_id: u16,
+    name: Option<String>,
+    trading: bool,
+    #[default] cancel_on_disconnect: bool,
+    #[default] fills: bool,
+    #[default] format: WireFormat,
+    heartbeat_ms: Option<u64>,
+    tenant: Option<String>,
+    token: Option<String>,
+    version: Option<u32>,
+});
+
+object_schema!(SStreamResult {
+    stream: String,
+    accepted: bool,
+    #[default] error: Option<SError>,
+});
+
+object_schema!(SGeneralDepth<T> {
+    time: i64,
+    symbol: String,
+    stream: String,
+    bids: Vec<T>,
+    asks: Vec<T>,
+});
+
+object_schema!(SGeneralKline {
+    time: i64,
+    start_time: i64,
+    symbol: String,
+    stream: String,
+    interval: String,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    amount: f64,
+    first_trade_id: i64,
+    last_trade_id: i64,
+    trade_count: i64,
+    is_closed: bool,
+    buy_volume: f64,
+    buy_amount: f64,
+    backfill: bool,
+});
+
+object_schema!(SOrder {
+    state: State,
+    order_id: i64,
+    symbol: String,
+    side: Side,
+    order_type: OrderType,
+    tif: TimeInForce,
+    price: f64,
+    quantity: f64,
+    internal_id: u32,
+    trade_time: i64,
+    trade_price: f64,
+    trade_quantity: f64,
+    acc: f64,
+    making: bool,
+});
+
+object_schema!(SFill {
+    internal_id: u32,
+    order_id: i64,
+    symbol: String,
+    side: Side,
+    trade_id: i64,
+    time: i64,
+    price: f64,
+    quantity: f64,
+    maker: bool,
+    commission: f64,
+    #[default]
+    commission_asset: String,
+});
+
+object_schema!(STradingStatus {
+    time: i64,
+    symbol: String,
+    trading: bool,
+    #[default] reason: Option<String>,
+    #[default] canceled: usize,
+});
+
+object_schema!(SVenueStatus {
+    time: i64,
+    venue: String,
+    #[default] symbol: Option<String>,
+    status: String,
+    previous: String,
+    #[default] msg: Option<String>,
+});
+
+object_schema!(SOrderCap {
+    time: i64,
+    symbol: String,
+    order_id: u32,
+    filter: String,
+    limit: i64,
+    open: i64,
+    queued: bool,
+});
+
+enum_schema!(WireFormat {
+    Json,
+    Msgpack,
+    Cbor
+});
+
+enum_schema!(Side { BUY, SELL });
+
+enum_schema!(OrderType {
+    LIMIT,
+    MARKET,
+    STOP,
+    STOP_MARKET,
+    STOP_LOSS,
+    STOP_LOSS_LIMIT,
+    TAKE_PROFIT,
+    TAKE_PROFIT_LIMIT,
+    TAKE_PROFIT_MARKET,
+    TRAILING_STOP_MARKET,
+    LIMIT_MAKER,
+});
+
+enum_schema!(TimeInForce {
+    GTC,
+    IOC,
+    FOK,
+    GTX,
+    GTD
+});
+
+enum_schema!(PositionSide { BOTH, LONG, SHORT });
+
+enum_schema!(State {
+    CANCELED,
+    PARTIALLY_FILLED,
+    FILLED,
+    NEW,
+    PENDING_NEW,
+    PENDING_CANCEL,
+    REJECTED,
+    EXPIRED,
+    EXPIRED_IN_MATCH,
+    LIVE,
+    MMP_CANCELED,
+    EXPIRED_BY_GATEWAY,
+});
+
+/// `true`、`false` 或转发方式的名称
+impl Schema for RawMode {
+    fn schema() -> Value {
+        #[allow(dead_code)]
+        fn check(value: RawMode) {
+            match value {
+                RawMode::Off | RawMode::Both | RawMode::Only => (),
+            }
+        }
+        json!({"anyOf": [
+            bool::schema(),
+            enumeration(&[&RawMode::Off, &RawMode::Both, &RawMode::Only]),
+        ]})
+    }
+}
+
+/// 流名称，或附带原始帧、标签、分档的对象
+impl Schema for SSubscription {
+    fn schema() -> Value {
+        json!({"anyOf": [
+            String::schema(),
+            object(&[
+                ("stream", String::schema(), true),
+                ("raw", RawMode::schema(), true),
+                ("bucket", f64::schema(), false),
+                ("tag", String::schema(), false),
+            ]),
+            object(&[
+                ("stream", String::schema(), true),
+                ("bucket", f64::schema(), true),
+                ("tag", String::schema(), false),
+            ]),
+            object(&[
+                ("stream", String::schema(), true),
+                ("tag", String::schema(), true),
+            ]),
+        ]})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let schema = request::<SLogin>("login");
+        let login = SRequest {
+            id: 1,
+            method: "login".to_string(),
+            params: SLogin {
+                session_id: 7,
+                name: None,
+                trading: true,
+                cancel_on_disconnect: false,
+                fills: false,
+                format: WireFormat::Msgpack,
+                heartbeat_ms: None,
+                tenant: None,
+                token: None,
+                version: None,
+            },
+        };
+        let value = serde_json::to_value(&login).unwrap();
+        assert_eq!(validate(&schema, &value), Ok(()));
+        // 带默认值的字段与 Option 字段可以不填
+        assert_eq!(
+            schema["properties"]["params"]["required"],
+            json!(["session_id", "trading"])
+        );
+
+        let invalid = |patch: Value| {
+            let mut value = value.clone();
+            for (key, v) in patch.as_object().unwrap() {
+                match v {
+                    Value::Null => value["params"].as_object_mut().unwrap().remove(key),
+                    v => value["params"]
+                        .as_object_mut()
+                        .unwrap()
+                        .insert(key.clone(), v.clone()),
+                };
+            }
+            validate(&schema, &value).unwrap_err()
+        };
+        assert_eq!(
+            invalid(json!({"trading": null})),
+            "/params: missing trading"
+        );
+        assert_eq!(
+            invalid(json!({"session_id": 70000})),
+            "/params/session_id: 70000 is greater than 65535"
+        );
+        assert!(invalid(json!({"format": "xml"})).starts_with("/params/format"));
+        assert!(invalid(json!({"name": 1})).starts_with("/params/name"));
+        let mut other = value.clone();
+        other["method"] = json!("subscribe");
+        assert!(validate(&schema, &other).is_err());
+
+        let subscription = SSubscription::schema();
+        for valid in [
+            json!("btcusdt@depth5"),
+            json!({"stream": "a", "tag": "t"}),
+            json!({"stream": "a", "raw": "both"}),
+        ] {
+            assert_eq!(validate(&subscription, &valid), Ok(()));
+        }
+        assert!(validate(&subscription, &json!({"stream": "a"})).is_err());
+    }
+}
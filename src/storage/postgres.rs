@@ -0,0 +1,345 @@
+//! Postgres 后端，所有分区的持仓在一张 `positions` 表中，多个网关可以共用一个库
+
+use super::{
+    AUDIT_COUNTS, AUDIT_GAPS, AUDIT_MISSING_FILLS, Audit, DailyReport, FillRecord, MarketSnapshot,
+    MissingFill, OrderRecord, SnapshotGap, Storage,
+};
+use futures_util::future::BoxFuture;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres, QueryBuilder};
+use tracing::info;
+
+/// 一条 INSERT 最多写入的快照数，不超过 Postgres 的参数个数限制
+const SNAPSHOT_CHUNK: usize = 10000;
+
+#[derive(Debug)]
+pub struct PostgresStorage {
+    conn: Pool<Postgres>,
+}
+
+/// 当前的表结构版本，记在 `schema_version` 表中
+const SCHEMA_VERSION: i32 = 2;
+
+/// 迁移时持有的 advisory lock，共用一个库的网关同时启动时依次迁移
+const SCHEMA_LOCK: i64 = 0x6366_6c6f_7700;
+
+const TABLES: [&str; 6] = [
+    "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS positions (partition TEXT NOT NULL, symbol TEXT NOT NULL,
+    net DOUBLE PRECISION NOT NULL, PRIMARY KEY (partition, symbol))",
+    "CREATE TABLE IF NOT EXISTS orders (time BIGINT NOT NULL, session_id INTEGER NOT NULL,
+    order_id BIGINT NOT NULL, exchange_order_id BIGINT NOT NULL, update_time BIGINT NOT NULL,
+    trade_id BIGINT NOT NULL, symbol TEXT NOT NULL, state TEXT NOT NULL, data TEXT NOT NULL,
+    strategy TEXT, tags TEXT NOT NULL, UNIQUE (symbol, exchange_order_id, update_time, state, trade_id))",
+    "CREATE TABLE IF NOT EXISTS fills (session_id INTEGER NOT NULL, internal_id BIGINT NOT NULL,
+    order_id BIGINT NOT NULL, symbol TEXT NOT NULL, side TEXT NOT NULL, trade_id BIGINT NOT NULL,
+    time BIGINT NOT NULL, price DOUBLE PRECISION NOT NULL, quantity DOUBLE PRECISION NOT NULL,
+    maker BOOLEAN NOT NULL, commission DOUBLE PRECISION NOT NULL,
+    commission_asset TEXT NOT NULL, strategy TEXT, tags TEXT NOT NULL,
+    UNIQUE (symbol, order_id, trade_id))",
+    "CREATE TABLE IF NOT EXISTS snapshots (time BIGINT NOT NULL, stream TEXT NOT NULL,
+    seq BIGINT NOT NULL, data TEXT NOT NULL, UNIQUE (stream, seq))",
+    "CREATE TABLE IF NOT EXISTS reports (day TEXT NOT NULL, venue TEXT NOT NULL,
+    start BIGINT NOT NULL, time BIGINT NOT NULL, data TEXT NOT NULL,
+    PRIMARY KEY (day, venue, start))",
+];
+
+/// `MIGRATIONS[i]` 把版本 i 升级到 i + 1，加入迁移之前的版本建的表可能已经有新的列，语句都可以重复执行
+const MIGRATIONS: [&[&str]; 2] = [
+    // 订单、成交与快照的唯一键。之前写入的订单没有交易所订单号，按行号记为负数，`update_time` 取写入
+    // 时间；之前的快照没有编号，也按行号记为负数；重复的成交只保留一行
+    &[
+        "ALTER TABLE orders ADD COLUMN IF NOT EXISTS exchange_order_id BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN IF NOT EXISTS update_time BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN IF NOT EXISTS trade_id BIGINT NOT NULL DEFAULT -1",
+        "ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS seq BIGINT NOT NULL DEFAULT -1",
+        "UPDATE orders o SET exchange_order_id = -r.n, update_time = o.time FROM
+        (SELECT ctid, row_number() OVER () AS n FROM orders WHERE exchange_order_id = 0) r
+        WHERE o.ctid = r.ctid",
+        "UPDATE snapshots s SET seq = -r.n FROM
+        (SELECT ctid, row_number() OVER () AS n FROM snapshots WHERE seq = -1) r
+        WHERE s.ctid = r.ctid",
+        "DELETE FROM fills a USING fills b WHERE a.ctid > b.ctid AND a.symbol = b.symbol
+        AND a.order_id = b.order_id AND a.trade_id = b.trade_id",
+        "CREATE UNIQUE INDEX IF NOT EXISTS orders_key
+        ON orders (symbol, exchange_order_id, update_time, state, trade_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS fills_key ON fills (symbol, order_id, trade_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS snapshots_key ON snapshots (stream, seq)",
+    ],
+    // 订单与成交的 `strategy` 与 `tags`
+    &[
+        "ALTER TABLE orders ADD COLUMN IF NOT EXISTS strategy TEXT,
+        ADD COLUMN IF NOT EXISTS tags TEXT NOT NULL DEFAULT ''",
+        "ALTER TABLE fills ADD COLUMN IF NOT EXISTS strategy TEXT,
+        ADD COLUMN IF NOT EXISTS tags TEXT NOT NULL DEFAULT ''",
+    ],
+];
+
+/// 迁移过的表列的顺序与新建的表不同，写入时列出列名
+const INSERT_ORDER: &str = "INSERT INTO orders (time, session_id, order_id, exchange_order_id,
+    update_time, trade_id, symbol, state, data, strategy, tags)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) ON CONFLICT DO NOTHING";
+
+const INSERT_FILL: &str = "INSERT INTO fills (session_id, internal_id, order_id, symbol, side,
+    trade_id, time, price, quantity, maker, commission, commission_asset, strategy, tags)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) ON CONFLICT DO NOTHING";
+
+impl PostgresStorage {
+    pub async fn open(url: &str) -> anyhow::Result<Self> {
+        let conn = PgPoolOptions::new().max_connections(4).connect(url).await?;
+        let storage = Self { conn };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+
+    /// 新库直接建最新的表并记为最新版本；已有 `orders` 表的库从记录的版本开始迁移，没有记录时为版本 0。
+    /// 建表与迁移在一个事务中完成
+    async fn migrate(&self) -> anyhow::Result<()> {
+        let mut tx = self.conn.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(SCHEMA_LOCK)
+            .execute(&mut *tx)
+            .await?;
+        let (fresh,): (bool,) = sqlx::query_as("SELECT to_regclass('orders') IS NULL")
+            .fetch_one(&mut *tx)
+            .await?;
+        for query in TABLES {
+            sqlx::query(query).execute(&mut *tx).await?;
+        }
+        let (recorded,): (Option<i32>,) = sqlx::query_as("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&mut *tx)
+            .await?;
+        let version = match fresh {
+            true => SCHEMA_VERSION,
+            false => recorded.unwrap_or(0),
+        };
+        anyhow::ensure!(
+            version <= SCHEMA_VERSION,
+            "storage schema version {} is newer than {}",
+            version,
+            SCHEMA_VERSION
+        );
+        for migration in &MIGRATIONS[version as usize..] {
+            for query in migration.iter() {
+                sqlx::query(query).execute(&mut *tx).await?;
+            }
+        }
+        sqlx::query("DELETE FROM schema_version")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO schema_version VALUES ($1)")
+            .bind(SCHEMA_VERSION)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        if version < SCHEMA_VERSION {
+            info!(
+                "Storage schema migrated from version {} to {}",
+                version, SCHEMA_VERSION
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn partitions(&self) -> BoxFuture<'_, anyhow::Result<Vec<String>>> {
+        Box::pin(async move {
+            let partitions: Vec<(String,)> =
+                sqlx::query_as("SELECT DISTINCT partition FROM positions")
+                    .fetch_all(&self.conn)
+                    .await?;
+            Ok(partitions.into_iter().map(|(p,)| p).collect())
+        })
+    }
+
+    fn load_positions<'a>(
+        &'a self,
+        partition: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<(String, f64)>>> {
+        Box::pin(async move {
+            Ok(sqlx::query_as(
+                "SELECT symbol, net FROM positions WHERE partition = $1 AND net <> 0",
+            )
+            .bind(partition)
+            .fetch_all(&self.conn)
+            .await?)
+        })
+    }
+
+    /// 所有分区共用一张表，不需要建表
+    fn create_partition<'a>(&'a self, _partition: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn move_partition<'a>(
+        &'a self,
+        from: &'a str,
+        to: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(async move {
+            let mut tx = self.conn.begin().await?;
+            sqlx::query(
+                "INSERT INTO positions SELECT $2, symbol, net FROM positions WHERE partition = $1
+                ON CONFLICT DO NOTHING",
+            )
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx)
+            .await?;
+            let moved = sqlx::query("DELETE FROM positions WHERE partition = $1")
+                .bind(from)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+            Ok(moved > 0)
+        })
+    }
+
+    fn save_position<'a>(
+        &'a self,
+        partition: &'a str,
+        key: &'a str,
+        net: f64,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT INTO positions VALUES ($1, $2, $3)
+                ON CONFLICT (partition, symbol) DO UPDATE SET net = EXCLUDED.net",
+            )
+            .bind(partition)
+            .bind(key)
+            .bind(net)
+            .execute(&self.conn)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn save_order<'a>(&'a self, order: &'a OrderRecord) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query(INSERT_ORDER)
+                .bind(order.time)
+                .bind(order.session_id as i32)
+                .bind(order.order_id as i64)
+                .bind(order.exchange_order_id)
+                .bind(order.update_time)
+                .bind(order.trade_id)
+                .bind(&order.symbol)
+                .bind(&order.state)
+                .bind(&order.data)
+                .bind(&order.strategy)
+                .bind(order.tags.join(","))
+                .execute(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_fill<'a>(&'a self, record: &'a FillRecord) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let fill = &record.fill;
+            sqlx::query(INSERT_FILL)
+                .bind(record.session_id as i32)
+                .bind(fill.internal_id as i64)
+                .bind(fill.order_id)
+                .bind(&fill.symbol)
+                .bind(format!("{:?}", fill.side))
+                .bind(fill.trade_id)
+                .bind(fill.time)
+                .bind(fill.price)
+                .bind(fill.quantity)
+                .bind(fill.maker)
+                .bind(fill.commission)
+                .bind(&fill.commission_asset)
+                .bind(&record.strategy)
+                .bind(record.tags.join(","))
+                .execute(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_snapshots<'a>(
+        &'a self,
+        snapshots: &'a [MarketSnapshot],
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            for chunk in snapshots.chunks(SNAPSHOT_CHUNK) {
+                let mut query = QueryBuilder::<Postgres>::new(
+                    "INSERT INTO snapshots (time, stream, seq, data) ",
+                );
+                query.push_values(chunk, |mut row, snapshot| {
+                    row.push_bind(snapshot.time)
+                        .push_bind(&snapshot.stream)
+                        .push_bind(snapshot.seq)
+                        .push_bind(&snapshot.data);
+                });
+                query.push(" ON CONFLICT DO NOTHING");
+                query.build().execute(&self.conn).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn save_report<'a>(&'a self, report: &'a DailyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT INTO reports VALUES ($1, $2, $3, $4, $5) ON CONFLICT (day, venue, start)
+                DO UPDATE SET time = EXCLUDED.time, data = EXCLUDED.data",
+            )
+            .bind(&report.day)
+            .bind(&report.venue)
+            .bind(report.start)
+            .bind(report.time)
+            .bind(&report.data)
+            .execute(&self.conn)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// 唯一键保证没有重复的行
+    fn audit(&self, start: i64, end: i64) -> BoxFuture<'_, anyhow::Result<Audit>> {
+        Box::pin(async move {
+            let (orders, fills, snapshots): (i64, i64, i64) = sqlx::query_as(AUDIT_COUNTS)
+                .bind(start)
+                .bind(end)
+                .fetch_one(&self.conn)
+                .await?;
+            let missing: Vec<(String, i64, i64)> = sqlx::query_as(AUDIT_MISSING_FILLS)
+                .bind(start)
+                .bind(end)
+                .fetch_all(&self.conn)
+                .await?;
+            let gaps: Vec<(String, i64, i64)> = sqlx::query_as(AUDIT_GAPS)
+                .bind(start)
+                .bind(end)
+                .fetch_all(&self.conn)
+                .await?;
+            Ok(Audit {
+                orders: orders as u64,
+                fills: fills as u64,
+                snapshots: snapshots as u64,
+                missing_fills: missing
+                    .into_iter()
+                    .map(|(symbol, order_id, trade_id)| MissingFill {
+                        symbol,
+                        order_id,
+                        trade_id,
+                    })
+                    .collect(),
+                gaps: gaps
+                    .into_iter()
+                    .map(|(stream, after, before)| SnapshotGap {
+                        stream,
+                        after,
+                        before,
+                    })
+                    .collect(),
+                duplicates: 0,
+            })
+        })
+    }
+}
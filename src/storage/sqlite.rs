@@ -0,0 +1,639 @@
+//! SQLite 后端，持仓每个分区一张表，与之前的持仓库文件兼容
+
+use super::{
+    AUDIT_COUNTS, AUDIT_GAPS, AUDIT_MISSING_FILLS, Audit, DailyReport, FillRecord, MarketSnapshot,
+    MissingFill, OrderRecord, SnapshotGap, Storage,
+};
+use futures_util::future::BoxFuture;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Pool, QueryBuilder, Sqlite, SqliteConnection};
+use tracing::info;
+
+/// 订单、成交、快照与报告的表，不是持仓分区
+const RESERVED: [&str; 4] = ["orders", "fills", "snapshots", "reports"];
+
+/// 一条 INSERT 最多写入的快照数，不超过 SQLite 的参数个数限制
+const SNAPSHOT_CHUNK: usize = 1000;
+
+/// 迁移过的表列的顺序与新建的表不同，写入时列出列名
+const INSERT_ORDER: &str = "INSERT OR IGNORE INTO orders (time, session_id, order_id,
+    exchange_order_id, update_time, trade_id, symbol, state, data, strategy, tags)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)";
+
+const INSERT_FILL: &str = "INSERT OR IGNORE INTO fills (session_id, internal_id, order_id, symbol,
+    side, trade_id, time, price, quantity, maker, commission, commission_asset, strategy, tags)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)";
+
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Pool<Sqlite>,
+}
+
+/// 当前的表结构版本，记在 `PRAGMA user_version` 中
+const SCHEMA_VERSION: i64 = 2;
+
+const TABLES: [&str; 4] = [
+    "CREATE TABLE IF NOT EXISTS orders (time INTEGER NOT NULL, session_id INTEGER NOT NULL,
+    order_id INTEGER NOT NULL, exchange_order_id INTEGER NOT NULL,
+    update_time INTEGER NOT NULL, trade_id INTEGER NOT NULL, symbol TEXT NOT NULL,
+    state TEXT NOT NULL, data TEXT NOT NULL, strategy TEXT, tags TEXT NOT NULL,
+    UNIQUE (symbol, exchange_order_id, update_time, state, trade_id))",
+    "CREATE TABLE IF NOT EXISTS fills (session_id INTEGER NOT NULL, internal_id INTEGER NOT NULL,
+    order_id INTEGER NOT NULL, symbol TEXT NOT NULL, side TEXT NOT NULL, trade_id INTEGER NOT NULL,
+    time INTEGER NOT NULL, price REAL NOT NULL, quantity REAL NOT NULL, maker INTEGER NOT NULL,
+    commission REAL NOT NULL, commission_asset TEXT NOT NULL, strategy TEXT,
+    tags TEXT NOT NULL, UNIQUE (symbol, order_id, trade_id))",
+    "CREATE TABLE IF NOT EXISTS snapshots (time INTEGER NOT NULL, stream TEXT NOT NULL,
+    seq INTEGER NOT NULL, data TEXT NOT NULL, UNIQUE (stream, seq))",
+    "CREATE TABLE IF NOT EXISTS reports (day TEXT NOT NULL, venue TEXT NOT NULL,
+    start INTEGER NOT NULL, time INTEGER NOT NULL, data TEXT NOT NULL,
+    UNIQUE (day, venue, start))",
+];
+
+impl SqliteStorage {
+    /// `sqlite://pos.db` 或文件路径，文件不存在时创建
+    pub async fn open(url: &str) -> anyhow::Result<Self> {
+        let path = url.strip_prefix("sqlite://").unwrap_or(url);
+        let conn = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .create_if_missing(true)
+                    .filename(path),
+            )
+            .await?;
+        let storage = Self { conn };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+
+    /// 新库直接建最新的表并记为最新版本；已有 `orders` 表的库从记录的版本开始逐个迁移，每个版本一个事务
+    async fn migrate(&self) -> anyhow::Result<()> {
+        let (mut version,): (i64,) = sqlx::query_as("PRAGMA user_version")
+            .fetch_one(&self.conn)
+            .await?;
+        anyhow::ensure!(
+            version <= SCHEMA_VERSION,
+            "storage schema version {} is newer than {}",
+            version,
+            SCHEMA_VERSION
+        );
+        let fresh = !self.exists("orders").await?;
+        let mut tx = self.conn.begin().await?;
+        for query in TABLES {
+            sqlx::query(query).execute(&mut *tx).await?;
+        }
+        if fresh {
+            version = SCHEMA_VERSION;
+            set_version(&mut tx, version).await?;
+        }
+        tx.commit().await?;
+
+        while version < SCHEMA_VERSION {
+            let mut tx = self.conn.begin().await?;
+            match version {
+                0 => migrate_keys(&mut tx).await?,
+                _ => migrate_tags(&mut tx).await?,
+            }
+            version += 1;
+            set_version(&mut tx, version).await?;
+            tx.commit().await?;
+            info!("Storage schema migrated to version {}", version);
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, table: &str) -> anyhow::Result<bool> {
+        Ok(
+            sqlx::query("SELECT name FROM sqlite_master WHERE type='table' AND name=$1")
+                .bind(table)
+                .fetch_optional(&self.conn)
+                .await?
+                .is_some(),
+        )
+    }
+}
+
+/// 版本 1：订单、成交与快照的唯一键。之前写入的订单没有交易所订单号，记为 -rowid，`update_time` 取写入
+/// 时间；之前的快照没有编号，记为 -rowid，两者都不会与新写入的行冲突；重复的成交只保留第一行
+async fn migrate_keys(tx: &mut SqliteConnection) -> anyhow::Result<()> {
+    add_column(
+        tx,
+        "orders",
+        "exchange_order_id",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    add_column(tx, "orders", "update_time", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column(tx, "orders", "trade_id", "INTEGER NOT NULL DEFAULT -1").await?;
+    add_column(tx, "snapshots", "seq", "INTEGER NOT NULL DEFAULT -1").await?;
+    for query in [
+        "UPDATE orders SET exchange_order_id = -rowid, update_time = time
+        WHERE exchange_order_id = 0",
+        "UPDATE snapshots SET seq = -rowid WHERE seq = -1",
+        "DELETE FROM fills WHERE rowid NOT IN
+        (SELECT MIN(rowid) FROM fills GROUP BY symbol, order_id, trade_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS orders_key
+        ON orders (symbol, exchange_order_id, update_time, state, trade_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS fills_key ON fills (symbol, order_id, trade_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS snapshots_key ON snapshots (stream, seq)",
+    ] {
+        sqlx::query(query).execute(&mut *tx).await?;
+    }
+    Ok(())
+}
+
+/// 版本 2：订单与成交的 `strategy` 与 `tags`
+async fn migrate_tags(tx: &mut SqliteConnection) -> anyhow::Result<()> {
+    for table in ["orders", "fills"] {
+        add_column(tx, table, "strategy", "TEXT").await?;
+        add_column(tx, table, "tags", "TEXT NOT NULL DEFAULT ''").await?;
+    }
+    Ok(())
+}
+
+async fn set_version(tx: &mut SqliteConnection, version: i64) -> anyhow::Result<()> {
+    let query = format!("PRAGMA user_version = {}", version);
+    sqlx::query(&query).execute(&mut *tx).await?;
+    Ok(())
+}
+
+/// 没有这一列时加上，加入迁移之前的版本建的表可能已经有这一列
+async fn add_column(
+    tx: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> anyhow::Result<()> {
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM pragma_table_info($1) WHERE name = $2")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&mut *tx)
+            .await?;
+    if count == 0 {
+        let query = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition);
+        sqlx::query(&query).execute(&mut *tx).await?;
+    }
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn partitions(&self) -> BoxFuture<'_, anyhow::Result<Vec<String>>> {
+        Box::pin(async move {
+            let tables: Vec<(String,)> =
+                sqlx::query_as("SELECT name FROM sqlite_master WHERE type='table'")
+                    .fetch_all(&self.conn)
+                    .await?;
+            Ok(tables
+                .into_iter()
+                .map(|(name,)| name)
+                .filter(|name| !RESERVED.contains(&name.as_str()))
+                .collect())
+        })
+    }
+
+    fn load_positions<'a>(
+        &'a self,
+        partition: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<(String, f64)>>> {
+        Box::pin(async move {
+            let query = format!("SELECT symbol, net FROM \"{}\" WHERE net <> 0", partition);
+            Ok(sqlx::query_as(&query).fetch_all(&self.conn).await?)
+        })
+    }
+
+    fn create_partition<'a>(&'a self, partition: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let query = format!(
+                "CREATE TABLE IF NOT EXISTS \"{}\" (symbol TEXT PRIMARY KEY NOT NULL,
+                net REAL NOT NULL )",
+                partition
+            );
+            sqlx::query(&query).execute(&self.conn).await?;
+            Ok(())
+        })
+    }
+
+    fn move_partition<'a>(
+        &'a self,
+        from: &'a str,
+        to: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(async move {
+            if !self.exists(from).await? {
+                return Ok(false);
+            }
+            let query = format!(
+                "INSERT OR IGNORE INTO \"{}\" SELECT symbol, net FROM \"{}\"",
+                to, from
+            );
+            sqlx::query(&query).execute(&self.conn).await?;
+            let query = format!("DROP TABLE \"{}\"", from);
+            sqlx::query(&query).execute(&self.conn).await?;
+            Ok(true)
+        })
+    }
+
+    fn save_position<'a>(
+        &'a self,
+        partition: &'a str,
+        key: &'a str,
+        net: f64,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let query = format!(
+                "REPLACE INTO \"{}\" (symbol, net) VALUES ($1, $2)",
+                partition
+            );
+            sqlx::query(&query)
+                .bind(key)
+                .bind(net)
+                .execute(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_order<'a>(&'a self, order: &'a OrderRecord) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query(INSERT_ORDER)
+                .bind(order.time)
+                .bind(order.session_id)
+                .bind(order.order_id)
+                .bind(order.exchange_order_id)
+                .bind(order.update_time)
+                .bind(order.trade_id)
+                .bind(&order.symbol)
+                .bind(&order.state)
+                .bind(&order.data)
+                .bind(&order.strategy)
+                .bind(order.tags.join(","))
+                .execute(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_fill<'a>(&'a self, record: &'a FillRecord) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let fill = &record.fill;
+            sqlx::query(INSERT_FILL)
+                .bind(record.session_id)
+                .bind(fill.internal_id)
+                .bind(fill.order_id)
+                .bind(&fill.symbol)
+                .bind(format!("{:?}", fill.side))
+                .bind(fill.trade_id)
+                .bind(fill.time)
+                .bind(fill.price)
+                .bind(fill.quantity)
+                .bind(fill.maker)
+                .bind(fill.commission)
+                .bind(&fill.commission_asset)
+                .bind(&record.strategy)
+                .bind(record.tags.join(","))
+                .execute(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_snapshots<'a>(
+        &'a self,
+        snapshots: &'a [MarketSnapshot],
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            for chunk in snapshots.chunks(SNAPSHOT_CHUNK) {
+                let mut query = QueryBuilder::<Sqlite>::new(
+                    "INSERT OR IGNORE INTO snapshots (time, stream, seq, data) ",
+                );
+                query.push_values(chunk, |mut row, snapshot| {
+                    row.push_bind(snapshot.time)
+                        .push_bind(&snapshot.stream)
+                        .push_bind(snapshot.seq)
+                        .push_bind(&snapshot.data);
+                });
+                query.build().execute(&self.conn).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn save_report<'a>(&'a self, report: &'a DailyReport) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query("REPLACE INTO reports VALUES ($1, $2, $3, $4, $5)")
+                .bind(&report.day)
+                .bind(&report.venue)
+                .bind(report.start)
+                .bind(report.time)
+                .bind(&report.data)
+                .execute(&self.conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// 唯一键保证没有重复的行
+    fn audit(&self, start: i64, end: i64) -> BoxFuture<'_, anyhow::Result<Audit>> {
+        Box::pin(async move {
+            let (orders, fills, snapshots): (i64, i64, i64) = sqlx::query_as(AUDIT_COUNTS)
+                .bind(start)
+                .bind(end)
+                .fetch_one(&self.conn)
+                .await?;
+            let missing: Vec<(String, i64, i64)> = sqlx::query_as(AUDIT_MISSING_FILLS)
+                .bind(start)
+                .bind(end)
+                .fetch_all(&self.conn)
+                .await?;
+            let gaps: Vec<(String, i64, i64)> = sqlx::query_as(AUDIT_GAPS)
+                .bind(start)
+                .bind(end)
+                .fetch_all(&self.conn)
+                .await?;
+            Ok(Audit {
+                orders: orders as u64,
+                fills: fills as u64,
+                snapshots: snapshots as u64,
+                missing_fills: missing
+                    .into_iter()
+                    .map(|(symbol, order_id, trade_id)| MissingFill {
+                        symbol,
+                        order_id,
+                        trade_id,
+                    })
+                    .collect(),
+                gaps: gaps
+                    .into_iter()
+                    .map(|(stream, after, before)| SnapshotGap {
+                        stream,
+                        after,
+                        before,
+                    })
+                    .collect(),
+                duplicates: 0,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{SFill, Side};
+
+    #[tokio::test]
+    async fn test_sqlite() {
+        let path = std::env::temp_dir().join(format!("storage-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let storage = SqliteStorage::open(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+
+        storage.create_partition("1").await.unwrap();
+        storage.save_position("1", "BTCUSDT", 0.5).await.unwrap();
+        storage.save_position("1", "ETHUSDT", 0.0).await.unwrap();
+        storage.create_partition("alpha/1").await.unwrap();
+        storage
+            .save_position("alpha/1", "BTCUSDT", 1.0)
+            .await
+            .unwrap();
+        assert_eq!(storage.partitions().await.unwrap().len(), 2);
+        assert_eq!(
+            storage.load_positions("1").await.unwrap(),
+            vec![("BTCUSDT".to_string(), 0.5)]
+        );
+
+        // 目标分区已有的持仓保留
+        assert!(storage.move_partition("1", "alpha/1").await.unwrap());
+        assert!(!storage.move_partition("1", "alpha/1").await.unwrap());
+        assert_eq!(storage.partitions().await.unwrap(), vec!["alpha/1"]);
+        assert_eq!(
+            storage.load_positions("alpha/1").await.unwrap(),
+            vec![("BTCUSDT".to_string(), 1.0)]
+        );
+
+        // 重复写入的回报、成交与快照都被忽略
+        let order = |update_time: i64, state: &str, trade_id: i64| OrderRecord {
+            time: 1,
+            session_id: 1,
+            order_id: 7,
+            exchange_order_id: 100,
+            update_time,
+            trade_id,
+            symbol: "BTCUSDT".into(),
+            state: state.into(),
+            strategy: Some("mm".into()),
+            tags: vec!["alpha".into(), "btc".into()],
+            data: "{}".into(),
+        };
+        for order in [
+            order(1, "NEW", -1),
+            order(2, "PARTIALLY_FILLED", 1),
+            order(2, "PARTIALLY_FILLED", 1),
+            order(2, "FILLED", 2),
+        ] {
+            storage.save_order(&order).await.unwrap();
+        }
+        let fill = FillRecord {
+            session_id: 1,
+            strategy: None,
+            tags: Vec::new(),
+            fill: SFill {
+                internal_id: 7,
+                order_id: 100,
+                symbol: "BTCUSDT".into(),
+                side: Side::BUY,
+                trade_id: 1,
+                time: 2,
+                price: 100.0,
+                quantity: 0.5,
+                maker: true,
+                commission: 0.01,
+                commission_asset: "USDT".into(),
+            },
+        };
+        storage.save_fill(&fill).await.unwrap();
+        storage.save_fill(&fill).await.unwrap();
+        let snapshots: Vec<_> = (0..(SNAPSHOT_CHUNK as i64 + 5))
+            .filter(|seq| *seq != 10)
+            .map(|seq| MarketSnapshot {
+                time: seq,
+                stream: "btcusdt@bookTicker".into(),
+                seq,
+                data: "{}".into(),
+            })
+            .collect();
+        storage.save_snapshots(&snapshots).await.unwrap();
+        storage.save_snapshots(&snapshots[..5]).await.unwrap();
+
+        let audit = storage.audit(0, i64::MAX).await.unwrap();
+        assert_eq!(
+            (audit.orders, audit.fills, audit.snapshots),
+            (3, 1, snapshots.len() as u64)
+        );
+        assert_eq!(
+            audit.missing_fills,
+            vec![MissingFill {
+                symbol: "BTCUSDT".into(),
+                order_id: 100,
+                trade_id: 2
+            }]
+        );
+        assert_eq!(
+            audit.gaps,
+            vec![SnapshotGap {
+                stream: "btcusdt@bookTicker".into(),
+                after: 9,
+                before: 11
+            }]
+        );
+        assert!(!audit.clean());
+        assert!(storage.audit(20, i64::MAX).await.unwrap().clean());
+
+        let (side,): (String,) = sqlx::query_as("SELECT side FROM fills")
+            .fetch_one(&storage.conn)
+            .await
+            .unwrap();
+        assert_eq!(side, "BUY");
+        let (strategy, tags): (Option<String>, String) =
+            sqlx::query_as("SELECT strategy, tags FROM orders LIMIT 1")
+                .fetch_one(&storage.conn)
+                .await
+                .unwrap();
+        assert_eq!(
+            (strategy.as_deref(), tags.as_str()),
+            (Some("mm"), "alpha,btc")
+        );
+
+        // 同一天同一次运行的报告被覆盖
+        for (time, data) in [(1, "{\"placed\":1}"), (2, "{\"placed\":2}")] {
+            let report = DailyReport {
+                day: "2024-01-02".into(),
+                venue: "binance-spot".into(),
+                start: 1,
+                time,
+                data: data.into(),
+            };
+            storage.save_report(&report).await.unwrap();
+        }
+        let reports: Vec<(i64, String)> = sqlx::query_as("SELECT time, data FROM reports")
+            .fetch_all(&storage.conn)
+            .await
+            .unwrap();
+        assert_eq!(reports, vec![(2, "{\"placed\":2}".to_string())]);
+        assert_eq!(storage.partitions().await.unwrap(), vec!["alpha/1"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let path = std::env::temp_dir().join(format!("storage-old-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let url = format!("sqlite://{}", path.display());
+
+        // 加入唯一键之前的表结构与数据：同一毫秒的两条回报，重复的成交，没有编号的快照
+        {
+            let conn = SqlitePoolOptions::new()
+                .connect_with(
+                    SqliteConnectOptions::new()
+                        .create_if_missing(true)
+                        .filename(&path),
+                )
+                .await
+                .unwrap();
+            for query in [
+                "CREATE TABLE \"1\" (symbol TEXT PRIMARY KEY NOT NULL, net REAL NOT NULL)",
+                "INSERT INTO \"1\" VALUES ('BTCUSDT', 0.5)",
+                "CREATE TABLE orders (time INTEGER NOT NULL, session_id INTEGER NOT NULL,
+                order_id INTEGER NOT NULL, symbol TEXT NOT NULL, state TEXT NOT NULL,
+                data TEXT NOT NULL)",
+                "INSERT INTO orders VALUES (5, 1, 7, 'BTCUSDT', 'NEW', '{}'),
+                (5, 1, 8, 'BTCUSDT', 'NEW', '{}')",
+                "CREATE TABLE fills (session_id INTEGER NOT NULL, internal_id INTEGER NOT NULL,
+                order_id INTEGER NOT NULL, symbol TEXT NOT NULL, side TEXT NOT NULL,
+                trade_id INTEGER NOT NULL, time INTEGER NOT NULL, price REAL NOT NULL,
+                quantity REAL NOT NULL, maker INTEGER NOT NULL, commission REAL NOT NULL,
+                commission_asset TEXT NOT NULL)",
+                "INSERT INTO fills VALUES (1, 7, 100, 'BTCUSDT', 'BUY', 1, 5, 100, 0.5, 1, 0, 'USDT'),
+                (1, 7, 100, 'BTCUSDT', 'BUY', 1, 5, 100, 0.5, 1, 0, 'USDT')",
+                "CREATE TABLE snapshots (time INTEGER NOT NULL, stream TEXT NOT NULL,
+                data TEXT NOT NULL)",
+                "INSERT INTO snapshots VALUES (5, 'btcusdt@bookTicker', '{}'),
+                (5, 'btcusdt@bookTicker', '{}')",
+            ] {
+                sqlx::query(query).execute(&conn).await.unwrap();
+            }
+            conn.close().await;
+        }
+
+        let storage = SqliteStorage::open(&url).await.unwrap();
+        let (version,): (i64,) = sqlx::query_as("PRAGMA user_version")
+            .fetch_one(&storage.conn)
+            .await
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        assert_eq!(storage.partitions().await.unwrap(), vec!["1"]);
+        assert_eq!(
+            storage.load_positions("1").await.unwrap(),
+            vec![("BTCUSDT".to_string(), 0.5)]
+        );
+
+        // 旧的回报与快照都保留，重复的成交只剩一行
+        let orders: Vec<(i64, i64, i64, i64, String)> = sqlx::query_as(
+            "SELECT order_id, exchange_order_id, update_time, trade_id, tags FROM orders
+            ORDER BY order_id",
+        )
+        .fetch_all(&storage.conn)
+        .await
+        .unwrap();
+        assert_eq!(
+            orders,
+            vec![(7, -1, 5, -1, String::new()), (8, -2, 5, -1, String::new())]
+        );
+        let seqs: Vec<(i64,)> = sqlx::query_as("SELECT seq FROM snapshots ORDER BY seq")
+            .fetch_all(&storage.conn)
+            .await
+            .unwrap();
+        assert_eq!(seqs, vec![(-2,), (-1,)]);
+
+        // 新写入的行按唯一键去重
+        let order = OrderRecord {
+            time: 6,
+            session_id: 1,
+            order_id: 9,
+            exchange_order_id: 101,
+            update_time: 6,
+            trade_id: -1,
+            symbol: "BTCUSDT".into(),
+            state: "NEW".into(),
+            strategy: Some("mm".into()),
+            tags: vec!["alpha".into()],
+            data: "{}".into(),
+        };
+        storage.save_order(&order).await.unwrap();
+        storage.save_order(&order).await.unwrap();
+        let snapshot = MarketSnapshot {
+            time: 6,
+            stream: "btcusdt@bookTicker".into(),
+            seq: 1,
+            data: "{}".into(),
+        };
+        storage
+            .save_snapshots(&[snapshot.clone(), snapshot])
+            .await
+            .unwrap();
+        let audit = storage.audit(0, i64::MAX).await.unwrap();
+        assert_eq!((audit.orders, audit.fills, audit.snapshots), (3, 1, 3));
+        assert!(audit.clean(), "{:?}", audit);
+
+        // 再次打开不重复迁移
+        drop(storage);
+        let storage = SqliteStorage::open(&url).await.unwrap();
+        assert_eq!(storage.audit(0, i64::MAX).await.unwrap().orders, 3);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
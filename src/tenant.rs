@@ -0,0 +1,225 @@
+//! 多租户隔离
+//!
+//! 多个团队共用一个网关进程时，每个租户在配置中列出自己的会话，会话不能跨租户。配置了租户后：
+//!
+//! - 登录必须带上会话所属的 `tenant`，租户配置了 `token` 时还要一致，否则拒绝登录
+//! - 持仓库按租户分表，表名为 `{tenant}/{session_id}`，原来不分租户的表在第一次登录时迁移
+//! - 每个租户的组合限额独立计算，只汇总本租户会话的持仓，见 [`crate::portfolio::Portfolio`]
+//! - 策略端只能查询本租户会话的持仓、参数与组合敞口；账户级别的资金流水与成交历史包含所有租户的
+//!   交易，只有 `account` 为 true 的租户可以查询
+//!
+//! 没有配置租户时行为与之前一致。
+
+use crate::chat::{SError, SLogin};
+use crate::error_code::PERMISSION_DENIED;
+use crate::portfolio::PortfolioConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// ```json
+/// "tenants": [
+///     {"id": "alpha", "sessions": [1, 2], "token": "xxx", "limits": {"gross_notional": 100000}},
+///     {"id": "beta", "sessions": [10], "account": true}
+/// ]
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TenantConfig {
+    pub id: String,
+    pub sessions: Vec<u16>,
+    /// 登录时需要带上的 token，为空时不校验
+    pub token: String,
+    /// 本租户的组合限额，与全局的 `portfolio` 写法相同
+    pub limits: PortfolioConfig,
+    /// 可以查询账户级别的资金流水与成交历史
+    pub account: bool,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    tenants: HashMap<String, TenantConfig>,
+    /// session_id -> 租户
+    sessions: HashMap<u16, String>,
+}
+
+/// 租户配置，可以廉价 clone，在 handler、trade 与持仓库之间共享
+#[derive(Debug, Clone, Default)]
+pub struct Tenants {
+    inner: Arc<Inner>,
+}
+
+impl Tenants {
+    /// 租户 id 为空或重复、会话属于多个租户时返回错误
+    pub fn new(configs: Vec<TenantConfig>) -> anyhow::Result<Self> {
+        let mut inner = Inner::default();
+        for config in configs {
+            if config.id.is_empty() || config.id.contains('/') {
+                anyhow::bail!("invalid tenant id {:?}", config.id);
+            }
+            for session_id in config.sessions.iter() {
+                if let Some(other) = inner.sessions.insert(*session_id, config.id.clone()) {
+                    anyhow::bail!(
+                        "session {} belongs to both {} and {}",
+                        session_id,
+                        other,
+                        config.id
+                    );
+                }
+            }
+            if let Some(other) = inner.tenants.insert(config.id.clone(), config) {
+                anyhow::bail!("duplicate tenant {}", other.id);
+            }
+        }
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.inner.tenants.is_empty()
+    }
+
+    /// 会话所属的租户
+    pub fn tenant_of(&self, session_id: u16) -> Option<&str> {
+        self.inner.sessions.get(&session_id).map(String::as_str)
+    }
+
+    /// 会话所属租户的组合限额
+    pub fn limits(&self, session_id: u16) -> Option<&PortfolioConfig> {
+        let tenant = self.tenant_of(session_id)?;
+        self.inner.tenants.get(tenant).map(|t| &t.limits)
+    }
+
+    /// 两个会话属于同一个租户；没有配置租户时总是为 true
+    pub fn same_tenant(&self, a: u16, b: u16) -> bool {
+        !self.enabled() || (self.tenant_of(a).is_some() && self.tenant_of(a) == self.tenant_of(b))
+    }
+
+    /// 会话是否可以查询账户级别的数据；没有配置租户时总是为 true
+    pub fn account(&self, session_id: u16) -> bool {
+        if !self.enabled() {
+            return true;
+        }
+        self.tenant_of(session_id)
+            .and_then(|t| self.inner.tenants.get(t))
+            .is_some_and(|t| t.account)
+    }
+
+    /// 持仓库中会话的表名
+    pub fn table(&self, session_id: u16) -> String {
+        match self.tenant_of(session_id) {
+            Some(tenant) => format!("{}/{}", tenant, session_id),
+            None => session_id.to_string(),
+        }
+    }
+
+    /// 校验登录的租户与 token
+    pub fn authorize(&self, login: &SLogin) -> Result<(), SError> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let denied = |msg: String| SError {
+            code: PERMISSION_DENIED,
+            msg,
+        };
+        let Some(tenant) = self
+            .tenant_of(login.session_id)
+            .and_then(|t| self.inner.tenants.get(t))
+        else {
+            return Err(denied(format!(
+                "session {} does not belong to any tenant",
+                login.session_id
+            )));
+        };
+        if login.tenant.as_ref() != Some(&tenant.id) {
+            return Err(denied(format!(
+                "session {} does not belong to tenant {}",
+                login.session_id,
+                login.tenant.as_deref().unwrap_or_default()
+            )));
+        }
+        if !tenant.token.is_empty() && login.token.as_ref() != Some(&tenant.token) {
+            return Err(denied(format!("invalid token for tenant {}", tenant.id)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(session_id: u16, tenant: Option<&str>, token: Option<&str>) -> SLogin {
+        SLogin {
+            session_id,
+            name: None,
+            trading: true,
+            cancel_on_disconnect: false,
+            fills: false,
+            format: Default::default(),
+            heartbeat_ms: None,
+            tenant: tenant.map(String::from),
+            token: token.map(String::from),
+            version: None,
+        }
+    }
+
+    #[test]
+    fn test_tenants() {
+        let tenants = Tenants::new(vec![
+            TenantConfig {
+                id: "alpha".into(),
+                sessions: vec![1, 2],
+                token: "secret".into(),
+                ..Default::default()
+            },
+            TenantConfig {
+                id: "beta".into(),
+                sessions: vec![10],
+                account: true,
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        assert!(
+            tenants
+                .authorize(&login(1, Some("alpha"), Some("secret")))
+                .is_ok()
+        );
+        assert!(tenants.authorize(&login(1, Some("alpha"), None)).is_err());
+        assert!(tenants.authorize(&login(1, Some("beta"), None)).is_err());
+        assert!(tenants.authorize(&login(10, Some("beta"), None)).is_ok());
+        assert!(tenants.authorize(&login(3, None, None)).is_err());
+
+        assert!(tenants.same_tenant(1, 2));
+        assert!(!tenants.same_tenant(1, 10));
+        assert!(!tenants.same_tenant(3, 3));
+        assert!(!tenants.account(1));
+        assert!(tenants.account(10));
+        assert_eq!(tenants.table(2), "alpha/2");
+        assert_eq!(tenants.table(3), "3");
+
+        assert!(
+            Tenants::new(vec![
+                TenantConfig {
+                    id: "alpha".into(),
+                    sessions: vec![1],
+                    ..Default::default()
+                },
+                TenantConfig {
+                    id: "beta".into(),
+                    sessions: vec![1],
+                    ..Default::default()
+                },
+            ])
+            .is_err()
+        );
+
+        let disabled = Tenants::default();
+        assert!(disabled.authorize(&login(3, None, None)).is_ok());
+        assert!(disabled.same_tenant(1, 10));
+        assert!(disabled.account(1));
+        assert_eq!(disabled.table(1), "1");
+    }
+}
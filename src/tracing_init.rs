@@ -1,5 +1,12 @@
+use serde::Deserialize;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::layer::Layered;
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, fmt, layer::SubscriberExt, util::SubscriberInitExt,
+};
 
 /// 初始化 tracing 日志系统
 ///
@@ -39,6 +46,375 @@ pub fn init_tracing(app_name: &str, log_dir: &str, level: &str) -> anyhow::Resul
     Ok(guard)
 }
 
+/// ```json
+/// "log": {
+///     "dir": "log",
+///     "format": "json",
+///     "syslog": true,
+///     "shipper": {"protocol": "tcp", "addr": "127.0.0.1:9000"},
+///     "otlp": {"endpoint": "http://127.0.0.1:4318/v1/traces"}
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    /// 日志文件目录
+    pub dir: String,
+    /// 日志文件格式
+    pub format: LogFormat,
+    /// 同时写入本机 syslog（/dev/log），启用 journald 的系统由 journald 接收
+    pub syslog: bool,
+    /// 按行发送 JSON 日志到 Vector / Logstash 的 socket 输入
+    pub shipper: Option<ShipperConfig>,
+    /// 把订单的 `order{cid}` span 导出到 OpenTelemetry，需要开启 `otlp` feature
+    pub otlp: Option<OtlpConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            dir: "log".into(),
+            format: LogFormat::Text,
+            syslog: false,
+            shipper: None,
+            otlp: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShipperConfig {
+    pub protocol: ShipProtocol,
+    pub addr: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipProtocol {
+    Udp,
+    Tcp,
+}
+
+/// OTLP/HTTP 导出配置，Jaeger 与 OpenTelemetry Collector 都接收 OTLP
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OtlpConfig {
+    /// traces 的 HTTP 接收地址
+    pub endpoint: String,
+    /// 上报的 service.name，为空时使用应用名称
+    pub service_name: String,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://127.0.0.1:4318/v1/traces".into(),
+            service_name: String::new(),
+        }
+    }
+}
+
+/// 各后台写日志线程的 guard，必须保持存活直到程序结束，否则日志可能丢失
+pub struct LogGuard {
+    _guards: Vec<WorkerGuard>,
+    #[cfg(feature = "otlp")]
+    tracer: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// 退出时把还没有导出的 span 发送出去
+#[cfg(feature = "otlp")]
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if let Some(tracer) = self.tracer.take() {
+            if let Err(e) = tracer.shutdown() {
+                eprintln!("Shutdown otlp exporter failed: {}", e);
+            }
+        }
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Layered<EnvFilter, Registry>> + Send + Sync>;
+
+/// 按配置初始化 tracing：文件日志可选文本或 JSON，另外可以写入 syslog 或发送到日志收集服务
+pub fn init_tracing_with_config(
+    app_name: &str,
+    config: &LogConfig,
+    level: &str,
+) -> anyhow::Result<LogGuard> {
+    std::fs::create_dir_all(&config.dir)?;
+
+    let file_appender = tracing_appender::rolling::minutely(&config.dir, app_name);
+    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+    let mut guards = vec![guard];
+
+    let file = fmt::layer()
+        .with_writer(non_blocking)
+        .with_target(true)
+        .with_file(true)
+        .with_line_number(true)
+        .with_ansi(false);
+    let mut layers: Vec<BoxedLayer> = vec![match config.format {
+        LogFormat::Text => file.boxed(),
+        LogFormat::Json => file.json().boxed(),
+    }];
+
+    if config.syslog {
+        #[cfg(unix)]
+        match syslog::SyslogWriter::connect(app_name) {
+            Ok(writer) => layers.push(
+                fmt::layer()
+                    .with_writer(writer)
+                    .with_target(true)
+                    .with_ansi(false)
+                    .without_time()
+                    .with_level(false)
+                    .boxed(),
+            ),
+            Err(e) => eprintln!("Connect syslog failed: {}", e),
+        }
+        #[cfg(not(unix))]
+        eprintln!("syslog is only supported on unix");
+    }
+
+    if let Some(shipper) = &config.shipper {
+        let (non_blocking, guard) = tracing_appender::non_blocking(ShipWriter::new(shipper));
+        guards.push(guard);
+        layers.push(
+            fmt::layer()
+                .with_writer(non_blocking)
+                .with_target(true)
+                .with_file(true)
+                .with_line_number(true)
+                .with_ansi(false)
+                .json()
+                .boxed(),
+        );
+    }
+
+    #[cfg(feature = "otlp")]
+    let tracer = match &config.otlp {
+        Some(otlp) => {
+            let (layer, tracer) = otlp::layer(app_name, otlp)?;
+            layers.push(layer);
+            Some(tracer)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "otlp"))]
+    if config.otlp.is_some() {
+        eprintln!("otlp export requires the otlp feature");
+    }
+
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    Registry::default().with(env_filter).with(layers).init();
+
+    tracing::info!("Tracing initialized for {} with {:?}", app_name, config);
+    Ok(LogGuard {
+        _guards: guards,
+        #[cfg(feature = "otlp")]
+        tracer,
+    })
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use super::{BoxedLayer, OtlpConfig};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing::Metadata;
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::filter::filter_fn;
+
+    /// 只导出订单的 span，span 内的日志作为 span event 一起导出
+    pub fn exported(meta: &Metadata<'_>) -> bool {
+        !meta.is_span() || meta.name() == "order"
+    }
+
+    /// 批量导出在 SDK 的后台线程中进行，不占用 tokio 工作线程
+    pub fn layer(
+        app_name: &str,
+        config: &OtlpConfig,
+    ) -> anyhow::Result<(BoxedLayer, SdkTracerProvider)> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(&config.endpoint)
+            .build()?;
+        let service_name = match config.service_name.is_empty() {
+            true => app_name.to_string(),
+            false => config.service_name.clone(),
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(service_name).build())
+            .build();
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("cryptoflow"))
+            .with_filter(filter_fn(exported))
+            .boxed();
+        Ok((layer, provider))
+    }
+}
+
+/// 断线后重连的最小间隔，避免日志服务不可用时每条日志都尝试连接
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 每条日志一行 JSON，UDP 每条一个报文，TCP 断线后自动重连；发送失败的日志直接丢弃
+struct ShipWriter {
+    protocol: ShipProtocol,
+    addr: String,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+    last_connect: Option<Instant>,
+}
+
+impl ShipWriter {
+    fn new(config: &ShipperConfig) -> Self {
+        Self {
+            protocol: config.protocol,
+            addr: config.addr.clone(),
+            udp: None,
+            tcp: None,
+            last_connect: None,
+        }
+    }
+
+    fn send(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self.protocol {
+            ShipProtocol::Udp => {
+                if self.udp.is_none() {
+                    self.udp = Some(UdpSocket::bind("0.0.0.0:0")?);
+                }
+                if let Some(socket) = &self.udp {
+                    socket.send_to(buf, &self.addr)?;
+                }
+            }
+            ShipProtocol::Tcp => {
+                if self.tcp.is_none() {
+                    if self
+                        .last_connect
+                        .is_some_and(|t| t.elapsed() < RECONNECT_INTERVAL)
+                    {
+                        return Ok(());
+                    }
+                    self.last_connect = Some(Instant::now());
+                    self.tcp = Some(TcpStream::connect(&self.addr)?);
+                }
+                if let Some(stream) = &mut self.tcp {
+                    if let Err(e) = stream.write_all(buf) {
+                        self.tcp = None;
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for ShipWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // 日志服务不可用不影响本地日志
+        let _ = self.send(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.tcp {
+            Some(stream) => stream.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod syslog {
+    use std::io::Write;
+    use std::os::unix::net::UnixDatagram;
+    use std::sync::Arc;
+    use tracing::{Level, Metadata};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// 按 RFC 3164 格式写入本机 syslog，每条日志一个报文
+    #[derive(Clone)]
+    pub struct SyslogWriter {
+        socket: Arc<UnixDatagram>,
+        tag: String,
+    }
+
+    impl SyslogWriter {
+        pub fn connect(tag: &str) -> std::io::Result<Self> {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect("/dev/log")?;
+            Ok(Self {
+                socket: Arc::new(socket),
+                tag: tag.into(),
+            })
+        }
+    }
+
+    /// user facility 下的优先级
+    pub fn priority(level: &Level) -> u8 {
+        let severity = match *level {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            _ => 7,
+        };
+        8 + severity
+    }
+
+    pub struct SyslogLine {
+        writer: SyslogWriter,
+        priority: u8,
+    }
+
+    impl Write for SyslogLine {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let line = String::from_utf8_lossy(buf);
+            let msg = format!(
+                "<{}>{}: {}",
+                self.priority,
+                self.writer.tag,
+                line.trim_end()
+            );
+            let _ = self.writer.socket.send(msg.as_bytes());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for SyslogWriter {
+        type Writer = SyslogLine;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            SyslogLine {
+                writer: self.clone(),
+                priority: priority(&Level::INFO),
+            }
+        }
+
+        fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+            SyslogLine {
+                writer: self.clone(),
+                priority: priority(meta.level()),
+            }
+        }
+    }
+}
+
 /// 初始化带 span 追踪的 tracing 系统
 /// 适用于需要分布式追踪的场景
 /// FIXME: 这个函数暂时没有使用
@@ -96,3 +472,84 @@ pub fn init_default_if_none() {
             .try_init();
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ship_writer_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let mut writer = ShipWriter::new(&ShipperConfig {
+            protocol: ShipProtocol::Udp,
+            addr: server.local_addr().unwrap().to_string(),
+        });
+        writer.write_all(b"{\"message\":\"hello\"}\n").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"{\"message\":\"hello\"}\n");
+
+        // 收集服务不可用时丢弃日志，不返回错误
+        let mut writer = ShipWriter::new(&ShipperConfig {
+            protocol: ShipProtocol::Tcp,
+            addr: "127.0.0.1:1".into(),
+        });
+        assert!(writer.write_all(b"lost\n").is_ok());
+    }
+
+    #[test]
+    fn test_otlp_config() {
+        let config: LogConfig = serde_json::from_str(r#"{"otlp": {}}"#).unwrap();
+        let otlp = config.otlp.unwrap();
+        assert_eq!(otlp.endpoint, "http://127.0.0.1:4318/v1/traces");
+        assert!(otlp.service_name.is_empty());
+        assert!(LogConfig::default().otlp.is_none());
+    }
+
+    #[cfg(feature = "otlp")]
+    #[test]
+    fn test_otlp_export() {
+        use std::io::Read;
+        use std::net::TcpListener;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = OtlpConfig {
+            endpoint: format!("http://{}/v1/traces", server.local_addr().unwrap()),
+            service_name: "test".into(),
+        };
+        let (layer, provider) = otlp::layer("app", &config).unwrap();
+        let subscriber = Registry::default().with(EnvFilter::new("info")).with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("order", cid = 7).entered();
+            tracing::info!("sent");
+            // 其它 span 不导出
+            let _other = tracing::info_span!("market").entered();
+        });
+        let accept = std::thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+        provider.shutdown().unwrap();
+        let request = accept.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces"));
+        assert!(request.contains("application/x-protobuf"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syslog_priority() {
+        assert_eq!(syslog::priority(&tracing::Level::ERROR), 11);
+        assert_eq!(syslog::priority(&tracing::Level::INFO), 14);
+        assert_eq!(syslog::priority(&tracing::Level::TRACE), 15);
+    }
+}
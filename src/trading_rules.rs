@@ -3,6 +3,7 @@
 //! 这个模块定义了一个通用的交易规则 trait，用于抽象不同交易所的交易规则差异。
 //! 支持币安、OKX 等多个交易所的统一接口。
 
+use crate::chat::Side;
 use std::fmt::Debug;
 
 /// 统一的交易规则接口
@@ -41,6 +42,41 @@ pub trait TradingRules: Debug + Clone {
     /// 返回订单的最小名义价值（价格 × 数量）
     fn min_notional(&self) -> f64;
 
+    /// 获取市价单的最小数量
+    /// 没有单独的市价单规则时与限价单相同
+    fn market_min_quantity(&self) -> f64 {
+        self.min_quantity()
+    }
+
+    /// 获取市价单的最大数量
+    fn market_max_quantity(&self) -> f64 {
+        self.max_quantity()
+    }
+
+    /// 获取市价单的数量步长
+    /// 0 表示不限制步长
+    fn market_lot_size(&self) -> f64 {
+        self.lot_size()
+    }
+
+    /// 获取价格相对参考价的范围
+    /// 返回 (下限倍数, 上限倍数)，买卖两边可以不同，None 表示不限制
+    fn percent_price(&self, _side: &Side) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// 获取计算参考价的分钟数
+    /// 参考价为这段时间内的平均价格，0 表示使用最新价格
+    fn avg_price_mins(&self) -> u32 {
+        0
+    }
+
+    /// 获取基础资产的最大持仓
+    /// None 表示不限制
+    fn max_position(&self) -> Option<f64> {
+        None
+    }
+
     /// 验证价格是否有效
     /// 检查给定价格是否符合交易规则
     fn is_valid_price(&self, price: f64) -> bool {
@@ -55,7 +91,7 @@ pub trait TradingRules: Debug + Clone {
         if tick_size > 0.0 {
             let remainder = (price / tick_size) % 1.0;
             // 允许小的浮点误差
-            remainder < 1e-8 || remainder > (1.0 - 1e-8)
+            !(1e-8..=(1.0 - 1e-8)).contains(&remainder)
         } else {
             true
         }
@@ -75,7 +111,7 @@ pub trait TradingRules: Debug + Clone {
         if lot_size > 0.0 {
             let remainder = (quantity / lot_size) % 1.0;
             // 允许小的浮点误差
-            remainder < 1e-8 || remainder > (1.0 - 1e-8)
+            !(1e-8..=(1.0 - 1e-8)).contains(&remainder)
         } else {
             true
         }
@@ -94,6 +130,46 @@ pub trait TradingRules: Debug + Clone {
         notional >= min_notional
     }
 
+    /// 验证市价单的数量是否有效
+    /// 检查给定数量是否符合市价单的交易规则
+    fn is_valid_market_quantity(&self, quantity: f64) -> bool {
+        let lot_size = self.market_lot_size();
+
+        if quantity < self.market_min_quantity() || quantity > self.market_max_quantity() {
+            return false;
+        }
+
+        if lot_size > 0.0 {
+            let remainder = (quantity / lot_size) % 1.0;
+            // 允许小的浮点误差
+            !(1e-8..=(1.0 - 1e-8)).contains(&remainder)
+        } else {
+            true
+        }
+    }
+
+    /// 验证价格是否在参考价的范围内
+    /// 没有价格范围限制时总是有效
+    fn is_within_percent_price(&self, side: &Side, price: f64, reference: f64) -> bool {
+        match self.percent_price(side) {
+            Some((down, up)) => {
+                // 允许小的浮点误差
+                price >= reference * down * (1.0 - 1e-9)
+                    && (up <= 0.0 || price <= reference * up * (1.0 + 1e-9))
+            }
+            None => true,
+        }
+    }
+
+    /// 验证买入后的持仓是否超过上限
+    /// `position` 为当前的持仓，卖出总是有效
+    fn is_within_max_position(&self, side: &Side, quantity: f64, position: f64) -> bool {
+        match (side, self.max_position()) {
+            (Side::BUY, Some(max)) => position + quantity <= max * (1.0 + 1e-9),
+            _ => true,
+        }
+    }
+
     /// 调整价格到有效值
     /// 将给定价格调整为符合交易规则的最接近值
     fn adjust_price(&self, price: f64) -> f64 {
@@ -120,7 +196,8 @@ pub trait TradingRules: Debug + Clone {
         let clamped_qty = quantity.max(min_qty).min(max_qty);
 
         if lot_size > 0.0 {
-            (clamped_qty / lot_size).floor() * lot_size
+            // 允许小的浮点误差，16.104 / 0.001 不应向下取整到 16103
+            (clamped_qty / lot_size + 1e-8).floor() * lot_size
         } else {
             clamped_qty
         }
@@ -273,6 +350,80 @@ mod tests {
         assert_eq!(product.adjust_price(150000.0), 100000.0);
     }
 
+    #[test]
+    fn test_extended_filters() {
+        #[derive(Debug, Clone)]
+        struct Banded(TestProduct);
+
+        impl TradingRules for Banded {
+            fn symbol(&self) -> &String {
+                self.0.symbol()
+            }
+            fn min_price(&self) -> f64 {
+                self.0.min_price()
+            }
+            fn max_price(&self) -> f64 {
+                self.0.max_price()
+            }
+            fn tick_size(&self) -> f64 {
+                self.0.tick_size()
+            }
+            fn min_quantity(&self) -> f64 {
+                self.0.min_quantity()
+            }
+            fn max_quantity(&self) -> f64 {
+                self.0.max_quantity()
+            }
+            fn lot_size(&self) -> f64 {
+                self.0.lot_size()
+            }
+            fn min_notional(&self) -> f64 {
+                self.0.min_notional()
+            }
+            fn market_max_quantity(&self) -> f64 {
+                100.0
+            }
+            fn market_lot_size(&self) -> f64 {
+                0.0
+            }
+            fn percent_price(&self, side: &Side) -> Option<(f64, f64)> {
+                match side {
+                    Side::BUY => Some((0.2, 1.2)),
+                    Side::SELL => Some((0.8, 5.0)),
+                }
+            }
+            fn max_position(&self) -> Option<f64> {
+                Some(10.0)
+            }
+        }
+
+        let product = TestProduct {
+            symbol: "BTCUSDT".to_string(),
+            min_price: 0.01,
+            max_price: 100000.0,
+            tick_size: 0.01,
+            min_quantity: 0.001,
+            max_quantity: 1000.0,
+            lot_size: 0.001,
+            min_notional: 10.0,
+        };
+        // 默认与限价单相同，不限制价格范围与持仓
+        assert!(!product.is_valid_market_quantity(0.0015));
+        assert!(product.is_within_percent_price(&Side::BUY, 1.0, 50000.0));
+        assert!(product.is_within_max_position(&Side::BUY, 1e9, 0.0));
+
+        let banded = Banded(product);
+        assert!(banded.is_valid_market_quantity(0.0015));
+        assert!(!banded.is_valid_market_quantity(150.0));
+        assert!(banded.is_within_percent_price(&Side::BUY, 59000.0, 50000.0));
+        assert!(!banded.is_within_percent_price(&Side::BUY, 61000.0, 50000.0));
+        assert!(banded.is_within_percent_price(&Side::SELL, 61000.0, 50000.0));
+        assert!(!banded.is_within_percent_price(&Side::SELL, 39000.0, 50000.0));
+        assert!(banded.is_within_max_position(&Side::BUY, 2.0, 8.0));
+        assert!(!banded.is_within_max_position(&Side::BUY, 2.5, 8.0));
+        assert!(banded.is_within_max_position(&Side::SELL, 20.0, 8.0));
+    }
+
     #[test]
     fn test_quantity_adjustment() {
         let product = TestProduct {
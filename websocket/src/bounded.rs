@@ -0,0 +1,271 @@
+//! 有界的内部通道
+//!
+//! 策略端连接与 handler 之间的消息通道容量由配置给出。行情队列满时按 `overflow` 处理：丢弃这条消息，
+//! 或者断开消费过慢的连接；订单、成交与回复从不丢弃，队列满时总是断开连接。每条通道记录当前深度、历史最大深度与丢弃数量，深度超过容量的 80% 时告警。
+
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::mpsc::error::{SendError, TryRecvError, TrySendError};
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+use tracing::{error, warn};
+
+/// ```json
+/// "channel": {
+///     "capacity": 10000,
+///     "overflow": "drop"
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ChannelConfig {
+    /// 每条通道最多缓存的消息数
+    pub capacity: usize,
+    /// 行情消息的溢出策略，见 [`BoundedSender::send_lossy`]
+    pub overflow: Overflow,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10000,
+            overflow: Overflow::Drop,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// 丢弃新消息
+    #[default]
+    Drop,
+    /// 关闭通道，接收端断开连接
+    Disconnect,
+}
+
+/// 通道的当前状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelGauge {
+    pub name: String,
+    pub capacity: usize,
+    pub depth: usize,
+    pub max_depth: usize,
+    pub dropped: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    name: String,
+    capacity: usize,
+    overflow: Overflow,
+    max_depth: AtomicUsize,
+    dropped: AtomicU64,
+    /// 深度超过高水位后只告警一次，回落到一半以下再重新告警
+    saturated: AtomicBool,
+    overflowed: AtomicBool,
+}
+
+impl Shared {
+    fn on_depth(&self, depth: usize) {
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+        let high = self.capacity * 4 / 5;
+        if depth >= high {
+            if !self.saturated.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "Channel {} depth {} reached {}% of capacity {}",
+                    self.name,
+                    depth,
+                    depth * 100 / self.capacity,
+                    self.capacity
+                );
+            }
+        } else if depth < self.capacity / 2 {
+            self.saturated.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn gauge(&self, depth: usize) -> ChannelGauge {
+        ChannelGauge {
+            name: self.name.clone(),
+            capacity: self.capacity,
+            depth,
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundedSender<T> {
+    inner: Sender<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> BoundedSender<T> {
+    /// 非阻塞发送，用于订单、成交与回复等不能丢弃的消息：队列满时不论 `overflow` 都放弃这条通道
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.try_send(msg, Overflow::Disconnect)
+    }
+
+    /// 非阻塞发送，用于行情等可以丢弃的消息：队列满时按 `overflow` 处理，丢弃消息时返回 Ok
+    pub fn send_lossy(&self, msg: T) -> Result<(), SendError<T>> {
+        self.try_send(msg, self.shared.overflow)
+    }
+
+    fn try_send(&self, msg: T, overflow: Overflow) -> Result<(), SendError<T>> {
+        if self.shared.overflowed.load(Ordering::Relaxed) {
+            return Err(SendError(msg));
+        }
+        match self.inner.try_send(msg) {
+            Ok(()) => {
+                self.shared.on_depth(self.depth());
+                Ok(())
+            }
+            Err(TrySendError::Full(msg)) => match overflow {
+                Overflow::Drop => {
+                    let dropped = self.shared.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                    if dropped == 1 || dropped % 1000 == 0 {
+                        warn!(
+                            "Channel {} is full, {} messages dropped",
+                            self.shared.name, dropped
+                        );
+                    }
+                    Ok(())
+                }
+                Overflow::Disconnect => {
+                    error!("Channel {} is full, disconnect", self.shared.name);
+                    self.shared.overflowed.store(true, Ordering::Relaxed);
+                    Err(SendError(msg))
+                }
+            },
+            Err(TrySendError::Closed(msg)) => Err(SendError(msg)),
+        }
+    }
+
+    /// 队列满时等待，用于可以背压的一侧
+    pub async fn send_wait(&self, msg: T) -> Result<(), SendError<T>> {
+        self.inner.send(msg).await?;
+        self.shared.on_depth(self.depth());
+        Ok(())
+    }
+
+    pub fn depth(&self) -> usize {
+        self.inner.max_capacity() - self.inner.capacity()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    pub fn gauge(&self) -> ChannelGauge {
+        self.shared.gauge(self.depth())
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundedReceiver<T> {
+    inner: Receiver<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> BoundedReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        self.inner.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// 发送端因队列满放弃了这条通道，接收端应断开连接
+    pub fn overflowed(&self) -> bool {
+        self.shared.overflowed.load(Ordering::Relaxed)
+    }
+
+    pub fn gauge(&self) -> ChannelGauge {
+        self.shared.gauge(self.inner.len())
+    }
+}
+
+pub fn bounded_channel<T>(
+    name: impl Into<String>,
+    config: &ChannelConfig,
+) -> (BoundedSender<T>, BoundedReceiver<T>) {
+    let capacity = config.capacity.max(1);
+    let (tx, rx) = channel(capacity);
+    let shared = Arc::new(Shared {
+        name: name.into(),
+        capacity,
+        overflow: config.overflow,
+        max_depth: AtomicUsize::new(0),
+        dropped: AtomicU64::new(0),
+        saturated: AtomicBool::new(false),
+        overflowed: AtomicBool::new(false),
+    });
+    (
+        BoundedSender {
+            inner: tx,
+            shared: shared.clone(),
+        },
+        BoundedReceiver { inner: rx, shared },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_channel() {
+        let config = ChannelConfig {
+            capacity: 2,
+            overflow: Overflow::Drop,
+        };
+        let (tx, mut rx) = bounded_channel("drop", &config);
+        tx.send_lossy(1).unwrap();
+        tx.send_lossy(2).unwrap();
+        // 队列满时丢弃新消息
+        tx.send_lossy(3).unwrap();
+        let gauge = tx.gauge();
+        assert_eq!((gauge.depth, gauge.max_depth, gauge.dropped), (2, 2, 1));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.gauge().depth, 1);
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert!(rx.try_recv().is_err());
+
+        // 不能丢弃的消息在 drop 策略下也断开连接
+        tx.send(4).unwrap();
+        tx.send_lossy(5).unwrap();
+        assert!(tx.send(6).is_err());
+        assert!(rx.overflowed());
+        assert_eq!(tx.gauge().dropped, 1);
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert!(tx.send_lossy(7).is_err());
+
+        let config = ChannelConfig {
+            capacity: 1,
+            overflow: Overflow::Disconnect,
+        };
+        let (tx, mut rx) = bounded_channel("disconnect", &config);
+        tx.send_lossy(1).unwrap();
+        assert!(tx.send_lossy(2).is_err());
+        assert!(rx.overflowed());
+        // 放弃之后即使有空位也不再发送
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(tx.send(3).is_err());
+    }
+}
@@ -21,6 +21,12 @@ pub struct Args {
     pub params: HashMap<String, String>,
 }
 
+impl Default for Args {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Args {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,145 @@
+//! 故障注入，只在开启 `chaos` feature 时编译，供测试使用
+//!
+//! 测试按客户端名称（如 `market`）取得 [`Chaos`] 并排入故障，故障依次作用于该名称的客户端之后收到的
+//! 文本帧：丢弃、延迟、截断成无法解析的 JSON，或断开连接。网关不需要为测试改动代码，重连、补订阅等
+//! 恢复逻辑走的是与生产相同的路径。
+
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message, Utf8Bytes};
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// 丢弃这一帧
+    Drop,
+    /// 延迟后再交给客户端处理
+    Delay(Duration),
+    /// 截断成无法解析的 JSON
+    Corrupt,
+    /// 丢弃这一帧并断开连接
+    Disconnect,
+}
+
+/// 一帧经过故障注入后的处理方式
+pub(crate) enum Intercept {
+    Pass(Result<Message, WsError>),
+    Drop,
+    Disconnect,
+}
+
+static REGISTRY: Lazy<Mutex<HashMap<String, Chaos>>> = Lazy::new(Mutex::default);
+
+/// 一个客户端名称上排队的故障，克隆后共享同一个队列
+#[derive(Debug, Clone, Default)]
+pub struct Chaos {
+    faults: Arc<Mutex<VecDeque<Fault>>>,
+}
+
+impl Chaos {
+    /// 客户端名称对应的故障队列，同名的客户端（包括重连后新建的客户端）共用
+    pub fn get(client_name: &str) -> Self {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|p| p.into_inner());
+        registry.entry(client_name.to_string()).or_default().clone()
+    }
+
+    /// 排入一个故障，作用于之后收到的第一个还没有故障的文本帧
+    pub fn inject(&self, fault: Fault) {
+        self.lock().push_back(fault);
+    }
+
+    /// 还没有生效的故障数
+    pub fn pending(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Fault>> {
+        self.faults.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Ping、Pong 等控制帧与接收错误原样通过
+    pub(crate) async fn intercept(&self, res: Result<Message, WsError>) -> Intercept {
+        let text = match &res {
+            Ok(Message::Text(text)) => text,
+            _ => return Intercept::Pass(res),
+        };
+        let Some(fault) = self.lock().pop_front() else {
+            return Intercept::Pass(res);
+        };
+        warn!("Inject {:?} into {}", fault, text);
+        match fault {
+            Fault::Drop => Intercept::Drop,
+            Fault::Delay(delay) => {
+                tokio::time::sleep(delay).await;
+                Intercept::Pass(res)
+            }
+            Fault::Corrupt => Intercept::Pass(Ok(Message::Text(Utf8Bytes::from(corrupt(text))))),
+            Fault::Disconnect => Intercept::Disconnect,
+        }
+    }
+}
+
+/// 保留前一半，对象与数组缺少结尾后不再是合法的 JSON
+fn corrupt(text: &str) -> String {
+    let mut end = text.len() / 2;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\u{fffd}", &text[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Message {
+        Message::Text(Utf8Bytes::from(s))
+    }
+
+    #[tokio::test]
+    async fn test_intercept() {
+        let chaos = Chaos::get("test_intercept");
+        chaos.inject(Fault::Drop);
+        chaos.inject(Fault::Corrupt);
+        chaos.inject(Fault::Delay(Duration::from_millis(10)));
+        chaos.inject(Fault::Disconnect);
+        // 同名的客户端共用队列
+        assert_eq!(Chaos::get("test_intercept").pending(), 4);
+        assert_eq!(Chaos::get("other").pending(), 0);
+
+        // 控制帧不消耗故障
+        let ping = chaos.intercept(Ok(Message::Ping(Default::default()))).await;
+        assert!(matches!(ping, Intercept::Pass(Ok(Message::Ping(_)))));
+        assert!(matches!(
+            chaos.intercept(Ok(text("{}"))).await,
+            Intercept::Drop
+        ));
+        let Intercept::Pass(Ok(Message::Text(corrupted))) =
+            chaos.intercept(Ok(text(r#"{"id":1,"result":null}"#))).await
+        else {
+            panic!("corrupted frame is not passed through");
+        };
+        assert!(serde_json::from_str::<serde_json::Value>(&corrupted).is_err());
+
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            chaos.intercept(Ok(text("{}"))).await,
+            Intercept::Pass(Ok(_))
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert!(matches!(
+            chaos.intercept(Ok(text("{}"))).await,
+            Intercept::Disconnect
+        ));
+        assert!(matches!(
+            chaos.intercept(Ok(text("{}"))).await,
+            Intercept::Pass(Ok(_))
+        ));
+    }
+}
@@ -115,6 +115,11 @@ where
         self.url = url.into();
     }
 
+    /// 当前的WebSocket URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
     /// 连接到WebSocket服务器
     pub async fn connect(&mut self) -> Result<Receiver<serde_json::Value>, Error> {
         let url_string = self.url.clone();
@@ -148,6 +153,7 @@ where
         // 消息接收+心跳任务
         let ping_text = self.protocol.ping_text();
         let rx_task = tokio::spawn(Self::run_ws_with_heartbeat(
+            self.client_name.clone(),
             read,
             tx_out.clone(),
             tx_in.clone(),
@@ -191,9 +197,12 @@ where
             .map_err(|_| Error::WebSocketError("获取订阅锁失败".to_string()))?
             .clone();
 
+        for request in self.protocol.on_connect() {
+            self.send_subscription(request).await?;
+        }
         for stored in subscriptions_clone.values() {
             // 按协议重放订阅请求
-            self.send_raw_json(stored.req_sub.clone()).await?;
+            self.send_subscription(stored.req_sub.clone()).await?;
         }
 
         // 直接返回 self.rx.take()，不再转发
@@ -298,10 +307,10 @@ where
         } else {
             return Err(Error::WebSocketError("获取订阅锁失败".to_string()));
         }
-        self.send_raw_json(stored.req_sub).await
+        self.send_subscription(stored.req_sub).await
     }
 
-    /// 使用订阅对象进行订阅
+    // 使用订阅对象进行订阅
     // 保留占位（OKX 重放时已通过 StoredSub 中的 req_sub 实现）
 
     /// 取消订阅
@@ -313,16 +322,17 @@ where
             return Err(Error::WebSocketError("获取订阅锁失败".to_string()));
         };
         if let Some(stored) = stored {
-            self.send_raw_json(stored.req_unsub).await
+            self.send_subscription(stored.req_unsub).await
         } else {
             // 未找到，按协议临时构造一次订阅，并取其中的退订报文
             let adhoc = self.protocol.build_subscribe(channel, &args);
-            self.send_raw_json(adhoc.req_unsub).await
+            self.send_subscription(adhoc.req_unsub).await
         }
     }
 
     /// 封装心跳与消息接收的 select! 逻辑
     async fn run_ws_with_heartbeat(
+        client_name: String,
         mut read: impl Stream<Item = Result<Message, WsError>> + Unpin,
         tx_out: Sender<serde_json::Value>,
         tx_in: Sender<Message>,
@@ -332,13 +342,21 @@ where
     ) {
         let mut waiting_pong = false;
         let mut ping_sent_time: Option<Instant> = None;
+        #[cfg(feature = "chaos")]
+        let chaos = crate::chaos::Chaos::get(&client_name);
         loop {
             tokio::select! {
                 msg_result = read.next() => {
                     if let Some(res) = msg_result {
-                        if let Err(_) = Self::handle_ws_message(
+                        #[cfg(feature = "chaos")]
+                        let res = match chaos.intercept(res).await {
+                            crate::chaos::Intercept::Pass(res) => res,
+                            crate::chaos::Intercept::Drop => continue,
+                            crate::chaos::Intercept::Disconnect => break,
+                        };
+                        if Self::handle_ws_message(
                             res, &tx_out, &tx_in, &last_ping_time, &mut waiting_pong, &mut ping_sent_time
-                        ).await {
+                        ).await.is_err() {
                             break;
                         }
                     } else {
@@ -359,7 +377,7 @@ where
                         waiting_pong = true;
                         ping_sent_time = Some(Instant::now());
                     } else {
-                        error!("心跳超时，未收到pong，准备重连...");
+                        error!("{} 心跳超时，未收到pong，准备重连...", client_name);
                         break;
                     }
                 }
@@ -417,7 +435,7 @@ where
     /// 发送原始 JSON 消息
     async fn send_raw_json(&self, message: serde_json::Value) -> Result<(), Error> {
         if let Some(tx) = &self.tx {
-            let message_str = serde_json::to_string(&message).map_err(|e| Error::JsonError(e))?;
+            let message_str = serde_json::to_string(&message).map_err(Error::JsonError)?;
             debug!("发送WebSocket消息: {}", message_str);
             tx.send(Message::Text(Utf8Bytes::from(message_str)))
                 .await
@@ -428,6 +446,15 @@ where
         }
     }
 
+    /// 发送订阅、退订请求，有凭证时按协议补上鉴权字段
+    async fn send_subscription(&self, request: serde_json::Value) -> Result<(), Error> {
+        let request = match &self.credentials {
+            Some(credentials) => self.protocol.authorize(request, credentials),
+            None => request,
+        };
+        self.send_raw_json(request).await
+    }
+
     /// 启动重连任务
     fn start_reconnect_task(&mut self) {
         if self.reconnect_task.is_some() {
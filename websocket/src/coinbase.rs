@@ -0,0 +1,215 @@
+//! Coinbase Advanced Trade 的行情推送
+//!
+//! 推送是 `{"channel": "ticker", "timestamp": ..., "sequence_num": 1, "events": [...]}`，按 `channel`
+//! 选择事件的类型；level2 的推送频道名为 `l2_data`。数值都是字符串。
+//! see: https://docs.cdp.coinbase.com/advanced-trade/docs/ws-channels
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+pub enum CoinbaseMessage {
+    Ticker(Envelope<TickerEvent>),
+    #[serde(rename = "l2_data")]
+    Level2(Envelope<Level2Event>),
+    Heartbeats(Envelope<HeartbeatEvent>),
+    MarketTrades(Envelope<Value>),
+    Candles(Envelope<Value>),
+    User(Envelope<Value>),
+    /// 订阅、退订的应答，事件为当前订阅的全部频道
+    Subscriptions(Envelope<Value>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    #[serde(default)]
+    pub client_id: String,
+    pub timestamp: String,
+    /// 每个连接从 0 开始递增，不连续说明丢了消息
+    pub sequence_num: u64,
+    pub events: Vec<T>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickerEvent {
+    /// `snapshot` 或 `update`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub tickers: Vec<Ticker>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ticker {
+    pub product_id: String,
+    pub price: String,
+    #[serde(default)]
+    pub volume_24_h: String,
+    #[serde(default)]
+    pub low_24_h: String,
+    #[serde(default)]
+    pub high_24_h: String,
+    #[serde(default)]
+    pub price_percent_chg_24_h: String,
+    #[serde(default)]
+    pub best_bid: String,
+    #[serde(default)]
+    pub best_bid_quantity: String,
+    #[serde(default)]
+    pub best_ask: String,
+    #[serde(default)]
+    pub best_ask_quantity: String,
+}
+
+/// (价格, 数量)
+pub type Level = (f64, f64);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Level2Event {
+    /// `snapshot` 为全量盘口，`update` 为增量
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub product_id: String,
+    pub updates: Vec<Level2Update>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Level2Update {
+    /// `bid` 或 `offer`
+    pub side: String,
+    pub event_time: String,
+    pub price_level: String,
+    /// 该价位的新数量，0 表示删除该价位
+    pub new_quantity: String,
+}
+
+impl Level2Event {
+    /// 按买卖方向拆分价位，数值无效的价位跳过
+    pub fn levels(&self) -> (Vec<Level>, Vec<Level>) {
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+        for update in &self.updates {
+            let (Ok(price), Ok(quantity)) =
+                (update.price_level.parse(), update.new_quantity.parse())
+            else {
+                continue;
+            };
+            match update.side.as_str() {
+                "bid" => bids.push((price, quantity)),
+                _ => asks.push((price, quantity)),
+            }
+        }
+        (bids, asks)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatEvent {
+    pub current_time: String,
+    pub heartbeat_counter: u64,
+}
+
+/// 解码推送，错误消息 `{"type": "error", ...}` 与未知的频道返回 None
+pub fn decode(value: Value) -> serde_json::Result<Option<CoinbaseMessage>> {
+    let known = value
+        .get("channel")
+        .and_then(Value::as_str)
+        .is_some_and(|channel| {
+            matches!(
+                channel,
+                "ticker"
+                    | "l2_data"
+                    | "heartbeats"
+                    | "market_trades"
+                    | "candles"
+                    | "user"
+                    | "subscriptions"
+            )
+        });
+    if !known {
+        return Ok(None);
+    }
+    serde_json::from_value(value).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode() {
+        let ticker = json!({
+            "channel": "ticker",
+            "client_id": "",
+            "timestamp": "2023-02-09T20:30:37.167359596Z",
+            "sequence_num": 0,
+            "events": [{
+                "type": "snapshot",
+                "tickers": [{
+                    "type": "ticker",
+                    "product_id": "BTC-USD",
+                    "price": "21932.98",
+                    "volume_24_h": "16038.28770938",
+                    "low_24_h": "21835.29",
+                    "high_24_h": "23011.18",
+                    "low_52_w": "15460",
+                    "high_52_w": "48240",
+                    "price_percent_chg_24_h": "-4.15775596190603",
+                    "best_bid": "21931.98",
+                    "best_bid_quantity": "8000.21",
+                    "best_ask": "21933.98",
+                    "best_ask_quantity": "8038.07770938"
+                }]
+            }]
+        });
+        match decode(ticker).unwrap() {
+            Some(CoinbaseMessage::Ticker(envelope)) => {
+                let ticker = &envelope.events[0].tickers[0];
+                assert_eq!(ticker.product_id, "BTC-USD");
+                assert_eq!(ticker.best_ask, "21933.98");
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        let level2 = json!({
+            "channel": "l2_data",
+            "timestamp": "2023-02-09T20:32:50.714964855Z",
+            "sequence_num": 4,
+            "events": [{
+                "type": "update",
+                "product_id": "BTC-USD",
+                "updates": [
+                    {"side": "bid", "event_time": "1970-01-01T00:00:00Z", "price_level": "21921.73", "new_quantity": "0.06317902"},
+                    {"side": "offer", "event_time": "1970-01-01T00:00:00Z", "price_level": "21921.74", "new_quantity": "0"}
+                ]
+            }]
+        });
+        match decode(level2).unwrap() {
+            Some(CoinbaseMessage::Level2(envelope)) => {
+                assert_eq!(envelope.sequence_num, 4);
+                let (bids, asks) = envelope.events[0].levels();
+                assert_eq!(bids, vec![(21921.73, 0.06317902)]);
+                assert_eq!(asks, vec![(21921.74, 0.0)]);
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        let heartbeat = json!({
+            "channel": "heartbeats",
+            "timestamp": "2023-06-23T20:31:26.122969572Z",
+            "sequence_num": 0,
+            "events": [{"current_time": "2023-06-23 20:31:56.121961769 +0000 UTC m=+91717.525857105", "heartbeat_counter": 3049}]
+        });
+        assert!(matches!(
+            decode(heartbeat).unwrap(),
+            Some(CoinbaseMessage::Heartbeats(_))
+        ));
+
+        let error = json!({"type": "error", "message": "authentication failure"});
+        assert!(decode(error).unwrap().is_none());
+        let invalid =
+            json!({"channel": "ticker", "timestamp": "", "sequence_num": 1, "events": [{}]});
+        assert!(decode(invalid).is_err());
+    }
+}
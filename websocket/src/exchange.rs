@@ -1,7 +1,9 @@
 use crate::auth::{Credentials, OkxWsAuth, OkxWsLoginRequest};
 use crate::channel::{Args, ChannelType};
 use crate::client::StoredSub;
-use crate::request::{BinanceWsRequest, OkxSubscription, OkxWsOperation, OkxWsRequest};
+use crate::request::{
+    BinanceWsRequest, CoinbaseWsRequest, OkxSubscription, OkxWsOperation, OkxWsRequest,
+};
 
 /// 协议策略：定义各交易所 WS 消息格式
 pub trait WsProtocol: Send + Sync {
@@ -18,6 +20,16 @@ pub trait WsProtocol: Send + Sync {
 
     /// 仅用于计算 HashMap 的 key，便于外部直接退订
     fn make_key(&self, channel: &ChannelType, args: &Args) -> String;
+
+    /// 每次连接（含重连）后、重放订阅前发送的请求，如 Coinbase 的心跳订阅
+    fn on_connect(&self) -> Vec<serde_json::Value> {
+        Vec::new()
+    }
+
+    /// 有凭证时，发送订阅、退订请求前补上鉴权字段；StoredSub 中保存的是未鉴权的请求
+    fn authorize(&self, request: serde_json::Value, _cred: &Credentials) -> serde_json::Value {
+        request
+    }
 }
 
 /// 提供各协议的默认端点
@@ -352,3 +364,89 @@ impl WsEndpoints for BinanceWsApiProtocol {
         Some("wss://ws-api.binance.com/ws-api/v3")
     }
 }
+
+/// Coinbase Advanced Trade 协议实现
+///
+/// 没有登录请求，每个订阅请求带一个新签发的 JWT（`api_key` 为密钥名称，`api_secret` 为私钥）；
+/// 行情频道可以不带 JWT，`user` 频道必须带。连接后订阅 `heartbeats`，避免没有成交的交易对
+/// 在空闲时被服务端断开。
+/// see: https://docs.cdp.coinbase.com/advanced-trade/docs/ws-overview
+#[derive(Clone, Default)]
+pub struct CoinbaseProtocol;
+
+impl CoinbaseProtocol {
+    /// 兼容 "btc-usd" 或 "BTC-USD"
+    fn normalize_symbol(inst_id: &str) -> String {
+        inst_id.to_uppercase()
+    }
+
+    fn map_channel(channel: &ChannelType) -> String {
+        match channel {
+            ChannelType::Tickers => "ticker".to_string(),
+            ChannelType::Trades => "market_trades".to_string(),
+            // level2 保证送达每次更新，没有单独的最优报价频道
+            ChannelType::Books | ChannelType::Depth => "level2".to_string(),
+            // 只有 5 分钟 K 线
+            ChannelType::Candle(_) => "candles".to_string(),
+        }
+    }
+
+    fn request(kind: &str, channel: &str, product_ids: Vec<String>) -> serde_json::Value {
+        serde_json::to_value(CoinbaseWsRequest {
+            kind: kind.to_string(),
+            product_ids,
+            channel: channel.to_string(),
+        })
+        .unwrap()
+    }
+}
+
+impl WsProtocol for CoinbaseProtocol {
+    fn ping_text(&self) -> Option<String> {
+        None
+    }
+
+    fn build_subscribe(&self, channel: ChannelType, args: &Args) -> StoredSub {
+        let channel_name = Self::map_channel(&channel);
+        let product_ids: Vec<String> = args
+            .symbol()
+            .map(Self::normalize_symbol)
+            .into_iter()
+            .collect();
+        StoredSub {
+            key: self.make_key(&channel, args),
+            local: None,
+            req_sub: Self::request("subscribe", &channel_name, product_ids.clone()),
+            req_unsub: Self::request("unsubscribe", &channel_name, product_ids),
+        }
+    }
+
+    fn make_key(&self, channel: &ChannelType, args: &Args) -> String {
+        let channel_name = Self::map_channel(channel);
+        match args.symbol() {
+            Some(inst) => format!("{}:{}", channel_name, Self::normalize_symbol(inst)),
+            None => channel_name,
+        }
+    }
+
+    fn on_connect(&self) -> Vec<serde_json::Value> {
+        vec![Self::request("subscribe", "heartbeats", Vec::new())]
+    }
+
+    fn authorize(&self, mut request: serde_json::Value, cred: &Credentials) -> serde_json::Value {
+        match crate::utils::coinbase_jwt(&cred.api_key, &cred.api_secret) {
+            Ok(jwt) => request["jwt"] = serde_json::Value::String(jwt),
+            Err(e) => tracing::error!("Coinbase JWT 签发失败: {}", e),
+        }
+        request
+    }
+}
+
+impl WsEndpoints for CoinbaseProtocol {
+    fn default_public_url() -> &'static str {
+        "wss://advanced-trade-ws.coinbase.com"
+    }
+    fn default_private_url() -> Option<&'static str> {
+        Some("wss://advanced-trade-ws-user.coinbase.com")
+    }
+}
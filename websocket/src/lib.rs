@@ -1,20 +1,30 @@
 mod auth;
+mod bounded;
 pub mod channel;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 mod client;
+pub mod coinbase;
 mod error;
 mod exchange;
+mod listener;
 mod request;
 mod server;
 mod utils;
 
+pub use bounded::{
+    BoundedReceiver, BoundedSender, ChannelConfig, ChannelGauge, Overflow, bounded_channel,
+};
 pub use error::Error;
+pub use listener::{Cidr, ListenerConfig};
 pub use server::WebSocketServer;
 pub use server::{Connection, TcpStreamReceiver, TcpStreamSender};
 
 pub use crate::client::WebsocketClient;
-pub use crate::exchange::{BinanceProtocol, BinanceWsApiProtocol, OkxProtocol};
+pub use crate::exchange::{BinanceProtocol, BinanceWsApiProtocol, CoinbaseProtocol, OkxProtocol};
 
 pub use crate::auth::Credentials;
+pub use crate::utils::Signer;
 
 /// Okx websocket client
 pub type OkxWebsocketClient = WebsocketClient<OkxProtocol>;
@@ -22,3 +32,5 @@ pub type OkxWebsocketClient = WebsocketClient<OkxProtocol>;
 pub type BinanceWebsocketClient = WebsocketClient<BinanceProtocol>;
 /// Binance WS-API websocket client
 pub type BinanceWsApiWebsocketClient = WebsocketClient<BinanceWsApiProtocol>;
+/// Coinbase Advanced Trade websocket client
+pub type CoinbaseWebsocketClient = WebsocketClient<CoinbaseProtocol>;
@@ -0,0 +1,230 @@
+//! 监听端口的准入控制
+//!
+//! 网关默认只监听本机，一旦误绑定到公网地址，任何人都可以连上来下单。
+//! 这里在 WebSocket 握手之前按来源网段、并发连接数和单 IP 建连频率拒绝连接。
+
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// ```json
+/// "listener": {
+///     "allow": ["127.0.0.1/32", "10.0.0.0/8"],
+///     "max_connections": 16,
+///     "rate_limit": 10,
+///     "rate_window_secs": 60
+/// }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ListenerConfig {
+    /// 允许连接的网段，空表示不限制
+    pub allow: Vec<String>,
+    /// 最大并发连接数，0 表示不限制
+    pub max_connections: usize,
+    /// 单个 IP 在 `rate_window_secs` 内最多建立的连接数，0 表示不限制
+    pub rate_limit: usize,
+    pub rate_window_secs: u64,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            max_connections: 0,
+            rate_limit: 0,
+            rate_window_secs: 60,
+        }
+    }
+}
+
+/// 例如 `192.168.0.0/16`、`::1/128`，省略前缀长度表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid cidr {}", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| anyhow::anyhow!("Invalid cidr {}", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // IPv6 监听时 IPv4 客户端以 ::ffff:a.b.c.d 的形式出现
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            _ => *ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 连接关闭时释放并发名额
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ListenerLimits {
+    allow: Vec<Cidr>,
+    max_connections: usize,
+    rate_limit: usize,
+    rate_window: Duration,
+    active: Arc<AtomicUsize>,
+    /// ip -> 窗口内的建连时间
+    history: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl ListenerLimits {
+    pub fn new(config: &ListenerConfig) -> anyhow::Result<Self> {
+        let allow = config
+            .allow
+            .iter()
+            .map(|s| s.parse())
+            .collect::<anyhow::Result<Vec<Cidr>>>()?;
+        Ok(Self {
+            allow,
+            max_connections: config.max_connections,
+            rate_limit: config.rate_limit,
+            rate_window: Duration::from_secs(config.rate_window_secs),
+            ..Default::default()
+        })
+    }
+
+    /// 检查能否接受来自 `ip` 的连接，拒绝时返回原因
+    pub fn admit(&self, ip: &IpAddr, now: Instant) -> Result<ConnectionPermit, String> {
+        if !self.allow.is_empty() && !self.allow.iter().any(|cidr| cidr.contains(ip)) {
+            return Err(format!("{} is not in the allowlist", ip));
+        }
+
+        if self.rate_limit > 0 {
+            let mut history = self.history.lock().unwrap_or_else(|p| p.into_inner());
+            history.retain(|_, times| {
+                while times
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= self.rate_window)
+                {
+                    times.pop_front();
+                }
+                !times.is_empty()
+            });
+            let times = history.entry(*ip).or_default();
+            if times.len() >= self.rate_limit {
+                return Err(format!(
+                    "{} exceeds {} connections per {:?}",
+                    ip, self.rate_limit, self.rate_window
+                ));
+            }
+            times.push_back(now);
+        }
+
+        let active = self.active.fetch_add(1, Ordering::SeqCst);
+        let permit = ConnectionPermit {
+            active: self.active.clone(),
+        };
+        if self.max_connections > 0 && active >= self.max_connections {
+            return Err(format!("{} connections reached", self.max_connections));
+        }
+        Ok(permit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr() {
+        let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains(&"10.2.0.1".parse().unwrap()));
+        assert!(cidr.contains(&"::ffff:10.1.0.9".parse().unwrap()));
+
+        let cidr: Cidr = "::1".parse().unwrap();
+        assert!(cidr.contains(&"::1".parse().unwrap()));
+        assert!(!cidr.contains(&"127.0.0.1".parse().unwrap()));
+        assert!(
+            "0.0.0.0/0"
+                .parse::<Cidr>()
+                .unwrap()
+                .contains(&"8.8.8.8".parse().unwrap())
+        );
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("localhost".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_admit() {
+        let limits = ListenerLimits::new(&ListenerConfig {
+            allow: vec!["127.0.0.0/8".into()],
+            max_connections: 2,
+            rate_limit: 3,
+            rate_window_secs: 60,
+        })
+        .unwrap();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limits.admit(&"192.168.1.1".parse().unwrap(), now).is_err());
+
+        let first = limits.admit(&local, now).unwrap();
+        let _second = limits.admit(&local, now).unwrap();
+        assert!(
+            limits
+                .admit(&local, now)
+                .unwrap_err()
+                .contains("connections reached")
+        );
+
+        // 断开后释放并发名额，但建连频率仍在窗口内
+        drop(first);
+        assert!(limits.admit(&local, now).unwrap_err().contains("per"));
+        let later = now + Duration::from_secs(60);
+        assert!(limits.admit(&local, later).is_ok());
+    }
+}
@@ -35,3 +35,14 @@ pub struct BinanceWsRequest {
     pub params: Vec<String>,
     pub id: u64,
 }
+
+/// Coinbase Advanced Trade 的订阅请求，每个请求只有一个频道
+#[derive(Debug, Clone, Serialize)]
+pub struct CoinbaseWsRequest {
+    /// `subscribe` 或 `unsubscribe`
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub product_ids: Vec<String>,
+    pub channel: String,
+}
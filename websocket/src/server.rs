@@ -1,3 +1,5 @@
+use crate::bounded::{BoundedReceiver, BoundedSender};
+use crate::listener::{ConnectionPermit, ListenerConfig, ListenerLimits};
 use futures_util::{
     SinkExt, StreamExt,
     stream::{SplitSink, SplitStream},
@@ -6,22 +8,16 @@ use std::{
     fmt::{Debug, Display},
     marker::PhantomData,
     net::SocketAddr,
+    time::Instant,
 };
-use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
-};
+use tokio::net::{TcpListener, TcpStream};
 use tokio_tungstenite::{WebSocketStream, tungstenite::Message};
-use tracing::info;
+use tracing::{info, warn};
 use url::Url;
 
 pub type TcpStreamSender = WebSocketSender<SplitSink<WebSocketStream<TcpStream>, Message>, Message>;
 pub type TcpStreamReceiver = WebSocketReceiver<SplitStream<WebSocketStream<TcpStream>>>;
-pub type Connection = (
-    SocketAddr,
-    UnboundedSender<Message>,
-    UnboundedReceiver<Message>,
-);
+pub type Connection = (SocketAddr, BoundedSender<Message>, BoundedReceiver<Message>);
 
 #[derive(Debug)]
 pub struct WebSocketReceiver<T>
@@ -31,6 +27,8 @@ where
 {
     peer: SocketAddr,
     inner: T,
+    /// 连接关闭（接收端析构）时释放并发名额
+    permit: Option<ConnectionPermit>,
 }
 
 impl<T> WebSocketReceiver<T>
@@ -39,7 +37,11 @@ where
     T::Item: Debug,
 {
     pub fn new(peer: SocketAddr, inner: T) -> Self {
-        Self { peer, inner }
+        Self {
+            peer,
+            inner,
+            permit: None,
+        }
     }
 
     pub fn addr(&self) -> &SocketAddr {
@@ -47,8 +49,7 @@ where
     }
 
     pub async fn recv(&mut self) -> Option<T::Item> {
-        let msg = self.inner.next().await;
-        msg
+        self.inner.next().await
     }
 }
 
@@ -95,6 +96,7 @@ where
 
 pub struct WebSocketServer {
     inner: TcpListener,
+    limits: ListenerLimits,
 }
 
 impl WebSocketServer {
@@ -108,23 +110,37 @@ impl WebSocketServer {
         .await?;
         info!("Bind address {}", addr);
 
-        Ok(Self { inner: listener })
+        Ok(Self {
+            inner: listener,
+            limits: ListenerLimits::default(),
+        })
+    }
+
+    /// 设置来源网段白名单、并发连接数和单 IP 建连频率限制
+    pub fn with_limits(mut self, config: &ListenerConfig) -> anyhow::Result<Self> {
+        self.limits = ListenerLimits::new(config)?;
+        Ok(self)
     }
 
     /// 接受新的连接
     /// 返回连接的地址，发送端，接收端
     pub async fn accept(&self) -> anyhow::Result<(SocketAddr, TcpStreamSender, TcpStreamReceiver)> {
-        let (stream, peer) = self.inner.accept().await?;
+        let (stream, peer, permit) = loop {
+            let (stream, peer) = self.inner.accept().await?;
+            // 握手之前拒绝，被拒绝的连接直接关闭
+            match self.limits.admit(&peer.ip(), Instant::now()) {
+                Ok(permit) => break (stream, peer, permit),
+                Err(reason) => warn!("Reject peer {}: {}", peer, reason),
+            }
+        };
 
         // let peer = peer.to_string();
         info!("Peer address connect: {}", peer);
         let ws = tokio_tungstenite::accept_async(stream).await?;
         let (write, read) = ws.split();
 
-        return Ok((
-            peer.clone(),
-            WebSocketSender::new(peer.clone(), write),
-            WebSocketReceiver::new(peer.clone(), read),
-        ));
+        let mut receiver = WebSocketReceiver::new(peer, read);
+        receiver.permit = Some(permit);
+        Ok((peer, WebSocketSender::new(peer, write), receiver))
     }
 }
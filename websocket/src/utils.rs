@@ -1,7 +1,12 @@
 use anyhow::Context;
 use base64::Engine as _;
+use ed25519_dalek::{SigningKey, pkcs8::DecodePrivateKey};
 use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
 use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 pub fn generate_timestamp_websocket() -> String {
     format!("{}", chrono::Utc::now().timestamp_millis())
@@ -21,42 +26,231 @@ pub fn generate_signature(
     let sig = mac.finalize().into_bytes();
     Ok(base64::engine::general_purpose::STANDARD.encode(sig))
 }
-pub fn sign_ed25519_base64(secret_or_pem: &str, payload: &str) -> anyhow::Result<String> {
-    use ed25519_dalek::{Signer, SigningKey, pkcs8::DecodePrivateKey};
+/// 持有解析好的 Ed25519 私钥
+#[derive(Debug, Clone)]
+pub struct Signer {
+    key: SigningKey,
+}
 
-    // 1) 如果传入的是内联 PEM 内容，直接解析 PEM
+impl Signer {
+    /// `secret_or_pem` 为内联 PEM 内容，或 PEM / DER 私钥文件路径
+    pub fn new(secret_or_pem: &str) -> anyhow::Result<Self> {
+        // 1) 如果传入的是内联 PEM 内容，直接解析 PEM
+
+        if secret_or_pem.contains("-----BEGIN") {
+            let key = SigningKey::from_pkcs8_pem(secret_or_pem)
+                .with_context(|| "解析 Ed25519 PKCS#8 PEM 私钥失败")?;
+            return Ok(Self { key });
+        }
+
+        // 2) 否则认为是文件路径，优先按文本读取并尝试 PEM；若非 PEM 再按二进制 DER 读取
+
+        if let Ok(pem_text) = std::fs::read_to_string(secret_or_pem) {
+            if pem_text.contains("-----BEGIN") {
+                let key = SigningKey::from_pkcs8_pem(&pem_text)
+                    .with_context(|| format!("解析 Ed25519 PEM 私钥失败: {}", secret_or_pem))?;
+                return Ok(Self { key });
+            }
+        }
 
-    if secret_or_pem.contains("-----BEGIN") {
-        let sk = ed25519_dalek::SigningKey::from_pkcs8_pem(secret_or_pem)
-            .with_context(|| "解析 Ed25519 PKCS#8 PEM 私钥失败")?;
+        // 3) 按 DER 二进制尝试
 
-        let sig = sk.sign(payload.as_bytes());
+        let der = std::fs::read(secret_or_pem)
+            .with_context(|| format!("读取 Ed25519 私钥(DER)失败: {}", secret_or_pem))?;
 
-        return Ok(base64::engine::general_purpose::STANDARD.encode(sig.to_bytes()));
+        let key =
+            SigningKey::from_pkcs8_der(&der).with_context(|| "解析 Ed25519 PKCS#8 DER 私钥失败")?;
+        Ok(Self { key })
     }
 
-    // 2) 否则认为是文件路径，优先按文本读取并尝试 PEM；若非 PEM 再按二进制 DER 读取
+    /// 签名并返回 Base64
+    pub fn sign_base64(&self, payload: &str) -> String {
+        use ed25519_dalek::Signer as _;
 
-    if let Ok(pem_text) = std::fs::read_to_string(secret_or_pem) {
-        if pem_text.contains("-----BEGIN") {
-            let sk = SigningKey::from_pkcs8_pem(&pem_text)
-                .with_context(|| format!("解析 Ed25519 PEM 私钥失败: {}", secret_or_pem))?;
+        let sig = self.key.sign(payload.as_bytes());
+        base64::engine::general_purpose::STANDARD.encode(sig.to_bytes())
+    }
+}
+
+/// (文件修改时间, 解析好的私钥)，内联 PEM 没有修改时间
+type CachedSigner = (Option<SystemTime>, Arc<Signer>);
 
-            let sig = sk.sign(payload.as_bytes());
+/// 以私钥文件路径（或内联 PEM）为键
+static SIGNERS: Lazy<Mutex<HashMap<String, CachedSigner>>> = Lazy::new(Default::default);
 
-            return Ok(base64::engine::general_purpose::STANDARD.encode(sig.to_bytes()));
+/// 取缓存的 `Signer`，私钥文件修改过时重新解析
+pub fn cached_signer(secret_or_pem: &str) -> anyhow::Result<Arc<Signer>> {
+    let mtime = if secret_or_pem.contains("-----BEGIN") {
+        None
+    } else {
+        std::fs::metadata(secret_or_pem)
+            .and_then(|m| m.modified())
+            .ok()
+    };
+
+    let mut signers = SIGNERS.lock().unwrap_or_else(|p| p.into_inner());
+    if let Some((cached, signer)) = signers.get(secret_or_pem) {
+        if *cached == mtime {
+            return Ok(signer.clone());
         }
     }
+    let signer = Arc::new(Signer::new(secret_or_pem)?);
+    signers.insert(secret_or_pem.to_string(), (mtime, signer.clone()));
+    Ok(signer)
+}
+
+pub fn sign_ed25519_base64(secret_or_pem: &str, payload: &str) -> anyhow::Result<String> {
+    Ok(cached_signer(secret_or_pem)?.sign_base64(payload))
+}
+
+/// Coinbase CDP 密钥签发的 JWT，有效期 2 分钟
+///
+/// `key_name` 为 `organizations/{org}/apiKeys/{id}`；`secret` 为 EC 私钥 PEM 时用 ES256，
+/// 否则按 Base64 的 Ed25519 私钥用 EdDSA。环境变量中 PEM 的换行可以写成 `\n`。
+/// see: https://docs.cdp.coinbase.com/advanced-trade/docs/ws-auth
+pub fn coinbase_jwt(key_name: &str, secret: &str) -> anyhow::Result<String> {
+    let secret = secret.replace("\\n", "\n");
+    let ec = secret.contains("-----BEGIN");
+    let mut nonce = [0u8; 16];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let header = serde_json::json!({
+        "alg": if ec { "ES256" } else { "EdDSA" },
+        "typ": "JWT",
+        "kid": key_name,
+        "nonce": nonce.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+    });
+    let now = chrono::Utc::now().timestamp();
+    let claims = serde_json::json!({
+        "iss": "cdp",
+        "sub": key_name,
+        "nbf": now,
+        "exp": now + 120,
+    });
+    let engine = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let message = format!(
+        "{}.{}",
+        engine.encode(header.to_string()),
+        engine.encode(claims.to_string())
+    );
+
+    let signature = if ec {
+        let key = openssl::pkey::PKey::private_key_from_pem(secret.as_bytes())
+            .with_context(|| "解析 Coinbase EC 私钥失败")?
+            .ec_key()?;
+        let digest = openssl::sha::sha256(message.as_bytes());
+        let sig = openssl::ecdsa::EcdsaSig::sign(&digest, &key)?;
+        // JWS 的 ES256 签名是定长的 r || s，而不是 DER
+        let mut raw = sig.r().to_vec_padded(32)?;
+        raw.extend(sig.s().to_vec_padded(32)?);
+        raw
+    } else {
+        use ed25519_dalek::Signer as _;
+
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(secret.trim())
+            .with_context(|| "解析 Coinbase Ed25519 私钥失败")?;
+        // 私钥是 32 字节种子，后面可能跟着 32 字节公钥
+        let seed: [u8; 32] = bytes
+            .get(..32)
+            .and_then(|seed| seed.try_into().ok())
+            .context("Coinbase Ed25519 私钥长度不足 32 字节")?;
+        SigningKey::from_bytes(&seed)
+            .sign(message.as_bytes())
+            .to_bytes()
+            .to_vec()
+    };
+    Ok(format!("{}.{}", message, engine.encode(signature)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::pkcs8::EncodePrivateKey;
+    use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+
+    fn pem(seed: u8) -> String {
+        SigningKey::from_bytes(&[seed; 32])
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_cached_signer() {
+        let path = std::env::temp_dir().join(format!("signer-{}.pem", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        std::fs::write(&path, pem(1)).unwrap();
 
-    // 3) 按 DER 二进制尝试
+        let first = cached_signer(path_str).unwrap();
+        assert!(Arc::ptr_eq(&first, &cached_signer(path_str).unwrap()));
+        assert_eq!(
+            sign_ed25519_base64(path_str, "a=1").unwrap(),
+            Signer::new(&pem(1)).unwrap().sign_base64("a=1")
+        );
 
-    let der = std::fs::read(secret_or_pem)
-        .with_context(|| format!("读取 Ed25519 私钥(DER)失败: {}", secret_or_pem))?;
+        // 文件更新后重新解析
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&path, pem(2)).unwrap();
+        let second = cached_signer(path_str).unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(
+            second.sign_base64("a=1"),
+            Signer::new(&pem(2)).unwrap().sign_base64("a=1")
+        );
 
-    let sk =
-        SigningKey::from_pkcs8_der(&der).with_context(|| "解析 Ed25519 PKCS#8 DER 私钥失败")?;
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_coinbase_jwt() {
+        let engine = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let decode = |part: &str| -> serde_json::Value {
+            serde_json::from_slice(&engine.decode(part).unwrap()).unwrap()
+        };
 
-    let sig = sk.sign(payload.as_bytes());
+        // EC 私钥，PEM 的换行写成 \n
+        let group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        let key = openssl::ec::EcKey::generate(&group).unwrap();
+        let pem = String::from_utf8(key.private_key_to_pem().unwrap()).unwrap();
+        let jwt = coinbase_jwt("organizations/o/apiKeys/k", &pem.replace('\n', "\\n")).unwrap();
+        let parts: Vec<_> = jwt.split('.').collect();
+        let header = decode(parts[0]);
+        assert_eq!(header["alg"], "ES256");
+        assert_eq!(header["kid"], "organizations/o/apiKeys/k");
+        let claims = decode(parts[1]);
+        assert_eq!(claims["iss"], "cdp");
+        assert_eq!(claims["sub"], "organizations/o/apiKeys/k");
+        assert_eq!(
+            claims["exp"].as_i64().unwrap() - claims["nbf"].as_i64().unwrap(),
+            120
+        );
+        let raw = engine.decode(parts[2]).unwrap();
+        assert_eq!(raw.len(), 64);
+        let sig = openssl::ecdsa::EcdsaSig::from_private_components(
+            openssl::bn::BigNum::from_slice(&raw[..32]).unwrap(),
+            openssl::bn::BigNum::from_slice(&raw[32..]).unwrap(),
+        )
+        .unwrap();
+        let digest = openssl::sha::sha256(format!("{}.{}", parts[0], parts[1]).as_bytes());
+        assert!(sig.verify(&digest, &key).unwrap());
 
-    Ok(base64::engine::general_purpose::STANDARD.encode(sig.to_bytes()))
+        // Ed25519 私钥，种子加公钥共 64 字节
+        let signing = SigningKey::from_bytes(&[3; 32]);
+        let mut secret = signing.to_bytes().to_vec();
+        secret.extend(signing.verifying_key().to_bytes());
+        let jwt = coinbase_jwt(
+            "k",
+            &base64::engine::general_purpose::STANDARD.encode(secret),
+        )
+        .unwrap();
+        let parts: Vec<_> = jwt.split('.').collect();
+        assert_eq!(decode(parts[0])["alg"], "EdDSA");
+        let sig = ed25519_dalek::Signature::from_slice(&engine.decode(parts[2]).unwrap()).unwrap();
+        signing
+            .verifying_key()
+            .verify_strict(format!("{}.{}", parts[0], parts[1]).as_bytes(), &sig)
+            .unwrap();
+        assert!(coinbase_jwt("k", "AAAA").is_err());
+    }
 }